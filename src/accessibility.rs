@@ -0,0 +1,130 @@
+use bevy::prelude::*;
+
+use crate::{
+    components::{Troop, Unique},
+    hud::HudMaterials,
+    material_cache::MaterialCache,
+    menu::ButtonMaterials,
+    settings::Settings,
+    Screen, STATE_CHANGE_STAGE,
+};
+
+/// Side of the faction logo laid on top of each troop token, small enough to stay on its face
+const ICON_SIZE: f32 = 0.024;
+/// Just above the top of a troop token
+const ICON_HEIGHT: f32 = 0.0019;
+
+pub struct AccessibilityPlugin;
+
+impl Plugin for AccessibilityPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // Scaling after the screens have spawned their text, so none of it is missed
+        app.add_system_to_stage(stage::POST_UPDATE, font_scale_system.system())
+            .add_system(theme_system.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                faction_icon_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                faction_icon_system.system(),
+            );
+    }
+}
+
+/// The size a piece of text was spawned with, before the player's scaling
+struct BaseFontSize(f32);
+
+/// The faction logo shown on a troop token, so factions can be told apart without their colors
+struct FactionIcon;
+
+type Texts<'a, 'b, 'c, 'd> = QuerySet<(
+    Query<'a, (Entity, &'b mut Text), Without<BaseFontSize>>,
+    Query<'a, (&'c mut Text, &'d BaseFontSize)>,
+)>;
+
+/// Scales every text by the player's font scale, on top of the UI scale
+fn font_scale_system(
+    commands: &mut Commands,
+    settings: Res<Settings>,
+    mut applied: Local<Option<f32>>,
+    mut texts: Texts,
+) {
+    let scale = settings.accessibility.font_scale * settings.graphics.ui_scale;
+    for (entity, mut text) in texts.q0_mut().iter_mut() {
+        commands.insert_one(entity, BaseFontSize(text.style.font_size));
+        text.style.font_size *= scale;
+    }
+    if *applied != Some(scale) {
+        *applied = Some(scale);
+        for (mut text, base) in texts.q1_mut().iter_mut() {
+            text.style.font_size = base.0 * scale;
+        }
+    }
+}
+
+/// Switches the menu and HUD colors whenever the high contrast setting changes
+fn theme_system(
+    settings: Res<Settings>,
+    (buttons, hud): (Res<ButtonMaterials>, Res<HudMaterials>),
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut applied: Local<Option<bool>>,
+) {
+    let high_contrast = settings.accessibility.high_contrast;
+    if *applied != Some(high_contrast) {
+        *applied = Some(high_contrast);
+        buttons.recolor(&mut materials, high_contrast);
+        hud.recolor(&mut materials, high_contrast);
+    }
+}
+
+/// Puts the faction logos on the troop tokens while the setting is on, and takes them off again
+fn faction_icon_system(
+    commands: &mut Commands,
+    (settings, asset_server, mut cache): (Res<Settings>, Res<AssetServer>, ResMut<MaterialCache>),
+    (mut meshes, mut materials): (ResMut<Assets<Mesh>>, ResMut<Assets<StandardMaterial>>),
+    mut shown: Local<bool>,
+    troops: Query<(Entity, &Unique, &Visible), With<Troop>>,
+    icons: Query<Entity, With<FactionIcon>>,
+) {
+    let enabled = settings.accessibility.faction_icons;
+    // Only troops spawned after the icons went on are missing one
+    let missing = enabled && icons.iter().next().is_none() && troops.iter().next().is_some();
+    if enabled == *shown && !missing {
+        return;
+    }
+    *shown = enabled;
+    for icon in icons.iter() {
+        commands.despawn(icon);
+    }
+    if !enabled {
+        return;
+    }
+    let mesh = meshes.add(Mesh::from(shape::Quad::new(Vec2::splat(ICON_SIZE))));
+    for (entity, unique, visible) in troops.iter() {
+        let material = cache.unshaded(
+            &format!("tokens/{}_logo.png", unique.faction.code()),
+            &asset_server,
+            &mut materials,
+        );
+        // Hidden along with the token, since visibility is only passed down when it changes
+        let icon = commands
+            .spawn(PbrBundle {
+                mesh: mesh.clone(),
+                material,
+                transform: Transform::from_translation(ICON_HEIGHT * Vec3::unit_y())
+                    * Transform::from_rotation(Quat::from_rotation_x(-0.5 * std::f32::consts::PI)),
+                visible: Visible {
+                    is_visible: visible.is_visible,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .with(FactionIcon)
+            .current_entity()
+            .unwrap();
+        commands.push_children(entity, &[icon]);
+    }
+}
@@ -0,0 +1,553 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
+
+use crate::{
+    bot::Bot,
+    components::Player,
+    data::Faction,
+    game_action::{submit, GameAction, GameActions},
+    menu::{ButtonMaterials, Lobby},
+    network::{Client, Network, NetworkType, Server},
+    phase::GamePhase,
+    protocol::ServerEvent,
+    resources::Info,
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// Seconds the factions get to make and break alliances once a Nexus is called
+pub const NEGOTIATION_SECONDS: f32 = 60.0;
+
+pub struct AlliancePlugin;
+
+impl Plugin for AlliancePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Negotiation>()
+            .on_state_enter(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_enter(RESPONSE_STAGE, Screen::JoinedGame, reset.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                negotiation_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                bot_negotiation_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                negotiation_clock_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                negotiation_panel_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                negotiation_panel_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                alliance_button_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                alliance_button_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                alliance_tile_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                alliance_tile_system.system(),
+            );
+    }
+}
+
+/// Two factions that share their victory
+#[derive(Archive, Unarchive, Copy, Clone, PartialEq, Debug)]
+#[archive(derive(CheckBytes))]
+pub struct Alliance {
+    pub first: Faction,
+    pub second: Faction,
+}
+
+impl Alliance {
+    pub fn includes(&self, faction: Faction) -> bool {
+        self.first == faction || self.second == faction
+    }
+
+    /// The other side of the alliance, if `faction` is in it
+    pub fn partner(&self, faction: Faction) -> Option<Faction> {
+        if self.first == faction {
+            Some(self.second)
+        } else if self.second == faction {
+            Some(self.first)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Archive, Unarchive, Copy, Clone, PartialEq, Debug)]
+#[archive(derive(CheckBytes))]
+pub enum AllianceRequest {
+    Propose {
+        from: Faction,
+        to: Faction,
+    },
+    Accept {
+        from: Faction,
+        to: Faction,
+    },
+    Break {
+        faction: Faction,
+    },
+    /// Nothing more to negotiate this Nexus
+    Done {
+        faction: Faction,
+    },
+}
+
+impl AllianceRequest {
+    pub fn faction(&self) -> Faction {
+        match *self {
+            AllianceRequest::Propose { from, .. } | AllianceRequest::Accept { from, .. } => from,
+            AllianceRequest::Break { faction } | AllianceRequest::Done { faction } => faction,
+        }
+    }
+}
+
+/// The bargaining that goes on while a Nexus is open. The host runs it and clients follow along.
+#[derive(Default)]
+pub struct Negotiation {
+    pub open: bool,
+    pub seconds: f32,
+    /// Offers waiting on an answer, from `first` to `second`
+    pub proposals: Vec<Alliance>,
+    pub done: Vec<Faction>,
+    pub requests: VecDeque<AllianceRequest>,
+}
+
+impl Negotiation {
+    pub fn start(&mut self) {
+        *self = Negotiation {
+            open: true,
+            seconds: NEGOTIATION_SECONDS,
+            ..Default::default()
+        };
+    }
+
+    /// Takes on the host's view of the negotiation
+    pub fn sync(&mut self, open: bool, proposals: Vec<Alliance>, done: Vec<Faction>) {
+        if open && !self.open {
+            self.seconds = NEGOTIATION_SECONDS;
+        }
+        self.open = open;
+        self.proposals = proposals;
+        self.done = done;
+    }
+}
+
+/// The faction this machine negotiates for. The host speaks for their own seat, or for whoever
+/// is active when everyone shares the machine.
+pub fn own_faction(
+    network: &Network,
+    lobby: &Lobby,
+    info: &Info,
+    players: &Query<&Player>,
+    client: &Query<&Client>,
+) -> Option<Faction> {
+    match network.network_type {
+        NetworkType::Client => client.iter().next().and_then(|client| client.faction),
+        _ if !lobby.seats.is_empty() => lobby.host().and_then(|seat| seat.faction),
+        _ if info.play_order.is_empty() => None,
+        _ => players
+            .get(info.get_active_player())
+            .ok()
+            .map(|player| player.faction),
+    }
+}
+
+fn reset(mut negotiation: ResMut<Negotiation>) {
+    *negotiation = Negotiation::default();
+}
+
+/// Settles alliance requests while the Nexus is open, and closes it once everyone is done or the
+/// time runs out
+fn negotiation_system(
+    (time, network, phase): (Res<Time>, Res<Network>, Res<GamePhase>),
+    (mut info, mut negotiation, mut actions): (
+        ResMut<Info>,
+        ResMut<Negotiation>,
+        ResMut<GameActions>,
+    ),
+    mut server: Query<&mut Server>,
+) {
+    if network.network_type == NetworkType::Client || !negotiation.open {
+        return;
+    }
+    let mut changed = false;
+    while let Some(request) = negotiation.requests.pop_front() {
+        if !info.in_game().contains(&request.faction()) {
+            continue;
+        }
+        let applied = match request {
+            AllianceRequest::Propose { from, to } => {
+                let proposal = Alliance {
+                    first: from,
+                    second: to,
+                };
+                if from != to
+                    && info.in_game().contains(&to)
+                    && info.ally(from).is_none()
+                    && info.ally(to).is_none()
+                    && !negotiation.proposals.contains(&proposal)
+                {
+                    println!("{} proposes an alliance to {}", from, to);
+                    negotiation.proposals.push(proposal);
+                    true
+                } else {
+                    false
+                }
+            }
+            AllianceRequest::Accept { from, to } => {
+                let proposal = Alliance {
+                    first: to,
+                    second: from,
+                };
+                if negotiation.proposals.contains(&proposal)
+                    && info.ally(from).is_none()
+                    && info.ally(to).is_none()
+                {
+                    println!("{} and {} are now allies", to, from);
+                    info.alliances.push(proposal);
+                    negotiation
+                        .proposals
+                        .retain(|other| !other.includes(from) && !other.includes(to));
+                    true
+                } else {
+                    false
+                }
+            }
+            AllianceRequest::Break { faction } => {
+                if let Some(ally) = info.ally(faction) {
+                    println!("{} breaks their alliance with {}", faction, ally);
+                    info.alliances
+                        .retain(|alliance| !alliance.includes(faction));
+                    true
+                } else {
+                    false
+                }
+            }
+            AllianceRequest::Done { faction } => {
+                if !negotiation.done.contains(&faction) {
+                    negotiation.done.push(faction);
+                    true
+                } else {
+                    false
+                }
+            }
+        };
+        if applied {
+            actions.resolved(&info, phase.phase, GameAction::Alliance { request });
+            changed = true;
+        }
+    }
+
+    negotiation.seconds -= time.delta_seconds();
+    let everyone_done = info
+        .in_game()
+        .iter()
+        .all(|faction| negotiation.done.contains(faction));
+    if everyone_done || negotiation.seconds <= 0.0 {
+        println!("The Nexus is over");
+        negotiation.open = false;
+        negotiation.proposals.clear();
+        changed = true;
+    }
+
+    if changed {
+        if let Some(mut server) = server.iter_mut().next() {
+            server.broadcast(&ServerEvent::Negotiation {
+                open: negotiation.open,
+                proposals: negotiation.proposals.clone(),
+                done: negotiation.done.clone(),
+                alliances: info.alliances.clone(),
+            });
+        }
+    }
+}
+
+/// Bots take any alliance offered to them and leave the rest of the talking to the players
+fn bot_negotiation_system(
+    (network, info): (Res<Network>, Res<Info>),
+    (negotiation, mut actions): (Res<Negotiation>, ResMut<GameActions>),
+    bots: Query<&Player, With<Bot>>,
+) {
+    if network.network_type == NetworkType::Client || !negotiation.open {
+        return;
+    }
+    for player in bots.iter() {
+        let faction = player.faction;
+        let done = AllianceRequest::Done { faction };
+        if negotiation.done.contains(&faction)
+            || negotiation.requests.contains(&done)
+            || actions.pending(faction)
+        {
+            continue;
+        }
+        if info.ally(faction).is_none() {
+            if let Some(offer) = negotiation
+                .proposals
+                .iter()
+                .find(|proposal| proposal.second == faction)
+            {
+                let accept = AllianceRequest::Accept {
+                    from: faction,
+                    to: offer.first,
+                };
+                actions
+                    .submitted
+                    .push_back(GameAction::Alliance { request: accept });
+            }
+        }
+        actions
+            .submitted
+            .push_back(GameAction::Alliance { request: done });
+    }
+}
+
+/// Keeps a client's countdown moving, since the host only says when the Nexus opens and closes
+fn negotiation_clock_system(
+    (time, network): (Res<Time>, Res<Network>),
+    mut negotiation: ResMut<Negotiation>,
+) {
+    if network.network_type == NetworkType::Client && negotiation.open {
+        negotiation.seconds = (negotiation.seconds - time.delta_seconds()).max(0.0);
+    }
+}
+
+struct NegotiationPanel;
+
+struct NegotiationClock;
+
+struct AllianceButton(AllianceRequest);
+
+/// The alliances, offers and finished factions the panel was last built from
+type PanelState = (Vec<Alliance>, Vec<Alliance>, Vec<Faction>);
+
+type AllianceButtonInteraction<'a> = (
+    &'a Interaction,
+    &'a mut Handle<ColorMaterial>,
+    &'a AllianceButton,
+);
+
+/// Shows the Nexus offers and the choices open to this machine's faction, rebuilding whenever the
+/// negotiation moves on
+fn negotiation_panel_system(
+    commands: &mut Commands,
+    (asset_server, button_materials, mut colors): (
+        Res<AssetServer>,
+        Res<ButtonMaterials>,
+        ResMut<Assets<ColorMaterial>>,
+    ),
+    (network, lobby, info, negotiation): (Res<Network>, Res<Lobby>, Res<Info>, Res<Negotiation>),
+    mut shown: Local<Option<PanelState>>,
+    (players, client): (Query<&Player>, Query<&Client>),
+    panels: Query<Entity, With<NegotiationPanel>>,
+    mut clocks: Query<&mut Text, With<NegotiationClock>>,
+) {
+    for mut text in clocks.iter_mut() {
+        text.value = format!("Nexus: {}s", negotiation.seconds.max(0.0).ceil() as u32);
+    }
+    let state = Some((
+        info.alliances.clone(),
+        negotiation.proposals.clone(),
+        negotiation.done.clone(),
+    ))
+    .filter(|_| negotiation.open);
+    if *shown == state {
+        return;
+    }
+    *shown = state;
+    for entity in panels.iter() {
+        commands.despawn_recursive(entity);
+    }
+    if !negotiation.open {
+        return;
+    }
+
+    let own = own_faction(&network, &lobby, &info, &players, &client);
+    let mut choices = Vec::new();
+    if let Some(faction) = own {
+        if let Some(ally) = info.ally(faction) {
+            choices.push((
+                Some(AllianceRequest::Break { faction }),
+                format!("Break with {}", ally),
+            ));
+        } else {
+            for &other in info
+                .in_game()
+                .iter()
+                .filter(|&&other| other != faction && info.ally(other).is_none())
+            {
+                let offered = Alliance {
+                    first: other,
+                    second: faction,
+                };
+                let asked = Alliance {
+                    first: faction,
+                    second: other,
+                };
+                choices.push(if negotiation.proposals.contains(&offered) {
+                    (
+                        Some(AllianceRequest::Accept {
+                            from: faction,
+                            to: other,
+                        }),
+                        format!("Accept {}", other),
+                    )
+                } else if negotiation.proposals.contains(&asked) {
+                    (None, format!("Asked {}", other))
+                } else {
+                    (
+                        Some(AllianceRequest::Propose {
+                            from: faction,
+                            to: other,
+                        }),
+                        format!("Ally with {}", other),
+                    )
+                });
+            }
+        }
+        choices.push(if negotiation.done.contains(&faction) {
+            (None, "Waiting on others".to_string())
+        } else {
+            (Some(AllianceRequest::Done { faction }), "Done".to_string())
+        });
+    }
+
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let text = |value: String| TextBundle {
+        text: Text {
+            font: font.clone(),
+            value,
+            style: TextStyle {
+                font_size: 18.0,
+                color: Color::ANTIQUE_WHITE,
+                ..Default::default()
+            },
+        },
+        ..Default::default()
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Percent(30.0),
+                    right: Val::Px(10.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Stretch,
+                padding: Rect::all(Val::Px(6.0)),
+                ..Default::default()
+            },
+            material: colors.add(Color::rgba(0.05, 0.05, 0.05, 0.8).into()),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(NegotiationPanel)
+        .with_children(|parent| {
+            parent.spawn(text(String::new())).with(NegotiationClock);
+            for proposal in negotiation.proposals.iter() {
+                parent.spawn(text(format!(
+                    "{} offers {} an alliance",
+                    proposal.first, proposal.second
+                )));
+            }
+            for (request, label) in choices {
+                match request {
+                    Some(request) => {
+                        parent
+                            .spawn(ButtonBundle {
+                                style: Style {
+                                    margin: Rect::all(Val::Px(3.0)),
+                                    padding: Rect::all(Val::Px(4.0)),
+                                    justify_content: JustifyContent::Center,
+                                    ..Default::default()
+                                },
+                                material: button_materials.normal.clone(),
+                                ..Default::default()
+                            })
+                            .with(AllianceButton(request))
+                            .with_children(|parent| {
+                                parent.spawn(text(label));
+                            });
+                    }
+                    None => {
+                        parent.spawn(text(label));
+                    }
+                }
+            }
+        });
+}
+
+fn alliance_button_system(
+    network: Res<Network>,
+    (button_materials, mut actions): (Res<ButtonMaterials>, ResMut<GameActions>),
+    mut interactions: Query<AllianceButtonInteraction, (Mutated<Interaction>, With<Button>)>,
+    mut client: Query<&mut Client>,
+) {
+    for (&interaction, mut material, AllianceButton(request)) in interactions.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {
+                *material = button_materials.pressed.clone();
+                submit(
+                    &network,
+                    &mut client,
+                    &mut actions,
+                    GameAction::Alliance { request: *request },
+                );
+            }
+            Interaction::Hovered => *material = button_materials.hovered.clone(),
+            Interaction::None => *material = button_materials.normal.clone(),
+        }
+    }
+}
+
+/// Sits on a faction's turn tile and names their ally
+pub struct AllianceText(pub Faction);
+
+fn alliance_tile_system(
+    info: Res<Info>,
+    mut shown: Local<Vec<Alliance>>,
+    mut texts: Query<(&AllianceText, &mut Text)>,
+) {
+    if *shown == info.alliances {
+        return;
+    }
+    *shown = info.alliances.clone();
+    for (AllianceText(faction), mut text) in texts.iter_mut() {
+        match info.ally(*faction) {
+            Some(ally) => {
+                text.value = format!("Allied with {}", ally);
+                text.style.color = ally.color();
+            }
+            None => text.value = String::new(),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::Hasher,
+    path::{Path, PathBuf},
+};
+
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
+
+/// Everything the game loads its models, textures and fonts from
+pub const ASSETS_DIR: &str = "assets";
+
+/// A hash of everything under one top level entry of the assets folder
+#[derive(Archive, Unarchive, PartialEq, Clone, Debug)]
+#[archive(derive(CheckBytes))]
+pub struct AssetFolder {
+    pub name: String,
+    pub hash: u64,
+}
+
+/// What this install has in its assets folder, so players whose cards would look different from
+/// the host's can be told about it when they join. Deck definitions already come from the host.
+pub struct AssetPack {
+    pub folders: Vec<AssetFolder>,
+}
+
+impl Default for AssetPack {
+    fn default() -> Self {
+        let root = Path::new(ASSETS_DIR);
+        let mut files = Vec::new();
+        collect_files(root, &mut files);
+        // Sorted, so two copies of the same folder always hash the same
+        files.sort();
+        let mut folders: Vec<(String, DefaultHasher)> = Vec::new();
+        for path in files {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let name = match relative.components().next() {
+                Some(component) => component.as_os_str().to_string_lossy().into_owned(),
+                None => continue,
+            };
+            let bytes = match fs::read(&path) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    println!("Couldn't hash {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            if folders.last().map_or(true, |(last, _)| *last != name) {
+                folders.push((name, DefaultHasher::new()));
+            }
+            let (_, hasher) = folders.last_mut().unwrap();
+            hasher.write(relative.to_string_lossy().as_bytes());
+            hasher.write(&bytes);
+        }
+        AssetPack {
+            folders: folders
+                .into_iter()
+                .map(|(name, hasher)| AssetFolder {
+                    name,
+                    hash: hasher.finish(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl AssetPack {
+    /// The folders that aren't the same in `other`, including any only one side has. A browser
+    /// has no assets folder and loads everything from the host, so nothing can differ.
+    pub fn differing(&self, other: &[AssetFolder]) -> Vec<String> {
+        if other.is_empty() {
+            return Vec::new();
+        }
+        let mut differing = self
+            .folders
+            .iter()
+            .filter(|folder| !other.contains(folder))
+            .map(|folder| folder.name.clone())
+            .collect::<Vec<_>>();
+        for folder in other {
+            if !self.folders.iter().any(|own| own.name == folder.name) {
+                differing.push(folder.name.clone());
+            }
+        }
+        differing
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Couldn't read {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bevy::{
+    audio::{play_queued_audio_system, AudioOutput, Decodable},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use rodio::{Decoder, Source};
+
+use crate::{
+    components::Storm, phase::BattleResolved, settings::Settings, Screen, STATE_CHANGE_STAGE,
+};
+
+/// Loops under the menus and the game alike
+const MUSIC: &str = "audio/desert_wind.mp3";
+
+pub struct AudioPlugin;
+
+impl Plugin for AudioPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_thread_local_resource::<AudioOutput<Sound>>()
+            .add_asset::<Sound>()
+            .init_resource::<Audio<Sound>>()
+            .add_system_to_stage(
+                stage::POST_UPDATE,
+                play_queued_audio_system::<Sound>.system(),
+            )
+            .add_event::<SoundEffect>()
+            .init_resource::<Sounds>()
+            .add_startup_system(load_sounds.system())
+            .add_system(volume_system.system())
+            .add_system(music_system.system())
+            .add_system(sound_effect_system.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                storm_sound_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                storm_sound_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                battle_sound_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                battle_sound_system.system(),
+            );
+    }
+}
+
+/// Something happened in the game that deserves a noise
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum SoundEffect {
+    CardFlip,
+    TokenPlaced,
+    StormMoved,
+    BattleResolved,
+}
+
+impl SoundEffect {
+    const ALL: [SoundEffect; 4] = [
+        SoundEffect::CardFlip,
+        SoundEffect::TokenPlaced,
+        SoundEffect::StormMoved,
+        SoundEffect::BattleResolved,
+    ];
+
+    fn path(&self) -> &'static str {
+        match self {
+            SoundEffect::CardFlip => "audio/card_flip.mp3",
+            SoundEffect::TokenPlaced => "audio/token_place.mp3",
+            SoundEffect::StormMoved => "audio/storm.mp3",
+            SoundEffect::BattleResolved => "audio/battle.mp3",
+        }
+    }
+}
+
+/// A volume shared with every sound already playing, so changes are heard straight away
+#[derive(Clone)]
+struct Level(Arc<AtomicU32>);
+
+impl Level {
+    fn new(volume: f32) -> Self {
+        Level(Arc::new(AtomicU32::new(volume.to_bits())))
+    }
+
+    fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn set(&self, volume: f32) {
+        self.0.store(volume.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Bevy's own audio plays everything at full volume, so sounds go through here instead
+#[derive(Clone, TypeUuid)]
+#[uuid = "5b1f04f8-5d53-4c0b-9a3e-3c1e6a1f2d7e"]
+pub struct Sound {
+    source: AudioSource,
+    level: Level,
+    looping: bool,
+}
+
+impl Decodable for Sound {
+    type Decoder = LeveledDecoder;
+
+    fn decoder(&self) -> Self::Decoder {
+        LeveledDecoder {
+            decoder: Decoder::new(Cursor::new(self.source.clone())).unwrap(),
+            sound: self.clone(),
+        }
+    }
+}
+
+pub struct LeveledDecoder {
+    decoder: Decoder<Cursor<AudioSource>>,
+    sound: Sound,
+}
+
+impl Iterator for LeveledDecoder {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = match self.decoder.next() {
+            Some(sample) => sample,
+            None if self.sound.looping => {
+                self.decoder = Decoder::new(Cursor::new(self.sound.source.clone())).ok()?;
+                self.decoder.next()?
+            }
+            None => return None,
+        };
+        Some((sample as f32 * self.sound.level.get()) as i16)
+    }
+}
+
+impl Source for LeveledDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.decoder.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.decoder.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.decoder.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        if self.sound.looping {
+            None
+        } else {
+            self.decoder.total_duration()
+        }
+    }
+}
+
+struct Sounds {
+    music: Handle<AudioSource>,
+    effects: HashMap<SoundEffect, Handle<AudioSource>>,
+    music_level: Level,
+    effects_level: Level,
+    music_playing: bool,
+}
+
+impl Default for Sounds {
+    fn default() -> Self {
+        Sounds {
+            music: Handle::default(),
+            effects: HashMap::new(),
+            music_level: Level::new(0.0),
+            effects_level: Level::new(0.0),
+            music_playing: false,
+        }
+    }
+}
+
+fn load_sounds(asset_server: Res<AssetServer>, mut sounds: ResMut<Sounds>) {
+    sounds.music = asset_server.load(MUSIC);
+    sounds.effects = SoundEffect::ALL
+        .iter()
+        .map(|&effect| (effect, asset_server.load(effect.path())))
+        .collect();
+}
+
+fn volume_system(settings: Res<Settings>, sounds: Res<Sounds>) {
+    sounds
+        .music_level
+        .set(settings.volume * settings.music_volume);
+    sounds
+        .effects_level
+        .set(settings.volume * settings.effects_volume);
+}
+
+/// Starts the music once it has loaded. A missing track just means silence.
+fn music_system(
+    mut sounds: ResMut<Sounds>,
+    sources: Res<Assets<AudioSource>>,
+    mut music: ResMut<Assets<Sound>>,
+    audio: Res<Audio<Sound>>,
+) {
+    if sounds.music_playing {
+        return;
+    }
+    if let Some(source) = sources.get(&sounds.music) {
+        audio.play(music.add(Sound {
+            source: source.clone(),
+            level: sounds.music_level.clone(),
+            looping: true,
+        }));
+        sounds.music_playing = true;
+    }
+}
+
+fn sound_effect_system(
+    sounds: Res<Sounds>,
+    (events, mut reader): (Res<Events<SoundEffect>>, Local<EventReader<SoundEffect>>),
+    sources: Res<Assets<AudioSource>>,
+    mut effects: ResMut<Assets<Sound>>,
+    audio: Res<Audio<Sound>>,
+) {
+    let mut played = Vec::new();
+    for &effect in reader.iter(&events) {
+        // The same effect several times in one frame would just be louder
+        if played.contains(&effect) {
+            continue;
+        }
+        played.push(effect);
+        if let Some(source) = sounds
+            .effects
+            .get(&effect)
+            .and_then(|handle| sources.get(handle))
+        {
+            audio.play(effects.add(Sound {
+                source: source.clone(),
+                level: sounds.effects_level.clone(),
+                looping: false,
+            }));
+        }
+    }
+}
+
+fn storm_sound_system(
+    mut sound_effects: ResMut<Events<SoundEffect>>,
+    storm: Query<&Storm, Mutated<Storm>>,
+) {
+    if storm.iter().next().is_some() {
+        sound_effects.send(SoundEffect::StormMoved);
+    }
+}
+
+fn battle_sound_system(
+    events: Res<Events<BattleResolved>>,
+    mut reader: Local<EventReader<BattleResolved>>,
+    mut sound_effects: ResMut<Events<SoundEffect>>,
+) {
+    // Battles revealed together only need the one noise
+    if reader.iter(&events).last().is_some() {
+        sound_effects.send(SoundEffect::BattleResolved);
+    }
+}
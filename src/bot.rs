@@ -0,0 +1,1197 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use rand::{prelude::SliceRandom, Rng};
+
+use crate::{
+    cards::{CardPlays, CardRequest},
+    components::{
+        Dead, Disorganized, LocationSector, Player, Prediction, Spice, Storm, Troop, Unique,
+    },
+    data::{Faction, Leader, Terrain, TraitorCard, TreacheryCard},
+    dune_core::{Occupancy, LAST_TURN},
+    game_action::{GameAction, GameActions},
+    menu::Lobby,
+    network::{Latencies, Network, NetworkType},
+    phase::{
+        Action, ActionQueue, Auction, Battle, BattlePlan, BiddingSubPhase, Context, GamePhase,
+        Movement, MovementRequest, Phase, PhaseEnded, PhaseStarted, PredictionPick,
+        PredictionPicks, Revival, RevivalRequest, StormSubPhase, TraitorPick, TraitorPicks,
+        TroopsMoved,
+    },
+    resources::{Info, RuleSet, SectorGraph, SectorNode},
+    storm_dial::{DialPick, StormDial, DIAL_MAX},
+    Screen, STATE_CHANGE_STAGE,
+};
+
+/// Seconds a bot waits after making a decision so humans can follow along
+const THINK_TIME: f32 = 0.5;
+/// Most troops a bot will send anywhere in one go
+const MAX_SHIPMENT: i32 = 6;
+/// Seconds the game waits on a disconnected player before a bot covers for them
+const STANDIN_DELAY: f32 = 30.0;
+
+pub struct BotPlugin;
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<BotSettings>()
+            .init_resource::<Departures>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                assign_bots_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                standin_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                departure_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                bot_prediction_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                bot_placement_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                bot_bidding_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                bot_revival_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                bot_movement_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                bot_battle_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                bot_card_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                bot_traitor_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                bot_storm_dial_system.system(),
+            );
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+}
+
+impl Difficulty {
+    pub fn agent(&self) -> Box<dyn Agent> {
+        match self {
+            Difficulty::Easy => Box::new(EasyAgent),
+            Difficulty::Normal => Box::new(NormalAgent),
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Easy,
+        }
+    }
+}
+
+impl std::fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difficulty::Easy => write!(f, "Easy"),
+            Difficulty::Normal => write!(f, "Normal"),
+        }
+    }
+}
+
+pub struct BotSettings {
+    pub difficulty: Difficulty,
+}
+
+impl Default for BotSettings {
+    fn default() -> Self {
+        BotSettings {
+            difficulty: Difficulty::Normal,
+        }
+    }
+}
+
+/// Marks a player whose faction nobody claimed in the lobby. Every machine knows which factions
+/// are bots so nobody can act for them, but only the host asks the agent for decisions.
+pub struct Bot {
+    pub agent: Box<dyn Agent>,
+}
+
+/// What a bot knows about itself when making a decision
+pub struct BotView {
+    pub faction: Faction,
+    pub turn: i32,
+    pub spice: i32,
+    pub cards: usize,
+}
+
+impl BotView {
+    fn new(player: &Player, info: &Info) -> Self {
+        BotView {
+            faction: player.faction,
+            turn: info.turn,
+            spice: player.spice,
+            cards: player.treachery_cards.len(),
+        }
+    }
+}
+
+/// Where every faction's troops and all the spice on the board are
+pub struct Board {
+    pub troops: HashMap<SectorNode, HashMap<Faction, i32>>,
+    pub spice: HashMap<SectorNode, i32>,
+    pub reserves: HashMap<Faction, i32>,
+    pub storm_sector: Option<i32>,
+}
+
+impl Board {
+    fn new(
+        storm: &Query<&Storm>,
+        sectors: &Query<&LocationSector>,
+        troops: &Query<(&Troop, &Unique), Without<Dead>>,
+        spice: &Query<&Spice, Without<Unique>>,
+    ) -> Self {
+        let node_of = |entity: Entity| {
+            sectors
+                .get(entity)
+                .ok()
+                .map(|loc_sec| SectorNode::new(&loc_sec.location.name, loc_sec.sector))
+        };
+        let mut board = Board {
+            troops: HashMap::new(),
+            spice: HashMap::new(),
+            reserves: HashMap::new(),
+            storm_sector: storm.iter().next().map(|storm| storm.sector),
+        };
+        for (troop, unique) in troops.iter() {
+            match troop.location.and_then(node_of) {
+                Some(node) => {
+                    *board
+                        .troops
+                        .entry(node)
+                        .or_default()
+                        .entry(unique.faction)
+                        .or_insert(0) += 1
+                }
+                None => *board.reserves.entry(unique.faction).or_insert(0) += 1,
+            }
+        }
+        for spice in spice.iter() {
+            if let Some(node) = spice.location.and_then(node_of) {
+                *board.spice.entry(node).or_insert(0) += spice.value;
+            }
+        }
+        board
+    }
+
+    pub fn reserves(&self, faction: Faction) -> i32 {
+        self.reserves.get(&faction).copied().unwrap_or(0)
+    }
+
+    /// Troops per faction across every sector of a territory
+    pub fn occupants(&self, location: &str) -> HashMap<Faction, i32> {
+        let mut occupants = HashMap::new();
+        for (node, factions) in self.troops.iter() {
+            if node.location == location {
+                for (&faction, &count) in factions.iter() {
+                    *occupants.entry(faction).or_insert(0) += count;
+                }
+            }
+        }
+        occupants
+    }
+
+    pub fn enemies(&self, faction: Faction, location: &str) -> i32 {
+        self.occupants(location)
+            .into_iter()
+            .filter(|&(other, _)| other != faction)
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// Every stack of the faction's troops on the board
+    pub fn stacks(&self, faction: Faction) -> Vec<(SectorNode, i32)> {
+        let mut stacks = self
+            .troops
+            .iter()
+            .filter_map(|(node, factions)| {
+                factions.get(&faction).map(|&count| (node.clone(), count))
+            })
+            .collect::<Vec<_>>();
+        stacks.sort_by(|(node1, _), (node2, _)| {
+            (&node1.location, node1.sector).cmp(&(&node2.location, node2.sector))
+        });
+        stacks
+    }
+}
+
+/// Makes every decision for a bot. Implementations only need to propose legal moves, the phase
+/// systems validate them exactly like requests coming from a human.
+pub trait Agent: Send + Sync {
+    fn predict(&self, factions: &[Faction]) -> (Faction, i32);
+    fn pick_traitor(&self, faction: Faction, leaders: &[Leader]) -> usize;
+    fn place_troops(&self, faction: Faction, options: &[SectorNode], count: i32)
+        -> Vec<SectorNode>;
+    fn bid(&self, view: &BotView, high_bid: i32) -> Option<i32>;
+    fn revive(&self, view: &BotView, dead: i32, leaders: &[Leader]) -> RevivalRequest;
+    fn ship(&self, view: &BotView, board: &Board, graph: &SectorGraph) -> MovementRequest;
+    fn move_troops(
+        &self,
+        view: &BotView,
+        board: &Board,
+        graph: &SectorGraph,
+        range: i32,
+    ) -> MovementRequest;
+    fn battle_plan(
+        &self,
+        view: &BotView,
+        graph: &SectorGraph,
+        location: &str,
+        troops: i32,
+        leaders: &[Leader],
+        cards: &[TreacheryCard],
+    ) -> BattlePlan;
+
+    /// Nothing is known about where the storm will start, so any number is as good as another
+    fn dial_storm(&self) -> i32 {
+        rand::thread_rng().gen_range(0..=DIAL_MAX)
+    }
+}
+
+/// Every sector the faction could ship troops to right now
+fn shipping_targets(faction: Faction, board: &Board, graph: &SectorGraph) -> Vec<SectorNode> {
+    let mut targets = if faction == Faction::Fremen {
+        // The Fremen can only bring troops in near The Great Flat
+        graph
+            .sectors_of("The Great Flat")
+            .flat_map(|flat| graph.reachable(flat, 2, board.storm_sector))
+            .collect::<Vec<_>>()
+    } else {
+        graph
+            .edges
+            .keys()
+            .filter(|node| !graph.in_storm(node, board.storm_sector))
+            .cloned()
+            .collect()
+    };
+    targets.sort_by(|node1, node2| {
+        (&node1.location, node1.sector).cmp(&(&node2.location, node2.sector))
+    });
+    targets.dedup();
+    targets
+}
+
+/// Largest shipment up to `limit` troops that costs at most `budget`
+fn affordable_shipment(faction: Faction, terrain: Terrain, limit: i32, budget: i32) -> i32 {
+    (1..=limit)
+        .rev()
+        .find(|&count| faction.shipping_cost(terrain, count) <= budget)
+        .unwrap_or(0)
+}
+
+/// Plays it safe: only buys a card when empty handed, revives for free and rarely ships
+pub struct EasyAgent;
+
+impl Agent for EasyAgent {
+    fn predict(&self, factions: &[Faction]) -> (Faction, i32) {
+        let mut rng = rand::thread_rng();
+        let faction = factions
+            .iter()
+            .filter(|&&faction| faction != Faction::BeneGesserit)
+            .copied()
+            .collect::<Vec<_>>()
+            .choose(&mut rng)
+            .copied()
+            .unwrap_or(Faction::Atreides);
+        (faction, rng.gen_range(1..=LAST_TURN))
+    }
+
+    fn pick_traitor(&self, faction: Faction, leaders: &[Leader]) -> usize {
+        // Any leader from another faction will do
+        leaders
+            .iter()
+            .position(|leader| leader.faction != faction)
+            .unwrap_or(0)
+    }
+
+    fn place_troops(&self, _: Faction, options: &[SectorNode], count: i32) -> Vec<SectorNode> {
+        options
+            .choose(&mut rand::thread_rng())
+            .map(|node| vec![node.clone(); count.max(0) as usize])
+            .unwrap_or_default()
+    }
+
+    fn bid(&self, view: &BotView, high_bid: i32) -> Option<i32> {
+        Some(high_bid + 1).filter(|&bid| view.cards == 0 && bid <= view.spice / 4)
+    }
+
+    fn revive(&self, view: &BotView, dead: i32, _: &[Leader]) -> RevivalRequest {
+        RevivalRequest {
+            faction: view.faction,
+            troops: dead.min(view.faction.free_revivals()),
+            leader: None,
+        }
+    }
+
+    fn ship(&self, view: &BotView, board: &Board, graph: &SectorGraph) -> MovementRequest {
+        let count = board.reserves(view.faction).min(2);
+        let empty = shipping_targets(view.faction, board, graph)
+            .into_iter()
+            .filter(|node| {
+                graph.terrain[&node.location] == Terrain::Stronghold
+                    && board.occupants(&node.location).is_empty()
+                    && view.faction.shipping_cost(Terrain::Stronghold, count) <= view.spice
+            })
+            .collect::<Vec<_>>();
+        match empty.choose(&mut rand::thread_rng()) {
+            Some(node) if count > 0 => MovementRequest::Ship {
+                faction: view.faction,
+                to: node.clone(),
+                count,
+            },
+            _ => MovementRequest::Pass {
+                faction: view.faction,
+            },
+        }
+    }
+
+    fn move_troops(&self, view: &BotView, _: &Board, _: &SectorGraph, _: i32) -> MovementRequest {
+        MovementRequest::Pass {
+            faction: view.faction,
+        }
+    }
+
+    fn battle_plan(
+        &self,
+        view: &BotView,
+        _: &SectorGraph,
+        location: &str,
+        troops: i32,
+        leaders: &[Leader],
+        _: &[TreacheryCard],
+    ) -> BattlePlan {
+        BattlePlan {
+            faction: view.faction,
+            location: location.to_string(),
+            leader: leaders
+                .iter()
+                .min_by_key(|leader| leader.power)
+                .map(|leader| leader.name.clone()),
+            troops: (troops + 1) / 2,
+            spice: 0,
+            weapon: None,
+            defense: None,
+        }
+    }
+}
+
+/// Goes after empty strongholds and spice, and bids harder while its hand is empty
+pub struct NormalAgent;
+
+impl NormalAgent {
+    /// Spice kept back from shipping so there is something left to bid with
+    const SPICE_RESERVE: i32 = 2;
+}
+
+impl Agent for NormalAgent {
+    fn predict(&self, factions: &[Faction]) -> (Faction, i32) {
+        let mut rng = rand::thread_rng();
+        // The strong starting positions are the most likely to win early
+        let favourites = [Faction::Harkonnen, Faction::Atreides, Faction::Emperor];
+        let faction = favourites
+            .iter()
+            .filter(|faction| factions.contains(faction))
+            .copied()
+            .collect::<Vec<_>>()
+            .choose(&mut rng)
+            .copied()
+            .unwrap_or(Faction::Atreides);
+        (faction, rng.gen_range(3..=8))
+    }
+
+    fn pick_traitor(&self, faction: Faction, leaders: &[Leader]) -> usize {
+        // The strongest enemy leader swings the most battles
+        leaders
+            .iter()
+            .enumerate()
+            .filter(|(_, leader)| leader.faction != faction)
+            .max_by_key(|(_, leader)| leader.power)
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn place_troops(
+        &self,
+        faction: Faction,
+        options: &[SectorNode],
+        count: i32,
+    ) -> Vec<SectorNode> {
+        // Hide the Bene Gesserit advisor somewhere the storm can't reach
+        if faction == Faction::BeneGesserit {
+            if let Some(node) = options.iter().find(|node| node.location == "Polar Sink") {
+                return vec![node.clone(); count.max(0) as usize];
+            }
+        }
+        // Spread out over every allowed territory
+        let mut territories = options.to_vec();
+        territories.dedup_by(|node1, node2| node1.location == node2.location);
+        if territories.is_empty() {
+            return Vec::new();
+        }
+        (0..count.max(0) as usize)
+            .map(|i| territories[i % territories.len()].clone())
+            .collect()
+    }
+
+    fn bid(&self, view: &BotView, high_bid: i32) -> Option<i32> {
+        // Spice is worth little in the last few turns, so spend it
+        let cap = if view.cards == 0 || view.turn + 3 >= LAST_TURN {
+            view.spice * 2 / 3
+        } else {
+            view.spice / 3
+        };
+        Some(high_bid + 1).filter(|&bid| bid <= cap)
+    }
+
+    fn revive(&self, view: &BotView, dead: i32, leaders: &[Leader]) -> RevivalRequest {
+        let faction = view.faction;
+        let dead = dead.min(faction.revival_limit());
+        let mut troops = dead.min(faction.free_revivals());
+        while troops < dead && faction.revival_cost(troops + 1) <= view.spice / 2 {
+            troops += 1;
+        }
+        let budget = view.spice - faction.revival_cost(troops);
+        let leader = leaders
+            .iter()
+            .filter(|leader| leader.power <= budget)
+            .max_by_key(|leader| leader.power)
+            .map(|leader| leader.name.clone());
+        RevivalRequest {
+            faction,
+            troops,
+            leader,
+        }
+    }
+
+    fn ship(&self, view: &BotView, board: &Board, graph: &SectorGraph) -> MovementRequest {
+        let faction = view.faction;
+        let reserves = board.reserves(faction).min(MAX_SHIPMENT);
+        let budget = if faction == Faction::Fremen {
+            view.spice
+        } else {
+            view.spice - Self::SPICE_RESERVE
+        };
+        let targets = shipping_targets(faction, board, graph);
+
+        // Take strongholds nobody else holds, topping up the weakest one first
+        let stronghold = targets
+            .iter()
+            .filter(|node| {
+                graph.terrain[&node.location] == Terrain::Stronghold
+                    && board.enemies(faction, &node.location) == 0
+                    && board
+                        .occupants(&node.location)
+                        .get(&faction)
+                        .map_or(true, |&count| count < MAX_SHIPMENT)
+            })
+            .min_by_key(|node| board.occupants(&node.location).get(&faction).copied());
+        if let Some(node) = stronghold {
+            let count = affordable_shipment(faction, Terrain::Stronghold, reserves, budget);
+            if count > 0 {
+                return MovementRequest::Ship {
+                    faction,
+                    to: node.clone(),
+                    count,
+                };
+            }
+        }
+
+        // Otherwise go harvest the richest uncontested spice
+        let field = targets
+            .iter()
+            .filter(|node| {
+                board.spice.get(node).map_or(false, |&spice| spice > 0)
+                    && board.occupants(&node.location).is_empty()
+            })
+            .max_by_key(|node| board.spice[node]);
+        if let Some(node) = field {
+            // Enough troops to carry the whole field away
+            let wanted = reserves.min((board.spice[node] + 2) / 3);
+            let count = affordable_shipment(faction, graph.terrain[&node.location], wanted, budget);
+            if count > 0 {
+                return MovementRequest::Ship {
+                    faction,
+                    to: node.clone(),
+                    count,
+                };
+            }
+        }
+        MovementRequest::Pass { faction }
+    }
+
+    fn move_troops(
+        &self,
+        view: &BotView,
+        board: &Board,
+        graph: &SectorGraph,
+        range: i32,
+    ) -> MovementRequest {
+        let faction = view.faction;
+        let mut best: Option<(i32, SectorNode, SectorNode, i32)> = None;
+        for (from, count) in board.stacks(faction) {
+            // Never give up a stronghold
+            if graph.terrain[&from.location] == Terrain::Stronghold {
+                continue;
+            }
+            for to in graph.reachable(&from, range, board.storm_sector) {
+                if to.location == from.location || board.enemies(faction, &to.location) > 0 {
+                    continue;
+                }
+                let score = match graph.terrain[&to.location] {
+                    Terrain::Stronghold => 100,
+                    _ => board.spice.get(&to).copied().unwrap_or(0),
+                };
+                if score > 0 && best.as_ref().map_or(true, |(best, ..)| score > *best) {
+                    best = Some((score, from.clone(), to, count));
+                }
+            }
+        }
+        match best {
+            Some((_, from, to, count)) => MovementRequest::Move {
+                faction,
+                from,
+                to,
+                count,
+            },
+            None => MovementRequest::Pass { faction },
+        }
+    }
+
+    fn battle_plan(
+        &self,
+        view: &BotView,
+        graph: &SectorGraph,
+        location: &str,
+        troops: i32,
+        leaders: &[Leader],
+        cards: &[TreacheryCard],
+    ) -> BattlePlan {
+        // Strongholds are worth everything, anywhere else keep half the troops alive
+        let troops = match graph.terrain.get(location) {
+            Some(Terrain::Stronghold) => troops,
+            _ => (troops + 1) / 2,
+        };
+        BattlePlan {
+            faction: view.faction,
+            location: location.to_string(),
+            leader: leaders
+                .iter()
+                .max_by_key(|leader| leader.power)
+                .map(|leader| leader.name.clone()),
+            troops,
+            spice: 0,
+            weapon: cards
+                .iter()
+                .find(|card| card.effect.is_weapon())
+                .map(|card| card.name.clone()),
+            defense: cards
+                .iter()
+                .find(|card| card.effect.is_defense())
+                .map(|card| card.name.clone()),
+        }
+    }
+}
+
+/// Hands every faction nobody picked in the lobby to a bot
+fn assign_bots_system(
+    commands: &mut Commands,
+    lobby: Res<Lobby>,
+    settings: Res<BotSettings>,
+    players: Query<(Entity, &Player), Added<Player>>,
+) {
+    // Without a lobby every faction is played from this machine
+    if lobby.seats.is_empty() {
+        return;
+    }
+    for (entity, player) in players.iter() {
+        if !lobby
+            .seats
+            .iter()
+            .any(|seat| seat.faction == Some(player.faction))
+        {
+            println!("{} will be played by a bot", player.faction);
+            commands.insert_one(
+                entity,
+                Bot {
+                    agent: settings.difficulty.agent(),
+                },
+            );
+        }
+    }
+}
+
+/// A bot covering for a player who dropped out, until they come back
+struct Standin;
+
+/// A faction whose player conceded. It is out of the game, and a bot answers for it in case
+/// anything was still waiting on it when it went.
+struct Conceded;
+
+/// Players who left the game from the pause menu, and whether they conceded on the way out
+#[derive(Default)]
+pub struct Departures {
+    pub left: Vec<(Faction, bool)>,
+}
+
+/// Gives a disconnected player a while to come back before a bot takes over their seat, so the
+/// game can't hang on them forever. Their seat is handed back as soon as they reconnect.
+fn standin_system(
+    commands: &mut Commands,
+    (time, latencies, settings): (Res<Time>, Res<Latencies>, Res<BotSettings>),
+    mut away: Local<HashMap<Faction, f32>>,
+    players: Query<(Entity, &Player, Option<&Standin>), Without<Conceded>>,
+) {
+    for (entity, player, standin) in players.iter() {
+        if latencies.connected(player.faction) {
+            away.remove(&player.faction);
+            if standin.is_some() {
+                println!("{} is back, taking over from their bot", player.faction);
+                commands.remove_one::<Bot>(entity);
+                commands.remove_one::<Standin>(entity);
+            }
+        } else if standin.is_none() {
+            let seconds = away.entry(player.faction).or_insert(0.0);
+            *seconds += time.delta_seconds();
+            if *seconds >= STANDIN_DELAY {
+                println!("{} disconnected, a bot will play for them", player.faction);
+                commands.insert(
+                    entity,
+                    (
+                        Bot {
+                            agent: settings.difficulty.agent(),
+                        },
+                        Standin,
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Someone who quits gets a stand-in straight away rather than after the usual wait, and keeps
+/// their seat to come back to. Someone who concedes takes their faction out of the game.
+fn departure_system(
+    commands: &mut Commands,
+    (mut departures, settings, mut info): (ResMut<Departures>, Res<BotSettings>, ResMut<Info>),
+    players: Query<(Entity, &Player), Without<Conceded>>,
+) {
+    for (faction, conceded) in departures.left.drain(..) {
+        let entity = if let Some((entity, _)) =
+            players.iter().find(|(_, player)| player.faction == faction)
+        {
+            entity
+        } else {
+            continue;
+        };
+        let bot = Bot {
+            agent: settings.difficulty.agent(),
+        };
+        if conceded {
+            println!("{} conceded and is out of the game", faction);
+            commands.remove_one::<Standin>(entity);
+            commands.insert(entity, (bot, Conceded));
+            if !info.eliminated.contains(&faction) {
+                info.eliminated.push(faction);
+            }
+        } else {
+            println!("{} left the game, a bot will play for them", faction);
+            commands.insert(entity, (bot, Standin));
+        }
+    }
+}
+
+/// Bots only make decisions on the machine running the game
+fn in_charge(network: &Network) -> bool {
+    network.network_type != NetworkType::Client
+}
+
+fn bot_prediction_system(
+    (network, info): (Res<Network>, Res<Info>),
+    (picks, mut actions): (Res<PredictionPicks>, ResMut<GameActions>),
+    bots: Query<&Bot>,
+    predictions: Query<&Prediction>,
+) {
+    if !in_charge(&network)
+        || info.context != Context::Predicting
+        || !picks.requests.is_empty()
+        || actions.pending(Faction::BeneGesserit)
+    {
+        return;
+    }
+    let bot = if let Ok(bot) = bots.get(info.get_active_player()) {
+        bot
+    } else {
+        return;
+    };
+    if let Some(prediction) = predictions.iter().next() {
+        let (faction, turn) = bot.agent.predict(&info.factions_in_play);
+        let pick = if prediction.faction.is_none() {
+            PredictionPick::Faction { faction }
+        } else {
+            PredictionPick::Turn { turn }
+        };
+        actions.submitted.push_back(GameAction::Prediction { pick });
+    }
+}
+
+fn bot_placement_system(
+    commands: &mut Commands,
+    (network, graph): (Res<Network>, Res<SectorGraph>),
+    mut info: ResMut<Info>,
+    players: Query<(&Player, &Bot)>,
+    sectors: Query<(Entity, &LocationSector)>,
+    mut troops: Query<(&mut Troop, &Unique)>,
+) {
+    if !in_charge(&network) || info.context != Context::PlacingTroops {
+        return;
+    }
+    let (player, bot) = if let Ok(bot) = players.get(info.get_active_player()) {
+        bot
+    } else {
+        return;
+    };
+    let faction = player.faction;
+    let (num_troops, locations, _) = faction.initial_values();
+    let placed = troops
+        .iter_mut()
+        .filter(|(troop, unique)| unique.faction == faction && troop.location.is_some())
+        .count() as i32;
+    let mut options = graph
+        .edges
+        .keys()
+        .filter(|node| {
+            locations
+                .as_ref()
+                .map_or(true, |locations| locations.contains(&node.location))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    options.sort_by(|node1, node2| {
+        (&node1.location, node1.sector).cmp(&(&node2.location, node2.sector))
+    });
+
+    let nodes = bot
+        .agent
+        .place_troops(faction, &options, num_troops - placed);
+    let reserves = troops
+        .iter_mut()
+        .filter(|(troop, unique)| unique.faction == faction && troop.location.is_none());
+    for (node, (mut troop, _)) in nodes.iter().zip(reserves) {
+        if let Some((entity, _)) = sectors.iter().find(|(_, loc_sec)| {
+            loc_sec.location.name == node.location && loc_sec.sector == node.sector
+        }) {
+            troop.location = Some(entity);
+            commands.insert_one(entity, Disorganized);
+        }
+    }
+    println!("{} placed {} troops", faction, nodes.len());
+    info.context = Context::None;
+}
+
+fn bot_bidding_system(
+    (network, info, phase): (Res<Network>, Res<Info>, Res<GamePhase>),
+    mut queue: ResMut<ActionQueue>,
+    (auction, mut actions): (Res<Auction>, ResMut<GameActions>),
+    players: Query<(&Player, &Bot)>,
+) {
+    if !in_charge(&network)
+        || info.context != Context::Bidding
+        || !queue.is_empty()
+        || auction.cards.is_empty()
+        || auction.closed
+    {
+        return;
+    }
+    if let Phase::Bidding {
+        subphase: BiddingSubPhase::Bidding,
+    } = phase.phase
+    {
+    } else {
+        return;
+    }
+    // The bidder is only set once the card is up for auction
+    if let Some((player, bot)) = info
+        .active_player
+        .and_then(|entity| players.get(entity).ok())
+    {
+        if auction
+            .bids
+            .iter()
+            .any(|&(faction, _)| faction == player.faction)
+            || actions.pending(player.faction)
+        {
+            return;
+        }
+        let high = auction.high_bid.map(|(_, amount)| amount).unwrap_or(0);
+        let bid = bot.agent.bid(&BotView::new(player, &info), high);
+        queue.push_single(Action::Delay { time: THINK_TIME }.into());
+        actions.submitted.push_back(GameAction::Bid {
+            faction: player.faction,
+            amount: bid,
+        });
+    }
+}
+
+/// Swaps a request the phase systems already turned down this turn for a pass, so a bot can
+/// never get stuck asking for the same thing forever
+fn retry_guard<T: Clone + PartialEq>(
+    last: &mut Option<(i32, T)>,
+    turn: i32,
+    request: T,
+    pass: T,
+) -> T {
+    let request = if last.as_ref() == Some(&(turn, request.clone())) {
+        pass
+    } else {
+        request
+    };
+    *last = Some((turn, request.clone()));
+    request
+}
+
+fn bot_revival_system(
+    (network, info, phase): (Res<Network>, Res<Info>, Res<GamePhase>),
+    (mut queue, revival, mut actions): (ResMut<ActionQueue>, Res<Revival>, ResMut<GameActions>),
+    mut last: Local<Option<(i32, RevivalRequest)>>,
+    players: Query<(&Player, &Bot)>,
+    troops: Query<&Unique, (With<Troop>, With<Dead>)>,
+    leaders: Query<(&Leader, &Unique, Option<&Dead>)>,
+) {
+    if !in_charge(&network)
+        || phase.phase != Phase::Revival
+        || info.context != Context::Reviving
+        || !queue.is_empty()
+    {
+        return;
+    }
+    let (player, bot) = if let Ok(bot) = players.get(info.get_active_player()) {
+        bot
+    } else {
+        return;
+    };
+    let faction = player.faction;
+    if revival
+        .requests
+        .iter()
+        .any(|request| request.faction == faction)
+        || actions.pending(faction)
+    {
+        return;
+    }
+    let dead = troops
+        .iter()
+        .filter(|unique| unique.faction == faction)
+        .count() as i32;
+    let faction_leaders = leaders
+        .iter()
+        .filter(|(_, unique, _)| unique.faction == faction)
+        .collect::<Vec<_>>();
+    // Leaders only come back once they are all in the tanks
+    let revivable = if faction_leaders.iter().all(|(_, _, dead)| dead.is_some()) {
+        faction_leaders
+            .iter()
+            .map(|(leader, _, _)| (*leader).clone())
+            .collect::<Vec<_>>()
+    } else {
+        Vec::new()
+    };
+
+    let request = bot
+        .agent
+        .revive(&BotView::new(player, &info), dead, &revivable);
+    let request = retry_guard(
+        &mut last,
+        info.turn,
+        request,
+        RevivalRequest {
+            faction,
+            troops: 0,
+            leader: None,
+        },
+    );
+    queue.push_single(Action::Delay { time: THINK_TIME }.into());
+    actions.submitted.push_back(GameAction::Revival { request });
+}
+
+/// The last request a bot made, and the moves that went through since
+type LastMove<'a> = (
+    Local<'a, Option<(i32, MovementRequest)>>,
+    Local<'a, EventReader<TroopsMoved>>,
+);
+
+fn bot_movement_system(
+    (network, info, graph, occupancy): (Res<Network>, Res<Info>, Res<SectorGraph>, Res<Occupancy>),
+    (mut queue, movement, mut actions, events): (
+        ResMut<ActionQueue>,
+        Res<Movement>,
+        ResMut<GameActions>,
+        Res<Events<TroopsMoved>>,
+    ),
+    (mut last, mut reader): LastMove,
+    players: Query<(&Player, &Bot)>,
+    (storm, sectors): (Query<&Storm>, Query<&LocationSector>),
+    troops: Query<(&Troop, &Unique), Without<Dead>>,
+    spice: Query<&Spice, Without<Unique>>,
+) {
+    // A move that went through wasn't turned down, so the same one is fair to ask for again
+    for event in reader.iter(&events) {
+        if last
+            .as_ref()
+            .map_or(false, |(_, request)| request.faction() == event.faction)
+        {
+            *last = None;
+        }
+    }
+    if !in_charge(&network)
+        || (info.context != Context::Shipping && info.context != Context::Moving)
+        || !queue.is_empty()
+    {
+        return;
+    }
+    let (player, bot) = if let Ok(bot) = players.get(info.get_active_player()) {
+        bot
+    } else {
+        return;
+    };
+    let faction = player.faction;
+    if movement
+        .requests
+        .iter()
+        .any(|request| request.faction() == faction)
+        || actions.pending(faction)
+    {
+        return;
+    }
+    let board = Board::new(&storm, &sectors, &troops, &spice);
+    let view = BotView::new(player, &info);
+    let request = if info.context == Context::Shipping {
+        bot.agent.ship(&view, &board, &graph)
+    } else {
+        let range = occupancy.movement_range(faction);
+        bot.agent.move_troops(&view, &board, &graph, range)
+    };
+    let request = retry_guard(
+        &mut last,
+        info.turn,
+        request,
+        MovementRequest::Pass { faction },
+    );
+    queue.push_single(Action::Delay { time: THINK_TIME }.into());
+    actions
+        .submitted
+        .push_back(GameAction::Movement { request });
+}
+
+/// Bots lock in a battle plan for every territory they share with another faction
+fn bot_battle_system(
+    (network, info, graph): (Res<Network>, Res<Info>, Res<SectorGraph>),
+    (battle, rules, mut actions): (Res<Battle>, Res<RuleSet>, ResMut<GameActions>),
+    (players, cards): (Query<(&Player, &Bot)>, Query<&TreacheryCard>),
+    leaders: Query<(&Leader, &Unique), Without<Dead>>,
+    occupancy: Res<Occupancy>,
+    (started, ended): (Res<Events<PhaseStarted>>, Res<Events<PhaseEnded>>),
+    (mut start_reader, mut end_reader, mut in_battle): (
+        Local<EventReader<PhaseStarted>>,
+        Local<EventReader<PhaseEnded>>,
+        Local<bool>,
+    ),
+) {
+    if end_reader
+        .iter(&ended)
+        .any(|event| event.phase == Phase::Battle)
+    {
+        *in_battle = false;
+    }
+    if start_reader
+        .iter(&started)
+        .any(|event| event.phase == Phase::Battle)
+    {
+        *in_battle = true;
+    }
+    if !in_charge(&network) || !*in_battle {
+        return;
+    }
+    let battles = occupancy.co_located_enemies();
+    for (player, bot) in players.iter() {
+        let faction = player.faction;
+        for (location, _) in battles
+            .iter()
+            .filter(|(_, factions)| factions.contains(&faction))
+        {
+            let planned = |plan: &BattlePlan| plan.faction == faction && plan.location == *location;
+            if battle.plans.iter().any(planned)
+                || actions
+                    .submitted
+                    .iter()
+                    .any(|action| matches!(action, GameAction::Battle { plan } if planned(plan)))
+            {
+                continue;
+            }
+            let alive = leaders
+                .iter()
+                .filter(|(_, unique)| unique.faction == faction)
+                .map(|(leader, _)| leader.clone())
+                .collect::<Vec<_>>();
+            let hand = player
+                .treachery_cards
+                .iter()
+                .filter_map(|&card| cards.get(card).ok().cloned())
+                .collect::<Vec<_>>();
+            let count = occupancy.territory(location)[&faction];
+            let mut plan = bot.agent.battle_plan(
+                &BotView::new(player, &info),
+                &graph,
+                location,
+                count,
+                &alive,
+                &hand,
+            );
+            // Pay for as many troops as the bot can afford so they fight at full strength
+            if rules.spice_dialing() {
+                plan.spice = plan.troops.min(player.spice);
+            }
+            println!(
+                "{} is ready to fight in {} with {} troops",
+                faction, location, plan.troops
+            );
+            actions.submitted.push_back(GameAction::Battle { plan });
+        }
+    }
+}
+
+/// Bots hold on to Weather Control and Family Atomics rather than keep the storm waiting
+fn bot_card_system(
+    (network, info, phase): (Res<Network>, Res<Info>, Res<GamePhase>),
+    (plays, mut actions): (Res<CardPlays>, ResMut<GameActions>),
+    players: Query<&Player, With<Bot>>,
+) {
+    if !in_charge(&network)
+        || info.context != Context::Prompting
+        || !matches!(phase.phase, Phase::Storm { .. })
+    {
+        return;
+    }
+    if let Ok(player) = players.get(info.get_active_player()) {
+        let faction = player.faction;
+        if !plays.passed.contains(&faction)
+            && !plays
+                .requests
+                .iter()
+                .any(|request| request.faction() == faction)
+            && !actions.pending(faction)
+        {
+            actions.submitted.push_back(GameAction::CardPlay {
+                request: CardRequest::Pass { faction },
+            });
+        }
+    }
+}
+
+fn bot_traitor_system(
+    (network, info): (Res<Network>, Res<Info>),
+    (traitor_picks, mut actions): (Res<TraitorPicks>, ResMut<GameActions>),
+    players: Query<(&Player, &Bot)>,
+    traitor_cards: Query<&TraitorCard>,
+) {
+    if !in_charge(&network) || info.context != Context::PickingTraitors {
+        return;
+    }
+    let (player, bot) = if let Ok(bot) = players.get(info.get_active_player()) {
+        bot
+    } else {
+        return;
+    };
+    if player.traitor_cards.len() <= 1
+        || traitor_picks
+            .requests
+            .iter()
+            .any(|pick| pick.faction == player.faction)
+        || actions.pending(player.faction)
+    {
+        return;
+    }
+    let leaders = player
+        .traitor_cards
+        .iter()
+        .filter_map(|&card| traitor_cards.get(card).ok())
+        .map(|card| card.leader.clone())
+        .collect::<Vec<_>>();
+    if let Some(leader) = leaders.get(bot.agent.pick_traitor(player.faction, &leaders)) {
+        actions.submitted.push_back(GameAction::Traitor {
+            pick: TraitorPick {
+                faction: player.faction,
+                leader: leader.name.clone(),
+            },
+        });
+    }
+}
+
+fn bot_storm_dial_system(
+    (network, phase): (Res<Network>, Res<GamePhase>),
+    (dial, mut actions): (Res<StormDial>, ResMut<GameActions>),
+    players: Query<(&Player, &Bot)>,
+) {
+    let dialing = Phase::Storm {
+        subphase: StormSubPhase::Dial,
+    };
+    if !in_charge(&network) || phase.phase != dialing {
+        return;
+    }
+    for (player, bot) in players.iter() {
+        let faction = player.faction;
+        if !dial.dialers.contains(&faction)
+            || dial.dialed.contains(&faction)
+            || dial.requests.iter().any(|pick| pick.faction == faction)
+            || actions.pending(faction)
+        {
+            continue;
+        }
+        actions.submitted.push_back(GameAction::StormDial {
+            pick: DialPick {
+                faction,
+                value: bot.agent.dial_storm(),
+            },
+        });
+    }
+}
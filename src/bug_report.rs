@@ -0,0 +1,228 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::Write,
+    mem,
+};
+
+use bevy::prelude::*;
+use instant::{Duration, Instant};
+use zip::{result::ZipResult, write::FileOptions, ZipWriter};
+
+use crate::{
+    components::{Dead, Player, Spice, Storm, Troop, Unique},
+    data::Location,
+    desync::digest,
+    dune_core::Occupancy,
+    game_action::GameActions,
+    network::{Network, NetworkType, PROTOCOL_VERSION},
+    phase::{GamePhase, SpiceDeck},
+    resources::{GameRng, Info, RuleSet},
+    savegame::SaveRequest,
+    settings::{Binding, Settings},
+    util::unix_time,
+    Screen, STATE_CHANGE_STAGE,
+};
+
+const REPORT_DIR: &str = "bug_reports";
+/// How much play the recording keeps
+const RECORD_TIME: Duration = Duration::from_secs(5 * 60);
+/// Longer entries are cut short, since resyncs and replicas carry the whole game
+const MAX_ENTRY_LEN: usize = 500;
+/// How long the host waits on the save system before writing the report without the game
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct BugReportPlugin;
+
+impl Plugin for BugReportPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<BugRecorder>()
+            .add_system(record_input_system.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                bug_report_system.system(),
+            );
+    }
+}
+
+/// The last few minutes of what arrived over the network and what was done at this machine, kept
+/// only while the setting is on
+pub struct BugRecorder {
+    enabled: bool,
+    started: Instant,
+    entries: VecDeque<(Duration, String)>,
+    /// Set to write out a report as soon as the game can be packed up with it
+    requested: bool,
+}
+
+impl Default for BugRecorder {
+    fn default() -> Self {
+        BugRecorder {
+            enabled: false,
+            started: Instant::now(),
+            entries: VecDeque::new(),
+            requested: false,
+        }
+    }
+}
+
+impl BugRecorder {
+    /// Adds an entry, which is only worked out while recording
+    pub fn record(&mut self, entry: impl FnOnce() -> String) {
+        if !self.enabled {
+            return;
+        }
+        let mut entry = entry();
+        if let Some((cut, _)) = entry.char_indices().nth(MAX_ENTRY_LEN) {
+            entry.truncate(cut);
+            entry.push_str("...");
+        }
+        let now = self.started.elapsed();
+        self.entries.push_back((now, entry));
+        while self
+            .entries
+            .front()
+            .map_or(false, |&(at, _)| now - at > RECORD_TIME)
+        {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    fn log(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(at, entry)| {
+                format!(
+                    "[{:>4}.{:03}] {}\n",
+                    at.as_secs(),
+                    at.subsec_millis(),
+                    entry
+                )
+            })
+            .collect()
+    }
+}
+
+/// Follows the setting, and notes down every decision made and key bound to an action pressed here
+fn record_input_system(
+    (settings, keyboard_input): (Res<Settings>, Res<Input<KeyCode>>),
+    mut recorder: ResMut<BugRecorder>,
+    mut actions: ResMut<GameActions>,
+) {
+    if recorder.enabled != settings.bug_capture {
+        recorder.enabled = settings.bug_capture;
+        recorder.entries.clear();
+    }
+    for action in actions.made_here.drain(..) {
+        recorder.record(|| format!("made {:?}", action));
+    }
+    for &binding in Binding::ALL.iter() {
+        if settings.just_pressed(&keyboard_input, binding) {
+            recorder.record(|| format!("pressed {}", binding));
+        }
+    }
+}
+
+/// Writes the recording out to a zip along with what every machine can see of the game. The host
+/// adds the seed, and waits a frame for the save system to add the whole game so it can be loaded
+/// straight back up.
+fn bug_report_system(
+    (network, game_rng, rules): (Res<Network>, Res<GameRng>, Res<RuleSet>),
+    (mut recorder, mut save, mut waiting): (
+        ResMut<BugRecorder>,
+        ResMut<SaveRequest>,
+        Local<Option<Instant>>,
+    ),
+    (info, phase, spice_deck, occupancy): (
+        Res<Info>,
+        Res<GamePhase>,
+        Res<SpiceDeck>,
+        Res<Occupancy>,
+    ),
+    (players, storm): (Query<&Player>, Query<&Storm>),
+    tanked: Query<&Unique, (With<Troop>, With<Dead>)>,
+    (spice, names): (Query<&Spice>, Query<&Location>),
+) {
+    let host = network.network_type != NetworkType::Client;
+    if mem::take(&mut recorder.requested) {
+        *waiting = Some(Instant::now());
+        save.report = host;
+    }
+    let since = if let Some(since) = *waiting {
+        since
+    } else {
+        return;
+    };
+    if save.report {
+        if since.elapsed() < SNAPSHOT_TIMEOUT {
+            return;
+        }
+        println!("The game wasn't packed up in time, so the report goes without it");
+        save.report = false;
+    }
+    *waiting = None;
+    let snapshot = save.snapshot.take();
+
+    // A browser has nowhere to put the file
+    if cfg!(target_arch = "wasm32") {
+        println!("Bug reports can't be written from the browser");
+        return;
+    }
+    // The seed never leaves the host, so a client's report mustn't carry whatever it has
+    let seed = if host {
+        format!("seed: {}\n", game_rng.seed)
+    } else {
+        String::new()
+    };
+    let about = format!(
+        "{}protocol: {}\nrules: {}\nturn: {}\nphase: {:?}\nrecording: {}\n",
+        seed,
+        PROTOCOL_VERSION,
+        *rules,
+        info.turn,
+        phase.phase,
+        if recorder.enabled { "on" } else { "off" },
+    );
+    let state = digest(
+        (&info, &phase.phase, &spice_deck, &occupancy),
+        &rules,
+        &players,
+        &storm,
+        &tanked,
+        &spice,
+        &names,
+    )
+    .into_iter()
+    .map(|section| format!("{}: {}\n", section.name, section.summary))
+    .collect::<String>();
+    let log = recorder.log();
+    let mut files = vec![
+        ("about.txt", about.as_bytes()),
+        ("state.txt", state.as_bytes()),
+        ("log.txt", log.as_bytes()),
+    ];
+    if let Some(snapshot) = snapshot.as_ref() {
+        files.push(("game.sav", &snapshot[..]));
+    }
+    match write_report(&files) {
+        Ok(path) => println!("Bug report written to {}", path),
+        Err(err) => println!("Failed to write bug report: {}", err),
+    }
+}
+
+fn write_report(files: &[(&str, &[u8])]) -> ZipResult<String> {
+    fs::create_dir_all(REPORT_DIR)?;
+    let path = format!("{}/report_{}.zip", REPORT_DIR, unix_time());
+    let mut zip = ZipWriter::new(File::create(&path)?);
+    for &(name, bytes) in files {
+        zip.start_file(name, FileOptions::default())?;
+        zip.write_all(bytes)?;
+    }
+    zip.finish()?;
+    Ok(path)
+}
@@ -0,0 +1,576 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rand::seq::SliceRandom;
+use rkyv::{Archive, Unarchive};
+
+use crate::{
+    alliance::own_faction,
+    bot::Bot,
+    cards::CardEffects,
+    components::{Dead, Player, Unique},
+    data::{Faction, FactionAbilities, Leader},
+    game_action::{submit, GameAction, GameActions},
+    hud::HudMaterials,
+    layout::{Anchored, AnchoredHeight},
+    lerper::{Lerp, LerpType},
+    locale::Tr,
+    menu::{ButtonMaterials, Lobby},
+    network::{Client, Network, NetworkRole, NetworkType, Server},
+    notification::{Notice, Notifications},
+    phase::{BattleResolved, GamePhase},
+    protocol::ServerEvent,
+    resources::{Data, GameRng, Info, RuleSet},
+    wheel::BattleWheels,
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// What the Harkonnen are paid for killing a captured leader
+pub const KILL_REWARD: i32 = 2;
+/// How far apart leader tokens are when stacked
+const LEADER_HEIGHT: f32 = 0.0072;
+
+type CaptureButtons<'a, 'b, 'c, 'd> = Query<
+    'a,
+    (
+        &'b Interaction,
+        &'c CaptureButton,
+        &'d mut Handle<ColorMaterial>,
+    ),
+    (Mutated<Interaction>, With<Button>),
+>;
+
+pub struct CapturePlugin;
+
+impl Plugin for CapturePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Captures>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                capture_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                bot_capture_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                captive_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                captive_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                capture_panel_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                capture_panel_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                capture_button_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                capture_button_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system());
+    }
+}
+
+/// A leader taken in battle. Under hidden captures only the captor and the owner are told who it
+/// is, so everyone else has `leader` left empty.
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct CapturedLeader {
+    pub by: Faction,
+    pub owner: Faction,
+    pub leader: Option<String>,
+}
+
+impl CapturedLeader {
+    /// What `viewer` may know about the capture, everything when nothing is hidden
+    pub fn seen_by(&self, viewer: Option<Faction>, hidden: bool) -> Self {
+        let known = !hidden || viewer == Some(self.by) || viewer == Some(self.owner);
+        CapturedLeader {
+            leader: self.leader.clone().filter(|_| known),
+            ..self.clone()
+        }
+    }
+}
+
+/// Marks a leader token standing behind its captor's shield
+pub struct Captive {
+    pub by: Faction,
+}
+
+/// Leaders the Harkonnen have taken. A captive waits on whether it is killed for spice or kept,
+/// and a kept one fights for its captor in one battle before going home.
+#[derive(Default)]
+pub struct Captures {
+    /// Captures the captor has yet to decide on, oldest first
+    pub pending: Vec<CapturedLeader>,
+    pub held: Vec<CapturedLeader>,
+    /// Decisions for the host to settle: who made them, and whether to kill
+    pub requests: VecDeque<(Faction, bool)>,
+    /// Leaders killed since their tokens were last moved
+    killed: Vec<CapturedLeader>,
+}
+
+impl Captures {
+    /// Takes on the host's view of the captures
+    pub fn sync(
+        &mut self,
+        pending: Vec<CapturedLeader>,
+        held: Vec<CapturedLeader>,
+        killed: Vec<CapturedLeader>,
+    ) {
+        self.pending = pending;
+        self.held = held;
+        // Several updates can come in before the tokens are next moved
+        self.killed.extend(killed);
+    }
+}
+
+/// Tells every client about the captures, leaving out the leaders they may not know about
+fn send_captures(
+    server: &mut Server,
+    captures: &Captures,
+    killed: &[CapturedLeader],
+    (hidden, in_play): (bool, &[Faction]),
+) {
+    let event = |viewer: Option<Faction>, hidden: bool| {
+        let seen = |list: &[CapturedLeader]| {
+            list.iter()
+                .map(|capture| capture.seen_by(viewer, hidden))
+                .collect()
+        };
+        ServerEvent::Captures {
+            pending: seen(&captures.pending),
+            held: seen(&captures.held),
+            killed: seen(killed),
+        }
+    };
+    server.broadcast(&event(None, hidden));
+    if !hidden {
+        return;
+    }
+    for &faction in in_play {
+        if let Some(address) = server.address_of(faction) {
+            server.send_event(address, &event(Some(faction), true));
+        }
+    }
+    for address in server.casters() {
+        server.send_event(address, &event(None, false));
+    }
+}
+
+/// Takes a random leader from whoever the Harkonnen beat, settles whether captives are killed or
+/// kept, and sends kept ones home once they have fought
+fn capture_system(
+    (network, rules, info, wheels): (Res<Network>, Res<RuleSet>, Res<Info>, Res<BattleWheels>),
+    (abilities, card_effects, phase): (Res<FactionAbilities>, Res<CardEffects>, Res<GamePhase>),
+    (mut captures, mut game_rng, mut notifications, mut actions): (
+        ResMut<Captures>,
+        ResMut<GameRng>,
+        ResMut<Notifications>,
+        ResMut<GameActions>,
+    ),
+    events: Res<Events<BattleResolved>>,
+    mut reader: Local<EventReader<BattleResolved>>,
+    mut players: Query<&mut Player>,
+    leaders: Query<(&Leader, &Unique), Without<Dead>>,
+    mut server: Query<&mut Server>,
+) {
+    if network.network_type == NetworkType::Client {
+        return;
+    }
+    let mut changed = false;
+    let mut notices = Vec::new();
+    for event in reader.iter(&events) {
+        let plans = wheels
+            .revealed
+            .iter()
+            .filter(|plan| plan.location == event.location)
+            .collect::<Vec<_>>();
+        // A captive only fights for its captor once
+        for plan in plans.iter() {
+            let returned = captures.held.iter().position(|capture| {
+                capture.by == plan.faction
+                    && capture.leader.is_some()
+                    && capture.leader == plan.leader
+            });
+            if let Some(i) = returned {
+                let capture = captures.held.remove(i);
+                println!(
+                    "{} goes back to {}",
+                    capture.leader.unwrap_or_default(),
+                    capture.owner
+                );
+                changed = true;
+            }
+        }
+        if !abilities.captures_leaders(event.winner, &info.factions_in_play, &card_effects.karama) {
+            continue;
+        }
+        for plan in plans.iter().filter(|plan| plan.faction != event.winner) {
+            let owner = plan.faction;
+            let candidates = leaders
+                .iter()
+                .filter(|(leader, unique)| leader.faction == owner && unique.faction == owner)
+                .map(|(leader, _)| leader.name.clone())
+                .filter(|name| {
+                    !captures
+                        .pending
+                        .iter()
+                        .any(|capture| capture.leader.as_ref() == Some(name))
+                })
+                .collect::<Vec<_>>();
+            if let Some(name) = candidates.choose(&mut game_rng.rng) {
+                println!("{} captures {} from {}", event.winner, name, owner);
+                captures.pending.push(CapturedLeader {
+                    by: event.winner,
+                    owner,
+                    leader: Some(name.clone()),
+                });
+                notices.push(Notice::LeaderCaptured {
+                    faction: event.winner,
+                    owner,
+                });
+                changed = true;
+            }
+        }
+    }
+
+    let mut killed = Vec::new();
+    while let Some((faction, kill)) = captures.requests.pop_front() {
+        let i = match captures
+            .pending
+            .iter()
+            .position(|capture| capture.by == faction)
+        {
+            Some(i) => i,
+            None => {
+                println!("{} has no captive to decide on", faction);
+                continue;
+            }
+        };
+        let capture = captures.pending.remove(i);
+        let name = capture.leader.clone().unwrap_or_default();
+        if kill {
+            if let Some(mut player) = players.iter_mut().find(|player| player.faction == faction) {
+                player.receive(KILL_REWARD);
+            }
+            println!("{} kills {} for {} spice", faction, name, KILL_REWARD);
+            killed.push(capture);
+        } else {
+            println!("{} keeps {} behind their shield", faction, name);
+            captures.held.push(capture);
+        }
+        actions.resolved(&info, phase.phase, GameAction::Capture { faction, kill });
+        changed = true;
+    }
+    captures.killed.extend(killed.iter().cloned());
+
+    if let Some(mut server) = server.iter_mut().next() {
+        if changed {
+            send_captures(
+                &mut server,
+                &captures,
+                &killed,
+                (rules.hidden_captures(), &info.factions_in_play),
+            );
+        }
+        for notice in notices.iter() {
+            server.broadcast(&ServerEvent::Notice {
+                notice: notice.clone(),
+            });
+        }
+    }
+    for notice in notices {
+        notifications.receive(notice);
+    }
+}
+
+/// Bots would rather have the spice than a leader they can only fight with once
+fn bot_capture_system(
+    network: Res<Network>,
+    (captures, mut actions): (Res<Captures>, ResMut<GameActions>),
+    bots: Query<&Player, With<Bot>>,
+) {
+    if network.network_type == NetworkType::Client {
+        return;
+    }
+    for player in bots.iter() {
+        let faction = player.faction;
+        if !captures.pending.iter().any(|capture| capture.by == faction)
+            || captures.requests.iter().any(|&(other, _)| other == faction)
+            || actions.pending(faction)
+        {
+            continue;
+        }
+        actions.submitted.push_back(GameAction::Capture {
+            faction,
+            kill: true,
+        });
+    }
+}
+
+/// Captives stand on top of the captor's own leaders, behind their shield
+fn captive_node(data: &Data, i: usize) -> Vec3 {
+    let slots = &data.token_nodes.leaders;
+    slots[i % slots.len()] + (1 + i / slots.len()) as f32 * LEADER_HEIGHT * Vec3::unit_y()
+}
+
+/// Where a leader stands in front of its own shield
+pub fn home_node(data: &Data, faction: Faction, name: &str) -> Vec3 {
+    let slot = data
+        .leaders
+        .iter()
+        .filter(|other| other.faction == faction)
+        .position(|other| other.name == name)
+        .unwrap_or(0);
+    data.token_nodes.leaders[slot]
+}
+
+fn move_token(commands: &mut Commands, entity: Entity, to: Vec3) {
+    commands.insert_one(
+        entity,
+        Lerp::new(
+            LerpType::world_to(Transform::from_translation(to)),
+            0.6,
+            0.0,
+        ),
+    );
+}
+
+/// Moves the leaders this machine knows to be captured behind their captor's shield, sends them
+/// home once they are let go and to the tanks once they are killed
+fn captive_system(
+    commands: &mut Commands,
+    (data, mut captures): (Res<Data>, ResMut<Captures>),
+    mut leaders: Query<(
+        Entity,
+        &Leader,
+        &mut Unique,
+        Option<&Captive>,
+        Option<&Dead>,
+    )>,
+) {
+    let mut tanked = Vec::new();
+    for capture in std::mem::take(&mut captures.killed) {
+        let name = if let Some(name) = capture.leader {
+            name
+        } else {
+            continue;
+        };
+        let height = leaders
+            .iter_mut()
+            .filter(|(_, leader, _, _, dead)| leader.faction == capture.owner && dead.is_some())
+            .count()
+            + tanked.len();
+        if let Some((entity, _, mut unique, _, _)) = leaders
+            .iter_mut()
+            .find(|(_, leader, _, _, _)| leader.name == name)
+        {
+            unique.faction = capture.owner;
+            commands.remove_one::<Captive>(entity);
+            commands.insert_one(entity, Dead);
+            move_token(
+                commands,
+                entity,
+                data.token_nodes.tanks_leader(capture.owner, height),
+            );
+            tanked.push(entity);
+        }
+    }
+
+    let known = captures
+        .held
+        .iter()
+        .filter_map(|capture| capture.leader.as_ref().map(|name| (name, capture.by)))
+        .collect::<Vec<_>>();
+    for (entity, leader, mut unique, captive, dead) in leaders.iter_mut() {
+        if tanked.contains(&entity) {
+            continue;
+        }
+        let held = known.iter().find(|(name, _)| **name == leader.name);
+        match (held, captive) {
+            (Some(&(_, by)), None) => {
+                let i = known
+                    .iter()
+                    .filter(|(_, other)| *other == by)
+                    .position(|(name, _)| **name == leader.name)
+                    .unwrap_or(0);
+                unique.faction = by;
+                commands.insert_one(entity, Captive { by });
+                move_token(commands, entity, captive_node(&data, i));
+            }
+            (None, Some(_)) => {
+                unique.faction = leader.faction;
+                commands.remove_one::<Captive>(entity);
+                if dead.is_none() {
+                    move_token(
+                        commands,
+                        entity,
+                        home_node(&data, leader.faction, &leader.name),
+                    );
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+struct CapturePanel;
+
+#[derive(Copy, Clone, PartialEq)]
+enum CaptureButton {
+    Kill,
+    Keep,
+}
+
+/// Asks the captor at this machine what to do with the leader they just took
+fn capture_panel_system(
+    commands: &mut Commands,
+    (network, lobby, info, captures): (Res<Network>, Res<Lobby>, Res<Info>, Res<Captures>),
+    (data, tr, asset_server): (Res<Data>, Res<Tr>, Res<AssetServer>),
+    (hud_materials, button_materials): (Res<HudMaterials>, Res<ButtonMaterials>),
+    mut shown: Local<Option<CapturedLeader>>,
+    (players, client): (Query<&Player>, Query<&Client>),
+    panels: Query<Entity, With<CapturePanel>>,
+) {
+    let own = own_faction(&network, &lobby, &info, &players, &client)
+        .filter(|_| network.role != NetworkRole::Spectator);
+    let next = own.and_then(|faction| {
+        captures
+            .pending
+            .iter()
+            .find(|capture| capture.by == faction)
+            .cloned()
+    });
+    if *shown == next {
+        return;
+    }
+    *shown = next.clone();
+    for entity in panels.iter() {
+        commands.despawn_recursive(entity);
+    }
+    let capture = if let Some(capture) = next {
+        capture
+    } else {
+        return;
+    };
+
+    let title = tr.fmt(
+        "capture.title",
+        &[
+            &capture.leader.clone().unwrap_or_default(),
+            &tr.faction(capture.owner),
+        ],
+    );
+    let buttons = [
+        (CaptureButton::Kill, tr.fmt("capture.kill", &[&KILL_REWARD])),
+        (CaptureButton::Keep, tr.get("capture.keep")),
+    ];
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let text = |value: String| TextBundle {
+        text: Text {
+            font: font.clone(),
+            value,
+            style: TextStyle {
+                font_size: 18.0,
+                color: Color::ANTIQUE_WHITE,
+                ..Default::default()
+            },
+        },
+        ..Default::default()
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Stretch,
+                padding: Rect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            material: hud_materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(CapturePanel)
+        .with(Anchored::new(data.ui_structure.capture.clone()).with_height(AnchoredHeight::Auto))
+        .with_children(|parent| {
+            parent.spawn(text(title));
+            for (button, label) in buttons.iter().cloned() {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            margin: Rect::all(Val::Px(3.0)),
+                            padding: Rect::all(Val::Px(4.0)),
+                            justify_content: JustifyContent::Center,
+                            ..Default::default()
+                        },
+                        material: button_materials.normal.clone(),
+                        ..Default::default()
+                    })
+                    .with(button)
+                    .with_children(|parent| {
+                        parent.spawn(text(label));
+                    });
+            }
+        });
+}
+
+fn capture_button_system(
+    (network, lobby, info): (Res<Network>, Res<Lobby>, Res<Info>),
+    (button_materials, mut actions): (Res<ButtonMaterials>, ResMut<GameActions>),
+    mut buttons: CaptureButtons,
+    (players, faction_client): (Query<&Player>, Query<&Client>),
+    mut client: Query<&mut Client>,
+) {
+    let mut clicked = None;
+    for (interaction, &button, mut material) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {
+                *material = button_materials.pressed.clone();
+                clicked = Some(button);
+            }
+            Interaction::Hovered => *material = button_materials.hovered.clone(),
+            Interaction::None => *material = button_materials.normal.clone(),
+        }
+    }
+    let (button, faction) = match (
+        clicked,
+        own_faction(&network, &lobby, &info, &players, &faction_client),
+    ) {
+        (Some(button), Some(faction)) => (button, faction),
+        _ => return,
+    };
+    submit(
+        &network,
+        &mut client,
+        &mut actions,
+        GameAction::Capture {
+            faction,
+            kill: button == CaptureButton::Kill,
+        },
+    );
+}
+
+fn reset(mut captures: ResMut<Captures>) {
+    *captures = Captures::default();
+}
@@ -0,0 +1,846 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    f32::consts::PI,
+};
+
+use bevy::{
+    prelude::*,
+    render::camera::{Camera, OrthographicProjection},
+};
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
+
+use crate::{
+    bot::Bot,
+    components::{Dead, LocationSector, Player, Secret, Troop, Unique},
+    data::{CardEffect, Faction, TreacheryCard},
+    game_action::{submit, GameAction, GameActions},
+    hud::HotSeat,
+    layout::UiLayout,
+    lerper::{Lerp, LerpType},
+    menu::Chat,
+    network::{Client, Network},
+    phase::{kill_token, Action, ActionQueue, Context, GamePhase, Phase, StormSubPhase},
+    resources::{Data, Info, SectorGraph, SectorNode},
+    util::world_to_screen,
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// Furthest the storm can be pushed with Weather Control
+pub const WEATHER_CONTROL_RANGE: i32 = 10;
+/// Most troops brought back from the tanks by a Revive card
+const REVIVE_TROOPS: usize = 5;
+/// Screen height the hand tray fans out along, from -1 at the bottom to 1 at the top
+const TRAY_Y: f32 = -0.75;
+/// Screen distance between neighbouring cards in the tray
+const TRAY_SPACING: f32 = 0.12;
+/// Tilt between neighbouring cards in the tray
+const TRAY_FAN: f32 = 0.06;
+const TRAY_SCALE: f32 = 0.5;
+const TRAY_HOVER_SCALE: f32 = 0.8;
+const TRAY_TIME: f32 = 0.15;
+
+pub struct CardsPlugin;
+
+impl Plugin for CardsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<CardPlays>()
+            .init_resource::<CardEffects>()
+            .init_resource::<HandInput>()
+            .init_resource::<HoveredCard>()
+            .on_state_enter(RESPONSE_STAGE, Screen::HostingGame, init_hand.system())
+            .on_state_enter(RESPONSE_STAGE, Screen::JoinedGame, init_hand.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                card_play_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                hand_system.system(),
+            )
+            .on_state_update(STATE_CHANGE_STAGE, Screen::JoinedGame, hand_system.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                tray_system.system(),
+            )
+            .on_state_update(STATE_CHANGE_STAGE, Screen::JoinedGame, tray_system.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system());
+    }
+}
+
+/// When a treachery card can be played on its own, rather than as part of a battle plan
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CardTiming {
+    BattlePlan,
+    StormStart,
+    StormMove,
+    Movement,
+    Anytime,
+}
+
+impl CardTiming {
+    pub fn is_open(&self, phase: &Phase) -> bool {
+        match self {
+            CardTiming::BattlePlan => false,
+            CardTiming::StormStart => {
+                *phase
+                    == Phase::Storm {
+                        subphase: StormSubPhase::WeatherControl,
+                    }
+            }
+            CardTiming::StormMove => {
+                *phase
+                    == Phase::Storm {
+                        subphase: StormSubPhase::FamilyAtomics,
+                    }
+            }
+            CardTiming::Movement => matches!(phase, Phase::Movement { .. }),
+            CardTiming::Anytime => !matches!(phase, Phase::Setup { .. } | Phase::EndGame),
+        }
+    }
+}
+
+impl CardEffect {
+    pub fn timing(&self) -> CardTiming {
+        match self {
+            CardEffect::Worthless
+            | CardEffect::PoisonWeapon
+            | CardEffect::ProjectileWeapon
+            | CardEffect::CheapHero
+            | CardEffect::PoisonDefense
+            | CardEffect::ProjectileDefense
+            | CardEffect::Lasgun => CardTiming::BattlePlan,
+            CardEffect::WeatherControl => CardTiming::StormStart,
+            CardEffect::Atomics => CardTiming::StormMove,
+            CardEffect::Movement => CardTiming::Movement,
+            CardEffect::Karama | CardEffect::Revive | CardEffect::Truthtrance => {
+                CardTiming::Anytime
+            }
+        }
+    }
+
+    pub fn is_weapon(&self) -> bool {
+        matches!(
+            self,
+            CardEffect::PoisonWeapon | CardEffect::ProjectileWeapon | CardEffect::Lasgun
+        )
+    }
+
+    pub fn is_defense(&self) -> bool {
+        matches!(
+            self,
+            CardEffect::PoisonDefense | CardEffect::ProjectileDefense
+        )
+    }
+
+    /// Whether the card is aimed at another faction
+    pub fn targeted(&self) -> bool {
+        matches!(self, CardEffect::Karama | CardEffect::Truthtrance)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum CardRequest {
+    /// `slot` is the card's secret slot, which is the same on every machine. `amount` is only used
+    /// by Weather Control, for the number of sectors to move the storm.
+    Play {
+        faction: Faction,
+        slot: u32,
+        target: Option<Faction>,
+        amount: i32,
+    },
+    /// Decline to play the card the game is waiting on
+    Pass { faction: Faction },
+    /// Throw a card away without using it, to make room in a full hand
+    Discard { faction: Faction, slot: u32 },
+}
+
+impl CardRequest {
+    pub fn faction(&self) -> Faction {
+        match self {
+            CardRequest::Play { faction, .. }
+            | CardRequest::Pass { faction }
+            | CardRequest::Discard { faction, .. } => *faction,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CardPlays {
+    pub requests: VecDeque<CardRequest>,
+    /// Factions that have declined to play the card the storm is waiting on
+    pub passed: Vec<Faction>,
+}
+
+/// Lasting consequences of the treachery cards that have been played
+#[derive(Default)]
+pub struct CardEffects {
+    /// Sectors the storm moves this turn instead of the storm card
+    pub weather_control: Option<i32>,
+    /// The Shield Wall is gone and no longer protects the cities behind it
+    pub atomics: bool,
+    /// Factions whose abilities have been cancelled for the rest of the turn
+    pub karama: Vec<Faction>,
+    /// Factions that may move a second group this turn
+    pub extra_moves: Vec<Faction>,
+    pub discard: Vec<Entity>,
+    turn: i32,
+}
+
+impl CardEffects {
+    pub fn cancelled(&self, faction: Faction) -> bool {
+        self.karama.contains(&faction)
+    }
+}
+
+/// Finds a card with the given effect in someone's hand
+pub fn holder_of(
+    effect: CardEffect,
+    players: &Query<(Entity, &Player)>,
+    cards: &Query<&TreacheryCard>,
+) -> Option<(Entity, Faction)> {
+    players.iter().find_map(|(entity, player)| {
+        player
+            .treachery_cards
+            .iter()
+            .any(|&card| cards.get(card).map_or(false, |card| card.effect == effect))
+            .then(|| (entity, player.faction))
+    })
+}
+
+fn discard_pos(i: usize) -> Transform {
+    Transform::from_translation(Vec3::new(1.53, 0.0049 + (i as f32 * 0.001), -0.87))
+}
+
+fn card_play_system(
+    commands: &mut Commands,
+    (mut queue, mut plays, mut effects, mut game_actions): (
+        ResMut<ActionQueue>,
+        ResMut<CardPlays>,
+        ResMut<CardEffects>,
+        ResMut<GameActions>,
+    ),
+    (info, phase, data, graph): (Res<Info>, Res<GamePhase>, Res<Data>, Res<SectorGraph>),
+    mut players: Query<&mut Player>,
+    mut cards: Query<(&Secret<TreacheryCard>, &mut Unique), Without<Troop>>,
+    sectors: Query<&LocationSector>,
+    mut troops: Query<(Entity, &mut Troop, &Unique, Option<&Dead>)>,
+) {
+    if effects.turn != info.turn {
+        effects.turn = info.turn;
+        effects.weather_control = None;
+        effects.karama.clear();
+        effects.extra_moves.clear();
+    }
+    if !queue.is_empty() {
+        return;
+    }
+    let request = if let Some(request) = plays.requests.pop_front() {
+        request
+    } else {
+        return;
+    };
+    let discarding = matches!(request, CardRequest::Discard { .. });
+    let (faction, slot, target, amount) = match request {
+        CardRequest::Discard { faction, slot } => (faction, slot, None, 0),
+        CardRequest::Pass { faction } => {
+            if !plays.passed.contains(&faction) {
+                plays.passed.push(faction);
+            }
+            game_actions.resolved(&info, phase.phase, GameAction::CardPlay { request });
+            return;
+        }
+        CardRequest::Play {
+            faction,
+            slot,
+            target,
+            amount,
+        } => (faction, slot, target, amount),
+    };
+    let mut player =
+        if let Some(player) = players.iter_mut().find(|player| player.faction == faction) {
+            player
+        } else {
+            return;
+        };
+    let (index, card) = if let Some(found) =
+        player
+            .treachery_cards
+            .iter()
+            .enumerate()
+            .find_map(|(i, &entity)| {
+                cards
+                    .get_mut(entity)
+                    .ok()
+                    .filter(|(secret, _)| secret.slot == slot)
+                    .and_then(|(secret, _)| secret.value.clone())
+                    .map(|card| (i, card))
+            }) {
+        found
+    } else {
+        println!("{} does not hold that card!", faction);
+        return;
+    };
+    let timing = card.effect.timing();
+    if discarding {
+        println!("{} discards {}", faction, card.name);
+    } else if !timing.is_open(&phase.phase) {
+        if timing == CardTiming::BattlePlan {
+            println!("{} can only be played in a battle plan!", card.name);
+        } else {
+            println!("{} cannot be played now!", card.name);
+        }
+        return;
+    }
+    if !discarding && card.effect.targeted() && target.map_or(true, |target| target == faction) {
+        println!("{} needs another faction as a target!", card.name);
+        return;
+    }
+
+    let mut actions = Vec::new();
+    match card.effect {
+        _ if discarding => (),
+        CardEffect::WeatherControl => {
+            let amount = amount.clamp(0, WEATHER_CONTROL_RANGE);
+            effects.weather_control = Some(amount);
+            println!("{} takes control of the storm: {} sectors", faction, amount);
+        }
+        CardEffect::Atomics => {
+            let shield_wall = graph.sectors_of("Shield Wall").collect::<Vec<_>>();
+            let near_shield_wall = troops.iter_mut().any(|(_, troop, unique, dead)| {
+                unique.faction == faction
+                    && dead.is_none()
+                    && troop
+                        .location
+                        .and_then(|location| sectors.get(location).ok())
+                        .map_or(false, |loc_sec| {
+                            let node = SectorNode::new(&loc_sec.location.name, loc_sec.sector);
+                            shield_wall.iter().any(|&wall| {
+                                graph.distance(&node, wall, None).map_or(false, |d| d <= 1)
+                            })
+                        })
+            });
+            if !near_shield_wall {
+                println!("{} has no troops near the Shield Wall!", faction);
+                return;
+            }
+            let mut tanks = HashMap::new();
+            for (_, _, unique, _) in troops.iter_mut().filter(|(_, _, _, dead)| dead.is_some()) {
+                *tanks.entry(unique.faction).or_insert(0) += 1;
+            }
+            for (entity, mut troop, unique, _) in troops.iter_mut().filter(|(_, troop, _, dead)| {
+                dead.is_none()
+                    && troop.location.map_or(false, |location| {
+                        sectors
+                            .get(location)
+                            .map_or(false, |loc_sec| loc_sec.location.name == "Shield Wall")
+                    })
+            }) {
+                let height = tanks.entry(unique.faction).or_insert(0);
+                troop.location = None;
+                actions.push(kill_token(
+                    commands,
+                    &data,
+                    entity,
+                    unique.faction,
+                    false,
+                    *height,
+                ));
+                *height += 1;
+            }
+            effects.atomics = true;
+            println!("{} destroys the Shield Wall with Family Atomics!", faction);
+        }
+        CardEffect::Movement => {
+            if !effects.extra_moves.contains(&faction) {
+                effects.extra_moves.push(faction);
+            }
+            println!("{} may move a second group this turn", faction);
+        }
+        CardEffect::Karama => {
+            let target = target.unwrap();
+            if !effects.karama.contains(&target) {
+                effects.karama.push(target);
+            }
+            println!(
+                "{} plays Karama: {} has lost its powers this turn",
+                faction, target
+            );
+        }
+        CardEffect::Truthtrance => {
+            println!(
+                "{} plays Truthtrance: {} must answer one question truthfully",
+                faction,
+                target.unwrap()
+            );
+        }
+        CardEffect::Revive => {
+            let dead = troops
+                .iter_mut()
+                .filter(|(_, _, unique, dead)| unique.faction == faction && dead.is_some())
+                .map(|(entity, _, _, _)| entity)
+                .collect::<Vec<_>>();
+            if dead.is_empty() {
+                println!("{} has nothing in the tanks to revive!", faction);
+                return;
+            }
+            let reserves = troops
+                .iter_mut()
+                .filter(|(_, troop, unique, dead)| {
+                    unique.faction == faction && dead.is_none() && troop.location.is_none()
+                })
+                .count();
+            let revived = dead.len().min(REVIVE_TROOPS);
+            for (i, &entity) in dead.iter().rev().take(revived).enumerate() {
+                commands.remove_one::<Dead>(entity);
+                actions.push(
+                    Action::add_lerp(
+                        entity,
+                        Lerp::new(
+                            LerpType::world_to(Transform::from_translation(
+                                data.token_nodes.fighters[0]
+                                    + ((reserves + i) as f32 * 0.0036 * Vec3::unit_y()),
+                            )),
+                            0.6,
+                            0.0,
+                        ),
+                    )
+                    .into(),
+                );
+            }
+            println!("{} revived {} troops for free", faction, revived);
+        }
+        _ => (),
+    }
+
+    // Played and discarded cards go face up onto the discard pile for everyone to see
+    let entity = player.treachery_cards.remove(index);
+    if let Ok((_, mut unique)) = cards.get_mut(entity) {
+        unique.public = true;
+    }
+    actions.push(
+        Action::add_lerp(
+            entity,
+            Lerp::new(
+                LerpType::world_to(discard_pos(effects.discard.len())),
+                0.6,
+                0.0,
+            ),
+        )
+        .into(),
+    );
+    effects.discard.push(entity);
+    queue.push_multiple(actions);
+    game_actions.resolved(&info, phase.phase, GameAction::CardPlay { request });
+}
+
+struct HandText;
+
+struct LimitText;
+
+/// Which card in the active player's hand is picked, shared by the keyboard and the tray
+#[derive(Default)]
+struct HandInput {
+    selected: usize,
+    target: usize,
+    amount: i32,
+}
+
+fn init_hand(commands: &mut Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(5.0),
+                    left: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                font,
+                value: String::new(),
+                style: TextStyle {
+                    font_size: 18.0,
+                    color: Color::ANTIQUE_WHITE,
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(HandText);
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(5.0),
+                    right: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                value: String::new(),
+                style: TextStyle {
+                    font_size: 18.0,
+                    color: Color::ANTIQUE_WHITE,
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(LimitText);
+}
+
+/// Lists the active player's treachery cards and lets them play one. H picks a card, T picks the
+/// target or storm distance, P plays it, D discards it, and Backspace declines when the game is
+/// waiting on a card.
+fn hand_system(
+    (info, phase, network): (Res<Info>, Res<GamePhase>, Res<Network>),
+    (keyboard_input, chat): (Res<Input<KeyCode>>, Res<Chat>),
+    (mut actions, mut input, hot_seat): (ResMut<GameActions>, ResMut<HandInput>, Res<HotSeat>),
+    players: Query<&Player, Without<Bot>>,
+    cards: Query<&Secret<TreacheryCard>>,
+    mut texts: Query<&mut Text, With<HandText>>,
+    mut client: Query<&mut Client>,
+) {
+    let player =
+        if info.play_order.is_empty() || hot_seat.concealed(&network, info.get_active_player()) {
+            None
+        } else {
+            players.get(info.get_active_player()).ok()
+        };
+    let hand = player
+        .map(|player| {
+            player
+                .treachery_cards
+                .iter()
+                .filter_map(|&entity| cards.get(entity).ok())
+                .filter_map(|secret| secret.value.clone().map(|card| (secret.slot, card)))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let targets = info
+        .factions_in_play
+        .iter()
+        .copied()
+        .filter(|&faction| Some(faction) != player.map(|player| player.faction))
+        .collect::<Vec<_>>();
+    if input.selected >= hand.len() {
+        input.selected = 0;
+    }
+
+    let mut request = None;
+    if let Some(player) = player.filter(|_| !chat.open) {
+        if keyboard_input.just_pressed(KeyCode::H) && !hand.is_empty() {
+            input.selected = (input.selected + 1) % hand.len();
+        } else if keyboard_input.just_pressed(KeyCode::T) {
+            input.target = (input.target + 1) % targets.len().max(1);
+            input.amount = (input.amount + 1) % (WEATHER_CONTROL_RANGE + 1);
+        } else if keyboard_input.just_pressed(KeyCode::P) {
+            if let Some((slot, card)) = hand.get(input.selected) {
+                request = Some(CardRequest::Play {
+                    faction: player.faction,
+                    slot: *slot,
+                    target: card
+                        .effect
+                        .targeted()
+                        .then(|| targets.get(input.target).copied())
+                        .flatten(),
+                    amount: input.amount,
+                });
+            }
+        } else if keyboard_input.just_pressed(KeyCode::D) {
+            if let Some((slot, _)) = hand.get(input.selected) {
+                request = Some(CardRequest::Discard {
+                    faction: player.faction,
+                    slot: *slot,
+                });
+            }
+        } else if keyboard_input.just_pressed(KeyCode::Back) && info.context == Context::Prompting {
+            request = Some(CardRequest::Pass {
+                faction: player.faction,
+            });
+        }
+    }
+    if let Some(request) = request {
+        submit(
+            &network,
+            &mut client,
+            &mut actions,
+            GameAction::CardPlay { request },
+        );
+    }
+
+    let value = if hand.is_empty() {
+        String::new()
+    } else {
+        let mut lines = hand
+            .iter()
+            .enumerate()
+            .map(|(i, (_, card))| {
+                format!(
+                    "{} {}{}",
+                    if i == input.selected { ">" } else { " " },
+                    card.name,
+                    if card.effect.timing().is_open(&phase.phase) {
+                        " *"
+                    } else {
+                        ""
+                    }
+                )
+            })
+            .collect::<Vec<_>>();
+        match hand.get(input.selected).map(|(_, card)| card.effect) {
+            Some(CardEffect::WeatherControl) => {
+                lines.push(format!("Storm: {} sectors", input.amount))
+            }
+            Some(effect) if effect.targeted() => {
+                if let Some(target) = targets.get(input.target) {
+                    lines.push(format!("Target: {}", target));
+                }
+            }
+            _ => (),
+        }
+        lines.push("[H] card  [T] target  [P] play  [D] discard  [Right click] menu".to_string());
+        lines.join("\n")
+    };
+    for mut text in texts.iter_mut() {
+        if text.value != value {
+            text.value = value.clone();
+        }
+    }
+}
+
+type LimitTexts<'a, 'b> = Query<'a, &'b mut Text, With<LimitText>>;
+
+/// The treachery card under the cursor in the hand tray, so it can be inspected or looked up in
+/// the rules
+#[derive(Default)]
+pub struct HoveredCard {
+    pub entity: Option<Entity>,
+    pub name: Option<String>,
+}
+
+#[derive(Default)]
+struct HandTray {
+    owner: Option<Entity>,
+    laid_out: Vec<Entity>,
+    hovered: Option<usize>,
+    last: (Vec<Entity>, Option<usize>, usize),
+}
+
+/// Fans the active player's treachery cards along the bottom of the screen, following whatever
+/// the server says is in their hand. Hovering a card enlarges it and clicking picks it for the
+/// hand keys, while a counter shows how close the hand is to its limit.
+fn tray_system(
+    commands: &mut Commands,
+    (info, network, hot_seat, data): (Res<Info>, Res<Network>, Res<HotSeat>, Res<Data>),
+    (queue, mouse_input, windows, layout): (
+        Res<ActionQueue>,
+        Res<Input<MouseButton>>,
+        Res<Windows>,
+        Res<UiLayout>,
+    ),
+    (mut input, mut hovered_card, mut tray, mut texts): (
+        ResMut<HandInput>,
+        ResMut<HoveredCard>,
+        Local<HandTray>,
+        LimitTexts,
+    ),
+    players: Query<&Player, Without<Bot>>,
+    cards: Query<(&Secret<TreacheryCard>, &Transform)>,
+    cameras: Query<(&Camera, &Transform), Without<OrthographicProjection>>,
+) {
+    let owner = if info.play_order.is_empty()
+        || hot_seat.concealed(&network, info.get_active_player())
+        || players.get(info.get_active_player()).is_err()
+    {
+        None
+    } else {
+        Some(info.get_active_player())
+    };
+
+    // Whoever held the tray before gets their cards put back face down on their turn tile
+    if tray.owner != owner {
+        if let Some(previous) = tray.owner {
+            let turn_tiles = data.ui_structure.get_turn_tiles();
+            if let (Some(j), Ok(player)) = (
+                info.play_order
+                    .iter()
+                    .position(|&entity| entity == previous),
+                players.get(previous),
+            ) {
+                for &card in tray
+                    .laid_out
+                    .iter()
+                    .filter(|card| player.treachery_cards.contains(card))
+                {
+                    commands.insert_one(
+                        card,
+                        Lerp::new(
+                            LerpType::world_to_ui(
+                                (
+                                    layout.place(&turn_tiles[j]).center(),
+                                    Quat::from_rotation_x(0.5 * PI) * Quat::from_rotation_z(PI),
+                                    0.4,
+                                )
+                                    .into(),
+                            ),
+                            TRAY_TIME,
+                            0.0,
+                        ),
+                    );
+                }
+            }
+        }
+        *tray = HandTray {
+            owner,
+            ..Default::default()
+        };
+    }
+
+    let (hand, limit) = owner
+        .and_then(|owner| players.get(owner).ok())
+        .map(|player| {
+            (
+                player
+                    .treachery_cards
+                    .iter()
+                    .copied()
+                    .filter(|&entity| {
+                        cards
+                            .get(entity)
+                            .map_or(false, |(secret, _)| secret.value.is_some())
+                    })
+                    .collect::<Vec<_>>(),
+                player.faction.hand_limit(),
+            )
+        })
+        .unwrap_or_default();
+
+    // Cards only take their tray spots once the game is done moving them around
+    if let (Some((camera, cam_transform)), Some(window)) =
+        (cameras.iter().next(), windows.get_primary())
+    {
+        tray.hovered = window.cursor_position().and_then(|cursor| {
+            tray.laid_out
+                .iter()
+                .rposition(|&entity| {
+                    cards.get(entity).map_or(false, |(_, transform)| {
+                        let matrix = transform.compute_matrix();
+                        let corners = [(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0), (1.0, 1.0)]
+                            .iter()
+                            .filter_map(|&(x, z)| {
+                                world_to_screen(
+                                    matrix.transform_point3(Vec3::new(0.125 * x, 0.0, 0.18 * z)),
+                                    window,
+                                    camera,
+                                    cam_transform,
+                                )
+                            })
+                            .collect::<Vec<_>>();
+                        corners.len() == 4
+                            && corners
+                                .iter()
+                                .fold(Vec2::splat(f32::MAX), |a, &b| a.min(b))
+                                .cmple(cursor)
+                                .all()
+                            && corners
+                                .iter()
+                                .fold(Vec2::splat(f32::MIN), |a, &b| a.max(b))
+                                .cmpge(cursor)
+                                .all()
+                    })
+                })
+                .filter(|&i| hand.get(i) == tray.laid_out.get(i))
+        });
+    }
+    let entity = tray.hovered.and_then(|i| hand.get(i)).copied();
+    let name = entity
+        .and_then(|entity| cards.get(entity).ok())
+        .and_then(|(secret, _)| secret.value.as_ref().map(|card| card.name.clone()));
+    if hovered_card.entity != entity || hovered_card.name != name {
+        hovered_card.entity = entity;
+        hovered_card.name = name;
+    }
+    if let Some(hovered) = tray.hovered {
+        if mouse_input.just_pressed(MouseButton::Left) {
+            input.selected = hovered;
+        }
+    }
+
+    let key = (hand.clone(), tray.hovered, input.selected);
+    if queue.is_empty() && key != tray.last {
+        let n = hand.len() as f32;
+        for (i, &card) in hand.iter().enumerate() {
+            let offset = i as f32 - 0.5 * (n - 1.0);
+            let (lift, scale) = if tray.hovered == Some(i) {
+                (0.2, TRAY_HOVER_SCALE)
+            } else if input.selected == i {
+                (0.05, TRAY_SCALE)
+            } else {
+                (0.0, TRAY_SCALE)
+            };
+            commands.insert_one(
+                card,
+                Lerp::new(
+                    LerpType::world_to_ui(
+                        (
+                            Vec2::new(offset * TRAY_SPACING, TRAY_Y + lift - 0.01 * offset.powi(2)),
+                            Quat::from_rotation_z(-offset * TRAY_FAN)
+                                * Quat::from_rotation_x(0.5 * PI),
+                            scale,
+                        )
+                            .into(),
+                    ),
+                    TRAY_TIME,
+                    0.0,
+                ),
+            );
+        }
+        tray.laid_out = hand.clone();
+        tray.last = key;
+    }
+
+    let (value, color) = if owner.is_none() {
+        (String::new(), Color::ANTIQUE_WHITE)
+    } else {
+        (
+            format!("Hand: {}/{}", hand.len(), limit),
+            if hand.len() >= limit {
+                Color::RED
+            } else {
+                Color::ANTIQUE_WHITE
+            },
+        )
+    };
+    for mut text in texts.iter_mut() {
+        if text.value != value {
+            text.value = value.clone();
+        }
+        text.style.color = color;
+    }
+}
+
+fn reset(
+    mut plays: ResMut<CardPlays>,
+    mut effects: ResMut<CardEffects>,
+    (mut input, mut hovered_card): (ResMut<HandInput>, ResMut<HoveredCard>),
+) {
+    *plays = CardPlays::default();
+    *effects = CardEffects::default();
+    *input = HandInput::default();
+    *hovered_card = HoveredCard::default();
+}
@@ -4,15 +4,16 @@ use bevy::{
     prelude::{GlobalTransform, Transform, Visible},
 };
 use ncollide3d::{
-    na::Vector3,
-    shape::{Cuboid, ShapeHandle},
+    na::{Point3, Vector3},
+    shape::{Cuboid, ShapeHandle, TriMesh},
 };
 
-use crate::data::{Faction, Leader, Location};
+use crate::data::{Faction, Leader, Location, LocationNodes};
 
 #[derive(Copy, Clone)]
 pub struct Spice {
     pub value: i32,
+    pub location: Option<Entity>,
 }
 
 #[derive(Copy, Clone)]
@@ -33,6 +34,17 @@ pub struct LocationSector {
 
 pub struct Disorganized;
 
+/// The Ixians' stronghold, which travels the board with their largest force
+#[derive(Default)]
+pub struct HiddenMobileStronghold {
+    pub location: Option<Entity>,
+}
+
+/// What to call an entity when the cursor is over it
+pub struct Tooltip(pub String);
+
+pub struct Dead;
+
 #[derive(Copy, Clone, Debug, Default)]
 pub struct SpiceNode {
     pub pos: Vec3,
@@ -93,6 +105,21 @@ impl ColliderBundle {
     }
 }
 
+/// The clickable surface of one sector of a territory, just above the board
+pub fn sector_shape(nodes: &LocationNodes) -> ShapeHandle<f32> {
+    let vertices = nodes
+        .vertices
+        .iter()
+        .map(|p| Point3::new(p.x, 0.01, -p.y))
+        .collect();
+    let indices = nodes
+        .indices
+        .chunks_exact(3)
+        .map(|chunk| Point3::new(chunk[0] as usize, chunk[1] as usize, chunk[2] as usize))
+        .collect();
+    ShapeHandle::new(TriMesh::new(vertices, indices, None))
+}
+
 #[derive(Bundle)]
 pub struct UniqueBundle {
     unique: Unique,
@@ -114,6 +141,26 @@ impl UniqueBundle {
     }
 }
 
+/// Card contents that may not be known locally. Clients only learn the identity of cards they own
+/// or that have been made public, and `slot` ties the entity to the server's copy of the card.
+pub struct Secret<T> {
+    pub slot: u32,
+    pub value: Option<T>,
+}
+
+impl<T> Secret<T> {
+    pub fn hidden(slot: u32) -> Self {
+        Secret { slot, value: None }
+    }
+
+    pub fn revealed(slot: u32, value: T) -> Self {
+        Secret {
+            slot,
+            value: Some(value),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Default, Debug)]
 pub struct Prediction {
     pub faction: Option<Faction>,
@@ -122,6 +169,7 @@ pub struct Prediction {
 
 pub struct Player {
     pub faction: Faction,
+    pub spice: i32,
     pub traitor_cards: Vec<Entity>,
     pub treachery_cards: Vec<Entity>,
 }
@@ -130,8 +178,27 @@ impl Player {
     pub fn new(faction: Faction, all_leaders: &Vec<Leader>) -> Self {
         Player {
             faction,
+            spice: faction.initial_values().2,
             traitor_cards: Vec::new(),
             treachery_cards: Vec::new(),
         }
     }
+
+    pub fn can_afford(&self, amount: i32) -> bool {
+        self.spice >= amount
+    }
+
+    /// Takes `amount` out of the player's spice, or nothing at all if they can't cover it
+    pub fn pay(&mut self, amount: i32) -> bool {
+        if self.can_afford(amount) {
+            self.spice -= amount;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn receive(&mut self, amount: i32) {
+        self.spice = self.spice.saturating_add(amount);
+    }
 }
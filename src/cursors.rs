@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+use bevy::{
+    prelude::*,
+    render::camera::{Camera, OrthographicProjection},
+};
+
+use crate::{
+    components::Player,
+    data::Faction,
+    network::{Client, Network, NetworkType, Server},
+    protocol::{ClientRequest, ServerEvent},
+    resources::Info,
+    util::cursor_on_plane,
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// Seconds between cursor updates. They go out unreliably, so a dropped one is soon replaced.
+const CURSOR_INTERVAL: f32 = 0.1;
+/// Just above the board so the markers don't sink into it
+const CURSOR_HEIGHT: f32 = 0.01;
+
+pub struct CursorPlugin;
+
+impl Plugin for CursorPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<RemoteCursors>()
+            .on_state_enter(RESPONSE_STAGE, Screen::HostingGame, init_cursors.system())
+            .on_state_enter(RESPONSE_STAGE, Screen::JoinedGame, init_cursors.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                share_cursor_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                share_cursor_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                remote_cursor_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                remote_cursor_system.system(),
+            );
+    }
+}
+
+/// Where everyone else is pointing on the board
+#[derive(Default)]
+pub struct RemoteCursors {
+    pub positions: HashMap<Faction, Vec2>,
+    mesh: Handle<Mesh>,
+}
+
+struct CursorMarker(Faction);
+
+fn init_cursors(mut cursors: ResMut<RemoteCursors>, mut meshes: ResMut<Assets<Mesh>>) {
+    cursors.positions.clear();
+    cursors.mesh = meshes.add(Mesh::from(shape::Icosphere {
+        radius: 0.01,
+        subdivisions: 2,
+    }));
+}
+
+/// Sends our own cursor out, as the faction we're playing
+fn share_cursor_system(
+    (time, mut timer, windows): (Res<Time>, Local<f32>, Res<Windows>),
+    (info, network): (Res<Info>, Res<Network>),
+    cameras: Query<(&Camera, &Transform), Without<OrthographicProjection>>,
+    players: Query<&Player>,
+    mut server: Query<&mut Server>,
+    mut client: Query<&mut Client>,
+) {
+    *timer += time.delta_seconds();
+    if *timer < CURSOR_INTERVAL {
+        return;
+    }
+    *timer = 0.0;
+    let position = if let Some(position) = cursor_on_plane(&windows, &cameras, CURSOR_HEIGHT) {
+        position
+    } else {
+        return;
+    };
+    let (x, z) = (position.x, position.z);
+    match network.network_type {
+        NetworkType::Client => {
+            if let Some(mut client) = client.iter_mut().next() {
+                if let Some(from) = client.faction {
+                    client.request(ClientRequest::Cursor { from, x, z });
+                }
+            }
+        }
+        NetworkType::Server => {
+            if info.play_order.is_empty() {
+                return;
+            }
+            if let (Some(mut server), Ok(player)) = (
+                server.iter_mut().next(),
+                players.get(info.get_active_player()),
+            ) {
+                server.broadcast(&ServerEvent::Cursor {
+                    from: player.faction,
+                    x,
+                    z,
+                });
+            }
+        }
+        NetworkType::None | NetworkType::Local => (),
+    }
+}
+
+/// Keeps a marker in each faction's colour wherever that player last pointed
+fn remote_cursor_system(
+    commands: &mut Commands,
+    cursors: Res<RemoteCursors>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut markers: Query<(&CursorMarker, &mut Transform)>,
+) {
+    for (&faction, &position) in cursors.positions.iter() {
+        let translation = Vec3::new(position.x, CURSOR_HEIGHT, position.y);
+        if let Some((_, mut transform)) = markers.iter_mut().find(|(marker, _)| marker.0 == faction)
+        {
+            transform.translation = translation;
+        } else {
+            commands
+                .spawn(PbrBundle {
+                    mesh: cursors.mesh.clone(),
+                    material: materials.add(StandardMaterial {
+                        albedo: faction.color(),
+                        shaded: false,
+                        ..Default::default()
+                    }),
+                    transform: Transform::from_translation(translation),
+                    ..Default::default()
+                })
+                .with(CursorMarker(faction))
+                .with(ScreenEntity);
+        }
+    }
+}
@@ -1,55 +1,434 @@
 use std::collections::HashMap;
 
 use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
     math::{Rect, Size, Vec2, Vec3},
+    reflect::TypeUuid,
+    render::color::Color,
     ui::Val,
+    utils::BoxedFuture,
 };
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Serialize, Deserialize, PartialEq, Eq, Debug, Hash)]
-pub enum Faction {
-    Atreides,
-    Harkonnen,
-    Emperor,
-    SpacingGuild,
-    Fremen,
-    BeneGesserit,
-}
+pub use crate::dune_core::{Faction, Terrain};
+use crate::resources::Data;
 
 impl Faction {
-    pub fn initial_values(&self) -> (i32, Option<Vec<String>>, i32) {
+    /// Short name used in asset file names
+    pub fn code(&self) -> &'static str {
         match self {
-            Self::Atreides => (10, Some(vec!["Arrakeen".to_string()]), 10),
-            Self::BeneGesserit => (1, None, 5),
-            Self::Fremen => (
-                10,
-                Some(vec![
-                    "Sietch Tabr".to_string(),
-                    "False Wall South".to_string(),
-                    "False Wall West".to_string(),
-                ]),
-                10,
-            ),
-            Self::Emperor => (0, None, 10),
-            Self::SpacingGuild => (5, Some(vec!["Tuek's Sietch".to_string()]), 5),
-            Self::Harkonnen => (10, Some(vec!["Carthag".to_string()]), 10),
+            Faction::Atreides => "at",
+            Faction::Harkonnen => "hk",
+            Faction::Emperor => "em",
+            Faction::SpacingGuild => "sg",
+            Faction::Fremen => "fr",
+            Faction::BeneGesserit => "bg",
+            Faction::Ixian => "ix",
+            Faction::Tleilaxu => "tl",
         }
     }
-}
 
-impl std::fmt::Display for Faction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    pub fn color(&self) -> Color {
         match self {
-            Faction::Atreides => write!(f, "Atreides"),
-            Faction::Harkonnen => write!(f, "Harkonnen"),
-            Faction::Emperor => write!(f, "Emperor"),
-            Faction::SpacingGuild => write!(f, "Spacing Guild"),
-            Faction::Fremen => write!(f, "Fremen"),
-            Faction::BeneGesserit => write!(f, "Bene Gesserit"),
+            Self::Atreides => Color::rgb(0.2, 0.7, 0.2),
+            Self::Harkonnen => Color::rgb(0.6, 0.6, 0.6),
+            Self::Emperor => Color::rgb(0.85, 0.15, 0.15),
+            Self::SpacingGuild => Color::rgb(0.95, 0.55, 0.1),
+            Self::Fremen => Color::rgb(0.9, 0.8, 0.4),
+            Self::BeneGesserit => Color::rgb(0.45, 0.45, 0.95),
+            Self::Ixian => Color::rgb(0.65, 0.3, 0.8),
+            Self::Tleilaxu => Color::rgb(0.2, 0.65, 0.65),
         }
     }
 }
 
+/// The part of a turn in which a faction power can interrupt the normal flow of play
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum AbilityWindow {
+    Storm,
+    Bidding,
+    Shipping,
+    Revival,
+    Battle,
+}
+
+/// A faction's special power. Phases ask the registry which abilities apply to their window
+/// instead of checking for particular factions themselves.
+pub trait FactionAbility: Send + Sync {
+    fn faction(&self) -> Faction;
+    fn name(&self) -> &'static str;
+    fn window(&self) -> AbilityWindow;
+
+    /// Receives the spice `payer` spends during the window instead of the bank
+    fn collects_from(&self, _payer: Faction) -> bool {
+        false
+    }
+
+    /// Looks at each treachery card before bidding on it
+    fn sees_auction(&self) -> bool {
+        false
+    }
+
+    /// Extra treachery cards drawn with every purchase
+    fn bonus_cards(&self) -> usize {
+        0
+    }
+
+    /// Only in play under the advanced rules
+    fn advanced(&self) -> bool {
+        false
+    }
+
+    /// Troops fight at full strength without spice being paid for them
+    fn fights_unspiced(&self) -> bool {
+        false
+    }
+
+    /// Leaders can be revived without waiting for the rest of them to die
+    fn grows_gholas(&self) -> bool {
+        false
+    }
+
+    /// Takes a leader from whoever they beat in battle
+    fn captures_leaders(&self) -> bool {
+        false
+    }
+}
+
+struct Prescience;
+
+impl FactionAbility for Prescience {
+    fn faction(&self) -> Faction {
+        Faction::Atreides
+    }
+
+    fn name(&self) -> &'static str {
+        "Prescience"
+    }
+
+    fn window(&self) -> AbilityWindow {
+        AbilityWindow::Bidding
+    }
+
+    fn sees_auction(&self) -> bool {
+        true
+    }
+}
+
+struct Voice;
+
+impl FactionAbility for Voice {
+    fn faction(&self) -> Faction {
+        Faction::BeneGesserit
+    }
+
+    fn name(&self) -> &'static str {
+        "The Voice"
+    }
+
+    fn window(&self) -> AbilityWindow {
+        AbilityWindow::Battle
+    }
+}
+
+struct StormKnowledge;
+
+impl FactionAbility for StormKnowledge {
+    fn faction(&self) -> Faction {
+        Faction::Fremen
+    }
+
+    fn name(&self) -> &'static str {
+        "Storm Knowledge"
+    }
+
+    fn window(&self) -> AbilityWindow {
+        AbilityWindow::Storm
+    }
+}
+
+struct ShippingPayments;
+
+impl FactionAbility for ShippingPayments {
+    fn faction(&self) -> Faction {
+        Faction::SpacingGuild
+    }
+
+    fn name(&self) -> &'static str {
+        "Shipping Payments"
+    }
+
+    fn window(&self) -> AbilityWindow {
+        AbilityWindow::Shipping
+    }
+
+    fn collects_from(&self, payer: Faction) -> bool {
+        payer != Faction::SpacingGuild
+    }
+}
+
+struct Capture;
+
+impl FactionAbility for Capture {
+    fn faction(&self) -> Faction {
+        Faction::Harkonnen
+    }
+
+    fn name(&self) -> &'static str {
+        "Capture"
+    }
+
+    fn window(&self) -> AbilityWindow {
+        AbilityWindow::Battle
+    }
+
+    fn captures_leaders(&self) -> bool {
+        true
+    }
+}
+
+struct Treachery;
+
+impl FactionAbility for Treachery {
+    fn faction(&self) -> Faction {
+        Faction::Harkonnen
+    }
+
+    fn name(&self) -> &'static str {
+        "Treachery"
+    }
+
+    fn window(&self) -> AbilityWindow {
+        AbilityWindow::Bidding
+    }
+
+    fn bonus_cards(&self) -> usize {
+        1
+    }
+}
+
+struct BidIncome;
+
+impl FactionAbility for BidIncome {
+    fn faction(&self) -> Faction {
+        Faction::Emperor
+    }
+
+    fn name(&self) -> &'static str {
+        "Bid Income"
+    }
+
+    fn window(&self) -> AbilityWindow {
+        AbilityWindow::Bidding
+    }
+
+    fn collects_from(&self, payer: Faction) -> bool {
+        payer != Faction::Emperor
+    }
+}
+
+struct Fedaykin;
+
+impl FactionAbility for Fedaykin {
+    fn faction(&self) -> Faction {
+        Faction::Fremen
+    }
+
+    fn name(&self) -> &'static str {
+        "Fedaykin"
+    }
+
+    fn window(&self) -> AbilityWindow {
+        AbilityWindow::Battle
+    }
+
+    fn advanced(&self) -> bool {
+        true
+    }
+
+    fn fights_unspiced(&self) -> bool {
+        true
+    }
+}
+
+struct Technology;
+
+impl FactionAbility for Technology {
+    fn faction(&self) -> Faction {
+        Faction::Ixian
+    }
+
+    fn name(&self) -> &'static str {
+        "Technology"
+    }
+
+    fn window(&self) -> AbilityWindow {
+        AbilityWindow::Bidding
+    }
+
+    fn sees_auction(&self) -> bool {
+        true
+    }
+}
+
+struct TleilaxuTanks;
+
+impl FactionAbility for TleilaxuTanks {
+    fn faction(&self) -> Faction {
+        Faction::Tleilaxu
+    }
+
+    fn name(&self) -> &'static str {
+        "Tleilaxu Tanks"
+    }
+
+    fn window(&self) -> AbilityWindow {
+        AbilityWindow::Revival
+    }
+
+    fn collects_from(&self, payer: Faction) -> bool {
+        payer != Faction::Tleilaxu
+    }
+}
+
+struct Gholas;
+
+impl FactionAbility for Gholas {
+    fn faction(&self) -> Faction {
+        Faction::Tleilaxu
+    }
+
+    fn name(&self) -> &'static str {
+        "Gholas"
+    }
+
+    fn window(&self) -> AbilityWindow {
+        AbilityWindow::Revival
+    }
+
+    fn grows_gholas(&self) -> bool {
+        true
+    }
+}
+
+pub struct FactionAbilities {
+    abilities: Vec<Box<dyn FactionAbility>>,
+    /// Whether abilities marked as advanced are in play, following the game's rule set
+    pub advanced: bool,
+}
+
+impl Default for FactionAbilities {
+    fn default() -> Self {
+        FactionAbilities {
+            advanced: false,
+            abilities: vec![
+                Box::new(Prescience),
+                Box::new(Voice),
+                Box::new(StormKnowledge),
+                Box::new(ShippingPayments),
+                Box::new(Capture),
+                Box::new(Treachery),
+                Box::new(BidIncome),
+                Box::new(Fedaykin),
+                Box::new(Technology),
+                Box::new(TleilaxuTanks),
+                Box::new(Gholas),
+            ],
+        }
+    }
+}
+
+impl FactionAbilities {
+    /// Abilities that can interrupt `window`, leaving out factions that are not playing or whose
+    /// powers have been cancelled
+    pub fn interrupts<'a>(
+        &'a self,
+        window: AbilityWindow,
+        in_play: &'a [Faction],
+        cancelled: &'a [Faction],
+    ) -> impl Iterator<Item = &'a dyn FactionAbility> {
+        self.abilities
+            .iter()
+            .map(|ability| ability.as_ref())
+            .filter(move |ability| {
+                ability.window() == window
+                    && (self.advanced || !ability.advanced())
+                    && in_play.contains(&ability.faction())
+                    && !cancelled.contains(&ability.faction())
+            })
+    }
+
+    /// Every ability `faction` has under the rule set being played, whatever its window
+    pub fn held_by(&self, faction: Faction) -> impl Iterator<Item = &dyn FactionAbility> {
+        self.abilities
+            .iter()
+            .map(|ability| ability.as_ref())
+            .filter(move |ability| {
+                ability.faction() == faction && (self.advanced || !ability.advanced())
+            })
+    }
+
+    /// Abilities of one faction that can interrupt `window`
+    pub fn of<'a>(
+        &'a self,
+        faction: Faction,
+        window: AbilityWindow,
+        in_play: &'a [Faction],
+        cancelled: &'a [Faction],
+    ) -> impl Iterator<Item = &'a dyn FactionAbility> {
+        self.interrupts(window, in_play, cancelled)
+            .filter(move |ability| ability.faction() == faction)
+    }
+
+    /// Whether `faction`'s troops fight at full strength without spice
+    pub fn fights_unspiced(
+        &self,
+        faction: Faction,
+        in_play: &[Faction],
+        cancelled: &[Faction],
+    ) -> bool {
+        self.of(faction, AbilityWindow::Battle, in_play, cancelled)
+            .any(|ability| ability.fights_unspiced())
+    }
+
+    /// Whether `faction` can revive a leader while others of theirs are still alive
+    pub fn grows_gholas(
+        &self,
+        faction: Faction,
+        in_play: &[Faction],
+        cancelled: &[Faction],
+    ) -> bool {
+        self.of(faction, AbilityWindow::Revival, in_play, cancelled)
+            .any(|ability| ability.grows_gholas())
+    }
+
+    /// Whether `faction` takes a leader from those it beats in battle
+    pub fn captures_leaders(
+        &self,
+        faction: Faction,
+        in_play: &[Faction],
+        cancelled: &[Faction],
+    ) -> bool {
+        self.of(faction, AbilityWindow::Battle, in_play, cancelled)
+            .any(|ability| ability.captures_leaders())
+    }
+
+    /// Who collects the spice `payer` spends during `window`, or `None` for the bank
+    pub fn payee(
+        &self,
+        window: AbilityWindow,
+        payer: Faction,
+        in_play: &[Faction],
+        cancelled: &[Faction],
+    ) -> Option<Faction> {
+        self.interrupts(window, in_play, cancelled)
+            .find(|ability| ability.collects_from(payer))
+            .map(|ability| ability.faction())
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Leader {
     pub name: String,
@@ -66,6 +445,30 @@ pub struct Location {
     pub sectors: HashMap<i32, LocationNodes>,
 }
 
+impl Location {
+    /// Finds the sector whose triangles contain the given point in board coordinates.
+    pub fn sector_at(&self, p: Vec2) -> Option<i32> {
+        self.sectors
+            .iter()
+            .find(|(_, nodes)| {
+                nodes.indices.chunks_exact(3).any(|tri| {
+                    let (a, b, c) = (
+                        nodes.vertices[tri[0] as usize].truncate(),
+                        nodes.vertices[tri[1] as usize].truncate(),
+                        nodes.vertices[tri[2] as usize].truncate(),
+                    );
+                    let d1 = (p - b).perp_dot(a - b);
+                    let d2 = (p - c).perp_dot(b - c);
+                    let d3 = (p - a).perp_dot(c - a);
+                    let has_neg = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+                    let has_pos = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+                    !(has_neg && has_pos)
+                })
+            })
+            .map(|(&sector, _)| sector)
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct LocationNodes {
     pub vertices: Vec<Vec3>,
@@ -73,14 +476,6 @@ pub struct LocationNodes {
     pub fighters: Vec<Vec3>,
 }
 
-#[derive(Copy, Clone, Serialize, Deserialize, PartialEq, Debug)]
-pub enum Terrain {
-    Sand,
-    Rock,
-    Stronghold,
-    PolarSink,
-}
-
 #[derive(Copy, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Bonus {
     Carryalls,
@@ -88,7 +483,8 @@ pub enum Bonus {
     Smugglers,
     Harvesters,
 }
-#[derive(Copy, Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Copy, Clone, Serialize, Deserialize, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
 pub enum CardEffect {
     Worthless,
     PoisonWeapon,
@@ -106,7 +502,7 @@ pub enum CardEffect {
 }
 
 impl CardEffect {
-    fn description(&self) -> String {
+    pub fn description(&self) -> String {
         match self {
             CardEffect::Worthless =>
                 "Play as part of your Battle Plan, in place of weapon, defense, or both.
@@ -162,7 +558,8 @@ impl CardEffect {
     }
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
 pub struct TreacheryCard {
     pub id: i32,
     pub effect: CardEffect,
@@ -175,12 +572,20 @@ pub struct TraitorCard {
     pub leader: Leader,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
 pub struct SpiceCard {
     pub name: String,
+    pub amount: i32,
     pub texture: String,
 }
 
+impl SpiceCard {
+    pub fn is_shai_hulud(&self) -> bool {
+        self.name == "Shai-Halud"
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct StormCard {
     pub val: i32,
@@ -196,7 +601,43 @@ pub struct TurnPredictionCard {
     pub turn: i32,
 }
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+/// The board layout every game is played on, under `assets/`
+pub const BOARD_PATH: &str = "boards/standard.board";
+
+/// Where everything sits on and around the board. Kept in its own asset so other maps and fan
+/// variants can be swapped in without rebuilding the game.
+#[derive(Clone, Serialize, Deserialize, TypeUuid)]
+#[uuid = "0c7e5a9d-2f4b-4d8e-9a61-7b3c2e1f5d48"]
+pub struct Board {
+    pub locations: Vec<Location>,
+    pub camera_nodes: CameraNodes,
+    pub prediction_nodes: PredictionNodes,
+    pub traitor_nodes: Vec<Vec2>,
+    pub token_nodes: TokenNodes,
+}
+
+#[derive(Default)]
+pub struct BoardLoader;
+
+impl AssetLoader for BoardLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let board = ron::de::from_bytes::<Board>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(board));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["board"]
+    }
+}
+
+#[derive(Copy, Clone, Default, Serialize, Deserialize)]
 pub struct CameraNodes {
     pub main: CameraNode,
     pub shield: CameraNode,
@@ -205,9 +646,26 @@ pub struct CameraNodes {
     pub traitor: CameraNode,
     pub spice: CameraNode,
     pub storm: CameraNode,
+    /// Pulled back from the board while the Bene Gesserit make their prediction
+    pub prediction: CameraNode,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+impl CameraNodes {
+    /// Every preset view, in the order of the camera shortcut keys
+    pub fn views(&self) -> [CameraNode; 7] {
+        [
+            self.main,
+            self.board,
+            self.shield,
+            self.treachery,
+            self.traitor,
+            self.spice,
+            self.storm,
+        ]
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct PredictionNodes {
     pub src: Vec2,
     pub factions: Vec<Vec2>,
@@ -216,27 +674,58 @@ pub struct PredictionNodes {
     pub chosen_turn: Vec2,
 }
 
-#[derive(Copy, Clone, Serialize, Deserialize, Debug)]
+#[derive(Copy, Clone, Default, Serialize, Deserialize, Debug)]
 pub struct CameraNode {
     pub pos: Vec3,
     pub at: Vec3,
     pub up: Vec3,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
 pub struct TokenNodes {
     pub leaders: Vec<Vec3>,
     pub spice: Vec<Vec3>,
     pub fighters: Vec<Vec3>,
+    pub tanks: TankNodes,
     pub factions: Vec<Vec3>,
 }
 
+impl TokenNodes {
+    /// Where the `i`th dead troop of a faction sits in the Tleilaxu Tanks
+    pub fn tanks_fighter(&self, faction: Faction, i: usize) -> Vec3 {
+        self.tanks.fighters[faction.index()] + (i as f32 * 0.0036 * Vec3::unit_y())
+    }
+
+    /// Where the `i`th dead leader of a faction sits in the Tleilaxu Tanks
+    pub fn tanks_leader(&self, faction: Faction, i: usize) -> Vec3 {
+        self.tanks.leaders[faction.index()] + (i as f32 * 0.0036 * Vec3::unit_y())
+    }
+}
+
+/// One stack of troops and one of leaders per faction, in the order of `Faction::ALL`
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct TankNodes {
+    pub fighters: Vec<Vec3>,
+    pub leaders: Vec<Vec3>,
+}
+
+/// Which side of the screen a HUD panel keeps to as the window changes shape
+#[derive(Copy, Clone, PartialEq, Default, Debug, Serialize, Deserialize)]
+pub enum UiAnchor {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct UiRect {
     pub left: f32,
     pub right: f32,
     pub top: f32,
     pub bottom: f32,
+    #[serde(default)]
+    pub anchor: UiAnchor,
 }
 
 impl UiRect {
@@ -263,34 +752,177 @@ impl UiRect {
     }
 }
 
+/// Assets that are loaded together, so the loading screen can show what it is waiting on
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AssetGroup {
+    Board,
+    Tokens,
+    Cards,
+    Ui,
+    Faction(Faction),
+}
+
+impl AssetGroup {
+    /// Every group a game between these factions uses
+    pub fn needed(factions: &[Faction]) -> Vec<AssetGroup> {
+        let mut groups = vec![
+            AssetGroup::Ui,
+            AssetGroup::Board,
+            AssetGroup::Tokens,
+            AssetGroup::Cards,
+        ];
+        groups.extend(factions.iter().map(|&faction| AssetGroup::Faction(faction)));
+        groups
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            AssetGroup::Board => "Board".to_string(),
+            AssetGroup::Tokens => "Tokens".to_string(),
+            AssetGroup::Cards => "Cards".to_string(),
+            AssetGroup::Ui => "Interface".to_string(),
+            AssetGroup::Faction(faction) => faction.to_string(),
+        }
+    }
+
+    pub fn paths(&self, data: &Data) -> Vec<String> {
+        match self {
+            AssetGroup::Board => vec![
+                BOARD_PATH.to_string(),
+                "board.gltf".to_string(),
+                "shield.gltf".to_string(),
+            ],
+            AssetGroup::Tokens => vec![
+                "big_token.gltf".to_string(),
+                "little_token.gltf".to_string(),
+                "spice_token.gltf".to_string(),
+                "tokens/spice_1.png".to_string(),
+                "tokens/spice_2.png".to_string(),
+                "tokens/spice_5.png".to_string(),
+                "tokens/spice_10.png".to_string(),
+            ],
+            AssetGroup::Cards => {
+                let mut paths = vec![
+                    "card.gltf".to_string(),
+                    "treachery/treachery_back.png".to_string(),
+                    "traitor/traitor_back.png".to_string(),
+                    "spice/spice_back.png".to_string(),
+                    "storm/storm_back.png".to_string(),
+                ];
+                paths.extend(
+                    data.treachery_cards
+                        .iter()
+                        .map(|card| format!("treachery/treachery_{}.png", card.texture)),
+                );
+                paths.extend(
+                    data.spice_cards
+                        .iter()
+                        .map(|card| format!("spice/spice_{}.png", card.texture)),
+                );
+                paths.extend((1..7).map(|val| format!("storm/storm_{}.png", val)));
+                paths.extend((1..=15).map(|turn| format!("predictions/prediction_t{}.png", turn)));
+                paths.sort();
+                paths.dedup();
+                paths
+            }
+            AssetGroup::Ui => vec![
+                "fonts/FiraSans-Bold.ttf".to_string(),
+                "wheel_dial.png".to_string(),
+            ],
+            AssetGroup::Faction(faction) => {
+                let code = faction.code();
+                let mut paths = vec![
+                    format!("tokens/{}_logo.png", code),
+                    format!("tokens/{}_troop.png", code),
+                    format!("shields/{}_shield_front.png", code),
+                    format!("shields/{}_shield_back.png", code),
+                    format!("predictions/prediction_{}.png", code),
+                ];
+                paths.extend(
+                    data.leaders
+                        .iter()
+                        .filter(|leader| leader.faction == *faction)
+                        .map(|leader| format!("leaders/{}.png", leader.texture)),
+                );
+                // The traitor deck only holds leaders of the factions that are playing
+                paths.extend(
+                    data.leaders
+                        .iter()
+                        .filter(|leader| leader.faction == *faction)
+                        .map(|leader| format!("traitor/traitor_{}.png", leader.texture)),
+                );
+                paths
+            }
+        }
+    }
+}
+
+/// How a missing asset is stood in for, if it can be
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub enum AssetKind {
+    Scene,
+    /// The labelled meshes inside a glTF file that the game spawns directly
+    Meshes(Vec<String>),
+    Texture,
+    Font,
+}
+
+/// An asset the game expects to find, with a name fit to show the player when it is missing
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub path: String,
+    /// The game can't be played at all without it
+    #[serde(default)]
+    pub critical: bool,
+    pub kind: AssetKind,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct UiStructure {
     pub turn_order: UiRect,
     pub phases: UiRect,
     pub turn_counter: UiRect,
+    pub territory: UiRect,
+    pub tutorial: UiRect,
+    pub reference: UiRect,
+    pub reference_button: UiRect,
+    pub notice: UiRect,
+    pub emote_wheel: UiRect,
+    pub emotes: UiRect,
+    pub interrupt: UiRect,
+    pub territory_banner: UiRect,
+    pub pause_menu: UiRect,
+    pub toasts: UiRect,
+    pub event_log: UiRect,
+    pub mentat_pause: UiRect,
+    pub capture: UiRect,
 }
 
 impl UiStructure {
     pub fn get_turn_tiles(&self) -> Vec<UiRect> {
-        let tile_height = (self.turn_order.bottom - self.turn_order.top) / 6.0;
-        (0..6)
+        let count = Faction::ALL.len();
+        let tile_height = (self.turn_order.bottom - self.turn_order.top) / count as f32;
+        (0..count)
             .map(|i| UiRect {
                 top: self.turn_order.top + i as f32 * tile_height,
                 left: self.turn_order.left,
                 right: self.turn_order.right,
                 bottom: self.turn_order.top + (i as f32 + 1.0) * tile_height,
+                anchor: self.turn_order.anchor,
             })
             .collect()
     }
 
-    pub fn get_phase_bounds(&self) -> Vec<UiRect> {
-        let tile_width = (self.turn_order.right - self.turn_order.left) / 6.0;
-        (0..6)
+    pub fn get_phase_bounds(&self, count: usize) -> Vec<UiRect> {
+        let tile_width = (self.phases.right - self.phases.left) / count as f32;
+        (0..count)
             .map(|i| UiRect {
-                top: self.turn_order.top,
-                left: self.turn_order.left + i as f32 * tile_width,
-                right: self.turn_order.right + (i as f32 + 1.0) * tile_width,
-                bottom: self.turn_order.bottom,
+                top: self.phases.top,
+                left: self.phases.left + i as f32 * tile_width,
+                right: self.phases.left + (i as f32 + 1.0) * tile_width,
+                bottom: self.phases.bottom,
+                anchor: self.phases.anchor,
             })
             .collect()
     }
@@ -0,0 +1,290 @@
+use std::collections::HashSet;
+
+use bevy::{
+    prelude::*,
+    render::{
+        camera::{Camera, OrthographicProjection},
+        mesh::Indices,
+        pipeline::PrimitiveTopology,
+    },
+};
+use ncollide3d::shape::TriMesh;
+
+use crate::{
+    components::{Collider, LocationSector, Storm, Tooltip},
+    settings::{Binding, Settings},
+    util::{grab, world_to_screen},
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// Just above the board, so the outlines aren't lost in it
+const WIREFRAME_HEIGHT: f32 = 0.002;
+
+pub struct DebugPlugin;
+
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<DebugOverlay>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                debug_toggle_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                debug_toggle_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                debug_overlay_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                debug_overlay_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system());
+    }
+}
+
+/// Outlines every sector's collider, numbers the sectors, picks out the ones in the storm and
+/// says what is under the cursor, for tracking down bad board data
+#[derive(Default)]
+pub struct DebugOverlay {
+    enabled: bool,
+    storm: Option<i32>,
+    under_cursor: Option<Entity>,
+    materials: Option<(Handle<StandardMaterial>, Handle<StandardMaterial>)>,
+}
+
+struct DebugEntity;
+
+struct DebugWireframe {
+    sector: i32,
+}
+
+/// Pinned to a point on the board
+struct DebugLabel {
+    position: Vec3,
+}
+
+struct DebugCursorText;
+
+/// Every edge of the collider's triangles, once each
+fn wireframe(collider: &Collider) -> Option<Mesh> {
+    let trimesh = collider.shape.as_shape::<TriMesh<f32>>()?;
+    let positions = trimesh
+        .points()
+        .iter()
+        .map(|p| [p.x, p.y + WIREFRAME_HEIGHT, p.z])
+        .collect::<Vec<_>>();
+    let mut edges = HashSet::new();
+    for face in trimesh.faces() {
+        let [a, b, c] = [face.indices.x, face.indices.y, face.indices.z];
+        for &(from, to) in [(a, b), (b, c), (c, a)].iter() {
+            edges.insert((from.min(to) as u32, from.max(to) as u32));
+        }
+    }
+    let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+    mesh.set_attribute(
+        Mesh::ATTRIBUTE_NORMAL,
+        vec![[0.0, 1.0, 0.0]; positions.len()],
+    );
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; positions.len()]);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_indices(Some(Indices::U32(
+        edges
+            .into_iter()
+            .flat_map(|(from, to)| vec![from, to])
+            .collect(),
+    )));
+    Some(mesh)
+}
+
+fn debug_toggle_system(
+    commands: &mut Commands,
+    (keyboard_input, settings): (Res<Input<KeyCode>>, Res<Settings>),
+    (mut overlay, asset_server): (ResMut<DebugOverlay>, Res<AssetServer>),
+    (mut meshes, mut materials): (ResMut<Assets<Mesh>>, ResMut<Assets<StandardMaterial>>),
+    sectors: Query<(&Collider, &Transform, &LocationSector)>,
+    debug_entities: Query<Entity, With<DebugEntity>>,
+) {
+    if !settings.just_pressed(&keyboard_input, Binding::DebugOverlay) {
+        return;
+    }
+    overlay.enabled = !overlay.enabled;
+    if !overlay.enabled {
+        for entity in debug_entities.iter() {
+            commands.despawn(entity);
+        }
+        return;
+    }
+
+    let clear = overlay
+        .materials
+        .get_or_insert_with(|| {
+            let material = |color| StandardMaterial {
+                albedo: color,
+                shaded: false,
+                ..Default::default()
+            };
+            (
+                materials.add(material(Color::GREEN)),
+                materials.add(material(Color::RED)),
+            )
+        })
+        .0
+        .clone();
+    // Picked out on the first frame the overlay is up
+    overlay.storm = None;
+    overlay.under_cursor = None;
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    for (collider, transform, loc_sec) in sectors.iter() {
+        if let Some(mesh) = wireframe(collider) {
+            commands
+                .spawn(PbrBundle {
+                    mesh: meshes.add(mesh),
+                    material: clear.clone(),
+                    transform: *transform,
+                    ..Default::default()
+                })
+                .with(ScreenEntity)
+                .with(DebugEntity)
+                .with(DebugWireframe {
+                    sector: loc_sec.sector,
+                });
+        }
+        // Numbered at the middle of the sector's own vertices
+        if let Some(nodes) = loc_sec.location.sectors.get(&loc_sec.sector) {
+            let count = nodes.vertices.len().max(1) as f32;
+            let middle = nodes.vertices.iter().fold(Vec3::zero(), |sum, p| sum + *p) / count;
+            commands
+                .spawn(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        ..Default::default()
+                    },
+                    text: Text {
+                        font: font.clone(),
+                        value: loc_sec.sector.to_string(),
+                        style: TextStyle {
+                            font_size: 14.0,
+                            color: Color::YELLOW,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(ScreenEntity)
+                .with(DebugEntity)
+                .with(DebugLabel {
+                    position: Vec3::new(middle.x, 0.01, -middle.y),
+                });
+        }
+    }
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(5.0),
+                    right: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                font,
+                value: String::new(),
+                style: TextStyle {
+                    font_size: 16.0,
+                    color: Color::YELLOW,
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(DebugEntity)
+        .with(DebugCursorText);
+}
+
+type Materials<'a, 'b, 'c> = Query<'a, (&'b mut Handle<StandardMaterial>, &'c DebugWireframe)>;
+
+fn debug_overlay_system(
+    (mut overlay, windows): (ResMut<DebugOverlay>, Res<Windows>),
+    cameras: Query<(&Camera, &Transform), Without<OrthographicProjection>>,
+    colliders: Query<(Entity, &Collider, &Transform)>,
+    (tooltips, storm): (Query<&Tooltip>, Query<&Storm>),
+    mut wireframes: Materials,
+    mut labels: Query<(&mut Style, &DebugLabel)>,
+    mut cursor_text: Query<&mut Text, With<DebugCursorText>>,
+) {
+    if !overlay.enabled {
+        return;
+    }
+
+    let storm_sector = storm.iter().next().map(|storm| storm.sector);
+    if storm_sector != overlay.storm {
+        overlay.storm = storm_sector;
+        if let Some((clear, stormy)) = overlay.materials.clone() {
+            for (mut material, wireframe) in wireframes.iter_mut() {
+                *material = if Some(wireframe.sector) == storm_sector {
+                    stormy.clone()
+                } else {
+                    clear.clone()
+                };
+            }
+        }
+    }
+
+    if let (Some((camera, cam_transform)), Some(window)) =
+        (cameras.iter().next(), windows.get_primary())
+    {
+        for (mut style, label) in labels.iter_mut() {
+            if let Some(pos) = world_to_screen(label.position, window, camera, cam_transform) {
+                style.position = Rect {
+                    left: Val::Px(pos.x),
+                    bottom: Val::Px(pos.y),
+                    ..Default::default()
+                };
+            }
+        }
+    }
+
+    // Disabled colliders too, since those are often the ones in question
+    let under_cursor = grab(
+        &windows,
+        &cameras,
+        colliders
+            .iter()
+            .map(|(entity, collider, transform)| (entity, collider, *transform)),
+    );
+    if under_cursor != overlay.under_cursor {
+        overlay.under_cursor = under_cursor;
+        let value = under_cursor.map_or_else(String::new, |entity| {
+            let enabled = matches!(colliders.get(entity), Ok((_, collider, _)) if collider.enabled);
+            format!(
+                "{:?}{}{}",
+                entity,
+                tooltips
+                    .get(entity)
+                    .map_or_else(|_| String::new(), |tooltip| format!(" ({})", tooltip.0)),
+                if enabled { "" } else { " [disabled]" }
+            )
+        });
+        if !value.is_empty() {
+            println!("Under cursor: {}", value);
+        }
+        for mut text in cursor_text.iter_mut() {
+            text.value = value.clone();
+        }
+    }
+}
+
+fn reset(mut overlay: ResMut<DebugOverlay>) {
+    *overlay = DebugOverlay::default();
+}
@@ -0,0 +1,184 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::{Faction, SpiceCard, TreacheryCard},
+    locale::Tr,
+    network::{ConnectionState, Network, NetworkType, Server},
+    protocol::ServerEvent,
+    resources::{open_data, Data},
+    Screen, STATE_CHANGE_STAGE,
+};
+
+/// Every `.ron` file in here is a deck preset the host can pick in the lobby
+pub const DECKS_DIR: &str = "data/decks";
+/// The preset games use unless the host picks another one
+pub const STANDARD_DECK: &str = "data/decks/standard.ron";
+
+pub struct DecksPlugin;
+
+impl Plugin for DecksPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<DeckPresets>().on_state_update(
+            STATE_CHANGE_STAGE,
+            Screen::Server,
+            deck_sync_system.system(),
+        );
+    }
+}
+
+/// The treachery and spice cards a game is played with
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct DeckPreset {
+    pub name: String,
+    pub treachery: Vec<TreacheryCard>,
+    pub spice: Vec<SpiceCard>,
+}
+
+impl DeckPreset {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = open_data(&path.to_string_lossy()).map_err(|e| e.to_string())?;
+        let preset: DeckPreset = ron::de::from_reader(file).map_err(|e| e.to_string())?;
+        preset.validate()?;
+        Ok(preset)
+    }
+
+    /// Makes sure a game can be played with these cards and that every card has something to
+    /// show on its face
+    pub fn validate(&self) -> Result<(), String> {
+        // Everyone is dealt a card during setup
+        if self.treachery.len() < Faction::ALL.len() {
+            return Err(format!(
+                "needs at least {} treachery cards, has {}",
+                Faction::ALL.len(),
+                self.treachery.len()
+            ));
+        }
+        let mut ids = HashSet::new();
+        if let Some(card) = self.treachery.iter().find(|card| !ids.insert(card.id)) {
+            return Err(format!("treachery card id {} is used twice", card.id));
+        }
+        if !self.spice.iter().any(|card| !card.is_shai_hulud()) {
+            return Err("needs at least one territory spice card".to_string());
+        }
+        if let Some(card) = self
+            .spice
+            .iter()
+            .find(|card| !card.is_shai_hulud() && card.amount <= 0)
+        {
+            return Err(format!("{} has no spice on it", card.name));
+        }
+        let textures = self
+            .treachery
+            .iter()
+            .map(|card| format!("treachery/treachery_{}.png", card.texture))
+            .chain(
+                self.spice
+                    .iter()
+                    .map(|card| format!("spice/spice_{}.png", card.texture)),
+            );
+        for texture in textures {
+            if !Path::new("assets").join(&texture).exists() {
+                return Err(format!("missing texture {}", texture));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every valid preset found on disk, and the one the host has picked
+pub struct DeckPresets {
+    pub presets: Vec<DeckPreset>,
+    pub selected: usize,
+}
+
+impl Default for DeckPresets {
+    fn default() -> Self {
+        // With no folder to look in, as in the browser, there is just the standard deck
+        let mut paths = fs::read_dir(DECKS_DIR)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().map_or(false, |ext| ext == "ron"))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_else(|_| vec![PathBuf::from(STANDARD_DECK)]);
+        // The standard deck always comes first
+        paths.sort_by_key(|path| (path != Path::new(STANDARD_DECK), path.clone()));
+        let presets = paths
+            .into_iter()
+            .filter_map(|path| match DeckPreset::open(&path) {
+                Ok(preset) => Some(preset),
+                Err(e) => {
+                    println!("Skipping deck preset {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        DeckPresets {
+            presets,
+            selected: 0,
+        }
+    }
+}
+
+impl DeckPresets {
+    pub fn selected(&self) -> Option<&DeckPreset> {
+        self.presets.get(self.selected)
+    }
+
+    pub fn next(&mut self) {
+        self.selected = (self.selected + 1) % self.presets.len().max(1);
+    }
+
+    pub fn label(&self, tr: &Tr) -> String {
+        let name = self
+            .selected()
+            .map_or_else(|| tr.get("lobby.no_deck"), |preset| preset.name.clone());
+        tr.fmt("lobby.deck", &[&name])
+    }
+}
+
+/// Puts the host's pick in play and sends it to every client, so their decks are built from the
+/// same cards once the game starts
+fn deck_sync_system(
+    (network, presets, mut data): (Res<Network>, Res<DeckPresets>, ResMut<Data>),
+    mut informed: Local<(usize, usize)>,
+    mut server: Query<&mut Server>,
+) {
+    if network.network_type == NetworkType::Client {
+        return;
+    }
+    let preset = if let Some(preset) = presets.selected() {
+        preset
+    } else {
+        return;
+    };
+    // A loaded game may have left its own decks behind
+    if data.treachery_cards != preset.treachery || data.spice_cards != preset.spice {
+        data.set_decks(preset);
+    }
+    if let Some(mut server) = server.iter_mut().next() {
+        // Anyone who connected since needs it too
+        let connected = server
+            .clients
+            .values()
+            .filter(|connection| connection.state == ConnectionState::Healthy)
+            .count();
+        if *informed != (connected, presets.selected) {
+            server.broadcast(&ServerEvent::DeckPreset {
+                preset: preset.clone(),
+            });
+            *informed = (connected, presets.selected);
+        }
+    }
+}
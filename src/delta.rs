@@ -0,0 +1,679 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::SocketAddr,
+};
+
+use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
+
+use crate::{
+    components::{Dead, LocationSector, Player, Spice, SpiceNode, Storm, Troop, Unique},
+    data::{Faction, Location},
+    lerper::{Lerp, LerpType},
+    network::{Client, ConnectionState, Network, NetworkType, Server},
+    phase::{place_spice, ActionQueue, GamePhase, Phase, TroopsDeployed},
+    protocol::{ClientRequest, ServerEvent},
+    resources::{Data, Info, RuleSet, SectorNode},
+    token_pool::TokenPool,
+    Screen, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// Versions the host keeps around to diff against. A client whose last acknowledged version has
+/// dropped out of this gets a keyframe instead.
+const HISTORY: usize = 32;
+/// Everyone is sent the whole state every this many versions, in case something went missing
+const KEYFRAME_INTERVAL: u32 = 20;
+const RESTACK_TIME: f32 = 0.3;
+
+pub struct DeltaPlugin;
+
+impl Plugin for DeltaPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<StateDeltas>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                broadcast_delta_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                collect_deployments_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                apply_delta_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system());
+    }
+}
+
+/// One piece of the public game state, small enough to be sent on its own when it changes
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum StateKey {
+    Turn,
+    Phase,
+    Storm,
+    /// Spice in a faction's reserve
+    Spice(Faction),
+    /// How many of a faction's troops are in one place: a sector of the board, the reserves or
+    /// the tanks
+    Troops {
+        faction: Faction,
+        location: Option<SectorNode>,
+        dead: bool,
+    },
+    /// Spice lying in a territory
+    BoardSpice(String),
+    /// A faction that is out of the game, valued by when it went out
+    Eliminated(Faction),
+}
+
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum StateValue {
+    Count(i32),
+    Phase(Phase),
+}
+
+/// A piece of state set to a new value, or gone when there is no value
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct StateChange {
+    pub key: StateKey,
+    pub value: Option<StateValue>,
+}
+
+type Snapshot = HashMap<StateKey, StateValue>;
+
+/// Everything that changed between two snapshots
+fn diff(from: &Snapshot, to: &Snapshot) -> Vec<StateChange> {
+    let changed = to
+        .iter()
+        .filter(|&(key, value)| from.get(key) != Some(value))
+        .map(|(key, value)| StateChange {
+            key: key.clone(),
+            value: Some(value.clone()),
+        });
+    let removed = from
+        .keys()
+        .filter(|key| !to.contains_key(key))
+        .map(|key| StateChange {
+            key: key.clone(),
+            value: None,
+        });
+    changed.chain(removed).collect()
+}
+
+fn apply(snapshot: &mut Snapshot, changes: Vec<StateChange>) {
+    for change in changes {
+        match change.value {
+            Some(value) => snapshot.insert(change.key, value),
+            None => snapshot.remove(&change.key),
+        };
+    }
+}
+
+/// The host's numbered versions of the state and how far each client has got through them, and
+/// on a client the version it is at and what has arrived since
+#[derive(Default)]
+pub struct StateDeltas {
+    version: u32,
+    history: VecDeque<(u32, Snapshot)>,
+    acked: HashMap<SocketAddr, u32>,
+    /// The last base and version sent to each client, so nothing is sent twice
+    sent: HashMap<SocketAddr, (Option<u32>, u32)>,
+    snapshot: Snapshot,
+    /// Diffs from the host as (base, version, changes), where no base means a keyframe
+    pub received: Vec<(Option<u32>, u32, Vec<StateChange>)>,
+    /// Shipments to fly onto the board: on the host, ones to announce along with the next
+    /// version, and on a client, ones to play as that version comes in
+    pub deployments: Vec<TroopsDeployed>,
+}
+
+impl StateDeltas {
+    pub fn acknowledge(&mut self, address: SocketAddr, version: u32) {
+        let acked = self.acked.entry(address).or_insert(version);
+        *acked = (*acked).max(version);
+    }
+}
+
+fn take_snapshot(
+    (info, phase): (&Info, Phase),
+    (players, storm): (&Query<&Player>, &Query<&Storm>),
+    (troops, sectors): (&Troops, &Query<&LocationSector>),
+    (spice, names): (&Query<&Spice>, &Query<&Location>),
+) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    snapshot.insert(StateKey::Turn, StateValue::Count(info.turn));
+    snapshot.insert(StateKey::Phase, StateValue::Phase(phase));
+    if let Some(storm) = storm.iter().next() {
+        snapshot.insert(StateKey::Storm, StateValue::Count(storm.sector));
+    }
+    for player in players.iter() {
+        snapshot.insert(
+            StateKey::Spice(player.faction),
+            StateValue::Count(player.spice),
+        );
+    }
+    for (i, &faction) in info.eliminated.iter().enumerate() {
+        snapshot.insert(StateKey::Eliminated(faction), StateValue::Count(i as i32));
+    }
+    let mut counts = HashMap::new();
+    for (troop, unique, dead) in troops.iter() {
+        let location = troop
+            .location
+            .and_then(|location| sectors.get(location).ok())
+            .map(|loc_sec| SectorNode::new(&loc_sec.location.name, loc_sec.sector));
+        *counts
+            .entry(StateKey::Troops {
+                faction: unique.faction,
+                location,
+                dead: dead.is_some(),
+            })
+            .or_insert(0) += 1;
+    }
+    for spice in spice.iter() {
+        if let Some(location) = spice.location.and_then(|location| names.get(location).ok()) {
+            *counts
+                .entry(StateKey::BoardSpice(location.name.clone()))
+                .or_insert(0) += spice.value;
+        }
+    }
+    snapshot.extend(
+        counts
+            .into_iter()
+            .map(|(key, count)| (key, StateValue::Count(count))),
+    );
+    snapshot
+}
+
+/// Numbers each new state once the queue settles, and sends every client what changed since the
+/// last version it acknowledged, or the whole state when that is too old or a keyframe is due
+fn broadcast_delta_system(
+    (mut deltas, info, phase, queue, rules): (
+        ResMut<StateDeltas>,
+        Res<Info>,
+        Res<GamePhase>,
+        Res<ActionQueue>,
+        Res<RuleSet>,
+    ),
+    (players, storm): (Query<&Player>, Query<&Storm>),
+    troops: Troops,
+    sectors: Query<&LocationSector>,
+    (spice, names): (Query<&Spice>, Query<&Location>),
+    mut server: Query<&mut Server>,
+) {
+    let mut server = if let Some(server) = server.iter_mut().next() {
+        server
+    } else {
+        return;
+    };
+    if !queue.is_empty() {
+        return;
+    }
+    let snapshot = take_snapshot(
+        (&info, phase.phase),
+        (&players, &storm),
+        (&troops, &sectors),
+        (&spice, &names),
+    );
+    if deltas.history.back().map(|(_, last)| last) != Some(&snapshot) {
+        // Sent ahead of the version that has the troops landed, on the same ordered channel
+        if !deltas.deployments.is_empty() {
+            let deployments = std::mem::take(&mut deltas.deployments);
+            server.broadcast(&ServerEvent::Deploy { deployments });
+        }
+        deltas.version += 1;
+        let version = deltas.version;
+        deltas.history.push_back((version, snapshot));
+        if deltas.history.len() > HISTORY {
+            deltas.history.pop_front();
+        }
+    }
+    let (version, latest) = if let Some((version, latest)) = deltas.history.back() {
+        (*version, latest)
+    } else {
+        return;
+    };
+    let keyframe = version % KEYFRAME_INTERVAL == 0;
+
+    let addresses = server
+        .clients
+        .values()
+        .filter(|connection| connection.state == ConnectionState::Healthy)
+        .map(|connection| connection.address)
+        .filter(|&address| server.is_joined(address))
+        .collect::<Vec<_>>();
+    let casters = server.casters();
+    let mut sends = Vec::new();
+    for address in addresses {
+        let acked = deltas.acked.get(&address).copied();
+        if acked == Some(version) {
+            continue;
+        }
+        let base = acked
+            .filter(|_| !keyframe)
+            .and_then(|acked| deltas.history.iter().find(|(v, _)| *v == acked));
+        let message = (base.map(|(base, _)| *base), version);
+        if deltas.sent.get(&address) == Some(&message) {
+            continue;
+        }
+        let mut changes = match base {
+            Some((_, base)) => diff(base, latest),
+            None => diff(&Snapshot::new(), latest),
+        };
+        // Spice behind a shield is only told to its owner and the casters
+        if !rules.public_spice() && !casters.contains(&address) {
+            changes.retain(|change| match change.key {
+                StateKey::Spice(faction) => server.address_of(faction) == Some(address),
+                _ => true,
+            });
+        }
+        sends.push((address, message, changes));
+    }
+    for (address, (base, version), changes) in sends {
+        deltas.sent.insert(address, (base, version));
+        server.send_event(
+            address,
+            &ServerEvent::StateDelta {
+                base,
+                version,
+                changes,
+            },
+        );
+    }
+}
+
+fn collect_deployments_system(
+    mut deltas: ResMut<StateDeltas>,
+    (deployed, mut reader): (
+        Res<Events<TroopsDeployed>>,
+        Local<EventReader<TroopsDeployed>>,
+    ),
+) {
+    deltas.deployments.extend(reader.iter(&deployed).cloned());
+}
+
+type Troops<'a, 'b, 'c, 'd> = Query<'a, (&'b Troop, &'c Unique, Option<&'d Dead>)>;
+type ClientTroops<'a, 'b, 'c> = Query<'a, (Entity, &'b mut Troop, &'c Unique, Option<&'c Dead>)>;
+type SpiceNodes<'a, 'b, 'c> = Query<'a, (Entity, &'b Location, &'c SpiceNode)>;
+
+/// Applies whatever the host sent that follows on from the version we are at, acknowledges it,
+/// and brings the board in line with it
+fn apply_delta_system(
+    commands: &mut Commands,
+    network: Res<Network>,
+    (mut deltas, mut info, mut phase): (ResMut<StateDeltas>, ResMut<Info>, ResMut<GamePhase>),
+    (data, mut pool): (Res<Data>, ResMut<TokenPool>),
+    (mut players, mut storm): (Query<&mut Player>, Query<&mut Storm>),
+    (mut troops, sectors): (ClientTroops, Query<(Entity, &LocationSector)>),
+    (spice_nodes, spice): (SpiceNodes, Query<(Entity, &Spice)>),
+    mut client: Query<&mut Client>,
+) {
+    if network.network_type != NetworkType::Client || deltas.received.is_empty() {
+        return;
+    }
+    let previous = deltas.snapshot.clone();
+    let mut applied = None;
+    for (base, version, changes) in std::mem::take(&mut deltas.received) {
+        match base {
+            None => {
+                deltas.snapshot.clear();
+                apply(&mut deltas.snapshot, changes);
+            }
+            // Anything built on a version we skipped is superseded by the next one the host sends
+            Some(base) if base == deltas.version => apply(&mut deltas.snapshot, changes),
+            Some(_) => continue,
+        }
+        deltas.version = version;
+        applied = Some(version);
+    }
+    let version = if let Some(version) = applied {
+        version
+    } else {
+        return;
+    };
+    if let Some(mut client) = client.iter_mut().next() {
+        client.request(ClientRequest::StateAck { version });
+    }
+
+    let changed = diff(&previous, &deltas.snapshot);
+    let mut troops_changed = HashSet::new();
+    for StateChange { key, value } in changed {
+        let count = match value {
+            Some(StateValue::Count(count)) => count,
+            _ => 0,
+        };
+        match key {
+            StateKey::Turn => info.turn = count,
+            StateKey::Phase => {
+                if let Some(StateValue::Phase(new)) = value {
+                    phase.phase = new;
+                }
+            }
+            StateKey::Storm => {
+                for mut storm in storm.iter_mut() {
+                    storm.sector = count;
+                }
+            }
+            StateKey::Spice(faction) => {
+                for mut player in players
+                    .iter_mut()
+                    .filter(|player| player.faction == faction)
+                {
+                    player.spice = count;
+                }
+            }
+            StateKey::Eliminated(faction) => {
+                if value.is_none() {
+                    info.eliminated.retain(|&out| out != faction);
+                } else if !info.eliminated.contains(&faction) {
+                    info.eliminated.push(faction);
+                }
+            }
+            StateKey::Troops { faction, .. } => {
+                troops_changed.insert(faction);
+            }
+            StateKey::BoardSpice(name) => {
+                if let Some((location, _, node)) = spice_nodes
+                    .iter()
+                    .find(|(_, location, _)| location.name == name)
+                {
+                    for (token, spice) in spice
+                        .iter()
+                        .filter(|(_, spice)| spice.location == Some(location))
+                    {
+                        pool.release_spice(commands, token, spice.value);
+                    }
+                    place_spice(commands, &mut pool, count, location, node, 0);
+                }
+            }
+        }
+    }
+    let mut deployments = std::mem::take(&mut deltas.deployments);
+    if !troops_changed.is_empty() {
+        reconcile_troops(
+            commands,
+            (&deltas.snapshot, &data),
+            (&troops_changed, &mut deployments),
+            &mut troops,
+            &sectors,
+        );
+    }
+}
+
+/// Moves troops of each faction in `factions` around until there are as many in each place as
+/// the host says, then lines up every stack that changed. Troops that came out of the reserves as
+/// part of one of the host's `deployments` fly in the way the host's did.
+fn reconcile_troops(
+    commands: &mut Commands,
+    (snapshot, data): (&Snapshot, &Data),
+    (factions, deployments): (&HashSet<Faction>, &mut Vec<TroopsDeployed>),
+    troops: &mut ClientTroops,
+    sectors: &Query<(Entity, &LocationSector)>,
+) {
+    let sector_entities = sectors
+        .iter()
+        .map(|(entity, loc_sec)| {
+            (
+                SectorNode::new(&loc_sec.location.name, loc_sec.sector),
+                entity,
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    let node_of = |entity: Option<Entity>| {
+        entity
+            .and_then(|entity| sectors.get(entity).ok())
+            .map(|(_, loc_sec)| SectorNode::new(&loc_sec.location.name, loc_sec.sector))
+    };
+
+    // Where every troop ends up, for lining up the stacks afterwards
+    let mut places = troops
+        .iter_mut()
+        .map(|(entity, troop, unique, dead)| {
+            (
+                entity,
+                (unique.faction, node_of(troop.location), dead.is_some()),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut restack = HashSet::new();
+    let mut deploying = HashMap::new();
+    for &faction in factions {
+        let mut wanted = snapshot
+            .iter()
+            .filter_map(|(key, value)| match (key, value) {
+                (
+                    StateKey::Troops {
+                        faction: owner,
+                        location,
+                        dead,
+                    },
+                    StateValue::Count(count),
+                ) if *owner == faction => Some(((location.clone(), *dead), *count)),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        let mut spare = Vec::new();
+        for (i, (_, (owner, location, dead))) in places.iter().enumerate() {
+            if *owner != faction {
+                continue;
+            }
+            match wanted.get_mut(&(location.clone(), *dead)) {
+                Some(count) if *count > 0 => *count -= 1,
+                _ => spare.push(i),
+            }
+        }
+        let mut spare = spare.into_iter();
+        for ((location, dead), count) in wanted {
+            for _ in 0..count {
+                let i = if let Some(i) = spare.next() {
+                    i
+                } else {
+                    println!("The host has more {} troops than we do", faction);
+                    break;
+                };
+                let (entity, (_, from, was_dead)) = places[i].clone();
+                if let Some(deployment) = deployments.iter_mut().find(|deployment| {
+                    from.is_none()
+                        && !was_dead
+                        && !dead
+                        && deployment.faction == faction
+                        && Some(&deployment.to) == location.as_ref()
+                        && deployment.count > 0
+                }) {
+                    deployment.count -= 1;
+                    let order = deploying.len();
+                    deploying.insert(entity, order);
+                }
+                restack.insert((faction, from, was_dead));
+                restack.insert((faction, location.clone(), dead));
+                if let Ok((_, mut troop, _, _)) = troops.get_mut(entity) {
+                    troop.location = location
+                        .as_ref()
+                        .and_then(|location| sector_entities.get(location))
+                        .copied();
+                }
+                if dead && !was_dead {
+                    commands.insert_one(entity, Dead);
+                } else if !dead && was_dead {
+                    commands.remove_one::<Dead>(entity);
+                }
+                places[i].1 = (faction, location.clone(), dead);
+            }
+        }
+    }
+
+    for (faction, location, dead) in restack {
+        let lerps = match location.as_ref() {
+            // Every faction in a sector has its own stack there
+            Some(location) => {
+                let mut present = places
+                    .iter()
+                    .filter(|(_, (_, at, _))| at.as_ref() == Some(location))
+                    .map(|(_, (owner, _, _))| *owner)
+                    .collect::<Vec<_>>();
+                present.sort_by_key(|faction| faction.index());
+                present.dedup();
+                let node = sector_entities
+                    .get(location)
+                    .and_then(|&entity| sectors.get(entity).ok())
+                    .and_then(|(_, loc_sec)| {
+                        let stack = present.iter().position(|&owner| owner == faction)?;
+                        loc_sec.location.sectors[&loc_sec.sector]
+                            .fighters
+                            .get(stack)
+                            .copied()
+                    });
+                node.map(|node| Vec3::new(node.x, node.z, -node.y))
+                    .map(|base| (base, 0.0018))
+            }
+            None if dead => Some((data.token_nodes.tanks_fighter(faction, 0), 0.0036)),
+            None => data
+                .token_nodes
+                .fighters
+                .first()
+                .map(|&base| (base, 0.0036)),
+        };
+        let (base, spacing) = if let Some(lerps) = lerps {
+            lerps
+        } else {
+            continue;
+        };
+        for (i, (entity, _)) in places
+            .iter()
+            .filter(|(_, place)| *place == (faction, location.clone(), dead))
+            .enumerate()
+        {
+            let dest = Transform::from_translation(base + i as f32 * spacing * Vec3::unit_y());
+            let lerp = match deploying.get(entity) {
+                Some(&order) => Lerp::deploy(dest, order),
+                None => Lerp::new(LerpType::world_to(dest), RESTACK_TIME, 0.0),
+            };
+            commands.insert_one(*entity, lerp);
+        }
+    }
+}
+
+fn reset(mut deltas: ResMut<StateDeltas>) {
+    *deltas = StateDeltas::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::AssetPlugin;
+
+    use super::*;
+    use crate::material_cache::MaterialCache;
+
+    fn snapshot(entries: &[(StateKey, i32)]) -> Snapshot {
+        entries
+            .iter()
+            .map(|(key, count)| (key.clone(), StateValue::Count(*count)))
+            .collect()
+    }
+
+    #[test]
+    fn diff_then_apply_reaches_the_target() {
+        let from = snapshot(&[
+            (StateKey::Turn, 1),
+            (StateKey::Storm, 4),
+            (StateKey::Spice(Faction::Atreides), 10),
+            (StateKey::BoardSpice("Broken Land".to_string()), 6),
+        ]);
+        let to = snapshot(&[
+            (StateKey::Turn, 1),
+            (StateKey::Storm, 7),
+            (StateKey::Spice(Faction::Atreides), 10),
+            (StateKey::Spice(Faction::Fremen), 3),
+        ]);
+        let changes = diff(&from, &to);
+        // Only what changed is sent: the storm, the new reserve and the harvested spice
+        assert_eq!(changes.len(), 3);
+        assert!(changes.contains(&StateChange {
+            key: StateKey::BoardSpice("Broken Land".to_string()),
+            value: None,
+        }));
+        let mut applied = from.clone();
+        apply(&mut applied, changes);
+        assert_eq!(applied, to);
+    }
+
+    /// Stands up just the delta plugin, as a client sitting in the game
+    fn client_app() -> App {
+        let mut app = App::build();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin)
+            .add_asset::<Mesh>()
+            .add_asset::<StandardMaterial>()
+            .init_resource::<MaterialCache>()
+            .init_resource::<TokenPool>()
+            .init_resource::<Data>()
+            .init_resource::<Info>()
+            .init_resource::<GamePhase>()
+            .add_resource(Network {
+                network_type: NetworkType::Client,
+                ..Default::default()
+            })
+            .add_resource(State::new(Screen::HostingGame))
+            .add_stage_after(
+                stage::UPDATE,
+                STATE_CHANGE_STAGE,
+                StateStage::<Screen>::default(),
+            )
+            .add_stage_after(
+                STATE_CHANGE_STAGE,
+                RESPONSE_STAGE,
+                StateStage::<Screen>::default(),
+            )
+            .add_plugin(DeltaPlugin);
+        app.app
+    }
+
+    #[test]
+    fn client_applies_what_the_host_sends() {
+        let mut app = client_app();
+        let player = app
+            .world
+            .spawn((Player::new(Faction::Atreides, &Vec::new()),));
+
+        let host = snapshot(&[(StateKey::Turn, 3), (StateKey::Spice(Faction::Atreides), 7)]);
+        let keyframe = diff(&Snapshot::new(), &host);
+        app.resources
+            .get_mut::<StateDeltas>()
+            .unwrap()
+            .received
+            .push((None, 1, keyframe));
+        app.update();
+        assert_eq!(app.resources.get::<Info>().unwrap().turn, 3);
+        assert_eq!(app.world.get::<Player>(player).unwrap().spice, 7);
+
+        let next = snapshot(&[(StateKey::Turn, 3), (StateKey::Spice(Faction::Atreides), 2)]);
+        let delta = diff(&host, &next);
+        app.resources
+            .get_mut::<StateDeltas>()
+            .unwrap()
+            .received
+            .push((Some(1), 2, delta));
+        app.update();
+        assert_eq!(app.resources.get::<StateDeltas>().unwrap().version, 2);
+        assert_eq!(app.world.get::<Player>(player).unwrap().spice, 2);
+    }
+
+    #[test]
+    fn keyframe_from_nothing() {
+        let to = snapshot(&[
+            (StateKey::Turn, 2),
+            (StateKey::Eliminated(Faction::Emperor), 0),
+        ]);
+        let mut applied = Snapshot::new();
+        apply(&mut applied, diff(&Snapshot::new(), &to));
+        assert_eq!(applied, to);
+        assert!(diff(&to, &to).is_empty());
+    }
+}
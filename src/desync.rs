@@ -0,0 +1,278 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    components::{Dead, Player, Spice, Storm, Troop, Unique},
+    data::{Faction, Location},
+    dune_core::Occupancy,
+    network::{Client, Network, NetworkType, Server},
+    phase::{ActionQueue, GamePhase, Phase, SpiceDeck},
+    protocol::{ClientRequest, ServerEvent},
+    resources::{Info, RuleSet},
+    Screen, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+pub struct DesyncPlugin;
+
+impl Plugin for DesyncPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<StateHashes>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                broadcast_hash_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                compare_hash_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system());
+    }
+}
+
+/// One part of the game state, written out the same way on every machine
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct StateSection {
+    pub name: String,
+    /// SHA-256 of the summary, which has to come out the same on every build
+    pub hash: Vec<u8>,
+    pub summary: String,
+}
+
+impl StateSection {
+    fn new(name: &str, summary: String) -> Self {
+        StateSection {
+            name: name.to_string(),
+            hash: Sha256::digest(summary.as_bytes()).to_vec(),
+            summary,
+        }
+    }
+}
+
+/// The last turn the host sent out a hash for, and the latest one a client has yet to check
+#[derive(Default)]
+pub struct StateHashes {
+    sent: Option<i32>,
+    pub received: Option<(i32, Vec<StateSection>)>,
+}
+
+/// Writes out everything every machine can see: the phase, the storm, the spice ledger when spice is
+/// public, the size of each hand and deck, and where the troops and spice are. Maps are sorted so
+/// the order never depends on the machine.
+pub fn digest(
+    (info, phase, spice_deck, occupancy): (&Info, &Phase, &SpiceDeck, &Occupancy),
+    rules: &RuleSet,
+    players: &Query<&Player>,
+    storm: &Query<&Storm>,
+    tanked: &Query<&Unique, (With<Troop>, With<Dead>)>,
+    spice: &Query<&Spice>,
+    names: &Query<&Location>,
+) -> Vec<StateSection> {
+    let mut factions = players.iter().collect::<Vec<_>>();
+    factions.sort_by_key(|player| player.faction.index());
+    let per_faction = |value: &dyn Fn(&Player) -> String| {
+        factions
+            .iter()
+            .map(|player| format!("{}: {}", player.faction, value(player)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let board = occupancy
+        .sectors
+        .iter()
+        .flat_map(|(node, counts)| {
+            counts.iter().map(move |(faction, count)| {
+                (
+                    (node.location.clone(), node.sector, faction.index()),
+                    *count,
+                )
+            })
+        })
+        .collect::<BTreeMap<_, _>>();
+    let mut tanks = BTreeMap::<usize, (Faction, i32)>::new();
+    for unique in tanked.iter() {
+        tanks
+            .entry(unique.faction.index())
+            .or_insert((unique.faction, 0))
+            .1 += 1;
+    }
+    let mut spice_on_board = BTreeMap::<String, i32>::new();
+    for (name, value) in spice.iter().filter_map(|spice| {
+        spice
+            .location
+            .and_then(|location| names.get(location).ok())
+            .map(|location| (location.name.clone(), spice.value))
+    }) {
+        *spice_on_board.entry(name).or_insert(0) += value;
+    }
+
+    let mut sections = vec![
+        StateSection::new("phase", format!("turn {}, {:?}", info.turn, phase)),
+        StateSection::new(
+            "storm",
+            storm
+                .iter()
+                .next()
+                .map_or_else(String::new, |storm| storm.sector.to_string()),
+        ),
+        StateSection::new(
+            "hands",
+            per_faction(&|player| {
+                format!(
+                    "{} treachery, {} traitors",
+                    player.treachery_cards.len(),
+                    player.traitor_cards.len()
+                )
+            }),
+        ),
+        StateSection::new(
+            "decks",
+            format!(
+                "{} spice discarded, {} set aside",
+                spice_deck.discard.len(),
+                spice_deck.set_aside.len()
+            ),
+        ),
+        StateSection::new(
+            "board",
+            board
+                .into_iter()
+                .map(|((location, sector, _), count)| format!("{}#{}: {}", location, sector, count))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        StateSection::new(
+            "tanks",
+            tanks
+                .values()
+                .map(|(faction, count)| format!("{}: {}", faction, count))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        StateSection::new(
+            "board spice",
+            spice_on_board
+                .into_iter()
+                .map(|(location, value)| format!("{}: {}", location, value))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+    ];
+    // Otherwise nobody but the host knows every faction's spice
+    if rules.public_spice() {
+        sections.push(StateSection::new(
+            "spice",
+            per_faction(&|player| player.spice.to_string()),
+        ));
+    }
+    sections
+}
+
+type Resources<'a> = (
+    Res<'a, Info>,
+    Res<'a, GamePhase>,
+    Res<'a, SpiceDeck>,
+    Res<'a, Occupancy>,
+);
+
+/// Sends everyone a hash of the game once per turn, as soon as the turn has settled
+fn broadcast_hash_system(
+    (mut hashes, queue, rules): (ResMut<StateHashes>, Res<ActionQueue>, Res<RuleSet>),
+    (info, phase, spice_deck, occupancy): Resources,
+    (players, storm): (Query<&Player>, Query<&Storm>),
+    tanked: Query<&Unique, (With<Troop>, With<Dead>)>,
+    (spice, names): (Query<&Spice>, Query<&Location>),
+    mut server: Query<&mut Server>,
+) {
+    if let Phase::Setup { .. } = phase.phase {
+        return;
+    }
+    if hashes.sent == Some(info.turn) || !queue.is_empty() {
+        return;
+    }
+    hashes.sent = Some(info.turn);
+    if let Some(mut server) = server.iter_mut().next() {
+        let sections = digest(
+            (&info, &phase.phase, &spice_deck, &occupancy),
+            &rules,
+            &players,
+            &storm,
+            &tanked,
+            &spice,
+            &names,
+        );
+        server.broadcast(&ServerEvent::StateHash {
+            turn: info.turn,
+            sections,
+        });
+    }
+}
+
+/// Checks the host's hash against our own once our copy of the game has settled, and asks for the
+/// host's state if they disagree
+fn compare_hash_system(
+    network: Res<Network>,
+    (mut hashes, queue, rules): (ResMut<StateHashes>, Res<ActionQueue>, Res<RuleSet>),
+    (info, phase, spice_deck, occupancy): Resources,
+    (players, storm): (Query<&Player>, Query<&Storm>),
+    tanked: Query<&Unique, (With<Troop>, With<Dead>)>,
+    (spice, names): (Query<&Spice>, Query<&Location>),
+    mut client: Query<&mut Client>,
+) {
+    if network.network_type != NetworkType::Client || hashes.received.is_none() || !queue.is_empty()
+    {
+        return;
+    }
+    let (turn, theirs) = hashes.received.take().unwrap();
+    let ours = digest(
+        (&info, &phase.phase, &spice_deck, &occupancy),
+        &rules,
+        &players,
+        &storm,
+        &tanked,
+        &spice,
+        &names,
+    );
+    let mismatched = theirs
+        .iter()
+        .filter(|section| {
+            !ours
+                .iter()
+                .any(|other| other.name == section.name && other.hash == section.hash)
+        })
+        .collect::<Vec<_>>();
+    if mismatched.is_empty() {
+        return;
+    }
+    println!("Desync detected on turn {}:", turn);
+    for section in mismatched {
+        let local = ours
+            .iter()
+            .find(|other| other.name == section.name)
+            .map_or("", |other| other.summary.as_str());
+        println!(
+            "  {}\n    host:  {}\n    local: {}",
+            section.name, section.summary, local
+        );
+    }
+    if let Some(mut client) = client.iter_mut().next() {
+        if let Some(token) = client.session {
+            client.request(ClientRequest::ResyncRequest {
+                token,
+                claims: Vec::new(),
+            });
+        }
+    }
+}
+
+fn reset(mut hashes: ResMut<StateHashes>) {
+    *hashes = StateHashes::default();
+}
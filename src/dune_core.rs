@@ -0,0 +1,808 @@
+//! The rules of the game on their own: factions, the phases of a turn, who holds what on the
+//! board, paying in spice and whether a decision can be made. Nothing in here knows about
+//! entities, rendering or the network, so it can be driven from anywhere, a headless server or a
+//! bot included.
+
+use std::collections::{HashMap, HashSet};
+
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Serialize, Deserialize, Archive, Unarchive, PartialEq, Eq, Debug, Hash)]
+#[archive(derive(CheckBytes))]
+pub enum Faction {
+    Atreides,
+    Harkonnen,
+    Emperor,
+    SpacingGuild,
+    Fremen,
+    BeneGesserit,
+    Ixian,
+    Tleilaxu,
+}
+
+impl Faction {
+    pub const ALL: [Faction; 8] = [
+        Faction::Atreides,
+        Faction::BeneGesserit,
+        Faction::Emperor,
+        Faction::Fremen,
+        Faction::Harkonnen,
+        Faction::SpacingGuild,
+        Faction::Ixian,
+        Faction::Tleilaxu,
+    ];
+
+    /// The factions of the base game, which are always in play
+    pub const BASE: [Faction; 6] = [
+        Faction::Atreides,
+        Faction::BeneGesserit,
+        Faction::Emperor,
+        Faction::Fremen,
+        Faction::Harkonnen,
+        Faction::SpacingGuild,
+    ];
+
+    /// Only in play when someone picks it in the lobby
+    pub fn expansion(&self) -> bool {
+        !Faction::BASE.contains(self)
+    }
+
+    pub fn initial_values(&self) -> (i32, Option<Vec<String>>, i32) {
+        match self {
+            Self::Atreides => (10, Some(vec!["Arrakeen".to_string()]), 10),
+            Self::BeneGesserit => (1, None, 5),
+            Self::Fremen => (
+                10,
+                Some(vec![
+                    "Sietch Tabr".to_string(),
+                    "False Wall South".to_string(),
+                    "False Wall West".to_string(),
+                ]),
+                10,
+            ),
+            Self::Emperor => (0, None, 10),
+            Self::SpacingGuild => (5, Some(vec!["Tuek's Sietch".to_string()]), 5),
+            Self::Harkonnen => (10, Some(vec!["Carthag".to_string()]), 10),
+            // The Hidden Mobile Stronghold is set down wherever the Ixians place their troops
+            Self::Ixian => (5, None, 10),
+            Self::Tleilaxu => (0, None, 5),
+        }
+    }
+
+    /// Position of the faction in `Faction::ALL`
+    pub fn index(&self) -> usize {
+        Faction::ALL
+            .iter()
+            .position(|faction| faction == self)
+            .unwrap()
+    }
+
+    /// Troops revived each turn at no cost. The Fremen revive their whole limit for free.
+    pub fn free_revivals(&self) -> i32 {
+        match self {
+            Self::Fremen => 3,
+            Self::Atreides | Self::Harkonnen | Self::Tleilaxu => 2,
+            _ => 1,
+        }
+    }
+
+    /// Most troops revived in a single turn. The Emperor may pay for 3 more than everyone else.
+    pub fn revival_limit(&self) -> i32 {
+        match self {
+            Self::Emperor => 6,
+            _ => 3,
+        }
+    }
+
+    /// Spice paid to revive `troops` troops, after the free revivals
+    pub fn revival_cost(&self, troops: i32) -> i32 {
+        2 * (troops - self.free_revivals()).max(0)
+    }
+
+    /// Spice claimed from CHOAM Charity while holding `spice`. The Bene Gesserit can always claim,
+    /// everyone else only when nearly broke.
+    pub fn charity(&self, spice: i32) -> i32 {
+        if *self == Self::BeneGesserit || spice <= 1 {
+            2
+        } else {
+            0
+        }
+    }
+
+    pub fn hand_limit(&self) -> usize {
+        match self {
+            Self::Harkonnen => 8,
+            _ => 4,
+        }
+    }
+
+    pub fn shipping_cost(&self, terrain: Terrain, troops: i32) -> i32 {
+        let cost = terrain.shipping_cost(troops);
+        match self {
+            Self::Fremen => 0,
+            Self::SpacingGuild => (cost + 1) / 2,
+            _ => cost,
+        }
+    }
+
+    pub fn movement_range(&self, ornithopters: bool) -> i32 {
+        if ornithopters {
+            3
+        } else if *self == Self::Fremen {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+impl std::fmt::Display for Faction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Faction::Atreides => write!(f, "Atreides"),
+            Faction::Harkonnen => write!(f, "Harkonnen"),
+            Faction::Emperor => write!(f, "Emperor"),
+            Faction::SpacingGuild => write!(f, "Spacing Guild"),
+            Faction::Fremen => write!(f, "Fremen"),
+            Faction::BeneGesserit => write!(f, "Bene Gesserit"),
+            Faction::Ixian => write!(f, "Ixian"),
+            Faction::Tleilaxu => write!(f, "Tleilaxu"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub enum Terrain {
+    Sand,
+    Rock,
+    Stronghold,
+    PolarSink,
+}
+
+impl Terrain {
+    /// Spice paid to ship `troops` here without any faction discount
+    pub fn shipping_cost(&self, troops: i32) -> i32 {
+        match self {
+            Terrain::Stronghold => troops,
+            _ => 2 * troops,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct SectorNode {
+    pub location: String,
+    pub sector: i32,
+}
+
+impl SectorNode {
+    pub fn new(location: &str, sector: i32) -> Self {
+        SectorNode {
+            location: location.to_string(),
+            sector,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum Context {
+    None,
+    Predicting,
+    PlacingTroops,
+    PickingTraitors,
+    Prompting,
+    StackResolving,
+    Bidding,
+    Reviving,
+    Shipping,
+    Moving,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum Phase {
+    Setup { subphase: SetupSubPhase },
+    Storm { subphase: StormSubPhase },
+    SpiceBlow { subphase: SpiceBlowSubPhase },
+    Nexus,
+    Charity,
+    Bidding { subphase: BiddingSubPhase },
+    Revival,
+    Movement { subphase: MovementSubPhase },
+    Battle,
+    Collection,
+    Control,
+    EndGame,
+}
+
+impl Phase {
+    pub fn next(&self) -> Self {
+        match self {
+            Phase::Setup { subphase } => match subphase {
+                SetupSubPhase::ChooseFactions => Phase::Setup {
+                    subphase: SetupSubPhase::Prediction,
+                },
+                SetupSubPhase::Prediction => Phase::Setup {
+                    subphase: SetupSubPhase::AtStart,
+                },
+                SetupSubPhase::AtStart => Phase::Setup {
+                    subphase: SetupSubPhase::DealTraitors,
+                },
+                SetupSubPhase::DealTraitors => Phase::Setup {
+                    subphase: SetupSubPhase::PickTraitors,
+                },
+                SetupSubPhase::PickTraitors => Phase::Setup {
+                    subphase: SetupSubPhase::DealTreachery,
+                },
+                SetupSubPhase::DealTreachery => Phase::Storm {
+                    subphase: StormSubPhase::Reveal,
+                },
+            },
+            Phase::Storm { subphase } => match subphase {
+                StormSubPhase::Reveal => Phase::Storm {
+                    subphase: StormSubPhase::WeatherControl,
+                },
+                StormSubPhase::Dial => Phase::Storm {
+                    subphase: StormSubPhase::MoveStorm,
+                },
+                StormSubPhase::WeatherControl => Phase::Storm {
+                    subphase: StormSubPhase::FamilyAtomics,
+                },
+                StormSubPhase::FamilyAtomics => Phase::Storm {
+                    subphase: StormSubPhase::MoveStorm,
+                },
+                StormSubPhase::MoveStorm => Phase::SpiceBlow {
+                    subphase: SpiceBlowSubPhase::Reveal,
+                },
+            },
+            Phase::SpiceBlow { .. } => Phase::Nexus,
+            Phase::Nexus => Phase::Charity,
+            Phase::Charity => Phase::Bidding {
+                subphase: BiddingSubPhase::DealCards,
+            },
+            Phase::Bidding { .. } => Phase::Revival,
+            Phase::Revival => Phase::Movement {
+                subphase: MovementSubPhase::Ornithopters,
+            },
+            Phase::Movement { .. } => Phase::Battle,
+            Phase::Battle => Phase::Collection,
+            Phase::Collection => Phase::Control,
+            Phase::Control => Phase::Storm {
+                subphase: StormSubPhase::Reveal,
+            },
+            Phase::EndGame => Phase::EndGame,
+        }
+    }
+
+    pub fn advance(&mut self) {
+        *self = self.next();
+    }
+
+    /// Position of the phase on the phase track, if it is part of a regular turn
+    pub fn track_index(&self) -> Option<usize> {
+        match self {
+            Phase::Setup { .. } | Phase::EndGame => None,
+            Phase::Storm { .. } => Some(0),
+            Phase::SpiceBlow { .. } | Phase::Nexus => Some(1),
+            Phase::Charity => Some(2),
+            Phase::Bidding { .. } => Some(3),
+            Phase::Revival => Some(4),
+            Phase::Movement { .. } => Some(5),
+            Phase::Battle => Some(6),
+            Phase::Collection => Some(7),
+            Phase::Control => Some(8),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum SetupSubPhase {
+    ChooseFactions,
+    Prediction,
+    AtStart,
+    DealTraitors,
+    PickTraitors,
+    DealTreachery,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum StormSubPhase {
+    Reveal,
+    /// Two players dial the first storm into place
+    Dial,
+    WeatherControl,
+    FamilyAtomics,
+    MoveStorm,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum SpiceBlowSubPhase {
+    Reveal,
+    Shuffle,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum BiddingSubPhase {
+    DealCards,
+    Bidding,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum MovementSubPhase {
+    Ornithopters,
+    Ship,
+    Move,
+}
+
+/// The game ends after this many turns if nobody has won
+pub const LAST_TURN: i32 = 15;
+/// Strongholds a faction needs to hold on its own to win
+pub const STRONGHOLDS_TO_WIN: usize = 3;
+/// Strongholds an alliance needs to hold between its members to win
+pub const ALLIED_STRONGHOLDS_TO_WIN: usize = 4;
+
+/// Fewest and most factions a game can be played with
+pub const MIN_PLAYERS: usize = 2;
+pub const MAX_PLAYERS: usize = 6;
+/// Below this many factions nobody is left to ally against, so a Nexus makes no alliances
+pub const MIN_ALLIANCE_PLAYERS: usize = 3;
+
+/// Most factions that can share a stronghold at once
+pub const STRONGHOLD_LIMIT: usize = 2;
+/// The cities with airfields, where troops at the start of movement earn their faction
+/// ornithopters
+pub const ORNITHOPTER_CITIES: [&str; 2] = ["Arrakeen", "Carthag"];
+
+/// Who has troops where on the board
+#[derive(Default)]
+pub struct Occupancy {
+    pub sectors: HashMap<SectorNode, HashMap<Faction, i32>>,
+    strongholds: HashSet<String>,
+    no_battles: HashSet<String>,
+    /// Everyone who had troops in Arrakeen or Carthag when movement began. They fly three
+    /// territories and harvest faster until the next movement, wherever their troops go meanwhile.
+    pub ornithopters: Vec<Faction>,
+}
+
+impl Occupancy {
+    /// Notes who has ornithopters from where troops stand now, as movement begins
+    pub fn take_ornithopters(&mut self) {
+        let mut ornithopters = ORNITHOPTER_CITIES
+            .iter()
+            .flat_map(|city| self.factions_in(city))
+            .collect::<Vec<_>>();
+        ornithopters.sort_by_key(|faction| faction.index());
+        ornithopters.dedup();
+        self.ornithopters = ornithopters;
+    }
+
+    pub fn has_ornithopters(&self, faction: Faction) -> bool {
+        self.ornithopters.contains(&faction)
+    }
+
+    /// Territories the faction's troops may cross in one move this turn
+    pub fn movement_range(&self, faction: Faction) -> i32 {
+        faction.movement_range(self.has_ornithopters(faction))
+    }
+
+    /// Spice each of the faction's troops collects this turn
+    pub fn harvest_rate(&self, faction: Faction) -> i32 {
+        if self.has_ornithopters(faction) {
+            3
+        } else {
+            2
+        }
+    }
+
+    /// Picks out the strongholds, and the territories where battles are never fought
+    pub fn learn_terrain(&mut self, terrain: &HashMap<String, Terrain>) {
+        for (name, &terrain) in terrain.iter() {
+            match terrain {
+                Terrain::Stronghold => {
+                    self.strongholds.insert(name.clone());
+                }
+                Terrain::PolarSink => {
+                    self.no_battles.insert(name.clone());
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Troops per faction across every sector of a territory
+    pub fn territory(&self, location: &str) -> HashMap<Faction, i32> {
+        let mut occupants = HashMap::new();
+        for (node, factions) in self.sectors.iter() {
+            if node.location == location {
+                for (&faction, &count) in factions.iter() {
+                    *occupants.entry(faction).or_insert(0) += count;
+                }
+            }
+        }
+        occupants
+    }
+
+    /// Every faction with troops in a territory, in turn order
+    pub fn factions_in(&self, location: &str) -> Vec<Faction> {
+        let mut factions = self.territory(location).into_keys().collect::<Vec<_>>();
+        factions.sort_by_key(|faction| faction.index());
+        factions
+    }
+
+    /// Every stronghold, with whoever is in it
+    pub fn strongholds(&self) -> impl Iterator<Item = (&str, Vec<Faction>)> + '_ {
+        self.strongholds
+            .iter()
+            .map(move |name| (name.as_str(), self.factions_in(name)))
+    }
+
+    /// The strongholds the faction holds, either on its own or shared only with its ally
+    pub fn strongholds_controlled(&self, faction: Faction, ally: Option<Faction>) -> Vec<&str> {
+        self.strongholds()
+            .filter(|(_, factions)| {
+                !factions.is_empty()
+                    && factions
+                        .iter()
+                        .all(|&other| other == faction || Some(other) == ally)
+            })
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Whether the faction is kept out of a stronghold that two others already share
+    pub fn stronghold_full(&self, location: &str, faction: Faction) -> bool {
+        self.strongholds.contains(location) && {
+            let factions = self.factions_in(location);
+            !factions.contains(&faction) && factions.len() >= STRONGHOLD_LIMIT
+        }
+    }
+
+    /// Everyone who holds enough strongholds to win, along with their allies, who count as one
+    /// side and share whatever it wins
+    pub fn stronghold_winners(
+        &self,
+        factions: &[Faction],
+        ally: impl Fn(Faction) -> Option<Faction>,
+    ) -> Vec<Faction> {
+        let mut winners = factions
+            .iter()
+            .filter(|&&faction| {
+                let ally = ally(faction);
+                self.strongholds_controlled(faction, ally).len()
+                    >= if ally.is_some() {
+                        ALLIED_STRONGHOLDS_TO_WIN
+                    } else {
+                        STRONGHOLDS_TO_WIN
+                    }
+            })
+            .flat_map(|&faction| std::iter::once(faction).chain(ally(faction)))
+            .collect::<Vec<_>>();
+        winners.sort_by_key(|faction| faction.index());
+        winners.dedup();
+        winners
+    }
+
+    /// Who wins when the last turn ends without anybody taking control of Dune: the Fremen if
+    /// they kept their sietches to themselves, otherwise the Spacing Guild
+    pub fn default_winner(&self, factions: &[Faction]) -> Option<Faction> {
+        let fremen_or_nobody = |name: &str| {
+            self.factions_in(name)
+                .iter()
+                .all(|&faction| faction == Faction::Fremen)
+        };
+        let tueks_sietch_clear = !self.factions_in("Tuek's Sietch").iter().any(|faction| {
+            matches!(
+                faction,
+                Faction::Harkonnen | Faction::Atreides | Faction::Emperor
+            )
+        });
+        if factions.contains(&Faction::Fremen)
+            && fremen_or_nobody("Sietch Tabr")
+            && fremen_or_nobody("Habbanya Sietch")
+            && tueks_sietch_clear
+        {
+            Some(Faction::Fremen)
+        } else if factions.contains(&Faction::SpacingGuild) {
+            Some(Faction::SpacingGuild)
+        } else {
+            None
+        }
+    }
+
+    /// Every territory where more than one faction has troops and a battle has to be fought
+    pub fn co_located_enemies(&self) -> Vec<(String, Vec<Faction>)> {
+        let mut locations = self
+            .sectors
+            .keys()
+            .map(|node| node.location.as_str())
+            .filter(|location| !self.no_battles.contains(*location))
+            .collect::<Vec<_>>();
+        locations.sort_unstable();
+        locations.dedup();
+        locations
+            .into_iter()
+            .map(|location| (location.to_string(), self.factions_in(location)))
+            .filter(|(_, factions)| factions.len() > 1)
+            .collect()
+    }
+}
+
+pub fn divide_spice(mut total: i32) -> (i32, i32, i32, i32) {
+    let (mut tens, mut fives, mut twos, mut ones) = (0, 0, 0, 0);
+    while total > 0 {
+        match total {
+            1 => {
+                total -= 1;
+                ones += 1;
+            }
+            2..=4 => {
+                total -= 2;
+                twos += 1;
+            }
+            5..=9 => {
+                total -= 5;
+                fives += 1;
+            }
+            _ => {
+                total -= 10;
+                tens += 1;
+            }
+        }
+    }
+    (tens, fives, twos, ones)
+}
+
+/// Picks the set of spice tokens that pays exactly `amount`, preferring the fewest tokens.
+/// Falls back to the smallest overpayment if no exact combination exists.
+pub fn pick_spice<T: Copy>(tokens: &[(T, i32)], amount: i32) -> Option<Vec<T>> {
+    if amount <= 0 {
+        return Some(Vec::new());
+    }
+    let total: i32 = tokens.iter().map(|(_, value)| value).sum();
+    if total < amount {
+        return None;
+    }
+    // best[v] holds the smallest token set found that sums to v
+    let mut best: Vec<Option<Vec<usize>>> = vec![None; total as usize + 1];
+    best[0] = Some(Vec::new());
+    for (i, &(_, value)) in tokens.iter().enumerate() {
+        for v in (value as usize..=total as usize).rev() {
+            if let Some(prev) = best[v - value as usize].clone() {
+                if best[v]
+                    .as_ref()
+                    .map_or(true, |cur| cur.len() > prev.len() + 1)
+                {
+                    let mut next = prev;
+                    next.push(i);
+                    best[v] = Some(next);
+                }
+            }
+        }
+    }
+    best[amount as usize..]
+        .iter()
+        .find_map(|set| set.clone())
+        .map(|set| set.into_iter().map(|i| tokens[i].0).collect())
+}
+
+/// What the game has to be waiting on before a decision can be made
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Requirement {
+    /// It is the faction's turn, in one of these contexts
+    Turn(&'static [Context]),
+    /// Alliances are being negotiated
+    Negotiation,
+    /// Everyone decides at the same time during this phase
+    Phase(Phase),
+    /// Any time at all, such as for cards, which keep track of their own windows
+    Anytime,
+}
+
+/// Where the game is at, as far as who may decide what goes
+pub struct TableState<'a> {
+    pub factions_in_play: &'a [Faction],
+    pub phase: Phase,
+    pub context: Context,
+    /// The faction whose turn it is
+    pub active: Option<Faction>,
+    pub negotiating: bool,
+}
+
+/// Checks that the game is waiting on a decision of this kind from the faction. What the decision
+/// costs and whether it can be afforded is left to the phase resolving it.
+pub fn check_action(
+    faction: Faction,
+    requirement: Requirement,
+    table: &TableState,
+) -> Result<(), String> {
+    if !table.factions_in_play.contains(&faction) {
+        return Err(format!("{} is not in this game", faction));
+    }
+    match requirement {
+        Requirement::Turn(contexts) => {
+            if !contexts.contains(&table.context) {
+                Err(format!("The game is waiting on {:?}", table.context))
+            } else if table.active != Some(faction) {
+                Err(format!("It is not {}'s turn", faction))
+            } else {
+                Ok(())
+            }
+        }
+        Requirement::Negotiation if !table.negotiating => {
+            Err("Alliances can only be made during a Nexus".to_string())
+        }
+        Requirement::Phase(phase) if table.phase != phase => Err(format!(
+            "That can only be done during the {:?} phase",
+            phase
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn occupancy(troops: &[(&str, Faction)]) -> Occupancy {
+        let mut occupancy = Occupancy::default();
+        let terrain = [
+            "Arrakeen",
+            "Carthag",
+            "Tuek's Sietch",
+            "Sietch Tabr",
+            "Habbanya Sietch",
+        ]
+        .iter()
+        .map(|&name| (name.to_string(), Terrain::Stronghold))
+        .chain(std::iter::once((
+            "Polar Sink".to_string(),
+            Terrain::PolarSink,
+        )))
+        .collect();
+        occupancy.learn_terrain(&terrain);
+        for &(location, faction) in troops {
+            *occupancy
+                .sectors
+                .entry(SectorNode {
+                    location: location.to_string(),
+                    sector: 0,
+                })
+                .or_default()
+                .entry(faction)
+                .or_insert(0) += 1;
+        }
+        occupancy
+    }
+
+    #[test]
+    fn shipping_cost() {
+        assert_eq!(Faction::Atreides.shipping_cost(Terrain::Stronghold, 3), 3);
+        assert_eq!(Faction::Atreides.shipping_cost(Terrain::Sand, 3), 6);
+        assert_eq!(
+            Faction::SpacingGuild.shipping_cost(Terrain::Stronghold, 3),
+            2
+        );
+        assert_eq!(Faction::SpacingGuild.shipping_cost(Terrain::Rock, 3), 3);
+        assert_eq!(Faction::Fremen.shipping_cost(Terrain::Sand, 5), 0);
+    }
+
+    #[test]
+    fn revival_and_hand_limits() {
+        assert_eq!(Faction::Emperor.revival_limit(), 6);
+        assert_eq!(Faction::Atreides.revival_limit(), 3);
+        assert_eq!(Faction::Harkonnen.hand_limit(), 8);
+        assert_eq!(Faction::BeneGesserit.hand_limit(), 4);
+    }
+
+    #[test]
+    fn stronghold_winners() {
+        let held = occupancy(&[
+            ("Arrakeen", Faction::Atreides),
+            ("Carthag", Faction::Atreides),
+            ("Tuek's Sietch", Faction::Atreides),
+            ("Sietch Tabr", Faction::Fremen),
+        ]);
+        let factions = [Faction::Atreides, Faction::Fremen, Faction::Harkonnen];
+        assert_eq!(
+            held.stronghold_winners(&factions, |_| None),
+            vec![Faction::Atreides]
+        );
+        // Allies need a fourth stronghold between them, and then win together
+        let allied = |faction| match faction {
+            Faction::Atreides => Some(Faction::Fremen),
+            Faction::Fremen => Some(Faction::Atreides),
+            _ => None,
+        };
+        assert_eq!(
+            held.stronghold_winners(&factions, allied),
+            vec![Faction::Atreides, Faction::Fremen]
+        );
+        let short = occupancy(&[
+            ("Arrakeen", Faction::Atreides),
+            ("Carthag", Faction::Atreides),
+            ("Tuek's Sietch", Faction::Atreides),
+        ]);
+        assert!(short.stronghold_winners(&factions, allied).is_empty());
+        // A stronghold shared with anyone but an ally is not held
+        let shared = occupancy(&[
+            ("Arrakeen", Faction::Atreides),
+            ("Carthag", Faction::Atreides),
+            ("Tuek's Sietch", Faction::Atreides),
+            ("Tuek's Sietch", Faction::Harkonnen),
+        ]);
+        assert!(shared.stronghold_winners(&factions, |_| None).is_empty());
+    }
+
+    #[test]
+    fn default_winner() {
+        let factions = [Faction::Fremen, Faction::SpacingGuild];
+        let clear = occupancy(&[("Sietch Tabr", Faction::Fremen)]);
+        assert_eq!(clear.default_winner(&factions), Some(Faction::Fremen));
+        let taken = occupancy(&[("Habbanya Sietch", Faction::Harkonnen)]);
+        assert_eq!(taken.default_winner(&factions), Some(Faction::SpacingGuild));
+        let tueks = occupancy(&[("Tuek's Sietch", Faction::Emperor)]);
+        assert_eq!(tueks.default_winner(&factions), Some(Faction::SpacingGuild));
+        // Anyone but the Great Houses may sit in Tuek's Sietch
+        let guild = occupancy(&[("Tuek's Sietch", Faction::SpacingGuild)]);
+        assert_eq!(guild.default_winner(&factions), Some(Faction::Fremen));
+        assert_eq!(taken.default_winner(&[Faction::Fremen]), None);
+        assert_eq!(clear.default_winner(&[Faction::Atreides]), None);
+    }
+
+    #[test]
+    fn pick_spice() {
+        let tokens = [('a', 1), ('b', 2), ('c', 5), ('d', 5), ('e', 10)];
+        let picked = |amount| {
+            let mut picked = super::pick_spice(&tokens, amount)?;
+            picked.sort_unstable();
+            Some(picked)
+        };
+        assert_eq!(picked(0), Some(vec![]));
+        assert_eq!(picked(-3), Some(vec![]));
+        assert_eq!(picked(10), Some(vec!['e']));
+        assert_eq!(picked(7), Some(vec!['b', 'c']));
+        assert_eq!(picked(23), Some(vec!['a', 'b', 'c', 'd', 'e']));
+        assert_eq!(picked(24), None);
+        // Nothing pays 4 exactly, so the smallest overpayment is made
+        assert_eq!(
+            super::pick_spice(&[('a', 5), ('b', 10)], 4),
+            Some(vec!['a'])
+        );
+    }
+
+    #[test]
+    fn check_action() {
+        const BIDDING: &[Context] = &[Context::Bidding];
+        let factions = [Faction::Atreides, Faction::Harkonnen];
+        let table = TableState {
+            factions_in_play: &factions,
+            phase: Phase::Charity,
+            context: Context::Bidding,
+            active: Some(Faction::Atreides),
+            negotiating: false,
+        };
+        let check = |faction, requirement| super::check_action(faction, requirement, &table);
+        assert!(check(Faction::Atreides, Requirement::Turn(BIDDING)).is_ok());
+        assert!(check(Faction::Harkonnen, Requirement::Turn(BIDDING)).is_err());
+        assert!(check(Faction::Atreides, Requirement::Turn(&[Context::Shipping])).is_err());
+        assert!(check(Faction::Fremen, Requirement::Anytime).is_err());
+        assert!(check(Faction::Harkonnen, Requirement::Anytime).is_ok());
+        assert!(check(Faction::Harkonnen, Requirement::Negotiation).is_err());
+        assert!(check(Faction::Harkonnen, Requirement::Phase(Phase::Charity)).is_ok());
+        assert!(check(Faction::Harkonnen, Requirement::Phase(Phase::Revival)).is_err());
+        let nexus = TableState {
+            negotiating: true,
+            ..table
+        };
+        assert!(super::check_action(Faction::Harkonnen, Requirement::Negotiation, &nexus).is_ok());
+    }
+}
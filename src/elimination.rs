@@ -0,0 +1,161 @@
+use bevy::prelude::*;
+
+use crate::{
+    alliance::Negotiation,
+    cards::{CardPlays, CardRequest},
+    components::{Dead, Player, Secret, Troop, Unique},
+    data::{Faction, Leader, TreacheryCard},
+    hud::{HudMaterials, TurnTile},
+    network::{Network, NetworkType, Server},
+    phase::{ActionQueue, GamePhase, Phase},
+    protocol::ServerEvent,
+    resources::Info,
+    Screen, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+pub struct EliminationPlugin;
+
+impl Plugin for EliminationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Removed>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                elimination_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                removal_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                turn_tile_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system());
+    }
+}
+
+/// The eliminated factions whose hands, spice and alliance have already been given up
+#[derive(Default)]
+struct Removed {
+    factions: Vec<Faction>,
+}
+
+/// A faction with every leader in the tanks and not a single troop left outside them is out of
+/// the game
+fn elimination_system(
+    (network, queue, phase, mut info): (
+        Res<Network>,
+        Res<ActionQueue>,
+        Res<GamePhase>,
+        ResMut<Info>,
+    ),
+    troops: Query<(&Troop, &Unique, Option<&Dead>)>,
+    leaders: Query<(&Leader, &Unique, Option<&Dead>)>,
+) {
+    if network.network_type == NetworkType::Client || !queue.is_empty() {
+        return;
+    }
+    if let Phase::Setup { .. } = phase.phase {
+        return;
+    }
+    for faction in info.in_game() {
+        let leader_alive = leaders
+            .iter()
+            .any(|(_, unique, dead)| unique.faction == faction && dead.is_none());
+        let troop_alive = troops
+            .iter()
+            .any(|(_, unique, dead)| unique.faction == faction && dead.is_none());
+        if !leader_alive && !troop_alive {
+            println!("{} has nothing left and is out of the game", faction);
+            info.eliminated.push(faction);
+        }
+    }
+}
+
+/// Takes an eliminated faction off the table: its treachery cards go to the discard pile, its
+/// spice goes back to the bank and its ally is free to look elsewhere. Whoever went out, for
+/// whatever reason, is handled here once.
+fn removal_system(
+    (network, mut info, mut removed): (Res<Network>, ResMut<Info>, ResMut<Removed>),
+    (mut plays, mut negotiation): (ResMut<CardPlays>, ResMut<Negotiation>),
+    mut players: Query<&mut Player>,
+    cards: Query<&Secret<TreacheryCard>>,
+    mut server: Query<&mut Server>,
+) {
+    if network.network_type == NetworkType::Client {
+        return;
+    }
+    // A new game starts with nobody out
+    let eliminated = info.eliminated.clone();
+    removed
+        .factions
+        .retain(|faction| eliminated.contains(faction));
+    let mut alliances_changed = false;
+    for faction in eliminated {
+        if removed.factions.contains(&faction) {
+            continue;
+        }
+        removed.factions.push(faction);
+        let mut player =
+            if let Some(player) = players.iter_mut().find(|player| player.faction == faction) {
+                player
+            } else {
+                continue;
+            };
+        plays
+            .requests
+            .retain(|request| request.faction() != faction);
+        plays.requests.extend(
+            player
+                .treachery_cards
+                .iter()
+                .filter_map(|&entity| cards.get(entity).ok())
+                .map(|secret| CardRequest::Discard {
+                    faction,
+                    slot: secret.slot,
+                }),
+        );
+        if player.spice > 0 {
+            println!("{}'s {} spice goes back to the bank", faction, player.spice);
+            player.spice = 0;
+        }
+        if let Some(ally) = info.ally(faction) {
+            println!("{}'s alliance with {} is over", faction, ally);
+            info.alliances
+                .retain(|alliance| !alliance.includes(faction));
+        }
+        negotiation
+            .proposals
+            .retain(|proposal| !proposal.includes(faction));
+        alliances_changed = true;
+    }
+    if alliances_changed {
+        if let Some(mut server) = server.iter_mut().next() {
+            server.broadcast(&ServerEvent::Negotiation {
+                open: negotiation.open,
+                proposals: negotiation.proposals.clone(),
+                done: negotiation.done.clone(),
+                alliances: info.alliances.clone(),
+            });
+        }
+    }
+}
+
+/// Greys out the turn tile of every faction that is out of the game
+fn turn_tile_system(
+    (info, materials): (Res<Info>, Res<HudMaterials>),
+    mut tiles: Query<(&TurnTile, &mut Handle<ColorMaterial>)>,
+) {
+    for (tile, mut material) in tiles.iter_mut() {
+        if info.eliminated.contains(&tile.0) && *material != materials.eliminated {
+            *material = materials.eliminated.clone();
+        }
+    }
+}
+
+fn reset(mut removed: ResMut<Removed>) {
+    *removed = Removed::default();
+}
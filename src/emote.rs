@@ -0,0 +1,467 @@
+use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
+
+use crate::{
+    alliance::own_faction,
+    components::Player,
+    data::Faction,
+    hud::HudMaterials,
+    input::{ContextMenu, MenuEntry, MenuTarget},
+    layout::{Anchored, AnchoredHeight},
+    lerper::{Lerp, LerpType},
+    locale::{Localized, Tr},
+    menu::{ButtonMaterials, Chat, Lobby},
+    network::{Client, Network, NetworkType, Server},
+    protocol::{ClientRequest, ServerEvent},
+    resources::{Data, Info},
+    settings::{Binding, Settings},
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+type EmoteButtons<'a, 'b, 'c, 'd> = Query<
+    'a,
+    (
+        &'b Interaction,
+        &'c EmoteButton,
+        &'d mut Handle<ColorMaterial>,
+    ),
+    Mutated<Interaction>,
+>;
+
+/// Just above the board, like the remote cursors
+const PING_HEIGHT: f32 = 0.012;
+const PING_RADIUS: f32 = 0.02;
+/// A ping grows from nothing to this many times its size on each pulse
+const PULSE_SCALE: f32 = 2.5;
+const PULSE_TIME: f32 = 0.6;
+const PULSES: u32 = 3;
+/// Seconds an emote stays on screen
+const EMOTE_TIME: f32 = 4.0;
+
+pub struct EmotePlugin;
+
+impl Plugin for EmotePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Signals>()
+            .on_state_enter(RESPONSE_STAGE, Screen::HostingGame, init_emotes.system())
+            .on_state_enter(RESPONSE_STAGE, Screen::JoinedGame, init_emotes.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                ping_input_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                ping_input_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                emote_wheel_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                emote_wheel_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                ping_system.system(),
+            )
+            .on_state_update(STATE_CHANGE_STAGE, Screen::JoinedGame, ping_system.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                emote_text_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                emote_text_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system());
+    }
+}
+
+/// Quick things to say without typing
+#[derive(Copy, Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum Emote {
+    Threaten,
+    ProposeAlliance,
+    Thinking,
+    GoodGame,
+}
+
+impl Emote {
+    pub const ALL: [Emote; 4] = [
+        Emote::Threaten,
+        Emote::ProposeAlliance,
+        Emote::Thinking,
+        Emote::GoodGame,
+    ];
+
+    fn key(self) -> &'static str {
+        match self {
+            Emote::Threaten => "emote.threaten",
+            Emote::ProposeAlliance => "emote.propose_alliance",
+            Emote::Thinking => "emote.thinking",
+            Emote::GoodGame => "emote.good_game",
+        }
+    }
+}
+
+/// A spot on the board a player wants everyone to look at, or something they want to say
+#[derive(Copy, Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum Signal {
+    Ping { from: Faction, x: f32, z: f32 },
+    Emote { from: Faction, emote: Emote },
+}
+
+impl Signal {
+    pub fn from(self) -> Faction {
+        match self {
+            Signal::Ping { from, .. } | Signal::Emote { from, .. } => from,
+        }
+    }
+}
+
+/// Pings and emotes from everyone, ours included, still to be shown
+#[derive(Default)]
+pub struct Signals {
+    pub pings: Vec<(Faction, Vec2)>,
+    pub emotes: Vec<(Faction, Emote)>,
+    /// Emotes on screen, with the seconds they have left
+    shown: Vec<(Faction, Emote, f32)>,
+    wheel_open: bool,
+    mesh: Handle<Mesh>,
+}
+
+impl Signals {
+    pub fn receive(&mut self, signal: Signal) {
+        match signal {
+            Signal::Ping { from, x, z } => self.pings.push((from, Vec2::new(x, z))),
+            Signal::Emote { from, emote } => self.emotes.push((from, emote)),
+        }
+    }
+}
+
+/// Sends a ping or emote to everyone. The host hears its own straight away, while a client waits
+/// for the server to pass it back like everyone else's.
+fn broadcast(
+    signal: Signal,
+    (network, signals): (&Network, &mut Signals),
+    server: &mut Query<&mut Server>,
+    client: &mut Query<&mut Client>,
+) {
+    match network.network_type {
+        NetworkType::Client => {
+            if let Some(mut client) = client.iter_mut().next() {
+                client.request(ClientRequest::Signal { signal });
+            }
+        }
+        NetworkType::Server => {
+            if let Some(mut server) = server.iter_mut().next() {
+                server.broadcast(&ServerEvent::Signal { signal });
+            }
+            signals.receive(signal);
+        }
+        NetworkType::None | NetworkType::Local => signals.receive(signal),
+    }
+}
+
+struct PingMarker {
+    pulses: u32,
+}
+
+struct EmoteWheel;
+
+struct EmoteButton(Emote);
+
+struct EmotePanel;
+
+struct EmoteText;
+
+fn init_emotes(
+    commands: &mut Commands,
+    (mut signals, mut meshes): (ResMut<Signals>, ResMut<Assets<Mesh>>),
+    (data, asset_server): (Res<Data>, Res<AssetServer>),
+    (materials, button_materials): (Res<HudMaterials>, Res<ButtonMaterials>),
+) {
+    signals.mesh = meshes.add(Mesh::from(shape::Icosphere {
+        radius: PING_RADIUS,
+        subdivisions: 2,
+    }));
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(8.0)),
+                display: Display::None,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(EmoteWheel)
+        .with(
+            Anchored::new(data.ui_structure.emote_wheel.clone()).with_height(AnchoredHeight::Auto),
+        )
+        .with_children(|parent| {
+            for &emote in Emote::ALL.iter() {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            margin: Rect::all(Val::Px(2.0)),
+                            padding: Rect::all(Val::Px(4.0)),
+                            ..Default::default()
+                        },
+                        material: button_materials.normal.clone(),
+                        ..Default::default()
+                    })
+                    .with(EmoteButton(emote))
+                    .with_children(|parent| {
+                        parent
+                            .spawn(TextBundle {
+                                text: Text {
+                                    font: font.clone(),
+                                    value: String::new(),
+                                    style: TextStyle {
+                                        font_size: 16.0,
+                                        color: Color::ANTIQUE_WHITE,
+                                        ..Default::default()
+                                    },
+                                },
+                                ..Default::default()
+                            })
+                            .with(Localized(emote.key()));
+                    });
+            }
+        });
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(8.0)),
+                display: Display::None,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(EmotePanel)
+        .with(Anchored::new(data.ui_structure.emotes.clone()).with_height(AnchoredHeight::Auto))
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        font,
+                        value: String::new(),
+                        style: TextStyle {
+                            font_size: 18.0,
+                            color: Color::ANTIQUE_WHITE,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(EmoteText);
+        });
+}
+
+/// Pinging a territory from its context menu marks the spot for everyone
+fn ping_input_system(
+    (mut signals, network, lobby, info): (ResMut<Signals>, Res<Network>, Res<Lobby>, Res<Info>),
+    mut menu: ResMut<ContextMenu>,
+    (players, faction_client): (Query<&Player>, Query<&Client>),
+    (mut server, mut client): (Query<&mut Server>, Query<&mut Client>),
+) {
+    let at = match menu.take(MenuEntry::Ping) {
+        Some(MenuTarget::Territory { at, .. }) => at,
+        _ => return,
+    };
+    let from = if let Some(from) = own_faction(&network, &lobby, &info, &players, &faction_client) {
+        from
+    } else {
+        return;
+    };
+    broadcast(
+        Signal::Ping {
+            from,
+            x: at.x,
+            z: at.z,
+        },
+        (&network, &mut signals),
+        &mut server,
+        &mut client,
+    );
+}
+
+/// The emote key opens a few buttons to pick from, and picking one sends it
+fn emote_wheel_system(
+    (mut signals, network, lobby, info): (ResMut<Signals>, Res<Network>, Res<Lobby>, Res<Info>),
+    (settings, keyboard_input, chat, button_materials): (
+        Res<Settings>,
+        Res<Input<KeyCode>>,
+        Res<Chat>,
+        Res<ButtonMaterials>,
+    ),
+    (mut buttons, mut wheels): (EmoteButtons, Query<&mut Style, With<EmoteWheel>>),
+    (players, faction_client): (Query<&Player>, Query<&Client>),
+    (mut server, mut client): (Query<&mut Server>, Query<&mut Client>),
+) {
+    let from = own_faction(&network, &lobby, &info, &players, &faction_client);
+    if settings.just_pressed(&keyboard_input, Binding::Emote) && !chat.open && from.is_some() {
+        signals.wheel_open = !signals.wheel_open;
+    }
+    let mut picked = None;
+    for (interaction, button, mut material) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {
+                *material = button_materials.pressed.clone();
+                picked = Some(button.0);
+            }
+            Interaction::Hovered => *material = button_materials.hovered.clone(),
+            Interaction::None => *material = button_materials.normal.clone(),
+        }
+    }
+    if let (Some(emote), Some(from), true) = (picked, from, signals.wheel_open) {
+        signals.wheel_open = false;
+        broadcast(
+            Signal::Emote { from, emote },
+            (&network, &mut signals),
+            &mut server,
+            &mut client,
+        );
+    }
+    let display = if signals.wheel_open {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    for mut style in wheels.iter_mut() {
+        if style.display != display {
+            style.display = display;
+        }
+    }
+}
+
+/// Puts a marker in the pinging faction's colour on each ping and pulses it a few times before
+/// taking it away
+fn ping_system(
+    commands: &mut Commands,
+    (mut signals, mut materials): (ResMut<Signals>, ResMut<Assets<StandardMaterial>>),
+    mut markers: Query<(Entity, &mut PingMarker, &Transform), Without<Lerp>>,
+) {
+    let flat = |scale: f32| Vec3::new(scale, 0.2 * scale, scale);
+    for (faction, position) in signals.pings.drain(..).collect::<Vec<_>>() {
+        let translation = Vec3::new(position.x, PING_HEIGHT, position.y);
+        let small = Transform {
+            translation,
+            scale: flat(0.01),
+            ..Default::default()
+        };
+        commands
+            .spawn(PbrBundle {
+                mesh: signals.mesh.clone(),
+                material: materials.add(StandardMaterial {
+                    albedo: faction.color(),
+                    shaded: false,
+                    ..Default::default()
+                }),
+                transform: small,
+                ..Default::default()
+            })
+            .with(PingMarker { pulses: PULSES })
+            .with(ScreenEntity)
+            .with(Lerp::new(
+                LerpType::world_to(Transform {
+                    scale: flat(PULSE_SCALE),
+                    ..small
+                }),
+                PULSE_TIME,
+                0.0,
+            ));
+    }
+    for (entity, mut marker, transform) in markers.iter_mut() {
+        marker.pulses = marker.pulses.saturating_sub(1);
+        if marker.pulses == 0 {
+            commands.despawn(entity);
+            continue;
+        }
+        let small = Transform {
+            scale: flat(0.01),
+            ..*transform
+        };
+        commands.insert_one(
+            entity,
+            Lerp::new(
+                LerpType::world_from_to(
+                    small,
+                    Transform {
+                        scale: flat(PULSE_SCALE),
+                        ..small
+                    },
+                ),
+                PULSE_TIME,
+                0.0,
+            ),
+        );
+    }
+}
+
+/// Lists the emotes sent in the last few seconds, with who sent them
+fn emote_text_system(
+    (mut signals, time, tr): (ResMut<Signals>, Res<Time>, Res<Tr>),
+    mut panels: Query<&mut Style, With<EmotePanel>>,
+    mut texts: Query<&mut Text, With<EmoteText>>,
+) {
+    let received = signals.emotes.drain(..).collect::<Vec<_>>();
+    signals.shown.extend(
+        received
+            .into_iter()
+            .map(|(faction, emote)| (faction, emote, EMOTE_TIME)),
+    );
+    for (_, _, remaining) in signals.shown.iter_mut() {
+        *remaining -= time.delta_seconds();
+    }
+    signals.shown.retain(|(_, _, remaining)| *remaining > 0.0);
+
+    let display = if signals.shown.is_empty() {
+        Display::None
+    } else {
+        Display::Flex
+    };
+    for mut style in panels.iter_mut() {
+        if style.display != display {
+            style.display = display;
+        }
+    }
+    let value = signals
+        .shown
+        .iter()
+        .map(|&(faction, emote, _)| {
+            tr.fmt("emote.line", &[&tr.faction(faction), &tr.get(emote.key())])
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    for mut text in texts.iter_mut() {
+        if text.value != value {
+            text.value = value.clone();
+        }
+    }
+}
+
+fn reset(mut signals: ResMut<Signals>) {
+    *signals = Signals::default();
+}
@@ -0,0 +1,300 @@
+use bevy::{prelude::*, ui::FocusPolicy, window::CursorMoved};
+
+use crate::{
+    menu::{Chat, Editing, Rebinding},
+    reference::RulesReference,
+    settings::{Binding, Settings},
+    Screen,
+};
+
+/// How far the left stick has to be pushed before it counts as a step
+const STICK_THRESHOLD: f32 = 0.5;
+/// Thickness of the bar under the focused button
+const INDICATOR_HEIGHT: f32 = 3.0;
+/// Buttons within this many pixels of each other vertically count as one row when tabbing
+const ROW_TOLERANCE: f32 = 4.0;
+
+pub struct FocusPlugin;
+
+impl Plugin for FocusPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // Ahead of everything else reading the keyboard, so the keys steering the focus don't
+        // also bid or submit
+        app.init_resource::<UiFocus>()
+            .add_system_to_stage(stage::PRE_UPDATE, focus_input_system.system())
+            .add_system(focus_indicator_system.system());
+    }
+}
+
+/// The button the keyboard or a gamepad is on, so the game can be played without a mouse. Menus
+/// take Tab and the arrow keys straight away; in game Tab belongs to the chat and the arrows to
+/// bidding, so the navigate key has to hand them over first.
+pub struct UiFocus {
+    pub entity: Option<Entity>,
+    /// The button pressed last frame, to let go of again
+    pressed: Option<Entity>,
+    material: Handle<ColorMaterial>,
+}
+
+impl FromResources for UiFocus {
+    fn from_resources(resources: &Resources) -> Self {
+        let mut materials = resources.get_mut::<Assets<ColorMaterial>>().unwrap();
+        UiFocus {
+            entity: None,
+            pressed: None,
+            material: materials.add(Color::rgb(1.0, 0.8, 0.3).into()),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Step {
+    Next,
+    Previous,
+    Towards(Vec2),
+}
+
+/// Every button that can be seen, with where its center is on screen
+fn visible_buttons(
+    buttons: &Query<(Entity, &Node, &GlobalTransform, Option<&Visible>), With<Button>>,
+) -> Vec<(Entity, Vec2)> {
+    let mut visible = buttons
+        .iter()
+        .filter(|(_, node, _, visible)| {
+            node.size.x > 0.0
+                && node.size.y > 0.0
+                && visible.map_or(true, |visible| visible.is_visible)
+        })
+        .map(|(entity, _, transform, _)| (entity, transform.translation.truncate()))
+        .collect::<Vec<_>>();
+    // Reading order, top to bottom and then left to right
+    visible.sort_by(|(_, a), (_, b)| {
+        let row = |position: &Vec2| (-position.y / ROW_TOLERANCE).round() as i32;
+        row(a)
+            .cmp(&row(b))
+            .then(a.x.partial_cmp(&b.x).unwrap_or(std::cmp::Ordering::Equal))
+    });
+    visible
+}
+
+/// The button a step lands on from `from`, or the first one when nothing has focus yet
+fn step_from(buttons: &[(Entity, Vec2)], from: Option<Entity>, step: Step) -> Option<Entity> {
+    let index = match from.and_then(|from| buttons.iter().position(|&(e, _)| e == from)) {
+        Some(index) => index,
+        None => return buttons.first().map(|&(entity, _)| entity),
+    };
+    match step {
+        Step::Next => Some(buttons[(index + 1) % buttons.len()].0),
+        Step::Previous => Some(buttons[(index + buttons.len() - 1) % buttons.len()].0),
+        Step::Towards(direction) => {
+            let origin = buttons[index].1;
+            buttons
+                .iter()
+                .filter_map(|&(entity, position)| {
+                    let offset = position - origin;
+                    let along = offset.dot(direction);
+                    if along <= 1.0 {
+                        return None;
+                    }
+                    // Straight ahead wins over something closer off to the side
+                    let across = (offset - direction * along).length();
+                    Some((entity, along + across * 2.0))
+                })
+                .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+                .map(|(entity, _)| entity)
+                .or(Some(buttons[index].0))
+        }
+    }
+}
+
+/// Moves the focus with the keyboard or a gamepad and presses the focused button. Moving or
+/// clicking the mouse hands control back to it.
+fn focus_input_system(
+    (mut focus, screen, settings): (ResMut<UiFocus>, Res<State<Screen>>, Res<Settings>),
+    (chat, editing, rebinding, reference): (
+        Res<Chat>,
+        Res<Editing>,
+        Res<Rebinding>,
+        Res<RulesReference>,
+    ),
+    (mut keyboard_input, mouse_input, gamepad_input): (
+        ResMut<Input<KeyCode>>,
+        Res<Input<MouseButton>>,
+        Res<Input<GamepadButton>>,
+    ),
+    (cursor_moved, gamepad_events): (Res<Events<CursorMoved>>, Res<Events<GamepadEvent>>),
+    (mut cursor_reader, mut gamepad_reader, mut stick): (
+        Local<EventReader<CursorMoved>>,
+        Local<EventReader<GamepadEvent>>,
+        Local<Vec2>,
+    ),
+    buttons: Query<(Entity, &Node, &GlobalTransform, Option<&Visible>), With<Button>>,
+    mut interactions: Query<&mut Interaction>,
+) {
+    if let Some(entity) = focus.pressed.take() {
+        if let Ok(mut interaction) = interactions.get_mut(entity) {
+            if *interaction == Interaction::Clicked {
+                *interaction = Interaction::None;
+            }
+        }
+    }
+
+    let mouse_used = cursor_reader.iter(&cursor_moved).next().is_some()
+        || mouse_input.just_pressed(MouseButton::Left);
+    let mut gamepad_step = None;
+    for GamepadEvent(_, event) in gamepad_reader.iter(&gamepad_events) {
+        let before = stick.length();
+        match event {
+            GamepadEventType::AxisChanged(GamepadAxisType::LeftStickX, value) => stick.x = *value,
+            GamepadEventType::AxisChanged(GamepadAxisType::LeftStickY, value) => stick.y = *value,
+            _ => continue,
+        }
+        if before < STICK_THRESHOLD && stick.length() >= STICK_THRESHOLD {
+            gamepad_step = Some(Step::Towards(if stick.x.abs() > stick.y.abs() {
+                Vec2::new(stick.x.signum(), 0.0)
+            } else {
+                Vec2::new(0.0, stick.y.signum())
+            }));
+        }
+    }
+    let mut gamepad_press = false;
+    let mut gamepad_back = false;
+    for GamepadButton(_, button) in gamepad_input.get_just_pressed() {
+        match button {
+            GamepadButtonType::DPadUp => gamepad_step = Some(Step::Towards(Vec2::unit_y())),
+            GamepadButtonType::DPadDown => gamepad_step = Some(Step::Towards(-Vec2::unit_y())),
+            GamepadButtonType::DPadLeft => gamepad_step = Some(Step::Towards(-Vec2::unit_x())),
+            GamepadButtonType::DPadRight => gamepad_step = Some(Step::Towards(Vec2::unit_x())),
+            GamepadButtonType::South => gamepad_press = true,
+            GamepadButtonType::East => gamepad_back = true,
+            _ => (),
+        }
+    }
+
+    let visible = visible_buttons(&buttons);
+    if focus
+        .entity
+        .map_or(false, |entity| !visible.iter().any(|&(e, _)| e == entity))
+    {
+        focus.entity = None;
+    }
+    if mouse_used || gamepad_back {
+        focus.entity = None;
+    }
+    if chat.open || editing.active() || rebinding.active() || reference.open {
+        return;
+    }
+
+    let in_game = matches!(screen.current(), Screen::HostingGame | Screen::JoinedGame);
+    let focused = focus.entity.is_some();
+    let mut consumed = Vec::new();
+    let mut step = gamepad_step;
+    if in_game && settings.just_pressed(&keyboard_input, Binding::Navigate) {
+        consumed.push(settings.key(Binding::Navigate));
+        if focused {
+            focus.entity = None;
+            step = None;
+        } else {
+            step = Some(Step::Next);
+        }
+    }
+    if !in_game && keyboard_input.just_pressed(KeyCode::Tab) {
+        consumed.push(KeyCode::Tab);
+        step = if keyboard_input.pressed(KeyCode::LShift) || keyboard_input.pressed(KeyCode::RShift)
+        {
+            Some(Step::Previous)
+        } else {
+            Some(Step::Next)
+        };
+    }
+    // The arrows and Enter only leave the game alone once the focus has been handed over
+    if focused || !in_game {
+        for &(key, direction) in [
+            (KeyCode::Up, Vec2::unit_y()),
+            (KeyCode::Down, -Vec2::unit_y()),
+            (KeyCode::Left, -Vec2::unit_x()),
+            (KeyCode::Right, Vec2::unit_x()),
+        ]
+        .iter()
+        {
+            if keyboard_input.just_pressed(key) {
+                consumed.push(key);
+                step = Some(Step::Towards(direction));
+            }
+        }
+    }
+    let mut press = gamepad_press && focused;
+    if focused {
+        if keyboard_input.just_pressed(KeyCode::Return) {
+            consumed.push(KeyCode::Return);
+            press = true;
+        }
+        if keyboard_input.just_pressed(KeyCode::Escape) {
+            consumed.push(KeyCode::Escape);
+            focus.entity = None;
+            step = None;
+            press = false;
+        }
+    }
+    for key in consumed {
+        keyboard_input.reset(key);
+    }
+
+    if let Some(step) = step {
+        focus.entity = step_from(&visible, focus.entity, step);
+    } else if press {
+        if let Some(entity) = focus.entity {
+            if let Ok(mut interaction) = interactions.get_mut(entity) {
+                *interaction = Interaction::Clicked;
+                focus.pressed = Some(entity);
+            }
+        }
+    }
+}
+
+struct FocusIndicator;
+
+/// Underlines the focused button. The bar is spawned again whenever the focus moves, so it stays
+/// above panels that came up since.
+fn focus_indicator_system(
+    commands: &mut Commands,
+    focus: Res<UiFocus>,
+    mut shown: Local<Option<(Entity, Vec2, Vec2)>>,
+    buttons: Query<(&Node, &GlobalTransform), With<Button>>,
+    indicators: Query<Entity, With<FocusIndicator>>,
+) {
+    let target = focus.entity.and_then(|entity| {
+        buttons
+            .get(entity)
+            .ok()
+            .map(|(node, transform)| (entity, transform.translation.truncate(), node.size))
+    });
+    if *shown == target {
+        return;
+    }
+    *shown = target;
+    for entity in indicators.iter() {
+        commands.despawn_recursive(entity);
+    }
+    let (center, size) = match target {
+        Some((_, center, size)) => (center, size),
+        None => return,
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(center.x - size.x / 2.0),
+                    bottom: Val::Px(center.y - size.y / 2.0 - INDICATOR_HEIGHT),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Px(size.x), Val::Px(INDICATOR_HEIGHT)),
+                ..Default::default()
+            },
+            material: focus.material.clone(),
+            focus_policy: FocusPolicy::Pass,
+            ..Default::default()
+        })
+        .with(FocusIndicator);
+}
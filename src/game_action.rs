@@ -0,0 +1,469 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
+
+use crate::{
+    alliance::{AllianceRequest, Negotiation},
+    bot::Bot,
+    capture::Captures,
+    cards::{CardEffects, CardPlays, CardRequest},
+    components::{Player, Secret},
+    data::{CardEffect, Faction, FactionAbilities, Terrain, TreacheryCard},
+    dune_core::{check_action, Requirement, TableState},
+    interrupt::Interrupts,
+    mentat::MentatPause,
+    network::{Client, Network, NetworkType, Server},
+    phase::{
+        Auction, Battle, BattlePlan, Context, GamePhase, Movement, MovementRequest, Phase,
+        PredictionPick, PredictionPicks, Revival, RevivalRequest, StormSubPhase, TraitorPick,
+        TraitorPicks,
+    },
+    protocol::{ClientRequest, ServerEvent},
+    resources::Info,
+    storm_dial::{DialPick, StormDial},
+    tutorial::ExpectedAction,
+    Screen, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// Actions a client keeps in mind after sending them, in case the host turns one down
+const SENT_HISTORY: usize = 16;
+
+pub struct GameActionPlugin;
+
+impl Plugin for GameActionPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GameActions>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                apply_action_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system());
+    }
+}
+
+/// Every decision a player can make. Whoever makes it, be it someone at this machine, a client, a
+/// bot or an expired clock, it has to get through `apply_action` before it touches the game.
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum GameAction {
+    Bid {
+        faction: Faction,
+        amount: Option<i32>,
+    },
+    Movement {
+        request: MovementRequest,
+    },
+    Revival {
+        request: RevivalRequest,
+    },
+    CardPlay {
+        request: CardRequest,
+    },
+    Traitor {
+        pick: TraitorPick,
+    },
+    Prediction {
+        pick: PredictionPick,
+    },
+    Alliance {
+        request: AllianceRequest,
+    },
+    Battle {
+        plan: BattlePlan,
+    },
+    /// A secret number toward where the first storm starts
+    StormDial {
+        pick: DialPick,
+    },
+    /// Whether to play a Karama against the action held in an interrupt window
+    Interrupt {
+        faction: Faction,
+        karama: bool,
+    },
+    /// Done looking over the board in the Mentat Pause
+    MentatReady {
+        faction: Faction,
+    },
+    /// Whether to kill a captured leader for spice or keep them for a battle
+    Capture {
+        faction: Faction,
+        kill: bool,
+    },
+}
+
+impl GameAction {
+    /// The faction making the decision
+    pub fn faction(&self) -> Faction {
+        match self {
+            GameAction::Bid { faction, .. } => *faction,
+            GameAction::Movement { request } => request.faction(),
+            GameAction::Revival { request } => request.faction,
+            GameAction::CardPlay { request } => request.faction(),
+            GameAction::Traitor { pick } => pick.faction,
+            GameAction::Prediction { .. } => Faction::BeneGesserit,
+            GameAction::Alliance { request } => request.faction(),
+            GameAction::Battle { plan } => plan.faction,
+            GameAction::StormDial { pick } => pick.faction,
+            GameAction::Interrupt { faction, .. } => *faction,
+            GameAction::MentatReady { faction } => *faction,
+            GameAction::Capture { faction, .. } => *faction,
+        }
+    }
+
+    /// What the game has to be waiting on for the decision to be made
+    pub fn requirement(&self) -> Requirement {
+        match self {
+            GameAction::Bid { .. } => Requirement::Turn(&[Context::Bidding]),
+            GameAction::Movement { .. } => Requirement::Turn(&[Context::Shipping, Context::Moving]),
+            GameAction::Revival { .. } => Requirement::Turn(&[Context::Reviving]),
+            GameAction::CardPlay { .. } => Requirement::Anytime,
+            GameAction::Traitor { .. } => Requirement::Turn(&[Context::PickingTraitors]),
+            GameAction::Prediction { .. } => Requirement::Turn(&[Context::Predicting]),
+            GameAction::Alliance { .. } => Requirement::Negotiation,
+            // Every faction in a battle plans it at the same time
+            GameAction::Battle { .. } => Requirement::Phase(Phase::Battle),
+            // Both players dial at the same time
+            GameAction::StormDial { .. } => Requirement::Phase(Phase::Storm {
+                subphase: StormSubPhase::Dial,
+            }),
+            // The open window knows who may answer it
+            GameAction::Interrupt { .. } => Requirement::Anytime,
+            GameAction::MentatReady { .. } => Requirement::Phase(Phase::Control),
+            // The capture system knows whether a captive is waiting on the faction
+            GameAction::Capture { .. } => Requirement::Anytime,
+        }
+    }
+
+    /// Turns down what no game could allow, and what the faction plainly can't pay for with
+    /// `spice`. The exact price of shipping or of a leader is left to the phase.
+    pub fn check_limits(&self, spice: i32) -> Result<(), String> {
+        match self {
+            GameAction::Bid {
+                faction,
+                amount: Some(amount),
+            } => {
+                if *amount <= 0 {
+                    Err(format!("{} cannot bid {}", faction, amount))
+                } else if *amount > spice {
+                    Err(format!("{} cannot afford to bid {}", faction, amount))
+                } else {
+                    Ok(())
+                }
+            }
+            GameAction::Movement {
+                request: MovementRequest::Ship { faction, count, .. },
+            } => {
+                // Strongholds are the cheapest place to ship to
+                let cheapest = faction.shipping_cost(Terrain::Stronghold, *count);
+                if *count <= 0 {
+                    Err(format!("{} cannot ship {} troops", faction, count))
+                } else if cheapest > spice {
+                    Err(format!(
+                        "{} cannot afford to ship {} troops",
+                        faction, count
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+            GameAction::Movement {
+                request: MovementRequest::Move { faction, count, .. },
+            } if *count <= 0 => Err(format!("{} cannot move {} troops", faction, count)),
+            GameAction::Revival { request } => {
+                let faction = request.faction;
+                if request.troops < 0 || request.troops > faction.revival_limit() {
+                    Err(format!(
+                        "{} cannot revive {} troops",
+                        faction, request.troops
+                    ))
+                } else if faction.revival_cost(request.troops) > spice {
+                    Err(format!(
+                        "{} cannot afford to revive {} troops",
+                        faction, request.troops
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// An action that made it into the game, with when it happened
+#[derive(Clone, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct LoggedAction {
+    pub turn: i32,
+    pub phase: Phase,
+    pub action: GameAction,
+}
+
+/// Actions waiting to be checked, and every one carried out so far this game, in order
+#[derive(Default)]
+pub struct GameActions {
+    pub submitted: VecDeque<GameAction>,
+    pub log: Vec<LoggedAction>,
+    /// The only kinds of decision this machine may make, while the tutorial is teaching them one
+    /// at a time
+    pub allowed: Option<Vec<ExpectedAction>>,
+    /// Why the last decision made at this machine was turned down, until the HUD picks it up
+    pub rejected: Option<String>,
+    /// On the host, the request each client's submitted action came in on
+    pub remote: Vec<(u32, GameAction)>,
+    /// On a client, the latest actions sent to the host with the requests they went in
+    sent: VecDeque<(u32, GameAction)>,
+    /// Actions made at this machine since the bug report recorder last took them
+    pub made_here: Vec<GameAction>,
+}
+
+impl GameActions {
+    /// Whether the faction already has a decision waiting to be checked
+    pub fn pending(&self, faction: Faction) -> bool {
+        self.submitted
+            .iter()
+            .any(|action| action.faction() == faction)
+    }
+
+    /// Notes down an action once the phase resolving it has carried it out
+    pub fn resolved(&mut self, info: &Info, phase: Phase, action: GameAction) {
+        self.log.push(LoggedAction {
+            turn: info.turn,
+            phase,
+            action,
+        });
+    }
+
+    /// The request a client's action came in on, which is done with once the action is checked
+    fn request_of(&mut self, action: &GameAction) -> Option<u32> {
+        let i = self.remote.iter().position(|(_, other)| other == action)?;
+        Some(self.remote.remove(i).0)
+    }
+
+    /// The action we sent in request `request`, now that the host has answered it
+    pub fn answered(&mut self, request: u32) -> Option<GameAction> {
+        let i = self.sent.iter().position(|&(id, _)| id == request)?;
+        self.sent.remove(i).map(|(_, action)| action)
+    }
+}
+
+/// Hands an action made at this machine to the host, which may be us
+pub fn submit(
+    network: &Network,
+    client: &mut Query<&mut Client>,
+    actions: &mut GameActions,
+    action: GameAction,
+) {
+    if let Some(allowed) = actions.allowed.as_ref() {
+        if !ExpectedAction::permits(allowed, &action) {
+            println!("The tutorial isn't ready for {:?} yet", action);
+            return;
+        }
+    }
+    actions.made_here.push(action.clone());
+    match network.network_type {
+        NetworkType::Client => {
+            if let Some(mut client) = client.iter_mut().next() {
+                let request = client.request(ClientRequest::Action {
+                    action: action.clone(),
+                });
+                actions.sent.push_back((request, action));
+                if actions.sent.len() > SENT_HISTORY {
+                    actions.sent.pop_front();
+                }
+            }
+        }
+        _ => actions.submitted.push_back(action),
+    }
+}
+
+/// Where the phases pick up the actions they resolve
+pub struct PhaseQueues<'a> {
+    auction: &'a mut Auction,
+    movement: &'a mut Movement,
+    revival: &'a mut Revival,
+    card_plays: &'a mut CardPlays,
+    traitor_picks: &'a mut TraitorPicks,
+    prediction_picks: &'a mut PredictionPicks,
+    negotiation: &'a mut Negotiation,
+    battle: &'a mut Battle,
+    storm_dial: &'a mut StormDial,
+    interrupts: &'a mut Interrupts,
+    mentat: &'a mut MentatPause,
+    captures: &'a mut Captures,
+}
+
+/// Checks that the action is one the game is waiting on and within what the faction's `spice`
+/// allows
+fn validate(
+    action: &GameAction,
+    (info, phase, active, spice): (&Info, Phase, Option<Faction>, i32),
+    negotiating: bool,
+) -> Result<(), String> {
+    check_action(
+        action.faction(),
+        action.requirement(),
+        &TableState {
+            factions_in_play: &info.factions_in_play,
+            phase,
+            context: info.context,
+            active,
+            negotiating,
+        },
+    )?;
+    action.check_limits(spice)
+}
+
+/// Validates the action and passes it to the phase that resolves it. The phases still check the
+/// details only they know about, like exactly what things cost, and log the action once it has
+/// gone through.
+pub fn apply_action(
+    action: &GameAction,
+    table: (&Info, Phase, Option<Faction>, i32),
+    queues: &mut PhaseQueues,
+) -> Result<(), String> {
+    validate(action, table, queues.negotiation.open)?;
+    match action.clone() {
+        GameAction::Bid { faction, amount } => queues.auction.bids.push_back((faction, amount)),
+        GameAction::Movement { request } => queues.movement.requests.push_back(request),
+        GameAction::Revival { request } => queues.revival.requests.push_back(request),
+        // Cards have windows all through the game, which the card system keeps track of
+        GameAction::CardPlay { request } => queues.card_plays.requests.push_back(request),
+        GameAction::Traitor { pick } => queues.traitor_picks.requests.push_back(pick),
+        GameAction::Prediction { pick } => queues.prediction_picks.requests.push_back(pick),
+        GameAction::Alliance { request } => queues.negotiation.requests.push_back(request),
+        GameAction::Battle { plan } => queues.battle.plans.push_back(plan),
+        GameAction::StormDial { pick } => queues.storm_dial.requests.push_back(pick),
+        GameAction::Interrupt { faction, karama } => queues.interrupts.answer(faction, karama)?,
+        GameAction::MentatReady { faction } => queues.mentat.requests.push_back(faction),
+        GameAction::Capture { faction, kill } => {
+            queues.captures.requests.push_back((faction, kill))
+        }
+    }
+    Ok(())
+}
+
+type Queues<'a> = (
+    ResMut<'a, Auction>,
+    ResMut<'a, Movement>,
+    ResMut<'a, Revival>,
+    ResMut<'a, CardPlays>,
+);
+
+fn apply_action_system(
+    (mut actions, info, phase): (ResMut<GameActions>, Res<Info>, Res<GamePhase>),
+    (mut auction, mut movement, mut revival, mut card_plays): Queues,
+    (mut traitor_picks, mut prediction_picks, mut negotiation, mut battle, mut mentat): (
+        ResMut<TraitorPicks>,
+        ResMut<PredictionPicks>,
+        ResMut<Negotiation>,
+        ResMut<Battle>,
+        ResMut<MentatPause>,
+    ),
+    (mut interrupts, mut storm_dial, mut captures, abilities, effects): (
+        ResMut<Interrupts>,
+        ResMut<StormDial>,
+        ResMut<Captures>,
+        Res<FactionAbilities>,
+        Res<CardEffects>,
+    ),
+    players: Query<(&Player, Option<&Bot>)>,
+    cards: Query<&Secret<TreacheryCard>>,
+    mut server: Query<&mut Server>,
+) {
+    let active = if info.play_order.is_empty() {
+        None
+    } else {
+        players
+            .get(info.get_active_player())
+            .ok()
+            .map(|(player, _)| player.faction)
+    };
+    let mut queues = PhaseQueues {
+        auction: &mut auction,
+        movement: &mut movement,
+        revival: &mut revival,
+        card_plays: &mut card_plays,
+        traitor_picks: &mut traitor_picks,
+        prediction_picks: &mut prediction_picks,
+        negotiation: &mut negotiation,
+        battle: &mut battle,
+        storm_dial: &mut storm_dial,
+        interrupts: &mut interrupts,
+        mentat: &mut mentat,
+        captures: &mut captures,
+    };
+    // Everyone at a machine who could answer a faction power with the Karama they hold
+    let holders = players
+        .iter()
+        .filter(|(_, bot)| bot.is_none())
+        .filter_map(|(player, _)| {
+            player.treachery_cards.iter().find_map(|&card| {
+                cards
+                    .get(card)
+                    .ok()
+                    .filter(|card| {
+                        card.value
+                            .as_ref()
+                            .map_or(false, |value| value.effect == CardEffect::Karama)
+                    })
+                    .map(|card| (player.faction, card.slot))
+            })
+        })
+        .collect::<Vec<_>>();
+    while let Some(action) = actions.submitted.pop_front() {
+        // Nothing else goes ahead until everyone has answered the held action
+        if queues.interrupts.window.is_some() && !matches!(action, GameAction::Interrupt { .. }) {
+            actions.submitted.push_front(action);
+            break;
+        }
+        let faction = action.faction();
+        let seat = players.iter().find(|(player, _)| player.faction == faction);
+        let spice = seat.map_or(0, |(player, _)| player.spice);
+        let table = (&*info, phase.phase, active, spice);
+        if validate(&action, table, queues.negotiation.open).is_ok()
+            && queues.interrupts.hold(
+                &action,
+                (&abilities, &info.factions_in_play, &effects.karama),
+                &holders,
+            )
+        {
+            continue;
+        }
+        let request = actions.request_of(&action);
+        match apply_action(&action, table, &mut queues) {
+            // Answers to a held action and battle plans are locked in as soon as they are queued
+            Ok(())
+                if matches!(
+                    action,
+                    GameAction::Interrupt { .. } | GameAction::Battle { .. }
+                ) =>
+            {
+                actions.resolved(&info, phase.phase, action)
+            }
+            Ok(()) => (),
+            Err(reason) => {
+                println!("Rejected {:?}: {}", action, reason);
+                // Whoever made the decision hears why, wherever they are sitting
+                let remote = server
+                    .iter_mut()
+                    .next()
+                    .and_then(|server| server.address_of(faction).map(|address| (server, address)));
+                if let Some((mut server, address)) = remote {
+                    // An action the host made for them, like when their clock ran out, has no request
+                    let request = request.unwrap_or(0);
+                    server.send_event(address, &ServerEvent::ActionRejected { request, reason });
+                } else if seat.map_or(false, |(_, bot)| bot.is_none()) {
+                    actions.rejected = Some(reason);
+                }
+            }
+        }
+    }
+}
+
+fn reset(mut actions: ResMut<GameActions>) {
+    *actions = GameActions::default();
+}
@@ -0,0 +1,241 @@
+use std::{collections::HashMap, fs, path::PathBuf, time::SystemTime};
+
+use bevy::prelude::*;
+
+use crate::{
+    components::{sector_shape, Collider, LocationSector, Secret, SpiceNode, Tooltip},
+    data::{Board, Location, SpiceCard, TreacheryCard, BOARD_PATH},
+    decks::{DeckPreset, DeckPresets, DECKS_DIR},
+    material_cache::MaterialCache,
+    resources::{Data, SectorGraph},
+    Screen, STATE_CHANGE_STAGE,
+};
+
+/// How often the deck presets are checked for changes
+const DECK_POLL_SECONDS: f32 = 1.0;
+
+/// Picks up edits to the board layout, the deck presets and their card art while a game is running,
+/// for balancing cards and tweaking the board without restarting. Only in debug builds.
+pub struct HotReloadPlugin;
+
+impl Plugin for HotReloadPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        app.add_startup_system(watch_assets.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                board_reload_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                board_reload_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                deck_reload_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                deck_reload_system.system(),
+            );
+    }
+}
+
+fn watch_assets(asset_server: Res<AssetServer>) {
+    match asset_server.watch_for_changes() {
+        Ok(()) => println!("Watching assets for changes"),
+        Err(e) => println!("Can't watch assets for changes: {:?}", e),
+    }
+}
+
+type SectorColliders<'a> = (&'a mut LocationSector, &'a mut Collider, &'a mut Tooltip);
+
+/// Moves the territories to match the board file, keeping every entity so the troops and spice
+/// on them stay where they are
+fn board_reload_system(
+    (mut data, mut graph, boards): (ResMut<Data>, ResMut<SectorGraph>, Res<Assets<Board>>),
+    (asset_server, events): (Res<AssetServer>, Res<Events<AssetEvent<Board>>>),
+    mut reader: Local<EventReader<AssetEvent<Board>>>,
+    mut locations: Query<(&mut Location, Option<&mut SpiceNode>, &Children)>,
+    mut sectors: Query<SectorColliders>,
+) {
+    let handle = asset_server.get_handle::<Board, _>(BOARD_PATH);
+    let modified = reader.iter(&events).any(|event| match event {
+        AssetEvent::Modified { handle: changed } => *changed == handle,
+        _ => false,
+    });
+    let board = match boards.get(&handle) {
+        Some(board) if modified => board,
+        _ => return,
+    };
+    data.set_board(board);
+    *graph = SectorGraph::new(&data.locations);
+    for (mut location, node, children) in locations.iter_mut() {
+        let reloaded = match data.locations.iter().find(|l| l.name == location.name) {
+            Some(reloaded) => reloaded.clone(),
+            None => {
+                println!(
+                    "{} is gone from the board, restart to remove it",
+                    location.name
+                );
+                continue;
+            }
+        };
+        if let (Some(mut node), Some(pos)) = (node, reloaded.spice) {
+            node.pos = pos;
+        }
+        for &child in children.iter() {
+            if let Ok((mut sector, mut collider, mut tooltip)) = sectors.get_mut(child) {
+                if let Some(nodes) = reloaded.sectors.get(&sector.sector) {
+                    collider.shape = sector_shape(nodes);
+                    *tooltip = Tooltip(format!("{}, sector {}", reloaded.name, sector.sector));
+                    sector.location = reloaded.clone();
+                }
+            }
+        }
+        if reloaded.sectors.len() != children.len() {
+            println!(
+                "{} has a different number of sectors, restart to add or remove them",
+                reloaded.name
+            );
+        }
+        *location = reloaded;
+    }
+    println!("Reloaded the board");
+}
+
+/// The last time each deck preset was seen to change, and when to look again
+#[derive(Default)]
+struct DeckWatch {
+    modified: HashMap<PathBuf, SystemTime>,
+    timer: Timer,
+    started: bool,
+}
+
+fn modified_presets(watch: &mut DeckWatch) -> Vec<PathBuf> {
+    let paths = fs::read_dir(DECKS_DIR)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().map_or(false, |ext| ext == "ron"))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    paths
+        .into_iter()
+        .filter(|path| {
+            let time = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+            match time {
+                Some(time) => watch.modified.insert(path.clone(), time) != Some(time),
+                None => false,
+            }
+        })
+        .collect()
+}
+
+type TreacheryCards<'a> = (
+    &'a mut Secret<TreacheryCard>,
+    Option<&'a mut TreacheryCard>,
+    &'a Children,
+);
+
+/// Rewrites the cards of the deck in play from its preset file when that changes: their rules,
+/// names and faces. Cards are matched by their place in the preset, so adding or removing cards
+/// needs a restart.
+fn deck_reload_system(
+    (time, asset_server): (Res<Time>, Res<AssetServer>),
+    (mut data, mut presets): (ResMut<Data>, ResMut<DeckPresets>),
+    (mut materials, mut cache): (ResMut<Assets<StandardMaterial>>, ResMut<MaterialCache>),
+    mut watch: Local<DeckWatch>,
+    mut treachery: Query<TreacheryCards>,
+    mut spice: Query<(&mut SpiceCard, &Children)>,
+    mut faces: Query<&mut Handle<StandardMaterial>>,
+) {
+    if !watch.started {
+        watch.started = true;
+        watch.timer = Timer::from_seconds(DECK_POLL_SECONDS, true);
+        // Everything on disk now is what the game was set up with
+        modified_presets(&mut watch);
+        return;
+    }
+    if !watch.timer.tick(time.delta_seconds()).just_finished() {
+        return;
+    }
+    for path in modified_presets(&mut watch) {
+        let preset = match DeckPreset::open(&path) {
+            Ok(preset) => preset,
+            Err(e) => {
+                println!("Not reloading {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        if let Some(listed) = presets
+            .presets
+            .iter_mut()
+            .find(|listed| listed.name == preset.name)
+        {
+            *listed = preset.clone();
+        }
+        if preset.name != data.deck_name {
+            continue;
+        }
+        if preset.treachery.len() != data.treachery_cards.len()
+            || preset.spice.len() != data.spice_cards.len()
+        {
+            println!(
+                "{} has a different number of cards, restart to add or remove them",
+                path.display()
+            );
+        }
+
+        let mut set_face = |face: Entity, texture: String| {
+            if let Ok(mut material) = faces.get_mut(face) {
+                *material = cache.textured(&texture, &asset_server, &mut materials);
+            }
+        };
+        for (mut secret, card, children) in treachery.iter_mut() {
+            // Cards nobody here has seen keep showing their backs. Slots are dealt out at random,
+            // so the card is found by where it sits in the old deck.
+            let reloaded = secret
+                .value
+                .as_ref()
+                .and_then(|old| {
+                    data.treachery_cards
+                        .iter()
+                        .position(|card| card.id == old.id)
+                })
+                .and_then(|i| preset.treachery.get(i));
+            let reloaded = match reloaded {
+                Some(reloaded) => reloaded,
+                None => continue,
+            };
+            secret.value = Some(reloaded.clone());
+            if let Some(mut card) = card {
+                *card = reloaded.clone();
+            }
+            set_face(
+                children[0],
+                format!("treachery/treachery_{}.png", reloaded.texture),
+            );
+        }
+        for (mut card, children) in spice.iter_mut() {
+            let reloaded = data
+                .spice_cards
+                .iter()
+                .position(|old| *old == *card)
+                .and_then(|i| preset.spice.get(i));
+            if let Some(reloaded) = reloaded {
+                *card = reloaded.clone();
+                set_face(children[0], format!("spice/spice_{}.png", reloaded.texture));
+            }
+        }
+        data.set_decks(&preset);
+        println!("Reloaded the {} deck", preset.name);
+    }
+}
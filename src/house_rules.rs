@@ -0,0 +1,423 @@
+use std::{
+    convert::TryFrom,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use rkyv::{Archive, Unarchive};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    components::Player,
+    data::Faction,
+    locale::Tr,
+    network::{ConnectionState, Network, NetworkType, Server},
+    phase::{Phase, PhaseStarted},
+    protocol::ServerEvent,
+    resources::Info,
+    Screen, STATE_CHANGE_STAGE,
+};
+
+/// Every `.rhai` file in here is a set of house rules the host can pick in the lobby
+pub const HOUSE_RULES_DIR: &str = "data/house_rules";
+/// The most steps a script gets for any one call, so a runaway loop can't hang the host
+const MAX_OPERATIONS: u64 = 100_000;
+/// Numbers a script gives back are held within this either way, far beyond anything a game needs
+/// but well clear of overflowing a reserve
+const MAX_SCRIPT_VALUE: i32 = 10_000;
+
+pub struct HouseRulesPlugin;
+
+impl Plugin for HouseRulesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<HouseRules>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::Server,
+                house_rules_sync_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                house_rules_phase_system.system(),
+            );
+    }
+}
+
+/// A house rules script, as loaded by the host and sent to everyone else. The SHA-256 hash lets
+/// every client check it is running exactly what the host is.
+///
+/// A script changes the rules by defining any of these functions, leaving the rest to the
+/// standard rules:
+/// - `starting_spice(faction, spice)` returns the spice a faction starts the game with
+/// - `charity(faction, spice, standard)` returns what a faction is given at CHOAM Charity
+/// - `storm_move(turn, sectors)` returns how many sectors the storm moves
+/// - `phase_started(phase, turn)` may return a map of spice to give each faction, such as
+///   `#{ Fremen: 2, Harkonnen: -1 }`, whenever a phase begins
+///
+/// Factions are named as in `data/leaders.ron`, and phases in snake case, like `"spice_blow"`.
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct HouseRulesScript {
+    pub name: String,
+    pub source: String,
+    pub hash: Vec<u8>,
+}
+
+impl HouseRulesScript {
+    pub fn new(name: String, source: String) -> Self {
+        let hash = hash_source(&source);
+        HouseRulesScript { name, source, hash }
+    }
+
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().replace('_', " "))
+            .unwrap_or_default();
+        let script = HouseRulesScript::new(name, source);
+        script.compile()?;
+        Ok(script)
+    }
+
+    /// Whether the source is what the host hashed
+    pub fn verify(&self) -> Result<(), String> {
+        if hash_source(&self.source) != self.hash {
+            return Err(format!("{} doesn't match its hash", self.name));
+        }
+        Ok(())
+    }
+
+    /// Compiles the script and runs whatever it does outside of its functions, which has to
+    /// succeed before it is allowed into a game
+    fn compile(&self) -> Result<Hooks, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine
+            .compile(&self.source)
+            .map_err(|e| format!("{}: {}", self.name, e))?;
+        engine
+            .consume_ast(&ast)
+            .map_err(|e| format!("{}: {}", self.name, e))?;
+        Ok(Hooks { engine, ast })
+    }
+
+    /// Eight hex digits of the hash, enough for players to compare at a glance
+    pub fn short_hash(&self) -> String {
+        self.hash
+            .iter()
+            .take(4)
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+fn hash_source(source: &str) -> Vec<u8> {
+    Sha256::digest(source.as_bytes()).to_vec()
+}
+
+/// A number from a script as spice or sectors, or `None` if it is out of all proportion
+fn script_int(hook: &str, value: rhai::INT) -> Option<i32> {
+    match i32::try_from(value) {
+        Ok(value) => Some(value.clamp(-MAX_SCRIPT_VALUE, MAX_SCRIPT_VALUE)),
+        Err(_) => {
+            println!(
+                "House rule {} returned {}, which is out of range",
+                hook, value
+            );
+            None
+        }
+    }
+}
+
+/// A compiled script, ready to be called into
+struct Hooks {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Hooks {
+    /// Calls the function named `hook`, or gives back `None` if the script doesn't define it.
+    /// A hook that fails is reported and treated as missing, so the game carries on under the
+    /// standard rules.
+    fn call(&self, hook: &str, args: impl rhai::FuncArgs) -> Option<Dynamic> {
+        match self
+            .engine
+            .call_fn::<_, Dynamic>(&mut Scope::new(), &self.ast, hook, args)
+        {
+            Ok(value) => Some(value),
+            Err(err) => {
+                if !matches!(*err, EvalAltResult::ErrorFunctionNotFound(..)) {
+                    println!("House rule {} failed: {}", hook, err);
+                }
+                None
+            }
+        }
+    }
+
+    fn call_int(&self, hook: &str, args: impl rhai::FuncArgs) -> Option<i32> {
+        let value = self.call(hook, args)?;
+        match value.as_int() {
+            Ok(value) => script_int(hook, value),
+            Err(kind) => {
+                println!("House rule {} returned {} rather than a number", hook, kind);
+                None
+            }
+        }
+    }
+}
+
+/// Every valid script found on disk, the one the host has picked, and the one in play
+#[derive(Default)]
+pub struct HouseRules {
+    pub scripts: Vec<HouseRulesScript>,
+    /// Counts from 1, with 0 playing by the standard rules
+    pub selected: usize,
+    active: Option<(HouseRulesScript, Hooks)>,
+    /// Why the last script put in play couldn't be
+    error: Option<String>,
+}
+
+impl FromResources for HouseRules {
+    fn from_resources(_: &Resources) -> Self {
+        let mut paths = fs::read_dir(HOUSE_RULES_DIR)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().map_or(false, |ext| ext == "rhai"))
+                    .collect::<Vec<PathBuf>>()
+            })
+            .unwrap_or_default();
+        paths.sort();
+        let scripts = paths
+            .into_iter()
+            .filter_map(|path| match HouseRulesScript::open(&path) {
+                Ok(script) => Some(script),
+                Err(e) => {
+                    println!("Skipping house rules {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+        HouseRules {
+            scripts,
+            ..Default::default()
+        }
+    }
+}
+
+impl HouseRules {
+    pub fn selected(&self) -> Option<&HouseRulesScript> {
+        self.selected
+            .checked_sub(1)
+            .and_then(|i| self.scripts.get(i))
+    }
+
+    pub fn next(&mut self) {
+        self.selected = (self.selected + 1) % (self.scripts.len() + 1);
+    }
+
+    /// The script in play, if any
+    pub fn active(&self) -> Option<&HouseRulesScript> {
+        self.active.as_ref().map(|(script, _)| script)
+    }
+
+    /// Puts a script in play, or goes back to the standard rules with `None` or a script that
+    /// won't run
+    pub fn set_active(&mut self, script: Option<HouseRulesScript>) -> Result<(), String> {
+        let active = script
+            .map(|script| -> Result<_, String> {
+                script.verify()?;
+                let hooks = script.compile()?;
+                Ok((script, hooks))
+            })
+            .transpose();
+        self.error = active.as_ref().err().cloned();
+        self.active = active.unwrap_or_default();
+        match self.error {
+            Some(ref e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+
+    /// What the lobby says about the rules in play, for clients who can't pick them
+    pub fn active_label(&self, tr: &Tr) -> String {
+        let name = match (self.active(), self.error.as_ref()) {
+            (Some(script), _) => format!("{} ({})", script.name, script.short_hash()),
+            (None, Some(e)) => tr.fmt("lobby.house_rules_failed", &[e]),
+            (None, None) => tr.get("lobby.no_house_rules"),
+        };
+        tr.fmt("lobby.house_rules", &[&name])
+    }
+
+    pub fn label(&self, tr: &Tr) -> String {
+        let name = self.selected().map_or_else(
+            || tr.get("lobby.no_house_rules"),
+            |script| format!("{} ({})", script.name, script.short_hash()),
+        );
+        tr.fmt("lobby.house_rules", &[&name])
+    }
+
+    fn hooks(&self) -> Option<&Hooks> {
+        self.active.as_ref().map(|(_, hooks)| hooks)
+    }
+
+    pub fn starting_spice(&self, faction: Faction, spice: i32) -> Option<i32> {
+        self.hooks()?.call_int(
+            "starting_spice",
+            (format!("{:?}", faction), spice as rhai::INT),
+        )
+    }
+
+    pub fn charity(&self, faction: Faction, spice: i32, standard: i32) -> Option<i32> {
+        self.hooks()?.call_int(
+            "charity",
+            (
+                format!("{:?}", faction),
+                spice as rhai::INT,
+                standard as rhai::INT,
+            ),
+        )
+    }
+
+    pub fn storm_move(&self, turn: i32, sectors: i32) -> Option<i32> {
+        self.hooks()?
+            .call_int("storm_move", (turn as rhai::INT, sectors as rhai::INT))
+    }
+
+    /// The spice each faction is given, or has taken, as `phase` begins
+    pub fn phase_started(&self, phase: Phase, turn: i32) -> Vec<(Faction, i32)> {
+        let value = match self.hooks().and_then(|hooks| {
+            hooks.call(
+                "phase_started",
+                (phase_name(phase).to_string(), turn as rhai::INT),
+            )
+        }) {
+            Some(value) => value,
+            None => return Vec::new(),
+        };
+        // Returning nothing leaves the phase alone
+        if value.is::<()>() {
+            return Vec::new();
+        }
+        let map = match value.try_cast::<Map>() {
+            Some(map) => map,
+            None => {
+                println!("House rule phase_started returned something other than a map");
+                return Vec::new();
+            }
+        };
+        map.into_iter()
+            .filter_map(|(key, amount)| {
+                let faction = Faction::ALL
+                    .iter()
+                    .copied()
+                    .find(|faction| format!("{:?}", faction) == key.as_str());
+                let amount = amount
+                    .as_int()
+                    .ok()
+                    .and_then(|amount| script_int("phase_started", amount));
+                match (faction, amount) {
+                    (Some(faction), Some(amount)) => Some((faction, amount)),
+                    _ => {
+                        println!("House rule phase_started gave {} an unusable amount", key);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// How a phase is named to scripts
+fn phase_name(phase: Phase) -> &'static str {
+    match phase {
+        Phase::Setup { .. } => "setup",
+        Phase::Storm { .. } => "storm",
+        Phase::SpiceBlow { .. } => "spice_blow",
+        Phase::Nexus => "nexus",
+        Phase::Charity => "charity",
+        Phase::Bidding { .. } => "bidding",
+        Phase::Revival => "revival",
+        Phase::Movement { .. } => "movement",
+        Phase::Battle => "battle",
+        Phase::Collection => "collection",
+        Phase::Control => "control",
+        Phase::EndGame => "end_game",
+    }
+}
+
+/// Puts the host's pick in play and sends it to every client, so whoever takes over as host
+/// carries on with the same rules
+fn house_rules_sync_system(
+    network: Res<Network>,
+    mut house_rules: ResMut<HouseRules>,
+    mut informed: Local<(usize, usize)>,
+    mut server: Query<&mut Server>,
+) {
+    if network.network_type == NetworkType::Client {
+        return;
+    }
+    let selected = house_rules.selected().cloned();
+    if house_rules.active() != selected.as_ref() {
+        if let Err(e) = house_rules.set_active(selected.clone()) {
+            println!("Playing by the standard rules: {}", e);
+        }
+    }
+    if let Some(mut server) = server.iter_mut().next() {
+        // Anyone who connected since needs it too
+        let connected = server
+            .clients
+            .values()
+            .filter(|connection| connection.state == ConnectionState::Healthy)
+            .count();
+        if *informed != (connected, house_rules.selected) {
+            server.broadcast(&ServerEvent::HouseRules {
+                script: house_rules.active().cloned(),
+            });
+            *informed = (connected, house_rules.selected);
+        }
+    }
+}
+
+/// Calls the script as each phase begins, subphases aside
+fn house_rules_phase_system(
+    (house_rules, info, events): (Res<HouseRules>, Res<Info>, Res<Events<PhaseStarted>>),
+    mut reader: Local<EventReader<PhaseStarted>>,
+    mut last: Local<Option<&'static str>>,
+    mut players: Query<&mut Player>,
+) {
+    for event in reader.iter(&events) {
+        let name = phase_name(event.phase);
+        if *last == Some(name) {
+            continue;
+        }
+        *last = Some(name);
+        if house_rules.active().is_none() {
+            continue;
+        }
+        if name == "setup" {
+            for mut player in players.iter_mut() {
+                if let Some(spice) = house_rules.starting_spice(player.faction, player.spice) {
+                    player.spice = spice.max(0);
+                }
+            }
+        }
+        for (faction, amount) in house_rules.phase_started(event.phase, info.turn) {
+            if let Some(mut player) = players.iter_mut().find(|player| player.faction == faction) {
+                if amount >= 0 {
+                    player.receive(amount);
+                    println!("House rules give {} {} spice", faction, amount);
+                } else {
+                    let amount = (-amount).min(player.spice);
+                    player.pay(amount);
+                    println!("House rules take {} spice from {}", amount, faction);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::mem::replace;
+
+use bevy::{
+    prelude::*,
+    render::camera::{Camera, OrthographicProjection},
+};
+
+use crate::{
+    components::{Collider, Tooltip},
+    util::hovered,
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// Warms up whatever the cursor is over
+const HOVER_TINT: Color = Color::rgb_linear(1.4, 1.25, 0.9);
+/// Keeps the tooltip clear of the cursor itself
+const TOOLTIP_OFFSET: f32 = 14.0;
+
+pub struct HoverPlugin;
+
+impl Plugin for HoverPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Hover>()
+            .on_state_enter(RESPONSE_STAGE, Screen::HostingGame, init_tooltip.system())
+            .on_state_enter(RESPONSE_STAGE, Screen::JoinedGame, init_tooltip.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                hover_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                hover_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system());
+    }
+}
+
+#[derive(Default)]
+pub struct Hover {
+    pub entity: Option<Entity>,
+    /// Materials swapped out for tinted copies, to be put back once the cursor moves on
+    tinted: Vec<(Entity, Handle<StandardMaterial>)>,
+}
+
+struct TooltipText;
+
+fn init_tooltip(commands: &mut Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            text: Text {
+                font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                value: String::new(),
+                style: TextStyle {
+                    font_size: 16.0,
+                    color: Color::ANTIQUE_WHITE,
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(TooltipText);
+}
+
+/// Ray-casts the clickable colliders every frame, tints the one under the cursor and names it in a
+/// tooltip next to the cursor
+fn hover_system(
+    mut hover: ResMut<Hover>,
+    (windows, mut materials): (Res<Windows>, ResMut<Assets<StandardMaterial>>),
+    cameras: Query<(&Camera, &Transform), Without<OrthographicProjection>>,
+    colliders: Query<(Entity, &Collider, &Transform)>,
+    (tooltips, children): (Query<&Tooltip>, Query<&Children>),
+    mut meshes: Query<&mut Handle<StandardMaterial>>,
+    mut texts: Query<(&mut Text, &mut Style), With<TooltipText>>,
+) {
+    let entity =
+        hovered(&windows, &cameras, &colliders).filter(|&entity| tooltips.get(entity).is_ok());
+
+    if entity != hover.entity {
+        for (child, original) in hover.tinted.drain(..) {
+            if let Ok(mut handle) = meshes.get_mut(child) {
+                *handle = original;
+            }
+        }
+        if let Some(Ok(kids)) = entity.map(|entity| children.get(entity)) {
+            for &child in kids.iter() {
+                if let Ok(mut handle) = meshes.get_mut(child) {
+                    if let Some(material) = materials.get(&*handle) {
+                        let tinted = StandardMaterial {
+                            albedo: HOVER_TINT,
+                            albedo_texture: material.albedo_texture.clone(),
+                            shaded: material.shaded,
+                        };
+                        let tinted = materials.add(tinted);
+                        hover.tinted.push((child, replace(&mut *handle, tinted)));
+                    }
+                }
+            }
+        }
+        hover.entity = entity;
+    }
+
+    let cursor = windows
+        .get_primary()
+        .and_then(|window| window.cursor_position());
+    for (mut text, mut style) in texts.iter_mut() {
+        let value = entity
+            .and_then(|entity| tooltips.get(entity).ok())
+            .map(|tooltip| tooltip.0.clone())
+            .unwrap_or_default();
+        if text.value != value {
+            text.value = value;
+        }
+        if let Some(cursor) = cursor {
+            style.position = Rect {
+                left: Val::Px(cursor.x + TOOLTIP_OFFSET),
+                bottom: Val::Px(cursor.y + TOOLTIP_OFFSET),
+                ..Default::default()
+            };
+        }
+    }
+}
+
+fn reset(mut hover: ResMut<Hover>) {
+    *hover = Hover::default();
+}
@@ -0,0 +1,598 @@
+use bevy::prelude::*;
+
+use crate::{
+    bot::Bot,
+    components::{Dead, Player, Storm, Troop, Unique},
+    data::Faction,
+    dune_core::{Occupancy, LAST_TURN},
+    game_action::GameActions,
+    layout::{Anchored, AnchoredHeight},
+    locale::{Localized, Tr},
+    network::{Latencies, Network, NetworkType},
+    phase::{ActionQueue, Phase, PhaseStarted, PhaseText, TurnStarted},
+    resources::{Data, Info, RuleSet},
+    savegame::{LoadedGame, SaveRequest},
+    timer::TimeBanks,
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// Seconds a rejected decision's reason, or a failed save or load, stays up
+const NOTICE_TIME: f32 = 4.0;
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<HudMaterials>()
+            .init_resource::<HotSeat>()
+            .on_state_enter(RESPONSE_STAGE, Screen::HostingGame, init_hud.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                phase_track_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                status_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                hot_seat_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset_hot_seat.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                latency_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                latency_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                ornithopter_icon_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                ornithopter_icon_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                player_status_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                player_status_system.system(),
+            )
+            .on_state_enter(RESPONSE_STAGE, Screen::HostingGame, init_notice.system())
+            .on_state_enter(RESPONSE_STAGE, Screen::JoinedGame, init_notice.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                notice_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                notice_system.system(),
+            );
+    }
+}
+
+pub struct HudMaterials {
+    pub panel: Handle<ColorMaterial>,
+    pub upcoming: Handle<ColorMaterial>,
+    pub current: Handle<ColorMaterial>,
+    pub done: Handle<ColorMaterial>,
+    pub eliminated: Handle<ColorMaterial>,
+    privacy: Handle<ColorMaterial>,
+}
+
+impl FromResources for HudMaterials {
+    fn from_resources(resources: &Resources) -> Self {
+        let mut materials = resources.get_mut::<Assets<ColorMaterial>>().unwrap();
+        HudMaterials {
+            panel: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.5).into()),
+            upcoming: materials.add(Color::rgba(0.15, 0.15, 0.15, 0.8).into()),
+            current: materials.add(Color::rgba(0.8, 0.55, 0.2, 0.9).into()),
+            done: materials.add(Color::rgba(0.3, 0.25, 0.2, 0.8).into()),
+            eliminated: materials.add(Color::rgba(0.35, 0.35, 0.35, 0.6).into()),
+            privacy: materials.add(Color::rgb(0.05, 0.04, 0.03).into()),
+        }
+    }
+}
+
+impl HudMaterials {
+    /// Changes the colors in place, so panels already on screen pick them up too
+    pub fn recolor(&self, materials: &mut Assets<ColorMaterial>, high_contrast: bool) {
+        let colors = if high_contrast {
+            [
+                Color::rgb(0.0, 0.0, 0.0),
+                Color::rgb(0.2, 0.2, 0.2),
+                Color::rgb(1.0, 0.85, 0.0),
+                Color::rgb(0.0, 0.45, 0.9),
+                Color::rgb(0.5, 0.5, 0.5),
+                Color::rgb(0.0, 0.0, 0.0),
+            ]
+        } else {
+            [
+                Color::rgba(0.0, 0.0, 0.0, 0.5),
+                Color::rgba(0.15, 0.15, 0.15, 0.8),
+                Color::rgba(0.8, 0.55, 0.2, 0.9),
+                Color::rgba(0.3, 0.25, 0.2, 0.8),
+                Color::rgba(0.35, 0.35, 0.35, 0.6),
+                Color::rgb(0.05, 0.04, 0.03),
+            ]
+        };
+        let handles = [
+            &self.panel,
+            &self.upcoming,
+            &self.current,
+            &self.done,
+            &self.eliminated,
+            &self.privacy,
+        ];
+        for (handle, color) in handles.iter().zip(colors.iter()) {
+            if let Some(material) = materials.get_mut(*handle) {
+                material.color = *color;
+            }
+        }
+    }
+}
+
+/// Tracks which seat has been revealed in a hot-seat game, so hands and traitor picks stay hidden
+/// until the next player has taken over the machine
+#[derive(Default)]
+pub struct HotSeat {
+    revealed: Option<Entity>,
+}
+
+impl HotSeat {
+    /// Hands the machine to a player without asking, for when they are the only one at it
+    pub fn reveal(&mut self, player: Entity) {
+        self.revealed = Some(player);
+    }
+
+    pub fn concealed(&self, network: &Network, player: Entity) -> bool {
+        network.network_type == NetworkType::Local && self.revealed != Some(player)
+    }
+}
+
+struct PrivacyOverlay;
+
+struct PrivacyText;
+
+struct PhaseTrackTile(usize);
+
+/// A faction's tile in the turn order
+pub struct TurnTile(pub Faction);
+
+/// Sits on a faction's turn tile and shows how that player's connection is doing
+pub struct LatencyText(pub Faction);
+
+/// Sits on a faction's turn tile and shows what everyone at the table can see of that player
+pub struct PlayerStatusText(pub Faction);
+
+/// Sits on a faction's turn tile and shows while it has ornithopters
+pub struct OrnithopterIcon(pub Faction);
+
+struct TurnText;
+
+enum StatusText {
+    Storm,
+    Pending,
+}
+
+fn init_hud(
+    commands: &mut Commands,
+    data: Res<Data>,
+    asset_server: Res<AssetServer>,
+    materials: Res<HudMaterials>,
+) {
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let text = |value: &str, font_size: f32| TextBundle {
+        text: Text {
+            font: font.clone(),
+            value: value.to_string(),
+            style: TextStyle {
+                font_size,
+                color: Color::ANTIQUE_WHITE,
+                ..Default::default()
+            },
+        },
+        ..Default::default()
+    };
+
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(5.0),
+                    left: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..text("", 40.0)
+        })
+        .with(ScreenEntity)
+        .with(PhaseText);
+
+    // Phase track along the top edge of the screen
+    let track = &data.ui_structure.phases;
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(Anchored::new(track.clone()).with_height(AnchoredHeight::Px(32.0)))
+        .with_children(|parent| {
+            for (i, name) in Phase::TRACK.iter().enumerate() {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            size: Size::new(
+                                Val::Percent(100.0 / Phase::TRACK.len() as f32),
+                                Val::Percent(100.0),
+                            ),
+                            margin: Rect::all(Val::Px(1.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        material: materials.upcoming.clone(),
+                        ..Default::default()
+                    })
+                    .with(PhaseTrackTile(i))
+                    .with_children(|parent| {
+                        parent.spawn(text("", 14.0)).with(Localized(name));
+                    });
+            }
+        });
+
+    // Turn counter, storm position and whoever we are waiting on
+    let counter = &data.ui_structure.turn_counter;
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::FlexEnd,
+                padding: Rect::all(Val::Px(6.0)),
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(Anchored::new(counter.clone()).with_height(AnchoredHeight::Auto))
+        .with_children(|parent| {
+            parent
+                .spawn(text("", 28.0))
+                .with(TurnText)
+                .spawn(text("", 18.0))
+                .with(StatusText::Storm)
+                .spawn(text("", 18.0))
+                .with(StatusText::Pending);
+        });
+
+    // Covers the whole screen between hot-seat turns
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.privacy.clone(),
+            visible: Visible {
+                is_visible: false,
+                is_transparent: true,
+            },
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(PrivacyOverlay)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    visible: Visible {
+                        is_visible: false,
+                        is_transparent: true,
+                    },
+                    ..text("", 40.0)
+                })
+                .with(PrivacyOverlay)
+                .with(PrivacyText);
+        });
+}
+
+/// Highlights the current phase whenever one starts and moves the turn counter with each turn
+fn phase_track_system(
+    (phases, turns): (Res<Events<PhaseStarted>>, Res<Events<TurnStarted>>),
+    (mut phase_reader, mut turn_reader): (
+        Local<EventReader<PhaseStarted>>,
+        Local<EventReader<TurnStarted>>,
+    ),
+    (materials, tr): (Res<HudMaterials>, Res<Tr>),
+    mut tiles: Query<(&PhaseTrackTile, &mut Handle<ColorMaterial>)>,
+    mut turn_text: Query<&mut Text, With<TurnText>>,
+) {
+    // Leave events unread until the HUD has been spawned
+    if tiles.iter_mut().next().is_none() {
+        return;
+    }
+    if let Some(event) = turn_reader.iter(&turns).last() {
+        if let Some(mut text) = turn_text.iter_mut().next() {
+            text.value = tr.fmt("hud.turn", &[&(event.turn + 1), &LAST_TURN]);
+        }
+    }
+    if let Some(event) = phase_reader.iter(&phases).last() {
+        let current = event.phase.track_index();
+        for (tile, mut material) in tiles.iter_mut() {
+            *material = match current {
+                Some(current) if tile.0 == current => materials.current.clone(),
+                Some(current) if tile.0 < current => materials.done.clone(),
+                _ => materials.upcoming.clone(),
+            };
+        }
+        if let (Phase::Setup { .. }, Some(mut text)) = (event.phase, turn_text.iter_mut().next()) {
+            text.value = tr.get("hud.setup");
+        }
+    }
+}
+
+fn status_system(
+    (info, latencies, tr): (Res<Info>, Res<Latencies>, Res<Tr>),
+    queue: Res<ActionQueue>,
+    players: Query<&Player>,
+    storm: Query<&Storm>,
+    mut texts: Query<(&StatusText, &mut Text)>,
+) {
+    for (status, mut text) in texts.iter_mut() {
+        let value = match status {
+            StatusText::Storm => storm
+                .iter()
+                .next()
+                .map(|storm| tr.fmt("hud.storm", &[&storm.sector]))
+                .unwrap_or_default(),
+            // Only point at a player when the game is actually waiting on them
+            StatusText::Pending => match players.get(info.get_active_player()) {
+                Ok(player) if queue.is_empty() => {
+                    if latencies.connected(player.faction) {
+                        tr.fmt("hud.waiting_on", &[&tr.faction(player.faction)])
+                    } else {
+                        tr.fmt("hud.reconnecting", &[&tr.faction(player.faction)])
+                    }
+                }
+                _ => String::new(),
+            },
+        };
+        if text.value != value {
+            text.value = value;
+        }
+    }
+}
+
+/// Asks for the machine to be handed over whenever a different faction has to act in a hot-seat
+/// game, and only reveals their screen once they click
+fn hot_seat_system(
+    (info, queue, network, tr): (Res<Info>, Res<ActionQueue>, Res<Network>, Res<Tr>),
+    mouse_input: Res<Input<MouseButton>>,
+    mut hot_seat: ResMut<HotSeat>,
+    players: Query<&Player, Without<Bot>>,
+    mut overlay: Query<&mut Visible, With<PrivacyOverlay>>,
+    mut texts: Query<&mut Text, With<PrivacyText>>,
+) {
+    if network.network_type != NetworkType::Local || info.play_order.is_empty() {
+        return;
+    }
+    let active = info.get_active_player();
+    let waiting = queue.is_empty() && hot_seat.concealed(&network, active);
+    let covered = match players.get(active) {
+        Ok(player) if waiting => {
+            let value = tr.fmt("hud.pass_to", &[&tr.faction(player.faction)]);
+            for mut text in texts.iter_mut() {
+                if text.value != value {
+                    text.value = value.clone();
+                }
+            }
+            // Released rather than pressed, so the same click can't also land on the board
+            if mouse_input.just_released(MouseButton::Left) {
+                hot_seat.revealed = Some(active);
+                false
+            } else {
+                true
+            }
+        }
+        _ => false,
+    };
+    for mut visible in overlay.iter_mut() {
+        if visible.is_visible != covered {
+            visible.is_visible = covered;
+        }
+    }
+}
+
+fn reset_hot_seat(mut hot_seat: ResMut<HotSeat>) {
+    *hot_seat = HotSeat::default();
+}
+
+struct NoticePanel;
+
+struct NoticeText;
+
+/// Spawned for clients as well as the host, since either can have a decision turned down
+fn init_notice(
+    commands: &mut Commands,
+    (data, asset_server): (Res<Data>, Res<AssetServer>),
+    materials: Res<HudMaterials>,
+) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                padding: Rect::all(Val::Px(8.0)),
+                display: Display::None,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(NoticePanel)
+        .with(Anchored::new(data.ui_structure.notice.clone()).with_height(AnchoredHeight::Auto))
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                        value: String::new(),
+                        style: TextStyle {
+                            font_size: 18.0,
+                            color: Color::rgb(1.0, 0.45, 0.35),
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(NoticeText);
+        });
+}
+
+/// Says why the last decision made here was turned down, or why the game couldn't be saved or
+/// loaded, for a few seconds
+fn notice_system(
+    (mut actions, time, tr): (ResMut<GameActions>, Res<Time>, Res<Tr>),
+    (mut save, mut loaded): (ResMut<SaveRequest>, ResMut<LoadedGame>),
+    mut shown: Local<Option<(String, f32)>>,
+    mut panels: Query<&mut Style, With<NoticePanel>>,
+    mut texts: Query<&mut Text, With<NoticeText>>,
+) {
+    if let Some(reason) = actions.rejected.take() {
+        *shown = Some((tr.fmt("hud.rejected", &[&reason]), NOTICE_TIME));
+    }
+    if let Some(err) = save.failed.take() {
+        *shown = Some((tr.fmt("hud.save_failed", &[&err]), NOTICE_TIME));
+    }
+    if let Some(err) = loaded.failed.take() {
+        *shown = Some((tr.fmt("hud.load_failed", &[&err]), NOTICE_TIME));
+    }
+    if let Some((_, remaining)) = shown.as_mut() {
+        *remaining -= time.delta_seconds();
+    }
+    if shown
+        .as_ref()
+        .map_or(false, |(_, remaining)| *remaining <= 0.0)
+    {
+        *shown = None;
+    }
+    let display = if shown.is_some() {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    for mut style in panels.iter_mut() {
+        if style.display != display {
+            style.display = display;
+        }
+    }
+    if let Some((value, _)) = shown.as_ref() {
+        for mut text in texts.iter_mut() {
+            if text.value != *value {
+                text.value = value.clone();
+            }
+        }
+    }
+}
+
+/// Bots and the host's own seat have no connection to report, so their tiles stay blank
+fn latency_system(latencies: ChangedRes<Latencies>, mut texts: Query<(&LatencyText, &mut Text)>) {
+    for (LatencyText(faction), mut text) in texts.iter_mut() {
+        let (value, color) = match latencies.players.get(faction) {
+            Some(latency) if !latency.connected => ("disconnected".to_string(), Color::RED),
+            Some(latency) => match latency.millis {
+                Some(millis) => (
+                    format!("{} ms", millis),
+                    if millis > 250 {
+                        Color::ORANGE
+                    } else {
+                        Color::ANTIQUE_WHITE
+                    },
+                ),
+                None => ("...".to_string(), Color::ANTIQUE_WHITE),
+            },
+            None => (String::new(), Color::ANTIQUE_WHITE),
+        };
+        text.value = value;
+        text.style.color = color;
+    }
+}
+
+fn ornithopter_icon_system(
+    occupancy: ChangedRes<Occupancy>,
+    mut icons: Query<(&OrnithopterIcon, &mut Visible)>,
+) {
+    for (OrnithopterIcon(faction), mut visible) in icons.iter_mut() {
+        let is_visible = occupancy.has_ornithopters(*faction);
+        if visible.is_visible != is_visible {
+            visible.is_visible = is_visible;
+        }
+    }
+}
+
+/// Keeps each turn tile's cards, spice, troops off the board and time bank up to date. Spice is
+/// read from the ledger, so it never shows a payment still being animated.
+fn player_status_system(
+    (rules, banks, tr): (Res<RuleSet>, Res<TimeBanks>, Res<Tr>),
+    players: Query<&Player>,
+    troops: Query<(&Troop, &Unique, Option<&Dead>)>,
+    mut texts: Query<(&PlayerStatusText, &mut Text)>,
+) {
+    for (PlayerStatusText(faction), mut text) in texts.iter_mut() {
+        let player = match players.iter().find(|player| player.faction == *faction) {
+            Some(player) => player,
+            None => continue,
+        };
+        let (mut reserves, mut tanks) = (0, 0);
+        for (troop, unique, dead) in troops.iter() {
+            if unique.faction != *faction {
+                continue;
+            }
+            if dead.is_some() {
+                tanks += 1;
+            } else if troop.location.is_none() {
+                reserves += 1;
+            }
+        }
+        let mut parts = vec![tr.fmt("hud.status_cards", &[&player.treachery_cards.len()])];
+        if rules.public_spice() {
+            parts.push(tr.fmt("hud.status_spice", &[&player.spice]));
+        }
+        parts.push(tr.fmt("hud.status_troops", &[&reserves, &tanks]));
+        if let Some(&seconds) = banks.remaining.get(faction) {
+            let seconds = seconds.max(0.0).ceil() as u32;
+            parts.push(format!("{}:{:02}", seconds / 60, seconds % 60));
+        }
+        let value = parts.join(" | ");
+        if text.value != value {
+            text.value = value;
+        }
+    }
+}
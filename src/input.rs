@@ -1,55 +1,197 @@
-use std::f32::consts::PI;
+use std::{collections::HashMap, mem::take};
 
 use bevy::{
+    input::mouse::{MouseMotion, MouseWheel},
     prelude::*,
-    render::camera::{Camera, OrthographicProjection},
+    render::camera::{Camera, OrthographicProjection, PerspectiveProjection},
 };
 
 use crate::{
-    components::{Collider, Disorganized, LocationSector, Player, Prediction, Troop, Unique},
-    data::{CameraNode, FactionPredictionCard, TurnPredictionCard},
+    alliance::{own_faction, Negotiation},
+    bot::Bot,
+    cards::HoveredCard,
+    components::{Collider, Dead, Disorganized, LocationSector, Player, Spice, Troop, Unique},
+    data::{CameraNode, Faction, FactionPredictionCard, Leader, TraitorCard, TurnPredictionCard},
+    dune_core::{check_action, TableState},
+    game_action::{submit, GameAction, GameActions},
+    hud::{HotSeat, HudMaterials},
     lerper::{Lerp, LerpType},
-    multi,
-    phase::{Action, ActionAggregation, ActionQueue, Context},
-    resources::{Data, Info},
-    util::{closest, closest_mut, MutRayCastResult, RayCastResult},
-    Screen, STATE_CHANGE_STAGE,
+    locale::Tr,
+    menu::{ButtonMaterials, Chat, Lobby},
+    network::{Client, Network, NetworkRole},
+    phase::{
+        Action, ActionAggregation, ActionQueue, Auction, Context, GamePhase, MovementRequest,
+        PredictionPick, RevivalRequest, TraitorPick,
+    },
+    resources::{Data, Info, SectorNode},
+    settings::{Binding, Settings},
+    util::{closest, closest_mut, cursor_on_plane, grab, MutRayCastResult, RayCastResult},
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
 };
 
+const SPECTATOR_PAN_SPEED: f32 = 0.8;
+const SPECTATOR_TURN_SPEED: f32 = 1.5;
+const SPECTATOR_ZOOM_STEP: f32 = 0.05;
+const CAMERA_TIME: f32 = 1.0;
+const ORBIT_SPEED: f32 = 0.005;
+const ORBIT_PAN_SPEED: f32 = 0.001;
+const ORBIT_ZOOM_STEP: f32 = 0.1;
+const ORBIT_NEAR: f32 = 0.4;
+const ORBIT_FAR: f32 = 5.0;
+/// Keeps the orbiting camera from dipping below the board
+const ORBIT_MIN_ELEVATION: f32 = 0.1;
+/// Tokens are carried just clear of everything else on the board while dragged
+const DRAG_HEIGHT: f32 = 0.05;
+const BOARD_HALF_WIDTH: f32 = 1.0;
+const BOARD_HALF_DEPTH: f32 = 1.1;
+/// Furthest the cursor can move, in pixels, between pressing and releasing for a right click to
+/// count as a click rather than a camera drag
+const CLICK_SLOP: f32 = 4.0;
+
+type DraggableToken<'a> = (
+    Entity,
+    &'a Collider,
+    &'a mut Transform,
+    &'a Unique,
+    Option<&'a Troop>,
+);
+type DraggableFilter = (
+    Or<(With<Troop>, With<Spice>)>,
+    Without<Dead>,
+    Without<Camera>,
+    Without<LocationSector>,
+);
+
 pub struct GameInputPlugin;
 
 impl Plugin for GameInputPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.on_state_update(
-            STATE_CHANGE_STAGE,
-            Screen::HostingGame,
-            camera_system.system(),
-        )
-        .on_state_update(
-            STATE_CHANGE_STAGE,
-            Screen::HostingGame,
-            sector_context_system.system(),
-        )
-        .on_state_update(
-            STATE_CHANGE_STAGE,
-            Screen::HostingGame,
-            prediction_context_system.system(),
-        )
-        .on_state_update(
-            STATE_CHANGE_STAGE,
-            Screen::JoinedGame,
-            camera_system.system(),
-        )
-        .on_state_update(
-            STATE_CHANGE_STAGE,
-            Screen::JoinedGame,
-            sector_context_system.system(),
-        )
-        .on_state_update(
-            STATE_CHANGE_STAGE,
-            Screen::JoinedGame,
-            prediction_context_system.system(),
-        );
+        app.init_resource::<StagedAction>()
+            .init_resource::<MovementInput>()
+            .init_resource::<ContextMenu>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                open_context_menu_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                context_menu_button_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                open_context_menu_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                context_menu_button_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                camera_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                orbit_camera_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                spectator_camera_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                drag_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                sector_context_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                prediction_context_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                bidding_context_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                revival_context_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                movement_context_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                traitor_context_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                staged_action_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                camera_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                orbit_camera_system.system(),
+            )
+            .on_state_update(STATE_CHANGE_STAGE, Screen::JoinedGame, drag_system.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                sector_context_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                prediction_context_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                bidding_context_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                revival_context_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                movement_context_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                traitor_context_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                staged_action_system.system(),
+            );
 
         app.on_state_update(
             STATE_CHANGE_STAGE,
@@ -59,56 +201,387 @@ impl Plugin for GameInputPlugin {
     }
 }
 
-pub fn debug_restart_system(mut state: ResMut<State<Screen>>, keyboard_input: Res<Input<KeyCode>>) {
-    if keyboard_input.just_pressed(KeyCode::F1) {
+/// Clicks that are previewed on the board but not yet committed
+#[derive(Default)]
+pub struct StagedAction {
+    pub placements: Vec<(Entity, Entity, Lerp)>,
+    pub movement: Option<MovementRequest>,
+    ghosts: Vec<(Entity, Transform)>,
+    /// The token being carried by the mouse and where it was picked up from
+    dragging: Option<(Entity, Transform)>,
+}
+
+impl StagedAction {
+    pub fn is_empty(&self) -> bool {
+        self.ghosts.is_empty()
+            && self.placements.is_empty()
+            && self.movement.is_none()
+            && self.dragging.is_none()
+    }
+
+    pub fn is_staged(&self, entity: Entity) -> bool {
+        self.ghosts.iter().any(|(ghost, _)| *ghost == entity)
+    }
+
+    fn preview(&mut self, commands: &mut Commands, entity: Entity, from: Transform, lerp: Lerp) {
+        if !self.is_staged(entity) {
+            self.ghosts.push((entity, from));
+        }
+        commands.insert_one(entity, lerp);
+    }
+
+    /// Send every previewed element back to where it was and drop the staged action
+    pub fn cancel(&mut self, commands: &mut Commands) {
+        for (entity, from) in self.ghosts.drain(..).chain(self.dragging.take()) {
+            commands.insert_one(entity, Lerp::new(LerpType::world_to(from), 0.2, 0.0));
+        }
+        self.placements.clear();
+        self.movement = None;
+    }
+
+    /// Take the staged action, leaving the previewed elements where they are
+    fn commit(&mut self) -> (Vec<(Entity, Entity, Lerp)>, Option<MovementRequest>) {
+        self.ghosts.clear();
+        (take(&mut self.placements), self.movement.take())
+    }
+}
+
+fn staged_action_system(
+    commands: &mut Commands,
+    info: Res<Info>,
+    mut staged: ResMut<StagedAction>,
+    transforms: Query<&Transform>,
+) {
+    match info.context {
+        Context::PlacingTroops | Context::Shipping | Context::Moving => (),
+        _ if !staged.is_empty() => {
+            // The context moved on without a confirm, so whatever is left is stale
+            let dragging = staged.dragging.take();
+            for (entity, from) in staged.ghosts.drain(..).chain(dragging) {
+                if transforms.get(entity).is_ok() {
+                    commands.insert_one(entity, Lerp::new(LerpType::world_to(from), 0.2, 0.0));
+                }
+            }
+            *staged = StagedAction::default();
+        }
+        _ => (),
+    }
+}
+
+pub fn debug_restart_system(
+    mut state: ResMut<State<Screen>>,
+    (keyboard_input, settings): (Res<Input<KeyCode>>, Res<Settings>),
+) {
+    if settings.just_pressed(&keyboard_input, Binding::Restart) {
         state.overwrite_next(Screen::MainMenu).unwrap();
     }
 }
 
+/// Spectators can watch, but nothing they click turns into a game action
+fn spectating(network: &Network) -> bool {
+    network.role == NetworkRole::Spectator
+}
+
+/// Lets spectators fly around the board: WASD to pan, Q and E to turn, scroll to zoom
+fn spectator_camera_system(
+    (network, time, chat): (Res<Network>, Res<Time>, Res<Chat>),
+    (keyboard_input, settings): (Res<Input<KeyCode>>, Res<Settings>),
+    wheel_events: Res<Events<MouseWheel>>,
+    mut wheel_reader: Local<EventReader<MouseWheel>>,
+    mut camera: Query<&mut Transform, (With<PerspectiveProjection>, Without<Lerp>)>,
+) {
+    let zoom = wheel_reader
+        .iter(&wheel_events)
+        .map(|event| event.y)
+        .sum::<f32>();
+    if !spectating(&network) || chat.open {
+        return;
+    }
+    if let Some(mut transform) = camera.iter_mut().next() {
+        let delta = time.delta_seconds();
+        // Pan along the board rather than along the view direction
+        let forward = transform.forward();
+        let forward = if forward.x.abs() + forward.z.abs() < f32::EPSILON {
+            // Looking straight down, so the top of the screen is forward
+            transform.rotation * Vec3::unit_y()
+        } else {
+            forward
+        };
+        let forward = Vec3::new(forward.x, 0.0, forward.z).normalize();
+        let right = Vec3::new(-forward.z, 0.0, forward.x);
+        let mut pan = Vec3::zero();
+        if settings.pressed(&keyboard_input, Binding::PanForward) {
+            pan += forward;
+        }
+        if settings.pressed(&keyboard_input, Binding::PanBack) {
+            pan -= forward;
+        }
+        if settings.pressed(&keyboard_input, Binding::PanRight) {
+            pan += right;
+        }
+        if settings.pressed(&keyboard_input, Binding::PanLeft) {
+            pan -= right;
+        }
+        transform.translation += pan * SPECTATOR_PAN_SPEED * settings.camera_sensitivity * delta;
+
+        let mut turn = 0.0;
+        if settings.pressed(&keyboard_input, Binding::TurnLeft) {
+            turn += 1.0;
+        }
+        if settings.pressed(&keyboard_input, Binding::TurnRight) {
+            turn -= 1.0;
+        }
+        transform.rotate(Quat::from_rotation_y(
+            turn * SPECTATOR_TURN_SPEED * settings.camera_sensitivity * delta,
+        ));
+
+        let step = transform.forward() * zoom * SPECTATOR_ZOOM_STEP;
+        transform.translation += step;
+    }
+}
+
 pub fn camera_system(
     commands: &mut Commands,
     data: Res<Data>,
     windows: Res<Windows>,
     mouse_input: Res<Input<MouseButton>>,
-    keyboard_input: Res<Input<KeyCode>>,
+    (keyboard_input, settings): (Res<Input<KeyCode>>, Res<Settings>),
     cameras: Query<(&Camera, &Transform), Without<OrthographicProjection>>,
-    camera: Query<Entity, (With<Camera>, Without<Lerp>, Without<OrthographicProjection>)>,
+    camera: Query<Entity, (With<Camera>, Without<OrthographicProjection>)>,
     colliders: Query<(Entity, &Collider, &Transform, &CameraNode)>,
 ) {
-    if mouse_input.just_pressed(MouseButton::Left) {
-        if let Some(camera) = camera.iter().next() {
-            if let Some(RayCastResult {
-                intersection: _,
-                entity: _,
-                component: &cam_node,
-            }) = closest(&windows, &cameras, &colliders)
-            {
-                commands.insert_one(
-                    camera,
-                    Lerp::new(
-                        LerpType::Camera {
-                            src: None,
-                            dest: cam_node,
-                        },
-                        1.0,
-                        0.0,
-                    ),
+    let dest = if mouse_input.just_pressed(MouseButton::Left) {
+        closest(&windows, &cameras, &colliders).map(
+            |RayCastResult {
+                 component: &cam_node,
+                 ..
+             }| cam_node,
+        )
+    } else if settings.just_pressed(&keyboard_input, Binding::LeaveView) {
+        Some(data.camera_nodes.main)
+    } else {
+        Binding::VIEWS
+            .iter()
+            .position(|&binding| settings.just_pressed(&keyboard_input, binding))
+            .map(|i| data.camera_nodes.views()[i])
+    };
+    // Replacing a lerp that is still running restarts it from wherever the camera is now
+    if let (Some(dest), Some(camera)) = (dest, camera.iter().next()) {
+        commands.insert_one(camera, Lerp::move_camera(dest, CAMERA_TIME));
+    }
+}
+
+/// The point the orbit camera circles, which starts at the middle of the board
+#[derive(Default)]
+struct OrbitFocus(Vec3);
+
+/// Right-drag orbits around the board, middle-drag pans across it and scrolling zooms in and out.
+/// Home snaps back to the main view. Clicking a camera node still flies to it as before.
+fn orbit_camera_system(
+    commands: &mut Commands,
+    (data, network, chat): (Res<Data>, Res<Network>, Res<Chat>),
+    (mouse_input, keyboard_input, settings): (
+        Res<Input<MouseButton>>,
+        Res<Input<KeyCode>>,
+        Res<Settings>,
+    ),
+    (motion_events, wheel_events): (Res<Events<MouseMotion>>, Res<Events<MouseWheel>>),
+    (mut motion_reader, mut wheel_reader): (
+        Local<EventReader<MouseMotion>>,
+        Local<EventReader<MouseWheel>>,
+    ),
+    (mut focus, cameras): (
+        Local<OrbitFocus>,
+        Query<Entity, With<PerspectiveProjection>>,
+    ),
+    mut camera: Query<&mut Transform, (With<PerspectiveProjection>, Without<Lerp>)>,
+) {
+    let motion = motion_reader
+        .iter(&motion_events)
+        .fold(Vec2::zero(), |total, event| total + event.delta);
+    let zoom = wheel_reader
+        .iter(&wheel_events)
+        .map(|event| event.y)
+        .sum::<f32>();
+    // Spectators already have their own free camera
+    if spectating(&network) {
+        return;
+    }
+    if settings.just_pressed(&keyboard_input, Binding::ResetCamera) && !chat.open {
+        if let Some(entity) = cameras.iter().next() {
+            focus.0 = Vec3::zero();
+            commands.insert_one(
+                entity,
+                Lerp::move_camera(data.camera_nodes.main, CAMERA_TIME),
+            );
+        }
+        return;
+    }
+    let mut transform = if let Some(transform) = camera.iter_mut().next() {
+        transform
+    } else {
+        return;
+    };
+
+    let offset = transform.translation - focus.0;
+    if mouse_input.pressed(MouseButton::Right) && motion != Vec2::zero() {
+        let right = transform.rotation * Vec3::unit_x();
+        let speed = ORBIT_SPEED * settings.camera_sensitivity;
+        let yaw = Quat::from_rotation_y(-motion.x * speed);
+        let pitch = Quat::from_axis_angle(right, -motion.y * speed);
+        // Drop the tilt rather than the whole drag when it would take us under the board
+        let rotation = if (yaw * pitch * offset).normalize().y < ORBIT_MIN_ELEVATION {
+            yaw
+        } else {
+            yaw * pitch
+        };
+        transform.translation = focus.0 + rotation * offset;
+        transform.rotation = rotation * transform.rotation;
+    } else if mouse_input.pressed(MouseButton::Middle) && motion != Vec2::zero() {
+        let right = transform.rotation * Vec3::unit_x();
+        let right = Vec3::new(right.x, 0.0, right.z).normalize();
+        let forward = Vec3::new(right.z, 0.0, -right.x);
+        // Drag the board along with the cursor, further when zoomed out
+        let pan = (forward * motion.y - right * motion.x)
+            * ORBIT_PAN_SPEED
+            * settings.camera_sensitivity
+            * offset.length();
+        focus.0 += pan;
+        transform.translation += pan;
+    }
+
+    if zoom != 0.0 {
+        let offset = transform.translation - focus.0;
+        let distance =
+            (offset.length() * (1.0 - zoom * ORBIT_ZOOM_STEP)).clamp(ORBIT_NEAR, ORBIT_FAR);
+        transform.translation = focus.0 + offset.normalize() * distance;
+    }
+}
+
+/// Pick up one of your troop or spice tokens with the mouse and carry it across the board. Dropping
+/// it on a territory stages it just like clicking would, anything else sends it back.
+fn drag_system(
+    commands: &mut Commands,
+    (info, network, hot_seat): (Res<Info>, Res<Network>, Res<HotSeat>),
+    (windows, mouse_input, mut staged, menu): (
+        Res<Windows>,
+        Res<Input<MouseButton>>,
+        ResMut<StagedAction>,
+        Res<ContextMenu>,
+    ),
+    cameras: Query<(&Camera, &Transform), Without<OrthographicProjection>>,
+    colliders: Query<(Entity, &Collider, &Transform, &LocationSector)>,
+    players: Query<&Player, Without<Bot>>,
+    mut tokens: Query<DraggableToken, DraggableFilter>,
+) {
+    if spectating(&network) || hot_seat.concealed(&network, info.get_active_player()) {
+        return;
+    }
+    let faction = if let Ok(player) = players.get(info.get_active_player()) {
+        player.faction
+    } else {
+        return;
+    };
+    let carrying = matches!(
+        info.context,
+        Context::PlacingTroops | Context::Shipping | Context::Moving
+    );
+
+    if let Some((entity, origin)) = staged.dragging {
+        if mouse_input.just_released(MouseButton::Left) {
+            staged.dragging = None;
+            let drop = closest(&windows, &cameras, &colliders);
+            let troop = tokens.get_component::<Troop>(entity).ok();
+            // Spice only ever moves through the phases, so it always goes back
+            let staged_drop = match (drop, troop) {
+                (
+                    Some(RayCastResult {
+                        intersection,
+                        entity: location_entity,
+                        component: loc_sec,
+                    }),
+                    Some(troop),
+                ) => {
+                    let node = SectorNode::new(&loc_sec.location.name, loc_sec.sector);
+                    let from = troop
+                        .location
+                        .and_then(|location| colliders.get(location).ok())
+                        .map(|(_, _, _, loc_sec)| {
+                            SectorNode::new(&loc_sec.location.name, loc_sec.sector)
+                        });
+                    let to = Transform::from_translation(intersection + 0.0018 * Vec3::unit_y());
+                    match (info.context, from) {
+                        (Context::PlacingTroops, None) => {
+                            let (_, locations, _) = faction.initial_values();
+                            locations
+                                .map_or(true, |locations| {
+                                    locations.contains(&loc_sec.location.name)
+                                })
+                                .then(|| (to, Some(location_entity), None))
+                        }
+                        (Context::Shipping, None) => Some((
+                            to,
+                            None,
+                            Some(MovementRequest::Ship {
+                                faction,
+                                to: node,
+                                count: 1,
+                            }),
+                        )),
+                        (Context::Moving, Some(from)) if from != node => Some((
+                            to,
+                            None,
+                            Some(MovementRequest::Move {
+                                faction,
+                                from,
+                                to: node,
+                                count: 1,
+                            }),
+                        )),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            if let Some((to, location_entity, request)) = staged_drop {
+                let lerp = Lerp::new(LerpType::world_to(to), 0.1, 0.0);
+                if let Some(location_entity) = location_entity {
+                    staged.placements.push((entity, location_entity, lerp));
+                } else {
+                    // Only one movement can be staged at a time
+                    staged.cancel(commands);
+                    staged.movement = request;
+                }
+                staged.preview(commands, entity, origin, lerp);
+                println!("Press Space to confirm or Delete to undo");
+            } else {
+                commands.insert_one(entity, Lerp::new(LerpType::world_to(origin), 0.3, 0.0));
+            }
+        } else if let Some(pos) = cursor_on_plane(&windows, &cameras, DRAG_HEIGHT) {
+            if let Ok(mut transform) = tokens.get_component_mut::<Transform>(entity) {
+                transform.translation = Vec3::new(
+                    pos.x.clamp(-BOARD_HALF_WIDTH, BOARD_HALF_WIDTH),
+                    DRAG_HEIGHT,
+                    pos.z.clamp(-BOARD_HALF_DEPTH, BOARD_HALF_DEPTH),
                 );
             }
         }
-    } else if keyboard_input.just_pressed(KeyCode::Escape) {
-        if let Some(camera) = camera.iter().next() {
-            commands.insert_one(
-                camera,
-                Lerp::new(
-                    LerpType::Camera {
-                        src: None,
-                        dest: data.camera_nodes.main,
-                    },
-                    1.0,
-                    0.0,
-                ),
-            );
+    } else if mouse_input.just_pressed(MouseButton::Left) && carrying && !menu.blocks_click() {
+        let grabbed = grab(
+            &windows,
+            &cameras,
+            tokens
+                .iter_mut()
+                .filter(|(entity, _, _, unique, _)| {
+                    unique.faction == faction && !staged.is_staged(*entity)
+                })
+                .map(|(entity, collider, transform, _, _)| (entity, collider, *transform)),
+        );
+        if let Some(entity) = grabbed {
+            if let Ok(&origin) = tokens.get_component::<Transform>(entity) {
+                commands.remove_one::<Lerp>(entity);
+                staged.dragging = Some((entity, origin));
+            }
         }
     }
 }
@@ -116,18 +589,35 @@ pub fn camera_system(
 fn sector_context_system(
     commands: &mut Commands,
     mut info: ResMut<Info>,
-    mut queue: ResMut<ActionQueue>,
-    windows: Res<Windows>,
-    mouse_input: Res<Input<MouseButton>>,
+    (mut queue, settings, menu): (ResMut<ActionQueue>, Res<Settings>, Res<ContextMenu>),
+    (windows, network, chat, hot_seat): (Res<Windows>, Res<Network>, Res<Chat>, Res<HotSeat>),
+    (mut staged, mouse_input, keyboard_input): (
+        ResMut<StagedAction>,
+        Res<Input<MouseButton>>,
+        Res<Input<KeyCode>>,
+    ),
     cameras: Query<(&Camera, &Transform), Without<OrthographicProjection>>,
     colliders: Query<(Entity, &Collider, &Transform, &LocationSector)>,
-    players: Query<&Player>,
+    players: Query<&Player, Without<Bot>>,
     mut troops: Query<(Entity, &Collider, &Transform, &mut Troop)>,
     uniques: Query<&Unique>,
 ) {
+    if spectating(&network) || hot_seat.concealed(&network, info.get_active_player()) {
+        return;
+    }
     match info.context {
         Context::PlacingTroops => {
-            if mouse_input.just_pressed(MouseButton::Left) {
+            let active_player = if let Ok(active_player) = players.get(info.get_active_player()) {
+                active_player
+            } else {
+                return;
+            };
+            let (num_troops, locations, _) = active_player.faction.initial_values();
+            // Picking up a token is left to the drag
+            if mouse_input.just_pressed(MouseButton::Left)
+                && staged.dragging.is_none()
+                && !menu.blocks_click()
+            {
                 if let Some(RayCastResult {
                     intersection,
                     entity: location_entity,
@@ -138,158 +628,137 @@ fn sector_context_system(
                     //    "Clicked on {}-{}",
                     //    location_sector.location.name, location_sector.sector
                     //);
-                    match info.context {
-                        Context::PlacingTroops => {
-                            if let Ok(active_player) = players.get(info.get_active_player()) {
-                                //println!("Active player: {:?}", active_player.faction);
-                                let (num_troops, locations, _) =
-                                    active_player.faction.initial_values();
-
-                                let mut place = false;
-                                //println!("Valid Locations: {:?}", locations);
-                                if let Some(locations) = locations {
-                                    if locations
-                                        .iter()
-                                        .find(|name| {
-                                            name.as_str() == location_sector.location.name.as_str()
-                                        })
-                                        .is_some()
-                                    {
-                                        place = true;
-                                    }
-                                } else {
-                                    place = true;
+                    let mut place = false;
+                    //println!("Valid Locations: {:?}", locations);
+                    if let Some(locations) = locations {
+                        if locations
+                            .iter()
+                            .find(|name| name.as_str() == location_sector.location.name.as_str())
+                            .is_some()
+                        {
+                            place = true;
+                        }
+                    } else {
+                        place = true;
+                    }
+                    if place {
+                        if let Some((lerp_entity, _, &troop_transform, _)) = troops
+                            .iter_mut()
+                            .filter(|(entity, _, _, troop)| {
+                                uniques.get(*entity).unwrap().faction == active_player.faction
+                                    && troop.location.is_none()
+                                    && !staged.is_staged(*entity)
+                            })
+                            .max_by(|(_, _, transform1, _), (_, _, transform2, _)| {
+                                transform1
+                                    .translation
+                                    .y
+                                    .partial_cmp(&transform2.translation.y)
+                                    .unwrap()
+                            })
+                        {
+                            let lerp = if let Some(MutRayCastResult {
+                                intersection: _,
+                                entity,
+                                component: _,
+                            }) = closest_mut(&windows, &cameras, &mut troops)
+                            {
+                                let troop_transform =
+                                    troops.get_component::<Transform>(entity).unwrap();
+                                Lerp::new(
+                                    LerpType::world_to(
+                                        *troop_transform
+                                            * Transform::from_translation(0.0036 * Vec3::unit_y()),
+                                    ),
+                                    0.5,
+                                    0.0,
+                                )
+                            } else {
+                                Lerp::new(
+                                    LerpType::world_to(
+                                        Transform::from_translation(intersection)
+                                            * Transform::from_translation(0.0018 * Vec3::unit_y()),
+                                    ),
+                                    0.5,
+                                    0.0,
+                                )
+                            };
+                            staged.preview(commands, lerp_entity, troop_transform, lerp);
+                            staged.placements.push((lerp_entity, location_entity, lerp));
+                            println!("Press Space to confirm or Delete to undo");
+                        }
+                    } else {
+                        println!("Tried to place troop in an invalid location!");
+                    }
+                }
+            } else if settings.just_pressed(&keyboard_input, Binding::Undo) {
+                staged.cancel(commands);
+            } else if settings.just_pressed(&keyboard_input, Binding::Confirm)
+                && !chat.open
+                && !staged.placements.is_empty()
+            {
+                let placements = staged.commit().0;
+                for (troop_entity, location_entity, _) in placements.iter() {
+                    if let Ok(mut troop) = troops.get_component_mut::<Troop>(*troop_entity) {
+                        troop.location = Some(*location_entity);
+                    }
+                }
+                let lerps = placements
+                    .into_iter()
+                    .map(|(troop_entity, _, lerp)| Action::add_lerp(troop_entity, lerp).into())
+                    .collect::<Vec<_>>();
+                let placed_troops = troops
+                    .iter_mut()
+                    .filter(|(entity, _, _, troop)| {
+                        uniques.get(*entity).unwrap().faction == active_player.faction
+                            && troop.location.is_some()
+                    })
+                    .count();
+                //println!(
+                //    "Total troops: {}, placed: {}",
+                //    num_troops, placed_troops
+                //);
+                if placed_troops == num_troops as usize {
+                    if let Some(mut context_action) = queue.pop() {
+                        if context_action.context == info.context {
+                            match context_action.action {
+                                ActionAggregation::Multiple(ref mut actions) => {
+                                    actions.extend(lerps)
                                 }
-                                if place {
-                                    let (lerp_entity, _, _, mut new_troop) = troops
-                                        .iter_mut()
-                                        .filter(|(entity, _, _, troop)| {
-                                            uniques.get(*entity).unwrap().faction
-                                                == active_player.faction
-                                                && troop.location.is_none()
-                                        })
-                                        .max_by(|(_, _, transform1, _), (_, _, transform2, _)| {
-                                            transform1
-                                                .translation
-                                                .y
-                                                .partial_cmp(&transform2.translation.y)
-                                                .unwrap()
-                                        })
-                                        .unwrap();
-                                    new_troop.location = Some(location_entity);
-                                    let lerp = if let Some(MutRayCastResult {
-                                        intersection: _,
-                                        entity,
-                                        component: _,
-                                    }) = closest_mut(&windows, &cameras, &mut troops)
-                                    {
-                                        let troop_transform =
-                                            troops.get_component::<Transform>(entity).unwrap();
-                                        Lerp::new(
-                                            LerpType::world_to(
-                                                *troop_transform
-                                                    * Transform::from_translation(
-                                                        0.0036 * Vec3::unit_y(),
-                                                    ),
-                                            ),
-                                            0.5,
-                                            0.0,
-                                        )
-                                    } else {
-                                        Lerp::new(
-                                            LerpType::world_to(
-                                                Transform::from_translation(intersection)
-                                                    * Transform::from_translation(
-                                                        0.0018 * Vec3::unit_y(),
-                                                    ),
-                                            ),
-                                            0.5,
-                                            0.0,
-                                        )
-                                    };
-                                    let placed_troops = troops
-                                        .iter_mut()
-                                        .filter(|(entity, _, _, troop)| {
-                                            uniques.get(*entity).unwrap().faction
-                                                == active_player.faction
-                                                && troop.location.is_some()
-                                        })
-                                        .count();
-                                    //println!(
-                                    //    "Total troops: {}, placed: {}",
-                                    //    num_troops, placed_troops
-                                    //);
-                                    if placed_troops == num_troops as usize {
-                                        if let Some(mut context_action) = queue.pop() {
-                                            if context_action.context == info.context {
-                                                match context_action.action {
-                                                    ActionAggregation::Multiple(
-                                                        ref mut actions,
-                                                    ) => actions.push(
-                                                        Action::add_lerp(lerp_entity, lerp).into(),
-                                                    ),
-                                                    ActionAggregation::Single(ref action) => {
-                                                        context_action.action = multi![
-                                                            action.clone(),
-                                                            Action::add_lerp(lerp_entity, lerp)
-                                                                .into()
-                                                        ]
-                                                    }
-                                                };
-                                                context_action.context = Context::None;
-                                                queue.push_front(context_action)
-                                            } else {
-                                                queue.push_seq_front(vec![
-                                                    Action::add_lerp(lerp_entity, lerp).into(),
-                                                    context_action,
-                                                ])
-                                            }
-                                        } else {
-                                            queue.push_front(
-                                                info.context.action(
-                                                    Action::add_lerp(lerp_entity, lerp).into(),
-                                                ),
-                                            )
-                                        }
-                                        info.context = Context::None;
-                                        for (e, _, _, _) in colliders.iter() {
-                                            commands.insert_one(e, Disorganized);
-                                        }
-                                    } else {
-                                        if let Some(context_action) = queue.peek_mut() {
-                                            if context_action.context == info.context {
-                                                match context_action.action {
-                                                    ActionAggregation::Multiple(
-                                                        ref mut actions,
-                                                    ) => actions.push(
-                                                        Action::add_lerp(lerp_entity, lerp).into(),
-                                                    ),
-                                                    ActionAggregation::Single(ref action) => {
-                                                        context_action.action = multi![
-                                                            action.clone(),
-                                                            Action::add_lerp(lerp_entity, lerp)
-                                                                .into()
-                                                        ]
-                                                    }
-                                                };
-                                            } else {
-                                                queue.push_front(info.context.action(
-                                                    Action::add_lerp(lerp_entity, lerp).into(),
-                                                ));
-                                            }
-                                        } else {
-                                            queue.push_front(info.context.action(
-                                                Action::add_lerp(lerp_entity, lerp).into(),
-                                            ));
-                                        }
-                                    }
-                                } else {
-                                    println!("Tried to place troop in an invalid location!");
+                                ActionAggregation::Single(ref action) => {
+                                    let mut actions = vec![action.clone()];
+                                    actions.extend(lerps);
+                                    context_action.action = ActionAggregation::Multiple(actions);
                                 }
-                            }
+                            };
+                            context_action.context = Context::None;
+                            queue.push_front(context_action)
+                        } else {
+                            queue.push_front(context_action);
+                            queue.push_multiple_front(lerps);
                         }
-                        _ => (),
+                    } else {
+                        queue.push_front(info.context.actions(lerps))
                     }
+                    info.context = Context::None;
+                    for (e, _, _, _) in colliders.iter() {
+                        commands.insert_one(e, Disorganized);
+                    }
+                } else if let Some(context_action) = queue.peek_mut() {
+                    if context_action.context == info.context {
+                        match context_action.action {
+                            ActionAggregation::Multiple(ref mut actions) => actions.extend(lerps),
+                            ActionAggregation::Single(ref action) => {
+                                let mut actions = vec![action.clone()];
+                                actions.extend(lerps);
+                                context_action.action = ActionAggregation::Multiple(actions);
+                            }
+                        };
+                    } else {
+                        queue.push_front(info.context.actions(lerps));
+                    }
+                } else {
+                    queue.push_front(info.context.actions(lerps));
                 }
             }
         }
@@ -299,139 +768,770 @@ fn sector_context_system(
         Context::PickingTraitors => {}
         Context::Prompting => {}
         Context::StackResolving => {}
+        Context::Bidding => {}
+        Context::Reviving => {}
+        Context::Shipping => {}
+        Context::Moving => {}
     }
 }
 
 fn prediction_context_system(
-    mut info: ResMut<Info>,
-    data: Res<Data>,
-    mut queue: ResMut<ActionQueue>,
-    windows: Res<Windows>,
+    info: Res<Info>,
+    mut actions: ResMut<GameActions>,
+    (windows, network, hot_seat): (Res<Windows>, Res<Network>, Res<HotSeat>),
     mouse_input: Res<Input<MouseButton>>,
     cameras: Query<(&Camera, &Transform), Without<OrthographicProjection>>,
     colliders: QuerySet<(
         Query<(Entity, &Collider, &Transform, &FactionPredictionCard)>,
         Query<(Entity, &Collider, &Transform, &TurnPredictionCard)>,
     )>,
-    mut predictions: Query<&mut Prediction>,
+    mut client: Query<&mut Client>,
 ) {
-    if info.context == Context::Predicting {
-        if mouse_input.just_pressed(MouseButton::Left) {
-            if let Some(RayCastResult {
-                intersection: _,
-                entity: element,
-                component: faction_card,
-            }) = closest(&windows, &cameras, colliders.q0())
-            {
-                if let Some(mut player_prediction) = predictions.iter_mut().next() {
-                    player_prediction.faction = Some(faction_card.faction);
-                }
-                let num_factions = info.factions_in_play.len();
-                let animation_time = 1.5;
-                let delay = animation_time / (2.0 * num_factions as f32);
-                let indiv_anim_time = animation_time - (delay * (num_factions - 1) as f32);
-                // Animate selected card
-                let chosen_action = Action::add_lerp(
-                    element,
-                    Lerp::new(
-                        LerpType::ui_to(
-                            (
-                                data.prediction_nodes.chosen_faction,
-                                Quat::from_rotation_x(0.5 * PI),
-                            )
-                                .into(),
-                        ),
-                        1.0,
-                        0.0,
-                    ),
-                )
-                .into();
+    if info.context != Context::Predicting
+        || spectating(&network)
+        || hot_seat.concealed(&network, info.get_active_player())
+        || !mouse_input.just_pressed(MouseButton::Left)
+    {
+        return;
+    }
+    let pick = if let Some(RayCastResult {
+        component: faction_card,
+        ..
+    }) = closest(&windows, &cameras, colliders.q0())
+    {
+        PredictionPick::Faction {
+            faction: faction_card.faction,
+        }
+    } else if let Some(RayCastResult {
+        component: turn_card,
+        ..
+    }) = closest(&windows, &cameras, colliders.q1())
+    {
+        PredictionPick::Turn {
+            turn: turn_card.turn,
+        }
+    } else {
+        return;
+    };
+    // Only the host hears about it, so nobody else can peek
+    submit(
+        &network,
+        &mut client,
+        &mut actions,
+        GameAction::Prediction { pick },
+    );
+}
 
-                // Animate out faction cards
-                let mut out_actions = colliders
-                    .q0()
-                    .iter()
-                    .filter(|(_, _, _, card)| card.faction != faction_card.faction)
-                    .enumerate()
-                    .map(|(i, (element, _, _, _))| {
-                        Action::add_lerp(
-                            element,
-                            Lerp::new(
-                                LerpType::ui_to(
-                                    (
-                                        data.prediction_nodes.src,
-                                        Quat::from_rotation_x(0.5 * PI) * Quat::from_rotation_z(PI),
-                                    )
-                                        .into(),
-                                ),
-                                indiv_anim_time,
-                                1.0 + (delay * i as f32),
-                            ),
-                        )
-                        .into()
-                    })
-                    .collect::<Vec<_>>();
-                out_actions.push(chosen_action);
-                queue.push_multiple_front(out_actions);
-                info.context = Context::None;
+fn bidding_context_system(
+    info: Res<Info>,
+    (network, hot_seat): (Res<Network>, Res<HotSeat>),
+    (auction, mut actions): (Res<Auction>, ResMut<GameActions>),
+    (keyboard_input, chat, settings): (Res<Input<KeyCode>>, Res<Chat>, Res<Settings>),
+    mut bid: Local<i32>,
+    players: Query<&Player, Without<Bot>>,
+    mut client: Query<&mut Client>,
+) {
+    if info.context == Context::Bidding
+        && !chat.open
+        && !spectating(&network)
+        && !hot_seat.concealed(&network, info.get_active_player())
+    {
+        let high = auction.high_bid.map(|(_, amount)| amount).unwrap_or(0);
+        if *bid <= high {
+            *bid = high + 1;
+        }
+        if settings.just_pressed(&keyboard_input, Binding::Increase) {
+            *bid += 1;
+            println!("Bid: {}", *bid);
+        } else if settings.just_pressed(&keyboard_input, Binding::Decrease) {
+            *bid = (*bid - 1).max(high + 1);
+            println!("Bid: {}", *bid);
+        }
+        let amount = if settings.just_pressed(&keyboard_input, Binding::Submit) {
+            Some(Some(*bid))
+        } else if settings.just_pressed(&keyboard_input, Binding::Pass) {
+            Some(None)
+        } else {
+            None
+        };
+        if let Some(amount) = amount {
+            if let Ok(player) = players.get(info.get_active_player()) {
+                submit(
+                    &network,
+                    &mut client,
+                    &mut actions,
+                    GameAction::Bid {
+                        faction: player.faction,
+                        amount,
+                    },
+                );
             }
-            if let Some(RayCastResult {
-                intersection: _,
-                entity: element,
-                component: turn_card,
-            }) = closest(&windows, &cameras, &colliders.q1())
+        }
+    }
+}
+
+#[derive(Default)]
+struct RevivalInput {
+    troops: i32,
+    leader: Option<usize>,
+}
+
+fn revival_context_system(
+    (info, network, hot_seat): (Res<Info>, Res<Network>, Res<HotSeat>),
+    mut actions: ResMut<GameActions>,
+    (keyboard_input, chat, settings): (Res<Input<KeyCode>>, Res<Chat>, Res<Settings>),
+    mut input: Local<RevivalInput>,
+    players: Query<&Player, Without<Bot>>,
+    leaders: Query<(&Leader, &Unique), With<Dead>>,
+    mut client: Query<&mut Client>,
+) {
+    if info.context != Context::Reviving {
+        input.leader = None;
+        return;
+    }
+    if chat.open || spectating(&network) || hot_seat.concealed(&network, info.get_active_player()) {
+        return;
+    }
+    let faction = if let Ok(player) = players.get(info.get_active_player()) {
+        player.faction
+    } else {
+        return;
+    };
+    if settings.just_pressed(&keyboard_input, Binding::Increase) {
+        input.troops = (input.troops + 1).min(faction.revival_limit());
+        println!(
+            "Revive {} troops for {} spice",
+            input.troops,
+            faction.revival_cost(input.troops)
+        );
+    } else if settings.just_pressed(&keyboard_input, Binding::Decrease) {
+        input.troops = (input.troops - 1).max(0);
+        println!(
+            "Revive {} troops for {} spice",
+            input.troops,
+            faction.revival_cost(input.troops)
+        );
+    }
+    let dead_leaders = leaders
+        .iter()
+        .filter(|(_, unique)| unique.faction == faction)
+        .map(|(leader, _)| leader)
+        .collect::<Vec<_>>();
+    if settings.just_pressed(&keyboard_input, Binding::CycleLeader) {
+        // Cycle through the dead leaders, then back to none
+        input.leader = match input.leader {
+            None if !dead_leaders.is_empty() => Some(0),
+            Some(i) if i + 1 < dead_leaders.len() => Some(i + 1),
+            _ => None,
+        };
+        match input.leader {
+            Some(i) => println!(
+                "Revive {} for {} spice",
+                dead_leaders[i].name, dead_leaders[i].power
+            ),
+            None => println!("Revive no leader"),
+        }
+    }
+
+    let request = if settings.just_pressed(&keyboard_input, Binding::Submit) {
+        Some(RevivalRequest {
+            faction,
+            troops: input.troops,
+            leader: input
+                .leader
+                .and_then(|i| dead_leaders.get(i))
+                .map(|leader| leader.name.clone()),
+        })
+    } else if settings.just_pressed(&keyboard_input, Binding::Pass) {
+        Some(RevivalRequest {
+            faction,
+            troops: 0,
+            leader: None,
+        })
+    } else {
+        None
+    };
+    if let Some(request) = request {
+        *input = RevivalInput::default();
+        submit(
+            &network,
+            &mut client,
+            &mut actions,
+            GameAction::Revival { request },
+        );
+    }
+}
+
+fn traitor_context_system(
+    (info, network, chat): (Res<Info>, Res<Network>, Res<Chat>),
+    (mut actions, hot_seat): (ResMut<GameActions>, Res<HotSeat>),
+    (keyboard_input, settings): (Res<Input<KeyCode>>, Res<Settings>),
+    mut prompted: Local<Option<Entity>>,
+    players: Query<&Player, Without<Bot>>,
+    traitor_cards: Query<&TraitorCard>,
+    mut client: Query<&mut Client>,
+) {
+    if info.context != Context::PickingTraitors {
+        *prompted = None;
+        return;
+    }
+    let active = info.get_active_player();
+    // Nobody else at a hot-seat table gets to see the options
+    if chat.open || spectating(&network) || hot_seat.concealed(&network, active) {
+        return;
+    }
+    let player = if let Ok(player) = players.get(active) {
+        player
+    } else {
+        return;
+    };
+    let leaders = player
+        .traitor_cards
+        .iter()
+        .filter_map(|&card| traitor_cards.get(card).ok())
+        .map(|card| &card.leader)
+        .collect::<Vec<_>>();
+    if *prompted != Some(active) {
+        *prompted = Some(active);
+        println!("{}, pick a traitor:", player.faction);
+        for (i, leader) in leaders.iter().enumerate() {
+            println!(
+                "  {}: {} ({}, {})",
+                i + 1,
+                leader.name,
+                leader.faction,
+                leader.power
+            );
+        }
+    }
+    if let Some(leader) = Binding::TRAITORS
+        .iter()
+        .position(|&binding| settings.just_pressed(&keyboard_input, binding))
+        .and_then(|i| leaders.get(i))
+    {
+        let pick = TraitorPick {
+            faction: player.faction,
+            leader: leader.name.clone(),
+        };
+        submit(
+            &network,
+            &mut client,
+            &mut actions,
+            GameAction::Traitor { pick },
+        );
+    }
+}
+
+/// How many troops the next shipment or move takes, and where a move starts once picked
+#[derive(Default)]
+struct MovementInput {
+    count: i32,
+    from: Option<SectorNode>,
+}
+
+fn movement_context_system(
+    commands: &mut Commands,
+    (info, network, chat, settings): (Res<Info>, Res<Network>, Res<Chat>, Res<Settings>),
+    (mut actions, hot_seat): (ResMut<GameActions>, Res<HotSeat>),
+    (windows, mouse_input, keyboard_input): (
+        Res<Windows>,
+        Res<Input<MouseButton>>,
+        Res<Input<KeyCode>>,
+    ),
+    (mut input, mut staged, mut menu): (
+        ResMut<MovementInput>,
+        ResMut<StagedAction>,
+        ResMut<ContextMenu>,
+    ),
+    cameras: Query<(&Camera, &Transform), Without<OrthographicProjection>>,
+    colliders: Query<(Entity, &Collider, &Transform, &LocationSector)>,
+    players: Query<&Player, Without<Bot>>,
+    troops: Query<(Entity, &Transform, &Troop, &Unique), Without<Dead>>,
+    mut client: Query<&mut Client>,
+) {
+    if info.context != Context::Shipping && info.context != Context::Moving {
+        input.from = None;
+        return;
+    }
+    if chat.open || spectating(&network) || hot_seat.concealed(&network, info.get_active_player()) {
+        return;
+    }
+    let faction = if let Ok(player) = players.get(info.get_active_player()) {
+        player.faction
+    } else {
+        return;
+    };
+    if input.count < 1 {
+        input.count = 1;
+    }
+    if settings.just_pressed(&keyboard_input, Binding::Increase) {
+        input.count += 1;
+        println!("Troops: {}", input.count);
+    } else if settings.just_pressed(&keyboard_input, Binding::Decrease) {
+        input.count = (input.count - 1).max(1);
+        println!("Troops: {}", input.count);
+    }
+
+    let mut request = None;
+    if settings.just_pressed(&keyboard_input, Binding::Pass) {
+        input.from = None;
+        staged.cancel(commands);
+        request = Some(MovementRequest::Pass { faction });
+    } else if settings.just_pressed(&keyboard_input, Binding::Undo) {
+        input.from = None;
+        staged.cancel(commands);
+    } else if settings.just_pressed(&keyboard_input, Binding::Confirm) {
+        // The preview is put back so the confirmed move can play out from where the troops were
+        request = staged.movement.take();
+        staged.cancel(commands);
+    } else {
+        // The menu picks a territory just like clicking on it does
+        let picked = [
+            MenuEntry::ShipHere,
+            MenuEntry::MoveFrom,
+            MenuEntry::MoveHere,
+        ]
+        .iter()
+        .find_map(|&entry| menu.take(entry))
+        .and_then(|target| match target {
+            MenuTarget::Territory { sector, at } => colliders
+                .get(sector)
+                .ok()
+                .map(|(_, _, _, loc_sec)| (at, loc_sec)),
+            MenuTarget::Card(_) => None,
+        });
+        let clicked = || {
+            if mouse_input.just_pressed(MouseButton::Left)
+                && staged.dragging.is_none()
+                && !menu.blocks_click()
             {
-                if let Some(mut player_prediction) = predictions.iter_mut().next() {
-                    player_prediction.turn = Some(turn_card.turn);
-                }
-                let animation_time = 1.5;
-                let delay = animation_time / 30.0;
-                let indiv_anim_time = animation_time - (delay * 14.0);
-                // Animate selected card
-                let chosen_action = Action::add_lerp(
-                    element,
-                    Lerp::new(
-                        LerpType::ui_to(
-                            (
-                                data.prediction_nodes.chosen_turn,
-                                Quat::from_rotation_x(0.5 * PI),
-                            )
-                                .into(),
-                        ),
-                        1.0,
-                        0.0,
-                    ),
-                )
-                .into();
-                // Animate out turn cards
-                let mut out_actions = colliders
-                    .q1()
+                closest(&windows, &cameras, &colliders).map(|hit| (hit.intersection, hit.component))
+            } else {
+                None
+            }
+        };
+        if let Some((intersection, loc_sec)) = picked.or_else(clicked) {
+            let node = SectorNode::new(&loc_sec.location.name, loc_sec.sector);
+            let staged_request = if info.context == Context::Shipping {
+                Some(MovementRequest::Ship {
+                    faction,
+                    to: node,
+                    count: input.count,
+                })
+            } else if let Some(from) = input.from.take() {
+                Some(MovementRequest::Move {
+                    faction,
+                    from,
+                    to: node,
+                    count: input.count,
+                })
+            } else {
+                println!("Moving from {} ({})", node.location, node.sector);
+                input.from = Some(node);
+                None
+            };
+            if let Some(staged_request) = staged_request {
+                staged.cancel(commands);
+                let source = if let MovementRequest::Move { ref from, .. } = staged_request {
+                    Some(from.clone())
+                } else {
+                    None
+                };
+                let mut group = troops
                     .iter()
-                    .filter(|(_, _, _, card)| card.turn != turn_card.turn)
-                    .enumerate()
-                    .map(|(i, (element, _, _, _))| {
-                        Action::add_lerp(
-                            element,
-                            Lerp::new(
-                                LerpType::ui_to(
-                                    (
-                                        data.prediction_nodes.src,
-                                        Quat::from_rotation_x(0.5 * PI) * Quat::from_rotation_z(PI),
-                                        0.6,
-                                    )
-                                        .into(),
-                                ),
-                                indiv_anim_time,
-                                1.0 + (delay * i as f32),
-                            ),
-                        )
-                        .into()
+                    .filter(|(_, _, troop, unique)| {
+                        unique.faction == faction
+                            && troop
+                                .location
+                                .and_then(|location| colliders.get(location).ok())
+                                .map(|(_, _, _, loc_sec)| {
+                                    SectorNode::new(&loc_sec.location.name, loc_sec.sector)
+                                })
+                                == source
                     })
                     .collect::<Vec<_>>();
-                out_actions.push(chosen_action);
-                queue.push_multiple_front(out_actions);
-                info.context = Context::None;
+                group.sort_by(|(_, transform1, _, _), (_, transform2, _, _)| {
+                    transform2
+                        .translation
+                        .y
+                        .partial_cmp(&transform1.translation.y)
+                        .unwrap()
+                });
+                for (i, (entity, &transform, _, _)) in
+                    group.into_iter().take(input.count as usize).enumerate()
+                {
+                    let lerp = Lerp::new(
+                        LerpType::world_to(Transform::from_translation(
+                            intersection + (i + 1) as f32 * 0.0018 * Vec3::unit_y(),
+                        )),
+                        0.5,
+                        0.0,
+                    );
+                    staged.preview(commands, entity, transform, lerp);
+                }
+                staged.movement = Some(staged_request);
+                println!("Press Space to confirm or Delete to undo");
             }
         }
     }
+
+    if let Some(request) = request {
+        submit(
+            &network,
+            &mut client,
+            &mut actions,
+            GameAction::Movement { request },
+        );
+    }
+}
+
+/// Something right clicked: a card in the hand, or a territory, either directly or through one of
+/// the troops standing on it
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MenuTarget {
+    Card(Entity),
+    Territory { sector: Entity, at: Vec3 },
+}
+
+/// One thing the context menu can offer
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MenuEntry {
+    Inspect,
+    Ping,
+    ShipHere,
+    MoveFrom,
+    MoveHere,
+    ViewOccupants,
+}
+
+impl MenuEntry {
+    fn key(self) -> &'static str {
+        match self {
+            MenuEntry::Inspect => "context_menu.inspect",
+            MenuEntry::Ping => "context_menu.ping",
+            MenuEntry::ShipHere => "context_menu.ship_here",
+            MenuEntry::MoveFrom => "context_menu.move_from",
+            MenuEntry::MoveHere => "context_menu.move_here",
+            MenuEntry::ViewOccupants => "context_menu.occupants",
+        }
+    }
+}
+
+/// The menu a right click opens, and the entry picked from it until whichever system carries it
+/// out takes it
+#[derive(Default)]
+pub struct ContextMenu {
+    target: Option<MenuTarget>,
+    panel: Option<Entity>,
+    chosen: Option<(MenuEntry, MenuTarget)>,
+    /// Where the right click started, to tell it apart from dragging the camera around
+    pressed: Option<Vec2>,
+    /// Set on the frame the menu closes, so the click that closed it goes no further
+    closing: bool,
+}
+
+impl ContextMenu {
+    /// Whether clicks on the board belong to the menu rather than the game
+    pub fn blocks_click(&self) -> bool {
+        self.panel.is_some() || self.closing
+    }
+
+    /// The target of `entry`, if that is what was picked
+    pub fn take(&mut self, entry: MenuEntry) -> Option<MenuTarget> {
+        match self.chosen {
+            Some((chosen, target)) if chosen == entry => {
+                self.chosen = None;
+                Some(target)
+            }
+            _ => None,
+        }
+    }
+
+    fn close(&mut self, commands: &mut Commands) {
+        if let Some(panel) = self.panel.take() {
+            commands.despawn_recursive(panel);
+            self.closing = true;
+        }
+        self.target = None;
+    }
+}
+
+struct ContextMenuButton(MenuEntry);
+
+struct OccupantsText;
+
+/// What can be done with `target` right now. Moves go through the same check the host puts
+/// every action through, so only what it would accept is offered.
+fn legal_entries(
+    target: MenuTarget,
+    faction: Option<Faction>,
+    table: &TableState,
+    (moving_from, own_troops): (bool, bool),
+) -> Vec<MenuEntry> {
+    let territory = match target {
+        MenuTarget::Card(_) => return vec![MenuEntry::Inspect],
+        MenuTarget::Territory { .. } => vec![MenuEntry::ViewOccupants, MenuEntry::Ping],
+    };
+    let movement = faction.filter(|&faction| {
+        let action = GameAction::Movement {
+            request: MovementRequest::Pass { faction },
+        };
+        check_action(faction, action.requirement(), table).is_ok()
+    });
+    let moves = match (movement, table.context) {
+        (None, _) => None,
+        (Some(_), Context::Shipping) => Some(MenuEntry::ShipHere),
+        (Some(_), Context::Moving) if moving_from => Some(MenuEntry::MoveHere),
+        (Some(_), Context::Moving) if own_troops => Some(MenuEntry::MoveFrom),
+        _ => None,
+    };
+    moves.into_iter().chain(territory).collect()
+}
+
+type Territories<'a, 'b, 'c, 'd> =
+    Query<'a, (Entity, &'b Collider, &'c Transform, &'d LocationSector)>;
+type Tokens<'a, 'b, 'c, 'd> = Query<'a, (Entity, &'b Collider, &'c Transform, &'d Troop)>;
+type Cameras<'a, 'b, 'c> = Query<'a, (&'b Camera, &'c Transform), Without<OrthographicProjection>>;
+type Standing<'a, 'b, 'c> = Query<'a, (&'b Troop, &'c Unique), Without<Dead>>;
+type MenuButtons<'a, 'b, 'c, 'd> = Query<
+    'a,
+    (
+        &'b Interaction,
+        &'c ContextMenuButton,
+        &'d mut Handle<ColorMaterial>,
+    ),
+    Mutated<Interaction>,
+>;
+
+/// A right click, without dragging the camera, opens a menu of what can be done with whatever is
+/// under the cursor
+fn open_context_menu_system(
+    commands: &mut Commands,
+    (mut menu, movement, hot_seat, phase): (
+        ResMut<ContextMenu>,
+        Res<MovementInput>,
+        Res<HotSeat>,
+        Res<GamePhase>,
+    ),
+    (info, negotiation, network, lobby): (Res<Info>, Res<Negotiation>, Res<Network>, Res<Lobby>),
+    (windows, mouse_input, hovered_card, chat): (
+        Res<Windows>,
+        Res<Input<MouseButton>>,
+        Res<HoveredCard>,
+        Res<Chat>,
+    ),
+    (asset_server, materials, button_materials, tr): (
+        Res<AssetServer>,
+        Res<HudMaterials>,
+        Res<ButtonMaterials>,
+        Res<Tr>,
+    ),
+    (cameras, territories, tokens): (Cameras, Territories, Tokens),
+    (players, troops, client): (Query<&Player>, Standing, Query<&Client>),
+) {
+    menu.closing = false;
+    if chat.open {
+        menu.pressed = None;
+        return;
+    }
+    let cursor = if let Some(cursor) = windows
+        .get_primary()
+        .and_then(|window| window.cursor_position())
+    {
+        cursor
+    } else {
+        return;
+    };
+    if mouse_input.just_pressed(MouseButton::Right) {
+        menu.pressed = Some(cursor);
+        return;
+    }
+    if !mouse_input.just_released(MouseButton::Right) {
+        return;
+    }
+    match menu.pressed.take() {
+        Some(start) if start.distance(cursor) <= CLICK_SLOP => (),
+        _ => return,
+    }
+    let target = if let Some(card) = hovered_card.entity {
+        Some(MenuTarget::Card(card))
+    } else if let Some(hit) = closest(&windows, &cameras, &tokens) {
+        hit.component.location.map(|sector| MenuTarget::Territory {
+            sector,
+            at: hit.intersection,
+        })
+    } else {
+        closest(&windows, &cameras, &territories).map(|hit| MenuTarget::Territory {
+            sector: hit.entity,
+            at: hit.intersection,
+        })
+    };
+    menu.close(commands);
+    let target = if let Some(target) = target {
+        target
+    } else {
+        return;
+    };
+
+    // Hidden hands and spectators can look, but the menu won't move anything for them
+    let faction = own_faction(&network, &lobby, &info, &players, &client).filter(|_| {
+        !spectating(&network)
+            && (info.play_order.is_empty()
+                || !hot_seat.concealed(&network, info.get_active_player()))
+    });
+    let active = if info.play_order.is_empty() {
+        None
+    } else {
+        players
+            .get(info.get_active_player())
+            .ok()
+            .map(|player| player.faction)
+    };
+    let own_troops = match (target, faction) {
+        (MenuTarget::Territory { sector, .. }, Some(faction)) => troops
+            .iter()
+            .any(|(troop, unique)| unique.faction == faction && troop.location == Some(sector)),
+        _ => false,
+    };
+    let entries = legal_entries(
+        target,
+        faction,
+        &TableState {
+            factions_in_play: &info.factions_in_play,
+            phase: phase.phase,
+            context: info.context,
+            active,
+            negotiating: negotiation.open,
+        },
+        (movement.from.is_some(), own_troops),
+    );
+
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let text = |value: String| TextBundle {
+        text: Text {
+            font: font.clone(),
+            value,
+            style: TextStyle {
+                font_size: 16.0,
+                color: Color::ANTIQUE_WHITE,
+                ..Default::default()
+            },
+        },
+        ..Default::default()
+    };
+    menu.panel = commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(cursor.x),
+                    bottom: Val::Px(cursor.y),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(4.0)),
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with_children(|parent| {
+            for &entry in entries.iter() {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            margin: Rect::all(Val::Px(2.0)),
+                            padding: Rect::all(Val::Px(4.0)),
+                            ..Default::default()
+                        },
+                        material: button_materials.normal.clone(),
+                        ..Default::default()
+                    })
+                    .with(ContextMenuButton(entry))
+                    .with_children(|parent| {
+                        parent.spawn(text(tr.get(entry.key())));
+                    });
+            }
+            parent.spawn(text(String::new())).with(OccupantsText);
+        })
+        .current_entity();
+    menu.target = Some(target);
+}
+
+/// Picking an entry hands it over to whatever carries it out and closes the menu, except for
+/// looking at who is in a territory, which the menu shows itself. A click anywhere else closes it.
+fn context_menu_button_system(
+    commands: &mut Commands,
+    (mut menu, mouse_input, button_materials, tr): (
+        ResMut<ContextMenu>,
+        Res<Input<MouseButton>>,
+        Res<ButtonMaterials>,
+        Res<Tr>,
+    ),
+    mut buttons: MenuButtons,
+    (sectors, troops): (Query<&LocationSector>, Standing),
+    mut texts: Query<&mut Text, With<OccupantsText>>,
+) {
+    let target = if let Some(target) = menu.target {
+        target
+    } else {
+        return;
+    };
+    let mut picked = None;
+    for (interaction, button, mut material) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {
+                *material = button_materials.pressed.clone();
+                picked = Some(button.0);
+            }
+            Interaction::Hovered => *material = button_materials.hovered.clone(),
+            Interaction::None => *material = button_materials.normal.clone(),
+        }
+    }
+    match (picked, target) {
+        (Some(MenuEntry::ViewOccupants), MenuTarget::Territory { sector, .. }) => {
+            let name = if let Ok(loc_sec) = sectors.get(sector) {
+                &loc_sec.location.name
+            } else {
+                return;
+            };
+            let mut counts = HashMap::new();
+            for (_, unique) in troops.iter().filter(|(troop, _)| {
+                troop
+                    .location
+                    .and_then(|location| sectors.get(location).ok())
+                    .map_or(false, |loc_sec| &loc_sec.location.name == name)
+            }) {
+                *counts.entry(unique.faction).or_insert(0) += 1;
+            }
+            let mut counts = counts.into_iter().collect::<Vec<_>>();
+            counts.sort_by_key(|(faction, _)| faction.index());
+            let mut lines = vec![tr.fmt("context_menu.territory", &[name])];
+            if counts.is_empty() {
+                lines.push(tr.get("context_menu.empty"));
+            }
+            lines.extend(counts.into_iter().map(|(faction, count)| {
+                tr.fmt("context_menu.occupant", &[&tr.faction(faction), &count])
+            }));
+            for mut text in texts.iter_mut() {
+                text.value = lines.join("\n");
+            }
+        }
+        (Some(entry), _) => {
+            menu.chosen = Some((entry, target));
+            menu.close(commands);
+        }
+        (None, _) => {
+            if mouse_input.just_pressed(MouseButton::Left) {
+                menu.close(commands);
+            }
+        }
+    }
+}
+
+fn reset(mut menu: ResMut<ContextMenu>) {
+    *menu = ContextMenu::default();
 }
@@ -0,0 +1,193 @@
+use std::f32::consts::PI;
+
+use bevy::{
+    prelude::*,
+    render::camera::{Camera, OrthographicProjection},
+};
+
+use crate::{
+    input::{ContextMenu, MenuEntry, MenuTarget},
+    lerper::{Lerp, LerpType},
+    util::screen_to_world,
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// Screen depth the inspected card is held at, in front of the hand tray
+const INSPECT_Z: f32 = 0.02;
+/// Screen depth of the shade over everything behind the inspected card
+const DIM_Z: f32 = 0.05;
+/// Share of the screen height the inspected card fills
+const INSPECT_HEIGHT: f32 = 0.8;
+/// Length of a treachery card's face along its long edge
+const CARD_LENGTH: f32 = 0.36;
+const INSPECT_TIME: f32 = 0.3;
+
+pub struct InspectPlugin;
+
+impl Plugin for InspectPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Inspection>()
+            .init_resource::<InspectMaterials>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                inspect_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                inspect_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system());
+    }
+}
+
+/// A copy of a card blown up in the middle of the screen, so its text can be read
+#[derive(Default)]
+struct Inspection {
+    /// The card being looked at, which stays where it is
+    card: Option<Entity>,
+    copy: Option<Entity>,
+    shade: Option<Entity>,
+    /// The copy is on its way back to the card, to be removed once it gets there
+    returning: bool,
+}
+
+struct InspectMaterials {
+    shade: Handle<StandardMaterial>,
+    quad: Handle<Mesh>,
+}
+
+impl FromResources for InspectMaterials {
+    fn from_resources(resources: &Resources) -> Self {
+        let mut materials = resources.get_mut::<Assets<StandardMaterial>>().unwrap();
+        let mut meshes = resources.get_mut::<Assets<Mesh>>().unwrap();
+        InspectMaterials {
+            shade: materials.add(StandardMaterial {
+                albedo: Color::rgba(0.0, 0.0, 0.0, 0.6),
+                shaded: false,
+                ..Default::default()
+            }),
+            quad: meshes.add(Mesh::from(shape::Quad::new(Vec2::one()))),
+        }
+    }
+}
+
+/// Inspecting a card in the hand tray from its context menu brings a copy of it up to the middle
+/// of the screen and shades the rest. The next click sends it back.
+fn inspect_system(
+    commands: &mut Commands,
+    (mut inspection, mut menu, mouse_input): (
+        ResMut<Inspection>,
+        ResMut<ContextMenu>,
+        Res<Input<MouseButton>>,
+    ),
+    materials: Res<InspectMaterials>,
+    cameras: Query<(&Camera, &Transform), Without<OrthographicProjection>>,
+    cards: Query<(&Transform, &Children)>,
+    faces: Query<(&Handle<Mesh>, &Handle<StandardMaterial>)>,
+    lerps: Query<&Lerp>,
+) {
+    let clicked =
+        mouse_input.just_pressed(MouseButton::Left) || mouse_input.just_pressed(MouseButton::Right);
+    match (inspection.copy, inspection.returning) {
+        (None, _) => {
+            let card = match menu.take(MenuEntry::Inspect) {
+                Some(MenuTarget::Card(card)) => card,
+                _ => return,
+            };
+            let (card, (transform, children)) = if let Ok(found) = cards.get(card) {
+                (card, found)
+            } else {
+                return;
+            };
+            let (camera, cam_transform) = if let Some(camera) = cameras.iter().next() {
+                camera
+            } else {
+                return;
+            };
+            let at_depth = |x: f32, y: f32, z: f32| {
+                screen_to_world(Vec3::new(x, y, z), *cam_transform, camera.projection_matrix)
+            };
+
+            let height = at_depth(0.0, INSPECT_HEIGHT, INSPECT_Z).distance(at_depth(
+                0.0,
+                -INSPECT_HEIGHT,
+                INSPECT_Z,
+            ));
+            let dest = Transform {
+                translation: at_depth(0.0, 0.0, INSPECT_Z),
+                rotation: cam_transform.rotation * Quat::from_rotation_x(0.5 * PI),
+                scale: Vec3::splat(height / CARD_LENGTH),
+            };
+            let copy = commands
+                .spawn((*transform, GlobalTransform::default()))
+                .with(ScreenEntity)
+                .with(Lerp::new(LerpType::world_to(dest), INSPECT_TIME, 0.0))
+                .with_children(|parent| {
+                    for (mesh, material) in children.iter().filter_map(|&face| faces.get(face).ok())
+                    {
+                        parent.spawn(PbrBundle {
+                            mesh: mesh.clone(),
+                            material: material.clone(),
+                            ..Default::default()
+                        });
+                    }
+                })
+                .current_entity();
+
+            let size = at_depth(1.0, 1.0, DIM_Z) - at_depth(-1.0, -1.0, DIM_Z);
+            let shade = commands
+                .spawn(PbrBundle {
+                    mesh: materials.quad.clone(),
+                    material: materials.shade.clone(),
+                    visible: Visible {
+                        is_visible: true,
+                        is_transparent: true,
+                    },
+                    transform: Transform {
+                        translation: at_depth(0.0, 0.0, DIM_Z),
+                        rotation: cam_transform.rotation,
+                        // Overshoots the edges rather than leave a sliver showing
+                        scale: Vec3::new(2.0 * size.length(), 2.0 * size.length(), 1.0),
+                    },
+                    ..Default::default()
+                })
+                .with(ScreenEntity)
+                .current_entity();
+            *inspection = Inspection {
+                card: Some(card),
+                copy,
+                shade,
+                returning: false,
+            };
+        }
+        (Some(copy), false) => {
+            if !clicked {
+                return;
+            }
+            inspection.returning = true;
+            if let Some((transform, _)) = inspection.card.and_then(|card| cards.get(card).ok()) {
+                commands.insert_one(
+                    copy,
+                    Lerp::new(LerpType::world_to(*transform), INSPECT_TIME, 0.0),
+                );
+            }
+        }
+        (Some(copy), true) => {
+            if lerps.get(copy).is_ok() {
+                return;
+            }
+            commands.despawn_recursive(copy);
+            if let Some(shade) = inspection.shade {
+                commands.despawn(shade);
+            }
+            *inspection = Inspection::default();
+        }
+    }
+}
+
+fn reset(mut inspection: ResMut<Inspection>) {
+    *inspection = Inspection::default();
+}
@@ -0,0 +1,465 @@
+use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
+
+use crate::{
+    alliance::own_faction,
+    cards::{CardEffects, CardRequest},
+    components::Player,
+    data::{AbilityWindow, Faction, FactionAbilities},
+    game_action::{submit, GameAction, GameActions},
+    hud::HudMaterials,
+    layout::{Anchored, AnchoredHeight},
+    locale::{Localized, Tr},
+    menu::{ButtonMaterials, Lobby},
+    network::{Client, Network, NetworkType, Server},
+    phase::{ActionQueue, MovementRequest},
+    protocol::ServerEvent,
+    resources::{Data, Info},
+    settings::Settings,
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+type PromptButtons<'a, 'b, 'c, 'd> = Query<
+    'a,
+    (
+        &'b Interaction,
+        &'c InterruptButton,
+        &'d mut Handle<ColorMaterial>,
+    ),
+    Mutated<Interaction>,
+>;
+type Panels<'a, 'b> = Query<'a, &'b mut Style, With<InterruptPanel>>;
+type Texts<'a, 'b> = Query<'a, &'b mut Text, With<InterruptText>>;
+
+/// Seconds everyone who could answer an action gets before it goes ahead anyway
+const INTERRUPT_TIME: f32 = 10.0;
+
+pub struct InterruptPlugin;
+
+impl Plugin for InterruptPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Interrupts>()
+            .on_state_enter(RESPONSE_STAGE, Screen::HostingGame, init_prompt.system())
+            .on_state_enter(RESPONSE_STAGE, Screen::JoinedGame, init_prompt.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                interrupt_window_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                prompt_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                prompt_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system());
+    }
+}
+
+/// The kinds of action a faction power can change, and so a Karama can answer
+#[derive(Copy, Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum InterruptKind {
+    Shipping,
+    Revival,
+    Bidding,
+}
+
+impl InterruptKind {
+    fn of(action: &GameAction) -> Option<Self> {
+        match action {
+            GameAction::Movement {
+                request: MovementRequest::Ship { .. },
+            } => Some(InterruptKind::Shipping),
+            GameAction::Revival { .. } => Some(InterruptKind::Revival),
+            GameAction::Bid {
+                amount: Some(_), ..
+            } => Some(InterruptKind::Bidding),
+            _ => None,
+        }
+    }
+
+    fn window(self) -> AbilityWindow {
+        match self {
+            InterruptKind::Shipping => AbilityWindow::Shipping,
+            InterruptKind::Revival => AbilityWindow::Revival,
+            InterruptKind::Bidding => AbilityWindow::Bidding,
+        }
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            InterruptKind::Shipping => "interrupt.shipping",
+            InterruptKind::Revival => "interrupt.revival",
+            InterruptKind::Bidding => "interrupt.bidding",
+        }
+    }
+}
+
+/// An action held back while everyone who could cancel the power behind it decides whether to
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct InterruptWindow {
+    pub actor: Faction,
+    /// The faction whose power applies to the action, which a Karama would cancel. Often the
+    /// actor, but the Guild is paid for everyone's shipping.
+    pub target: Faction,
+    pub kind: InterruptKind,
+    /// Everyone who may still answer
+    pub waiting: Vec<Faction>,
+    pub seconds: f32,
+}
+
+#[derive(Default)]
+pub struct Interrupts {
+    /// The window open right now. Clients are sent their copy by the host.
+    pub window: Option<InterruptWindow>,
+    /// The action being held, with the slot of the Karama each faction that may answer holds
+    held: Option<(GameAction, Vec<(Faction, u32)>)>,
+    /// The Karama played against the held action
+    karama: Option<(Faction, u32)>,
+    /// The held action once the window has closed, waiting on its Karama to land
+    resolving: Option<(GameAction, Faction)>,
+    /// Already had its window, so it goes straight through when it comes back
+    cleared: Option<GameAction>,
+    /// The window changed since the clients were last told
+    dirty: bool,
+    /// Answered at this machine, so the prompt goes away without waiting on the host
+    answered: Vec<Faction>,
+}
+
+impl Interrupts {
+    /// Holds `action` back if a faction power applies to it and anyone else holding a Karama,
+    /// given by `holders` with the card's slot, could cancel that power first
+    pub fn hold(
+        &mut self,
+        action: &GameAction,
+        (abilities, in_play, cancelled): (&FactionAbilities, &[Faction], &[Faction]),
+        holders: &[(Faction, u32)],
+    ) -> bool {
+        if self.cleared.as_ref() == Some(action) {
+            self.cleared = None;
+            return false;
+        }
+        let kind = if let Some(kind) = InterruptKind::of(action) {
+            kind
+        } else {
+            return false;
+        };
+        let actor = action.faction();
+        let target = if let Some(ability) = abilities
+            .interrupts(kind.window(), in_play, cancelled)
+            .find(|ability| ability.faction() == actor || ability.collects_from(actor))
+        {
+            ability.faction()
+        } else {
+            return false;
+        };
+        let eligible = holders
+            .iter()
+            .filter(|(faction, _)| *faction != target)
+            .copied()
+            .collect::<Vec<_>>();
+        if eligible.is_empty() {
+            return false;
+        }
+        println!(
+            "Holding {:?} while {:?} decide whether to play Karama on {}",
+            action,
+            eligible
+                .iter()
+                .map(|(faction, _)| faction)
+                .collect::<Vec<_>>(),
+            target
+        );
+        self.window = Some(InterruptWindow {
+            actor,
+            target,
+            kind,
+            waiting: eligible.iter().map(|(faction, _)| *faction).collect(),
+            seconds: INTERRUPT_TIME,
+        });
+        self.held = Some((action.clone(), eligible));
+        self.dirty = true;
+        true
+    }
+
+    /// A Karama closes the window at once, since nobody else needs to play one
+    pub fn answer(&mut self, faction: Faction, karama: bool) -> Result<(), String> {
+        let window = match self.window.as_mut() {
+            Some(window) if window.waiting.contains(&faction) => window,
+            _ => return Err(format!("{} has nothing to answer", faction)),
+        };
+        window.waiting.retain(|&waiting| waiting != faction);
+        if karama {
+            self.karama = self.held.as_ref().and_then(|(_, eligible)| {
+                eligible
+                    .iter()
+                    .find(|(holder, _)| *holder == faction)
+                    .copied()
+            });
+            window.waiting.clear();
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Whatever the host says is open now
+    pub fn receive(&mut self, window: Option<InterruptWindow>) {
+        if window.is_none() {
+            self.answered.clear();
+        }
+        self.window = window;
+    }
+}
+
+/// Counts the open window down, closes it once everyone has answered or the time is up, and
+/// lets the held action go on once any Karama played against it has taken effect
+fn interrupt_window_system(
+    (mut interrupts, mut actions): (ResMut<Interrupts>, ResMut<GameActions>),
+    (time, effects, queue): (Res<Time>, Res<CardEffects>, Res<ActionQueue>),
+    mut server: Query<&mut Server>,
+) {
+    let closed = match interrupts.window.as_mut() {
+        Some(window) => {
+            window.seconds -= time.delta_seconds();
+            (window.waiting.is_empty() || window.seconds <= 0.0).then(|| window.target)
+        }
+        None => None,
+    };
+    if let Some(target) = closed {
+        interrupts.window = None;
+        interrupts.dirty = true;
+        let held = interrupts.held.take().map(|(action, _)| action);
+        match (interrupts.karama.take(), held) {
+            (Some((faction, slot)), Some(action)) => {
+                println!("{} plays Karama before {:?} goes ahead", faction, action);
+                actions.submitted.push_front(GameAction::CardPlay {
+                    request: CardRequest::Play {
+                        faction,
+                        slot,
+                        target: Some(target),
+                        amount: 0,
+                    },
+                });
+                interrupts.resolving = Some((action, target));
+            }
+            (None, Some(action)) => {
+                interrupts.cleared = Some(action.clone());
+                actions.submitted.push_front(action);
+            }
+            (_, None) => (),
+        }
+    }
+    // The Karama lands as soon as the card is played, or never if the play was turned down
+    let landed = interrupts.resolving.as_ref().map_or(false, |(_, target)| {
+        effects.cancelled(*target)
+            || (queue.is_empty()
+                && !actions
+                    .submitted
+                    .iter()
+                    .any(|action| matches!(action, GameAction::CardPlay { .. })))
+    });
+    if landed {
+        if let Some((action, _)) = interrupts.resolving.take() {
+            interrupts.cleared = Some(action.clone());
+            actions.submitted.push_front(action);
+        }
+    }
+    if interrupts.dirty {
+        interrupts.dirty = false;
+        if let Some(mut server) = server.iter_mut().next() {
+            server.broadcast(&ServerEvent::Interrupt {
+                window: interrupts.window.clone(),
+            });
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum InterruptButton {
+    Karama,
+    Pass,
+    /// Passes now and on every window after, until turned off in the settings file
+    AlwaysPass,
+}
+
+struct InterruptPanel;
+
+struct InterruptText;
+
+fn init_prompt(
+    commands: &mut Commands,
+    (data, asset_server): (Res<Data>, Res<AssetServer>),
+    (materials, button_materials): (Res<HudMaterials>, Res<ButtonMaterials>),
+) {
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let text = |font_size: f32| TextBundle {
+        text: Text {
+            font: font.clone(),
+            value: String::new(),
+            style: TextStyle {
+                font_size,
+                color: Color::ANTIQUE_WHITE,
+                ..Default::default()
+            },
+        },
+        ..Default::default()
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                padding: Rect::all(Val::Px(8.0)),
+                display: Display::None,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(InterruptPanel)
+        .with(Anchored::new(data.ui_structure.interrupt.clone()).with_height(AnchoredHeight::Auto))
+        .with_children(|parent| {
+            parent.spawn(text(18.0)).with(InterruptText);
+            parent
+                .spawn(NodeBundle {
+                    material: materials.panel.clone(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for &(button, key) in [
+                        (InterruptButton::Karama, "interrupt.karama"),
+                        (InterruptButton::Pass, "interrupt.pass"),
+                        (InterruptButton::AlwaysPass, "interrupt.always_pass"),
+                    ]
+                    .iter()
+                    {
+                        parent
+                            .spawn(ButtonBundle {
+                                style: Style {
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    margin: Rect::all(Val::Px(4.0)),
+                                    padding: Rect::all(Val::Px(4.0)),
+                                    ..Default::default()
+                                },
+                                material: button_materials.normal.clone(),
+                                ..Default::default()
+                            })
+                            .with(button)
+                            .with_children(|parent| {
+                                parent.spawn(text(16.0)).with(Localized(key));
+                            });
+                    }
+                });
+        });
+}
+
+/// Asks whoever at this machine may answer the open window, unless they always pass. Everyone
+/// sharing a hot seat machine is asked in turn.
+fn prompt_system(
+    (mut interrupts, mut actions, mut settings): (
+        ResMut<Interrupts>,
+        ResMut<GameActions>,
+        ResMut<Settings>,
+    ),
+    (network, lobby, info): (Res<Network>, Res<Lobby>, Res<Info>),
+    (time, tr, button_materials): (Res<Time>, Res<Tr>, Res<ButtonMaterials>),
+    mut buttons: PromptButtons,
+    (mut panels, mut texts): (Panels, Texts),
+    (players, faction_client): (Query<&Player>, Query<&Client>),
+    mut client: Query<&mut Client>,
+) {
+    // The host keeps its own clock, the clients just count down what they were told
+    if network.network_type == NetworkType::Client {
+        if let Some(window) = interrupts.window.as_mut() {
+            window.seconds = (window.seconds - time.delta_seconds()).max(0.0);
+        }
+    }
+    let answering = interrupts.window.as_ref().and_then(|window| {
+        let unanswered = |faction: &Faction| {
+            window.waiting.contains(faction) && !interrupts.answered.contains(faction)
+        };
+        match network.network_type {
+            NetworkType::Local => window.waiting.iter().copied().find(|f| unanswered(f)),
+            _ => own_faction(&network, &lobby, &info, &players, &faction_client)
+                .filter(|faction| unanswered(faction)),
+        }
+    });
+
+    let mut answer = None;
+    for (interaction, &button, mut material) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {
+                *material = button_materials.pressed.clone();
+                answer = Some(button);
+            }
+            Interaction::Hovered => *material = button_materials.hovered.clone(),
+            Interaction::None => *material = button_materials.normal.clone(),
+        }
+    }
+    if settings.auto_pass {
+        answer = Some(InterruptButton::Pass);
+    }
+    if let (Some(faction), Some(button)) = (answering, answer) {
+        if let InterruptButton::AlwaysPass = button {
+            settings.auto_pass = true;
+            settings.save();
+        }
+        interrupts.answered.push(faction);
+        submit(
+            &network,
+            &mut client,
+            &mut actions,
+            GameAction::Interrupt {
+                faction,
+                karama: matches!(button, InterruptButton::Karama),
+            },
+        );
+    }
+
+    let shown = interrupts
+        .window
+        .as_ref()
+        .filter(|_| answering.map_or(false, |faction| !interrupts.answered.contains(&faction)))
+        .map(|window| {
+            tr.fmt(
+                "interrupt.prompt",
+                &[
+                    &tr.faction(window.actor),
+                    &tr.get(window.kind.key()),
+                    &tr.faction(window.target),
+                    &window.seconds.ceil(),
+                ],
+            )
+        });
+    let display = if shown.is_some() {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    for mut style in panels.iter_mut() {
+        if style.display != display {
+            style.display = display;
+        }
+    }
+    if let Some(value) = shown {
+        for mut text in texts.iter_mut() {
+            if text.value != value {
+                text.value = value.clone();
+            }
+        }
+    }
+}
+
+fn reset(mut interrupts: ResMut<Interrupts>) {
+    *interrupts = Interrupts::default();
+}
@@ -0,0 +1,147 @@
+use bevy::{prelude::*, window::WindowResized};
+
+use crate::{
+    data::{UiAnchor, UiRect},
+    settings::Settings,
+};
+
+/// The HUD spreads out with the window up to this width over height, and past it stays anchored
+/// to the edges so ultrawide screens keep the middle clear for the board
+const REFERENCE_ASPECT: f32 = 16.0 / 9.0;
+
+pub struct LayoutPlugin;
+
+impl Plugin for LayoutPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // After the screens have spawned their panels, so none of them are missed
+        app.init_resource::<UiLayout>()
+            .add_system_to_stage(stage::POST_UPDATE, layout_system.system());
+    }
+}
+
+/// The window the HUD is laid out for, in logical pixels, and how much bigger the player wants it
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct UiLayout {
+    pub window: Vec2,
+    pub scale: f32,
+}
+
+impl Default for UiLayout {
+    fn default() -> Self {
+        UiLayout {
+            window: Vec2::new(1280.0, 720.0),
+            scale: 1.0,
+        }
+    }
+}
+
+impl UiLayout {
+    /// Where a panel from the UI structure ends up on this window, in the same -1 to 1 space it is
+    /// written in. Panels never leave the window, however big they are scaled.
+    pub fn place(&self, rect: &UiRect) -> UiRect {
+        let (width, height) = (self.window.x.max(1.0), self.window.y.max(1.0));
+        let reference = width.min(height * REFERENCE_ASPECT) * self.scale;
+        let size_x = (0.5 * (rect.right - rect.left) * reference).min(width);
+        let size_y = (0.5 * (rect.top - rect.bottom) * height * self.scale).min(height);
+        let x = match rect.anchor {
+            UiAnchor::Left => 0.5 * (rect.left + 1.0) * reference,
+            UiAnchor::Right => width - 0.5 * (1.0 - rect.right) * reference - size_x,
+            UiAnchor::Center => {
+                0.5 * width + 0.25 * (rect.left + rect.right) * reference - 0.5 * size_x
+            }
+        }
+        .max(0.0)
+        .min(width - size_x);
+        let y = (0.5 * (1.0 - rect.top) * height * self.scale)
+            .max(0.0)
+            .min(height - size_y);
+        UiRect {
+            left: 2.0 * x / width - 1.0,
+            right: 2.0 * (x + size_x) / width - 1.0,
+            top: 1.0 - 2.0 * y / height,
+            bottom: 1.0 - 2.0 * (y + size_y) / height,
+            anchor: rect.anchor,
+        }
+    }
+}
+
+/// How tall an anchored panel is
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum AnchoredHeight {
+    /// As tall as its rect
+    Rect,
+    /// A fixed height, scaled along with the rest of the HUD
+    Px(f32),
+    /// As tall as whatever is in it
+    Auto,
+}
+
+/// A HUD panel kept in place by the layout, from a rect in the UI structure
+pub struct Anchored {
+    pub rect: UiRect,
+    pub height: AnchoredHeight,
+}
+
+impl Anchored {
+    pub fn new(rect: UiRect) -> Self {
+        Anchored {
+            rect,
+            height: AnchoredHeight::Rect,
+        }
+    }
+
+    pub fn with_height(mut self, height: AnchoredHeight) -> Self {
+        self.height = height;
+        self
+    }
+}
+
+type Panels<'a, 'b, 'c, 'd, 'e> = QuerySet<(
+    Query<'a, (&'b mut Style, &'c Anchored)>,
+    Query<'a, (&'d mut Style, &'e Anchored), Added<Anchored>>,
+)>;
+
+/// Lays the anchored panels out again whenever the window or the UI scale changes
+fn layout_system(
+    (windows, settings): (Res<Windows>, Res<Settings>),
+    (resized, mut reader): (
+        Res<Events<WindowResized>>,
+        Local<EventReader<WindowResized>>,
+    ),
+    mut layout: ResMut<UiLayout>,
+    mut panels: Panels,
+) {
+    let window = if let Some(window) = windows.get_primary() {
+        window
+    } else {
+        return;
+    };
+    let current = UiLayout {
+        window: Vec2::new(window.width(), window.height()),
+        scale: settings.graphics.ui_scale,
+    };
+    let resized = reader.iter(&resized).last().is_some();
+    let place = |style: &mut Style, anchored: &Anchored| {
+        let rect = current.place(&anchored.rect);
+        style.position_type = PositionType::Absolute;
+        style.position = rect.top_left();
+        style.size = Size::new(
+            rect.size().width,
+            match anchored.height {
+                AnchoredHeight::Rect => rect.size().height,
+                AnchoredHeight::Px(px) => Val::Px(px * current.scale),
+                AnchoredHeight::Auto => Val::Auto,
+            },
+        );
+    };
+    if resized || current != *layout {
+        *layout = current;
+        for (mut style, anchored) in panels.q0_mut().iter_mut() {
+            place(&mut style, anchored);
+        }
+    } else {
+        for (mut style, anchored) in panels.q1_mut().iter_mut() {
+            place(&mut style, anchored);
+        }
+    }
+}
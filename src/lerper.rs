@@ -5,11 +5,21 @@ use bevy::{
     render::camera::{Camera, OrthographicProjection},
 };
 
-use crate::{data::CameraNode, util::screen_to_world};
+use crate::{audio::SoundEffect, data::CameraNode, phase::CardDrawn, util::screen_to_world};
 
 const UI_SCALE: f32 = 0.01;
 const UI_Z: f32 = 0.1;
 const SPEED_MOD: f32 = 1.0;
+const DEAL_TIME: f32 = 0.6;
+/// How far a dealt card rises over the table on its way
+const DEAL_ARC: f32 = 0.12;
+/// Just enough lift for a card to turn over without going through the table
+const FLIP_ARC: f32 = 0.06;
+const DEPLOY_TIME: f32 = 0.8;
+/// How high shipped troops fly over the board on their way down to it
+const DEPLOY_ARC: f32 = 0.15;
+/// Time between one shipped troop taking off and the next
+const DEPLOY_STAGGER: f32 = 0.08;
 
 #[derive(Copy, Clone)]
 pub enum LerpType {
@@ -82,6 +92,8 @@ pub struct Lerp {
     pub time: f32,
     animation_time: f32,
     delay: f32,
+    /// Height of the hop over the straight line from src to dest, at its peak halfway through
+    arc: f32,
 }
 
 impl Lerp {
@@ -93,9 +105,47 @@ impl Lerp {
             time,
             animation_time: time,
             delay,
+            arc: 0.0,
         }
     }
 
+    /// Sends a card in an arc from wherever it is, for dealing from a deck to a hand or the table
+    pub fn deal(lerp_type: LerpType, delay: f32) -> Self {
+        Lerp {
+            arc: DEAL_ARC,
+            ..Lerp::new(lerp_type, DEAL_TIME, delay)
+        }
+    }
+
+    /// Turns a card over around its long axis on its way to `dest`, which should have the other
+    /// side up. Pair it with a `FlipFace` to change what is on the face while it is edge-on.
+    pub fn flip(dest: Transform, delay: f32) -> Self {
+        Lerp {
+            arc: FLIP_ARC,
+            ..Lerp::new(LerpType::world_to(dest), DEAL_TIME, delay)
+        }
+    }
+
+    /// Lifts a troop off its reserve stack and flies it over the board to `dest`. Troops shipped
+    /// together take off one after another in `order`.
+    pub fn deploy(dest: Transform, order: usize) -> Self {
+        Lerp {
+            arc: DEPLOY_ARC,
+            ..Lerp::new(
+                LerpType::world_to(dest),
+                DEPLOY_TIME,
+                order as f32 * DEPLOY_STAGGER,
+            )
+        }
+    }
+
+    /// How far through the animation this is, from 0 until the delay is up to 1 at the end
+    pub fn progress(&self) -> f32 {
+        1.0 - (self.time / self.animation_time).clamp(0.0, 1.0)
+    }
+
+    /// Eases the camera over to a preset view. Swapping in a new one mid-flight starts it from
+    /// wherever the camera currently is.
     pub fn move_camera(dest: CameraNode, time: f32) -> Self {
         Lerp {
             lerp_type: LerpType::Camera { src: None, dest },
@@ -104,10 +154,18 @@ impl Lerp {
             time,
             animation_time: time,
             delay: 0.0,
+            arc: 0.0,
         }
     }
 }
 
+/// A new material for a card's face, put on halfway through its flip
+pub struct FlipFace(pub Handle<StandardMaterial>);
+
+/// A troop just shipped from the reserves, with its place in the shipment, so it flies to its
+/// stack with `Lerp::deploy` instead of sliding there
+pub struct Deploying(pub usize);
+
 #[derive(Default, Copy, Clone)]
 pub struct UITransform {
     translation: Vec2,
@@ -186,7 +244,9 @@ pub struct LerpPlugin;
 impl Plugin for LerpPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_system(camera_system.system())
-            .add_system(lerp_system.system());
+            .add_system(lerp_system.system())
+            .add_system(flip_face_system.system())
+            .add_system(drawn_card_system.system());
     }
 }
 
@@ -270,7 +330,8 @@ fn lerp_system(
 
                     commands.remove_one::<Lerp>(entity);
                 } else {
-                    let mut lerp_amount = (lerp.animation_time - lerp.time) / lerp.animation_time;
+                    let progress = (lerp.animation_time - lerp.time) / lerp.animation_time;
+                    let mut lerp_amount = progress;
                     match lerp.lerp_type {
                         LerpType::World { .. } | LerpType::UI { .. } => {
                             lerp_amount = -0.5 * (PI * lerp_amount).cos() + 0.5;
@@ -292,6 +353,7 @@ fn lerp_system(
                     transform.rotation =
                         lerp.src.unwrap().rotation.lerp(dest.rotation, lerp_amount);
                     transform.scale = lerp.src.unwrap().scale.lerp(dest.scale, lerp_amount);
+                    transform.translation += lerp.arc * (PI * progress).sin() * Vec3::unit_y();
 
                     lerp.time -= time.delta_seconds() * SPEED_MOD;
                 }
@@ -306,27 +368,24 @@ fn camera_system(
     mut cameras: Query<(Entity, &mut Lerp, &mut Transform), With<Camera>>,
 ) {
     for (entity, mut lerp, mut transform) in cameras.iter_mut() {
-        if let LerpType::Camera { mut src, dest } = lerp.lerp_type {
-            if src.is_none() {
-                src.replace(transform.clone());
+        if let LerpType::Camera { src, dest } = lerp.lerp_type {
+            // Remember where we started so the ease doesn't chase its own tail
+            if lerp.src.is_none() {
+                lerp.src = Some(src.unwrap_or(*transform));
             }
+            let dest_transform = Transform::from_translation(dest.pos).looking_at(dest.at, dest.up);
             if lerp.time <= 0.0 {
-                *transform = Transform::from_translation(dest.pos).looking_at(dest.at, dest.up);
+                *transform = dest_transform;
 
                 commands.remove_one::<Lerp>(entity);
             } else {
-                let dest_transform =
-                    Transform::from_translation(dest.pos).looking_at(dest.at, dest.up);
+                let src = lerp.src.unwrap();
                 let mut lerp_amount = PI * (lerp.animation_time - lerp.time) / lerp.animation_time;
                 lerp_amount = -0.5 * lerp_amount.cos() + 0.5;
                 transform.translation = src
-                    .unwrap()
                     .translation
                     .lerp(dest_transform.translation, lerp_amount);
-                transform.rotation = src
-                    .unwrap()
-                    .rotation
-                    .lerp(dest_transform.rotation, lerp_amount);
+                transform.rotation = src.rotation.slerp(dest_transform.rotation, lerp_amount);
 
                 lerp.time -= time.delta_seconds() * SPEED_MOD;
             }
@@ -335,3 +394,46 @@ fn camera_system(
         }
     }
 }
+
+fn flip_face_system(
+    commands: &mut Commands,
+    mut sound_effects: ResMut<Events<SoundEffect>>,
+    cards: Query<(Entity, &FlipFace, &Children, Option<&Lerp>)>,
+    mut faces: Query<&mut Handle<StandardMaterial>>,
+) {
+    for (entity, face, children, lerp) in cards.iter() {
+        // Without a flip in progress there's nobody to hide the swap from
+        if lerp.map_or(true, |lerp| lerp.progress() >= 0.5) {
+            if let Ok(mut material) = faces.get_mut(children[0]) {
+                *material = face.0.clone();
+                sound_effects.send(SoundEffect::CardFlip);
+            }
+            commands.remove_one::<FlipFace>(entity);
+        }
+    }
+}
+
+/// A drawn card makes its noise once it actually leaves the deck, which can be a while after the
+/// draw when the deal is queued behind other animations
+fn drawn_card_system(
+    events: Res<Events<CardDrawn>>,
+    mut reader: Local<EventReader<CardDrawn>>,
+    mut drawn: Local<Vec<Entity>>,
+    mut sound_effects: ResMut<Events<SoundEffect>>,
+    // Cards being turned over already make a noise as they flip
+    cards: Query<(Option<&Lerp>, Option<&FlipFace>)>,
+) {
+    drawn.extend(reader.iter(&events).map(|event| event.card));
+    let mut dealt = false;
+    drawn.retain(|&card| match cards.get(card) {
+        Ok((Some(lerp), flip)) if lerp.progress() > 0.0 => {
+            dealt |= flip.is_none();
+            false
+        }
+        Ok(_) => true,
+        Err(_) => false,
+    });
+    if dealt {
+        sound_effects.send(SoundEffect::CardFlip);
+    }
+}
@@ -0,0 +1,140 @@
+use std::{collections::HashMap, fmt::Display, fs};
+
+use bevy::prelude::*;
+
+use crate::{
+    data::Faction,
+    resources::open_data,
+    settings::{Binding, Settings},
+};
+
+/// Every `<language>.ron` in here maps text keys to what is shown for them
+pub const LANG_DIR: &str = "data/lang";
+/// Holds every key, so anything missing from another language shows up in this one
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+pub struct LocalePlugin;
+
+impl Plugin for LocalePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // After the screens have spawned their text, so none of it is missed
+        app.init_resource::<Tr>()
+            .add_system_to_stage(stage::POST_UPDATE, localize_system.system())
+            .add_system_to_stage(stage::POST_UPDATE, relocalize_system.system());
+    }
+}
+
+/// Looks up what to show for each piece of text in the player's language
+pub struct Tr {
+    pub language: String,
+    strings: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+}
+
+impl FromResources for Tr {
+    fn from_resources(resources: &Resources) -> Self {
+        Tr::load(&resources.get::<Settings>().unwrap().language)
+    }
+}
+
+impl Tr {
+    pub fn load(language: &str) -> Self {
+        let read = |language: &str| -> HashMap<String, String> {
+            let path = format!("{}/{}.ron", LANG_DIR, language);
+            match open_data(&path) {
+                Ok(file) => ron::de::from_reader(file).unwrap_or_else(|e| {
+                    println!("Ignoring unreadable language file {}: {}", path, e);
+                    HashMap::new()
+                }),
+                Err(_) => {
+                    println!("No language file at {}", path);
+                    HashMap::new()
+                }
+            }
+        };
+        Tr {
+            language: language.to_string(),
+            strings: read(language),
+            fallback: read(DEFAULT_LANGUAGE),
+        }
+    }
+
+    /// Falls back to the default language, then to the key itself
+    pub fn get(&self, key: &str) -> String {
+        self.strings
+            .get(key)
+            .or_else(|| self.fallback.get(key))
+            .cloned()
+            .unwrap_or_else(|| key.to_string())
+    }
+
+    /// Fills each `{}` in the text with the next argument
+    pub fn fmt(&self, key: &str, args: &[&dyn Display]) -> String {
+        let text = self.get(key);
+        let mut parts = text.split("{}");
+        let mut s = parts.next().unwrap_or_default().to_string();
+        for (i, part) in parts.enumerate() {
+            if let Some(arg) = args.get(i) {
+                s.push_str(&arg.to_string());
+            }
+            s.push_str(part);
+        }
+        s
+    }
+
+    pub fn faction(&self, faction: Faction) -> String {
+        self.get(&format!("faction.{:?}", faction))
+    }
+
+    pub fn binding(&self, binding: Binding) -> String {
+        self.get(&format!("binding.{:?}", binding))
+    }
+
+    /// Every language there is a file for, by file name
+    pub fn languages() -> Vec<String> {
+        let mut languages = fs::read_dir(LANG_DIR)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().map_or(false, |ext| ext == "ron"))
+                    .filter_map(|path| {
+                        path.file_stem()
+                            .map(|stem| stem.to_string_lossy().to_string())
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        languages.sort();
+        languages
+    }
+
+    /// The language after this one, wrapping round
+    pub fn next_language(&self) -> String {
+        let languages = Tr::languages();
+        languages
+            .iter()
+            .position(|language| *language == self.language)
+            .and_then(|i| languages.get((i + 1) % languages.len()))
+            .or_else(|| languages.first())
+            .cloned()
+            .unwrap_or_else(|| DEFAULT_LANGUAGE.to_string())
+    }
+}
+
+/// Text that never changes except with the language
+pub struct Localized(pub &'static str);
+
+fn localize_system(tr: Res<Tr>, mut texts: Query<(&mut Text, &Localized), Added<Localized>>) {
+    for (mut text, localized) in texts.iter_mut() {
+        text.value = tr.get(localized.0);
+    }
+}
+
+fn relocalize_system(tr: ChangedRes<Tr>, mut texts: Query<(&mut Text, &Localized)>) {
+    for (mut text, localized) in texts.iter_mut() {
+        let value = tr.get(localized.0);
+        if text.value != value {
+            text.value = value;
+        }
+    }
+}
@@ -1,90 +1,218 @@
 #[macro_use]
 mod resources;
+mod accessibility;
+mod alliance;
+mod asset_pack;
+mod audio;
+mod bot;
+mod bug_report;
+mod capture;
+mod cards;
 mod components;
+mod cursors;
 mod data;
+mod debug;
+mod decks;
+mod delta;
+mod desync;
+mod dune_core;
+mod elimination;
+mod emote;
+mod focus;
+mod game_action;
+mod hot_reload;
+mod house_rules;
+mod hover;
+mod hud;
 mod input;
+mod inspect;
+mod interrupt;
+mod layout;
 mod lerper;
+mod locale;
+mod material_cache;
+mod mentat;
 mod menu;
+mod migration;
 mod network;
+mod notification;
+mod occupancy;
+#[cfg(not(target_arch = "wasm32"))]
+mod page_server;
+mod pause;
+mod performance;
 mod phase;
+mod piles;
+mod profile;
+mod protocol;
+mod reference;
+mod savegame;
+mod scenario;
+mod secret;
+mod settings;
+#[cfg(test)]
+mod simulation;
 mod stack;
+mod stats;
+mod storm_dial;
+mod territory;
+mod timer;
+mod token_pool;
+mod transport;
+mod tutorial;
 mod util;
-
+mod victory;
+mod wheel;
+
+use accessibility::AccessibilityPlugin;
+use alliance::{AlliancePlugin, AllianceText, Negotiation};
+use asset_pack::AssetPack;
+use audio::AudioPlugin;
+use bot::{BotPlugin, Departures};
+use bug_report::{BugRecorder, BugReportPlugin};
+use capture::{CapturePlugin, Captures};
+use cards::CardsPlugin;
 use components::*;
+use cursors::{CursorPlugin, RemoteCursors};
 use data::*;
+use debug::DebugPlugin;
+use decks::DecksPlugin;
+use delta::{DeltaPlugin, StateDeltas};
+use desync::{DesyncPlugin, StateHashes};
+use dune_core::divide_spice;
+use elimination::EliminationPlugin;
+use emote::{EmotePlugin, Signals};
+use focus::FocusPlugin;
+use game_action::{GameActionPlugin, GameActions};
+use hot_reload::HotReloadPlugin;
+use house_rules::{HouseRules, HouseRulesPlugin};
+use hover::HoverPlugin;
+use hud::{HudPlugin, LatencyText, OrnithopterIcon, PlayerStatusText, TurnTile};
 use input::GameInputPlugin;
+use inspect::InspectPlugin;
+use interrupt::{InterruptPlugin, Interrupts};
+use layout::{Anchored, LayoutPlugin};
 use lerper::LerpPlugin;
-use menu::MenuPlugin;
+use locale::{LocalePlugin, Tr};
+use material_cache::{MaterialCache, MaterialCachePlugin};
+use mentat::{MentatPause, MentatPlugin};
+use menu::{Chat, Lobby, MenuPlugin};
+use migration::{HostMigration, MigrationPlugin};
 use network::*;
+use notification::{NotificationPlugin, Notifications};
+use occupancy::OccupancyPlugin;
+use pause::PausePlugin;
+use performance::PerformancePlugin;
 use phase::*;
+use piles::PilesPlugin;
+use profile::ProfilePlugin;
+use protocol::{ClientMessage, ClientRequest, ServerEvent};
+use reference::ReferencePlugin;
 use resources::*;
-use util::divide_spice;
-
-use bevy::{asset::LoadState, prelude::*, render::camera::PerspectiveProjection};
-
-use bytecheck::CheckBytes;
-use rkyv::{check_archive, Archive, ArchiveWriter, Seek, Unarchive, Write};
+use savegame::{LoadedGame, ResyncRequests, SaveGame, SaveGamePlugin};
+use scenario::{LoadedScenario, ScenarioPlugin};
+use secret::{HostOutcomes, SecretPlugin, SecretReveals};
+use settings::{KeyLight, SettingsPlugin};
+use stats::StatsPlugin;
+use storm_dial::{StormDial, StormDialPlugin};
+use territory::TerritoryPlugin;
+use timer::{TimeBanks, TurnTimerPlugin};
+use token_pool::{TokenPool, TokenPoolPlugin};
+use tutorial::TutorialPlugin;
+use victory::VictoryPlugin;
+use wheel::{BattleWheelPlugin, BattleWheels};
+
+use bevy::{
+    asset::LoadState,
+    prelude::*,
+    render::{
+        camera::PerspectiveProjection,
+        texture::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
 
 use ncollide3d::{
-    na::{Point3, Vector3},
-    shape::{ConvexHull, Cuboid, Cylinder, ShapeHandle, TriMesh},
+    na::Vector3,
+    shape::{ConvexHull, Cuboid, Cylinder, ShapeHandle},
     transformation::ToTriMesh,
 };
 
 use rand::seq::SliceRandom;
 
-use std::{collections::HashMap, f32::consts::PI, io::Cursor};
+use std::{collections::HashMap, f32::consts::PI};
 
 #[derive(Copy, Clone, Debug)]
 pub enum Screen {
     MainMenu,
     Server,
     Join,
+    Settings,
+    Profile,
     Loading,
+    /// Spawning the game a piece at a time once everything it needs has loaded
+    SettingUp,
     HostingGame,
     JoinedGame,
+    GameOver,
 }
 
 struct ScreenEntity;
 
-#[derive(Archive, Unarchive, PartialEq, Clone, Debug)]
-#[archive(derive(CheckBytes))]
-pub enum MessageData {
-    Load,
-    Loaded,
-    ServerInfo { players: Vec<String> },
+const STATE_CHANGE_STAGE: &str = "state_change";
+const RESPONSE_STAGE: &str = "response";
+
+#[derive(Default)]
+struct LoadingAssets {
+    groups: Vec<(AssetGroup, Vec<HandleUntyped>)>,
+    /// One handle per entry in the asset manifest, in the same order
+    manifest: Vec<HandleUntyped>,
+    /// Stand-ins for assets that failed to load, kept alive for as long as the game might use them
+    placeholders: Vec<HandleUntyped>,
 }
 
-impl MessageData {
-    fn into_bytes(&self) -> Vec<u8> {
-        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new()));
-        writer
-            .archive_root(self)
-            .expect("Failed to serialize message data!");
-        writer.into_inner().into_inner()
-    }
+/// The pieces of the game spawned on frames of their own once loading is done, in order, so the
+/// window never stalls for the whole table at once
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum SetupStage {
+    Board,
+    /// One faction per frame
+    Factions,
+    Decks,
+    Ui,
+}
 
-    fn from_bytes(bytes: &[u8]) -> Self {
-        let archived = check_archive::<Self>(bytes, 0).expect("Failed to validate message data!");
-        archived.unarchive()
-    }
+#[derive(Default)]
+struct GameSetup {
+    stage: Option<SetupStage>,
+    /// Factions seated so far
+    factions: usize,
+    /// Frames of setup finished so far, for the progress bar
+    steps: usize,
+    step_done: bool,
 }
 
-const STATE_CHANGE_STAGE: &str = "state_change";
-const RESPONSE_STAGE: &str = "response";
+impl GameSetup {
+    /// Whether `stage` has a step left to do this frame
+    fn running(&self, stage: SetupStage) -> bool {
+        self.stage == Some(stage) && !self.step_done
+    }
 
-#[derive(Default)]
-struct LoadingAssets {
-    assets: Vec<HandleUntyped>,
+    fn finish_step(&mut self) {
+        self.step_done = true;
+    }
 }
 
 fn main() {
     let mut app = App::build();
-    app.add_resource(Msaa { samples: 4 })
-        .add_resource(ClearColor(Color::BLACK))
+    app.add_resource(ClearColor(Color::BLACK))
         .init_resource::<Data>()
+        .init_resource::<AssetPack>()
+        .init_resource::<SectorGraph>()
+        .init_resource::<FactionAbilities>()
         .init_resource::<Info>()
-        .init_resource::<LoadingAssets>();
+        .init_resource::<GameRng>()
+        .init_resource::<LoadingAssets>()
+        .init_resource::<GameSetup>();
 
     app.add_resource(State::new(Screen::MainMenu));
 
@@ -99,12 +227,64 @@ fn main() {
         StateStage::<Screen>::default(),
     );
 
-    app.add_plugins(DefaultPlugins)
+    // Window and multisampling preferences have to be in place before the window is made
+    app.add_plugin(SettingsPlugin)
+        .add_plugin(LocalePlugin)
+        .add_plugin(LayoutPlugin)
+        .add_plugins(DefaultPlugins)
+        .add_asset::<Board>()
+        .init_asset_loader::<BoardLoader>()
         .add_plugin(GameInputPlugin)
         .add_plugin(PhasePlugin)
         .add_plugin(LerpPlugin)
         .add_plugin(MenuPlugin)
-        .add_plugin(NetworkPlugin);
+        .add_plugin(NetworkPlugin)
+        .add_plugin(SaveGamePlugin)
+        .add_plugin(ScenarioPlugin)
+        .add_plugin(SecretPlugin)
+        .add_plugin(VictoryPlugin)
+        .add_plugin(StatsPlugin)
+        .add_plugin(ProfilePlugin)
+        .add_plugin(HudPlugin)
+        .add_plugin(HoverPlugin)
+        .add_plugin(PilesPlugin)
+        .add_plugin(BotPlugin)
+        .add_plugin(CardsPlugin)
+        .add_plugin(AudioPlugin)
+        .add_plugin(CursorPlugin)
+        .add_plugin(TurnTimerPlugin)
+        .add_plugin(AlliancePlugin)
+        .add_plugin(OccupancyPlugin)
+        .add_plugin(PausePlugin)
+        .add_plugin(EliminationPlugin)
+        .add_plugin(NotificationPlugin)
+        .add_plugin(MentatPlugin)
+        .add_plugin(CapturePlugin)
+        .add_plugin(DesyncPlugin)
+        .add_plugin(DeltaPlugin)
+        .add_plugin(DebugPlugin)
+        .add_plugin(PerformancePlugin)
+        .add_plugin(DecksPlugin)
+        .add_plugin(HouseRulesPlugin)
+        .add_plugin(AccessibilityPlugin)
+        .add_plugin(FocusPlugin)
+        .add_plugin(TerritoryPlugin)
+        .add_plugin(BattleWheelPlugin)
+        .add_plugin(TutorialPlugin)
+        .add_plugin(ReferencePlugin)
+        .add_plugin(InspectPlugin)
+        .add_plugin(EmotePlugin)
+        .add_plugin(InterruptPlugin)
+        .add_plugin(StormDialPlugin)
+        .add_plugin(MaterialCachePlugin)
+        .add_plugin(TokenPoolPlugin)
+        .add_plugin(HotReloadPlugin)
+        .add_plugin(MigrationPlugin)
+        .add_plugin(GameActionPlugin)
+        .add_plugin(BugReportPlugin);
+    // Browsers draw through WebGL2 rather than wgpu
+    #[cfg(target_arch = "wasm32")]
+    app.add_plugin(bevy_webgl2::WebGL2Plugin);
 
     app.add_stage("end", SystemStage::parallel())
         .add_system_to_stage("end", propagate_visibility.system())
@@ -114,14 +294,44 @@ fn main() {
         .on_state_update(STATE_CHANGE_STAGE, Screen::Loading, load_game.system())
         .on_state_exit(RESPONSE_STAGE, Screen::Loading, tear_down.system());
 
-    app.on_state_enter(RESPONSE_STAGE, Screen::HostingGame, init_game.system())
-        .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, tear_down.system())
+    app.on_state_enter(RESPONSE_STAGE, Screen::SettingUp, init_setup.system())
+        .on_state_update(STATE_CHANGE_STAGE, Screen::SettingUp, setup_board.system())
+        .on_state_update(
+            STATE_CHANGE_STAGE,
+            Screen::SettingUp,
+            setup_faction.system(),
+        )
+        .on_state_update(STATE_CHANGE_STAGE, Screen::SettingUp, setup_decks.system())
+        .on_state_update(STATE_CHANGE_STAGE, Screen::SettingUp, setup_ui.system())
+        .on_state_update(
+            STATE_CHANGE_STAGE,
+            Screen::SettingUp,
+            setup_progress_system.system(),
+        )
+        .on_state_exit(RESPONSE_STAGE, Screen::SettingUp, tear_down_setup.system());
+
+    app.on_state_exit(RESPONSE_STAGE, Screen::HostingGame, tear_down.system())
         .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset_game.system());
 
     app.on_state_update(
         STATE_CHANGE_STAGE,
         Screen::Server,
         process_network_messages.system(),
+    )
+    .on_state_update(
+        STATE_CHANGE_STAGE,
+        Screen::HostingGame,
+        process_network_messages.system(),
+    )
+    .on_state_update(
+        STATE_CHANGE_STAGE,
+        Screen::JoinedGame,
+        process_network_messages.system(),
+    )
+    .on_state_update(
+        STATE_CHANGE_STAGE,
+        Screen::GameOver,
+        process_network_messages.system(),
     );
 
     app.run();
@@ -143,21 +353,70 @@ fn init_camera(commands: &mut Commands) {
         .spawn(CameraUiBundle::default());
 }
 
-struct LoadingBar;
+/// The fill of the loading bar for one asset group
+struct LoadingBar(usize);
+
+struct LoadingProgress;
+
+struct LoadingErrors;
 
 fn init_loading_game(
     commands: &mut Commands,
-    asset_server: Res<AssetServer>,
+    (asset_server, mut data, lobby, loaded, mut house_rules): (
+        Res<AssetServer>,
+        ResMut<Data>,
+        Res<Lobby>,
+        Res<LoadedGame>,
+        ResMut<HouseRules>,
+    ),
+    scenario: Res<LoadedScenario>,
+    mut info: ResMut<Info>,
     mut loading_assets: ResMut<LoadingAssets>,
     mut colors: ResMut<Assets<ColorMaterial>>,
 ) {
-    loading_assets.assets = asset_server.load_folder(".").unwrap();
+    // A saved game, a scenario or the lobby decides who is playing
+    if let Some(ref save) = loaded.save {
+        // Saved cards are only meaningful in the deck they were saved from
+        data.set_decks(&save.decks);
+        // The same goes for the rules it was played by
+        if let Err(e) = house_rules.set_active(save.house_rules.clone()) {
+            println!("Playing by the standard rules: {}", e);
+        }
+        info.factions_in_play = Faction::ALL
+            .iter()
+            .copied()
+            .filter(|faction| save.play_order.contains(faction))
+            .collect();
+    } else if let Some(ref scenario) = scenario.scenario {
+        info.factions_in_play = scenario.factions_in_play();
+    } else if info.factions_in_play.is_empty() {
+        info.factions_in_play = lobby.factions_in_play();
+    }
+    let factions = info.factions_in_play.clone();
+    loading_assets.groups = AssetGroup::needed(&factions)
+        .into_iter()
+        .map(|group| {
+            let handles = group
+                .paths(&data)
+                .iter()
+                .map(|path| asset_server.load_untyped(path.as_str()))
+                .collect();
+            (group, handles)
+        })
+        .collect();
+    loading_assets.manifest = data
+        .asset_manifest
+        .iter()
+        .map(|entry| asset_server.load_untyped(entry.path.as_str()))
+        .collect();
+    let segments = loading_assets.groups.len();
 
     commands
         .spawn(NodeBundle {
             style: Style {
                 size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
                 margin: Rect::all(Val::Auto),
+                flex_direction: FlexDirection::ColumnReverse,
                 justify_content: JustifyContent::Center,
                 align_items: AlignItems::Center,
                 ..Default::default()
@@ -177,57 +436,354 @@ fn init_loading_game(
                     material: colors.add(Color::BLACK.into()),
                     ..Default::default()
                 })
+                .with_children(|parent| {
+                    // One segment of the bar per group, each filling up on its own
+                    let clear = colors.add(Color::NONE.into());
+                    let fill = colors.add(Color::RED.into());
+                    for i in 0..segments {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    size: Size::new(
+                                        Val::Percent(100.0 / segments as f32),
+                                        Val::Percent(100.0),
+                                    ),
+                                    padding: Rect {
+                                        right: Val::Px(2.0),
+                                        ..Default::default()
+                                    },
+                                    ..Default::default()
+                                },
+                                material: clear.clone(),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                parent
+                                    .spawn(NodeBundle {
+                                        style: Style {
+                                            size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
+                                            ..Default::default()
+                                        },
+                                        material: fill.clone(),
+                                        ..Default::default()
+                                    })
+                                    .with(LoadingBar(i));
+                            });
+                    }
+                })
+                .spawn(TextBundle {
+                    text: Text {
+                        font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                        value: String::new(),
+                        style: TextStyle {
+                            font_size: 20.0,
+                            color: Color::ANTIQUE_WHITE,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(LoadingProgress)
+                .spawn(TextBundle {
+                    text: Text {
+                        font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                        value: String::new(),
+                        style: TextStyle {
+                            font_size: 20.0,
+                            color: Color::ANTIQUE_WHITE,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(LoadingErrors);
+        });
+}
+
+fn load_game(
+    (mut state, mut loading_assets): (ResMut<State<Screen>>, ResMut<LoadingAssets>),
+    (asset_server, mut data, keyboard_input): (Res<AssetServer>, ResMut<Data>, Res<Input<KeyCode>>),
+    (mut meshes, mut textures): (ResMut<Assets<Mesh>>, ResMut<Assets<Texture>>),
+    (boards, mut graph): (Res<Assets<Board>>, ResMut<SectorGraph>),
+    mut loading_bar: Query<(&mut Style, &LoadingBar)>,
+    mut errors: Query<&mut Text, With<LoadingErrors>>,
+    mut progress: Query<&mut Text, (With<LoadingProgress>, Without<LoadingErrors>)>,
+) {
+    let mut counts = HashMap::new();
+    let mut group_counts = Vec::new();
+    for (group, handles) in loading_assets.groups.iter() {
+        // Failed assets are done too, as far as the bar is concerned
+        let mut done = 0;
+        for handle in handles.iter() {
+            match asset_server.get_load_state(handle) {
+                LoadState::NotLoaded => *counts.entry("loading").or_insert(0) += 1,
+                LoadState::Loading => *counts.entry("loading").or_insert(0) += 1,
+                LoadState::Loaded => {
+                    *counts.entry("loaded").or_insert(0) += 1;
+                    done += 1;
+                }
+                LoadState::Failed => {
+                    *counts.entry("failed").or_insert(0) += 1;
+                    done += 1;
+                }
+            }
+        }
+        group_counts.push((group, done, handles.len()));
+    }
+    for (mut bar, &LoadingBar(i)) in loading_bar.iter_mut() {
+        if let Some(&(_, done, total)) = group_counts.get(i) {
+            bar.size.width = Val::Percent(100.0 * done as f32 / total.max(1) as f32);
+        }
+    }
+    let value = group_counts
+        .iter()
+        .find(|(_, done, total)| done < total)
+        .map(|(group, done, total)| format!("Loading {} ({}/{})", group.name(), done, total))
+        .unwrap_or_default();
+    for mut text in progress.iter_mut() {
+        if text.value != value {
+            text.value = value.clone();
+        }
+    }
+    let board = asset_server.get_handle::<Board, _>(BOARD_PATH);
+    if *counts.entry("failed").or_insert(0) == 0 {
+        if *counts.entry("loading").or_insert(0) == 0 {
+            if let Some(board) = boards.get(&board) {
+                data.set_board(board);
+                *graph = SectorGraph::new(&data.locations);
+            }
+            state.set_next(Screen::SettingUp).unwrap();
+        }
+        return;
+    }
+
+    let failed = data
+        .asset_manifest
+        .iter()
+        .zip(loading_assets.manifest.iter())
+        .filter(|(_, handle)| asset_server.get_load_state(*handle) == LoadState::Failed)
+        .map(|(entry, _)| entry)
+        .collect::<Vec<_>>();
+    let others = loading_assets
+        .groups
+        .iter()
+        .flat_map(|(_, handles)| handles.iter())
+        .filter(|handle| {
+            asset_server.get_load_state(*handle) == LoadState::Failed
+                && !loading_assets.manifest.contains(handle)
+        })
+        .filter_map(|handle| asset_server.get_handle_path(handle))
+        .map(|path| path.path().display().to_string())
+        .collect::<Vec<_>>();
+    // Nothing can be placed without a board
+    let critical = failed.iter().any(|entry| entry.critical)
+        || asset_server.get_load_state(&board) == LoadState::Failed;
+    let mut lines = vec!["Failed to load:".to_string()];
+    lines.extend(
+        failed
+            .iter()
+            .map(|entry| format!("{} ({})", entry.name, entry.path)),
+    );
+    lines.extend(others.iter().cloned());
+    if critical {
+        lines.push("The game can't start without these. Press Escape to go back.".to_string());
+    }
+    let value = lines.join("\n");
+    for mut text in errors.iter_mut() {
+        if text.value != value {
+            text.value = value.clone();
+        }
+    }
+
+    if *counts.entry("loading").or_insert(0) > 0 {
+        return;
+    }
+    if critical {
+        if keyboard_input.just_pressed(KeyCode::Escape) {
+            state.set_next(Screen::MainMenu).unwrap();
+        }
+        return;
+    }
+    // Everything else gets a stand-in so it shows up as something rather than nothing
+    let placeholder_texture = Texture::new_fill(
+        Extent3d::new(1, 1, 1),
+        TextureDimension::D2,
+        &[255, 0, 255, 255],
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    let mut placeholders = Vec::new();
+    for entry in failed.iter() {
+        println!("Using a placeholder for {} ({})", entry.name, entry.path);
+        match entry.kind {
+            AssetKind::Meshes(ref labels) => {
+                for label in labels {
+                    let handle: Handle<Mesh> =
+                        asset_server.get_handle(format!("{}#{}", entry.path, label).as_str());
+                    placeholders.push(
+                        meshes
+                            .set(handle, Mesh::from(shape::Cube { size: 0.05 }))
+                            .clone_untyped(),
+                    );
+                }
+            }
+            AssetKind::Texture => {
+                let handle: Handle<Texture> = asset_server.get_handle(entry.path.as_str());
+                placeholders.push(
+                    textures
+                        .set(handle, placeholder_texture.clone())
+                        .clone_untyped(),
+                );
+            }
+            AssetKind::Scene | AssetKind::Font => (),
+        }
+    }
+    for path in others.iter().filter(|path| path.ends_with(".png")) {
+        println!("Using a placeholder for {}", path);
+        let handle: Handle<Texture> = asset_server.get_handle(path.as_str());
+        placeholders.push(
+            textures
+                .set(handle, placeholder_texture.clone())
+                .clone_untyped(),
+        );
+    }
+    loading_assets.placeholders = placeholders;
+    if let Some(board) = boards.get(&board) {
+        data.set_board(board);
+        *graph = SectorGraph::new(&data.locations);
+    }
+    state.set_next(Screen::SettingUp).unwrap();
+}
+
+/// Stays up over the table while it is being set up
+struct SetupOverlay;
+
+struct SetupBar;
+
+struct SetupText;
+
+fn init_setup(
+    commands: &mut Commands,
+    (mut setup, asset_server, tr): (ResMut<GameSetup>, Res<AssetServer>, Res<Tr>),
+    mut colors: ResMut<Assets<ColorMaterial>>,
+) {
+    *setup = GameSetup {
+        stage: Some(SetupStage::Board),
+        ..Default::default()
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: colors.add(Color::BLACK.into()),
+            ..Default::default()
+        })
+        .with(SetupOverlay)
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(50.0), Val::Percent(10.0)),
+                        border: Rect::all(Val::Px(5.0)),
+                        ..Default::default()
+                    },
+                    material: colors.add(Color::BLACK.into()),
+                    ..Default::default()
+                })
                 .with_children(|parent| {
                     parent
                         .spawn(NodeBundle {
                             style: Style {
-                                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                                size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
                                 ..Default::default()
                             },
                             material: colors.add(Color::RED.into()),
                             ..Default::default()
                         })
-                        .with(LoadingBar);
-                });
+                        .with(SetupBar);
+                })
+                .spawn(TextBundle {
+                    text: Text {
+                        font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                        value: tr.get("setup.board"),
+                        style: TextStyle {
+                            font_size: 20.0,
+                            color: Color::ANTIQUE_WHITE,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(SetupText);
         });
 }
 
-fn load_game(
+/// Moves setup on to its next step once the last one has spawned its part of the table, and
+/// starts the game after the last
+fn setup_progress_system(
     mut state: ResMut<State<Screen>>,
-    asset_server: Res<AssetServer>,
-    loading_assets: Res<LoadingAssets>,
-    mut loading_bar: Query<&mut Style, With<LoadingBar>>,
+    (mut setup, info, tr): (ResMut<GameSetup>, Res<Info>, Res<Tr>),
+    mut bar: Query<&mut Style, With<SetupBar>>,
+    mut text: Query<&mut Text, With<SetupText>>,
 ) {
-    let mut counts = HashMap::new();
-    for handle in loading_assets.assets.iter() {
-        match asset_server.get_load_state(handle) {
-            LoadState::NotLoaded => *counts.entry("loading").or_insert(0) += 1,
-            LoadState::Loading => *counts.entry("loading").or_insert(0) += 1,
-            LoadState::Loaded => *counts.entry("loaded").or_insert(0) += 1,
-            LoadState::Failed => *counts.entry("failed").or_insert(0) += 1,
+    if !setup.step_done {
+        return;
+    }
+    setup.step_done = false;
+    setup.steps += 1;
+    setup.stage = match setup.stage {
+        Some(SetupStage::Board) => Some(SetupStage::Factions),
+        Some(SetupStage::Factions) if setup.factions < info.factions_in_play.len() => {
+            Some(SetupStage::Factions)
         }
+        Some(SetupStage::Factions) => Some(SetupStage::Decks),
+        Some(SetupStage::Decks) => Some(SetupStage::Ui),
+        _ => None,
+    };
+    // The board, every faction, the decks and the turn order tiles
+    let total = info.factions_in_play.len() + 3;
+    for mut style in bar.iter_mut() {
+        style.size.width = Val::Percent(100.0 * setup.steps as f32 / total as f32);
     }
-    loading_bar.iter_mut().next().map(|mut bar| {
-        bar.size.width = Val::Percent(
-            100.0
-                * (*counts.entry("loaded").or_insert(0) as f32
-                    / loading_assets.assets.len() as f32),
-        );
-    });
-    if *counts.entry("loading").or_insert(0) == 0 {
-        state.set_next(Screen::HostingGame).unwrap();
+    let value = match setup.stage {
+        Some(SetupStage::Board) => tr.get("setup.board"),
+        Some(SetupStage::Factions) => tr.fmt(
+            "setup.faction",
+            &[&tr.faction(info.factions_in_play[setup.factions])],
+        ),
+        Some(SetupStage::Decks) => tr.get("setup.decks"),
+        Some(SetupStage::Ui) => tr.get("setup.ui"),
+        None => {
+            state.set_next(Screen::HostingGame).unwrap();
+            return;
+        }
+    };
+    for mut text in text.iter_mut() {
+        text.value = value.clone();
+    }
+}
+
+fn tear_down_setup(commands: &mut Commands, overlay: Query<Entity, With<SetupOverlay>>) {
+    for entity in overlay.iter() {
+        commands.despawn_recursive(entity);
     }
 }
 
-fn init_game(
+/// Spawns the board, its territories, the light and the storm, and starts the random sequence
+fn setup_board(
     commands: &mut Commands,
-    data: Res<Data>,
+    (mut setup, data, asset_server): (ResMut<GameSetup>, Res<Data>, Res<AssetServer>),
     mut info: ResMut<Info>,
-    asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut colors: ResMut<Assets<ColorMaterial>>,
-    network: Res<Network>,
+    (mut game_rng, network): (ResMut<GameRng>, Res<Network>),
 ) {
+    if !setup.running(SetupStage::Board) {
+        return;
+    }
     // Board
     info.default_clickables.push(
         commands
@@ -236,6 +792,7 @@ fn init_game(
             ))))
             .with(ScreenEntity)
             .with(data.camera_nodes.board)
+            .with(Tooltip("Arrakis".to_string()))
             .with_children(|parent| {
                 parent.spawn_scene(asset_server.get_handle("board.gltf"));
             })
@@ -243,57 +800,19 @@ fn init_game(
             .unwrap(),
     );
 
-    commands
-        .spawn(TextBundle {
-            style: Style {
-                position_type: PositionType::Absolute,
-                position: Rect {
-                    top: Val::Px(5.0),
-                    left: Val::Px(5.0),
-                    ..Default::default()
-                },
-                ..Default::default()
-            },
-            text: Text {
-                font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
-                value: "Test".to_string(),
-                style: TextStyle {
-                    font_size: 40.0,
-                    color: Color::ANTIQUE_WHITE,
-                    ..Default::default()
-                },
-            },
-            ..Default::default()
-        })
-        .with(ScreenEntity)
-        .with(PhaseText);
-
     for location in data.locations.iter() {
         commands
             .spawn((location.clone(),))
             .with(ScreenEntity)
             .with_children(|parent| {
                 for (&sector, nodes) in location.sectors.iter() {
-                    let vertices = nodes
-                        .vertices
-                        .iter()
-                        .map(|p| Point3::new(p.x, 0.01, -p.y))
-                        .collect();
-                    let indices = nodes
-                        .indices
-                        .chunks_exact(3)
-                        .map(|chunk| {
-                            Point3::new(chunk[0] as usize, chunk[1] as usize, chunk[2] as usize)
-                        })
-                        .collect();
                     parent
-                        .spawn(ColliderBundle::new(ShapeHandle::new(TriMesh::new(
-                            vertices, indices, None,
-                        ))))
+                        .spawn(ColliderBundle::new(sector_shape(nodes)))
                         .with(LocationSector {
                             location: location.clone(),
                             sector,
-                        });
+                        })
+                        .with(Tooltip(format!("{}, sector {}", location.name, sector)));
                 }
             });
 
@@ -308,37 +827,53 @@ fn init_game(
             transform: Transform::from_translation(Vec3::new(10.0, 10.0, 10.0)),
             ..Default::default()
         })
+        .with(KeyLight)
         .with(ScreenEntity);
 
     commands.spawn((Storm::default(),)).with(ScreenEntity);
 
-    let mut rng = rand::thread_rng();
+    // Start the game from the top of the sequence so it can be replayed from the seed, which only
+    // means anything on the host
+    game_rng.restart();
+    if network.network_type != NetworkType::Client {
+        println!("Game seed: {}", game_rng.seed);
+    }
+
+    if info.factions_in_play.is_empty() {
+        info.factions_in_play = Faction::BASE.to_vec();
+    }
+    info.play_order.clear();
+    setup.finish_step();
+}
 
-    info.factions_in_play = vec![
-        Faction::Atreides,
-        Faction::BeneGesserit,
-        Faction::Emperor,
-        Faction::Fremen,
-        Faction::Harkonnen,
-        Faction::SpacingGuild,
-    ];
+/// Seats the next faction in play: its shield, leaders, troops, starting spice and player
+fn setup_faction(
+    commands: &mut Commands,
+    (mut setup, data, asset_server, tr): (ResMut<GameSetup>, Res<Data>, Res<AssetServer>, Res<Tr>),
+    (mut materials, mut cache, mut pool): (
+        ResMut<Assets<StandardMaterial>>,
+        ResMut<MaterialCache>,
+        ResMut<TokenPool>,
+    ),
+    (mut info, mut game_rng): (ResMut<Info>, ResMut<GameRng>),
+    (network, mut server): (Res<Network>, Query<&mut Server>),
+) {
+    if !setup.running(SetupStage::Factions) {
+        return;
+    }
+    let faction = info.factions_in_play[setup.factions];
 
     let shield_face = asset_server.get_handle("shield.gltf#Mesh0/Primitive1");
     let shield_back = asset_server.get_handle("shield.gltf#Mesh0/Primitive2");
-
     let card_face = asset_server.get_handle("card.gltf#Mesh0/Primitive0");
     let card_back = asset_server.get_handle("card.gltf#Mesh0/Primitive1");
-
-    let prediction_back_texture = asset_server.get_handle("treachery/treachery_back.png");
-    let prediction_back_material = materials.add(StandardMaterial {
-        albedo_texture: Some(prediction_back_texture),
-        ..Default::default()
-    });
-
+    let prediction_back_material = cache.textured(
+        "treachery/treachery_back.png",
+        &asset_server,
+        &mut materials,
+    );
     let little_token = asset_server.get_handle("little_token.gltf#Mesh0/Primitive0");
     let big_token = asset_server.get_handle("big_token.gltf#Mesh0/Primitive0");
-    let spice_token = asset_server.get_handle("spice_token.gltf#Mesh0/Primitive0");
-
     let little_token_shape = ShapeHandle::new(
         ConvexHull::try_from_points(&Cylinder::<f32>::new(0.0018, 0.03).to_trimesh(32).coords)
             .unwrap(),
@@ -347,286 +882,230 @@ fn init_game(
         ConvexHull::try_from_points(&Cylinder::<f32>::new(0.0035, 0.06).to_trimesh(32).coords)
             .unwrap(),
     );
-    let spice_token_shape = ShapeHandle::new(
-        ConvexHull::try_from_points(&Cylinder::<f32>::new(0.0018, 0.017).to_trimesh(32).coords)
-            .unwrap(),
-    );
-
     let shield_shape = ShapeHandle::new(Cuboid::new(Vector3::new(0.525, 0.285, 0.06)));
     let faction_prediction_shape =
         ShapeHandle::new(Cuboid::new(Vector3::new(0.125, 0.0005, 0.18) * 0.01));
-    let turn_prediction_shape =
-        ShapeHandle::new(Cuboid::new(Vector3::new(0.125, 0.0005, 0.18) * 0.006));
-
-    let turn_tiles = data.ui_structure.get_turn_tiles();
-
-    info.play_order = info
-        .factions_in_play
-        .iter()
-        .enumerate()
-        .map(|(i, &faction)| {
-            let faction_code = match faction {
-                Faction::Atreides => "at",
-                Faction::Harkonnen => "hk",
-                Faction::Emperor => "em",
-                Faction::SpacingGuild => "sg",
-                Faction::Fremen => "fr",
-                Faction::BeneGesserit => "bg",
-            };
-
-            let logo_texture =
-                asset_server.get_handle(format!("tokens/{}_logo.png", faction_code).as_str());
 
-            commands
-                .spawn(NodeBundle {
-                    style: Style {
-                        position_type: PositionType::Absolute,
-                        position: turn_tiles[i].top_left(),
-                        size: turn_tiles[i].size(),
-                        align_items: AlignItems::FlexStart,
-                        padding: Rect {
-                            top: Val::Percent(1.0),
-                            bottom: Val::Percent(1.0),
-                            left: Val::Percent(1.0),
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    },
-                    material: colors.add(if i % 2 == 0 {
-                        (Color::RED + Color::rgba_linear(0.0, 0.0, 0.0, -0.5)).into()
-                    } else {
-                        (Color::GREEN + Color::rgba_linear(0.0, 0.0, 0.0, -0.5)).into()
-                    }),
-                    ..Default::default()
-                })
-                .with(ScreenEntity)
-                .with_children(|parent| {
-                    parent
-                        .spawn(ImageBundle {
-                            style: Style {
-                                size: Size::new(Val::Px(20.0), Val::Px(20.0)),
-                                ..Default::default()
-                            },
-                            material: colors.add(logo_texture.into()),
-                            ..Default::default()
-                        })
-                        .spawn(TextBundle {
-                            text: Text {
-                                font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
-                                value: faction.to_string(),
-                                style: TextStyle {
-                                    font_size: 20.0,
-                                    color: Color::ANTIQUE_WHITE,
-                                    ..Default::default()
-                                },
-                                ..Default::default()
-                            },
-                            ..Default::default()
-                        });
-                });
+    let faction_code = faction.code();
 
-            let shield_front_texture = asset_server
-                .get_handle(format!("shields/{}_shield_front.png", faction_code).as_str());
-            let shield_back_texture = asset_server
-                .get_handle(format!("shields/{}_shield_back.png", faction_code).as_str());
-            let shield_front_material = materials.add(StandardMaterial {
-                albedo_texture: Some(shield_front_texture),
+    let shield_front_material = cache.textured(
+        &format!("shields/{}_shield_front.png", faction_code),
+        &asset_server,
+        &mut materials,
+    );
+    let shield_back_material = cache.textured(
+        &format!("shields/{}_shield_back.png", faction_code),
+        &asset_server,
+        &mut materials,
+    );
+    commands
+        .spawn(
+            ColliderBundle::new(shield_shape.clone())
+                .with_transform(Transform::from_translation(Vec3::new(0.0, 0.27, 1.34))),
+        )
+        .with(ScreenEntity)
+        .with(data.camera_nodes.shield)
+        .with_bundle(UniqueBundle::new(faction))
+        .with(Tooltip(tr.fmt("tooltip.shield", &[&tr.faction(faction)])))
+        .with_children(|parent| {
+            parent.spawn(PbrBundle {
+                mesh: shield_face.clone(),
+                material: shield_front_material,
                 ..Default::default()
             });
-            let shield_back_material = materials.add(StandardMaterial {
-                albedo_texture: Some(shield_back_texture),
+            parent.spawn(PbrBundle {
+                mesh: shield_back.clone(),
+                material: shield_back_material,
                 ..Default::default()
             });
-            commands
-                .spawn(
-                    ColliderBundle::new(shield_shape.clone())
-                        .with_transform(Transform::from_translation(Vec3::new(0.0, 0.27, 1.34))),
-                )
-                .with(ScreenEntity)
-                .with(data.camera_nodes.shield)
-                .with_bundle(UniqueBundle::new(faction))
-                .with_children(|parent| {
-                    parent.spawn(PbrBundle {
-                        mesh: shield_face.clone(),
-                        material: shield_front_material,
-                        ..Default::default()
-                    });
-                    parent.spawn(PbrBundle {
-                        mesh: shield_back.clone(),
-                        material: shield_back_material,
-                        ..Default::default()
-                    });
-                });
-            let prediction_front_texture = asset_server
-                .get_handle(format!("predictions/prediction_{}.png", faction_code).as_str());
-            let prediction_front_material = materials.add(StandardMaterial {
-                albedo_texture: Some(prediction_front_texture),
+        });
+    let prediction_front_material = cache.textured(
+        &format!("predictions/prediction_{}.png", faction_code),
+        &asset_server,
+        &mut materials,
+    );
+    commands
+        .spawn(ColliderBundle::new(faction_prediction_shape.clone()))
+        .with(ScreenEntity)
+        .with_bundle(UniqueBundle::new(Faction::BeneGesserit))
+        .with(FactionPredictionCard { faction })
+        .with(Tooltip(
+            tr.fmt("tooltip.faction_prediction", &[&tr.faction(faction)]),
+        ))
+        .with_children(|parent| {
+            parent.spawn(PbrBundle {
+                mesh: card_face.clone(),
+                material: prediction_front_material,
                 ..Default::default()
             });
-            commands
-                .spawn(ColliderBundle::new(faction_prediction_shape.clone()))
-                .with(ScreenEntity)
-                .with_bundle(UniqueBundle::new(Faction::BeneGesserit))
-                .with(FactionPredictionCard { faction })
-                .with_children(|parent| {
-                    parent.spawn(PbrBundle {
-                        mesh: card_face.clone(),
-                        material: prediction_front_material,
-                        ..Default::default()
-                    });
-                    parent.spawn(PbrBundle {
-                        mesh: card_back.clone(),
-                        material: prediction_back_material.clone(),
-                        ..Default::default()
-                    });
-                });
+            parent.spawn(PbrBundle {
+                mesh: card_back.clone(),
+                material: prediction_back_material.clone(),
+                ..Default::default()
+            });
+        });
 
-            for (i, leader) in data
-                .leaders
-                .iter()
-                .filter(|l| l.faction == faction)
-                .enumerate()
-            {
-                let texture =
-                    asset_server.get_handle(format!("leaders/{}.png", leader.texture).as_str());
-                let material = materials.add(StandardMaterial {
-                    albedo_texture: Some(texture),
+    for (i, leader) in data
+        .leaders
+        .iter()
+        .filter(|l| l.faction == faction)
+        .enumerate()
+    {
+        let material = cache.textured(
+            &format!("leaders/{}.png", leader.texture),
+            &asset_server,
+            &mut materials,
+        );
+
+        commands
+            .spawn(
+                ColliderBundle::new(big_token_shape.clone())
+                    .with_transform(Transform::from_translation(data.token_nodes.leaders[i])),
+            )
+            .with(ScreenEntity)
+            .with_bundle(UniqueBundle::new(faction))
+            .with(leader.clone())
+            .with(Tooltip(leader.name.clone()))
+            .with_children(|parent| {
+                parent.spawn(PbrBundle {
+                    mesh: big_token.clone(),
+                    material,
                     ..Default::default()
                 });
-
-                commands
-                    .spawn(
-                        ColliderBundle::new(big_token_shape.clone()).with_transform(
-                            Transform::from_translation(data.token_nodes.leaders[i]),
-                        ),
-                    )
-                    .with(ScreenEntity)
-                    .with_bundle(UniqueBundle::new(faction))
-                    .with_children(|parent| {
-                        parent.spawn(PbrBundle {
-                            mesh: big_token.clone(),
-                            material,
-                            ..Default::default()
-                        });
-                    });
-            }
-
-            let troop_texture =
-                asset_server.get_handle(format!("tokens/{}_troop.png", faction_code).as_str());
-            let troop_material = materials.add(StandardMaterial {
-                albedo_texture: Some(troop_texture),
-                ..Default::default()
             });
+    }
 
-            for i in 0..20 {
-                commands
-                    .spawn(
-                        ColliderBundle::new(little_token_shape.clone()).with_transform(
-                            Transform::from_translation(
-                                data.token_nodes.fighters[0] + (i as f32 * 0.0036 * Vec3::unit_y()),
-                            ),
-                        ),
-                    )
-                    .with(ScreenEntity)
-                    .with_bundle(UniqueBundle::new(faction))
-                    .with(Troop {
-                        value: 1,
-                        location: None,
-                    })
-                    .with_children(|parent| {
-                        parent.spawn(PbrBundle {
-                            mesh: little_token.clone(),
-                            material: troop_material.clone(),
-                            ..Default::default()
-                        });
-                    });
-            }
+    let troop_material = pool.troop_material(faction);
 
-            let spice_1_texture = asset_server.get_handle("tokens/spice_1.png");
-            let spice_1_material = materials.add(StandardMaterial {
-                albedo_texture: Some(spice_1_texture),
-                ..Default::default()
-            });
-            let spice_2_texture = asset_server.get_handle("tokens/spice_2.png");
-            let spice_2_material = materials.add(StandardMaterial {
-                albedo_texture: Some(spice_2_texture),
-                ..Default::default()
-            });
-            let spice_5_texture = asset_server.get_handle("tokens/spice_5.png");
-            let spice_5_material = materials.add(StandardMaterial {
-                albedo_texture: Some(spice_5_texture),
-                ..Default::default()
+    for i in 0..20 {
+        commands
+            .spawn(
+                ColliderBundle::new(little_token_shape.clone()).with_transform(
+                    Transform::from_translation(
+                        data.token_nodes.fighters[0] + (i as f32 * 0.0036 * Vec3::unit_y()),
+                    ),
+                ),
+            )
+            .with(ScreenEntity)
+            .with_bundle(UniqueBundle::new(faction))
+            .with(Troop {
+                value: 1,
+                location: None,
+            })
+            .with(Tooltip(tr.fmt("tooltip.troop", &[&tr.faction(faction)])))
+            .with_children(|parent| {
+                parent.spawn(PbrBundle {
+                    mesh: little_token.clone(),
+                    material: troop_material.clone(),
+                    ..Default::default()
+                });
             });
-            let spice_10_texture = asset_server.get_handle("tokens/spice_10.png");
-            let spice_10_material = materials.add(StandardMaterial {
-                albedo_texture: Some(spice_10_texture),
-                ..Default::default()
+    }
+
+    // Waits beside the reserves until the Ixians have troops on the board
+    if faction == Faction::Ixian {
+        commands
+            .spawn(
+                ColliderBundle::new(big_token_shape.clone())
+                    .with_transform(Transform::from_translation(data.token_nodes.fighters[1])),
+            )
+            .with(ScreenEntity)
+            .with(HiddenMobileStronghold::default())
+            .with(Tooltip(tr.get("tooltip.hms")))
+            .with_children(|parent| {
+                parent.spawn(PbrBundle {
+                    mesh: big_token.clone(),
+                    material: materials.add(StandardMaterial {
+                        albedo: faction.color(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                });
             });
+    }
 
-            let (_, _, spice) = faction.initial_values();
-
-            let (tens, fives, twos, ones) = divide_spice(spice);
-            for (i, (value, s)) in (0..tens)
-                .zip(std::iter::repeat((10, 0)))
-                .chain((0..fives).zip(std::iter::repeat((5, 1))))
-                .chain((0..twos).zip(std::iter::repeat((2, 2))))
-                .chain((0..ones).zip(std::iter::repeat((1, 3))))
-            {
-                let material = match value {
-                    1 => spice_1_material.clone(),
-                    2 => spice_2_material.clone(),
-                    5 => spice_5_material.clone(),
-                    _ => spice_10_material.clone(),
-                };
-                commands
-                    .spawn(
-                        ColliderBundle::new(spice_token_shape.clone()).with_transform(
-                            Transform::from_translation(
-                                data.token_nodes.spice[s] + (i as f32 * 0.0036 * Vec3::unit_y()),
-                            ),
-                        ),
-                    )
-                    .with(ScreenEntity)
-                    .with_bundle(UniqueBundle::new(faction))
-                    .with(Spice { value })
-                    .with_children(|parent| {
-                        parent.spawn(PbrBundle {
-                            mesh: spice_token.clone(),
-                            material,
-                            ..Default::default()
-                        });
-                    });
-            }
+    let (_, _, spice) = faction.initial_values();
+
+    let (tens, fives, twos, ones) = divide_spice(spice);
+    for (i, (value, s)) in (0..tens)
+        .zip(std::iter::repeat((10, 0)))
+        .chain((0..fives).zip(std::iter::repeat((5, 1))))
+        .chain((0..twos).zip(std::iter::repeat((2, 2))))
+        .chain((0..ones).zip(std::iter::repeat((1, 3))))
+    {
+        let token = pool.spawn_spice(
+            commands,
+            value,
+            None,
+            Transform::from_translation(
+                data.token_nodes.spice[s] + (i as f32 * 0.0036 * Vec3::unit_y()),
+            ),
+        );
+        commands.insert(token, UniqueBundle::new(faction));
+    }
 
-            commands
-                .spawn((Player::new(faction, &data.leaders),))
-                .with(ScreenEntity);
+    commands
+        .spawn((Player::new(faction, &data.leaders),))
+        .with(ScreenEntity);
 
-            if faction == Faction::BeneGesserit {
-                commands.with(Prediction {
-                    faction: None,
-                    turn: None,
-                });
-            }
+    if faction == Faction::BeneGesserit {
+        commands.with(Prediction {
+            faction: None,
+            turn: None,
+        });
+    }
 
-            commands.current_entity().unwrap()
-        })
-        .collect();
+    info.play_order.push(commands.current_entity().unwrap());
+    setup.factions += 1;
+    // Clients are told the order the host drew rather than drawing their own
+    if setup.factions == info.factions_in_play.len() && network.network_type != NetworkType::Client
+    {
+        // Players are seated in the order factions are in play
+        let mut order = (0..info.play_order.len()).collect::<Vec<_>>();
+        order.shuffle(&mut game_rng.rng);
+        info.play_order = order.iter().map(|&i| info.play_order[i]).collect();
+        if let Some(mut server) = server.iter_mut().next() {
+            server.broadcast(&ServerEvent::PlayOrder {
+                factions: order.iter().map(|&i| info.factions_in_play[i]).collect(),
+            });
+        }
+    }
+    setup.finish_step();
+}
 
-    info.play_order.shuffle(&mut rng);
+/// Stacks up the prediction cards and the treachery, traitor, spice and storm decks
+fn setup_decks(
+    commands: &mut Commands,
+    (mut setup, data, asset_server, tr): (ResMut<GameSetup>, Res<Data>, Res<AssetServer>, Res<Tr>),
+    (mut materials, mut cache): (ResMut<Assets<StandardMaterial>>, ResMut<MaterialCache>),
+    mut info: ResMut<Info>,
+    (network, mut game_rng): (Res<Network>, ResMut<GameRng>),
+) {
+    if !setup.running(SetupStage::Decks) {
+        return;
+    }
+    let card_face = asset_server.get_handle("card.gltf#Mesh0/Primitive0");
+    let card_back = asset_server.get_handle("card.gltf#Mesh0/Primitive1");
+    let prediction_back_material = cache.textured(
+        "treachery/treachery_back.png",
+        &asset_server,
+        &mut materials,
+    );
+    let turn_prediction_shape =
+        ShapeHandle::new(Cuboid::new(Vector3::new(0.125, 0.0005, 0.18) * 0.006));
 
     (1..=15).for_each(|turn| {
-        let prediction_front_texture =
-            asset_server.get_handle(format!("predictions/prediction_t{}.png", turn).as_str());
-        let prediction_front_material = materials.add(StandardMaterial {
-            albedo_texture: Some(prediction_front_texture),
-            ..Default::default()
-        });
+        let prediction_front_material = cache.textured(
+            &format!("predictions/prediction_t{}.png", turn),
+            &asset_server,
+            &mut materials,
+        );
         commands
             .spawn(ColliderBundle::new(turn_prediction_shape.clone()))
             .with(ScreenEntity)
             .with_bundle(UniqueBundle::new(Faction::BeneGesserit))
             .with(TurnPredictionCard { turn })
+            .with(Tooltip(tr.fmt("tooltip.turn_prediction", &[&turn])))
             .with_children(|parent| {
                 parent.spawn(PbrBundle {
                     mesh: card_face.clone(),
@@ -641,93 +1120,123 @@ fn init_game(
             });
     });
 
-    let treachery_back_texture = asset_server.get_handle("treachery/treachery_back.png");
-    let treachery_back_material = materials.add(StandardMaterial {
-        albedo_texture: Some(treachery_back_texture),
-        ..Default::default()
-    });
+    // Clients only learn which card is which once the server tells them
+    let hidden = network.network_type == NetworkType::Client;
+    // Every machine has the same slots, but only the host knows which card sits in which. Clients
+    // number theirs in order, since they don't know what's in them anyway.
+    let mut deal_slots = |mut slots: Vec<u32>| {
+        if !hidden {
+            slots.shuffle(&mut game_rng.rng);
+        }
+        slots
+    };
+    let treachery_slots = deal_slots((0..data.treachery_cards.len() as u32).collect());
+
+    let treachery_back_material = cache.textured(
+        "treachery/treachery_back.png",
+        &asset_server,
+        &mut materials,
+    );
 
     for (i, card) in data.treachery_cards.iter().enumerate() {
-        let treachery_front_texture = asset_server
-            .get_handle(format!("treachery/treachery_{}.png", card.texture.as_str()).as_str());
-        let treachery_front_material = materials.add(StandardMaterial {
-            albedo_texture: Some(treachery_front_texture),
-            ..Default::default()
-        });
+        let treachery_front_material = cache.textured(
+            &format!("treachery/treachery_{}.png", card.texture),
+            &asset_server,
+            &mut materials,
+        );
 
         commands
             .spawn((
-                card.clone(),
                 Transform::from_translation(Vec3::new(1.23, 0.0049 + (i as f32 * 0.001), -0.87))
                     * Transform::from_rotation(Quat::from_rotation_z(PI)),
                 GlobalTransform::default(),
             ))
-            .with(ScreenEntity)
-            .with_children(|parent| {
-                parent.spawn(PbrBundle {
-                    mesh: card_face.clone(),
-                    material: treachery_front_material,
-                    ..Default::default()
-                });
-                parent.spawn(PbrBundle {
-                    mesh: card_back.clone(),
-                    material: treachery_back_material.clone(),
-                    ..Default::default()
-                });
+            .with(ScreenEntity);
+        if hidden {
+            commands.with(Secret::<TreacheryCard>::hidden(treachery_slots[i]));
+        } else {
+            commands
+                .with(card.clone())
+                .with(Secret::revealed(treachery_slots[i], card.clone()));
+        }
+        commands.with_children(|parent| {
+            parent.spawn(PbrBundle {
+                mesh: card_face.clone(),
+                material: if hidden {
+                    treachery_back_material.clone()
+                } else {
+                    treachery_front_material
+                },
+                ..Default::default()
+            });
+            parent.spawn(PbrBundle {
+                mesh: card_back.clone(),
+                material: treachery_back_material.clone(),
+                ..Default::default()
             });
+        });
     }
 
-    let traitor_back_texture = asset_server.get_handle("traitor/traitor_back.png");
-    let traitor_back_material = materials.add(StandardMaterial {
-        albedo_texture: Some(traitor_back_texture),
-        ..Default::default()
-    });
+    let traitor_back_material =
+        cache.textured("traitor/traitor_back.png", &asset_server, &mut materials);
 
-    for (i, card) in data.leaders.iter().enumerate() {
-        let traitor_front_texture = asset_server
-            .get_handle(format!("traitor/traitor_{}.png", card.texture.as_str()).as_str());
-        let traitor_front_material = materials.add(StandardMaterial {
-            albedo_texture: Some(traitor_front_texture),
-            ..Default::default()
-        });
+    // Only the leaders of factions in play go into the deck
+    let traitors = data
+        .leaders
+        .iter()
+        .filter(|card| info.factions_in_play.contains(&card.faction))
+        .collect::<Vec<_>>();
+    let traitor_slots = deal_slots((0..traitors.len() as u32).collect());
+    for (n, card) in traitors.into_iter().enumerate() {
+        let traitor_front_material = cache.textured(
+            &format!("traitor/traitor_{}.png", card.texture),
+            &asset_server,
+            &mut materials,
+        );
 
         commands
             .spawn((
-                TraitorCard {
-                    leader: card.clone(),
-                },
-                Transform::from_translation(Vec3::new(1.23, 0.0049 + (i as f32 * 0.001), -0.3))
+                Transform::from_translation(Vec3::new(1.23, 0.0049 + (n as f32 * 0.001), -0.3))
                     * Transform::from_rotation(Quat::from_rotation_z(PI)),
                 GlobalTransform::default(),
             ))
-            .with(ScreenEntity)
-            .with_children(|parent| {
-                parent.spawn(PbrBundle {
-                    mesh: card_face.clone(),
-                    material: traitor_front_material,
-                    ..Default::default()
-                });
-                parent.spawn(PbrBundle {
-                    mesh: card_back.clone(),
-                    material: traitor_back_material.clone(),
-                    ..Default::default()
-                });
+            .with(ScreenEntity);
+        let card = TraitorCard {
+            leader: card.clone(),
+        };
+        if hidden {
+            commands.with(Secret::<TraitorCard>::hidden(traitor_slots[n]));
+        } else {
+            commands
+                .with(card.clone())
+                .with(Secret::revealed(traitor_slots[n], card));
+        }
+        commands.with_children(|parent| {
+            parent.spawn(PbrBundle {
+                mesh: card_face.clone(),
+                material: if hidden {
+                    traitor_back_material.clone()
+                } else {
+                    traitor_front_material
+                },
+                ..Default::default()
+            });
+            parent.spawn(PbrBundle {
+                mesh: card_back.clone(),
+                material: traitor_back_material.clone(),
+                ..Default::default()
             });
+        });
     }
 
-    let spice_back_texture = asset_server.get_handle("spice/spice_back.png");
-    let spice_back_material = materials.add(StandardMaterial {
-        albedo_texture: Some(spice_back_texture),
-        ..Default::default()
-    });
+    let spice_back_material = cache.textured("spice/spice_back.png", &asset_server, &mut materials);
 
     for (i, card) in data.spice_cards.iter().enumerate() {
-        let spice_front_texture =
-            asset_server.get_handle(format!("spice/spice_{}.png", card.texture.as_str()).as_str());
-        let spice_front_material = materials.add(StandardMaterial {
-            albedo_texture: Some(spice_front_texture),
-            ..Default::default()
-        });
+        let spice_front_material = cache.textured(
+            &format!("spice/spice_{}.png", card.texture),
+            &asset_server,
+            &mut materials,
+        );
 
         commands
             .spawn((
@@ -751,19 +1260,14 @@ fn init_game(
             });
     }
 
-    let storm_back_texture = asset_server.get_handle("storm/storm_back.png");
-    let storm_back_material = materials.add(StandardMaterial {
-        albedo_texture: Some(storm_back_texture),
-        ..Default::default()
-    });
+    let storm_back_material = cache.textured("storm/storm_back.png", &asset_server, &mut materials);
 
     for val in 1..7 {
-        let storm_front_texture =
-            asset_server.get_handle(format!("storm/storm_{}.png", val).as_str());
-        let storm_front_material = materials.add(StandardMaterial {
-            albedo_texture: Some(storm_front_texture),
-            ..Default::default()
-        });
+        let storm_front_material = cache.textured(
+            &format!("storm/storm_{}.png", val),
+            &asset_server,
+            &mut materials,
+        );
 
         commands
             .spawn((
@@ -797,6 +1301,7 @@ fn init_game(
             )
             .with(ScreenEntity)
             .with(data.camera_nodes.treachery)
+            .with(Tooltip(tr.get("tooltip.treachery_deck")))
             .current_entity()
             .unwrap(),
     );
@@ -809,6 +1314,7 @@ fn init_game(
             )
             .with(ScreenEntity)
             .with(data.camera_nodes.traitor)
+            .with(Tooltip(tr.get("tooltip.traitor_deck")))
             .current_entity()
             .unwrap(),
     );
@@ -821,6 +1327,34 @@ fn init_game(
             )
             .with(ScreenEntity)
             .with(data.camera_nodes.spice)
+            .with(Tooltip(tr.get("tooltip.spice_deck")))
+            .current_entity()
+            .unwrap(),
+    );
+
+    // The discard piles sit beside their decks
+    info.default_clickables.push(
+        commands
+            .spawn(
+                ColliderBundle::new(deck_shape.clone())
+                    .with_transform(Transform::from_translation(Vec3::new(1.53, 0.0049, -0.87))),
+            )
+            .with(ScreenEntity)
+            .with(data.camera_nodes.treachery)
+            .with(Tooltip(tr.get("tooltip.treachery_discard")))
+            .current_entity()
+            .unwrap(),
+    );
+
+    info.default_clickables.push(
+        commands
+            .spawn(
+                ColliderBundle::new(deck_shape.clone())
+                    .with_transform(Transform::from_translation(Vec3::new(1.53, 0.0049, 0.3))),
+            )
+            .with(ScreenEntity)
+            .with(data.camera_nodes.spice)
+            .with(Tooltip(tr.get("tooltip.spice_discard")))
             .current_entity()
             .unwrap(),
     );
@@ -833,40 +1367,553 @@ fn init_game(
             )
             .with(ScreenEntity)
             .with(data.camera_nodes.storm)
+            .with(Tooltip(tr.get("tooltip.storm_deck")))
             .current_entity()
             .unwrap(),
     );
+    setup.finish_step();
+}
+
+/// Lays out a turn order tile for every faction in play
+fn setup_ui(
+    commands: &mut Commands,
+    (mut setup, data, asset_server): (ResMut<GameSetup>, Res<Data>, Res<AssetServer>),
+    info: Res<Info>,
+    mut colors: ResMut<Assets<ColorMaterial>>,
+) {
+    if !setup.running(SetupStage::Ui) {
+        return;
+    }
+    let turn_tiles = data.ui_structure.get_turn_tiles();
+    let ornithopter_texture = asset_server.get_handle("bonuses/ornothopters.png");
+    for (i, &faction) in info.factions_in_play.iter().enumerate() {
+        let faction_code = faction.code();
+        let logo_texture =
+            asset_server.get_handle(format!("tokens/{}_logo.png", faction_code).as_str());
+
+        commands
+            .spawn(NodeBundle {
+                style: Style {
+                    align_items: AlignItems::FlexStart,
+                    padding: Rect {
+                        top: Val::Percent(1.0),
+                        bottom: Val::Percent(1.0),
+                        left: Val::Percent(1.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                material: colors.add(if i % 2 == 0 {
+                    (Color::RED + Color::rgba_linear(0.0, 0.0, 0.0, -0.5)).into()
+                } else {
+                    (Color::GREEN + Color::rgba_linear(0.0, 0.0, 0.0, -0.5)).into()
+                }),
+                ..Default::default()
+            })
+            .with(ScreenEntity)
+            .with(TurnTile(faction))
+            .with(Anchored::new(turn_tiles[i].clone()))
+            .with_children(|parent| {
+                parent
+                    .spawn(ImageBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(20.0), Val::Px(20.0)),
+                            ..Default::default()
+                        },
+                        material: colors.add(logo_texture.into()),
+                        ..Default::default()
+                    })
+                    .spawn(TextBundle {
+                        text: Text {
+                            font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                            value: faction.to_string(),
+                            style: TextStyle {
+                                font_size: 20.0,
+                                color: Color::ANTIQUE_WHITE,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .spawn(ImageBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(20.0), Val::Px(20.0)),
+                            margin: Rect {
+                                left: Val::Px(6.0),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        material: colors.add(ornithopter_texture.clone().into()),
+                        visible: Visible {
+                            is_visible: false,
+                            is_transparent: true,
+                        },
+                        ..Default::default()
+                    })
+                    .with(OrnithopterIcon(faction))
+                    .spawn(TextBundle {
+                        style: Style {
+                            margin: Rect {
+                                left: Val::Px(6.0),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        text: Text {
+                            font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                            style: TextStyle {
+                                font_size: 14.0,
+                                color: Color::ANTIQUE_WHITE,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .with(LatencyText(faction))
+                    .spawn(TextBundle {
+                        style: Style {
+                            margin: Rect {
+                                left: Val::Px(6.0),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        text: Text {
+                            font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                            style: TextStyle {
+                                font_size: 14.0,
+                                color: Color::ANTIQUE_WHITE,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .with(PlayerStatusText(faction))
+                    .spawn(TextBundle {
+                        style: Style {
+                            margin: Rect {
+                                left: Val::Px(6.0),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        text: Text {
+                            font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                            style: TextStyle {
+                                font_size: 14.0,
+                                color: Color::ANTIQUE_WHITE,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .with(AllianceText(faction));
+            });
+    }
+    setup.finish_step();
 }
 
 fn process_network_messages(
-    mut info: ResMut<Info>,
-    mut state: ResMut<State<Screen>>,
-    network: Res<Network>,
-    mut server: Query<&mut Server>,
-    mut client: Query<&mut Client>,
+    (mut info, mut rules, mut negotiation, mut actions): (
+        ResMut<Info>,
+        ResMut<RuleSet>,
+        ResMut<Negotiation>,
+        ResMut<GameActions>,
+    ),
+    (mut lobby, mut game_data, mut wheels, mut deltas): (
+        ResMut<Lobby>,
+        ResMut<Data>,
+        ResMut<BattleWheels>,
+        ResMut<StateDeltas>,
+    ),
+    (mut loaded, mut resync, mut chat, mut reveals, mut house_rules): (
+        ResMut<LoadedGame>,
+        ResMut<ResyncRequests>,
+        ResMut<Chat>,
+        ResMut<SecretReveals>,
+        ResMut<HouseRules>,
+    ),
+    (mut state, mut outcomes, mut hashes, mut migration): (
+        ResMut<State<Screen>>,
+        ResMut<HostOutcomes>,
+        ResMut<StateHashes>,
+        ResMut<HostMigration>,
+    ),
+    (network, assets, mut cursors, mut latencies, mut time_banks, mut mentat): (
+        Res<Network>,
+        Res<AssetPack>,
+        ResMut<RemoteCursors>,
+        ResMut<Latencies>,
+        ResMut<TimeBanks>,
+        ResMut<MentatPause>,
+    ),
+    (mut signals, mut interrupts, mut storm_dial, mut departures, mut notifications, mut captures): (
+        ResMut<Signals>,
+        ResMut<Interrupts>,
+        ResMut<StormDial>,
+        ResMut<Departures>,
+        ResMut<Notifications>,
+        ResMut<Captures>,
+    ),
+    mut recorder: ResMut<BugRecorder>,
+    (mut server, mut client): (Query<&mut Server>, Query<&mut Client>),
 ) {
     match network.network_type {
         NetworkType::Client => {
             if let Some(mut client) = client.iter_mut().next() {
-                for data in client.messages.drain(..) {
-                    let message = MessageData::from_bytes(&data[..]);
-                    match message {
-                        MessageData::Load => {
+                if client.reconnected {
+                    client.reconnected = false;
+                    if let Some(token) = client.session {
+                        client.request(ClientRequest::ResyncRequest {
+                            token,
+                            claims: reveals.claims.clone(),
+                        });
+                    }
+                }
+                for event in client.events() {
+                    recorder.record(|| format!("received {:?}", event));
+                    match event {
+                        ServerEvent::Load => {
                             state.overwrite_next(Screen::Loading).unwrap();
                         }
-                        MessageData::ServerInfo { players } => {
+                        ServerEvent::ServerInfo {
+                            players,
+                            rules: server_rules,
+                        } => {
                             info.players = players;
+                            *rules = server_rules;
+                        }
+                        ServerEvent::DeckPreset { preset } => {
+                            println!("Playing with the {} deck", preset.name);
+                            game_data.set_decks(&preset);
+                        }
+                        ServerEvent::HouseRules { script } => {
+                            if let Some(ref script) = script {
+                                println!(
+                                    "Playing with the {} house rules ({})",
+                                    script.name,
+                                    script.short_hash()
+                                );
+                            }
+                            if let Err(e) = house_rules.set_active(script) {
+                                println!("Can't run the host's house rules: {}", e);
+                            }
+                        }
+                        ServerEvent::LobbyState {
+                            seats,
+                            player_count,
+                            excluded,
+                            faction,
+                        } => {
+                            lobby.seats = seats;
+                            lobby.player_count = player_count;
+                            lobby.excluded = excluded;
+                            client.faction = faction;
+                        }
+                        ServerEvent::Chat { from, text } => {
+                            chat.receive(from, text);
+                        }
+                        ServerEvent::Signal { signal } => signals.receive(signal),
+                        ServerEvent::Notice { notice } => notifications.receive(notice),
+                        ServerEvent::MentatPause { open, ready } => mentat.sync(open, ready),
+                        ServerEvent::Captures {
+                            pending,
+                            held,
+                            killed,
+                        } => captures.sync(pending, held, killed),
+                        // The server hands ours back along with everyone else's
+                        ServerEvent::Cursor { from, x, z } if client.faction != Some(from) => {
+                            cursors.positions.insert(from, Vec2::new(x, z));
+                        }
+                        ServerEvent::Latency { players } => {
+                            latencies.players = players
+                                .into_iter()
+                                .map(|latency| (latency.faction, latency))
+                                .collect();
+                        }
+                        ServerEvent::TimeBanks { banks } => {
+                            time_banks.remaining = banks
+                                .into_iter()
+                                .map(|bank| (bank.faction, bank.seconds))
+                                .collect();
+                        }
+                        ServerEvent::Negotiation {
+                            open,
+                            proposals,
+                            done,
+                            alliances,
+                        } => {
+                            negotiation.sync(open, proposals, done);
+                            info.alliances = alliances;
+                        }
+                        ServerEvent::BattleWheels {
+                            open,
+                            locked,
+                            revealed,
+                        } => {
+                            wheels.sync(open, locked, revealed);
+                        }
+                        ServerEvent::StormDial {
+                            dialers,
+                            dialed,
+                            revealed,
+                        } => storm_dial.sync(dialers, dialed, revealed),
+                        ServerEvent::ActionRejected { request, reason } => {
+                            match actions.answered(request) {
+                                Some(action) => {
+                                    println!("Server rejected {:?}: {}", action, reason)
+                                }
+                                None => println!("Server rejected our action: {}", reason),
+                            }
+                            actions.rejected = Some(reason);
+                        }
+                        ServerEvent::Interrupt { window } => interrupts.receive(window),
+                        ServerEvent::AssetMismatch { folders } => {
+                            println!(
+                                "Our assets differ from the host's in: {}",
+                                folders.join(", ")
+                            );
+                            client.differing_assets = folders;
+                        }
+                        ServerEvent::JoinDenied { reason } => {
+                            println!("Server turned us away: {}", reason);
+                            client.rejection = Some(reason);
+                        }
+                        ServerEvent::StateHash { turn, sections } => {
+                            hashes.received = Some((turn, sections));
+                        }
+                        ServerEvent::StateDelta {
+                            base,
+                            version,
+                            changes,
+                        } => {
+                            deltas.received.push((base, version, changes));
+                        }
+                        ServerEvent::Deploy { deployments } => {
+                            deltas.deployments.extend(deployments);
+                        }
+                        ServerEvent::BackupHost {
+                            address,
+                            password,
+                            chosen,
+                        } => {
+                            migration.backup = address.parse().ok();
+                            migration.password = password;
+                            migration.chosen = chosen;
+                        }
+                        ServerEvent::Replica {
+                            state,
+                            sessions,
+                            seals,
+                        } => {
+                            migration.replica = Some((state, sessions, seals));
+                        }
+                        ServerEvent::SecretProof { claim } => {
+                            reveals.keep_claim(claim);
+                        }
+                        ServerEvent::DealSecret { kind, slot, key }
+                        | ServerEvent::RevealCard { kind, slot, key } => {
+                            reveals.pending.push((kind, slot, key));
+                        }
+                        ServerEvent::HideCards { kind, slots } => {
+                            reveals
+                                .pending
+                                .extend(slots.into_iter().map(|slot| (kind, slot, String::new())));
+                        }
+                        ServerEvent::DeckOrder { kind, slots } => {
+                            outcomes.deck_orders.push((kind, slots));
+                        }
+                        ServerEvent::PlayOrder { factions } => {
+                            outcomes.play_order = Some(factions);
+                        }
+                        ServerEvent::StormMoved { sectors } => {
+                            outcomes.storm_moves.push_back(sectors);
+                        }
+                        ServerEvent::SpiceDrawn { key } => {
+                            outcomes.spice_draws.push_back(key);
+                        }
+                        ServerEvent::PlayerLeft { faction, conceded } => {
+                            if conceded {
+                                println!("{} conceded", faction);
+                            } else {
+                                println!("{} left the game", faction);
+                            }
+                        }
+                        ServerEvent::ResyncState {
+                            faction,
+                            state: bytes,
+                        } => {
+                            client.faction = faction;
+                            match SaveGame::from_bytes(&bytes) {
+                                Ok(save) => {
+                                    loaded.save = Some(save);
+                                    state.overwrite_next(Screen::Loading).unwrap();
+                                }
+                                Err(err) => {
+                                    println!("Failed to load the resync: {}", err);
+                                    loaded.failed = Some(err);
+                                }
+                            }
                         }
                         _ => (),
                     }
                 }
             }
         }
-        NetworkType::Server => if let Some(mut server) = server.iter_mut().next() {},
-        NetworkType::None => (),
+        NetworkType::Server => {
+            if let Some(mut server) = server.iter_mut().next() {
+                for (address, ClientMessage { id, request }) in server.requests() {
+                    recorder.record(|| format!("received {:?} from {}", request, address));
+                    // Until they have a name, clients can only ask for one or reclaim an old seat
+                    if !server.is_joined(address)
+                        && !matches!(
+                            request,
+                            ClientRequest::JoinRequest { .. } | ClientRequest::ResyncRequest { .. }
+                        )
+                    {
+                        println!("Ignoring {:?} from {}, who hasn't joined", request, address);
+                        continue;
+                    }
+                    // Spectators can talk and catch up, but never touch the game
+                    if server.is_spectator(address)
+                        && !matches!(
+                            request,
+                            ClientRequest::Chat { .. }
+                                | ClientRequest::ResyncRequest { .. }
+                                | ClientRequest::StateAck { .. }
+                        )
+                    {
+                        println!("Ignoring {:?} from spectator {}", request, address);
+                        continue;
+                    }
+                    match request {
+                        // Never passed on, so a prediction stays between the Bene Gesserit and the host
+                        ClientRequest::Action { action } => {
+                            if server.address_of(action.faction()) == Some(address) {
+                                actions.remote.push((id, action.clone()));
+                                actions.submitted.push_back(action);
+                            } else {
+                                println!("{} cannot act for {}", address, action.faction());
+                            }
+                        }
+                        // Stamped with the sender's own seat, so nobody can talk as someone else
+                        // or pass themselves off as an observer
+                        ClientRequest::Chat { text, .. } => {
+                            let from = server.faction_of(address);
+                            server.broadcast(&ServerEvent::Chat {
+                                from,
+                                text: text.clone(),
+                            });
+                            chat.receive(from, text);
+                        }
+                        ClientRequest::Signal { signal }
+                            if server.address_of(signal.from()) != Some(address) =>
+                        {
+                            println!("{} cannot signal for {}", address, signal.from());
+                        }
+                        ClientRequest::Signal { signal } => {
+                            server.broadcast(&ServerEvent::Signal { signal });
+                            signals.receive(signal);
+                        }
+                        ClientRequest::StateAck { version } => {
+                            deltas.acknowledge(address, version);
+                        }
+                        ClientRequest::Cursor { from, .. }
+                            if server.address_of(from) != Some(address) =>
+                        {
+                            println!("{} cannot point for {}", address, from);
+                        }
+                        ClientRequest::Cursor { from, x, z } => {
+                            server.broadcast(&ServerEvent::Cursor { from, x, z });
+                            cursors.positions.insert(from, Vec2::new(x, z));
+                        }
+                        ClientRequest::SelectFaction { faction } => {
+                            if select_seat(
+                                state.current(),
+                                &mut lobby,
+                                &address.to_string(),
+                                faction,
+                            ) {
+                                server.bind_faction(address, faction);
+                            } else {
+                                println!("{} cannot take {:?}", address, faction);
+                            }
+                        }
+                        ClientRequest::PlayerLeft { faction, .. }
+                            if server.address_of(faction) != Some(address) =>
+                        {
+                            println!("{} cannot leave for {}", address, faction);
+                        }
+                        ClientRequest::PlayerLeft { faction, conceded } => {
+                            server.broadcast(&ServerEvent::PlayerLeft { faction, conceded });
+                            departures.left.push((faction, conceded));
+                        }
+                        ClientRequest::Ready { ready } => {
+                            if matches!(state.current(), Screen::Server) {
+                                lobby.set_ready(&address.to_string(), ready);
+                            }
+                        }
+                        ClientRequest::JoinRequest {
+                            name,
+                            password,
+                            rating,
+                            assets: client_assets,
+                        } => match server.join(address, &name, &password, rating) {
+                            Ok(()) => {
+                                // Warned rather than turned away, since a reskin plays the same
+                                let folders = assets.differing(&client_assets);
+                                if !folders.is_empty() {
+                                    println!(
+                                        "{}'s assets differ from ours in: {}",
+                                        name,
+                                        folders.join(", ")
+                                    );
+                                    server.send_event(
+                                        address,
+                                        &ServerEvent::AssetMismatch {
+                                            folders: folders.clone(),
+                                        },
+                                    );
+                                }
+                                server.set_differing_assets(address, folders);
+                            }
+                            Err(reason) => {
+                                server.send_event(address, &ServerEvent::JoinDenied { reason });
+                            }
+                        },
+                        ClientRequest::ResyncRequest { token, claims } => {
+                            if let Some(faction) = server.rebind(token, address) {
+                                // Back with a new host, so the cards it holds need checking
+                                // before it is caught up
+                                match faction {
+                                    Some(faction) if !migration.seals.is_empty() => {
+                                        migration.claims.push((address, faction, claims));
+                                    }
+                                    _ => resync.clients.push((address, faction)),
+                                }
+                            } else {
+                                println!("Unknown session from {}", address);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        NetworkType::None | NetworkType::Local => (),
     }
 }
 
+/// Seats are only handed out in the lobby. Once the game is under way the factions nobody took
+/// are played by bots, and taking one over would hand a player its cards.
+fn select_seat(screen: &Screen, lobby: &mut Lobby, player: &str, faction: Option<Faction>) -> bool {
+    matches!(screen, Screen::Server) && lobby.select(player, faction)
+}
+
 fn propagate_visibility(
     root: Query<(&Visible, &Children), (Without<Parent>, Changed<Visible>)>,
     mut children: Query<&mut Visible, With<Parent>>,
@@ -891,3 +1938,38 @@ fn tear_down(commands: &mut Commands, screen_entities: Query<Entity, With<Screen
 fn reset_game(mut info: ResMut<Info>) {
     info.reset();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lobby() -> Lobby {
+        let mut lobby = Lobby::default();
+        lobby.sync_players(&[("127.0.0.1:9000".to_string(), "Paul".to_string(), None)]);
+        lobby
+    }
+
+    #[test]
+    fn seats_are_taken_in_the_lobby() {
+        let mut lobby = lobby();
+        assert!(select_seat(
+            &Screen::Server,
+            &mut lobby,
+            "127.0.0.1:9000",
+            Some(Faction::Atreides)
+        ));
+        assert_eq!(lobby.seats[0].faction, Some(Faction::Atreides));
+    }
+
+    #[test]
+    fn seats_are_fixed_once_the_game_starts() {
+        let mut lobby = lobby();
+        assert!(!select_seat(
+            &Screen::HostingGame,
+            &mut lobby,
+            "127.0.0.1:9000",
+            Some(Faction::Harkonnen)
+        ));
+        assert_eq!(lobby.seats[0].faction, None);
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+pub struct MaterialCachePlugin;
+
+impl Plugin for MaterialCachePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<MaterialCache>()
+            .add_system(texture_reload_system.system());
+    }
+}
+
+/// One material per texture, shared by everything drawn with it. Cards, shields and tokens are
+/// respawned every game, and would otherwise each add a copy of the same material.
+#[derive(Default)]
+pub struct MaterialCache {
+    materials: HashMap<(String, bool), Handle<StandardMaterial>>,
+}
+
+impl MaterialCache {
+    /// The lit material for the texture at `path`
+    pub fn textured(
+        &mut self,
+        path: &str,
+        asset_server: &AssetServer,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Handle<StandardMaterial> {
+        self.get(path, true, asset_server, materials)
+    }
+
+    /// The material for the texture at `path` that ignores the lights, for icons and overlays
+    pub fn unshaded(
+        &mut self,
+        path: &str,
+        asset_server: &AssetServer,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Handle<StandardMaterial> {
+        self.get(path, false, asset_server, materials)
+    }
+
+    fn get(
+        &mut self,
+        path: &str,
+        shaded: bool,
+        asset_server: &AssetServer,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Handle<StandardMaterial> {
+        self.materials
+            .entry((path.to_string(), shaded))
+            .or_insert_with(|| {
+                materials.add(StandardMaterial {
+                    albedo_texture: Some(asset_server.get_handle(path)),
+                    shaded,
+                    ..Default::default()
+                })
+            })
+            .clone()
+    }
+}
+
+/// Touches every material drawn with a texture that changed on disk, so the new image is bound
+/// in place of the old one
+fn texture_reload_system(
+    cache: Res<MaterialCache>,
+    events: Res<Events<AssetEvent<Texture>>>,
+    mut reader: Local<EventReader<AssetEvent<Texture>>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for event in reader.iter(&events) {
+        if let AssetEvent::Modified { handle } = event {
+            for material in cache.materials.values() {
+                if materials.get(material).map_or(false, |material| {
+                    material.albedo_texture.as_ref() == Some(handle)
+                }) {
+                    materials.get_mut(material);
+                }
+            }
+        }
+    }
+}
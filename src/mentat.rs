@@ -0,0 +1,392 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{
+    alliance::own_faction,
+    bot::Bot,
+    components::Player,
+    data::Faction,
+    dune_core::{Occupancy, LAST_TURN},
+    game_action::{submit, GameAction, GameActions},
+    hud::HudMaterials,
+    layout::{Anchored, AnchoredHeight},
+    locale::Tr,
+    menu::{ButtonMaterials, Lobby},
+    network::{Client, Network, NetworkRole, NetworkType, Server},
+    notification::Notifications,
+    phase::{ActionQueue, GamePhase, Phase},
+    protocol::ServerEvent,
+    resources::{Data, Info},
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+type SummaryButtons<'a, 'b, 'c, 'd> = Query<
+    'a,
+    (
+        &'b Interaction,
+        &'c SummaryButton,
+        &'d mut Handle<ColorMaterial>,
+    ),
+    (Mutated<Interaction>, With<Button>),
+>;
+
+/// The most of the turn's events the summary lists, the latest ones
+const SUMMARY_EVENTS: usize = 10;
+
+pub struct MentatPlugin;
+
+impl Plugin for MentatPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<MentatPause>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                mentat_pause_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                bot_ready_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                summary_panel_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                summary_panel_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                summary_button_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                summary_button_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system());
+    }
+}
+
+/// The break at the end of every turn but the last, while everyone takes stock of the board. The
+/// host holds the next turn back until every faction still in the game is ready.
+#[derive(Default)]
+pub struct MentatPause {
+    pub open: bool,
+    pub ready: Vec<Faction>,
+    /// Factions that have said they are ready, for the host to settle
+    pub requests: VecDeque<Faction>,
+    /// The turn the pause was last held after, so it is only held once a turn
+    held: Option<i32>,
+    /// Whether the summary is tucked away so the board can be looked over
+    hidden: bool,
+}
+
+impl MentatPause {
+    /// Whether the pause after `turn` has been held and everyone was ready
+    pub fn finished(&self, turn: i32) -> bool {
+        !self.open && self.held == Some(turn)
+    }
+
+    /// Takes on the host's view of the pause
+    pub fn sync(&mut self, open: bool, ready: Vec<Faction>) {
+        if open && !self.open {
+            self.hidden = false;
+        }
+        self.open = open;
+        self.ready = ready;
+    }
+}
+
+/// Opens the pause once the control phase has nothing left to do, and closes it once everyone
+/// still in the game is ready
+fn mentat_pause_system(
+    (network, queue, phase, info): (Res<Network>, Res<ActionQueue>, Res<GamePhase>, Res<Info>),
+    (mut pause, mut actions): (ResMut<MentatPause>, ResMut<GameActions>),
+    mut server: Query<&mut Server>,
+) {
+    if network.network_type == NetworkType::Client {
+        return;
+    }
+    let mut changed = false;
+    if phase.phase == Phase::Control
+        && queue.is_empty()
+        && info.turn + 1 < LAST_TURN
+        && pause.held != Some(info.turn)
+    {
+        println!("Mentat pause after turn {}", info.turn + 1);
+        pause.open = true;
+        pause.ready.clear();
+        pause.held = Some(info.turn);
+        pause.hidden = false;
+        changed = true;
+    }
+    while let Some(faction) = pause.requests.pop_front() {
+        if pause.open && info.in_game().contains(&faction) && !pause.ready.contains(&faction) {
+            println!("{} is ready for the next turn", faction);
+            pause.ready.push(faction);
+            actions.resolved(&info, phase.phase, GameAction::MentatReady { faction });
+            changed = true;
+        }
+    }
+    if pause.open
+        && info
+            .in_game()
+            .iter()
+            .all(|faction| pause.ready.contains(faction))
+    {
+        println!("Everyone is ready, on to the next turn");
+        pause.open = false;
+        changed = true;
+    }
+
+    if changed {
+        if let Some(mut server) = server.iter_mut().next() {
+            server.broadcast(&ServerEvent::MentatPause {
+                open: pause.open,
+                ready: pause.ready.clone(),
+            });
+        }
+    }
+}
+
+/// Bots have seen all they need to of the board
+fn bot_ready_system(
+    network: Res<Network>,
+    (pause, mut actions): (Res<MentatPause>, ResMut<GameActions>),
+    bots: Query<&Player, With<Bot>>,
+) {
+    if network.network_type == NetworkType::Client || !pause.open {
+        return;
+    }
+    for player in bots.iter() {
+        let faction = player.faction;
+        if pause.ready.contains(&faction)
+            || pause.requests.contains(&faction)
+            || actions.pending(faction)
+        {
+            continue;
+        }
+        actions
+            .submitted
+            .push_back(GameAction::MentatReady { faction });
+    }
+}
+
+/// Everyone who readies up from this machine: its own seat, or every human player when they all
+/// share it
+fn own_factions(
+    (network, lobby, info): (&Network, &Lobby, &Info),
+    players: &Query<&Player>,
+    humans: &Query<&Player, Without<Bot>>,
+    client: &Query<&Client>,
+) -> Vec<Faction> {
+    if network.role == NetworkRole::Spectator {
+        Vec::new()
+    } else if network.network_type == NetworkType::Client || !lobby.seats.is_empty() {
+        own_faction(network, lobby, info, players, client)
+            .into_iter()
+            .collect()
+    } else {
+        humans.iter().map(|player| player.faction).collect()
+    }
+}
+
+struct SummaryPanel;
+
+#[derive(Copy, Clone, PartialEq)]
+enum SummaryButton {
+    Ready,
+    /// Tucks the summary away, or brings it back
+    Review,
+}
+
+/// What the panel was last built from
+type PanelState = (Vec<Faction>, bool);
+
+/// Sums up the turn that just ended: who holds each stronghold, what happened and who is still
+/// looking over the board
+fn summary_panel_system(
+    commands: &mut Commands,
+    (network, lobby, info, pause): (Res<Network>, Res<Lobby>, Res<Info>, Res<MentatPause>),
+    (occupancy, notifications, data, tr): (Res<Occupancy>, Res<Notifications>, Res<Data>, Res<Tr>),
+    (asset_server, hud_materials, button_materials): (
+        Res<AssetServer>,
+        Res<HudMaterials>,
+        Res<ButtonMaterials>,
+    ),
+    mut shown: Local<Option<PanelState>>,
+    (players, humans, client): (Query<&Player>, Query<&Player, Without<Bot>>, Query<&Client>),
+    panels: Query<Entity, With<SummaryPanel>>,
+) {
+    let state = Some((pause.ready.clone(), pause.hidden)).filter(|_| pause.open);
+    if *shown == state {
+        return;
+    }
+    *shown = state;
+    for entity in panels.iter() {
+        commands.despawn_recursive(entity);
+    }
+    if !pause.open {
+        return;
+    }
+
+    let mut lines = Vec::new();
+    if !pause.hidden {
+        lines.push(tr.fmt("mentat.title", &[&(info.turn + 1), &LAST_TURN]));
+        lines.push(tr.get("mentat.strongholds"));
+        let mut strongholds = occupancy.strongholds().collect::<Vec<_>>();
+        strongholds.sort_by_key(|(name, _)| *name);
+        for (name, factions) in strongholds {
+            let holders = if factions.is_empty() {
+                tr.get("mentat.empty")
+            } else {
+                factions
+                    .iter()
+                    .map(|&faction| tr.faction(faction))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            lines.push(tr.fmt("mentat.stronghold", &[&name, &holders]));
+        }
+        lines.push(tr.get("mentat.events"));
+        let events = notifications.this_turn();
+        if events.is_empty() {
+            lines.push(tr.get("mentat.no_events"));
+        }
+        for notice in &events[events.len().saturating_sub(SUMMARY_EVENTS)..] {
+            lines.push(notice.text(&tr));
+        }
+    }
+    let waiting = info
+        .in_game()
+        .into_iter()
+        .filter(|faction| !pause.ready.contains(faction))
+        .map(|faction| tr.faction(faction))
+        .collect::<Vec<_>>();
+    lines.push(tr.fmt("mentat.waiting", &[&waiting.join(", ")]));
+
+    let own = own_factions((&network, &lobby, &info), &players, &humans, &client);
+    let mut buttons = Vec::new();
+    if own.iter().any(|faction| !pause.ready.contains(faction)) {
+        buttons.push((SummaryButton::Ready, tr.get("mentat.ready")));
+    }
+    buttons.push((
+        SummaryButton::Review,
+        tr.get(if pause.hidden {
+            "mentat.show_summary"
+        } else {
+            "mentat.review"
+        }),
+    ));
+
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let text = |value: String| TextBundle {
+        text: Text {
+            font: font.clone(),
+            value,
+            style: TextStyle {
+                font_size: 18.0,
+                color: Color::ANTIQUE_WHITE,
+                ..Default::default()
+            },
+        },
+        ..Default::default()
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Stretch,
+                padding: Rect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            material: hud_materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(SummaryPanel)
+        .with(
+            Anchored::new(data.ui_structure.mentat_pause.clone()).with_height(AnchoredHeight::Auto),
+        )
+        .with_children(|parent| {
+            parent.spawn(text(lines.join("\n")));
+            for (button, label) in buttons {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            margin: Rect::all(Val::Px(3.0)),
+                            padding: Rect::all(Val::Px(4.0)),
+                            justify_content: JustifyContent::Center,
+                            ..Default::default()
+                        },
+                        material: button_materials.normal.clone(),
+                        ..Default::default()
+                    })
+                    .with(button)
+                    .with_children(|parent| {
+                        parent.spawn(text(label));
+                    });
+            }
+        });
+}
+
+fn summary_button_system(
+    (network, lobby, info): (Res<Network>, Res<Lobby>, Res<Info>),
+    (button_materials, mut pause, mut actions): (
+        Res<ButtonMaterials>,
+        ResMut<MentatPause>,
+        ResMut<GameActions>,
+    ),
+    mut buttons: SummaryButtons,
+    (players, humans, faction_client): (
+        Query<&Player>,
+        Query<&Player, Without<Bot>>,
+        Query<&Client>,
+    ),
+    mut client: Query<&mut Client>,
+) {
+    let mut clicked = None;
+    for (interaction, &button, mut material) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {
+                *material = button_materials.pressed.clone();
+                clicked = Some(button);
+            }
+            Interaction::Hovered => *material = button_materials.hovered.clone(),
+            Interaction::None => *material = button_materials.normal.clone(),
+        }
+    }
+    match clicked {
+        Some(SummaryButton::Ready) => {
+            let own = own_factions(
+                (&network, &lobby, &info),
+                &players,
+                &humans,
+                &faction_client,
+            );
+            for faction in own {
+                if !pause.ready.contains(&faction) {
+                    submit(
+                        &network,
+                        &mut client,
+                        &mut actions,
+                        GameAction::MentatReady { faction },
+                    );
+                }
+            }
+        }
+        Some(SummaryButton::Review) => pause.hidden = !pause.hidden,
+        None => (),
+    }
+}
+
+fn reset(mut pause: ResMut<MentatPause>) {
+    *pause = MentatPause::default();
+}
@@ -1,12 +1,37 @@
+use std::net::SocketAddr;
+
 use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
 
 use crate::{
-    network::{Client, ConnectionState, Network, NetworkType, Server},
-    resources::Info,
-    tear_down, MessageData, Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+    asset_pack::AssetPack,
+    bot::BotSettings,
+    components::Player,
+    data::Faction,
+    decks::DeckPresets,
+    dune_core::{MAX_PLAYERS, MIN_PLAYERS},
+    house_rules::HouseRules,
+    locale::{Localized, Tr},
+    network::{
+        player_name, Client, ConnectionState, Discovery, Latencies, LobbyDirectory, Network,
+        NetworkRole, NetworkType, Server, CLIENT_PORT, GAME_VERSION, MAX_NAME_LENGTH, SERVER_PORT,
+    },
+    profile::Profile,
+    protocol::{ClientRequest, ServerEvent},
+    resources::{GameRng, Info, RuleSet},
+    savegame::{read_save, LoadedGame},
+    scenario::{read_scenario, LoadedScenario},
+    settings::{Binding, DisplayMode, LightQuality, Settings, RESOLUTIONS},
+    tear_down,
+    timer::TurnTimer,
+    tutorial::Tutorial,
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
 };
 pub struct MenuPlugin;
 
+const HOST: &str = "127.0.0.1:12345";
+
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut bevy::prelude::AppBuilder) {
         app.add_startup_system(init_main_menu.system())
@@ -17,8 +42,42 @@ impl Plugin for MenuPlugin {
             .on_state_exit(RESPONSE_STAGE, Screen::Server, tear_down.system())
             .on_state_enter(RESPONSE_STAGE, Screen::Join, init_join_menu.system())
             .on_state_exit(RESPONSE_STAGE, Screen::Join, tear_down.system())
+            .init_resource::<Editing>()
+            .on_state_update(STATE_CHANGE_STAGE, Screen::Join, text_field_system.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::Server,
+                text_field_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::Join, stop_editing.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::Server, stop_editing.system())
+            .init_resource::<Rebinding>()
+            .on_state_enter(
+                RESPONSE_STAGE,
+                Screen::Settings,
+                init_settings_menu.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::Settings, tear_down.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::Settings, save_settings.system())
+            .on_state_update(STATE_CHANGE_STAGE, Screen::Settings, button_system.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::Settings,
+                settings_button_system.system(),
+            )
+            .on_state_update(STATE_CHANGE_STAGE, Screen::Settings, rebind_system.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::Settings,
+                settings_label_system.system(),
+            )
             .on_state_update(STATE_CHANGE_STAGE, Screen::MainMenu, button_system.system())
             .on_state_update(STATE_CHANGE_STAGE, Screen::Join, button_system.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::Join,
+                discovered_list_system.system(),
+            )
             .on_state_update(STATE_CHANGE_STAGE, Screen::Server, button_system.system())
             .on_state_update(
                 STATE_CHANGE_STAGE,
@@ -29,26 +88,146 @@ impl Plugin for MenuPlugin {
                 STATE_CHANGE_STAGE,
                 Screen::Server,
                 server_disconnect.system(),
+            )
+            .init_resource::<Lobby>()
+            .on_state_update(STATE_CHANGE_STAGE, Screen::Server, lobby_system.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::Server,
+                lobby_button_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::Server,
+                lobby_option_label_system.system(),
+            )
+            .init_resource::<Chat>()
+            .on_state_enter(RESPONSE_STAGE, Screen::HostingGame, init_chat.system())
+            .on_state_enter(RESPONSE_STAGE, Screen::JoinedGame, init_chat.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                chat_input_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                chat_input_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                chat_display_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                chat_display_system.system(),
             );
     }
 }
 
 enum ButtonActionType {
     HostGame,
+    HotSeat,
+    Tutorial,
+    Scenario,
     JoinGame,
+    LoadGame,
+    OpenSettings,
+    OpenProfile,
+    Rebind(Binding),
+    CameraSensitivity,
+    Volume,
+    MusicVolume,
+    EffectsVolume,
+    Multisampling,
+    VSync,
+    DisplayMode,
+    Resolution,
+    Lighting,
+    UiScale,
+    TroopMarkers,
+    FactionIcons,
+    HighContrast,
+    FontScale,
+    Language,
+    BugCapture,
+    ResetSettings,
+    SelectFaction(Faction),
+    ToggleReady,
+    BotDifficulty,
+    TimeBank,
+    OutOfTime,
+    Rules,
+    Deck,
+    HouseRules,
+    PlayerCount,
+    /// Leaves a faction out of the game, or puts it back
+    ToggleFaction(Faction),
     StartGame,
     GoBack,
     ConnectToServer,
+    SpectateServer,
+    RefreshGames,
+    BrowseOnline,
+    ToggleHideProtected,
+    JoinDiscovered(SocketAddr),
+    EditField(TextField),
+}
+
+/// The text boxes on the host and join screens, which edit the online settings
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum TextField {
+    PlayerName,
+    Password,
+}
+
+impl TextField {
+    fn label(self, settings: &Settings, editing: bool) -> String {
+        let cursor = if editing { "_" } else { "" };
+        match self {
+            TextField::PlayerName => format!("Name: {}{}", settings.online.player_name, cursor),
+            TextField::Password if settings.online.password.is_empty() && !editing => {
+                "Password: none".to_string()
+            }
+            TextField::Password => format!(
+                "Password: {}{}",
+                "*".repeat(settings.online.password.chars().count()),
+                cursor
+            ),
+        }
+    }
+
+    fn value(self, settings: &mut Settings) -> &mut String {
+        match self {
+            TextField::PlayerName => &mut settings.online.player_name,
+            TextField::Password => &mut settings.online.password,
+        }
+    }
+}
+
+/// The text box being typed in, if any
+#[derive(Default)]
+pub struct Editing {
+    field: Option<TextField>,
+}
+
+impl Editing {
+    /// Whether the keyboard is going into a text box
+    pub fn active(&self) -> bool {
+        self.field.is_some()
+    }
 }
 
 struct ButtonAction {
     action_type: ButtonActionType,
 }
 
-struct ButtonMaterials {
-    normal: Handle<ColorMaterial>,
-    hovered: Handle<ColorMaterial>,
-    pressed: Handle<ColorMaterial>,
+pub struct ButtonMaterials {
+    pub normal: Handle<ColorMaterial>,
+    pub hovered: Handle<ColorMaterial>,
+    pub pressed: Handle<ColorMaterial>,
 }
 
 impl FromResources for ButtonMaterials {
@@ -62,8 +241,52 @@ impl FromResources for ButtonMaterials {
     }
 }
 
+impl ButtonMaterials {
+    /// Changes the colors in place, so buttons already on screen pick them up too
+    pub fn recolor(&self, materials: &mut Assets<ColorMaterial>, high_contrast: bool) {
+        let colors = if high_contrast {
+            [
+                Color::rgb(0.0, 0.0, 0.0),
+                Color::rgb(0.0, 0.2, 0.6),
+                Color::rgb(1.0, 0.85, 0.0),
+            ]
+        } else {
+            [
+                Color::rgb(0.15, 0.15, 0.15),
+                Color::rgb(0.25, 0.25, 0.25),
+                Color::rgb(0.35, 0.75, 0.35),
+            ]
+        };
+        for (handle, color) in [&self.normal, &self.hovered, &self.pressed]
+            .iter()
+            .zip(colors.iter())
+        {
+            if let Some(material) = materials.get_mut(*handle) {
+                material.color = *color;
+            }
+        }
+    }
+}
+
 fn button_system(
-    mut state: ResMut<State<Screen>>,
+    (mut state, mut discovery, mut directory, user_settings): (
+        ResMut<State<Screen>>,
+        ResMut<Discovery>,
+        ResMut<LobbyDirectory>,
+        Res<Settings>,
+    ),
+    (mut editing, mut tutorial, mut scenario, profile): (
+        ResMut<Editing>,
+        ResMut<Tutorial>,
+        ResMut<LoadedScenario>,
+        Res<Profile>,
+    ),
+    (mut loaded, mut lobby, mut network, mut game_rng): (
+        ResMut<LoadedGame>,
+        ResMut<Lobby>,
+        ResMut<Network>,
+        ResMut<GameRng>,
+    ),
     button_materials: Res<ButtonMaterials>,
     mut interactions: Query<
         (&Interaction, &mut Handle<ColorMaterial>, &ButtonAction),
@@ -80,22 +303,146 @@ fn button_system(
                     ButtonActionType::HostGame => {
                         state.set_next(Screen::Server).unwrap();
                     }
+                    ButtonActionType::HotSeat => {
+                        // No lobby, so every faction is played from this machine
+                        *lobby = Lobby::default();
+                        *game_rng = GameRng::default();
+                        network.network_type = NetworkType::Local;
+                        state.set_next(Screen::Loading).unwrap();
+                    }
+                    ButtonActionType::Tutorial => {
+                        // One seat at this machine, and bots for everyone else
+                        *lobby = Lobby {
+                            seats: vec![LobbySeat {
+                                player: HOST.to_string(),
+                                name: player_name(&user_settings),
+                                faction: Some(tutorial.script.faction),
+                                ready: true,
+                                rating: None,
+                            }],
+                        };
+                        *game_rng = GameRng::default();
+                        network.network_type = NetworkType::Local;
+                        tutorial.start();
+                        state.set_next(Screen::Loading).unwrap();
+                    }
+                    ButtonActionType::Scenario => {
+                        if let Some(loaded) = read_scenario() {
+                            // Played hot seat, like a game that started from the beginning
+                            *lobby = Lobby::default();
+                            *game_rng = GameRng::default();
+                            network.network_type = NetworkType::Local;
+                            scenario.scenario = Some(loaded);
+                            state.set_next(Screen::Loading).unwrap();
+                        } else {
+                            println!("No scenario to play");
+                        }
+                    }
                     ButtonActionType::JoinGame => {
                         state.set_next(Screen::Join).unwrap();
                     }
+                    ButtonActionType::OpenSettings => {
+                        state.set_next(Screen::Settings).unwrap();
+                    }
+                    ButtonActionType::OpenProfile => {
+                        state.set_next(Screen::Profile).unwrap();
+                    }
+                    ButtonActionType::LoadGame => match read_save() {
+                        Ok(save) => {
+                            loaded.save = Some(save);
+                            state.set_next(Screen::Loading).unwrap();
+                        }
+                        Err(err) => println!("Failed to load the game: {}", err),
+                    },
                     ButtonActionType::StartGame => {
-                        if let Some(mut server) = server.iter_mut().next() {
-                            server.send_to_all(MessageData::Load.into_bytes());
+                        if !lobby.all_ready() {
+                            println!("Waiting for all players to pick a faction and ready up");
+                        } else if !lobby.seats_filled() {
+                            println!(
+                                "Not enough factions left in to seat {} players",
+                                lobby.player_count
+                            );
+                        } else if let Some(mut server) = server.iter_mut().next() {
+                            server.broadcast(&ServerEvent::Load);
                             state.set_next(Screen::Loading).unwrap();
                         }
                     }
+                    // Handled by the lobby button system
+                    ButtonActionType::SelectFaction(_)
+                    | ButtonActionType::ToggleReady
+                    | ButtonActionType::BotDifficulty
+                    | ButtonActionType::TimeBank
+                    | ButtonActionType::OutOfTime
+                    | ButtonActionType::Rules
+                    | ButtonActionType::Deck
+                    | ButtonActionType::HouseRules
+                    | ButtonActionType::PlayerCount
+                    | ButtonActionType::ToggleFaction(_) => (),
+                    // Handled by the settings button system
+                    ButtonActionType::Rebind(_)
+                    | ButtonActionType::CameraSensitivity
+                    | ButtonActionType::Volume
+                    | ButtonActionType::MusicVolume
+                    | ButtonActionType::EffectsVolume
+                    | ButtonActionType::Multisampling
+                    | ButtonActionType::VSync
+                    | ButtonActionType::DisplayMode
+                    | ButtonActionType::Resolution
+                    | ButtonActionType::Lighting
+                    | ButtonActionType::UiScale
+                    | ButtonActionType::TroopMarkers
+                    | ButtonActionType::FactionIcons
+                    | ButtonActionType::HighContrast
+                    | ButtonActionType::FontScale
+                    | ButtonActionType::Language
+                    | ButtonActionType::BugCapture
+                    | ButtonActionType::ResetSettings => (),
                     ButtonActionType::GoBack => {
                         state.set_next(Screen::MainMenu).unwrap();
                     }
-                    ButtonActionType::ConnectToServer => {
+                    ButtonActionType::ConnectToServer | ButtonActionType::SpectateServer => {
+                        network.rejection = None;
+                        network.role = match action.action_type {
+                            ButtonActionType::SpectateServer => NetworkRole::Spectator,
+                            _ => NetworkRole::Player,
+                        };
                         // Connect to server
                         if let Some(mut client) = client.iter_mut().next() {
-                            client.connect_to("127.0.0.1:12345".parse().unwrap());
+                            client.caster = network.role == NetworkRole::Spectator
+                                && user_settings.online.caster;
+                            client.rating = profile.shared_rating(&user_settings);
+                            client.connect_to(
+                                HOST.parse().unwrap(),
+                                network.role,
+                                (&player_name(&user_settings), &user_settings.online.password),
+                            );
+                            state.set_next(Screen::Server).unwrap();
+                        }
+                    }
+                    ButtonActionType::RefreshGames => discovery.refresh(),
+                    ButtonActionType::BrowseOnline => {
+                        directory.browse(&user_settings.online.directory)
+                    }
+                    ButtonActionType::ToggleHideProtected => {
+                        directory.hide_protected = !directory.hide_protected;
+                        directory.changed = true;
+                    }
+                    ButtonActionType::EditField(field) => {
+                        editing.field = if editing.field == Some(field) {
+                            None
+                        } else {
+                            Some(field)
+                        };
+                    }
+                    ButtonActionType::JoinDiscovered(address) => {
+                        network.rejection = None;
+                        network.role = NetworkRole::Player;
+                        if let Some(mut client) = client.iter_mut().next() {
+                            client.connect_to(
+                                address,
+                                network.role,
+                                (&player_name(&user_settings), &user_settings.online.password),
+                            );
                             state.set_next(Screen::Server).unwrap();
                         }
                     }
@@ -111,7 +458,7 @@ fn init_main_menu(
     commands: &mut Commands,
     asset_server: Res<AssetServer>,
     button_materials: Res<ButtonMaterials>,
-    mut network: ResMut<Network>,
+    (mut network, mut latencies): (ResMut<Network>, ResMut<Latencies>),
     nodes: Query<Entity, Or<(With<Server>, With<Client>)>>,
 ) {
     for entity in nodes.iter() {
@@ -119,6 +466,10 @@ fn init_main_menu(
     }
 
     network.network_type = NetworkType::None;
+    network.role = NetworkRole::Player;
+    network.rejection = None;
+    // Nobody left over from the last online game should look disconnected in the next one
+    latencies.players.clear();
 
     commands
         .spawn(NodeBundle {
@@ -148,18 +499,20 @@ fn init_main_menu(
                     action_type: ButtonActionType::HostGame,
                 })
                 .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text {
-                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                            value: "Host Game".to_string(),
-                            style: TextStyle {
-                                font_size: 20.0,
-                                color: Color::ANTIQUE_WHITE,
-                                ..Default::default()
+                    parent
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: String::new(),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::ANTIQUE_WHITE,
+                                    ..Default::default()
+                                },
                             },
-                        },
-                        ..Default::default()
-                    });
+                            ..Default::default()
+                        })
+                        .with(Localized("menu.host_game"));
                 })
                 .spawn(ButtonBundle {
                     style: Style {
@@ -172,182 +525,130 @@ fn init_main_menu(
                     ..Default::default()
                 })
                 .with(ButtonAction {
-                    action_type: ButtonActionType::JoinGame,
+                    action_type: ButtonActionType::HotSeat,
                 })
                 .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text {
-                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                            value: "Join Game".to_string(),
-                            style: TextStyle {
-                                font_size: 20.0,
-                                color: Color::ANTIQUE_WHITE,
-                                ..Default::default()
+                    parent
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: String::new(),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::ANTIQUE_WHITE,
+                                    ..Default::default()
+                                },
                             },
-                        },
-                        ..Default::default()
-                    });
-                });
-        });
-}
-
-struct ServerList;
-
-fn init_server_menu(
-    commands: &mut Commands,
-    asset_server: Res<AssetServer>,
-    button_materials: Res<ButtonMaterials>,
-    mut network: ResMut<Network>,
-) {
-    match network.network_type {
-        NetworkType::None | NetworkType::Server => {
-            commands
-                .spawn(NodeBundle {
+                            ..Default::default()
+                        })
+                        .with(Localized("menu.hot_seat"));
+                })
+                .spawn(ButtonBundle {
                     style: Style {
-                        size: Size::new(Val::Percent(50.0), Val::Percent(100.0)),
-                        margin: Rect::all(Val::Auto),
+                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
                         justify_content: JustifyContent::Center,
                         align_items: AlignItems::Center,
                         ..Default::default()
                     },
+                    material: button_materials.normal.clone(),
                     ..Default::default()
                 })
-                .with(ScreenEntity)
+                .with(ButtonAction {
+                    action_type: ButtonActionType::Tutorial,
+                })
                 .with_children(|parent| {
                     parent
                         .spawn(TextBundle {
                             text: Text {
                                 font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                                value: "Joined Users:".to_string(),
+                                value: String::new(),
                                 style: TextStyle {
                                     font_size: 20.0,
-                                    color: Color::BLACK,
+                                    color: Color::ANTIQUE_WHITE,
                                     ..Default::default()
                                 },
                             },
                             ..Default::default()
                         })
-                        .with(ServerList);
+                        .with(Localized("menu.tutorial"));
                 })
-                .spawn(NodeBundle {
+                .spawn(ButtonBundle {
                     style: Style {
-                        size: Size::new(Val::Percent(50.0), Val::Percent(100.0)),
-                        margin: Rect::all(Val::Auto),
+                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
                         justify_content: JustifyContent::Center,
                         align_items: AlignItems::Center,
                         ..Default::default()
                     },
+                    material: button_materials.normal.clone(),
                     ..Default::default()
                 })
-                .with(ScreenEntity)
+                .with(ButtonAction {
+                    action_type: ButtonActionType::Scenario,
+                })
                 .with_children(|parent| {
                     parent
-                        .spawn(ButtonBundle {
-                            style: Style {
-                                size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
-                                justify_content: JustifyContent::Center,
-                                align_items: AlignItems::Center,
-                                ..Default::default()
-                            },
-                            material: button_materials.normal.clone(),
-                            ..Default::default()
-                        })
-                        .with(ButtonAction {
-                            action_type: ButtonActionType::StartGame,
-                        })
-                        .with_children(|parent| {
-                            parent.spawn(TextBundle {
-                                text: Text {
-                                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                                    value: "Start Game".to_string(),
-                                    style: TextStyle {
-                                        font_size: 20.0,
-                                        color: Color::ANTIQUE_WHITE,
-                                        ..Default::default()
-                                    },
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: String::new(),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::ANTIQUE_WHITE,
+                                    ..Default::default()
                                 },
-                                ..Default::default()
-                            });
-                        })
-                        .spawn(ButtonBundle {
-                            style: Style {
-                                size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
-                                justify_content: JustifyContent::Center,
-                                align_items: AlignItems::Center,
-                                ..Default::default()
                             },
-                            material: button_materials.normal.clone(),
                             ..Default::default()
                         })
-                        .with(ButtonAction {
-                            action_type: ButtonActionType::GoBack,
-                        })
-                        .with_children(|parent| {
-                            parent.spawn(TextBundle {
-                                text: Text {
-                                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                                    value: "Back".to_string(),
-                                    style: TextStyle {
-                                        font_size: 20.0,
-                                        color: Color::ANTIQUE_WHITE,
-                                        ..Default::default()
-                                    },
-                                },
-                                ..Default::default()
-                            });
-                        });
-                });
-
-            println!("Binding 127.0.0.1:12345");
-            commands.spawn((Server::new("12345"),));
-            network.network_type = NetworkType::Server;
-        }
-        NetworkType::Client => {
-            commands
-                .spawn(NodeBundle {
+                        .with(Localized("menu.scenario"));
+                })
+                .spawn(ButtonBundle {
                     style: Style {
-                        size: Size::new(Val::Percent(50.0), Val::Percent(100.0)),
-                        margin: Rect::all(Val::Auto),
+                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
                         justify_content: JustifyContent::Center,
                         align_items: AlignItems::Center,
                         ..Default::default()
                     },
+                    material: button_materials.normal.clone(),
                     ..Default::default()
                 })
-                .with(ScreenEntity)
+                .with(ButtonAction {
+                    action_type: ButtonActionType::JoinGame,
+                })
                 .with_children(|parent| {
                     parent
                         .spawn(TextBundle {
                             text: Text {
                                 font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                                value: "Joined Users:".to_string(),
+                                value: String::new(),
                                 style: TextStyle {
                                     font_size: 20.0,
-                                    color: Color::BLACK,
+                                    color: Color::ANTIQUE_WHITE,
                                     ..Default::default()
                                 },
                             },
                             ..Default::default()
                         })
-                        .with(ServerList);
+                        .with(Localized("menu.join_game"));
                 })
-                .spawn(NodeBundle {
+                .spawn(ButtonBundle {
                     style: Style {
-                        size: Size::new(Val::Percent(50.0), Val::Percent(100.0)),
-                        margin: Rect::all(Val::Auto),
+                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
                         justify_content: JustifyContent::Center,
                         align_items: AlignItems::Center,
                         ..Default::default()
                     },
+                    material: button_materials.normal.clone(),
                     ..Default::default()
                 })
-                .with(ScreenEntity)
+                .with(ButtonAction {
+                    action_type: ButtonActionType::LoadGame,
+                })
                 .with_children(|parent| {
                     parent
                         .spawn(TextBundle {
                             text: Text {
                                 font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                                value: "Waiting for Server...".to_string(),
+                                value: String::new(),
                                 style: TextStyle {
                                     font_size: 20.0,
                                     color: Color::ANTIQUE_WHITE,
@@ -356,179 +657,2227 @@ fn init_server_menu(
                             },
                             ..Default::default()
                         })
-                        .spawn(ButtonBundle {
-                            style: Style {
-                                size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
-                                justify_content: JustifyContent::Center,
-                                align_items: AlignItems::Center,
-                                ..Default::default()
+                        .with(Localized("menu.load_game"));
+                })
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: button_materials.normal.clone(),
+                    ..Default::default()
+                })
+                .with(ButtonAction {
+                    action_type: ButtonActionType::OpenSettings,
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: String::new(),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::ANTIQUE_WHITE,
+                                    ..Default::default()
+                                },
                             },
-                            material: button_materials.normal.clone(),
                             ..Default::default()
                         })
-                        .with(ButtonAction {
-                            action_type: ButtonActionType::GoBack,
-                        })
-                        .with_children(|parent| {
-                            parent.spawn(TextBundle {
-                                text: Text {
-                                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                                    value: "Back".to_string(),
-                                    style: TextStyle {
-                                        font_size: 20.0,
-                                        color: Color::ANTIQUE_WHITE,
-                                        ..Default::default()
-                                    },
+                        .with(Localized("menu.settings"));
+                })
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: button_materials.normal.clone(),
+                    ..Default::default()
+                })
+                .with(ButtonAction {
+                    action_type: ButtonActionType::OpenProfile,
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: String::new(),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::ANTIQUE_WHITE,
+                                    ..Default::default()
+                                },
+                            },
+                            ..Default::default()
+                        })
+                        .with(Localized("menu.profile"));
+                });
+        });
+}
+
+/// The option after `current`, wrapping around to the first
+fn next_of<T: Copy + PartialEq>(options: &[T], current: T) -> T {
+    let index = options
+        .iter()
+        .position(|&option| option == current)
+        .map_or(0, |index| (index + 1) % options.len());
+    options[index]
+}
+
+pub const SENSITIVITY_STEPS: [f32; 6] = [0.25, 0.5, 0.75, 1.0, 1.5, 2.0];
+const MSAA_STEPS: [u32; 3] = [1, 4, 8];
+const UI_SCALE_STEPS: [f32; 5] = [0.75, 1.0, 1.25, 1.5, 2.0];
+const TROOP_MARKER_STEPS: [u32; 4] = [0, 3, 5, 10];
+const FONT_SCALE_STEPS: [f32; 5] = [0.8, 1.0, 1.25, 1.5, 2.0];
+
+/// The action waiting on its new key, and what happened to the last attempt
+#[derive(Default)]
+pub struct Rebinding {
+    binding: Option<Binding>,
+    notice: String,
+}
+
+impl Rebinding {
+    /// Whether the next key pressed is taken for an action
+    pub fn active(&self) -> bool {
+        self.binding.is_some()
+    }
+}
+
+type ClickedButton<'a> = (&'a Interaction, &'a ButtonAction);
+type ButtonChanged = (Mutated<Interaction>, With<Button>);
+
+/// Shows what a settings button currently does
+struct SettingLabel;
+
+struct SettingsNotice;
+
+fn init_settings_menu(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    button_materials: Res<ButtonMaterials>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    *rebinding = Rebinding::default();
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let text_style = TextStyle {
+        font_size: 16.0,
+        color: Color::ANTIQUE_WHITE,
+        ..Default::default()
+    };
+    let actions = Binding::ALL
+        .iter()
+        .map(|&binding| ButtonActionType::Rebind(binding))
+        .chain(vec![
+            ButtonActionType::CameraSensitivity,
+            ButtonActionType::Volume,
+            ButtonActionType::MusicVolume,
+            ButtonActionType::EffectsVolume,
+            ButtonActionType::Multisampling,
+            ButtonActionType::VSync,
+            ButtonActionType::DisplayMode,
+            ButtonActionType::Resolution,
+            ButtonActionType::Lighting,
+            ButtonActionType::UiScale,
+            ButtonActionType::TroopMarkers,
+            ButtonActionType::FactionIcons,
+            ButtonActionType::HighContrast,
+            ButtonActionType::FontScale,
+            ButtonActionType::Language,
+            ButtonActionType::BugCapture,
+            ButtonActionType::ResetSettings,
+            ButtonActionType::GoBack,
+        ])
+        .collect::<Vec<_>>();
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                padding: Rect::all(Val::Px(10.0)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        font: font.clone(),
+                        value: String::new(),
+                        style: TextStyle {
+                            font_size: 20.0,
+                            color: Color::ANTIQUE_WHITE,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(SettingsNotice)
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.0), Val::Percent(90.0)),
+                        flex_direction: FlexDirection::ColumnReverse,
+                        flex_wrap: FlexWrap::Wrap,
+                        align_content: AlignContent::Center,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for action_type in actions {
+                        parent
+                            .spawn(ButtonBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(280.0), Val::Px(26.0)),
+                                    margin: Rect::all(Val::Px(2.0)),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..Default::default()
                                 },
+                                material: button_materials.normal.clone(),
                                 ..Default::default()
+                            })
+                            .with(ButtonAction { action_type })
+                            .with_children(|parent| {
+                                parent
+                                    .spawn(TextBundle {
+                                        text: Text {
+                                            font: font.clone(),
+                                            value: String::new(),
+                                            style: text_style.clone(),
+                                        },
+                                        ..Default::default()
+                                    })
+                                    .with(SettingLabel);
                             });
-                        });
+                    }
                 });
-        }
-    }
+        });
 }
 
-fn server_client_list(
-    network: Res<Network>,
-    mut info: ResMut<Info>,
-    mut server: Query<&mut Server>,
-    mut list: Query<&mut Text, With<ServerList>>,
+fn settings_button_system(
+    (mut settings, mut rebinding, mut tr): (ResMut<Settings>, ResMut<Rebinding>, ResMut<Tr>),
+    interactions: Query<ClickedButton, ButtonChanged>,
 ) {
-    match network.network_type {
-        NetworkType::Client => {
-            let mut s = "Joined Users:".to_string();
-            for client in info.players.iter() {
-                s.push_str(&format!("\n{}", client.to_string()));
+    for (_, action) in interactions
+        .iter()
+        .filter(|(&interaction, _)| interaction == Interaction::Clicked)
+    {
+        match action.action_type {
+            ButtonActionType::Rebind(binding) => {
+                rebinding.binding = Some(binding);
+                rebinding.notice = tr.fmt("settings.press_key", &[&tr.binding(binding)]);
             }
-            if let Some(ref mut list) = list.iter_mut().next() {
-                list.value = s;
+            ButtonActionType::CameraSensitivity => {
+                let next = SENSITIVITY_STEPS
+                    .iter()
+                    .position(|&step| step > settings.camera_sensitivity)
+                    .unwrap_or(0);
+                settings.camera_sensitivity = SENSITIVITY_STEPS[next];
             }
-        }
-        NetworkType::Server => {
-            if let Some(mut server) = server.iter_mut().next() {
-                let mut s = "Joined Users:\n127.0.0.1:12345".to_string();
-                let mut users = vec!["127.0.0.1:12345".to_string()];
-                for client in server.clients.iter().filter_map(|(address, connection)| {
-                    if connection.state == ConnectionState::Healthy {
-                        Some(address)
-                    } else {
-                        None
-                    }
-                }) {
-                    s.push_str(&format!("\n{}", client.to_string()));
-                    users.push(client.to_string());
-                }
-                if let Some(ref mut list) = list.iter_mut().next() {
-                    list.value = s;
+            ButtonActionType::Volume => settings.volume = next_volume(settings.volume),
+            ButtonActionType::MusicVolume => {
+                settings.music_volume = next_volume(settings.music_volume)
+            }
+            ButtonActionType::EffectsVolume => {
+                settings.effects_volume = next_volume(settings.effects_volume)
+            }
+            ButtonActionType::Multisampling => {
+                let next = MSAA_STEPS
+                    .iter()
+                    .position(|&step| step > settings.graphics.msaa)
+                    .unwrap_or(0);
+                settings.graphics.msaa = MSAA_STEPS[next];
+                rebinding.notice = tr.get("settings.msaa_restart");
+            }
+            ButtonActionType::VSync => settings.graphics.vsync = !settings.graphics.vsync,
+            ButtonActionType::DisplayMode => {
+                settings.graphics.mode = next_of(&DisplayMode::ALL, settings.graphics.mode)
+            }
+            ButtonActionType::Resolution => {
+                // Steps through every resolution, then back to the platform default
+                settings.graphics.resolution = match settings.graphics.resolution {
+                    None => Some(RESOLUTIONS[0]),
+                    Some(current) => RESOLUTIONS
+                        .iter()
+                        .position(|&resolution| resolution == current)
+                        .and_then(|index| RESOLUTIONS.get(index + 1))
+                        .copied(),
                 }
-                if info.players != users {
-                    server.send_to_all(
-                        MessageData::ServerInfo {
-                            players: users.clone(),
-                        }
-                        .into_bytes(),
-                    );
-                    info.players = users;
+            }
+            ButtonActionType::Lighting => {
+                settings.graphics.lighting = next_of(&LightQuality::ALL, settings.graphics.lighting)
+            }
+            ButtonActionType::UiScale => {
+                let next = UI_SCALE_STEPS
+                    .iter()
+                    .position(|&step| step > settings.graphics.ui_scale)
+                    .unwrap_or(0);
+                settings.graphics.ui_scale = UI_SCALE_STEPS[next];
+            }
+            ButtonActionType::TroopMarkers => {
+                let next = TROOP_MARKER_STEPS
+                    .iter()
+                    .position(|&step| step > settings.graphics.troop_markers)
+                    .unwrap_or(0);
+                settings.graphics.troop_markers = TROOP_MARKER_STEPS[next];
+            }
+            ButtonActionType::FactionIcons => {
+                settings.accessibility.faction_icons = !settings.accessibility.faction_icons
+            }
+            ButtonActionType::HighContrast => {
+                settings.accessibility.high_contrast = !settings.accessibility.high_contrast
+            }
+            ButtonActionType::FontScale => {
+                let next = FONT_SCALE_STEPS
+                    .iter()
+                    .position(|&step| step > settings.accessibility.font_scale)
+                    .unwrap_or(0);
+                settings.accessibility.font_scale = FONT_SCALE_STEPS[next];
+            }
+            ButtonActionType::Language => {
+                settings.language = tr.next_language();
+                *tr = Tr::load(&settings.language);
+            }
+            ButtonActionType::BugCapture => settings.bug_capture = !settings.bug_capture,
+            ButtonActionType::ResetSettings => {
+                *settings = Settings::default();
+                *rebinding = Rebinding::default();
+                if tr.language != settings.language {
+                    *tr = Tr::load(&settings.language);
                 }
             }
+            _ => (),
         }
-        NetworkType::None => (),
     }
 }
 
-fn server_disconnect(
-    mut state: ResMut<State<Screen>>,
-    network: Res<Network>,
-    client: Query<&Client>,
+/// Steps of a tenth, wrapping from full back round to silent
+pub fn next_volume(volume: f32) -> f32 {
+    let tenths = (volume * 10.0).round() as i32;
+    ((tenths + 1) % 11) as f32 / 10.0
+}
+
+/// Hands the next key pressed to whichever action is waiting for one
+fn rebind_system(
+    (keyboard_input, tr): (Res<Input<KeyCode>>, Res<Tr>),
+    (mut settings, mut rebinding): (ResMut<Settings>, ResMut<Rebinding>),
 ) {
-    if network.network_type == NetworkType::Client {
-        if let Some(client) = client.iter().next() {
-            if let Some(server) = client.server {
-                if server.state == ConnectionState::Disconnected {
-                    state.overwrite_next(Screen::MainMenu).unwrap();
-                }
+    let binding = if let Some(binding) = rebinding.binding {
+        binding
+    } else {
+        return;
+    };
+    if let Some(&key) = keyboard_input.get_just_pressed().next() {
+        rebinding.binding = None;
+        rebinding.notice = if key == KeyCode::Escape {
+            String::new()
+        } else {
+            match settings.rebind(binding, key) {
+                Ok(()) => tr.fmt(
+                    "settings.rebound",
+                    &[&tr.binding(binding), &format!("{:?}", key)],
+                ),
+                Err(other) => tr.fmt(
+                    "settings.key_in_use",
+                    &[&format!("{:?}", key), &tr.binding(other)],
+                ),
+            }
+        };
+    }
+}
+
+fn settings_label_system(
+    (settings, rebinding, tr): (Res<Settings>, Res<Rebinding>, Res<Tr>),
+    buttons: Query<&ButtonAction>,
+    mut labels: Query<(&Parent, &mut Text), With<SettingLabel>>,
+    mut notice: Query<&mut Text, (With<SettingsNotice>, Without<SettingLabel>)>,
+) {
+    for (parent, mut text) in labels.iter_mut() {
+        let value = match buttons.get(parent.0).map(|action| &action.action_type) {
+            Ok(ButtonActionType::Rebind(binding)) if rebinding.binding == Some(*binding) => {
+                format!("{}: ...", tr.binding(*binding))
+            }
+            Ok(ButtonActionType::Rebind(binding)) => {
+                format!("{}: {:?}", tr.binding(*binding), settings.key(*binding))
             }
+            Ok(ButtonActionType::CameraSensitivity) => tr.fmt(
+                "settings.camera_sensitivity",
+                &[&settings.camera_sensitivity],
+            ),
+            Ok(ButtonActionType::Volume) => {
+                tr.fmt("settings.volume", &[&(settings.volume * 100.0).round()])
+            }
+            Ok(ButtonActionType::MusicVolume) => tr.fmt(
+                "settings.music_volume",
+                &[&(settings.music_volume * 100.0).round()],
+            ),
+            Ok(ButtonActionType::EffectsVolume) => tr.fmt(
+                "settings.effects_volume",
+                &[&(settings.effects_volume * 100.0).round()],
+            ),
+            Ok(ButtonActionType::Multisampling) => {
+                tr.fmt("settings.multisampling", &[&settings.graphics.msaa])
+            }
+            Ok(ButtonActionType::VSync) => {
+                tr.fmt("settings.vsync", &[&on_off(&tr, settings.graphics.vsync)])
+            }
+            Ok(ButtonActionType::DisplayMode) => tr.fmt(
+                "settings.display_mode",
+                &[&tr.get(settings.graphics.mode.key())],
+            ),
+            Ok(ButtonActionType::Resolution) => {
+                let resolution = match settings.graphics.resolution {
+                    Some((width, height)) => format!("{}x{}", width, height),
+                    None => tr.get("settings.resolution.default"),
+                };
+                tr.fmt("settings.resolution", &[&resolution])
+            }
+            Ok(ButtonActionType::Lighting) => tr.fmt(
+                "settings.lighting",
+                &[&tr.get(settings.graphics.lighting.key())],
+            ),
+            Ok(ButtonActionType::UiScale) => tr.fmt(
+                "settings.ui_scale",
+                &[&(settings.graphics.ui_scale * 100.0).round()],
+            ),
+            Ok(ButtonActionType::TroopMarkers) => {
+                let threshold = match settings.graphics.troop_markers {
+                    0 => tr.get("settings.off"),
+                    threshold => tr.fmt("settings.troop_markers.above", &[&threshold]),
+                };
+                tr.fmt("settings.troop_markers", &[&threshold])
+            }
+            Ok(ButtonActionType::FactionIcons) => tr.fmt(
+                "settings.faction_icons",
+                &[&on_off(&tr, settings.accessibility.faction_icons)],
+            ),
+            Ok(ButtonActionType::HighContrast) => tr.fmt(
+                "settings.high_contrast",
+                &[&on_off(&tr, settings.accessibility.high_contrast)],
+            ),
+            Ok(ButtonActionType::FontScale) => tr.fmt(
+                "settings.font_scale",
+                &[&(settings.accessibility.font_scale * 100.0).round()],
+            ),
+            Ok(ButtonActionType::Language) => {
+                tr.fmt("settings.language", &[&tr.get("language.name")])
+            }
+            Ok(ButtonActionType::BugCapture) => tr.fmt(
+                "settings.bug_capture",
+                &[&on_off(&tr, settings.bug_capture)],
+            ),
+            Ok(ButtonActionType::ResetSettings) => tr.get("settings.reset"),
+            _ => tr.get("menu.back"),
+        };
+        if text.value != value {
+            text.value = value;
+        }
+    }
+    for mut text in notice.iter_mut() {
+        let value = if rebinding.notice.is_empty() {
+            tr.get("settings.hint")
+        } else {
+            rebinding.notice.clone()
+        };
+        if text.value != value {
+            text.value = value;
         }
     }
 }
 
-fn init_join_menu(
+fn on_off(tr: &Tr, on: bool) -> String {
+    tr.get(if on { "settings.on" } else { "settings.off" })
+}
+
+fn save_settings(settings: Res<Settings>) {
+    settings.save();
+}
+
+/// Types into whichever text box was clicked, until it is clicked again or Return is pressed
+fn text_field_system(
+    (mut editing, mut settings): (ResMut<Editing>, ResMut<Settings>),
+    (keyboard_input, characters): (Res<Input<KeyCode>>, Res<Events<ReceivedCharacter>>),
+    mut reader: Local<EventReader<ReceivedCharacter>>,
+    mut shown: Local<Option<TextField>>,
+    mut labels: Query<(&mut Text, &TextField)>,
+    mut server: Query<&mut Server>,
+) {
+    let typed = reader.iter(&characters).map(|c| c.char).collect::<Vec<_>>();
+    let mut changed = *shown != editing.field;
+    if let Some(field) = editing.field {
+        let value = field.value(&mut settings);
+        for c in typed.into_iter().filter(|c| !c.is_control()) {
+            if field != TextField::PlayerName || value.chars().count() < MAX_NAME_LENGTH {
+                value.push(c);
+                changed = true;
+            }
+        }
+        if keyboard_input.just_pressed(KeyCode::Back) {
+            changed |= value.pop().is_some();
+        }
+        if keyboard_input.just_pressed(KeyCode::Return)
+            || keyboard_input.just_pressed(KeyCode::Escape)
+        {
+            editing.field = None;
+            changed = true;
+        }
+    }
+    if !changed {
+        return;
+    }
+    *shown = editing.field;
+    for (mut text, &field) in labels.iter_mut() {
+        text.value = field.label(&settings, editing.field == Some(field));
+    }
+    // Joins are checked against whatever the host has typed so far
+    if let Some(mut server) = server.iter_mut().next() {
+        server.password = settings.online.password.clone();
+        server.name = player_name(&settings);
+    }
+    if editing.field.is_none() {
+        settings.save();
+    }
+}
+
+fn stop_editing(mut editing: ResMut<Editing>, settings: Res<Settings>) {
+    if editing.field.take().is_some() {
+        settings.save();
+    }
+}
+
+struct ServerList;
+
+fn init_server_menu(
     commands: &mut Commands,
     asset_server: Res<AssetServer>,
     button_materials: Res<ButtonMaterials>,
     mut network: ResMut<Network>,
+    (mut info, mut lobby): (ResMut<Info>, ResMut<Lobby>),
+    (mut game_rng, user_settings, profile): (ResMut<GameRng>, Res<Settings>, Res<Profile>),
 ) {
-    commands
-        .spawn(NodeBundle {
-            style: Style {
-                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
-                margin: Rect::all(Val::Auto),
-                justify_content: JustifyContent::Center,
-                align_items: AlignItems::Center,
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .with(ScreenEntity)
-        .with_children(|parent| {
-            parent
-                .spawn(ButtonBundle {
+    // Decided once the game starts, from the factions that were picked
+    info.factions_in_play.clear();
+    *lobby = Lobby::default();
+    *game_rng = GameRng::default();
+    // Spectators don't get a seat, so there is nothing for them to pick
+    if network.role == NetworkRole::Player {
+        init_lobby_picker(commands, &asset_server, &button_materials, &Faction::ALL);
+    }
+
+    match network.network_type {
+        NetworkType::None | NetworkType::Server | NetworkType::Local => {
+            init_faction_toggles(commands, &asset_server, &button_materials);
+            commands
+                .spawn(NodeBundle {
                     style: Style {
-                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                        size: Size::new(Val::Percent(50.0), Val::Percent(100.0)),
+                        margin: Rect::all(Val::Auto),
                         justify_content: JustifyContent::Center,
                         align_items: AlignItems::Center,
                         ..Default::default()
                     },
-                    material: button_materials.normal.clone(),
                     ..Default::default()
                 })
-                .with(ButtonAction {
-                    action_type: ButtonActionType::ConnectToServer,
-                })
+                .with(ScreenEntity)
                 .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text {
-                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                            value: "Join Game".to_string(),
-                            style: TextStyle {
-                                font_size: 20.0,
-                                color: Color::ANTIQUE_WHITE,
-                                ..Default::default()
+                    parent
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: String::new(),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::BLACK,
+                                    ..Default::default()
+                                },
                             },
-                        },
-                        ..Default::default()
-                    });
+                            ..Default::default()
+                        })
+                        .with(ServerList);
                 })
-                .spawn(ButtonBundle {
+                .spawn(NodeBundle {
                     style: Style {
-                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                        size: Size::new(Val::Percent(50.0), Val::Percent(100.0)),
+                        margin: Rect::all(Val::Auto),
                         justify_content: JustifyContent::Center,
                         align_items: AlignItems::Center,
                         ..Default::default()
                     },
-                    material: button_materials.normal.clone(),
                     ..Default::default()
                 })
-                .with(ButtonAction {
-                    action_type: ButtonActionType::GoBack,
-                })
+                .with(ScreenEntity)
                 .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text {
-                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                            value: "Back".to_string(),
-                            style: TextStyle {
-                                font_size: 20.0,
-                                color: Color::ANTIQUE_WHITE,
+                    parent
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
                                 ..Default::default()
                             },
-                        },
-                        ..Default::default()
-                    });
-                });
-        });
-
-    println!("Binding 127.0.0.1:12346");
-    commands.spawn((Client::new("12346"),));
-    network.network_type = NetworkType::Client;
+                            material: button_materials.normal.clone(),
+                            ..Default::default()
+                        })
+                        .with(ButtonAction {
+                            action_type: ButtonActionType::StartGame,
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn(TextBundle {
+                                    text: Text {
+                                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                        value: String::new(),
+                                        style: TextStyle {
+                                            font_size: 20.0,
+                                            color: Color::ANTIQUE_WHITE,
+                                            ..Default::default()
+                                        },
+                                    },
+                                    ..Default::default()
+                                })
+                                .with(Localized("menu.start_game"));
+                        })
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            material: button_materials.normal.clone(),
+                            ..Default::default()
+                        })
+                        .with(ButtonAction {
+                            action_type: ButtonActionType::BotDifficulty,
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn(TextBundle {
+                                    text: Text {
+                                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                        value: String::new(),
+                                        style: TextStyle {
+                                            font_size: 20.0,
+                                            color: Color::ANTIQUE_WHITE,
+                                            ..Default::default()
+                                        },
+                                    },
+                                    ..Default::default()
+                                })
+                                .with(LobbyOptionText::BotDifficulty);
+                        })
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            material: button_materials.normal.clone(),
+                            ..Default::default()
+                        })
+                        .with(ButtonAction {
+                            action_type: ButtonActionType::TimeBank,
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn(TextBundle {
+                                    text: Text {
+                                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                        value: String::new(),
+                                        style: TextStyle {
+                                            font_size: 20.0,
+                                            color: Color::ANTIQUE_WHITE,
+                                            ..Default::default()
+                                        },
+                                    },
+                                    ..Default::default()
+                                })
+                                .with(LobbyOptionText::TimeBank);
+                        })
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            material: button_materials.normal.clone(),
+                            ..Default::default()
+                        })
+                        .with(ButtonAction {
+                            action_type: ButtonActionType::OutOfTime,
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn(TextBundle {
+                                    text: Text {
+                                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                        value: String::new(),
+                                        style: TextStyle {
+                                            font_size: 20.0,
+                                            color: Color::ANTIQUE_WHITE,
+                                            ..Default::default()
+                                        },
+                                    },
+                                    ..Default::default()
+                                })
+                                .with(LobbyOptionText::OutOfTime);
+                        })
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            material: button_materials.normal.clone(),
+                            ..Default::default()
+                        })
+                        .with(ButtonAction {
+                            action_type: ButtonActionType::Rules,
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn(TextBundle {
+                                    text: Text {
+                                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                        value: String::new(),
+                                        style: TextStyle {
+                                            font_size: 20.0,
+                                            color: Color::ANTIQUE_WHITE,
+                                            ..Default::default()
+                                        },
+                                    },
+                                    ..Default::default()
+                                })
+                                .with(LobbyOptionText::Rules);
+                        })
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            material: button_materials.normal.clone(),
+                            ..Default::default()
+                        })
+                        .with(ButtonAction {
+                            action_type: ButtonActionType::Deck,
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn(TextBundle {
+                                    text: Text {
+                                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                        value: String::new(),
+                                        style: TextStyle {
+                                            font_size: 20.0,
+                                            color: Color::ANTIQUE_WHITE,
+                                            ..Default::default()
+                                        },
+                                    },
+                                    ..Default::default()
+                                })
+                                .with(LobbyOptionText::Deck);
+                        })
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            material: button_materials.normal.clone(),
+                            ..Default::default()
+                        })
+                        .with(ButtonAction {
+                            action_type: ButtonActionType::HouseRules,
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn(TextBundle {
+                                    text: Text {
+                                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                        value: String::new(),
+                                        style: TextStyle {
+                                            font_size: 20.0,
+                                            color: Color::ANTIQUE_WHITE,
+                                            ..Default::default()
+                                        },
+                                    },
+                                    ..Default::default()
+                                })
+                                .with(LobbyOptionText::HouseRules);
+                        })
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            material: button_materials.normal.clone(),
+                            ..Default::default()
+                        })
+                        .with(ButtonAction {
+                            action_type: ButtonActionType::PlayerCount,
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn(TextBundle {
+                                    text: Text {
+                                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                        value: String::new(),
+                                        style: TextStyle {
+                                            font_size: 20.0,
+                                            color: Color::ANTIQUE_WHITE,
+                                            ..Default::default()
+                                        },
+                                    },
+                                    ..Default::default()
+                                })
+                                .with(LobbyOptionText::PlayerCount);
+                        })
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            material: button_materials.normal.clone(),
+                            ..Default::default()
+                        })
+                        .with(ButtonAction {
+                            action_type: ButtonActionType::EditField(TextField::PlayerName),
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn(TextBundle {
+                                    text: Text {
+                                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                        value: TextField::PlayerName.label(&user_settings, false),
+                                        style: TextStyle {
+                                            font_size: 20.0,
+                                            color: Color::ANTIQUE_WHITE,
+                                            ..Default::default()
+                                        },
+                                    },
+                                    ..Default::default()
+                                })
+                                .with(TextField::PlayerName);
+                        })
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            material: button_materials.normal.clone(),
+                            ..Default::default()
+                        })
+                        .with(ButtonAction {
+                            action_type: ButtonActionType::EditField(TextField::Password),
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn(TextBundle {
+                                    text: Text {
+                                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                        value: TextField::Password.label(&user_settings, false),
+                                        style: TextStyle {
+                                            font_size: 20.0,
+                                            color: Color::ANTIQUE_WHITE,
+                                            ..Default::default()
+                                        },
+                                    },
+                                    ..Default::default()
+                                })
+                                .with(TextField::Password);
+                        })
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            material: button_materials.normal.clone(),
+                            ..Default::default()
+                        })
+                        .with(ButtonAction {
+                            action_type: ButtonActionType::GoBack,
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn(TextBundle {
+                                    text: Text {
+                                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                        value: String::new(),
+                                        style: TextStyle {
+                                            font_size: 20.0,
+                                            color: Color::ANTIQUE_WHITE,
+                                            ..Default::default()
+                                        },
+                                    },
+                                    ..Default::default()
+                                })
+                                .with(Localized("menu.back"));
+                        });
+                });
+
+            println!("Binding 0.0.0.0:{}", SERVER_PORT);
+            let mut server = Server::new(SERVER_PORT);
+            server.password = user_settings.online.password.clone();
+            server.name = player_name(&user_settings);
+            server.allow_casters = user_settings.online.allow_casters;
+            server.rating = profile.shared_rating(&user_settings);
+            commands.spawn((server,));
+            network.network_type = NetworkType::Server;
+        }
+        NetworkType::Client => {
+            commands
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(50.0), Val::Percent(100.0)),
+                        margin: Rect::all(Val::Auto),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with(ScreenEntity)
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: String::new(),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::BLACK,
+                                    ..Default::default()
+                                },
+                            },
+                            ..Default::default()
+                        })
+                        .with(ServerList);
+                })
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(50.0), Val::Percent(100.0)),
+                        margin: Rect::all(Val::Auto),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with(ScreenEntity)
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: String::new(),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::ANTIQUE_WHITE,
+                                    ..Default::default()
+                                },
+                            },
+                            ..Default::default()
+                        })
+                        .with(Localized("menu.waiting_for_server"))
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            material: button_materials.normal.clone(),
+                            ..Default::default()
+                        })
+                        .with(ButtonAction {
+                            action_type: ButtonActionType::GoBack,
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn(TextBundle {
+                                    text: Text {
+                                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                        value: String::new(),
+                                        style: TextStyle {
+                                            font_size: 20.0,
+                                            color: Color::ANTIQUE_WHITE,
+                                            ..Default::default()
+                                        },
+                                    },
+                                    ..Default::default()
+                                })
+                                .with(Localized("menu.back"));
+                        });
+                });
+        }
+    }
+}
+
+fn server_client_list(
+    (network, rules, tr, house_rules): (Res<Network>, Res<RuleSet>, Res<Tr>, Res<HouseRules>),
+    mut info: ResMut<Info>,
+    mut lobby: ResMut<Lobby>,
+    mut informed: Local<(usize, RuleSet)>,
+    mut server: Query<&mut Server>,
+    client: Query<&Client>,
+    mut list: Query<&mut Text, With<ServerList>>,
+) {
+    match network.network_type {
+        NetworkType::Client => {
+            let mut s = tr.get("menu.joined_users");
+            if lobby.seats.is_empty() {
+                for client in info.players.iter() {
+                    s.push_str(&format!("\n{}", client.to_string()));
+                }
+            } else {
+                s.push_str(&format!(
+                    "\n{}",
+                    tr.fmt("lobby.players", &[&lobby.player_count])
+                ));
+                s.push_str(&lobby.describe());
+            }
+            // So everyone can check they are playing by the same script as the host
+            s.push_str(&format!("\n{}", house_rules.active_label(&tr)));
+            if let Some(client) = client.iter().next() {
+                if !client.differing_assets.is_empty() {
+                    s.push_str(&format!(
+                        "\n{}",
+                        tr.fmt(
+                            "lobby.own_assets_differ",
+                            &[&client.differing_assets.join(", ")]
+                        )
+                    ));
+                }
+            }
+            if let Some(ref mut list) = list.iter_mut().next() {
+                list.value = s;
+            }
+        }
+        NetworkType::Server => {
+            if let Some(mut server) = server.iter_mut().next() {
+                let mut seated = vec![(HOST.to_string(), server.name.clone(), server.rating)];
+                for (address, name) in server.clients.iter().filter_map(|(&address, connection)| {
+                    // Spectators watch without taking a seat, and nobody sits down before joining
+                    match server.name_of(address) {
+                        Some(name)
+                            if connection.state == ConnectionState::Healthy
+                                && !server.is_spectator(address) =>
+                        {
+                            Some((address, name))
+                        }
+                        _ => None,
+                    }
+                }) {
+                    seated.push((
+                        address.to_string(),
+                        name.to_string(),
+                        server.rating_of(address),
+                    ));
+                }
+                lobby.sync_players(&seated);
+                // Everyone still gets in, but the host should know whose cards may look different
+                let mismatches = seated
+                    .iter()
+                    .filter_map(|(address, name, _)| {
+                        let folders = server.differing_assets_of(address.parse().ok()?);
+                        if folders.is_empty() {
+                            None
+                        } else {
+                            Some(format!(
+                                "\n{}",
+                                tr.fmt("lobby.assets_differ", &[name, &folders.join(", ")])
+                            ))
+                        }
+                    })
+                    .collect::<String>();
+                let users = seated
+                    .into_iter()
+                    .map(|(_, name, _)| name)
+                    .collect::<Vec<_>>();
+                if let Some(ref mut list) = list.iter_mut().next() {
+                    list.value = format!(
+                        "{}{}{}",
+                        tr.get("menu.joined_users"),
+                        lobby.describe(),
+                        mismatches
+                    );
+                }
+                // Spectators don't show up in the player list but still need the rules
+                let connected = server
+                    .clients
+                    .values()
+                    .filter(|connection| connection.state == ConnectionState::Healthy)
+                    .count();
+                if info.players != users || *informed != (connected, *rules) {
+                    server.broadcast(&ServerEvent::ServerInfo {
+                        players: users.clone(),
+                        rules: *rules,
+                    });
+                    info.players = users;
+                    *informed = (connected, *rules);
+                }
+            }
+        }
+        NetworkType::None | NetworkType::Local => (),
+    }
+}
+
+fn server_disconnect(
+    commands: &mut Commands,
+    mut state: ResMut<State<Screen>>,
+    mut network: ResMut<Network>,
+    client: Query<(Entity, &Client)>,
+) {
+    if network.network_type == NetworkType::Client {
+        if let Some((entity, client)) = client.iter().next() {
+            // Back to the join screen to say why, where a fresh client gets spawned
+            if let Some(reason) = &client.rejection {
+                network.rejection = Some(reason.clone());
+                commands.despawn(entity);
+                state.overwrite_next(Screen::Join).unwrap();
+                return;
+            }
+            if let Some(server) = client.server {
+                if server.state == ConnectionState::Disconnected {
+                    state.overwrite_next(Screen::MainMenu).unwrap();
+                }
+            }
+        }
+    }
+}
+
+/// Holds a button for each game found on the local network
+struct DiscoveredList;
+
+struct DiscoveredEntry;
+
+/// Lists games found on the local network, followed by any from the lobby directory
+fn discovered_list_system(
+    commands: &mut Commands,
+    (mut discovery, mut directory): (ResMut<Discovery>, ResMut<LobbyDirectory>),
+    (asset_server, button_materials): (Res<AssetServer>, Res<ButtonMaterials>),
+    lists: Query<Entity, With<DiscoveredList>>,
+    entries: Query<Entity, With<DiscoveredEntry>>,
+) {
+    if !discovery.changed && !directory.changed {
+        return;
+    }
+    let list = if let Some(list) = lists.iter().next() {
+        list
+    } else {
+        return;
+    };
+    discovery.changed = false;
+    directory.changed = false;
+    for entry in entries.iter() {
+        commands.despawn_recursive(entry);
+    }
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let mut labels = if discovery.games.is_empty() {
+        vec![(
+            None,
+            "Looking for games on the local network...".to_string(),
+        )]
+    } else {
+        discovery
+            .games
+            .iter()
+            .map(|game| {
+                let mut label = format!(
+                    "{} ({}/{}) {}",
+                    game.name, game.players, game.max_players, game.address
+                );
+                if game.incompatible.is_some() {
+                    label.push_str(" - incompatible version");
+                }
+                (Some(game.address), label)
+            })
+            .collect()
+    };
+    if let Some(error) = &directory.error {
+        labels.push((None, error.clone()));
+    }
+    labels.extend(directory.visible().filter_map(|game| {
+        // Whatever the directory sent that isn't an address can't be joined anyway
+        let address = game.address.parse().ok()?;
+        let mut label = format!(
+            "Online: {} ({}/{}){}",
+            game.name,
+            game.players,
+            game.max_players,
+            if game.password_protected {
+                " [password]"
+            } else {
+                ""
+            }
+        );
+        if game.version != GAME_VERSION {
+            label.push_str(" - incompatible version");
+        }
+        Some((Some(address), label))
+    }));
+    commands.set_current_entity(list);
+    commands.with_children(|parent| {
+        for (address, label) in labels {
+            let text = TextBundle {
+                text: Text {
+                    font: font.clone(),
+                    value: label,
+                    style: TextStyle {
+                        font_size: 20.0,
+                        color: Color::ANTIQUE_WHITE,
+                        ..Default::default()
+                    },
+                },
+                ..Default::default()
+            };
+            if let Some(address) = address {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Percent(100.0), Val::Px(32.0)),
+                            margin: Rect::all(Val::Px(4.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        material: button_materials.normal.clone(),
+                        ..Default::default()
+                    })
+                    .with(ButtonAction {
+                        action_type: ButtonActionType::JoinDiscovered(address),
+                    })
+                    .with(DiscoveredEntry)
+                    .with_children(|parent| {
+                        parent.spawn(text);
+                    });
+            } else {
+                parent.spawn(text).with(DiscoveredEntry);
+            }
+        }
+    });
+}
+
+fn init_join_menu(
+    commands: &mut Commands,
+    (asset_server, user_settings, assets): (Res<AssetServer>, Res<Settings>, Res<AssetPack>),
+    button_materials: Res<ButtonMaterials>,
+    mut network: ResMut<Network>,
+) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                margin: Rect::all(Val::Auto),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with_children(|parent| {
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: button_materials.normal.clone(),
+                    ..Default::default()
+                })
+                .with(ButtonAction {
+                    action_type: ButtonActionType::ConnectToServer,
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: String::new(),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::ANTIQUE_WHITE,
+                                    ..Default::default()
+                                },
+                            },
+                            ..Default::default()
+                        })
+                        .with(Localized("menu.join_game"));
+                })
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: button_materials.normal.clone(),
+                    ..Default::default()
+                })
+                .with(ButtonAction {
+                    action_type: ButtonActionType::SpectateServer,
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: String::new(),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::ANTIQUE_WHITE,
+                                    ..Default::default()
+                                },
+                            },
+                            ..Default::default()
+                        })
+                        .with(Localized("menu.spectate"));
+                })
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: button_materials.normal.clone(),
+                    ..Default::default()
+                })
+                .with(ButtonAction {
+                    action_type: ButtonActionType::RefreshGames,
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: String::new(),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::ANTIQUE_WHITE,
+                                    ..Default::default()
+                                },
+                            },
+                            ..Default::default()
+                        })
+                        .with(Localized("menu.refresh"));
+                })
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: button_materials.normal.clone(),
+                    ..Default::default()
+                })
+                .with(ButtonAction {
+                    action_type: ButtonActionType::BrowseOnline,
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: String::new(),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::ANTIQUE_WHITE,
+                                    ..Default::default()
+                                },
+                            },
+                            ..Default::default()
+                        })
+                        .with(Localized("menu.browse_online"));
+                })
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: button_materials.normal.clone(),
+                    ..Default::default()
+                })
+                .with(ButtonAction {
+                    action_type: ButtonActionType::ToggleHideProtected,
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: String::new(),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::ANTIQUE_WHITE,
+                                    ..Default::default()
+                                },
+                            },
+                            ..Default::default()
+                        })
+                        .with(Localized("menu.hide_locked"));
+                })
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: button_materials.normal.clone(),
+                    ..Default::default()
+                })
+                .with(ButtonAction {
+                    action_type: ButtonActionType::EditField(TextField::PlayerName),
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: TextField::PlayerName.label(&user_settings, false),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::ANTIQUE_WHITE,
+                                    ..Default::default()
+                                },
+                            },
+                            ..Default::default()
+                        })
+                        .with(TextField::PlayerName);
+                })
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: button_materials.normal.clone(),
+                    ..Default::default()
+                })
+                .with(ButtonAction {
+                    action_type: ButtonActionType::EditField(TextField::Password),
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: TextField::Password.label(&user_settings, false),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::ANTIQUE_WHITE,
+                                    ..Default::default()
+                                },
+                            },
+                            ..Default::default()
+                        })
+                        .with(TextField::Password);
+                })
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: button_materials.normal.clone(),
+                    ..Default::default()
+                })
+                .with(ButtonAction {
+                    action_type: ButtonActionType::GoBack,
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle {
+                            text: Text {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                value: String::new(),
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::ANTIQUE_WHITE,
+                                    ..Default::default()
+                                },
+                            },
+                            ..Default::default()
+                        })
+                        .with(Localized("menu.back"));
+                });
+            if let Some(reason) = &network.rejection {
+                parent.spawn(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            bottom: Val::Percent(35.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    text: Text {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        value: format!("Could not join: {}", reason),
+                        style: TextStyle {
+                            font_size: 20.0,
+                            color: Color::RED,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                });
+            }
+        });
+
+    // Filled in by the discovered list system as games are heard from
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Percent(10.0),
+                    left: Val::Percent(30.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(40.0), Val::Percent(30.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(DiscoveredList);
+
+    println!("Binding 0.0.0.0:{}", CLIENT_PORT);
+    let mut client = Client::new(CLIENT_PORT);
+    client.assets = assets.folders.clone();
+    commands.spawn((client,));
+    network.network_type = NetworkType::Client;
+}
+
+const CHAT_LINES: usize = 8;
+const CHAT_MAX_LENGTH: usize = 120;
+
+#[derive(Default)]
+pub struct Chat {
+    pub history: Vec<(Option<Faction>, String)>,
+    pub input: String,
+    pub open: bool,
+    pub scroll: usize,
+    pub unread: usize,
+}
+
+impl Chat {
+    pub fn receive(&mut self, from: Option<Faction>, text: String) {
+        self.history.push((from, text));
+        if !self.open {
+            self.unread += 1;
+        }
+        // Hold the view in place if the player has scrolled back
+        if self.scroll > 0 {
+            self.scroll += 1;
+        }
+    }
+}
+
+struct ChatHistory;
+
+struct ChatInput;
+
+struct ChatLine {
+    index: usize,
+    name: bool,
+}
+
+fn init_chat(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    mut colors: ResMut<Assets<ColorMaterial>>,
+) {
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let clear = colors.add(Color::NONE.into());
+    let text_style = TextStyle {
+        font_size: 16.0,
+        color: Color::ANTIQUE_WHITE,
+        ..Default::default()
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(5.0),
+                    left: Val::Px(5.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(30.0), Val::Auto),
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(4.0)),
+                ..Default::default()
+            },
+            material: colors.add(Color::rgba(0.0, 0.0, 0.0, 0.5).into()),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        font: font.clone(),
+                        value: String::new(),
+                        style: text_style.clone(),
+                    },
+                    ..Default::default()
+                })
+                .with(ChatInput)
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        ..Default::default()
+                    },
+                    material: clear.clone(),
+                    ..Default::default()
+                })
+                .with(ChatHistory)
+                .with_children(|parent| {
+                    for index in 0..CHAT_LINES {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    flex_direction: FlexDirection::Row,
+                                    ..Default::default()
+                                },
+                                material: clear.clone(),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                parent
+                                    .spawn(TextBundle {
+                                        text: Text {
+                                            font: font.clone(),
+                                            value: String::new(),
+                                            style: text_style.clone(),
+                                        },
+                                        ..Default::default()
+                                    })
+                                    .with(ChatLine { index, name: true })
+                                    .spawn(TextBundle {
+                                        text: Text {
+                                            font: font.clone(),
+                                            value: String::new(),
+                                            style: text_style.clone(),
+                                        },
+                                        ..Default::default()
+                                    })
+                                    .with(ChatLine { index, name: false });
+                            });
+                    }
+                });
+        });
+}
+
+fn chat_input_system(
+    mut chat: ResMut<Chat>,
+    (info, network): (Res<Info>, Res<Network>),
+    (keyboard_input, characters): (Res<Input<KeyCode>>, Res<Events<ReceivedCharacter>>),
+    mut reader: Local<EventReader<ReceivedCharacter>>,
+    players: Query<&Player>,
+    mut server: Query<&mut Server>,
+    mut client: Query<&mut Client>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        chat.open = !chat.open;
+        chat.unread = 0;
+        chat.scroll = 0;
+    }
+    let typed = reader.iter(&characters).map(|c| c.char).collect::<Vec<_>>();
+    if !chat.open {
+        return;
+    }
+    for c in typed.into_iter().filter(|c| !c.is_control()) {
+        if chat.input.len() < CHAT_MAX_LENGTH {
+            chat.input.push(c);
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        chat.input.pop();
+    }
+    if keyboard_input.just_pressed(KeyCode::PageUp) {
+        chat.scroll = (chat.scroll + 1).min(chat.history.len().saturating_sub(CHAT_LINES));
+    } else if keyboard_input.just_pressed(KeyCode::PageDown) {
+        chat.scroll = chat.scroll.saturating_sub(1);
+    }
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        let text = chat.input.trim().to_string();
+        chat.input.clear();
+        if text.is_empty() {
+            return;
+        }
+        match network.network_type {
+            NetworkType::Client => {
+                // The server echoes our message back to us along with everyone else
+                if let Some(mut client) = client.iter_mut().next() {
+                    let from = client.faction;
+                    client.request(ClientRequest::Chat { from, text });
+                }
+            }
+            _ => {
+                let from = if info.play_order.is_empty() {
+                    None
+                } else {
+                    players
+                        .get(info.get_active_player())
+                        .ok()
+                        .map(|player| player.faction)
+                };
+                if let Some(mut server) = server.iter_mut().next() {
+                    server.broadcast(&ServerEvent::Chat {
+                        from,
+                        text: text.clone(),
+                    });
+                }
+                chat.receive(from, text);
+            }
+        }
+    }
+}
+
+fn chat_display_system(
+    chat: Res<Chat>,
+    mut history: Query<&mut Style, With<ChatHistory>>,
+    mut input: Query<&mut Text, With<ChatInput>>,
+    mut lines: Query<(&mut Text, &ChatLine), Without<ChatInput>>,
+) {
+    for mut style in history.iter_mut() {
+        style.display = if chat.open {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+    for mut text in input.iter_mut() {
+        text.value = if chat.open {
+            format!("> {}_", chat.input)
+        } else if chat.unread > 0 {
+            format!("[Tab] Chat ({} new)", chat.unread)
+        } else {
+            "[Tab] Chat".to_string()
+        };
+    }
+    for (mut text, line) in lines.iter_mut() {
+        let entry = chat
+            .history
+            .len()
+            .checked_sub(1 + line.index + chat.scroll)
+            .and_then(|i| chat.history.get(i));
+        if let Some((from, message)) = entry {
+            if line.name {
+                text.value = match from {
+                    Some(faction) => format!("{}: ", faction),
+                    None => "Observer: ".to_string(),
+                };
+                text.style.color = from.map_or(Color::ANTIQUE_WHITE, |faction| faction.color());
+            } else {
+                text.value = message.clone();
+            }
+        } else {
+            text.value = String::new();
+        }
+    }
+}
+
+type LobbyOptions<'a> = (
+    Res<'a, Tr>,
+    Res<'a, BotSettings>,
+    Res<'a, TurnTimer>,
+    Res<'a, RuleSet>,
+    Res<'a, DeckPresets>,
+    Res<'a, HouseRules>,
+);
+
+/// Labels the host's game options with their current values
+enum LobbyOptionText {
+    /// Unclaimed factions are played by bots of this difficulty
+    BotDifficulty,
+    TimeBank,
+    OutOfTime,
+    Rules,
+    Deck,
+    HouseRules,
+    PlayerCount,
+    /// Whether the faction is in the game or left out
+    Available(Faction),
+}
+
+#[derive(Archive, Unarchive, PartialEq, Clone, Debug)]
+#[archive(derive(CheckBytes))]
+pub struct LobbySeat {
+    pub player: String,
+    pub name: String,
+    pub faction: Option<Faction>,
+    pub ready: bool,
+    /// Shared from the player's profile, if they chose to
+    pub rating: Option<u32>,
+}
+
+pub struct Lobby {
+    pub seats: Vec<LobbySeat>,
+    /// How many factions the game is played with, whether anybody claims them or not
+    pub player_count: u32,
+    /// Factions the host left out, which nobody can pick and no bot fills in for
+    pub excluded: Vec<Faction>,
+}
+
+impl Default for Lobby {
+    fn default() -> Self {
+        Lobby {
+            seats: Vec::new(),
+            player_count: MAX_PLAYERS as u32,
+            excluded: Vec::new(),
+        }
+    }
+}
+
+impl Lobby {
+    /// Adds seats for new players and drops seats for players that left. Players are given by
+    /// address, along with the name they joined with and any rating they shared.
+    pub fn sync_players(&mut self, players: &[(String, String, Option<u32>)]) {
+        self.seats
+            .retain(|seat| players.iter().any(|(player, _, _)| *player == seat.player));
+        for (player, name, rating) in players {
+            if let Some(seat) = self.seats.iter_mut().find(|seat| seat.player == *player) {
+                seat.name = name.clone();
+                seat.rating = *rating;
+            } else {
+                self.seats.push(LobbySeat {
+                    player: player.clone(),
+                    name: name.clone(),
+                    faction: None,
+                    ready: false,
+                    rating: *rating,
+                });
+            }
+        }
+    }
+
+    pub fn seat(&self, player: &str) -> Option<&LobbySeat> {
+        self.seats.iter().find(|seat| seat.player == player)
+    }
+
+    /// The seat of whoever is hosting
+    pub fn host(&self) -> Option<&LobbySeat> {
+        self.seat(HOST)
+    }
+
+    fn claimed(&self) -> impl Iterator<Item = Faction> + '_ {
+        self.seats.iter().filter_map(|seat| seat.faction)
+    }
+
+    /// Takes a faction for a player, as long as it is available, nobody else holds it and there
+    /// is still a seat at the table for it
+    pub fn select(&mut self, player: &str, faction: Option<Faction>) -> bool {
+        if let Some(faction) = faction {
+            let holding = self
+                .seat(player)
+                .map_or(false, |seat| seat.faction.is_some());
+            if self.excluded.contains(&faction)
+                || self.claimed().any(|claimed| claimed == faction)
+                || (!holding && self.claimed().count() >= self.player_count as usize)
+            {
+                return false;
+            }
+        }
+        if let Some(seat) = self.seats.iter_mut().find(|seat| seat.player == player) {
+            seat.faction = faction;
+            seat.ready = false;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn set_ready(&mut self, player: &str, ready: bool) {
+        if let Some(seat) = self.seats.iter_mut().find(|seat| seat.player == player) {
+            // Nobody can be ready without a faction
+            seat.ready = ready && seat.faction.is_some();
+        }
+    }
+
+    /// Steps the player count up, wrapping back round to the fewest the claimed seats allow
+    pub fn next_player_count(&mut self) {
+        let fewest = self.claimed().count().max(MIN_PLAYERS) as u32;
+        self.player_count = if self.player_count >= MAX_PLAYERS as u32 {
+            fewest
+        } else {
+            (self.player_count + 1).max(fewest)
+        };
+        self.unready();
+    }
+
+    /// Leaves a faction out of the game or puts it back. A faction somebody holds stays in.
+    pub fn toggle_excluded(&mut self, faction: Faction) -> bool {
+        if let Some(i) = self
+            .excluded
+            .iter()
+            .position(|&excluded| excluded == faction)
+        {
+            self.excluded.remove(i);
+        } else if self.claimed().any(|claimed| claimed == faction) {
+            return false;
+        } else {
+            self.excluded.push(faction);
+        }
+        self.unready();
+        true
+    }
+
+    /// The table changed under everyone, so they have to ready up again
+    fn unready(&mut self) {
+        for seat in self.seats.iter_mut() {
+            seat.ready = false;
+        }
+    }
+
+    /// Every claimed faction, with the rest of the seats filled by the available base factions in
+    /// the usual order. Expansion factions only play when somebody picks them.
+    pub fn factions_in_play(&self) -> Vec<Faction> {
+        let mut open = (self.player_count as usize).saturating_sub(self.claimed().count());
+        Faction::ALL
+            .iter()
+            .copied()
+            .filter(|&faction| {
+                if self.claimed().any(|claimed| claimed == faction) {
+                    true
+                } else if open > 0 && !faction.expansion() && !self.excluded.contains(&faction) {
+                    open -= 1;
+                    true
+                } else {
+                    false
+                }
+            })
+            .collect()
+    }
+
+    /// Whether enough factions are available to fill every seat
+    pub fn seats_filled(&self) -> bool {
+        self.factions_in_play().len() == self.player_count as usize
+    }
+
+    pub fn all_ready(&self) -> bool {
+        !self.seats.is_empty() && self.seats.iter().all(|seat| seat.ready)
+    }
+
+    pub fn describe(&self) -> String {
+        self.seats
+            .iter()
+            .map(|seat| {
+                format!(
+                    "\n{}{} - {} - {}",
+                    seat.name,
+                    seat.rating
+                        .map_or(String::new(), |rating| format!(" ({})", rating)),
+                    seat.faction
+                        .map_or("No faction".to_string(), |faction| faction.to_string()),
+                    if seat.ready { "Ready" } else { "Not ready" }
+                )
+            })
+            .collect()
+    }
+}
+
+fn init_lobby_picker(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    button_materials: &ButtonMaterials,
+    factions: &[Faction],
+) {
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(0.0),
+                    left: Val::Px(0.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(100.0), Val::Percent(15.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with_children(|parent| {
+            for action_type in factions
+                .iter()
+                .map(|&faction| ButtonActionType::SelectFaction(faction))
+                .chain(std::iter::once(ButtonActionType::ToggleReady))
+            {
+                let label = match action_type {
+                    ButtonActionType::SelectFaction(faction) => faction.to_string(),
+                    _ => "Ready".to_string(),
+                };
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Percent(10.0), Val::Percent(40.0)),
+                            margin: Rect::all(Val::Px(4.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        material: button_materials.normal.clone(),
+                        ..Default::default()
+                    })
+                    .with(ButtonAction { action_type })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle {
+                            text: Text {
+                                font: font.clone(),
+                                value: label,
+                                style: TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::ANTIQUE_WHITE,
+                                    ..Default::default()
+                                },
+                            },
+                            ..Default::default()
+                        });
+                    });
+            }
+        });
+}
+
+/// The host's row of switches for which factions the game can use
+fn init_faction_toggles(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    button_materials: &ButtonMaterials,
+) {
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Percent(15.0),
+                    left: Val::Px(0.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(100.0), Val::Percent(8.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with_children(|parent| {
+            for &faction in Faction::ALL.iter() {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Percent(10.0), Val::Percent(60.0)),
+                            margin: Rect::all(Val::Px(4.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        material: button_materials.normal.clone(),
+                        ..Default::default()
+                    })
+                    .with(ButtonAction {
+                        action_type: ButtonActionType::ToggleFaction(faction),
+                    })
+                    .with_children(|parent| {
+                        parent
+                            .spawn(TextBundle {
+                                text: Text {
+                                    font: font.clone(),
+                                    value: String::new(),
+                                    style: TextStyle {
+                                        font_size: 16.0,
+                                        color: Color::ANTIQUE_WHITE,
+                                        ..Default::default()
+                                    },
+                                },
+                                ..Default::default()
+                            })
+                            .with(LobbyOptionText::Available(faction));
+                    });
+            }
+        });
+}
+
+fn lobby_button_system(
+    (network, mut presets, mut house_rules): (
+        Res<Network>,
+        ResMut<DeckPresets>,
+        ResMut<HouseRules>,
+    ),
+    (mut lobby, mut settings, mut turn_timer, mut rules): (
+        ResMut<Lobby>,
+        ResMut<BotSettings>,
+        ResMut<TurnTimer>,
+        ResMut<RuleSet>,
+    ),
+    interactions: Query<(&Interaction, &ButtonAction), (Mutated<Interaction>, With<Button>)>,
+    mut client: Query<&mut Client>,
+) {
+    for (_, action) in interactions
+        .iter()
+        .filter(|(&interaction, _)| interaction == Interaction::Clicked)
+    {
+        match network.network_type {
+            NetworkType::Client => {
+                if let Some(mut client) = client.iter_mut().next() {
+                    let ready = lobby
+                        .seats
+                        .iter()
+                        .find(|seat| seat.faction.is_some() && seat.faction == client.faction)
+                        .map_or(false, |seat| seat.ready);
+                    match action.action_type {
+                        ButtonActionType::SelectFaction(faction) => {
+                            client.request(ClientRequest::SelectFaction {
+                                faction: Some(faction),
+                            })
+                        }
+                        ButtonActionType::ToggleReady => {
+                            client.request(ClientRequest::Ready { ready: !ready })
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => match action.action_type {
+                ButtonActionType::SelectFaction(faction) => {
+                    if !lobby.select(HOST, Some(faction)) {
+                        println!("{} has already been taken", faction);
+                    }
+                }
+                ButtonActionType::ToggleReady => {
+                    let ready = lobby.seat(HOST).map_or(false, |seat| seat.ready);
+                    lobby.set_ready(HOST, !ready);
+                }
+                ButtonActionType::BotDifficulty => {
+                    settings.difficulty = settings.difficulty.next();
+                }
+                ButtonActionType::TimeBank => turn_timer.next_bank(),
+                ButtonActionType::OutOfTime => {
+                    turn_timer.out_of_time = turn_timer.out_of_time.next();
+                }
+                ButtonActionType::Rules => *rules = rules.next(),
+                ButtonActionType::Deck => presets.next(),
+                ButtonActionType::HouseRules => house_rules.next(),
+                ButtonActionType::PlayerCount => lobby.next_player_count(),
+                ButtonActionType::ToggleFaction(faction) => {
+                    if !lobby.toggle_excluded(faction) {
+                        println!("{} is held by a player and has to stay in", faction);
+                    }
+                }
+                _ => (),
+            },
+        }
+    }
+}
+
+fn lobby_option_label_system(
+    (tr, settings, turn_timer, rules, presets, house_rules): LobbyOptions,
+    lobby: Res<Lobby>,
+    mut option_texts: Query<(&mut Text, &LobbyOptionText)>,
+) {
+    for (mut text, option) in option_texts.iter_mut() {
+        let value = match option {
+            LobbyOptionText::BotDifficulty => tr.fmt(
+                "lobby.bots",
+                &[&tr.get(&format!("difficulty.{:?}", settings.difficulty))],
+            ),
+            LobbyOptionText::TimeBank => turn_timer.bank_label(&tr),
+            LobbyOptionText::OutOfTime => turn_timer.out_of_time_label(&tr),
+            LobbyOptionText::Rules => {
+                tr.fmt("lobby.rules", &[&tr.get(&format!("rules.{:?}", *rules))])
+            }
+            LobbyOptionText::Deck => presets.label(&tr),
+            LobbyOptionText::HouseRules => house_rules.label(&tr),
+            LobbyOptionText::PlayerCount => tr.fmt("lobby.players", &[&lobby.player_count]),
+            LobbyOptionText::Available(faction) => tr.fmt(
+                if lobby.excluded.contains(faction) {
+                    "lobby.faction_out"
+                } else {
+                    "lobby.faction_in"
+                },
+                &[&tr.faction(*faction)],
+            ),
+        };
+        if text.value != value {
+            text.value = value;
+        }
+    }
+}
+
+/// Sends every client the lobby along with the faction they currently hold
+fn lobby_system(
+    network: Res<Network>,
+    lobby: Res<Lobby>,
+    mut last: Local<(Vec<LobbySeat>, u32, Vec<Faction>)>,
+    mut server: Query<&mut Server>,
+) {
+    if network.network_type != NetworkType::Server
+        || (last.0 == lobby.seats && last.1 == lobby.player_count && last.2 == lobby.excluded)
+    {
+        return;
+    }
+    if let Some(mut server) = server.iter_mut().next() {
+        let addresses = server.clients.keys().cloned().collect::<Vec<_>>();
+        for address in addresses {
+            let faction = lobby
+                .seat(&address.to_string())
+                .and_then(|seat| seat.faction);
+            server.send_event(
+                address,
+                &ServerEvent::LobbyState {
+                    seats: lobby.seats.clone(),
+                    player_count: lobby.player_count,
+                    excluded: lobby.excluded.clone(),
+                    faction,
+                },
+            );
+        }
+        *last = (
+            lobby.seats.clone(),
+            lobby.player_count,
+            lobby.excluded.clone(),
+        );
+    }
 }
@@ -0,0 +1,670 @@
+use std::{collections::HashMap, mem, net::SocketAddr};
+
+use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rand::seq::SliceRandom;
+use rkyv::{Archive, Unarchive};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    components::{Player, Prediction, Secret, Unique},
+    data::{Faction, TraitorCard, TreacheryCard},
+    network::{
+        Client, ConnectionState, Network, NetworkRole, NetworkType, Server, Session, SERVER_PORT,
+    },
+    protocol::ServerEvent,
+    resources::{Data, GameRng, Info},
+    savegame::{CardKind, LoadedGame, ResyncRequests, SaveGame},
+    secret::SecretReveals,
+    Screen, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// Seconds between the host sending its backup a fresh copy of the game
+const REPLICATE_INTERVAL: f32 = 15.0;
+
+pub struct MigrationPlugin;
+
+impl Plugin for MigrationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<HostMigration>()
+            .add_system(migration_system.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                replicate_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                deal_proofs_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                reclaim_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system());
+    }
+}
+
+/// A client's seat, carried over so it can be reclaimed from whoever hosts next. Only a digest of
+/// the token goes along, so the backup can't take anyone else's seat.
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct ReplicaSession {
+    pub token_digest: Vec<u8>,
+    pub address: String,
+    pub faction: Option<Faction>,
+    pub spectator: bool,
+    pub caster: bool,
+    pub name: Option<String>,
+    pub rating: Option<u32>,
+}
+
+impl ReplicaSession {
+    pub fn new(token: u64, session: &Session) -> Self {
+        ReplicaSession {
+            token_digest: token_digest(token),
+            address: session.address.to_string(),
+            faction: session.faction,
+            spectator: session.role == NetworkRole::Spectator,
+            caster: session.caster,
+            name: session.name.clone(),
+            rating: session.rating,
+        }
+    }
+}
+
+/// A secret the host dealt out, like the card in a slot or the Bene Gesserit prediction, as held by
+/// whoever it was dealt to. Nobody else can make the proof, so it can be shown to a new host.
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct SecretClaim {
+    pub label: String,
+    pub value: String,
+    pub proof: Vec<u8>,
+}
+
+impl SecretClaim {
+    fn holds(&self, seals: &HashMap<String, Vec<u8>>) -> bool {
+        seals.get(&self.label) == Some(&seal_digest(&self.proof, &self.label, &self.value))
+    }
+}
+
+/// What the backup checks a claim against, which says nothing about the secret on its own
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct SecretSeal {
+    pub label: String,
+    pub digest: Vec<u8>,
+}
+
+const PREDICTED_FACTION: &str = "predicted faction";
+const PREDICTED_TURN: &str = "predicted turn";
+const SPICE: &str = "spice";
+/// Spice changes hands between replicas, so this many of a player's latest amounts are kept in case
+/// the backup was sent an older one
+pub const SPICE_CLAIMS: usize = 8;
+
+/// Unless spice is public, only its owner knows how much a faction has
+fn spice_label(faction: Faction) -> String {
+    format!("{} {}", SPICE, faction)
+}
+
+pub fn is_spice_label(label: &str) -> bool {
+    label.starts_with(SPICE)
+}
+
+pub fn card_label(kind: CardKind, slot: u32) -> String {
+    format!("{:?} {}", kind, slot)
+}
+
+fn parse_card_label(label: &str) -> Option<(CardKind, u32)> {
+    let (kind, slot) = label.split_at(label.find(' ')?);
+    let kind = match kind {
+        "Treachery" => CardKind::Treachery,
+        "Traitor" => CardKind::Traitor,
+        _ => return None,
+    };
+    Some((kind, slot.trim().parse().ok()?))
+}
+
+/// The secrets in a Bene Gesserit prediction, as far as it has been made
+fn prediction_secrets((faction, turn): (Option<Faction>, Option<i32>)) -> Vec<(String, String)> {
+    faction
+        .map(|faction| (PREDICTED_FACTION.to_string(), faction.to_string()))
+        .into_iter()
+        .chain(turn.map(|turn| (PREDICTED_TURN.to_string(), turn.to_string())))
+        .collect()
+}
+
+fn sha256(parts: &[&[u8]]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(&(part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher.finalize().to_vec()
+}
+
+fn seal_digest(proof: &[u8], label: &str, value: &str) -> Vec<u8> {
+    sha256(&[proof, label.as_bytes(), value.as_bytes()])
+}
+
+pub fn token_digest(token: u64) -> Vec<u8> {
+    sha256(&[&token.to_le_bytes()])
+}
+
+/// Who takes over the game if the host goes away.
+///
+/// The backup is only sent what it may see itself. Everyone else proves what they were dealt when
+/// they reconnect to it, and whatever nobody holds is dealt out again.
+pub struct HostMigration {
+    /// On the host, the client holding the replica. On a client, where the backup will host.
+    pub backup: Option<SocketAddr>,
+    /// Set on the client the host picked as its backup
+    pub chosen: bool,
+    pub password: String,
+    /// Set on the host when the backup is due a fresh replica
+    pub due: bool,
+    /// The latest copy of the game, if we are the backup
+    pub replica: Option<(Vec<u8>, Vec<ReplicaSession>, Vec<SecretSeal>)>,
+    /// Proofs handed out by this host are made with this, which never leaves it
+    seal_key: u64,
+    /// Once we have taken over, what the old host's proofs are checked against
+    pub seals: HashMap<String, Vec<u8>>,
+    /// Secrets players say the old host dealt them, waiting to be checked
+    pub claims: Vec<(SocketAddr, Faction, Vec<SecretClaim>)>,
+}
+
+impl Default for HostMigration {
+    fn default() -> Self {
+        HostMigration {
+            backup: None,
+            chosen: false,
+            password: String::new(),
+            due: false,
+            replica: None,
+            seal_key: rand::random(),
+            seals: HashMap::new(),
+            claims: Vec::new(),
+        }
+    }
+}
+
+impl HostMigration {
+    /// The proof for whoever `value` is dealt to
+    pub fn claim(&self, label: String, value: String) -> SecretClaim {
+        let proof = sha256(&[
+            &self.seal_key.to_le_bytes(),
+            label.as_bytes(),
+            value.as_bytes(),
+        ]);
+        SecretClaim {
+            label,
+            value,
+            proof,
+        }
+    }
+
+    /// What the backup needs to check the claim to `value` with
+    pub fn seal(&self, label: String, value: String) -> SecretSeal {
+        let claim = self.claim(label, value);
+        SecretSeal {
+            digest: seal_digest(&claim.proof, &claim.label, &claim.value),
+            label: claim.label,
+        }
+    }
+
+    /// Seals for every card in a slot, the prediction and every faction's spice, whoever holds them
+    pub fn seals_for(&self, save: &SaveGame) -> Vec<SecretSeal> {
+        let cards = save
+            .cards
+            .iter()
+            .filter_map(|card| Some((card_label(card.kind, card.slot?), card.key.clone())));
+        let prediction = save
+            .players
+            .iter()
+            .filter_map(|player| player.prediction)
+            .flat_map(prediction_secrets);
+        let spice = save
+            .players
+            .iter()
+            .map(|player| (spice_label(player.faction), player.spice.to_string()));
+        cards
+            .chain(prediction)
+            .chain(spice)
+            .map(|(label, value)| self.seal(label, value))
+            .collect()
+    }
+}
+
+/// Deals out the identities of the cards in a save that were hidden from whoever sent it. The ones
+/// that turn out to be held by somebody are put right once they prove it.
+pub fn deal_hidden(
+    save: &SaveGame,
+    data: &Data,
+    info: &Info,
+    rng: &mut GameRng,
+) -> Vec<(CardKind, u32, String)> {
+    let mut dealt = Vec::new();
+    for &kind in [CardKind::Treachery, CardKind::Traitor].iter() {
+        let all = match kind {
+            CardKind::Treachery => data
+                .treachery_cards
+                .iter()
+                .map(|card| card.id.to_string())
+                .collect::<Vec<_>>(),
+            _ => data
+                .leaders
+                .iter()
+                .filter(|leader| info.factions_in_play.contains(&leader.faction))
+                .map(|leader| leader.name.clone())
+                .collect(),
+        };
+        let cards = save.cards.iter().filter(|card| card.kind == kind);
+        let hidden = cards
+            .clone()
+            .filter(|card| card.key.is_empty())
+            .filter_map(|card| card.slot)
+            .collect::<Vec<_>>();
+        let mut spare = all
+            .into_iter()
+            .filter(|key| !cards.clone().any(|card| card.key == *key))
+            .collect::<Vec<_>>();
+        spare.shuffle(&mut rng.rng);
+        dealt.extend(
+            hidden
+                .into_iter()
+                .zip(spare)
+                .map(|(slot, key)| (kind, slot, key)),
+        );
+    }
+    dealt
+}
+
+/// Picks a backup among the seated clients and keeps it and everyone else up to date
+fn replicate_system(
+    time: Res<Time>,
+    mut migration: ResMut<HostMigration>,
+    mut timer: Local<f32>,
+    mut server: Query<&mut Server>,
+) {
+    *timer -= time.delta_seconds();
+    if *timer > 0.0 {
+        return;
+    }
+    *timer = REPLICATE_INTERVAL;
+    let mut server = if let Some(server) = server.iter_mut().next() {
+        server
+    } else {
+        return;
+    };
+    let backup = server
+        .clients
+        .values()
+        .filter(|connection| {
+            connection.state == ConnectionState::Healthy && !server.is_spectator(connection.address)
+        })
+        .map(|connection| connection.address)
+        .min();
+    if backup != migration.backup {
+        if let Some(address) = backup {
+            println!("{} is now the backup host", address);
+        }
+        migration.backup = backup;
+    }
+    if let Some(backup) = backup {
+        migration.due = true;
+        // Sent every time so clients that joined since hear about it too
+        let clients = server.clients.keys().copied().collect::<Vec<_>>();
+        for address in clients {
+            let event = ServerEvent::BackupHost {
+                address: SocketAddr::new(backup.ip(), SERVER_PORT).to_string(),
+                password: server.password.clone(),
+                chosen: address == backup,
+            };
+            server.send_event(address, &event);
+        }
+    }
+}
+
+/// Once the host is gone, the backup starts hosting the game from its replica and everyone else
+/// reconnects to it, reclaiming their seats with their session tokens
+fn migration_system(
+    commands: &mut Commands,
+    (mut network, mut migration, mut loaded): (
+        ResMut<Network>,
+        ResMut<HostMigration>,
+        ResMut<LoadedGame>,
+    ),
+    mut state: ResMut<State<Screen>>,
+    mut client: Query<(Entity, &mut Client)>,
+) {
+    if network.network_type != NetworkType::Client {
+        return;
+    }
+    match state.current() {
+        Screen::HostingGame | Screen::JoinedGame => (),
+        _ => return,
+    }
+    let (entity, mut client) = if let Some(client) = client.iter_mut().next() {
+        client
+    } else {
+        return;
+    };
+    let host_lost =
+        matches!(client.server, Some(server) if server.state != ConnectionState::Healthy);
+    if !host_lost {
+        return;
+    }
+    let backup = if let Some(backup) = migration.backup.take() {
+        backup
+    } else {
+        return;
+    };
+
+    if let Some((bytes, sessions, seals)) = migration.replica.take() {
+        println!("The host is gone, taking over the game");
+        let mut server = Server::new(SERVER_PORT);
+        server.password = migration.password.clone();
+        // Taking over mid-game, so the seats carried over are the ones the game started with
+        server.seats_fixed = true;
+        let own = client.session.map(token_digest);
+        for seat in sessions
+            .into_iter()
+            .filter(|seat| Some(&seat.token_digest) != own.as_ref())
+        {
+            if let Ok(address) = seat.address.parse() {
+                server.carried_over.insert(
+                    seat.token_digest,
+                    Session {
+                        address,
+                        faction: seat.faction,
+                        role: if seat.spectator {
+                            NetworkRole::Spectator
+                        } else {
+                            NetworkRole::Player
+                        },
+                        caster: seat.caster,
+                        name: seat.name,
+                        rating: seat.rating,
+                        // Nobody has compared their assets with this host's yet
+                        differing_assets: Vec::new(),
+                    },
+                );
+            }
+        }
+        migration.seals = seals
+            .into_iter()
+            .map(|seal| (seal.label, seal.digest))
+            .collect();
+        commands.despawn(entity);
+        commands.spawn((server,));
+        network.network_type = NetworkType::Server;
+        match SaveGame::from_bytes(&bytes) {
+            Ok(save) => loaded.save = Some(save),
+            Err(err) => {
+                println!("Failed to load the replica: {}", err);
+                loaded.failed = Some(err);
+            }
+        }
+        state.overwrite_next(Screen::Loading).unwrap();
+    } else if migration.chosen {
+        // Picked before the host had a settled game to send, so there is nothing to host from
+        println!("The host is gone before sending us the game");
+        commands.despawn(entity);
+        network.rejection = Some("The host left before it could hand the game over".to_string());
+        state.overwrite_next(Screen::Join).unwrap();
+    } else {
+        println!("The host is gone, reconnecting to {}", backup);
+        client.server = None;
+        let (name, password) = client.credentials.clone();
+        client.connect_to(backup, network.role, (&name, &password));
+    }
+}
+
+/// Gives the owner of every card dealt, of every spice reserve and the Bene Gesserit, once they
+/// have predicted, the proofs to show a new host
+fn deal_proofs_system(
+    network: Res<Network>,
+    migration: Res<HostMigration>,
+    mut server: Query<&mut Server>,
+    treachery_cards: Query<(&Secret<TreacheryCard>, &Unique), Changed<Unique>>,
+    traitor_cards: Query<(&Secret<TraitorCard>, &Unique), Changed<Unique>>,
+    predictions: Query<(&Player, &Prediction), Changed<Prediction>>,
+    players: Query<&Player, Changed<Player>>,
+) {
+    if network.network_type != NetworkType::Server {
+        return;
+    }
+    let mut server = if let Some(server) = server.iter_mut().next() {
+        server
+    } else {
+        return;
+    };
+    let cards = treachery_cards
+        .iter()
+        .filter_map(|(secret, unique)| {
+            let card = secret.value.as_ref()?;
+            Some((
+                unique,
+                card_label(CardKind::Treachery, secret.slot),
+                card.id.to_string(),
+            ))
+        })
+        .chain(traitor_cards.iter().filter_map(|(secret, unique)| {
+            let card = secret.value.as_ref()?;
+            Some((
+                unique,
+                card_label(CardKind::Traitor, secret.slot),
+                card.leader.name.clone(),
+            ))
+        }))
+        .filter(|(unique, _, _)| !unique.public)
+        .map(|(unique, label, value)| (unique.faction, label, value));
+    let predicted = predictions.iter().flat_map(|(player, prediction)| {
+        prediction_secrets((prediction.faction, prediction.turn))
+            .into_iter()
+            .map(move |(label, value)| (player.faction, label, value))
+    });
+    let spice = players.iter().map(|player| {
+        (
+            player.faction,
+            spice_label(player.faction),
+            player.spice.to_string(),
+        )
+    });
+    for (faction, label, value) in cards.chain(predicted).chain(spice).collect::<Vec<_>>() {
+        if let Some(address) = server.address_of(faction) {
+            server.send_event(
+                address,
+                &ServerEvent::SecretProof {
+                    claim: migration.claim(label, value),
+                },
+            );
+        }
+    }
+}
+
+/// Checks what reconnecting players say the old host dealt them, puts the cards they hold back in
+/// their hands and their spice back behind their shields, and catches them up
+fn reclaim_system(
+    commands: &mut Commands,
+    network: Res<Network>,
+    (mut migration, mut reveals, mut resync): (
+        ResMut<HostMigration>,
+        ResMut<SecretReveals>,
+        ResMut<ResyncRequests>,
+    ),
+    mut server: Query<&mut Server>,
+    (data, info): (Res<Data>, Res<Info>),
+    mut treachery_cards: Query<(Entity, &mut Secret<TreacheryCard>, Option<&Unique>)>,
+    mut traitor_cards: Query<(Entity, &mut Secret<TraitorCard>, Option<&Unique>)>,
+    mut players: Query<(&mut Player, Option<&mut Prediction>)>,
+) {
+    if network.network_type != NetworkType::Server || migration.claims.is_empty() {
+        return;
+    }
+    // Who holds each card in a slot, and what it is as far as we know
+    let mut cards = treachery_cards
+        .iter_mut()
+        .filter_map(|(_, secret, unique)| {
+            Some((
+                (CardKind::Treachery, secret.slot),
+                (
+                    unique.map(|unique| unique.faction),
+                    secret.value.as_ref()?.id.to_string(),
+                ),
+            ))
+        })
+        .chain(traitor_cards.iter_mut().filter_map(|(_, secret, unique)| {
+            Some((
+                (CardKind::Traitor, secret.slot),
+                (
+                    unique.map(|unique| unique.faction),
+                    secret.value.as_ref()?.leader.name.clone(),
+                ),
+            ))
+        }))
+        .collect::<HashMap<_, _>>();
+    let mut moved = Vec::new();
+    let mut claimants = Vec::new();
+    for (address, faction, claims) in mem::take(&mut migration.claims) {
+        claimants.push(faction);
+        for claim in claims {
+            if !claim.holds(&migration.seals) {
+                // Only one of the spice amounts kept can match, and the rest are stale
+                if !is_spice_label(&claim.label) {
+                    println!("{} can't prove {} to this host", faction, claim.label);
+                }
+                continue;
+            }
+            // The seal only vouches for the slot once, so nobody can claim it twice
+            migration.seals.remove(&claim.label);
+            if claim.label == spice_label(faction) {
+                for (mut player, _) in players.iter_mut() {
+                    if player.faction == faction {
+                        player.spice = claim.value.parse().unwrap_or(player.spice);
+                    }
+                }
+                continue;
+            }
+            if claim.label == PREDICTED_FACTION || claim.label == PREDICTED_TURN {
+                for (player, prediction) in players.iter_mut() {
+                    let mut prediction = match prediction {
+                        Some(prediction) if player.faction == faction => prediction,
+                        _ => continue,
+                    };
+                    if claim.label == PREDICTED_FACTION {
+                        prediction.faction = Faction::ALL
+                            .iter()
+                            .copied()
+                            .find(|predicted| predicted.to_string() == claim.value);
+                    } else {
+                        prediction.turn = claim.value.parse().ok();
+                    }
+                }
+                continue;
+            }
+            let slot = match parse_card_label(&claim.label) {
+                Some(slot) => slot,
+                None => continue,
+            };
+            match cards.get(&slot) {
+                Some((Some(owner), _)) if *owner == faction => (),
+                _ => {
+                    println!("{} no longer holds {}", faction, claim.label);
+                    continue;
+                }
+            }
+            // Whichever slot was dealt the claimed card in its place gets this one's instead
+            let other = cards
+                .iter()
+                .find(|(other, (_, key))| other.0 == slot.0 && *key == claim.value)
+                .map(|(&other, _)| other);
+            if let Some(other) = other.filter(|&other| other != slot) {
+                let displaced = cards[&slot].1.clone();
+                cards.get_mut(&other).unwrap().1 = displaced.clone();
+                moved.push((other.0, other.1, displaced));
+            }
+            cards.get_mut(&slot).unwrap().1 = claim.value.clone();
+            moved.push((slot.0, slot.1, claim.value));
+        }
+        resync.clients.push((address, Some(faction)));
+    }
+
+    // Settle the cards now, so the resync sends the right ones, and let the reveal catch up the
+    // card faces and components
+    for (kind, slot, key) in moved {
+        match kind {
+            CardKind::Treachery => {
+                let card = data
+                    .treachery_cards
+                    .iter()
+                    .find(|card| card.id.to_string() == key);
+                for (entity, mut secret, _) in treachery_cards.iter_mut() {
+                    if secret.slot == slot {
+                        secret.value = card.cloned();
+                        if let Some(card) = card {
+                            commands.insert_one(entity, card.clone());
+                        }
+                    }
+                }
+            }
+            _ => {
+                let card = data
+                    .leaders
+                    .iter()
+                    .filter(|leader| info.factions_in_play.contains(&leader.faction))
+                    .find(|leader| leader.name == key)
+                    .map(|leader| TraitorCard {
+                        leader: leader.clone(),
+                    });
+                for (entity, mut secret, _) in traitor_cards.iter_mut() {
+                    if secret.slot == slot {
+                        secret.value = card.clone();
+                        if let Some(ref card) = card {
+                            commands.insert_one(entity, card.clone());
+                        }
+                    }
+                }
+            }
+        }
+        reveals.pending.push((kind, slot, key));
+    }
+
+    // Hand out fresh proofs, since this host's are made differently
+    if let Some(mut server) = server.iter_mut().next() {
+        let proofs = cards
+            .into_iter()
+            .filter_map(|((kind, slot), (owner, key))| Some((owner?, card_label(kind, slot), key)))
+            .chain(players.iter_mut().flat_map(|(player, prediction)| {
+                let faction = player.faction;
+                let spice = (spice_label(faction), player.spice.to_string());
+                prediction
+                    .map(|prediction| prediction_secrets((prediction.faction, prediction.turn)))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .chain(std::iter::once(spice))
+                    .map(move |(label, value)| (faction, label, value))
+            }))
+            .filter(|(faction, _, _)| claimants.contains(faction))
+            .collect::<Vec<_>>();
+        for (faction, label, value) in proofs {
+            if let Some(address) = server.address_of(faction) {
+                server.send_event(
+                    address,
+                    &ServerEvent::SecretProof {
+                        claim: migration.claim(label, value),
+                    },
+                );
+            }
+        }
+    }
+}
+
+fn reset(mut migration: ResMut<HostMigration>) {
+    *migration = HostMigration::default();
+}
@@ -1,31 +1,107 @@
 use std::{
     collections::{HashMap, VecDeque},
-    io::Cursor,
-    net::SocketAddr,
-    time::Instant,
+    io::{Cursor, Read, Write as _},
+    net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
 };
 
 use bevy::prelude::*;
 use bytecheck::CheckBytes;
-use laminar::{Packet, Socket, SocketEvent};
+use instant::Instant;
 use rkyv::{check_archive, Archive, ArchiveWriter, Seek, Unarchive, Write};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    asset_pack::AssetFolder,
+    data::Faction,
+    migration::token_digest,
+    protocol::{ClientMessage, ClientRequest, ServerEvent},
+    settings::Settings,
+    transport::{self, Transport, TransportEvent},
+    Screen, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// Bumped whenever `Message`, `ClientRequest` or `ServerEvent` change shape, so mismatched builds
+/// refuse to talk
+pub const PROTOCOL_VERSION: u32 = 33;
+pub const GAME_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub const SERVER_PORT: u16 = 12345;
+pub const CLIENT_PORT: u16 = 12346;
+/// Hosts in the lobby broadcast here, and the join screen listens
+const DISCOVERY_PORT: u16 = 12347;
+/// Seconds between advertisements
+const ADVERTISE_INTERVAL: f32 = 1.0;
+/// Games that stop advertising for this long have gone away
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Seconds between re-registering with the lobby directory, which it takes as a heartbeat
+const REGISTER_INTERVAL: f32 = 15.0;
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+/// Seconds between the server pinging each client
+const PING_INTERVAL: f32 = 1.0;
+/// Longest name a player can go by
+pub const MAX_NAME_LENGTH: usize = 24;
+/// Batches smaller than this aren't worth compressing
+const COMPRESS_THRESHOLD: usize = 128;
+/// Longest message a peer may send. The biggest are resyncs and replicas, which carry a whole
+/// game.
+const MAX_MESSAGE_LEN: usize = 8 * 1024 * 1024;
+/// Most a batch may inflate to, so a small payload can't unpack into a huge one
+const MAX_BATCH_LEN: usize = 2 * MAX_MESSAGE_LEN;
 
 pub struct NetworkPlugin;
 
 impl Plugin for NetworkPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.init_resource::<Network>()
+            .init_resource::<Discovery>()
+            .init_resource::<LobbyDirectory>()
+            .init_resource::<Latencies>()
+            .add_system(latency_system.system())
             .add_system(server_system.system())
-            .add_system(client_system.system());
+            .add_system(client_system.system())
+            // Last, so everything queued during the frame goes out together
+            .add_system_to_stage(stage::LAST, flush_system.system())
+            .on_state_enter(RESPONSE_STAGE, Screen::HostingGame, start_play.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::Server,
+                advertise_system.system(),
+            )
+            .on_state_update(STATE_CHANGE_STAGE, Screen::Server, register_system.system())
+            .on_state_update(STATE_CHANGE_STAGE, Screen::Join, directory_system.system())
+            .on_state_enter(RESPONSE_STAGE, Screen::Join, start_discovery.system())
+            .on_state_update(STATE_CHANGE_STAGE, Screen::Join, discovery_system.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::Join, stop_discovery.system());
     }
 }
 
 #[derive(Archive, Unarchive, PartialEq, Clone, Debug)]
 #[archive(derive(CheckBytes))]
 pub enum Message {
-    Connect,
-    Ping,
+    /// Kept first with the version up front so that even a build with a different message layout
+    /// can read enough of it to say why it won't play along
+    Connect {
+        protocol: u32,
+        version: String,
+    },
+    /// The server turned the client away, and why
+    Reject(String),
+    /// Sent by the server with the time it went out, and echoed back in a `Pong`
+    Ping(u64),
+    Pong(u64),
+    Session(u64),
+    /// Sent by a client that only wants to watch, asking to see every hand if `caster`
+    Spectate {
+        caster: bool,
+    },
+    /// Whether the server lets a spectator that asked see every hand
+    Caster(bool),
     Data(Vec<u8>),
+    /// Every message sent on one channel during a frame, framed by `Batch::pack`
+    Batch(Vec<u8>),
 }
 
 impl Message {
@@ -37,41 +113,243 @@ impl Message {
         writer.into_inner().into_inner()
     }
 
-    fn from_bytes(bytes: &[u8]) -> Self {
-        let archived = check_archive::<Self>(bytes, 0).expect("Failed to validate message!");
-        archived.unarchive()
+    /// Anything that doesn't validate came from a build we can't understand
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        check_archive::<Self>(bytes, 0)
+            .ok()
+            .map(|archived| archived.unarchive())
+    }
+
+    fn connect() -> Self {
+        Message::Connect {
+            protocol: PROTOCOL_VERSION,
+            version: GAME_VERSION.to_string(),
+        }
+    }
+}
+
+/// Why the other side's build can't play with this one, if it can't
+fn incompatibility(protocol: u32, version: &str) -> Option<String> {
+    if protocol != PROTOCOL_VERSION || version != GAME_VERSION {
+        Some(format!(
+            "Version mismatch: this game is {} (protocol {}), the other is {} (protocol {})",
+            GAME_VERSION, PROTOCOL_VERSION, version, protocol
+        ))
+    } else {
+        None
     }
 }
 
 pub struct Network {
     pub network_type: NetworkType,
+    pub role: NetworkRole,
+    /// Why the last server we tried to join turned us away
+    pub rejection: Option<String>,
 }
 
 impl Default for Network {
     fn default() -> Self {
         Network {
             network_type: NetworkType::None,
+            role: NetworkRole::Player,
+            rejection: None,
+        }
+    }
+}
+
+/// How a message travels. Game actions must all arrive in order, but cosmetic updates like
+/// cursor positions are sent often enough that a lost or stale one is better skipped than resent.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Channel {
+    Reliable,
+    Unreliable,
+}
+
+/// Messages queued for one peer on one channel until the end of the frame
+#[derive(Default)]
+struct Outbox {
+    reliable: Vec<Vec<u8>>,
+    unreliable: Vec<Vec<u8>>,
+}
+
+impl Outbox {
+    fn queue(&mut self, channel: Channel, message: Vec<u8>) {
+        match channel {
+            Channel::Reliable => self.reliable.push(message),
+            Channel::Unreliable => self.unreliable.push(message),
+        }
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = (Channel, Vec<Vec<u8>>)> {
+        let reliable = std::mem::take(&mut self.reliable);
+        let unreliable = std::mem::take(&mut self.unreliable);
+        vec![
+            (Channel::Reliable, reliable),
+            (Channel::Unreliable, unreliable),
+        ]
+        .into_iter()
+        .filter(|(_, messages)| !messages.is_empty())
+    }
+}
+
+/// Several messages in one payload: a flag byte saying whether the rest is deflated, then each
+/// message as a little endian u32 length followed by that many bytes
+struct Batch;
+
+impl Batch {
+    const RAW: u8 = 0;
+    const DEFLATED: u8 = 1;
+
+    fn pack(messages: &[Vec<u8>]) -> Vec<u8> {
+        let mut framed = Vec::with_capacity(messages.iter().map(|m| m.len() + 4).sum());
+        for message in messages {
+            framed.extend_from_slice(&(message.len() as u32).to_le_bytes());
+            framed.extend_from_slice(message);
+        }
+        if framed.len() >= COMPRESS_THRESHOLD {
+            let deflated = miniz_oxide::deflate::compress_to_vec(&framed, 6);
+            if deflated.len() < framed.len() {
+                return std::iter::once(Batch::DEFLATED).chain(deflated).collect();
+            }
+        }
+        std::iter::once(Batch::RAW).chain(framed).collect()
+    }
+
+    /// The framed bytes of a batch, or nothing if it is corrupt or too big
+    fn unpack(payload: &[u8]) -> Option<Vec<u8>> {
+        match payload.split_first()? {
+            (&Batch::RAW, framed) if framed.len() <= MAX_BATCH_LEN => Some(framed.to_vec()),
+            (&Batch::DEFLATED, deflated) => {
+                miniz_oxide::inflate::decompress_to_vec_with_limit(deflated, MAX_BATCH_LEN).ok()
+            }
+            _ => None,
         }
     }
 }
 
+/// Splits framed bytes back into messages. A message cut short waits in the buffer for the rest
+/// of it to arrive, unless it claims to be longer than any message can be, which leaves nothing
+/// after it worth reading.
+#[derive(Default)]
+struct Deframer {
+    buffer: Vec<u8>,
+}
+
+impl Deframer {
+    fn push(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        self.buffer.extend_from_slice(bytes);
+        let mut messages = Vec::new();
+        let mut read = 0;
+        while let Some(header) = self.buffer.get(read..read + 4) {
+            let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+            if len > MAX_MESSAGE_LEN {
+                println!("Dropping a message of {} bytes", len);
+                self.buffer.clear();
+                return messages;
+            }
+            let body = if let Some(body) = self.buffer.get(read + 4..read + 4 + len) {
+                body
+            } else {
+                break;
+            };
+            messages.push(body.to_vec());
+            read += 4 + len;
+        }
+        self.buffer.drain(..read);
+        messages
+    }
+}
+
+/// The messages in a batch from a peer. Reliable batches arrive in order and share one buffer,
+/// while a lost unreliable one shouldn't leave half a message behind for the next.
+fn receive_batch(deframer: &mut Deframer, payload: &[u8], reliable: bool) -> Vec<Vec<u8>> {
+    let framed = if let Some(framed) = Batch::unpack(payload) {
+        framed
+    } else {
+        println!("Dropping a batch that doesn't unpack");
+        return Vec::new();
+    };
+    if reliable {
+        deframer.push(&framed)
+    } else {
+        Deframer::default().push(&framed)
+    }
+}
+
+/// Bytes of batches sent and of packets received since the socket was opened, for the
+/// performance overlay
+#[derive(Default, Copy, Clone)]
+pub struct Traffic {
+    pub sent: u64,
+    pub received: u64,
+}
+
 #[derive(PartialEq)]
 pub enum NetworkType {
     None,
     Client,
     Server,
+    /// Every faction takes turns at the same machine, with no sockets involved
+    Local,
+}
+
+/// Spectators follow the game without a seat, so they never hold a faction or send commands
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum NetworkRole {
+    Player,
+    Spectator,
 }
 
 pub struct Server {
-    pub socket: Socket,
+    pub socket: Box<dyn Transport>,
     pub clients: HashMap<SocketAddr, Connection>,
-    pub messages: VecDeque<Vec<u8>>,
+    pub sessions: HashMap<u64, Session>,
+    /// Seats from before the host changed, known only by a digest of their tokens until their
+    /// players come back for them
+    pub carried_over: HashMap<Vec<u8>, Session>,
+    messages: VecDeque<(SocketAddr, Vec<u8>)>,
+    /// Clients have to send this to get in. Empty means anyone can join.
+    pub password: String,
+    /// What the host goes by in the lobby
+    pub name: String,
+    /// The host's rating, if it shares one
+    pub rating: Option<u32>,
+    /// Pings are stamped with the time since this
+    started: Instant,
+    /// Spectators who ask can see every hand
+    pub allow_casters: bool,
+    /// How far spectators lag behind the game, which only starts once it does
+    pub spectator_delay: Duration,
+    /// Set once play starts, after which nobody changes which faction they answer for
+    pub seats_fixed: bool,
+    outboxes: HashMap<SocketAddr, Outbox>,
+    deframers: HashMap<SocketAddr, Deframer>,
+    /// Messages held back from spectators, with when they were sent
+    delayed: VecDeque<(Instant, SocketAddr, Channel, Vec<u8>)>,
+    pub traffic: Traffic,
+}
+
+#[derive(Clone)]
+pub struct Session {
+    pub address: SocketAddr,
+    pub faction: Option<Faction>,
+    pub role: NetworkRole,
+    /// A spectator the host lets see every hand
+    pub caster: bool,
+    /// Set once the client has given its name and the password, until then it can't do anything
+    pub name: Option<String>,
+    /// The rating the player shared when joining, if any
+    pub rating: Option<u32>,
+    /// The asset folders the player has that differ from the host's
+    pub differing_assets: Vec<String>,
 }
 
 #[derive(Copy, Clone)]
 pub struct Connection {
     pub address: SocketAddr,
     pub state: ConnectionState,
+    /// Round trip time of the last ping, in milliseconds
+    pub rtt: Option<u32>,
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -82,83 +360,416 @@ pub enum ConnectionState {
 }
 
 impl Server {
-    pub fn new(port: &str) -> Self {
-        let socket =
-            Socket::bind(format!("127.0.0.1:{}", port)).expect("Failed to bind server socket!");
+    pub fn new(port: u16) -> Self {
+        let socket = transport::host(port);
         Server {
             socket,
             clients: HashMap::new(),
+            sessions: HashMap::new(),
+            carried_over: HashMap::new(),
             messages: VecDeque::new(),
+            password: String::new(),
+            name: String::new(),
+            rating: None,
+            started: Instant::now(),
+            allow_casters: false,
+            spectator_delay: Duration::default(),
+            seats_fixed: false,
+            outboxes: HashMap::new(),
+            deframers: HashMap::new(),
+            delayed: VecDeque::new(),
+            traffic: Traffic::default(),
         }
     }
 
-    pub fn send_to_all(&mut self, message: Vec<u8>) {
-        for &address in self.clients.iter().filter_map(|(address, connection)| {
-            if connection.state == ConnectionState::Healthy {
-                Some(address)
-            } else {
-                None
-            }
-        }) {
-            println!(
-                "Sending {:?} to {}",
-                Message::Data(message.clone()),
-                address
-            );
-            self.socket
-                .send(Packet::reliable_ordered(
-                    address,
-                    Message::Data(message.clone()).into_bytes(),
-                    None,
-                ))
-                .expect("Failed to send connection message to server!");
+    pub fn bind_faction(&mut self, address: SocketAddr, faction: Option<Faction>) {
+        if self.seats_fixed {
+            println!("Seats are fixed, {} keeps its faction", address);
+            return;
+        }
+        if let Some(session) = self
+            .sessions
+            .values_mut()
+            .find(|session| session.address == address)
+        {
+            session.faction = faction;
+        }
+    }
+
+    /// Lets a connected client in if the name is free and the password is right. A client that is
+    /// turned away loses its session, so it starts over if it tries again.
+    pub fn join(
+        &mut self,
+        address: SocketAddr,
+        name: &str,
+        password: &str,
+        rating: Option<u32>,
+    ) -> Result<(), String> {
+        let name = name.trim();
+        let taken = name.eq_ignore_ascii_case(&self.name)
+            || self.sessions.values().any(|session| {
+                session.address != address
+                    && matches!(&session.name, Some(other) if other.eq_ignore_ascii_case(name))
+            });
+        let reason = if password != self.password {
+            Some("Wrong password".to_string())
+        } else if name.is_empty() {
+            Some("A name is needed to join".to_string())
+        } else if name.chars().count() > MAX_NAME_LENGTH {
+            Some(format!(
+                "Names can be at most {} letters long",
+                MAX_NAME_LENGTH
+            ))
+        } else if taken {
+            Some(format!("Somebody is already called {}", name))
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            println!("Turning {} away: {}", address, reason);
+            self.sessions
+                .retain(|_, session| session.address != address);
+            return Err(reason);
+        }
+        if let Some(session) = self
+            .sessions
+            .values_mut()
+            .find(|session| session.address == address)
+        {
+            session.name = Some(name.to_string());
+            session.rating = rating;
+        }
+        Ok(())
+    }
+
+    /// Remembers which of a joined player's asset folders differ from the host's
+    pub fn set_differing_assets(&mut self, address: SocketAddr, folders: Vec<String>) {
+        if let Some(session) = self
+            .sessions
+            .values_mut()
+            .find(|session| session.address == address)
+        {
+            session.differing_assets = folders;
+        }
+    }
+
+    pub fn differing_assets_of(&self, address: SocketAddr) -> &[String] {
+        self.sessions
+            .values()
+            .find(|session| session.address == address)
+            .map_or(&[][..], |session| &session.differing_assets[..])
+    }
+
+    pub fn is_joined(&self, address: SocketAddr) -> bool {
+        self.name_of(address).is_some()
+    }
+
+    pub fn name_of(&self, address: SocketAddr) -> Option<&str> {
+        self.sessions
+            .values()
+            .find(|session| session.address == address)
+            .and_then(|session| session.name.as_deref())
+    }
+
+    pub fn rating_of(&self, address: SocketAddr) -> Option<u32> {
+        self.sessions
+            .values()
+            .find(|session| session.address == address)
+            .and_then(|session| session.rating)
+    }
+
+    pub fn is_spectator(&self, address: SocketAddr) -> bool {
+        self.sessions
+            .values()
+            .any(|session| session.address == address && session.role == NetworkRole::Spectator)
+    }
+
+    /// Spectators who see every hand
+    pub fn casters(&self) -> Vec<SocketAddr> {
+        self.sessions
+            .values()
+            .filter(|session| session.role == NetworkRole::Spectator && session.caster)
+            .map(|session| session.address)
+            .collect()
+    }
+
+    pub fn address_of(&self, faction: Faction) -> Option<SocketAddr> {
+        self.sessions
+            .values()
+            .find(|session| session.faction == Some(faction))
+            .map(|session| session.address)
+    }
+
+    pub fn faction_of(&self, address: SocketAddr) -> Option<Faction> {
+        self.sessions
+            .values()
+            .find(|session| session.address == address)
+            .and_then(|session| session.faction)
+    }
+
+    /// Moves an existing session over to a new address, returning the faction it was bound to
+    pub fn rebind(&mut self, token: u64, address: SocketAddr) -> Option<Option<Faction>> {
+        if let Some(session) = self.carried_over.remove(&token_digest(token)) {
+            self.sessions.insert(token, session);
+        }
+        if self.sessions.contains_key(&token) {
+            self.sessions
+                .retain(|&other, session| other == token || session.address != address);
+            let session = self.sessions.get_mut(&token).unwrap();
+            session.address = address;
+            Some(session.faction)
+        } else {
+            None
         }
     }
 
-    pub fn send_to(&mut self, address: SocketAddr, message: Vec<u8>) {
+    /// Sends `event` to every client, on whichever channel suits it
+    pub fn broadcast(&mut self, event: &ServerEvent) {
+        self.send_to_all(event.channel(), event.into_bytes());
+    }
+
+    /// Sends `event` to one client alone. Only ever used for game state, so always reliable.
+    pub fn send_event(&mut self, address: SocketAddr, event: &ServerEvent) {
         if let Some(connection) = self.clients.get(&address) {
             if connection.state == ConnectionState::Healthy {
-                self.socket
-                    .send(Packet::reliable_ordered(
-                        address,
-                        Message::Data(message).into_bytes(),
-                        None,
-                    ))
-                    .expect("Failed to send connection message to server!");
+                self.queue(address, Channel::Reliable, event.into_bytes());
+            }
+        }
+    }
+
+    /// Everything clients have asked since last time, with who asked. Anything that doesn't
+    /// validate is dropped here.
+    pub fn requests(&mut self) -> Vec<(SocketAddr, ClientMessage)> {
+        self.messages
+            .drain(..)
+            .filter_map(|(address, data)| {
+                let message = ClientMessage::from_bytes(&data[..]);
+                if message.is_none() {
+                    println!("Ignoring a message from {} that doesn't validate", address);
+                }
+                message.map(|message| (address, message))
+            })
+            .collect()
+    }
+
+    fn send_to_all(&mut self, channel: Channel, message: Vec<u8>) {
+        let addresses = self
+            .clients
+            .values()
+            .filter(|connection| connection.state == ConnectionState::Healthy)
+            .map(|connection| connection.address)
+            .collect::<Vec<_>>();
+        for address in addresses {
+            if channel == Channel::Reliable {
+                println!("Sending {:?} to {}", message, address);
             }
+            self.queue(address, channel, message.clone());
+        }
+    }
+
+    /// Spectators get everything late once there is a delay, so a stream of the game is always
+    /// behind it
+    fn queue(&mut self, address: SocketAddr, channel: Channel, message: Vec<u8>) {
+        if self.spectator_delay > Duration::default() && self.is_spectator(address) {
+            self.delayed
+                .push_back((Instant::now(), address, channel, message));
+        } else {
+            self.outboxes
+                .entry(address)
+                .or_default()
+                .queue(channel, message);
+        }
+    }
+
+    fn flush(&mut self) {
+        while let Some(&(sent, address, channel, _)) = self.delayed.front() {
+            if sent.elapsed() < self.spectator_delay {
+                break;
+            }
+            let (_, _, _, message) = self.delayed.pop_front().unwrap();
+            self.outboxes
+                .entry(address)
+                .or_default()
+                .queue(channel, message);
+        }
+        let batches = self
+            .outboxes
+            .iter_mut()
+            .flat_map(|(&address, outbox)| {
+                outbox
+                    .drain()
+                    .map(move |(channel, messages)| (address, channel, messages))
+            })
+            .collect::<Vec<_>>();
+        for (address, channel, messages) in batches {
+            let payload = Message::Batch(Batch::pack(&messages)).into_bytes();
+            self.traffic.sent += payload.len() as u64;
+            self.socket
+                .send(address, channel, payload)
+                .expect("Failed to send message batch to client!");
         }
     }
 }
 
 pub struct Client {
-    pub socket: Socket,
+    pub socket: Box<dyn Transport>,
     pub server: Option<Connection>,
-    pub messages: VecDeque<Vec<u8>>,
+    pub session: Option<u64>,
+    pub faction: Option<Faction>,
+    /// Asks to see every hand when spectating, and then whether the server agreed
+    pub caster: bool,
+    pub reconnected: bool,
+    messages: VecDeque<Vec<u8>>,
+    /// Numbers the requests sent to the server
+    next_request: u32,
+    /// The name and password sent to the server once it answers
+    pub credentials: (String, String),
+    /// Sent along with the name, when the player shares it
+    pub rating: Option<u32>,
+    /// Sent along with the name, for the server to compare against its own
+    pub assets: Vec<AssetFolder>,
+    /// The asset folders the server says differ from its own
+    pub differing_assets: Vec<String>,
+    /// Set once the handshake has failed, by either side
+    pub rejection: Option<String>,
+    outbox: Outbox,
+    deframer: Deframer,
+    pub traffic: Traffic,
 }
 
 impl Client {
-    pub fn new(port: &str) -> Self {
-        let socket =
-            Socket::bind(format!("127.0.0.1:{}", port)).expect("Failed to bind client socket!");
+    pub fn new(port: u16) -> Self {
+        let socket = transport::client(port);
         Client {
             socket,
             server: None,
+            session: None,
+            faction: None,
+            caster: false,
+            reconnected: false,
             messages: VecDeque::new(),
+            next_request: 0,
+            credentials: (String::new(), String::new()),
+            rating: None,
+            assets: Vec::new(),
+            differing_assets: Vec::new(),
+            rejection: None,
+            outbox: Outbox::default(),
+            deframer: Deframer::default(),
+            traffic: Traffic::default(),
         }
     }
 
-    pub fn connect_to(&mut self, address: SocketAddr) {
+    pub fn connect_to(
+        &mut self,
+        address: SocketAddr,
+        role: NetworkRole,
+        (name, password): (&str, &str),
+    ) {
+        self.credentials = (name.to_string(), password.to_string());
         //self.server = Some(Connection {
         //    address,
         //    state: ConnectionState::Healthy,
         //});
         self.socket
-            .send(Packet::reliable_ordered(
-                address,
-                Message::Connect.into_bytes(),
-                None,
-            ))
+            .send(address, Channel::Reliable, Message::connect().into_bytes())
             .expect("Failed to send connection message to server!");
+        if role == NetworkRole::Spectator {
+            self.socket
+                .send(
+                    address,
+                    Channel::Reliable,
+                    Message::Spectate {
+                        caster: self.caster,
+                    }
+                    .into_bytes(),
+                )
+                .expect("Failed to send spectate message to server!");
+        }
+    }
+
+    /// Wraps `request` up with the next number
+    fn envelope(&mut self, request: ClientRequest) -> ClientMessage {
+        self.next_request = self.next_request.wrapping_add(1);
+        ClientMessage {
+            id: self.next_request,
+            request,
+        }
+    }
+
+    /// Sends `request` to the server on whichever channel suits it, and gives back the number it
+    /// went out with
+    pub fn request(&mut self, request: ClientRequest) -> u32 {
+        let channel = request.channel();
+        let message = self.envelope(request);
+        self.send(channel, message.into_bytes());
+        message.id
+    }
+
+    /// Everything the server has said since last time. Anything that doesn't validate is dropped
+    /// here.
+    pub fn events(&mut self) -> Vec<ServerEvent> {
+        self.messages
+            .drain(..)
+            .filter_map(|data| {
+                let event = ServerEvent::from_bytes(&data[..]);
+                if event.is_none() {
+                    println!("Ignoring a message from the server that doesn't validate");
+                }
+                event
+            })
+            .collect()
+    }
+
+    fn send(&mut self, channel: Channel, message: Vec<u8>) {
+        if let Some(server) = self.server {
+            if server.state == ConnectionState::Healthy {
+                self.outbox.queue(channel, message);
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        let address = if let Some(server) = self.server {
+            server.address
+        } else {
+            return;
+        };
+        let batches = self.outbox.drain().collect::<Vec<_>>();
+        for (channel, messages) in batches {
+            let payload = Message::Batch(Batch::pack(&messages)).into_bytes();
+            self.traffic.sent += payload.len() as u64;
+            self.socket
+                .send(address, channel, payload)
+                .expect("Failed to send message batch to server!");
+        }
+    }
+}
+
+fn start_play(settings: Res<Settings>, mut server: Query<&mut Server>) {
+    for mut server in server.iter_mut() {
+        server.seats_fixed = true;
+        server.spectator_delay = Duration::from_secs(60 * settings.online.spectator_delay as u64);
+    }
+}
+
+fn flush_system(
+    network: Res<Network>,
+    mut server: Query<&mut Server>,
+    mut client: Query<&mut Client>,
+) {
+    match network.network_type {
+        NetworkType::Server => {
+            for mut server in server.iter_mut() {
+                server.flush();
+            }
+        }
+        NetworkType::Client => {
+            for mut client in client.iter_mut() {
+                client.flush();
+            }
+        }
+        _ => (),
     }
 }
 
@@ -166,61 +777,172 @@ fn server_system(network: Res<Network>, mut server: Query<&mut Server>) {
     if network.network_type == NetworkType::Server {
         if let Some(mut server) = server.iter_mut().next() {
             //println!("Listening for client events");
-            server.socket.manual_poll(Instant::now());
-            match server.socket.recv() {
+            match server.socket.poll() {
                 Some(event) => match event {
-                    SocketEvent::Packet(packet) => {
+                    TransportEvent::Packet {
+                        address,
+                        channel,
+                        payload,
+                    } => {
                         //println!(
                         //    "Received packet {:?} from {}",
-                        //    Message::from_bytes(packet.payload()),
-                        //    packet.addr()
+                        //    Message::from_bytes(&payload),
+                        //    address
                         //);
-                        let message = Message::from_bytes(packet.payload());
+                        server.traffic.received += payload.len() as u64;
+                        let known = server
+                            .sessions
+                            .values()
+                            .any(|session| session.address == address);
+                        let message = match Message::from_bytes(&payload) {
+                            Some(message) => message,
+                            None => {
+                                if !known {
+                                    server
+                                        .socket
+                                        .send(
+                                            address,
+                                            Channel::Reliable,
+                                            Message::Reject(
+                                                "Incompatible game version".to_string(),
+                                            )
+                                            .into_bytes(),
+                                        )
+                                        .expect("Failed to send rejection message to client!");
+                                }
+                                return;
+                            }
+                        };
                         match message {
-                            Message::Connect => {
+                            Message::Connect { protocol, version } => {
+                                if let Some(reason) = incompatibility(protocol, &version) {
+                                    println!("Rejecting {}: {}", address, reason);
+                                    server
+                                        .socket
+                                        .send(
+                                            address,
+                                            Channel::Reliable,
+                                            Message::Reject(reason).into_bytes(),
+                                        )
+                                        .expect("Failed to send rejection message to client!");
+                                    return;
+                                }
                                 server
                                     .socket
-                                    .send(Packet::reliable_ordered(
-                                        packet.addr(),
-                                        Message::Connect.into_bytes(),
-                                        None,
-                                    ))
+                                    .send(
+                                        address,
+                                        Channel::Reliable,
+                                        Message::connect().into_bytes(),
+                                    )
                                     .expect(
                                         "Failed to send connection response message to client!",
                                     );
+                                if !server
+                                    .sessions
+                                    .values()
+                                    .any(|session| session.address == address)
+                                {
+                                    let token = rand::random::<u64>();
+                                    server.sessions.insert(
+                                        token,
+                                        Session {
+                                            address,
+                                            faction: None,
+                                            role: NetworkRole::Player,
+                                            caster: false,
+                                            name: None,
+                                            rating: None,
+                                            differing_assets: Vec::new(),
+                                        },
+                                    );
+                                    server
+                                        .socket
+                                        .send(
+                                            address,
+                                            Channel::Reliable,
+                                            Message::Session(token).into_bytes(),
+                                        )
+                                        .expect("Failed to send session message to client!");
+                                }
+                            }
+                            Message::Pong(sent) => {
+                                let now = server.started.elapsed().as_millis() as u64;
+                                if let Some(client) = server.clients.get_mut(&address) {
+                                    client.rtt = Some(now.saturating_sub(sent) as u32);
+                                }
                             }
-                            Message::Ping => {
+                            // A seat comes with a hand, so whoever holds one can't go on to see
+                            // everyone else's
+                            Message::Spectate { .. }
+                                if server.sessions.values().any(|session| {
+                                    session.address == address && session.faction.is_some()
+                                }) =>
+                            {
+                                println!("{} holds a seat, so can't spectate", address);
+                            }
+                            Message::Spectate { caster } => {
+                                let caster = caster && server.allow_casters;
+                                if let Some(session) = server
+                                    .sessions
+                                    .values_mut()
+                                    .find(|session| session.address == address)
+                                {
+                                    session.role = NetworkRole::Spectator;
+                                    session.caster = caster;
+                                    println!("{} is spectating", address);
+                                }
                                 server
                                     .socket
-                                    .send(Packet::reliable_ordered(
-                                        packet.addr(),
-                                        Message::Ping.into_bytes(),
-                                        None,
-                                    ))
-                                    .expect("Failed to send ping response message to client!");
+                                    .send(
+                                        address,
+                                        Channel::Reliable,
+                                        Message::Caster(caster).into_bytes(),
+                                    )
+                                    .expect("Failed to send caster message to client!");
+                            }
+                            // Only clients that made it through the handshake get a say in the game
+                            Message::Data(data) if known => {
+                                if channel == Channel::Reliable {
+                                    println!("Received data {:?} from {}", data, address);
+                                }
+                                server.messages.push_back((address, data));
                             }
-                            Message::Data(data) => {
-                                println!("Received data {:?} from {}", data, packet.addr());
-                                server.messages.push_back(data);
+                            Message::Batch(payload) if known => {
+                                let reliable = channel == Channel::Reliable;
+                                let server = &mut *server;
+                                let deframer = server.deframers.entry(address).or_default();
+                                for data in receive_batch(deframer, &payload, reliable) {
+                                    if reliable {
+                                        println!("Received data {:?} from {}", data, address);
+                                    }
+                                    server.messages.push_back((address, data));
+                                }
                             }
+                            _ => (),
                         }
                     }
-                    SocketEvent::Connect(address) => {
+                    TransportEvent::Connect(address) => {
                         // a client connected
-                        server.clients.entry(address).or_insert_with(|| Connection {
-                            address,
-                            state: ConnectionState::Healthy,
-                        });
+                        server.deframers.remove(&address);
+                        server
+                            .clients
+                            .entry(address)
+                            .and_modify(|client| client.state = ConnectionState::Healthy)
+                            .or_insert_with(|| Connection {
+                                address,
+                                state: ConnectionState::Healthy,
+                                rtt: None,
+                            });
                         println!("Client {} connected!", address);
                     }
-                    SocketEvent::Timeout(address) => {
+                    TransportEvent::Timeout(address) => {
                         // a client timed out
                         if let Some(client) = server.clients.get_mut(&address) {
                             client.state = ConnectionState::TimedOut;
                         }
                         println!("Client {} timed out!", address);
                     }
-                    SocketEvent::Disconnect(address) => {
+                    TransportEvent::Disconnect(address) => {
                         // a client disconnected
                         if let Some(client) = server.clients.get_mut(&address) {
                             client.state = ConnectionState::Disconnected;
@@ -238,60 +960,573 @@ fn client_system(network: Res<Network>, mut client: Query<&mut Client>) {
     if network.network_type == NetworkType::Client {
         if let Some(mut client) = client.iter_mut().next() {
             //println!("Listening for server events");
-            client.socket.manual_poll(Instant::now());
-            match client.socket.recv() {
-                Some(event) => match event {
-                    SocketEvent::Packet(packet) => {
-                        // the server sent a packet
-                        //println!(
-                        //    "Received packet {:?} from {}",
-                        //    Message::from_bytes(packet.payload()),
-                        //    packet.addr()
-                        //);
-                        let message = Message::from_bytes(packet.payload());
-                        match message {
-                            Message::Data(data) => {
-                                println!("Received data {:?} from {}", data, packet.addr());
-                                client.messages.push_back(data);
+            match client.socket.poll() {
+                Some(event) => {
+                    match event {
+                        TransportEvent::Packet {
+                            address,
+                            channel,
+                            payload,
+                        } => {
+                            // the server sent a packet
+                            //println!(
+                            //    "Received packet {:?} from {}",
+                            //    Message::from_bytes(&payload),
+                            //    address
+                            //);
+                            client.traffic.received += payload.len() as u64;
+                            let message = match Message::from_bytes(&payload) {
+                                Some(message) => message,
+                                None => {
+                                    client.rejection = Some(
+                                        "The server is running an incompatible version".to_string(),
+                                    );
+                                    return;
+                                }
+                            };
+                            match message {
+                                Message::Connect { protocol, version } => {
+                                    client.rejection = incompatibility(protocol, &version);
+                                    // A client coming back already has a seat to reclaim
+                                    if client.rejection.is_none() && client.session.is_none() {
+                                        let (name, password) = client.credentials.clone();
+                                        let rating = client.rating;
+                                        let assets = client.assets.clone();
+                                        let join = client.envelope(ClientRequest::JoinRequest {
+                                            name,
+                                            password,
+                                            rating,
+                                            assets,
+                                        });
+                                        client
+                                            .socket
+                                            .send(
+                                                address,
+                                                Channel::Reliable,
+                                                Message::Data(join.into_bytes()).into_bytes(),
+                                            )
+                                            .expect("Failed to send join request to server!");
+                                    }
+                                }
+                                Message::Reject(reason) => {
+                                    println!("Server rejected us: {}", reason);
+                                    client.rejection = Some(reason);
+                                }
+                                Message::Data(data) => {
+                                    if channel == Channel::Reliable {
+                                        println!("Received data {:?} from {}", data, address);
+                                    }
+                                    client.messages.push_back(data);
+                                }
+                                Message::Batch(payload) => {
+                                    let reliable = channel == Channel::Reliable;
+                                    let client = &mut *client;
+                                    for data in
+                                        receive_batch(&mut client.deframer, &payload, reliable)
+                                    {
+                                        if reliable {
+                                            println!("Received data {:?} from {}", data, address);
+                                        }
+                                        client.messages.push_back(data);
+                                    }
+                                }
+                                Message::Ping(sent) => {
+                                    client
+                                        .socket
+                                        .send(
+                                            address,
+                                            Channel::Unreliable,
+                                            Message::Pong(sent).into_bytes(),
+                                        )
+                                        .expect("Failed to send ping response message to server!");
+                                }
+                                Message::Caster(caster) => {
+                                    client.caster = caster;
+                                }
+                                // Keep the original token so a reconnect can reclaim its seat
+                                Message::Session(token) if client.session.is_none() => {
+                                    client.session = Some(token);
+                                }
+                                _ => (),
                             }
-                            _ => (),
                         }
-                    }
-                    SocketEvent::Connect(address) => {
-                        // the server connected
-                        client.server = Some(Connection {
-                            address,
-                            state: ConnectionState::Healthy,
-                        });
-                        println!("Server {} connected!", address);
-                    }
-                    SocketEvent::Timeout(address) => {
-                        // the server timed out
-                        if let Some(ref mut server) = client.server {
-                            server.state = ConnectionState::TimedOut;
+                        TransportEvent::Connect(address) => {
+                            // the server connected
+                            client.reconnected = client.session.is_some();
+                            client.deframer = Deframer::default();
+                            client.server = Some(Connection {
+                                address,
+                                state: ConnectionState::Healthy,
+                                rtt: None,
+                            });
+                            println!("Server {} connected!", address);
                         }
-                        println!("Server {} timed out!", address);
-                    }
-                    SocketEvent::Disconnect(address) => {
-                        // the server disconnected
-                        if let Some(ref mut server) = client.server {
-                            server.state = ConnectionState::Disconnected;
+                        TransportEvent::Timeout(address) => {
+                            // the server timed out
+                            if let Some(ref mut server) = client.server {
+                                server.state = ConnectionState::TimedOut;
+                            }
+                            println!("Server {} timed out!", address);
+                        }
+                        TransportEvent::Disconnect(address) => {
+                            // the server disconnected
+                            if let Some(ref mut server) = client.server {
+                                server.state = ConnectionState::Disconnected;
+                            }
+                            println!("Server {} disconnected!", address);
                         }
-                        println!("Server {} disconnected!", address);
                     }
-                },
+                }
                 None => (),
             }
-            if let Some(server) = client.server {
-                client
-                    .socket
-                    .send(Packet::reliable_ordered(
-                        server.address,
-                        Message::Ping.into_bytes(),
-                        None,
-                    ))
-                    .expect("Failed to send ping response message to server!");
+        }
+    }
+}
+
+/// What a hosted game tells the local network about itself
+#[derive(Archive, Unarchive, PartialEq, Clone, Debug)]
+#[archive(derive(CheckBytes))]
+struct Advertisement {
+    protocol: u32,
+    version: String,
+    name: String,
+    players: u32,
+    max_players: u32,
+    port: u16,
+}
+
+impl Advertisement {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new()));
+        writer
+            .archive_root(self)
+            .expect("Failed to serialize advertisement!");
+        writer.into_inner().into_inner()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        check_archive::<Self>(bytes, 0)
+            .ok()
+            .map(|archived| archived.unarchive())
+    }
+}
+
+pub struct DiscoveredGame {
+    pub address: SocketAddr,
+    pub name: String,
+    pub players: u32,
+    pub max_players: u32,
+    /// Why we couldn't join it, if we couldn't
+    pub incompatible: Option<String>,
+    last_seen: Instant,
+}
+
+/// Games being hosted on the local network, as heard on the join screen
+#[derive(Default)]
+pub struct Discovery {
+    socket: Option<UdpSocket>,
+    pub games: Vec<DiscoveredGame>,
+    /// Set whenever the list changes, so the join screen knows to redraw it
+    pub changed: bool,
+}
+
+impl Discovery {
+    /// Forgets every game heard so far, leaving only the ones still advertising
+    pub fn refresh(&mut self) {
+        self.games.clear();
+        self.changed = true;
+    }
+}
+
+fn game_name(settings: &Settings) -> String {
+    if !settings.online.game_name.is_empty() {
+        return settings.online.game_name.clone();
+    }
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .map(|user| format!("{}'s game", user))
+        .unwrap_or_else(|_| "Dune".to_string())
+}
+
+/// What we go by in other people's lobbies
+pub fn player_name(settings: &Settings) -> String {
+    if !settings.online.player_name.trim().is_empty() {
+        return settings.online.player_name.trim().to_string();
+    }
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "Player".to_string())
+}
+
+fn seated_players(server: &Server) -> u32 {
+    server
+        .clients
+        .iter()
+        .filter(|&(&address, connection)| {
+            connection.state == ConnectionState::Healthy
+                && server.is_joined(address)
+                && !server.is_spectator(address)
+        })
+        .count() as u32
+        // The host has a seat too
+        + 1
+}
+
+/// Broadcasts the lobby to the local network while it is waiting for players
+fn advertise_system(
+    (time, settings): (Res<Time>, Res<Settings>),
+    network: Res<Network>,
+    mut advertiser: Local<Option<UdpSocket>>,
+    mut timer: Local<f32>,
+    server: Query<&Server>,
+) {
+    if network.network_type != NetworkType::Server {
+        return;
+    }
+    *timer -= time.delta_seconds();
+    if *timer > 0.0 {
+        return;
+    }
+    *timer = ADVERTISE_INTERVAL;
+    if advertiser.is_none() {
+        *advertiser = UdpSocket::bind(("0.0.0.0", 0))
+            .and_then(|socket| socket.set_broadcast(true).map(|_| socket))
+            .map_err(|err| println!("Not advertising on the local network: {}", err))
+            .ok();
+    }
+    if let (Some(socket), Some(server)) = (advertiser.as_ref(), server.iter().next()) {
+        let advertisement = Advertisement {
+            protocol: PROTOCOL_VERSION,
+            version: GAME_VERSION.to_string(),
+            name: game_name(&settings),
+            players: seated_players(server),
+            max_players: Faction::ALL.len() as u32,
+            port: SERVER_PORT,
+        };
+        if let Err(err) = socket.send_to(
+            &advertisement.to_bytes(),
+            ("255.255.255.255", DISCOVERY_PORT),
+        ) {
+            println!("Failed to advertise game: {}", err);
+        }
+    }
+}
+
+fn start_discovery(mut discovery: ResMut<Discovery>) {
+    discovery.refresh();
+    discovery.socket = UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT))
+        .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+        .map_err(|err| println!("Can't look for games on the local network: {}", err))
+        .ok();
+}
+
+fn stop_discovery(mut discovery: ResMut<Discovery>) {
+    discovery.socket = None;
+}
+
+/// Collects advertisements and drops games that have stopped sending them
+fn discovery_system(mut discovery: ResMut<Discovery>) {
+    let discovery = &mut *discovery;
+    let mut heard = Vec::new();
+    if let Some(socket) = discovery.socket.as_ref() {
+        let mut buffer = [0; 1024];
+        while let Ok((len, from)) = socket.recv_from(&mut buffer) {
+            if let Some(advertisement) = Advertisement::from_bytes(&buffer[..len]) {
+                heard.push((
+                    SocketAddr::new(from.ip(), advertisement.port),
+                    advertisement,
+                ));
+            }
+        }
+    }
+    let now = Instant::now();
+    for (address, advertisement) in heard {
+        let incompatibility = incompatibility(advertisement.protocol, &advertisement.version);
+        if let Some(game) = discovery
+            .games
+            .iter_mut()
+            .find(|game| game.address == address)
+        {
+            game.last_seen = now;
+            if game.players != advertisement.players {
+                game.players = advertisement.players;
+                discovery.changed = true;
             }
+        } else {
+            discovery.games.push(DiscoveredGame {
+                address,
+                name: advertisement.name,
+                players: advertisement.players,
+                max_players: advertisement.max_players,
+                incompatible: incompatibility,
+                last_seen: now,
+            });
+            discovery.changed = true;
         }
     }
+    let count = discovery.games.len();
+    discovery
+        .games
+        .retain(|game| now.duration_since(game.last_seen) < DISCOVERY_TIMEOUT);
+    if discovery.games.len() != count {
+        discovery.changed = true;
+    }
+}
+
+/// What a host tells the lobby directory. The directory fills in the address from the request.
+#[derive(Serialize)]
+struct Registration {
+    name: String,
+    port: u16,
+    password_protected: bool,
+    players: u32,
+    max_players: u32,
+    version: String,
+}
+
+/// A game as the lobby directory lists it
+#[derive(Clone, Deserialize)]
+pub struct ListedGame {
+    pub address: String,
+    pub name: String,
+    pub password_protected: bool,
+    pub players: u32,
+    pub max_players: u32,
+    pub version: String,
+}
+
+/// Filled in by a background thread once its request is done
+type Pending<T> = Arc<Mutex<Option<Result<T, String>>>>;
+
+/// Just enough HTTP for the lobby directory, kept off the main thread by the callers.
+/// Only plain `http://` is understood, so a directory behind TLS needs a proxy in front of it.
+fn http_request(method: &str, url: &str, body: Option<&str>) -> Result<String, String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("Only http:// lobby directories are supported, not {}", url))?;
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let address = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    }
+    .to_socket_addrs()
+    .map_err(|err| format!("Can't find {}: {}", host, err))?
+    .next()
+    .ok_or_else(|| format!("Can't find {}", host))?;
+    let mut stream = TcpStream::connect_timeout(&address, HTTP_TIMEOUT)
+        .map_err(|err| format!("Can't reach {}: {}", host, err))?;
+    stream
+        .set_read_timeout(Some(HTTP_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(HTTP_TIMEOUT)))
+        .map_err(|err| err.to_string())?;
+    let body = body.unwrap_or("");
+    // HTTP/1.0 so the reply is never chunked and simply ends when the connection closes
+    write!(
+        stream,
+        "{} {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        host,
+        body.len(),
+        body
+    )
+    .map_err(|err| format!("Failed to send to {}: {}", host, err))?;
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .map_err(|err| format!("Failed to read from {}: {}", host, err))?;
+    let (head, content) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| format!("Malformed reply from {}", host))?;
+    let status = head.split_whitespace().nth(1).unwrap_or("");
+    if status.starts_with('2') {
+        Ok(content.to_string())
+    } else {
+        Err(format!(
+            "{} replied {}",
+            host,
+            head.lines().next().unwrap_or("")
+        ))
+    }
+}
+
+/// Public games, as listed by the lobby directory from the settings
+#[derive(Default)]
+pub struct LobbyDirectory {
+    pub games: Vec<ListedGame>,
+    pub error: Option<String>,
+    /// Leaves password-protected games out of the list
+    pub hide_protected: bool,
+    /// Set whenever the list changes, so the join screen knows to redraw it
+    pub changed: bool,
+    browsing: Option<Pending<Vec<ListedGame>>>,
+}
+
+impl LobbyDirectory {
+    /// Asks the directory for its games. They show up once the reply comes back.
+    pub fn browse(&mut self, directory: &str) {
+        self.changed = true;
+        if directory.is_empty() {
+            self.error = Some("No lobby directory set in the settings file".to_string());
+            return;
+        }
+        // Browsers can only reach the host that served them
+        if cfg!(target_arch = "wasm32") {
+            self.error = Some("The lobby directory can't be browsed from a browser".to_string());
+            return;
+        }
+        self.error = None;
+        let url = format!("{}/games", directory.trim_end_matches('/'));
+        let pending = Pending::default();
+        let result = pending.clone();
+        thread::spawn(move || {
+            let games = http_request("GET", &url, None)
+                .and_then(|body| serde_json::from_str(&body).map_err(|err| err.to_string()));
+            *result.lock().unwrap() = Some(games);
+        });
+        self.browsing = Some(pending);
+    }
+
+    pub fn visible(&self) -> impl Iterator<Item = &ListedGame> {
+        self.games
+            .iter()
+            .filter(move |game| !(self.hide_protected && game.password_protected))
+    }
+}
+
+fn directory_system(mut directory: ResMut<LobbyDirectory>) {
+    let result = directory
+        .browsing
+        .as_ref()
+        .and_then(|pending| pending.lock().unwrap().take());
+    if let Some(result) = result {
+        directory.browsing = None;
+        match result {
+            Ok(games) => directory.games = games,
+            Err(err) => {
+                println!("Failed to browse lobby directory: {}", err);
+                directory.error = Some(err);
+            }
+        }
+        directory.changed = true;
+    }
+}
+
+/// Keeps a hosted lobby listed with the directory, if one is configured
+fn register_system(
+    (time, settings): (Res<Time>, Res<Settings>),
+    network: Res<Network>,
+    mut timer: Local<f32>,
+    server: Query<&Server>,
+) {
+    if network.network_type != NetworkType::Server || settings.online.directory.is_empty() {
+        return;
+    }
+    *timer -= time.delta_seconds();
+    if *timer > 0.0 {
+        return;
+    }
+    *timer = REGISTER_INTERVAL;
+    if let Some(server) = server.iter().next() {
+        let registration = Registration {
+            name: game_name(&settings),
+            port: SERVER_PORT,
+            password_protected: !server.password.is_empty(),
+            players: seated_players(server),
+            max_players: Faction::ALL.len() as u32,
+            version: GAME_VERSION.to_string(),
+        };
+        let body = serde_json::to_string(&registration).expect("Failed to serialize registration!");
+        let url = format!("{}/games", settings.online.directory.trim_end_matches('/'));
+        thread::spawn(move || {
+            if let Err(err) = http_request("POST", &url, Some(&body)) {
+                println!("Failed to register with lobby directory: {}", err);
+            }
+        });
+    }
+}
+
+/// How a player's connection to the server is doing, as last reported by the server
+#[derive(Archive, Unarchive, PartialEq, Copy, Clone, Debug)]
+#[archive(derive(CheckBytes))]
+pub struct Latency {
+    pub faction: Faction,
+    /// Round trip time in milliseconds, once the first ping has come back
+    pub millis: Option<u32>,
+    pub connected: bool,
+}
+
+/// Every seated player's connection, kept up to date by the server and shared with clients
+#[derive(Default)]
+pub struct Latencies {
+    pub players: HashMap<Faction, Latency>,
+}
+
+impl Latencies {
+    pub fn connected(&self, faction: Faction) -> bool {
+        self.players
+            .get(&faction)
+            .map_or(true, |latency| latency.connected)
+    }
+}
+
+/// Pings every client and tells everyone how each seated player's connection is doing
+fn latency_system(
+    (time, network): (Res<Time>, Res<Network>),
+    mut latencies: ResMut<Latencies>,
+    mut timer: Local<f32>,
+    mut server: Query<&mut Server>,
+) {
+    if network.network_type != NetworkType::Server {
+        return;
+    }
+    *timer -= time.delta_seconds();
+    if *timer > 0.0 {
+        return;
+    }
+    *timer = PING_INTERVAL;
+    if let Some(mut server) = server.iter_mut().next() {
+        let now = server.started.elapsed().as_millis() as u64;
+        let healthy = server
+            .clients
+            .values()
+            .filter(|connection| connection.state == ConnectionState::Healthy)
+            .map(|connection| connection.address)
+            .collect::<Vec<_>>();
+        for address in healthy {
+            server
+                .socket
+                .send(
+                    address,
+                    Channel::Unreliable,
+                    Message::Ping(now).into_bytes(),
+                )
+                .expect("Failed to send ping message to client!");
+        }
+        latencies.players = server
+            .sessions
+            .values()
+            .filter_map(|session| {
+                let faction = session.faction?;
+                let connection = server.clients.get(&session.address);
+                Some((
+                    faction,
+                    Latency {
+                        faction,
+                        millis: connection.and_then(|connection| connection.rtt),
+                        connected: connection.map_or(false, |connection| {
+                            connection.state == ConnectionState::Healthy
+                        }),
+                    },
+                ))
+            })
+            .collect();
+        server.broadcast(&ServerEvent::Latency {
+            players: latencies.players.values().copied().collect(),
+        });
+    }
 }
@@ -0,0 +1,366 @@
+use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
+
+use crate::{
+    data::Faction,
+    dune_core::LAST_TURN,
+    hud::HudMaterials,
+    layout::{Anchored, AnchoredHeight},
+    locale::Tr,
+    menu::Chat,
+    network::{Network, NetworkType, Server},
+    phase::{BattleResolved, BidPassed, CardBought, TurnStarted},
+    protocol::ServerEvent,
+    resources::{Data, Info},
+    settings::{Binding, Settings},
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// Seconds a toast stays on screen
+const TOAST_TIME: f32 = 5.0;
+/// The most toasts shown at once, the oldest making way for new ones
+const MAX_TOASTS: usize = 4;
+/// Lines of the event log shown at a time
+const LOG_LINES: usize = 16;
+
+pub struct NotificationPlugin;
+
+impl Plugin for NotificationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Notifications>()
+            .on_state_enter(
+                RESPONSE_STAGE,
+                Screen::HostingGame,
+                init_notifications.system(),
+            )
+            .on_state_enter(
+                RESPONSE_STAGE,
+                Screen::JoinedGame,
+                init_notifications.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                notice_feed_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                event_log_input_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                event_log_input_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                toast_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                toast_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                event_log_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                event_log_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system());
+    }
+}
+
+/// Something that happened in the game worth telling everyone about. Only the host runs the
+/// phases, so it works these out and hands them to its clients.
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum Notice {
+    TurnStarted { turn: i32 },
+    CardBought { faction: Faction },
+    BidPassed { faction: Faction },
+    BattleWon { faction: Faction, location: String },
+    Eliminated { faction: Faction },
+    LeaderCaptured { faction: Faction, owner: Faction },
+}
+
+impl Notice {
+    pub fn text(&self, tr: &Tr) -> String {
+        match self {
+            Notice::TurnStarted { turn } => {
+                tr.fmt("notice.turn_started", &[&(turn + 1), &LAST_TURN])
+            }
+            Notice::CardBought { faction } => {
+                tr.fmt("notice.card_bought", &[&tr.faction(*faction)])
+            }
+            Notice::BidPassed { faction } => tr.fmt("notice.bid_passed", &[&tr.faction(*faction)]),
+            Notice::BattleWon { faction, location } => {
+                tr.fmt("notice.battle_won", &[&tr.faction(*faction), location])
+            }
+            Notice::Eliminated { faction } => tr.fmt("notice.eliminated", &[&tr.faction(*faction)]),
+            Notice::LeaderCaptured { faction, owner } => tr.fmt(
+                "notice.leader_captured",
+                &[&tr.faction(*faction), &tr.faction(*owner)],
+            ),
+        }
+    }
+}
+
+/// Everything that has happened this game, and what is still on screen as a toast
+#[derive(Default)]
+pub struct Notifications {
+    log: Vec<Notice>,
+    /// Toasts on screen, with the seconds they have left
+    toasts: Vec<(Notice, f32)>,
+    log_open: bool,
+    /// How many lines back from the latest the log has been scrolled
+    scroll: usize,
+}
+
+impl Notifications {
+    /// What has happened since the current turn began
+    pub fn this_turn(&self) -> &[Notice] {
+        let start = self
+            .log
+            .iter()
+            .rposition(|notice| matches!(notice, Notice::TurnStarted { .. }))
+            .map_or(0, |i| i + 1);
+        &self.log[start..]
+    }
+
+    pub fn receive(&mut self, notice: Notice) {
+        self.log.push(notice.clone());
+        self.toasts.push((notice, TOAST_TIME));
+        if self.toasts.len() > MAX_TOASTS {
+            self.toasts.remove(0);
+        }
+        // Hold the view in place if the player has scrolled back
+        if self.scroll > 0 {
+            self.scroll += 1;
+        }
+    }
+}
+
+struct ToastPanel;
+
+struct ToastText;
+
+struct EventLogPanel;
+
+struct EventLogText;
+
+fn init_notifications(
+    commands: &mut Commands,
+    (data, asset_server, materials): (Res<Data>, Res<AssetServer>, Res<HudMaterials>),
+) {
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let text = |font_size: f32| TextBundle {
+        text: Text {
+            font: font.clone(),
+            value: String::new(),
+            style: TextStyle {
+                font_size,
+                color: Color::ANTIQUE_WHITE,
+                ..Default::default()
+            },
+        },
+        ..Default::default()
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(8.0)),
+                display: Display::None,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(ToastPanel)
+        .with(Anchored::new(data.ui_structure.toasts.clone()).with_height(AnchoredHeight::Auto))
+        .with_children(|parent| {
+            parent.spawn(text(16.0)).with(ToastText);
+        });
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(8.0)),
+                display: Display::None,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(EventLogPanel)
+        .with(Anchored::new(data.ui_structure.event_log.clone()))
+        .with_children(|parent| {
+            parent.spawn(text(16.0)).with(EventLogText);
+        });
+}
+
+/// Turns what the phases report into notices, for the host and every client
+fn notice_feed_system(
+    (network, info): (Res<Network>, Res<Info>),
+    mut notifications: ResMut<Notifications>,
+    (turns, bought, passed, battles): (
+        Res<Events<TurnStarted>>,
+        Res<Events<CardBought>>,
+        Res<Events<BidPassed>>,
+        Res<Events<BattleResolved>>,
+    ),
+    (mut turn_reader, mut bought_reader, mut passed_reader, mut battle_reader): (
+        Local<EventReader<TurnStarted>>,
+        Local<EventReader<CardBought>>,
+        Local<EventReader<BidPassed>>,
+        Local<EventReader<BattleResolved>>,
+    ),
+    mut eliminated: Local<Vec<Faction>>,
+    mut server: Query<&mut Server>,
+) {
+    let mut notices = Vec::new();
+    notices.extend(
+        turn_reader
+            .iter(&turns)
+            .map(|event| Notice::TurnStarted { turn: event.turn }),
+    );
+    notices.extend(bought_reader.iter(&bought).map(|event| Notice::CardBought {
+        faction: event.faction,
+    }));
+    notices.extend(passed_reader.iter(&passed).map(|event| Notice::BidPassed {
+        faction: event.faction,
+    }));
+    notices.extend(battle_reader.iter(&battles).map(|event| Notice::BattleWon {
+        faction: event.winner,
+        location: event.location.clone(),
+    }));
+    // A new game starts with nobody out
+    eliminated.retain(|faction| info.eliminated.contains(faction));
+    for &faction in info.eliminated.iter() {
+        if !eliminated.contains(&faction) {
+            eliminated.push(faction);
+            notices.push(Notice::Eliminated { faction });
+        }
+    }
+    if network.network_type == NetworkType::Client {
+        return;
+    }
+    for notice in notices {
+        if let Some(mut server) = server.iter_mut().next() {
+            server.broadcast(&ServerEvent::Notice {
+                notice: notice.clone(),
+            });
+        }
+        notifications.receive(notice);
+    }
+}
+
+/// The event log key opens and closes the log, and Page Up and Page Down scroll it, unless the
+/// chat has them
+fn event_log_input_system(
+    (keyboard_input, settings, chat): (Res<Input<KeyCode>>, Res<Settings>, Res<Chat>),
+    mut notifications: ResMut<Notifications>,
+) {
+    if chat.open {
+        return;
+    }
+    if settings.just_pressed(&keyboard_input, Binding::EventLog) {
+        notifications.log_open = !notifications.log_open;
+        notifications.scroll = 0;
+    }
+    if !notifications.log_open {
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::PageUp) {
+        notifications.scroll =
+            (notifications.scroll + 1).min(notifications.log.len().saturating_sub(LOG_LINES));
+    } else if keyboard_input.just_pressed(KeyCode::PageDown) {
+        notifications.scroll = notifications.scroll.saturating_sub(1);
+    }
+}
+
+fn toast_system(
+    (mut notifications, time, tr): (ResMut<Notifications>, Res<Time>, Res<Tr>),
+    mut panels: Query<&mut Style, With<ToastPanel>>,
+    mut texts: Query<&mut Text, With<ToastText>>,
+) {
+    for (_, remaining) in notifications.toasts.iter_mut() {
+        *remaining -= time.delta_seconds();
+    }
+    notifications
+        .toasts
+        .retain(|(_, remaining)| *remaining > 0.0);
+
+    // The log already shows everything while it is open
+    let display = if notifications.toasts.is_empty() || notifications.log_open {
+        Display::None
+    } else {
+        Display::Flex
+    };
+    for mut style in panels.iter_mut() {
+        if style.display != display {
+            style.display = display;
+        }
+    }
+    let value = notifications
+        .toasts
+        .iter()
+        .map(|(notice, _)| notice.text(&tr))
+        .collect::<Vec<_>>()
+        .join("\n");
+    for mut text in texts.iter_mut() {
+        if text.value != value {
+            text.value = value.clone();
+        }
+    }
+}
+
+fn event_log_system(
+    (notifications, settings, tr): (Res<Notifications>, Res<Settings>, Res<Tr>),
+    mut panels: Query<&mut Style, With<EventLogPanel>>,
+    mut texts: Query<&mut Text, With<EventLogText>>,
+) {
+    let display = if notifications.log_open {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    for mut style in panels.iter_mut() {
+        if style.display != display {
+            style.display = display;
+        }
+    }
+    if !notifications.log_open {
+        return;
+    }
+    let end = notifications.log.len().saturating_sub(notifications.scroll);
+    let start = end.saturating_sub(LOG_LINES);
+    let key = format!("{:?}", settings.key(Binding::EventLog));
+    let mut value = tr.fmt("notice.log_title", &[&key]);
+    if notifications.log.is_empty() {
+        value.push_str(&format!("\n{}", tr.get("notice.log_empty")));
+    }
+    for notice in &notifications.log[start..end] {
+        value.push_str(&format!("\n{}", notice.text(&tr)));
+    }
+    for mut text in texts.iter_mut() {
+        if text.value != value {
+            text.value = value.clone();
+        }
+    }
+}
+
+fn reset(mut notifications: ResMut<Notifications>) {
+    *notifications = Notifications::default();
+}
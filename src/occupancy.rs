@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    components::{Dead, LocationSector, Troop, Unique},
+    data::Faction,
+    dune_core::Occupancy,
+    network::{Network, NetworkType},
+    phase::{GamePhase, Phase},
+    resources::{SectorGraph, SectorNode},
+};
+
+pub struct OccupancyPlugin;
+
+impl Plugin for OccupancyPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // Runs ahead of the phase systems so they always see this frame's board
+        app.init_resource::<Occupancy>()
+            .add_system(occupancy_system.system());
+    }
+}
+
+fn occupancy_system(
+    mut occupancy: ResMut<Occupancy>,
+    (graph, network, phase): (Res<SectorGraph>, Res<Network>, Res<GamePhase>),
+    mut moving: Local<bool>,
+    sectors: Query<&LocationSector>,
+    troops: Query<(&Troop, &Unique), Without<Dead>>,
+) {
+    let mut occupied = HashMap::<SectorNode, HashMap<Faction, i32>>::new();
+    for (troop, unique) in troops.iter() {
+        if let Some(loc_sec) = troop
+            .location
+            .and_then(|location| sectors.get(location).ok())
+        {
+            *occupied
+                .entry(SectorNode::new(&loc_sec.location.name, loc_sec.sector))
+                .or_default()
+                .entry(unique.faction)
+                .or_insert(0) += 1;
+        }
+    }
+    if occupancy.strongholds().next().is_none() {
+        occupancy.learn_terrain(&graph.terrain);
+    }
+    if occupancy.sectors != occupied {
+        occupancy.sectors = occupied;
+    }
+
+    // The host notes who has ornithopters as its movement phase begins. Clients only see the
+    // phase change, and troops don't move before they do, so they can work it out the same way.
+    let now_moving = matches!(phase.phase, Phase::Movement { .. });
+    if now_moving && !*moving && network.network_type == NetworkType::Client {
+        occupancy.take_ornithopters();
+    }
+    *moving = now_moving;
+}
@@ -0,0 +1,98 @@
+//! Serves the browser build and the host's own assets over plain HTTP, so anyone on the network
+//! can join a game from a browser at `http://<host>:12349/`. Cards and tokens then always look
+//! the same as the host's, since they come from the host.
+//!
+//! The browser build goes in `web/`, made with
+//! `cargo build --release --target wasm32-unknown-unknown` and then
+//! `wasm-bindgen --target web --out-dir web --out-name dune` on the `.wasm` it produces.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    sync::Once,
+    thread,
+};
+
+use crate::{asset_pack::ASSETS_DIR, transport::WEB_PAGE_PORT};
+
+/// Where the page and the browser build are served from
+const WEB_DIR: &str = "web";
+
+static STARTED: Once = Once::new();
+
+/// Starts serving in the background, once for as long as the game runs
+pub fn start() {
+    STARTED.call_once(|| match TcpListener::bind(("0.0.0.0", WEB_PAGE_PORT)) {
+        Ok(listener) => {
+            println!("Serving the browser build on port {}", WEB_PAGE_PORT);
+            thread::spawn(move || {
+                for stream in listener.incoming().filter_map(Result::ok) {
+                    thread::spawn(move || serve(stream));
+                }
+            });
+        }
+        Err(err) => println!("Not serving the browser build: {}", err),
+    });
+}
+
+/// The file a request path points at. Assets come from the assets folder, the same as for the
+/// host itself, and nothing outside the two folders can be asked for.
+fn resolve(path: &str) -> Option<PathBuf> {
+    let path = path.split('?').next().unwrap_or("").trim_start_matches('/');
+    let relative = Path::new(path);
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(match relative.strip_prefix(ASSETS_DIR) {
+        Ok(asset) => Path::new(ASSETS_DIR).join(asset),
+        Err(_) if path.is_empty() => Path::new(WEB_DIR).join("index.html"),
+        Err(_) => Path::new(WEB_DIR).join(relative),
+    })
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html",
+        Some("js") => "application/javascript",
+        // Browsers only compile a module straight off the wire when it says what it is
+        Some("wasm") => "application/wasm",
+        Some("png") => "image/png",
+        Some("gltf") => "model/gltf+json",
+        Some("ttf") => "font/ttf",
+        _ => "application/octet-stream",
+    }
+}
+
+fn serve(mut stream: TcpStream) {
+    let mut request = String::new();
+    if BufReader::new(&stream).read_line(&mut request).is_err() {
+        return;
+    }
+    let mut parts = request.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let file = resolve(path)
+        .filter(|_| method == "GET")
+        .and_then(|file| fs::read(&file).ok().map(|body| (content_type(&file), body)));
+    // HTTP/1.0, so the connection simply closes after each reply
+    let result = match file {
+        Some((content_type, body)) => write!(
+            stream,
+            "HTTP/1.0 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            content_type,
+            body.len()
+        )
+        .and_then(|_| stream.write_all(&body)),
+        None => write!(
+            stream,
+            "HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+        ),
+    };
+    if let Err(err) = result {
+        println!("Failed to serve {}: {}", path, err);
+    }
+}
@@ -0,0 +1,377 @@
+use bevy::prelude::*;
+
+use crate::{
+    alliance::own_faction,
+    bot::Departures,
+    bug_report::BugRecorder,
+    components::Player,
+    hud::HudMaterials,
+    layout::{Anchored, AnchoredHeight},
+    locale::{Localized, Tr},
+    menu::{next_volume, ButtonMaterials, Chat, Lobby, SENSITIVITY_STEPS},
+    network::{Client, Network, NetworkType, Server},
+    protocol::{ClientRequest, ServerEvent},
+    resources::{Data, Info},
+    savegame::SaveRequest,
+    settings::{Binding, Settings},
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<PauseMenu>()
+            .on_state_enter(
+                RESPONSE_STAGE,
+                Screen::HostingGame,
+                init_pause_menu.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                pause_key_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                pause_button_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                pause_panel_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system());
+    }
+}
+
+/// Whether the pause menu is up, and whether it is showing the settings that apply mid-game. The
+/// game keeps running underneath, since everyone else is still playing.
+#[derive(Default)]
+pub struct PauseMenu {
+    pub open: bool,
+    settings: bool,
+}
+
+impl PauseMenu {
+    /// Closes the menu, keeping anything changed in its settings for next time
+    fn close(&mut self, settings: &Settings) {
+        if self.settings {
+            settings.save();
+        }
+        *self = PauseMenu::default();
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum PauseButton {
+    Resume,
+    Settings,
+    SaveGame,
+    BugReport,
+    Concede,
+    Quit,
+    Volume,
+    MusicVolume,
+    EffectsVolume,
+    CameraSensitivity,
+}
+
+impl PauseButton {
+    /// The fixed label, for everything but the settings, which show their values instead
+    fn key(self) -> Option<&'static str> {
+        match self {
+            PauseButton::Resume => Some("pause.resume"),
+            PauseButton::Settings => Some("pause.settings"),
+            PauseButton::SaveGame => Some("pause.save_game"),
+            PauseButton::BugReport => Some("pause.bug_report"),
+            PauseButton::Concede => Some("pause.concede"),
+            PauseButton::Quit => Some("pause.quit"),
+            _ => None,
+        }
+    }
+}
+
+struct PausePanel;
+
+/// Holds the settings buttons, shown when Settings is picked
+struct PauseSettings;
+
+/// The text on a settings button, which shows its current value
+struct PauseSettingLabel(PauseButton);
+
+type PauseButtons<'a, 'b, 'c, 'd> = Query<
+    'a,
+    (
+        &'b Interaction,
+        &'c PauseButton,
+        &'d mut Handle<ColorMaterial>,
+    ),
+    (Mutated<Interaction>, With<Button>),
+>;
+
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    button: PauseButton,
+    font: Handle<Font>,
+    button_materials: &ButtonMaterials,
+) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                margin: Rect::all(Val::Px(2.0)),
+                padding: Rect::all(Val::Px(6.0)),
+                ..Default::default()
+            },
+            material: button_materials.normal.clone(),
+            ..Default::default()
+        })
+        .with(button)
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text {
+                    font,
+                    value: String::new(),
+                    style: TextStyle {
+                        font_size: 20.0,
+                        color: Color::ANTIQUE_WHITE,
+                        ..Default::default()
+                    },
+                },
+                ..Default::default()
+            });
+            if let Some(key) = button.key() {
+                parent.with(Localized(key));
+            } else {
+                parent.with(PauseSettingLabel(button));
+            }
+        });
+}
+
+fn init_pause_menu(
+    commands: &mut Commands,
+    (data, asset_server, network): (Res<Data>, Res<AssetServer>, Res<Network>),
+    (materials, button_materials): (Res<HudMaterials>, Res<ButtonMaterials>),
+) {
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    // Only the host holds the whole game, so only the host can save it
+    let buttons = [
+        PauseButton::Resume,
+        PauseButton::Settings,
+        PauseButton::SaveGame,
+        PauseButton::BugReport,
+        PauseButton::Concede,
+        PauseButton::Quit,
+    ];
+    let buttons = buttons.iter().copied().filter(|&button| {
+        button != PauseButton::SaveGame || network.network_type != NetworkType::Client
+    });
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(8.0)),
+                display: Display::None,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(PausePanel)
+        .with(Anchored::new(data.ui_structure.pause_menu.clone()).with_height(AnchoredHeight::Auto))
+        .with_children(|parent| {
+            for button in buttons {
+                spawn_button(parent, button, font.clone(), &button_materials);
+            }
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        margin: Rect {
+                            top: Val::Px(8.0),
+                            ..Default::default()
+                        },
+                        display: Display::None,
+                        ..Default::default()
+                    },
+                    material: materials.panel.clone(),
+                    ..Default::default()
+                })
+                .with(PauseSettings)
+                .with_children(|parent| {
+                    for &button in [
+                        PauseButton::Volume,
+                        PauseButton::MusicVolume,
+                        PauseButton::EffectsVolume,
+                        PauseButton::CameraSensitivity,
+                    ]
+                    .iter()
+                    {
+                        spawn_button(parent, button, font.clone(), &button_materials);
+                    }
+                });
+        });
+}
+
+/// The pause key opens and closes the menu, unless it is being typed into the chat
+fn pause_key_system(
+    (keyboard_input, settings, chat): (Res<Input<KeyCode>>, Res<Settings>, Res<Chat>),
+    mut pause: ResMut<PauseMenu>,
+) {
+    if chat.open || !settings.just_pressed(&keyboard_input, Binding::Pause) {
+        return;
+    }
+    if pause.open {
+        pause.close(&settings);
+    } else {
+        pause.open = true;
+    }
+}
+
+/// Leaving tells everyone else first, so a bot can take the seat straight away. Conceding takes
+/// the faction out of the game, and the host stays on to keep running the game for the rest.
+fn pause_button_system(
+    (mut pause, mut state, mut settings): (
+        ResMut<PauseMenu>,
+        ResMut<State<Screen>>,
+        ResMut<Settings>,
+    ),
+    (network, lobby, info, button_materials): (
+        Res<Network>,
+        Res<Lobby>,
+        Res<Info>,
+        Res<ButtonMaterials>,
+    ),
+    (mut save, mut departures, mut recorder): (
+        ResMut<SaveRequest>,
+        ResMut<Departures>,
+        ResMut<BugRecorder>,
+    ),
+    mut buttons: PauseButtons,
+    (players, faction_client): (Query<&Player>, Query<&Client>),
+    (mut server, mut client): (Query<&mut Server>, Query<&mut Client>),
+) {
+    let mut clicked = None;
+    for (interaction, &button, mut material) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {
+                *material = button_materials.pressed.clone();
+                clicked = Some(button);
+            }
+            Interaction::Hovered => *material = button_materials.hovered.clone(),
+            Interaction::None => *material = button_materials.normal.clone(),
+        }
+    }
+    let button = match clicked {
+        Some(button) if pause.open => button,
+        _ => return,
+    };
+    match button {
+        PauseButton::Resume => pause.close(&settings),
+        PauseButton::Settings => pause.settings = !pause.settings,
+        PauseButton::SaveGame => {
+            save.pending = true;
+            pause.close(&settings);
+        }
+        PauseButton::BugReport => {
+            recorder.request();
+            pause.close(&settings);
+        }
+        PauseButton::Volume => settings.volume = next_volume(settings.volume),
+        PauseButton::MusicVolume => settings.music_volume = next_volume(settings.music_volume),
+        PauseButton::EffectsVolume => {
+            settings.effects_volume = next_volume(settings.effects_volume)
+        }
+        PauseButton::CameraSensitivity => {
+            let next = SENSITIVITY_STEPS
+                .iter()
+                .position(|&step| step > settings.camera_sensitivity)
+                .unwrap_or(0);
+            settings.camera_sensitivity = SENSITIVITY_STEPS[next];
+        }
+        PauseButton::Concede | PauseButton::Quit => {
+            let conceded = button == PauseButton::Concede;
+            let own = own_faction(&network, &lobby, &info, &players, &faction_client);
+            if let Some(faction) = own {
+                match network.network_type {
+                    NetworkType::Client => {
+                        if let Some(mut client) = client.iter_mut().next() {
+                            client.request(ClientRequest::PlayerLeft { faction, conceded });
+                        }
+                    }
+                    _ => {
+                        if let Some(mut server) = server.iter_mut().next() {
+                            server.broadcast(&ServerEvent::PlayerLeft { faction, conceded });
+                        }
+                        if conceded {
+                            departures.left.push((faction, true));
+                        }
+                    }
+                }
+            } else if conceded {
+                println!("There is no faction here to concede");
+            }
+            pause.close(&settings);
+            if !conceded || network.network_type == NetworkType::Client {
+                state.set_next(Screen::MainMenu).unwrap();
+            }
+        }
+    }
+}
+
+fn pause_panel_system(
+    (pause, settings, tr): (Res<PauseMenu>, Res<Settings>, Res<Tr>),
+    mut panels: Query<&mut Style, With<PausePanel>>,
+    mut settings_panels: Query<&mut Style, (With<PauseSettings>, Without<PausePanel>)>,
+    mut labels: Query<(&mut Text, &PauseSettingLabel)>,
+) {
+    let show = |visible: bool| {
+        if visible {
+            Display::Flex
+        } else {
+            Display::None
+        }
+    };
+    for mut style in panels.iter_mut() {
+        if style.display != show(pause.open) {
+            style.display = show(pause.open);
+        }
+    }
+    for mut style in settings_panels.iter_mut() {
+        if style.display != show(pause.settings) {
+            style.display = show(pause.settings);
+        }
+    }
+    if !pause.settings {
+        return;
+    }
+    let percent = |volume: f32| (volume * 100.0).round();
+    for (mut text, label) in labels.iter_mut() {
+        let value = match label.0 {
+            PauseButton::Volume => tr.fmt("settings.volume", &[&percent(settings.volume)]),
+            PauseButton::MusicVolume => {
+                tr.fmt("settings.music_volume", &[&percent(settings.music_volume)])
+            }
+            PauseButton::EffectsVolume => tr.fmt(
+                "settings.effects_volume",
+                &[&percent(settings.effects_volume)],
+            ),
+            PauseButton::CameraSensitivity => tr.fmt(
+                "settings.camera_sensitivity",
+                &[&settings.camera_sensitivity],
+            ),
+            _ => continue,
+        };
+        if text.value != value {
+            text.value = value;
+        }
+    }
+}
+
+fn reset(mut pause: ResMut<PauseMenu>) {
+    *pause = PauseMenu::default();
+}
@@ -0,0 +1,263 @@
+use instant::Instant;
+
+use bevy::{
+    diagnostic::{
+        Diagnostic, DiagnosticId, Diagnostics, EntityCountDiagnosticsPlugin,
+        FrameTimeDiagnosticsPlugin,
+    },
+    prelude::*,
+};
+
+use crate::{
+    lerper::Lerp,
+    network::{Client, Network, NetworkType, Server, Traffic},
+    settings::{Binding, Settings},
+    RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+const BYTES_SENT: DiagnosticId = DiagnosticId::from_u128(0x8c3f_52d1_6a0e_4b7c_9e21_d4a8_0f13_7b01);
+const BYTES_RECEIVED: DiagnosticId =
+    DiagnosticId::from_u128(0x8c3f_52d1_6a0e_4b7c_9e21_d4a8_0f13_7b02);
+const LERPS: DiagnosticId = DiagnosticId::from_u128(0x8c3f_52d1_6a0e_4b7c_9e21_d4a8_0f13_7b03);
+
+/// The stages timed every frame, in the order they run, each with the diagnostic holding its
+/// milliseconds. Rendering is whatever is left of the frame.
+const STAGES: [(&str, DiagnosticId); 5] = [
+    (
+        stage::PRE_UPDATE,
+        DiagnosticId::from_u128(0x8c3f_52d1_6a0e_4b7c_9e21_d4a8_0f13_7b10),
+    ),
+    (
+        stage::UPDATE,
+        DiagnosticId::from_u128(0x8c3f_52d1_6a0e_4b7c_9e21_d4a8_0f13_7b11),
+    ),
+    (
+        STATE_CHANGE_STAGE,
+        DiagnosticId::from_u128(0x8c3f_52d1_6a0e_4b7c_9e21_d4a8_0f13_7b12),
+    ),
+    (
+        RESPONSE_STAGE,
+        DiagnosticId::from_u128(0x8c3f_52d1_6a0e_4b7c_9e21_d4a8_0f13_7b13),
+    ),
+    (
+        stage::POST_UPDATE,
+        DiagnosticId::from_u128(0x8c3f_52d1_6a0e_4b7c_9e21_d4a8_0f13_7b14),
+    ),
+];
+/// The stages that stamp the time between the timed ones
+const CLOCK_STAGES: [&str; 6] = [
+    "clock_pre_update",
+    "clock_update",
+    "clock_state_change",
+    "clock_response",
+    "clock_post_update",
+    "clock_end",
+];
+
+/// Frames of history the averages are taken over
+const HISTORY: usize = 60;
+/// Seconds between refreshes of the overlay, so the numbers can be read
+const REFRESH_TIME: f32 = 0.5;
+
+pub struct PerformancePlugin;
+
+impl Plugin for PerformancePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_plugin(FrameTimeDiagnosticsPlugin::default())
+            .add_plugin(EntityCountDiagnosticsPlugin::default())
+            .init_resource::<PerformanceOverlay>()
+            .init_resource::<StageClock>()
+            .add_startup_system(setup_diagnostics.system())
+            .add_system(traffic_system.system())
+            .add_system(lerp_count_system.system())
+            .add_system(performance_toggle_system.system())
+            .add_system(performance_overlay_system.system());
+        // A stamp ahead of every timed stage and one after the last, each closing the stage
+        // before it
+        for (i, &(name, _)) in STAGES.iter().enumerate() {
+            app.add_stage_before(name, CLOCK_STAGES[i], SystemStage::serial());
+        }
+        app.add_stage_after(
+            stage::POST_UPDATE,
+            CLOCK_STAGES[STAGES.len()],
+            SystemStage::serial(),
+        );
+        app.add_system_to_stage(CLOCK_STAGES[0], start_clock_system.system());
+        for &name in CLOCK_STAGES[1..].iter() {
+            app.add_system_to_stage(name, stage_clock_system.system());
+        }
+    }
+}
+
+/// Frame rate, entity count, network traffic, animations in flight and how long each stage
+/// takes, for spotting where a slow frame goes on any machine
+#[derive(Default)]
+pub struct PerformanceOverlay {
+    enabled: bool,
+    /// Seconds until the numbers are refreshed
+    refresh: f32,
+}
+
+/// When the last stamp was taken this frame, and which stage ends at the next one
+#[derive(Default)]
+struct StageClock {
+    last: Option<Instant>,
+    stage: usize,
+}
+
+struct PerformanceText;
+
+fn setup_diagnostics(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add(Diagnostic::new(BYTES_SENT, "bytes_sent", HISTORY));
+    diagnostics.add(Diagnostic::new(BYTES_RECEIVED, "bytes_received", HISTORY));
+    diagnostics.add(Diagnostic::new(LERPS, "lerps", HISTORY));
+    for &(name, id) in STAGES.iter() {
+        diagnostics.add(Diagnostic::new(id, name, HISTORY));
+    }
+}
+
+fn start_clock_system(mut clock: ResMut<StageClock>) {
+    clock.last = Some(Instant::now());
+    clock.stage = 0;
+}
+
+fn stage_clock_system(mut clock: ResMut<StageClock>, mut diagnostics: ResMut<Diagnostics>) {
+    let now = Instant::now();
+    if let (Some(last), Some(&(_, id))) = (clock.last, STAGES.get(clock.stage)) {
+        diagnostics.add_measurement(id, (now - last).as_secs_f64() * 1000.0);
+    }
+    clock.last = Some(now);
+    clock.stage += 1;
+}
+
+/// Bytes per second through whichever socket is open, measured over the last frame
+fn traffic_system(
+    (network, time): (Res<Network>, Res<Time>),
+    mut diagnostics: ResMut<Diagnostics>,
+    mut last: Local<Traffic>,
+    (server, client): (Query<&Server>, Query<&Client>),
+) {
+    let traffic = match network.network_type {
+        NetworkType::Server => server.iter().next().map(|server| server.traffic),
+        NetworkType::Client => client.iter().next().map(|client| client.traffic),
+        _ => None,
+    }
+    .unwrap_or_default();
+    // A new socket starts counting again
+    if traffic.sent < last.sent || traffic.received < last.received {
+        *last = Traffic::default();
+    }
+    let seconds = time.delta_seconds_f64();
+    if seconds > 0.0 {
+        diagnostics.add_measurement(BYTES_SENT, (traffic.sent - last.sent) as f64 / seconds);
+        diagnostics.add_measurement(
+            BYTES_RECEIVED,
+            (traffic.received - last.received) as f64 / seconds,
+        );
+    }
+    *last = traffic;
+}
+
+fn lerp_count_system(mut diagnostics: ResMut<Diagnostics>, lerps: Query<&Lerp>) {
+    diagnostics.add_measurement(LERPS, lerps.iter().count() as f64);
+}
+
+fn performance_toggle_system(
+    commands: &mut Commands,
+    (keyboard_input, settings, asset_server): (
+        Res<Input<KeyCode>>,
+        Res<Settings>,
+        Res<AssetServer>,
+    ),
+    mut overlay: ResMut<PerformanceOverlay>,
+    texts: Query<Entity, With<PerformanceText>>,
+) {
+    if !settings.just_pressed(&keyboard_input, Binding::Performance) {
+        return;
+    }
+    overlay.enabled = !overlay.enabled;
+    overlay.refresh = 0.0;
+    for entity in texts.iter() {
+        commands.despawn(entity);
+    }
+    if !overlay.enabled {
+        return;
+    }
+    // Not a screen entity, so it stays up from one screen to the next
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(5.0),
+                    left: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                value: String::new(),
+                style: TextStyle {
+                    font_size: 16.0,
+                    color: Color::YELLOW,
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        })
+        .with(PerformanceText);
+}
+
+fn performance_overlay_system(
+    (mut overlay, diagnostics, time): (ResMut<PerformanceOverlay>, Res<Diagnostics>, Res<Time>),
+    mut texts: Query<&mut Text, With<PerformanceText>>,
+) {
+    if !overlay.enabled {
+        return;
+    }
+    overlay.refresh -= time.delta_seconds();
+    if overlay.refresh > 0.0 {
+        return;
+    }
+    overlay.refresh = REFRESH_TIME;
+
+    let average = |id: DiagnosticId| {
+        diagnostics
+            .get(id)
+            .and_then(|diagnostic| diagnostic.average())
+            .unwrap_or(0.0)
+    };
+    let frame_time = average(FrameTimeDiagnosticsPlugin::FRAME_TIME) * 1000.0;
+    let mut lines = vec![
+        format!(
+            "{:.0} fps ({:.1} ms)",
+            average(FrameTimeDiagnosticsPlugin::FPS),
+            frame_time
+        ),
+        format!(
+            "{:.0} entities",
+            average(EntityCountDiagnosticsPlugin::ENTITY_COUNT)
+        ),
+        format!(
+            "{:.1} KB/s out, {:.1} KB/s in",
+            average(BYTES_SENT) / 1024.0,
+            average(BYTES_RECEIVED) / 1024.0
+        ),
+        format!("{:.0} animations", average(LERPS)),
+    ];
+    let mut staged = 0.0;
+    for &(name, id) in STAGES.iter() {
+        let millis = average(id);
+        staged += millis;
+        lines.push(format!("{}: {:.2} ms", name, millis));
+    }
+    lines.push(format!(
+        "render and the rest: {:.2} ms",
+        (frame_time - staged).max(0.0)
+    ));
+    let value = lines.join("\n");
+    for mut text in texts.iter_mut() {
+        text.value = value.clone();
+    }
+}
@@ -1,14 +1,36 @@
 use std::{
     collections::{HashMap, VecDeque},
     f32::consts::PI,
-    ops::DerefMut,
 };
 
 use crate::{
-    components::{Collider, Disorganized, Troop, UniqueBundle},
-    data::{TraitorCard, TurnPredictionCard},
-    lerper::{Lerp, LerpType, UITransform},
-    util::{hand_positions, shuffle_deck},
+    alliance::Negotiation,
+    bot::Bot,
+    cards::{holder_of, CardEffects, CardPlays},
+    components::{
+        Collider, Dead, Disorganized, HiddenMobileStronghold, Prediction, Secret, Spice, SpiceNode,
+        Troop, UniqueBundle,
+    },
+    data::{
+        AbilityWindow, CardEffect, FactionAbilities, Location, SpiceCard, TraitorCard,
+        TurnPredictionCard,
+    },
+    dune_core::{divide_spice, pick_spice, Occupancy, LAST_TURN, MIN_ALLIANCE_PLAYERS},
+    game_action::{GameAction, GameActions},
+    house_rules::HouseRules,
+    hud::HotSeat,
+    layout::UiLayout,
+    lerper::{Deploying, Lerp, LerpType, UITransform},
+    locale::Tr,
+    network::{Client, Network, NetworkRole, NetworkType, Server},
+    piles::Collapsed,
+    protocol::ServerEvent,
+    savegame::CardKind,
+    secret::{deck_position, send_deck_order, HostOutcomes},
+    storm_dial::{StormDial, REVEAL_TIME},
+    token_pool::TokenPool,
+    util::{auction_positions, hand_positions, shuffle_deck},
+    wheel::BattleWheels,
     Screen, RESPONSE_STAGE, STATE_CHANGE_STAGE,
 };
 use bevy::{prelude::*, render::camera::Camera};
@@ -17,7 +39,14 @@ use rand::{prelude::SliceRandom, Rng};
 use crate::{
     components::{LocationSector, Player, Storm, Unique},
     data::{Faction, FactionPredictionCard, Leader, StormCard, TreacheryCard},
-    resources::{Data, Info},
+    resources::{Data, GameRng, Info, RuleSet, SectorGraph, SectorNode},
+};
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
+
+pub use crate::dune_core::{
+    BiddingSubPhase, Context, MovementSubPhase, Phase, SetupSubPhase, SpiceBlowSubPhase,
+    StormSubPhase,
 };
 
 #[macro_export]
@@ -40,6 +69,33 @@ impl Plugin for PhasePlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_resource(ActionQueue::default())
             .init_resource::<GamePhase>()
+            .init_resource::<Auction>()
+            .init_resource::<Movement>()
+            .init_resource::<SpiceDeck>()
+            .init_resource::<Revival>()
+            .init_resource::<TraitorPicks>()
+            .init_resource::<PredictionPicks>()
+            .init_resource::<Battle>()
+            .init_resource::<RuleSet>()
+            .add_event::<PhaseStarted>()
+            .add_event::<PhaseEnded>()
+            .add_event::<TurnStarted>()
+            .add_event::<CardDrawn>()
+            .add_event::<CardBought>()
+            .add_event::<BidPassed>()
+            .add_event::<TroopsMoved>()
+            .add_event::<TroopsDeployed>()
+            .add_event::<BattleResolved>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                crate::Screen::HostingGame,
+                phase_event_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                crate::Screen::HostingGame,
+                rule_set_system.system(),
+            )
             .on_state_update(
                 STATE_CHANGE_STAGE,
                 crate::Screen::HostingGame,
@@ -70,25 +126,164 @@ impl Plugin for PhasePlugin {
                 crate::Screen::HostingGame,
                 setup_phase_system.system(),
             )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                crate::Screen::HostingGame,
+                prediction_pick_system.system(),
+            )
             .on_state_update(
                 STATE_CHANGE_STAGE,
                 crate::Screen::HostingGame,
                 storm_phase_system.system(),
             )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                crate::Screen::HostingGame,
+                spice_blow_phase_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                crate::Screen::HostingGame,
+                nexus_phase_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                crate::Screen::HostingGame,
+                charity_phase_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                crate::Screen::HostingGame,
+                bidding_phase_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                crate::Screen::HostingGame,
+                revival_phase_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                crate::Screen::HostingGame,
+                traitor_reveal_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                crate::Screen::HostingGame,
+                battle_strength_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                crate::Screen::HostingGame,
+                battle_resolution_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                crate::Screen::HostingGame,
+                hidden_stronghold_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                crate::Screen::HostingGame,
+                movement_phase_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                crate::Screen::HostingGame,
+                collection_phase_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                crate::Screen::HostingGame,
+                reconcile_spice_system.system(),
+            )
             .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system());
     }
 }
 
 pub struct PhaseText;
 
-#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
-pub enum Context {
-    None,
-    Predicting,
-    PlacingTroops,
-    PickingTraitors,
-    Prompting,
-    StackResolving,
+/// Sent whenever a phase or subphase begins
+pub struct PhaseStarted {
+    pub phase: Phase,
+}
+
+/// Sent whenever a phase or subphase is over, just before the next one starts
+pub struct PhaseEnded {
+    pub phase: Phase,
+}
+
+/// Sent whenever a turn begins, the first one included once setup is over
+pub struct TurnStarted {
+    pub turn: i32,
+}
+
+/// Sent whenever a card leaves its deck, whether for a hand, the auction or the table
+pub struct CardDrawn {
+    pub card: Entity,
+}
+
+/// Sent whenever a faction wins a card at auction
+pub struct CardBought {
+    pub faction: Faction,
+}
+
+/// Sent whenever a faction passes on the card up for auction
+pub struct BidPassed {
+    pub faction: Faction,
+}
+
+/// Sent whenever a faction ships troops onto the board or moves them across it
+pub struct TroopsMoved {
+    pub faction: Faction,
+}
+
+/// Sent as troops shipped from the reserves take off for the board, so clients can fly theirs
+/// the same way
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct TroopsDeployed {
+    pub faction: Faction,
+    pub to: SectorNode,
+    pub count: i32,
+}
+
+/// Sent once the plans of a battle are revealed and it is clear who won
+pub struct BattleResolved {
+    pub location: String,
+    pub winner: Faction,
+}
+
+/// Puts the advanced faction powers in or out of play to match the rules
+fn rule_set_system(rules: ChangedRes<RuleSet>, mut abilities: ResMut<FactionAbilities>) {
+    abilities.advanced = rules.advanced_powers();
+}
+
+fn phase_event_system(
+    state: Res<GamePhase>,
+    info: Res<Info>,
+    mut last: Local<Option<(Phase, i32)>>,
+    mut started: ResMut<Events<PhaseStarted>>,
+    mut ended: ResMut<Events<PhaseEnded>>,
+    mut turns: ResMut<Events<TurnStarted>>,
+) {
+    let current = (state.phase, info.turn);
+    if *last == Some(current) {
+        return;
+    }
+    let setup = |phase: Phase| matches!(phase, Phase::Setup { .. });
+    // The turn counter doesn't move between setup and the first turn
+    let new_turn = match *last {
+        Some((phase, turn)) => {
+            ended.send(PhaseEnded { phase });
+            turn != info.turn || (setup(phase) && !setup(state.phase))
+        }
+        None => !setup(state.phase),
+    };
+    if new_turn {
+        turns.send(TurnStarted { turn: info.turn });
+    }
+    started.send(PhaseStarted { phase: state.phase });
+    *last = Some(current);
 }
 
 impl Context {
@@ -127,6 +322,11 @@ impl Action {
         }
     }
 
+    /// Arcs a card over the table to `dest`
+    pub fn deal_to(element: Entity, dest: Transform) -> Self {
+        Self::add_lerp(element, Lerp::deal(LerpType::world_to(dest), 0.0))
+    }
+
     pub fn then(self, next: ActionChain) -> ActionChain {
         ActionChain {
             current: self,
@@ -543,99 +743,217 @@ fn action_subsystem(
 
 fn stack_troops_system(
     commands: &mut Commands,
-    mut queue: ResMut<ActionQueue>,
-    troops: Query<(Entity, &Unique, &Troop)>,
+    (info, mut queue, mut deployed): (
+        Res<Info>,
+        ResMut<ActionQueue>,
+        ResMut<Events<TroopsDeployed>>,
+    ),
+    troops: Query<(Entity, &Unique, &Troop, Option<&Deploying>)>,
     locations: Query<(Entity, &LocationSector), With<Disorganized>>,
 ) {
     for (loc_entity, loc_sec) in locations.iter() {
         let mut map = HashMap::new();
-        for (entity, faction) in troops.iter().filter_map(|(entity, unique, troop)| {
-            troop.location.and_then(|location| {
-                if location == loc_entity {
-                    Some((entity, unique.faction))
-                } else {
-                    None
-                }
-            })
-        }) {
-            map.entry(faction).or_insert(Vec::new()).push(entity);
+        for (entity, faction, deploying) in
+            troops
+                .iter()
+                .filter_map(|(entity, unique, troop, deploying)| {
+                    troop.location.and_then(|location| {
+                        if location == loc_entity {
+                            Some((entity, unique.faction, deploying.map(|order| order.0)))
+                        } else {
+                            None
+                        }
+                    })
+                })
+        {
+            map.entry(faction)
+                .or_insert(Vec::new())
+                .push((entity, deploying));
         }
-        for (node_ind, troops) in map.values().enumerate() {
+        for (node_ind, (&faction, troops)) in map.iter().enumerate() {
             let node = loc_sec.location.sectors[&loc_sec.sector].fighters[node_ind];
-            queue.push_multiple_front(
+            let count = troops
+                .iter()
+                .filter(|(_, deploying)| deploying.is_some())
+                .count();
+            if count > 0 {
+                deployed.send(TroopsDeployed {
+                    faction,
+                    to: SectorNode::new(&loc_sec.location.name, loc_sec.sector),
+                    count: count as i32,
+                });
+            }
+            // Tidying up can happen in the middle of anything, so don't hold the queue up on it
+            queue.push_multiple_front_for_context(
                 troops
                     .iter()
                     .enumerate()
-                    .map(|(i, entity)| {
-                        Action::add_lerp(
-                            *entity,
-                            Lerp::new(
-                                LerpType::world_to(
-                                    Transform::from_translation(Vec3::new(node.x, node.z, -node.y))
-                                        * Transform::from_translation(
-                                            i as f32 * 0.0018 * Vec3::unit_y(),
-                                        ),
-                                ),
-                                0.1,
-                                0.0,
-                            ),
-                        )
-                        .into()
+                    .map(|(i, &(entity, deploying))| {
+                        let dest = Transform::from_translation(Vec3::new(node.x, node.z, -node.y))
+                            * Transform::from_translation(i as f32 * 0.0018 * Vec3::unit_y());
+                        let lerp = match deploying {
+                            Some(order) => {
+                                commands.remove_one::<Deploying>(entity);
+                                Lerp::deploy(dest, order)
+                            }
+                            None => Lerp::new(LerpType::world_to(dest), 0.1, 0.0),
+                        };
+                        Action::add_lerp(entity, lerp).into()
                     })
                     .collect::<Vec<_>>(),
+                info.context,
             );
         }
         commands.remove_one::<Disorganized>(loc_entity);
     }
 }
 
-fn public_troop_system(mut troops: Query<(&Troop, &mut Unique)>) {
-    for (troop, mut unique) in troops.iter_mut() {
-        unique.public = troop.location.is_some();
+fn public_troop_system(
+    mut troops: Query<(&Troop, &mut Unique, Option<&Dead>)>,
+    mut leaders: Query<(&mut Unique, Option<&Dead>), With<Leader>>,
+) {
+    // Everything in the Tleilaxu Tanks is in plain sight
+    for (troop, mut unique, dead) in troops.iter_mut() {
+        unique.public = troop.location.is_some() || dead.is_some();
+    }
+    for (mut unique, dead) in leaders.iter_mut() {
+        unique.public = dead.is_some();
+    }
+}
+
+/// Moves the Hidden Mobile Stronghold to wherever the Ixians have the most troops, once the
+/// board has settled
+fn hidden_stronghold_system(
+    mut queue: ResMut<ActionQueue>,
+    troops: Query<(&Troop, &Unique), Without<Dead>>,
+    sectors: Query<&LocationSector>,
+    mut strongholds: Query<(Entity, &mut HiddenMobileStronghold)>,
+) {
+    if !queue.is_empty() {
+        return;
+    }
+    let mut counts = HashMap::new();
+    for (troop, _) in troops
+        .iter()
+        .filter(|(_, unique)| unique.faction == Faction::Ixian)
+    {
+        if let Some(location) = troop.location {
+            *counts.entry(location).or_insert(0) += troop.value;
+        }
+    }
+    let largest = if let Some((&location, _)) = counts
+        .iter()
+        .max_by_key(|(location, &count)| (count, location.id()))
+    {
+        location
+    } else {
+        return;
+    };
+    for (entity, mut stronghold) in strongholds.iter_mut() {
+        // Stays put unless its current home has been abandoned or outgrown
+        if stronghold.location.map_or(false, |location| {
+            counts.get(&location).copied().unwrap_or(0) >= counts[&largest]
+        }) {
+            continue;
+        }
+        if let Ok(loc_sec) = sectors.get(largest) {
+            stronghold.location = Some(largest);
+            let node = loc_sec.location.sectors[&loc_sec.sector].fighters[0];
+            println!(
+                "The Hidden Mobile Stronghold moves to {}",
+                loc_sec.location.name
+            );
+            queue.push_single(
+                Action::add_lerp(
+                    entity,
+                    Lerp::new(
+                        LerpType::world_to(Transform::from_translation(
+                            Vec3::new(node.x, node.z, -node.y) + 0.01 * Vec3::unit_y(),
+                        )),
+                        0.6,
+                        0.0,
+                    ),
+                )
+                .into(),
+            );
+        }
     }
 }
 
 fn active_player_system(
     info: Res<Info>,
-    players: Query<&Player>,
-    mut uniques: Query<(&mut Visible, &Unique)>,
+    network: Res<Network>,
+    (players, client): (Query<&Player>, Query<&Client>),
+    mut uniques: Query<(&mut Visible, &Unique, Option<&Collapsed>)>,
 ) {
     let entity = info
         .active_player
         .unwrap_or(info.play_order[info.current_turn]);
-    let active_player_faction = players.get(entity).unwrap().faction;
-    for (mut visible, unique) in uniques.iter_mut() {
-        if visible.is_visible != (unique.public || unique.faction == active_player_faction) {
-            visible.is_visible = unique.public || unique.faction == active_player_faction;
+    // Spectators only ever see what is public, unless the host lets them see everything
+    let spectating = network.role == NetworkRole::Spectator;
+    let caster = spectating && client.iter().any(|client| client.caster);
+    let active_player_faction = Some(players.get(entity).unwrap().faction).filter(|_| !spectating);
+    for (mut visible, unique, collapsed) in uniques.iter_mut() {
+        let is_visible = collapsed.is_none()
+            && (unique.public || caster || Some(unique.faction) == active_player_faction);
+        if visible.is_visible != is_visible {
+            visible.is_visible = is_visible;
         }
     }
 }
 
 fn phase_text_system(
-    state: Res<GamePhase>,
+    (state, tr): (Res<GamePhase>, Res<Tr>),
     info: Res<Info>,
+    auction: Res<Auction>,
     players: Query<&Player>,
     mut text: Query<&mut Text, With<PhaseText>>,
 ) {
     let active_faction = players.get(info.get_active_player()).unwrap().faction;
+    let active = tr.faction(active_faction);
     let s = match state.phase {
         Phase::Setup { subphase } => match subphase {
-            SetupSubPhase::ChooseFactions => "Choosing Factions...".to_string(),
-            SetupSubPhase::Prediction => "Bene Gesserit are making a prediction...".to_string(),
-            SetupSubPhase::AtStart => format!("{:?} Initial Placement...", active_faction),
-            SetupSubPhase::DealTraitors => "Dealing Traitor Cards...".to_string(),
-            SetupSubPhase::PickTraitors => "Picking Traitors...".to_string(),
-            SetupSubPhase::DealTreachery => "Dealing Treachery Cards...".to_string(),
+            SetupSubPhase::ChooseFactions => tr.get("phase_text.choose_factions"),
+            SetupSubPhase::Prediction => tr.get("phase_text.prediction"),
+            SetupSubPhase::AtStart => tr.fmt("phase_text.at_start", &[&active]),
+            SetupSubPhase::DealTraitors => tr.get("phase_text.deal_traitors"),
+            SetupSubPhase::PickTraitors => tr.get("phase_text.pick_traitors"),
+            SetupSubPhase::DealTreachery => tr.get("phase_text.deal_treachery"),
+        },
+        Phase::Storm { subphase: _ } => tr.get("phase_text.storm"),
+        Phase::SpiceBlow { .. } => tr.get("phase_text.spice_blow"),
+        Phase::Nexus => tr.get("phase_text.nexus"),
+        Phase::Charity => tr.get("phase_text.charity"),
+        Phase::Bidding { subphase } => match subphase {
+            BiddingSubPhase::DealCards => tr.get("phase_text.bidding"),
+            BiddingSubPhase::Bidding => match auction.high_bid {
+                Some((bidder, amount)) => tr.fmt(
+                    "phase_text.high_bid",
+                    &[
+                        &active,
+                        &amount,
+                        &tr.faction(players.get(bidder).unwrap().faction),
+                    ],
+                ),
+                None => tr.fmt("phase_text.to_bid", &[&active]),
+            },
+        },
+        Phase::Revival => tr.fmt(
+            "phase_text.revival",
+            &[
+                &active,
+                &active_faction.free_revivals(),
+                &active_faction.revival_limit(),
+            ],
+        ),
+        Phase::Movement { subphase } => match subphase {
+            MovementSubPhase::Ornithopters => tr.get("phase_text.movement"),
+            MovementSubPhase::Ship => tr.fmt("phase_text.shipping", &[&active]),
+            MovementSubPhase::Move => tr.fmt("phase_text.moving", &[&active]),
         },
-        Phase::Storm { subphase: _ } => "Storm Phase".to_string(),
-        Phase::SpiceBlow => "Spice Blow Phase".to_string(),
-        Phase::Nexus => "Nexus Phase".to_string(),
-        Phase::Bidding => "Bidding Phase".to_string(),
-        Phase::Revival => "Revival Phase".to_string(),
-        Phase::Movement => "Movement Phase".to_string(),
-        Phase::Battle => "Battle Phase".to_string(),
-        Phase::Collection => "Collection Phase".to_string(),
-        Phase::Control => "Control Phase".to_string(),
+        Phase::Battle => tr.get("phase_text.battle"),
+        Phase::Collection => tr.get("phase_text.collection"),
+        Phase::Control => tr.get("phase_text.control"),
         Phase::EndGame => "".to_string(),
     };
 
@@ -645,10 +963,20 @@ fn phase_text_system(
 }
 
 fn setup_phase_system(
-    mut queue: ResMut<ActionQueue>,
+    commands: &mut Commands,
+    (mut queue, mut info, mut drawn): (
+        ResMut<ActionQueue>,
+        ResMut<Info>,
+        ResMut<Events<CardDrawn>>,
+    ),
     mut state: ResMut<GamePhase>,
-    mut info: ResMut<Info>,
-    data: Res<Data>,
+    (data, mut game_rng, mut traitor_picks, mut game_actions, layout): (
+        Res<Data>,
+        ResMut<GameRng>,
+        ResMut<TraitorPicks>,
+        ResMut<GameActions>,
+        Res<UiLayout>,
+    ),
     mut players: Query<(Entity, &mut Player)>,
     mut treachery_cards: Query<(Entity, &mut Transform, &TreacheryCard)>,
     mut traitor_cards: Query<(Entity, &mut Transform, &TraitorCard)>,
@@ -660,29 +988,59 @@ fn setup_phase_system(
     clickable_locations: Query<(Entity, &LocationSector)>,
     cameras: Query<Entity, With<Camera>>,
     mut troops: Query<(Entity, &mut Troop, &Unique, &Transform)>,
+    (network, mut server, treachery_secrets, traitor_secrets): (
+        Res<Network>,
+        Query<&mut Server>,
+        Query<&Secret<TreacheryCard>>,
+        Query<&Secret<TraitorCard>>,
+    ),
 ) {
+    let phase = state.phase;
     // We need to resolve any pending actions first
     if queue.is_empty() {
         if let Phase::Setup { ref mut subphase } = state.phase {
             match subphase {
                 SetupSubPhase::ChooseFactions => {
-                    let mut rng = rand::thread_rng();
-                    shuffle_deck(
-                        &mut rng,
-                        0.001,
-                        &mut treachery_cards
+                    // Only the host shuffles, and clients are sent how the decks were stacked
+                    if network.network_type != NetworkType::Client {
+                        let rng = &mut game_rng.rng;
+                        shuffle_deck(
+                            rng,
+                            0.001,
+                            &mut treachery_cards
+                                .iter_mut()
+                                .map(|(entity, transform, _)| (entity, transform))
+                                .collect(),
+                        );
+                        shuffle_deck(
+                            rng,
+                            0.001,
+                            &mut traitor_cards
+                                .iter_mut()
+                                .map(|(entity, transform, _)| (entity, transform))
+                                .collect(),
+                        );
+                        let treachery_order = treachery_cards
                             .iter_mut()
-                            .map(|(entity, transform, _)| (entity, transform))
-                            .collect(),
-                    );
-                    shuffle_deck(
-                        &mut rng,
-                        0.001,
-                        &mut traitor_cards
+                            .filter_map(|(entity, transform, _)| {
+                                treachery_secrets
+                                    .get(entity)
+                                    .ok()
+                                    .map(|secret| (secret.slot, transform.translation.y))
+                            })
+                            .collect();
+                        send_deck_order(&mut server, CardKind::Treachery, treachery_order);
+                        let traitor_order = traitor_cards
                             .iter_mut()
-                            .map(|(entity, transform, _)| (entity, transform))
-                            .collect(),
-                    );
+                            .filter_map(|(entity, transform, _)| {
+                                traitor_secrets
+                                    .get(entity)
+                                    .ok()
+                                    .map(|secret| (secret.slot, transform.translation.y))
+                            })
+                            .collect();
+                        send_deck_order(&mut server, CardKind::Traitor, traitor_order);
+                    }
                     // skip for now
                     state.phase.advance();
                 }
@@ -690,6 +1048,13 @@ fn setup_phase_system(
                     for (entity, player) in players.iter_mut() {
                         if player.faction == Faction::BeneGesserit {
                             info.active_player = Some(entity);
+                            queue.push_single(
+                                Action::add_lerp(
+                                    cameras.iter().next().unwrap(),
+                                    Lerp::move_camera(data.camera_nodes.prediction, 1.0),
+                                )
+                                .into(),
+                            );
                             // Lerp in faction cards
                             let num_factions = info.factions_in_play.len();
                             let animation_time = 1.5;
@@ -775,6 +1140,13 @@ fn setup_phase_system(
                                 .collect();
                             queue.push_single(Action::Enable { clickables }.into());
                             queue.push_single(Action::ContextChange(Context::Predicting).into());
+                            queue.push_single(
+                                Action::add_lerp(
+                                    cameras.iter().next().unwrap(),
+                                    Lerp::move_camera(data.camera_nodes.main, 1.0),
+                                )
+                                .into(),
+                            );
                             queue.push_single(Action::PassTurn.into());
                             queue.push_single(Action::AdvancePhase.into());
                             break;
@@ -945,7 +1317,7 @@ fn setup_phase_system(
                         .ui_structure
                         .get_turn_tiles()
                         .iter()
-                        .map(|tile| tile.center())
+                        .map(|tile| layout.place(tile).center())
                         .collect::<Vec<_>>();
 
                     let mut delay = 0.0;
@@ -958,9 +1330,8 @@ fn setup_phase_system(
                                     actions.push(
                                         Action::add_lerp(
                                             card,
-                                            Lerp::new(
+                                            Lerp::deal(
                                                 LerpType::card_to_ui(positions[i], 1.0),
-                                                0.6,
                                                 delay,
                                             ),
                                         )
@@ -976,7 +1347,7 @@ fn setup_phase_system(
                                     actions.push(
                                         Action::add_lerp(
                                             card,
-                                            Lerp::new(
+                                            Lerp::deal(
                                                 LerpType::world_to_ui(
                                                     (
                                                         turn_tile_pts[j],
@@ -986,7 +1357,6 @@ fn setup_phase_system(
                                                     )
                                                         .into(),
                                                 ),
-                                                0.6,
                                                 delay,
                                             ),
                                         )
@@ -1008,29 +1378,199 @@ fn setup_phase_system(
                     *subphase = SetupSubPhase::PickTraitors;
                 }
                 SetupSubPhase::PickTraitors => {
-                    // TODO: Add traitor cards as clickables
-                    queue.push_single(Action::Enable { clickables: vec![] }.into());
-                    queue.push_single(Action::ContextChange(Context::PickingTraitors).into());
-                    queue.push_single(Action::PassTurn.into());
+                    let turn_tile_pts = data
+                        .ui_structure
+                        .get_turn_tiles()
+                        .iter()
+                        .map(|tile| layout.place(tile).center())
+                        .collect::<Vec<_>>();
+                    let to_tile = |card: Entity, j: usize| {
+                        Action::add_lerp(
+                            card,
+                            Lerp::new(
+                                LerpType::world_to_ui(
+                                    (
+                                        turn_tile_pts[j],
+                                        Quat::from_rotation_x(0.5 * PI) * Quat::from_rotation_z(PI),
+                                        0.4,
+                                    )
+                                        .into(),
+                                ),
+                                0.6,
+                                0.0,
+                            ),
+                        )
+                        .into()
+                    };
+                    // Everyone but the Harkonnen keeps one of the four traitors they were dealt
+                    let picking = info.play_order.iter().copied().find(|&entity| {
+                        players.get_mut(entity).map_or(false, |(_, player)| {
+                            player.faction != Faction::Harkonnen && player.traitor_cards.len() > 1
+                        })
+                    });
+                    let entity = if let Some(entity) = picking {
+                        entity
+                    } else {
+                        let actions = info
+                            .play_order
+                            .iter()
+                            .enumerate()
+                            .filter_map(|(j, &entity)| {
+                                players.get_mut(entity).ok().map(|(_, player)| {
+                                    player
+                                        .traitor_cards
+                                        .iter()
+                                        .map(|&card| to_tile(card, j))
+                                        .collect::<Vec<_>>()
+                                })
+                            })
+                            .flatten()
+                            .collect::<Vec<_>>();
+                        queue.push_multiple(actions);
+                        traitor_picks.requests.clear();
+                        info.active_player = None;
+                        info.context = Context::None;
+                        *subphase = SetupSubPhase::DealTreachery;
+                        return;
+                    };
+                    let j = info.play_order.iter().position(|&e| e == entity).unwrap();
+                    let held = players
+                        .iter_mut()
+                        .flat_map(|(_, player)| player.traitor_cards.clone())
+                        .collect::<Vec<_>>();
+                    let deck = traitor_cards
+                        .iter_mut()
+                        .filter(|(card, _, _)| !held.contains(card))
+                        .count();
+                    let (_, mut player) = players.get_mut(entity).unwrap();
+                    if info.active_player != Some(entity) {
+                        // Bring the picking player's traitors up into their hand
+                        info.active_player = Some(entity);
+                        info.context = Context::PickingTraitors;
+                        let positions = hand_positions(player.traitor_cards.len() as i32);
+                        queue.push_multiple(
+                            player
+                                .traitor_cards
+                                .iter()
+                                .zip(positions)
+                                .map(|(&card, position)| {
+                                    Action::add_lerp(
+                                        card,
+                                        Lerp::new(LerpType::card_to_ui(position, 1.0), 0.6, 0.0),
+                                    )
+                                    .into()
+                                })
+                                .collect(),
+                        );
+                        return;
+                    }
+                    if let Some(pick) = traitor_picks.requests.pop_front() {
+                        if pick.faction != player.faction {
+                            println!("It is not {}'s turn to pick a traitor!", pick.faction);
+                            return;
+                        }
+                        let kept = if let Some(&card) = player.traitor_cards.iter().find(|&&card| {
+                            traitor_cards
+                                .get_component::<TraitorCard>(card)
+                                .map_or(false, |traitor| traitor.leader.name == pick.leader)
+                        }) {
+                            card
+                        } else {
+                            println!("{} was not dealt {}!", pick.faction, pick.leader);
+                            return;
+                        };
+                        let returned = player
+                            .traitor_cards
+                            .drain(..)
+                            .filter(|&card| card != kept)
+                            .collect::<Vec<_>>();
+                        player.traitor_cards.push(kept);
+                        println!("{} picked a traitor", player.faction);
+                        game_actions.resolved(&info, phase, GameAction::Traitor { pick });
+
+                        // The rest go back on top of the traitor deck
+                        let mut actions = vec![to_tile(kept, j)];
+                        for (i, &card) in returned.iter().enumerate() {
+                            commands.remove_one::<Unique>(card);
+                            actions.push(
+                                Action::add_lerp(
+                                    card,
+                                    Lerp::new(
+                                        LerpType::world_to(
+                                            Transform::from_translation(Vec3::new(
+                                                1.23,
+                                                0.0049 + ((deck + i) as f32 * 0.001),
+                                                -0.3,
+                                            )) * Transform::from_rotation(Quat::from_rotation_z(
+                                                PI,
+                                            )),
+                                        ),
+                                        0.6,
+                                        0.0,
+                                    ),
+                                )
+                                .into(),
+                            );
+                        }
+                        queue.push_multiple(actions);
+                    }
                 }
                 SetupSubPhase::DealTreachery => {
-                    /*
-                    for &entity in info.play_order.iter() {
+                    let turn_tile_pts = data
+                        .ui_structure
+                        .get_turn_tiles()
+                        .iter()
+                        .map(|tile| layout.place(tile).center())
+                        .collect::<Vec<_>>();
+                    let mut deck = treachery_cards
+                        .iter_mut()
+                        .map(|(entity, transform, _)| (entity, transform.translation.y))
+                        .collect::<Vec<_>>();
+                    deck.sort_by(|(_, y1), (_, y2)| y1.partial_cmp(y2).unwrap());
+                    let mut actions = Vec::new();
+                    let mut delay = 0.0;
+                    for (j, &entity) in info.play_order.iter().enumerate() {
                         if let Ok((_, mut player)) = players.get_mut(entity) {
-                            player
-                                .treachery_cards
-                                .push(collections.treachery_deck.pop().unwrap());
-                            if player.faction == Faction::Harkonnen {
-                                player
-                                    .treachery_cards
-                                    .push(collections.treachery_deck.pop().unwrap());
+                            let count = if player.faction == Faction::Harkonnen {
+                                2
+                            } else {
+                                1
+                            };
+                            for _ in 0..count {
+                                if let Some((card, _)) = deck.pop() {
+                                    player.treachery_cards.push(card);
+                                    drawn.send(CardDrawn { card });
+                                    actions.push(
+                                        Action::add_lerp(
+                                            card,
+                                            Lerp::deal(
+                                                LerpType::world_to_ui(
+                                                    (
+                                                        turn_tile_pts[j],
+                                                        Quat::from_rotation_x(0.5 * PI)
+                                                            * Quat::from_rotation_z(PI),
+                                                        0.4,
+                                                    )
+                                                        .into(),
+                                                ),
+                                                delay,
+                                            ),
+                                        )
+                                        .then(
+                                            Action::Assign {
+                                                element: card,
+                                                faction: player.faction,
+                                            }
+                                            .into(),
+                                        ),
+                                    );
+                                    delay += 0.2;
+                                }
                             }
                         }
                     }
-                    state.phase = Phase::Storm {
-                        subphase: StormSubPhase::Reveal,
-                    };
-                    */
+                    queue.push_multiple(actions);
+                    queue.push_single(Action::AdvancePhase.into());
                 }
             }
         }
@@ -1038,158 +1578,1941 @@ fn setup_phase_system(
 }
 
 fn storm_phase_system(
-    mut queue: ResMut<ActionQueue>,
+    (mut queue, mut plays, effects, mut dial): (
+        ResMut<ActionQueue>,
+        ResMut<CardPlays>,
+        Res<CardEffects>,
+        ResMut<StormDial>,
+    ),
     mut state: ResMut<GamePhase>,
     mut info: ResMut<Info>,
-    mut treachery_cards: Query<(Entity, &mut Transform, &TreacheryCard)>,
+    (players, treachery_cards): (Query<(Entity, &Player)>, Query<&TreacheryCard>),
     mut storm_query: Query<&mut Storm>,
     storm_cards: Query<&StormCard>,
+    (mut game_rng, house_rules, mut game_actions): (
+        ResMut<GameRng>,
+        Res<HouseRules>,
+        ResMut<GameActions>,
+    ),
+    (network, mut outcomes, mut server): (Res<Network>, ResMut<HostOutcomes>, Query<&mut Server>),
 ) {
+    let phase = state.phase;
     if queue.is_empty() {
         if let Phase::Storm { ref mut subphase } = state.phase {
             match subphase {
                 StormSubPhase::Reveal => {
                     // Make card visible to everyone
                     if info.turn == 0 {
-                        *subphase = StormSubPhase::MoveStorm;
+                        *subphase = StormSubPhase::Dial;
                     } else {
                         *subphase = StormSubPhase::WeatherControl;
                     }
                 }
-                StormSubPhase::WeatherControl => {
-                    if let Some((entity, _, _)) = treachery_cards
-                        .iter_mut()
-                        .find(|(_, _, card)| card.name == "Weather Control")
-                    {
-                        // TODO: Add weather control card as clickable
-                        todo!();
-                        queue.push_single(Action::Enable { clickables: vec![] }.into());
-                        queue.push_single(Action::ContextChange(Context::Prompting).into());
-                        queue.push_single(Action::PassTurn.into());
+                StormSubPhase::Dial => {
+                    // The players on either side of where the storm starts dial it into place
+                    if dial.dialers.is_empty() {
+                        let order = info
+                            .play_order
+                            .iter()
+                            .filter_map(|&entity| players.get(entity).ok())
+                            .map(|(_, player)| player.faction)
+                            .filter(|faction| !info.eliminated.contains(faction))
+                            .collect::<Vec<_>>();
+                        dial.dialers = order.first().into_iter().copied().collect();
+                        dial.dialers
+                            .extend(order.last().copied().filter(|_| order.len() > 1));
+                        if dial.dialers.is_empty() {
+                            *subphase = StormSubPhase::MoveStorm;
+                            return;
+                        }
+                        println!("{:?} dial the first storm", dial.dialers);
+                    }
+                    while let Some(pick) = dial.requests.pop_front() {
+                        match dial.accept(pick.clone()) {
+                            Ok(()) => {
+                                game_actions.resolved(&info, phase, GameAction::StormDial { pick })
+                            }
+                            Err(reason) => println!("Rejected storm dial: {}", reason),
+                        }
+                    }
+                    if let Some(total) = dial.total() {
+                        println!("The storm dials add up to {}", total);
+                        // Give the dials time to turn over before the storm moves
+                        queue.push_single(Action::Delay { time: REVEAL_TIME }.into());
+                        *subphase = StormSubPhase::MoveStorm;
                     }
                 }
-                StormSubPhase::FamilyAtomics => {
-                    if let Some((entity, _, _)) = treachery_cards
-                        .iter_mut()
-                        .find(|(_, _, card)| card.name == "Family Atomics")
-                    {
-                        // TODO: Add family atomics as clickable
-                        queue.push_single(Action::Enable { clickables: vec![] }.into());
-                        queue.push_single(Action::ContextChange(Context::Prompting).into());
-                        queue.push_single(Action::PassTurn.into());
+                StormSubPhase::WeatherControl | StormSubPhase::FamilyAtomics => {
+                    let (effect, next) = if *subphase == StormSubPhase::WeatherControl {
+                        (CardEffect::WeatherControl, StormSubPhase::FamilyAtomics)
+                    } else {
+                        (CardEffect::Atomics, StormSubPhase::MoveStorm)
+                    };
+                    // Whoever holds the card gets the chance to play it before the storm moves
+                    match holder_of(effect, &players, &treachery_cards) {
+                        Some((entity, faction)) if !plays.passed.contains(&faction) => {
+                            info.active_player = Some(entity);
+                            info.context = Context::Prompting;
+                        }
+                        _ => {
+                            plays.passed.clear();
+                            info.active_player = None;
+                            info.context = Context::None;
+                            *subphase = next;
+                        }
                     }
                 }
                 StormSubPhase::MoveStorm => {
-                    /*
-                    let mut rng = rand::thread_rng();
-                    if info.turn == 0 {
-                        for mut storm in storm_query.iter_mut() {
-                            storm.sector = rng.gen_range(0..18);
+                    let rng = &mut game_rng.rng;
+                    let delta = if network.network_type == NetworkType::Client {
+                        // Only the host rolls, so wait to hear how far it went
+                        match outcomes.storm_moves.pop_front() {
+                            Some(sectors) => sectors,
+                            None => return,
                         }
+                    } else if info.turn == 0 {
+                        dial.total().unwrap_or_else(|| rng.gen_range(0..18))
+                    } else if let Some(sectors) = effects.weather_control {
+                        sectors
+                    } else {
+                        let mut vals = storm_cards.iter().map(|card| card.val).collect::<Vec<_>>();
+                        vals.sort_unstable();
+                        vals.choose(rng).copied().unwrap_or(0)
+                    };
+                    let delta = if network.network_type == NetworkType::Client {
+                        delta
                     } else {
-                        let &storm_card = collections.storm_deck.last().unwrap();
-                        let delta = storm_cards.get(storm_card).unwrap().val;
-                        for mut storm in storm_query.iter_mut() {
-                            storm.sector += delta;
-                            storm.sector %= 18;
+                        let delta = house_rules
+                            .storm_move(info.turn, delta)
+                            .map_or(delta, |sectors| sectors.rem_euclid(18));
+                        if let Some(mut server) = server.iter_mut().next() {
+                            server.broadcast(&ServerEvent::StormMoved { sectors: delta });
                         }
-                        // TODO: Kill everything it passed over and wipe spice
-                        collections.storm_deck.shuffle(&mut rng)
-                        // TODO: Choose a first player
-                        // TODO: Assign bonuses
+                        delta
+                    };
+                    for mut storm in storm_query.iter_mut() {
+                        storm.sector += delta;
+                        storm.sector %= 18;
                     }
-                    */
+                    // TODO: Kill everything it passed over and wipe spice
+                    // TODO: Choose a first player
+                    // TODO: Assign bonuses
+                    queue.push_single(Action::AdvancePhase.into());
                 }
             }
         }
     }
 }
 
-#[derive(Copy, Clone)]
-pub enum Phase {
-    Setup { subphase: SetupSubPhase },
-    Storm { subphase: StormSubPhase },
-    SpiceBlow,
-    Nexus,
-    Bidding,
-    Revival,
-    Movement,
-    Battle,
-    Collection,
-    Control,
-    EndGame,
+#[derive(Default)]
+pub struct Auction {
+    pub cards: VecDeque<(Entity, Vec2)>,
+    pub starting_bidder: usize,
+    pub bidder: usize,
+    pub high_bid: Option<(Entity, i32)>,
+    pub passes: usize,
+    pub closed: bool,
+    pub bids: VecDeque<(Faction, Option<i32>)>,
 }
 
-impl Phase {
-    pub fn next(&self) -> Self {
-        match self {
-            Phase::Setup { subphase } => match subphase {
-                SetupSubPhase::ChooseFactions => Phase::Setup {
-                    subphase: SetupSubPhase::Prediction,
-                },
-                SetupSubPhase::Prediction => Phase::Setup {
-                    subphase: SetupSubPhase::AtStart,
-                },
-                SetupSubPhase::AtStart => Phase::Setup {
-                    subphase: SetupSubPhase::DealTraitors,
-                },
-                SetupSubPhase::DealTraitors => Phase::Setup {
-                    subphase: SetupSubPhase::PickTraitors,
-                },
-                SetupSubPhase::PickTraitors => Phase::Setup {
-                    subphase: SetupSubPhase::DealTreachery,
-                },
-                SetupSubPhase::DealTreachery => Phase::Storm {
-                    subphase: StormSubPhase::Reveal,
-                },
-            },
-            Phase::Storm { subphase } => match subphase {
-                StormSubPhase::Reveal => Phase::Storm {
-                    subphase: StormSubPhase::WeatherControl,
-                },
-                StormSubPhase::WeatherControl => Phase::Storm {
-                    subphase: StormSubPhase::FamilyAtomics,
-                },
-                StormSubPhase::FamilyAtomics => Phase::Storm {
-                    subphase: StormSubPhase::MoveStorm,
-                },
-                StormSubPhase::MoveStorm => Phase::SpiceBlow,
-            },
-            Phase::SpiceBlow => Phase::Nexus,
-            Phase::Nexus => Phase::Bidding,
-            Phase::Bidding => Phase::Revival,
-            Phase::Revival => Phase::Movement,
-            Phase::Movement => Phase::Battle,
-            Phase::Battle => Phase::Collection,
-            Phase::Collection => Phase::Control,
-            Phase::Control => Phase::Storm {
-                subphase: StormSubPhase::Reveal,
-            },
-            Phase::EndGame => Phase::EndGame,
-        }
-    }
-
-    pub fn advance(&mut self) {
-        *self = self.next();
-    }
+fn next_bidder(eligible: &[usize], from: usize) -> usize {
+    eligible
+        .iter()
+        .copied()
+        .find(|&e| e > from)
+        .or_else(|| eligible.first().copied())
+        .unwrap_or(from)
 }
 
-#[derive(Copy, Clone)]
-pub enum SetupSubPhase {
-    ChooseFactions,
-    Prediction,
-    AtStart,
-    DealTraitors,
-    PickTraitors,
-    DealTreachery,
-}
+fn auction_card_lerp(card: Entity, pos: Vec2, face_up: bool) -> ActionChain {
+    let rotation = if face_up {
+        Quat::from_rotation_x(0.5 * PI)
+    } else {
+        Quat::from_rotation_x(0.5 * PI) * Quat::from_rotation_z(PI)
+    };
+    Action::add_lerp(
+        card,
+        Lerp::new(LerpType::world_to_ui((pos, rotation, 0.6).into()), 0.4, 0.0),
+    )
+    .into()
+}
+
+/// Marks a troop or leader as dead and sends it to the top of its faction's stack in the
+/// Tleilaxu Tanks, `height` tokens up
+pub fn kill_token(
+    commands: &mut Commands,
+    data: &Data,
+    entity: Entity,
+    faction: Faction,
+    leader: bool,
+    height: usize,
+) -> ActionChain {
+    commands.insert_one(entity, Dead);
+    let node = if leader {
+        data.token_nodes.tanks_leader(faction, height)
+    } else {
+        data.token_nodes.tanks_fighter(faction, height)
+    };
+    Action::add_lerp(
+        entity,
+        Lerp::new(
+            LerpType::world_to(Transform::from_translation(node)),
+            0.6,
+            0.0,
+        ),
+    )
+    .into()
+}
+
+/// Spawns `amount` worth of spice tokens on a territory's spice node, `height` tokens up
+pub fn place_spice(
+    commands: &mut Commands,
+    pool: &mut TokenPool,
+    amount: i32,
+    location: Entity,
+    node: &SpiceNode,
+    height: usize,
+) {
+    let (tens, fives, twos, ones) = divide_spice(amount);
+    for (i, value) in std::iter::repeat(10)
+        .take(tens as usize)
+        .chain(std::iter::repeat(5).take(fives as usize))
+        .chain(std::iter::repeat(2).take(twos as usize))
+        .chain(std::iter::repeat(1).take(ones as usize))
+        .enumerate()
+    {
+        pool.spawn_spice(
+            commands,
+            value,
+            Some(location),
+            Transform::from_translation(
+                Vec3::new(node.pos.x, node.pos.z, -node.pos.y)
+                    + ((height + i) as f32 * 0.0036 * Vec3::unit_y()),
+            ),
+        );
+    }
+}
+
+/// Spawns `amount` worth of spice tokens from the bank on top of a faction's stacks. `held` is the
+/// value of every token the faction already has.
+fn give_spice(
+    commands: &mut Commands,
+    pool: &mut TokenPool,
+    data: &Data,
+    faction: Faction,
+    held: &[i32],
+    amount: i32,
+) {
+    let (tens, fives, twos, ones) = divide_spice(amount);
+    for (node, value, count) in [(0, 10, tens), (1, 5, fives), (2, 2, twos), (3, 1, ones)] {
+        let height = held.iter().filter(|&&v| v == value).count();
+        for i in 0..count as usize {
+            let token = pool.spawn_spice(
+                commands,
+                value,
+                None,
+                Transform::from_translation(
+                    data.token_nodes.spice[node] + ((height + i) as f32 * 0.0036 * Vec3::unit_y()),
+                ),
+            );
+            commands.insert(token, UniqueBundle::new(faction));
+        }
+    }
+}
+
+/// Keeps the spice tokens in front of each player in step with their ledger, making change as
+/// needed. Waits for any tokens still on their way somewhere to arrive first.
+fn reconcile_spice_system(
+    commands: &mut Commands,
+    (queue, data, mut pool): (Res<ActionQueue>, Res<Data>, ResMut<TokenPool>),
+    players: Query<&Player>,
+    spice: Query<(Entity, &Spice, &Unique)>,
+) {
+    if !queue.is_empty() {
+        return;
+    }
+    for player in players.iter() {
+        let tokens = spice
+            .iter()
+            .filter(|(_, _, unique)| unique.faction == player.faction)
+            .map(|(entity, spice, _)| (entity, spice.value))
+            .collect::<Vec<_>>();
+        let total = tokens.iter().map(|(_, value)| value).sum::<i32>();
+        if total == player.spice {
+            continue;
+        }
+        let mut held = tokens.iter().map(|&(_, value)| value).collect::<Vec<_>>();
+        let mut missing = player.spice - total;
+        if missing < 0 {
+            // Hand back enough tokens to cover the difference and take change for any overshoot
+            let returned = pick_spice(&tokens, -missing).unwrap();
+            for &(token, value) in tokens.iter().filter(|(token, _)| returned.contains(token)) {
+                pool.release_spice(commands, token, value);
+            }
+            held = tokens
+                .iter()
+                .filter(|(entity, _)| !returned.contains(entity))
+                .map(|&(_, value)| value)
+                .collect();
+            missing = player.spice - held.iter().sum::<i32>();
+        }
+        give_spice(commands, &mut pool, &data, player.faction, &held, missing);
+    }
+}
+
+fn charity_phase_system(
+    mut queue: ResMut<ActionQueue>,
+    (state, info, house_rules): (Res<GamePhase>, Res<Info>, Res<HouseRules>),
+    mut players: Query<&mut Player>,
+) {
+    if queue.is_empty() {
+        if let Phase::Charity = state.phase {
+            for mut player in players
+                .iter_mut()
+                .filter(|player| !info.eliminated.contains(&player.faction))
+            {
+                let standard = player.faction.charity(player.spice);
+                let amount = house_rules
+                    .charity(player.faction, player.spice, standard)
+                    .unwrap_or(standard);
+                if amount > 0 {
+                    player.receive(amount);
+                    println!("{} claimed {} from CHOAM Charity", player.faction, amount);
+                }
+            }
+            queue.push_single(Action::AdvancePhase.into());
+        }
+    }
+}
+
+fn collection_phase_system(
+    commands: &mut Commands,
+    (mut queue, state, info, occupancy): (
+        ResMut<ActionQueue>,
+        Res<GamePhase>,
+        Res<Info>,
+        Res<Occupancy>,
+    ),
+    mut pool: ResMut<TokenPool>,
+    (mut players, sectors): (Query<&mut Player>, Query<&LocationSector>),
+    troops: Query<(&Troop, &Unique), Without<Dead>>,
+    mut spice_nodes: Query<(Entity, &Location, &mut SpiceNode)>,
+    spice: Query<(Entity, &Spice)>,
+) {
+    if queue.is_empty() {
+        if let Phase::Collection = state.phase {
+            let territory_of = |troop: &Troop| {
+                troop
+                    .location
+                    .and_then(|location| sectors.get(location).ok())
+                    .map(|loc_sec| loc_sec.location.name.as_str())
+            };
+            let mut collected = HashMap::new();
+            for (location, territory, mut node) in spice_nodes.iter_mut() {
+                if node.val <= 0 {
+                    continue;
+                }
+                let mut remaining = node.val;
+                for &entity in info.play_order.iter() {
+                    let faction = players.get_mut(entity).unwrap().faction;
+                    let count = troops
+                        .iter()
+                        .filter(|(troop, unique)| {
+                            unique.faction == faction
+                                && territory_of(troop) == Some(territory.name.as_str())
+                        })
+                        .count() as i32;
+                    // Ornithopters from the start of movement let a faction harvest faster
+                    let amount = (count * occupancy.harvest_rate(faction)).min(remaining);
+                    if amount > 0 {
+                        remaining -= amount;
+                        *collected.entry(faction).or_insert(0) += amount;
+                        println!(
+                            "{} collected {} spice from {}",
+                            faction, amount, territory.name
+                        );
+                    }
+                }
+                if remaining != node.val {
+                    for (entity, spice) in spice
+                        .iter()
+                        .filter(|(_, spice)| spice.location == Some(location))
+                    {
+                        pool.release_spice(commands, entity, spice.value);
+                    }
+                    place_spice(commands, &mut pool, remaining, location, &node, 0);
+                    node.val = remaining;
+                }
+            }
+            for mut player in players.iter_mut() {
+                if let Some(&amount) = collected.get(&player.faction) {
+                    player.receive(amount);
+                }
+            }
+            // Collection remakes most of the spice on the board at once
+            pool.report();
+            queue.push_single(Action::AdvancePhase.into());
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SpiceDeck {
+    pub discard: Vec<Entity>,
+    pub set_aside: Vec<Entity>,
+    pub revealed: bool,
+}
+
+fn spice_blow_phase_system(
+    commands: &mut Commands,
+    mut queue: ResMut<ActionQueue>,
+    mut state: ResMut<GamePhase>,
+    mut info: ResMut<Info>,
+    (mut spice_deck, mut game_rng, mut drawn): (
+        ResMut<SpiceDeck>,
+        ResMut<GameRng>,
+        ResMut<Events<CardDrawn>>,
+    ),
+    (data, mut pool, network, mut outcomes): (
+        Res<Data>,
+        ResMut<TokenPool>,
+        Res<Network>,
+        ResMut<HostOutcomes>,
+    ),
+    mut server: Query<&mut Server>,
+    mut spice_cards: Query<(Entity, &mut Transform, &SpiceCard)>,
+    mut spice_nodes: Query<(Entity, &Location, &mut SpiceNode)>,
+    storm: Query<&Storm>,
+    mut troops: Query<(Entity, &mut Troop, &Unique, Option<&Dead>)>,
+    spice: Query<(Entity, &Spice)>,
+    sectors: Query<&LocationSector>,
+) {
+    if queue.is_empty() {
+        if let Phase::SpiceBlow { ref mut subphase } = state.phase {
+            let deck_pos = |i: usize| {
+                Transform::from_translation(Vec3::new(1.23, 0.0049 + (i as f32 * 0.001), 0.3))
+                    * Transform::from_rotation(Quat::from_rotation_z(PI))
+            };
+            let discard_pos = |i: usize| {
+                Transform::from_translation(Vec3::new(1.53, 0.0049 + (i as f32 * 0.001), 0.3))
+            };
+            match subphase {
+                SpiceBlowSubPhase::Reveal => {
+                    let mut deck = spice_cards
+                        .iter_mut()
+                        .filter(|(entity, _, _)| {
+                            !spice_deck.discard.contains(entity)
+                                && !spice_deck.set_aside.contains(entity)
+                        })
+                        .map(|(entity, transform, card)| {
+                            (entity, transform.translation.y, card.clone())
+                        })
+                        .collect::<Vec<_>>();
+                    deck.sort_by(|(_, y1, _), (_, y2, _)| y1.partial_cmp(y2).unwrap());
+
+                    // Only the host knows the order of the deck, so clients wait to hear what it
+                    // drew
+                    let top = if network.network_type != NetworkType::Client {
+                        deck.pop()
+                    } else if deck.is_empty() {
+                        None
+                    } else if let Some(key) = outcomes.spice_draws.pop_front() {
+                        deck.iter()
+                            .rposition(|(_, _, card)| card.name == key)
+                            .map(|i| deck.remove(i))
+                    } else {
+                        return;
+                    };
+                    if let Some((entity, _, card)) = top {
+                        if let Some(mut server) = server.iter_mut().next() {
+                            server.broadcast(&ServerEvent::SpiceDrawn {
+                                key: card.name.clone(),
+                            });
+                        }
+                        drawn.send(CardDrawn { card: entity });
+                        let flip = Action::add_lerp(
+                            entity,
+                            Lerp::flip(discard_pos(spice_deck.discard.len()), 0.0),
+                        );
+                        if card.is_shai_hulud() {
+                            if info.turn == 0 {
+                                // Worms on the first turn are ignored and go back into the deck
+                                queue.push_single(
+                                    Action::add_lerp(
+                                        entity,
+                                        Lerp::new(
+                                            LerpType::world_to(Transform::from_translation(
+                                                Vec3::new(
+                                                    1.53,
+                                                    0.0049
+                                                        + (spice_deck.set_aside.len() as f32
+                                                            * 0.001),
+                                                    0.6,
+                                                ),
+                                            )),
+                                            0.6,
+                                            0.0,
+                                        ),
+                                    )
+                                    .into(),
+                                );
+                                spice_deck.set_aside.push(entity);
+                                return;
+                            }
+                            let territory = spice_deck.discard.iter().rev().find_map(|&entity| {
+                                spice_cards
+                                    .get_component::<SpiceCard>(entity)
+                                    .ok()
+                                    .filter(|card| !card.is_shai_hulud())
+                                    .map(|card| card.name.clone())
+                            });
+                            if let Some(territory) = territory {
+                                println!("Shai-Hulud devours everything in {}!", territory);
+                                let mut actions = Vec::new();
+                                let mut tanks = HashMap::new();
+                                for (_, _, unique, _) in
+                                    troops.iter_mut().filter(|(_, _, _, dead)| dead.is_some())
+                                {
+                                    *tanks.entry(unique.faction).or_insert(0) += 1;
+                                }
+                                for (entity, mut troop, unique, _) in
+                                    troops.iter_mut().filter(|(_, troop, _, dead)| {
+                                        dead.is_none()
+                                            && troop.location.map_or(false, |location| {
+                                                sectors.get(location).map_or(false, |loc_sec| {
+                                                    loc_sec.location.name == territory
+                                                })
+                                            })
+                                    })
+                                {
+                                    let height = tanks.entry(unique.faction).or_insert(0);
+                                    troop.location = None;
+                                    actions.push(kill_token(
+                                        commands,
+                                        &data,
+                                        entity,
+                                        unique.faction,
+                                        false,
+                                        *height,
+                                    ));
+                                    *height += 1;
+                                }
+                                if let Some((location, _, mut node)) = spice_nodes
+                                    .iter_mut()
+                                    .find(|(_, location, _)| location.name == territory)
+                                {
+                                    for (entity, spice) in spice
+                                        .iter()
+                                        .filter(|(_, spice)| spice.location == Some(location))
+                                    {
+                                        pool.release_spice(commands, entity, spice.value);
+                                    }
+                                    node.val = 0;
+                                }
+                                if !actions.is_empty() {
+                                    queue.push_multiple(actions);
+                                }
+                            }
+                            info.nexus = true;
+                            queue.push_single(flip.into());
+                            spice_deck.discard.push(entity);
+                        } else {
+                            queue.push_single(flip.into());
+                            spice_deck.discard.push(entity);
+                            let storm_sector = storm.iter().next().map(|storm| storm.sector);
+                            if let Some((location, loc, mut node)) = spice_nodes
+                                .iter_mut()
+                                .find(|(_, location, _)| location.name == card.name)
+                            {
+                                if storm_sector.is_some()
+                                    && loc.sector_at(node.pos.truncate()) == storm_sector
+                                {
+                                    println!("The storm blows away the spice in {}", card.name);
+                                } else {
+                                    let height = spice
+                                        .iter()
+                                        .filter(|(_, spice)| spice.location == Some(location))
+                                        .count();
+                                    place_spice(
+                                        commands,
+                                        &mut pool,
+                                        card.amount,
+                                        location,
+                                        &node,
+                                        height,
+                                    );
+                                    node.val += card.amount;
+                                }
+                            }
+                            spice_deck.revealed = true;
+                            if spice_deck.set_aside.is_empty() {
+                                queue.push_single(Action::AdvancePhase.into());
+                            } else {
+                                // Put the ignored worms back before shuffling
+                                let actions = spice_deck
+                                    .set_aside
+                                    .drain(..)
+                                    .enumerate()
+                                    .map(|(i, entity)| {
+                                        Action::add_lerp(
+                                            entity,
+                                            Lerp::new(
+                                                LerpType::world_to(deck_pos(deck.len() + i)),
+                                                0.6,
+                                                0.0,
+                                            ),
+                                        )
+                                        .into()
+                                    })
+                                    .collect::<Vec<_>>();
+                                queue.push_multiple(actions);
+                                *subphase = SpiceBlowSubPhase::Shuffle;
+                            }
+                        }
+                    } else {
+                        // Out of cards, so the discard pile becomes the new deck
+                        println!(
+                            "The spice deck is exhausted, so {} discarded cards are shuffled into a new one",
+                            spice_deck.discard.len()
+                        );
+                        let actions = spice_deck
+                            .discard
+                            .drain(..)
+                            .enumerate()
+                            .map(|(i, entity)| {
+                                Action::add_lerp(
+                                    entity,
+                                    Lerp::new(LerpType::world_to(deck_pos(i)), 0.6, 0.0),
+                                )
+                                .into()
+                            })
+                            .collect::<Vec<_>>();
+                        if actions.is_empty() {
+                            queue.push_single(Action::AdvancePhase.into());
+                        } else {
+                            queue.push_multiple(actions);
+                            *subphase = SpiceBlowSubPhase::Shuffle;
+                        }
+                    }
+                }
+                SpiceBlowSubPhase::Shuffle => {
+                    if network.network_type != NetworkType::Client {
+                        shuffle_deck(
+                            &mut game_rng.rng,
+                            0.001,
+                            &mut spice_cards
+                                .iter_mut()
+                                .filter(|(entity, _, _)| !spice_deck.discard.contains(entity))
+                                .map(|(entity, transform, _)| (entity, transform))
+                                .collect(),
+                        );
+                    }
+                    if spice_deck.revealed {
+                        spice_deck.revealed = false;
+                        queue.push_single(Action::AdvancePhase.into());
+                    } else {
+                        *subphase = SpiceBlowSubPhase::Reveal;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Opens alliance negotiations when Shai-Hulud has called a Nexus, and waits for them to close
+fn nexus_phase_system(
+    mut queue: ResMut<ActionQueue>,
+    state: Res<GamePhase>,
+    mut info: ResMut<Info>,
+    mut negotiation: ResMut<Negotiation>,
+) {
+    if queue.is_empty() {
+        if let Phase::Nexus = state.phase {
+            if info.nexus {
+                println!("Nexus!");
+                info.nexus = false;
+                if info.in_game().len() >= MIN_ALLIANCE_PLAYERS {
+                    negotiation.start();
+                }
+            }
+            if !negotiation.open {
+                queue.push_single(Action::AdvancePhase.into());
+            }
+        }
+    }
+}
+
+/// Moves `amount` of spice from the payer's ledger to the payee's, or back to the bank if there is
+/// no payee. Nothing changes if the payer can't afford it.
+fn settle_spice<'a>(
+    players: impl Iterator<Item = Mut<'a, Player>>,
+    payer: Faction,
+    payee: Option<Faction>,
+    amount: i32,
+) -> bool {
+    let mut players = players.collect::<Vec<_>>();
+    let paid = players
+        .iter_mut()
+        .find(|player| player.faction == payer)
+        .map_or(false, |player| player.pay(amount));
+    if let Some(player) = payee
+        .filter(|_| paid)
+        .and_then(|payee| players.iter_mut().find(|player| player.faction == payee))
+    {
+        player.receive(amount);
+    }
+    paid
+}
+
+/// Moves `amount` worth of the payer's spice tokens to the payee's stacks, or back to the bank if
+/// there is no payee. This only animates a payment already settled in the ledgers, so any change
+/// owed is made afterwards by `reconcile_spice_system`.
+fn transfer_spice(
+    commands: &mut Commands,
+    pool: &mut TokenPool,
+    data: &Data,
+    spice: &Query<(Entity, &Spice, &Unique)>,
+    payer: Faction,
+    payee: Option<Faction>,
+    amount: i32,
+) -> Vec<ActionChain> {
+    let tokens = spice
+        .iter()
+        .filter(|(_, _, unique)| unique.faction == payer)
+        .map(|(entity, spice, _)| (entity, spice.value))
+        .collect::<Vec<_>>();
+    let mut stacked = spice
+        .iter()
+        .filter(|(_, _, unique)| Some(unique.faction) == payee)
+        .map(|(_, spice, _)| spice.value)
+        .collect::<Vec<_>>();
+    let mut actions = Vec::new();
+    for token in pick_spice(&tokens, amount).unwrap_or_default() {
+        let value = spice.get(token).unwrap().1.value;
+        if let Some(payee) = payee {
+            let node = match value {
+                10 => 0,
+                5 => 1,
+                2 => 2,
+                _ => 3,
+            };
+            let height = stacked.iter().filter(|&&v| v == value).count();
+            stacked.push(value);
+            actions.push(
+                Action::add_lerp(
+                    token,
+                    Lerp::new(
+                        LerpType::world_to(Transform::from_translation(
+                            data.token_nodes.spice[node]
+                                + (height as f32 * 0.0036 * Vec3::unit_y()),
+                        )),
+                        0.6,
+                        0.0,
+                    ),
+                )
+                .then(
+                    Action::Assign {
+                        element: token,
+                        faction: payee,
+                    }
+                    .into(),
+                ),
+            );
+        } else {
+            pool.release_spice(commands, token, value);
+        }
+    }
+    actions
+}
+
+fn bidding_phase_system(
+    commands: &mut Commands,
+    (mut queue, mut game_rng, mut pool, mut game_actions): (
+        ResMut<ActionQueue>,
+        ResMut<GameRng>,
+        ResMut<TokenPool>,
+        ResMut<GameActions>,
+    ),
+    mut state: ResMut<GamePhase>,
+    mut info: ResMut<Info>,
+    (mut auction, mut drawn, mut bought, mut passed): (
+        ResMut<Auction>,
+        ResMut<Events<CardDrawn>>,
+        ResMut<Events<CardBought>>,
+        ResMut<Events<BidPassed>>,
+    ),
+    (data, mut card_effects, abilities, layout): (
+        Res<Data>,
+        ResMut<CardEffects>,
+        Res<FactionAbilities>,
+        Res<UiLayout>,
+    ),
+    mut players: Query<(Entity, &mut Player)>,
+    treachery_cards: Query<(Entity, &Transform), With<TreacheryCard>>,
+    spice: Query<(Entity, &Spice, &Unique)>,
+    (network, mut server, mut secrets): (
+        Res<Network>,
+        Query<&mut Server>,
+        Query<&mut Secret<TreacheryCard>>,
+    ),
+) {
+    let phase = state.phase;
+    if queue.is_empty() {
+        if let Phase::Bidding { ref mut subphase } = state.phase {
+            let held = players
+                .iter_mut()
+                .flat_map(|(_, player)| player.treachery_cards.clone())
+                .collect::<Vec<_>>();
+            let mut deck = treachery_cards
+                .iter()
+                .filter(|(entity, _)| {
+                    !held.contains(entity)
+                        && !auction.cards.iter().any(|(card, _)| card == entity)
+                        && !card_effects.discard.contains(entity)
+                })
+                .collect::<Vec<_>>();
+            deck.sort_by(|(_, transform1), (_, transform2)| {
+                transform2
+                    .translation
+                    .y
+                    .partial_cmp(&transform1.translation.y)
+                    .unwrap()
+            });
+            // Indices into the play order of everyone who can still take a card
+            let eligible = info
+                .play_order
+                .iter()
+                .enumerate()
+                .filter_map(|(i, &entity)| {
+                    players.get_mut(entity).ok().and_then(|(_, player)| {
+                        if player.treachery_cards.len() < player.faction.hand_limit()
+                            && !info.eliminated.contains(&player.faction)
+                        {
+                            Some(i)
+                        } else {
+                            None
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            let turn_tile_pts = data
+                .ui_structure
+                .get_turn_tiles()
+                .iter()
+                .map(|tile| layout.place(tile).center())
+                .collect::<Vec<_>>();
+
+            match subphase {
+                BiddingSubPhase::DealCards => {
+                    if deck.len() < eligible.len() && !card_effects.discard.is_empty() {
+                        // The deck has run out, so the discard pile is shuffled into a new one
+                        // underneath whatever is left
+                        let mut discard = std::mem::take(&mut card_effects.discard);
+                        println!(
+                            "The treachery deck is down to {} cards, so {} discarded cards are shuffled back in",
+                            deck.len(),
+                            discard.len()
+                        );
+                        for &card in discard.iter() {
+                            commands.remove_one::<Unique>(card);
+                        }
+                        // Clients restack the deck once the host says how it shuffled it
+                        if network.network_type == NetworkType::Client {
+                            return;
+                        }
+                        discard.shuffle(&mut game_rng.rng);
+                        // Everyone saw these cards, so they go back into different slots and
+                        // clients forget them until they are dealt again
+                        let reshuffled = discard
+                            .iter()
+                            .filter_map(|&card| Some((card, secrets.get_mut(card).ok()?.slot)))
+                            .collect::<Vec<_>>();
+                        let mut slots =
+                            reshuffled.iter().map(|&(_, slot)| slot).collect::<Vec<_>>();
+                        slots.shuffle(&mut game_rng.rng);
+                        for (&(card, _), &slot) in reshuffled.iter().zip(slots.iter()) {
+                            if let Ok(mut secret) = secrets.get_mut(card) {
+                                secret.slot = slot;
+                            }
+                        }
+                        if let Some(mut server) = server.iter_mut().next() {
+                            server.broadcast(&ServerEvent::HideCards {
+                                kind: CardKind::Treachery,
+                                slots,
+                            });
+                        }
+                        let stack = discard
+                            .into_iter()
+                            .chain(deck.iter().rev().map(|&(card, _)| card))
+                            .collect::<Vec<_>>();
+                        send_deck_order(
+                            &mut server,
+                            CardKind::Treachery,
+                            stack
+                                .iter()
+                                .enumerate()
+                                .filter_map(|(i, &card)| {
+                                    secrets
+                                        .get_mut(card)
+                                        .ok()
+                                        .map(|secret| (secret.slot, i as f32))
+                                })
+                                .collect(),
+                        );
+                        let actions = stack
+                            .into_iter()
+                            .enumerate()
+                            .map(|(i, card)| {
+                                Action::deal_to(card, deck_position(CardKind::Treachery, i)).into()
+                            })
+                            .collect();
+                        queue.push_multiple(actions);
+                        return;
+                    }
+                    let n = eligible.len().min(deck.len());
+                    if n == 0 {
+                        queue.push_single(Action::AdvancePhase.into());
+                        return;
+                    }
+                    let positions = auction_positions(n as i32);
+                    auction.cards = deck[..n]
+                        .iter()
+                        .zip(positions)
+                        .map(|(&(entity, _), pos)| (entity, pos))
+                        .collect();
+                    auction.starting_bidder = eligible[0];
+                    auction.bidder = eligible[0];
+                    auction.high_bid = None;
+                    auction.passes = 0;
+                    auction.closed = false;
+                    auction.bids.clear();
+                    for &(card, _) in auction.cards.iter() {
+                        drawn.send(CardDrawn { card });
+                    }
+                    queue.push_multiple(
+                        auction
+                            .cards
+                            .iter()
+                            .enumerate()
+                            .map(|(i, &(card, pos))| {
+                                Action::add_lerp(
+                                    card,
+                                    Lerp::deal(
+                                        LerpType::world_to_ui(
+                                            (
+                                                pos,
+                                                Quat::from_rotation_x(0.5 * PI)
+                                                    * Quat::from_rotation_z(PI),
+                                                0.6,
+                                            )
+                                                .into(),
+                                        ),
+                                        0.2 * i as f32,
+                                    ),
+                                )
+                                .into()
+                            })
+                            .collect(),
+                    );
+                    info.active_player = None;
+                    queue.push_single(Action::ContextChange(Context::Bidding).into());
+                    *subphase = BiddingSubPhase::Bidding;
+                }
+                BiddingSubPhase::Bidding => {
+                    if auction.cards.is_empty() || eligible.is_empty() || auction.closed {
+                        // Everyone is full or passed, so the rest of the cards go back to the deck
+                        let top = deck
+                            .first()
+                            .map(|(_, transform)| transform.translation.y)
+                            .unwrap_or(0.0049);
+                        let actions = auction
+                            .cards
+                            .drain(..)
+                            .enumerate()
+                            .map(|(i, (card, _))| {
+                                Action::deal_to(
+                                    card,
+                                    Transform::from_translation(Vec3::new(
+                                        1.23,
+                                        top + ((i + 1) as f32 * 0.001),
+                                        -0.87,
+                                    )) * Transform::from_rotation(Quat::from_rotation_z(PI)),
+                                )
+                                .into()
+                            })
+                            .collect::<Vec<_>>();
+                        if !actions.is_empty() {
+                            queue.push_multiple(actions);
+                        }
+                        info.active_player = None;
+                        info.context = Context::None;
+                        queue.push_single(Action::AdvancePhase.into());
+                        return;
+                    }
+                    if !eligible.contains(&auction.bidder) {
+                        auction.bidder = eligible
+                            .iter()
+                            .copied()
+                            .find(|&e| e >= auction.bidder)
+                            .unwrap_or(eligible[0]);
+                    }
+                    let bidder_entity = info.play_order[auction.bidder];
+                    let bidder_faction = players.get_mut(bidder_entity).unwrap().1.faction;
+                    if info.active_player != Some(bidder_entity) {
+                        info.active_player = Some(bidder_entity);
+                        let (card, pos) = auction.cards[0];
+                        let prescient = abilities
+                            .of(
+                                bidder_faction,
+                                AbilityWindow::Bidding,
+                                &info.factions_in_play,
+                                &card_effects.karama,
+                            )
+                            .filter(|ability| ability.sees_auction())
+                            .inspect(|ability| {
+                                println!("{} uses {}", bidder_faction, ability.name())
+                            })
+                            .count()
+                            > 0;
+                        queue.push_single(auction_card_lerp(card, pos, prescient));
+                    }
+
+                    if let Some((faction, amount)) = auction.bids.pop_front() {
+                        if faction != bidder_faction {
+                            println!("It is not {}'s turn to bid!", faction);
+                            return;
+                        }
+                        let high = auction.high_bid.map(|(_, amount)| amount).unwrap_or(0);
+                        let affordable = amount.map_or(false, |amount| {
+                            players
+                                .get_mut(bidder_entity)
+                                .map_or(false, |(_, player)| player.can_afford(amount))
+                        });
+                        match amount {
+                            Some(amount) if amount > high && affordable => {
+                                auction.high_bid = Some((bidder_entity, amount));
+                                auction.passes = 0;
+                            }
+                            Some(amount) => {
+                                println!("{} cannot bid {}!", faction, amount);
+                                return;
+                            }
+                            None => {
+                                auction.passes += 1;
+                                passed.send(BidPassed { faction });
+                            }
+                        }
+                        game_actions.resolved(&info, phase, GameAction::Bid { faction, amount });
+                        auction.bidder = next_bidder(&eligible, auction.bidder);
+
+                        if let Some((winner, price)) = auction.high_bid {
+                            if auction.passes + 1 >= eligible.len() {
+                                let (card, _) = auction.cards.pop_front().unwrap();
+                                let (_, mut player) = players.get_mut(winner).unwrap();
+                                let faction = player.faction;
+                                let winner_pos =
+                                    info.play_order.iter().position(|&e| e == winner).unwrap();
+                                let mut actions = vec![Action::add_lerp(
+                                    card,
+                                    Lerp::new(
+                                        LerpType::world_to_ui(
+                                            (
+                                                turn_tile_pts[winner_pos],
+                                                Quat::from_rotation_x(0.5 * PI)
+                                                    * Quat::from_rotation_z(PI),
+                                                0.4,
+                                            )
+                                                .into(),
+                                        ),
+                                        0.6,
+                                        0.0,
+                                    ),
+                                )
+                                .then(
+                                    Action::Assign {
+                                        element: card,
+                                        faction,
+                                    }
+                                    .into(),
+                                )];
+                                player.treachery_cards.push(card);
+
+                                let bonus_cards = abilities
+                                    .of(
+                                        faction,
+                                        AbilityWindow::Bidding,
+                                        &info.factions_in_play,
+                                        &card_effects.karama,
+                                    )
+                                    .map(|ability| ability.bonus_cards())
+                                    .sum::<usize>();
+                                if bonus_cards > 0
+                                    && player.treachery_cards.len() < faction.hand_limit()
+                                {
+                                    if let Some(&(bonus, _)) = deck.first() {
+                                        player.treachery_cards.push(bonus);
+                                        actions.push(
+                                            Action::add_lerp(
+                                                bonus,
+                                                Lerp::new(
+                                                    LerpType::world_to_ui(
+                                                        (
+                                                            turn_tile_pts[winner_pos],
+                                                            Quat::from_rotation_x(0.5 * PI)
+                                                                * Quat::from_rotation_z(PI),
+                                                            0.4,
+                                                        )
+                                                            .into(),
+                                                    ),
+                                                    0.6,
+                                                    0.2,
+                                                ),
+                                            )
+                                            .then(
+                                                Action::Assign {
+                                                    element: bonus,
+                                                    faction,
+                                                }
+                                                .into(),
+                                            ),
+                                        );
+                                    }
+                                }
+
+                                let payee = abilities.payee(
+                                    AbilityWindow::Bidding,
+                                    faction,
+                                    &info.factions_in_play,
+                                    &card_effects.karama,
+                                );
+                                settle_spice(
+                                    players.iter_mut().map(|(_, player)| player),
+                                    faction,
+                                    payee,
+                                    price,
+                                );
+                                actions.extend(transfer_spice(
+                                    commands, &mut pool, &data, &spice, faction, payee, price,
+                                ));
+                                println!("{} bought a card for {} spice", faction, price);
+                                bought.send(CardBought { faction });
+                                queue.push_multiple(actions);
+
+                                auction.high_bid = None;
+                                auction.passes = 0;
+                                auction.starting_bidder =
+                                    (auction.starting_bidder + 1) % info.play_order.len();
+                                auction.bidder = auction.starting_bidder;
+                                info.active_player = None;
+                            }
+                        } else if auction.passes >= eligible.len() {
+                            println!("Everyone passed, bidding is over");
+                            auction.closed = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum MovementRequest {
+    Ship {
+        faction: Faction,
+        to: SectorNode,
+        count: i32,
+    },
+    Move {
+        faction: Faction,
+        from: SectorNode,
+        to: SectorNode,
+        count: i32,
+    },
+    Pass {
+        faction: Faction,
+    },
+}
+
+impl MovementRequest {
+    pub fn faction(&self) -> Faction {
+        match self {
+            MovementRequest::Ship { faction, .. }
+            | MovementRequest::Move { faction, .. }
+            | MovementRequest::Pass { faction } => *faction,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Movement {
+    pub requests: VecDeque<MovementRequest>,
+}
+
+fn movement_phase_system(
+    commands: &mut Commands,
+    (mut queue, mut game_actions): (ResMut<ActionQueue>, ResMut<GameActions>),
+    mut state: ResMut<GamePhase>,
+    mut info: ResMut<Info>,
+    (mut movement, mut pool): (ResMut<Movement>, ResMut<TokenPool>),
+    (data, graph, mut card_effects, abilities): (
+        Res<Data>,
+        Res<SectorGraph>,
+        ResMut<CardEffects>,
+        Res<FactionAbilities>,
+    ),
+    mut players: Query<&mut Player>,
+    (storm, mut occupancy, mut moved): (
+        Query<&Storm>,
+        ResMut<Occupancy>,
+        ResMut<Events<TroopsMoved>>,
+    ),
+    sectors: Query<(Entity, &LocationSector)>,
+    mut troops: Query<(Entity, &mut Troop, &Unique), Without<Dead>>,
+    spice: Query<(Entity, &Spice, &Unique)>,
+) {
+    let phase = state.phase;
+    if queue.is_empty() {
+        if let Phase::Movement { ref mut subphase } = state.phase {
+            let storm_sector = storm.iter().next().map(|storm| storm.sector);
+            let node_of = |entity: Entity| {
+                sectors
+                    .get(entity)
+                    .ok()
+                    .map(|(_, loc_sec)| SectorNode::new(&loc_sec.location.name, loc_sec.sector))
+            };
+            let entity_of = |node: &SectorNode| {
+                sectors
+                    .iter()
+                    .find(|(_, loc_sec)| {
+                        loc_sec.location.name == node.location && loc_sec.sector == node.sector
+                    })
+                    .map(|(entity, _)| entity)
+            };
+            match subphase {
+                MovementSubPhase::Ornithopters => {
+                    occupancy.take_ornithopters();
+                    movement.requests.clear();
+                    info.current_turn = 0;
+                    info.active_player = None;
+                    queue.push_single(
+                        Action::Enable {
+                            clickables: sectors.iter().map(|(entity, _)| entity).collect(),
+                        }
+                        .into(),
+                    );
+                    *subphase = MovementSubPhase::Ship;
+                }
+                MovementSubPhase::Ship => {
+                    let faction = players.get_mut(info.get_active_player()).unwrap().faction;
+                    // An eliminated faction has nothing left to ship or move
+                    if info.eliminated.contains(&faction) {
+                        movement
+                            .requests
+                            .retain(|request| request.faction() != faction);
+                        queue.push_single(Action::PassTurn.into());
+                        return;
+                    }
+                    info.context = Context::Shipping;
+                    if let Some(request) = movement.requests.pop_front() {
+                        if request.faction() != faction {
+                            println!("It is not {}'s turn to ship!", request.faction());
+                            return;
+                        }
+                        let action = GameAction::Movement {
+                            request: request.clone(),
+                        };
+                        match request {
+                            MovementRequest::Ship { to, count, .. } => {
+                                if count <= 0 || graph.in_storm(&to, storm_sector) {
+                                    println!("Cannot ship {} troops to {:?}!", count, to);
+                                    return;
+                                }
+                                if faction == Faction::Fremen
+                                    && !graph.sectors_of("The Great Flat").any(|node| {
+                                        graph
+                                            .distance(node, &to, storm_sector)
+                                            .map_or(false, |d| d <= 2)
+                                    })
+                                {
+                                    println!("Fremen can only send troops near The Great Flat!");
+                                    return;
+                                }
+                                let mut reserves = troops
+                                    .iter_mut()
+                                    .filter(|(_, troop, unique)| {
+                                        unique.faction == faction && troop.location.is_none()
+                                    })
+                                    .collect::<Vec<_>>();
+                                if (reserves.len() as i32) < count {
+                                    println!(
+                                        "{} does not have {} troops in reserve!",
+                                        faction, count
+                                    );
+                                    return;
+                                }
+                                if occupancy.stronghold_full(&to.location, faction) {
+                                    println!("{} is already held by two factions!", to.location);
+                                    return;
+                                }
+                                let payee = abilities.payee(
+                                    AbilityWindow::Shipping,
+                                    faction,
+                                    &info.factions_in_play,
+                                    &card_effects.karama,
+                                );
+                                let terrain = graph.terrain[&to.location];
+                                let cost = if card_effects.cancelled(faction) {
+                                    terrain.shipping_cost(count)
+                                } else {
+                                    faction.shipping_cost(terrain, count)
+                                };
+                                if let Some(destination) = entity_of(&to).filter(|_| {
+                                    settle_spice(players.iter_mut(), faction, payee, cost)
+                                }) {
+                                    let actions = transfer_spice(
+                                        commands, &mut pool, &data, &spice, faction, payee, cost,
+                                    );
+                                    for (order, (entity, troop, _)) in
+                                        reserves.iter_mut().take(count as usize).enumerate()
+                                    {
+                                        troop.location = Some(destination);
+                                        commands.insert_one(*entity, Deploying(order));
+                                    }
+                                    commands.insert_one(destination, Disorganized);
+                                    moved.send(TroopsMoved { faction });
+                                    if !actions.is_empty() {
+                                        queue.push_multiple(actions);
+                                    }
+                                    println!(
+                                        "{} shipped {} troops for {} spice",
+                                        faction, count, cost
+                                    );
+                                } else {
+                                    println!("{} cannot afford {} spice!", faction, cost);
+                                    return;
+                                }
+                            }
+                            MovementRequest::Pass { .. } => (),
+                            MovementRequest::Move { .. } => {
+                                println!("{} must ship before moving!", faction);
+                                return;
+                            }
+                        }
+                        game_actions.resolved(&info, phase, action);
+                        info.context = Context::Moving;
+                        *subphase = MovementSubPhase::Move;
+                    }
+                }
+                MovementSubPhase::Move => {
+                    info.context = Context::Moving;
+                    let faction = players.get_mut(info.get_active_player()).unwrap().faction;
+                    if let Some(request) = movement.requests.pop_front() {
+                        if request.faction() != faction {
+                            println!("It is not {}'s turn to move!", request.faction());
+                            return;
+                        }
+                        let action = GameAction::Movement {
+                            request: request.clone(),
+                        };
+                        match request {
+                            MovementRequest::Move {
+                                from, to, count, ..
+                            } => {
+                                // Karama takes the Fremen's reach away, but not ornithopters
+                                let range = if card_effects.cancelled(faction)
+                                    && !occupancy.has_ornithopters(faction)
+                                {
+                                    1
+                                } else {
+                                    occupancy.movement_range(faction)
+                                };
+                                if graph
+                                    .distance(&from, &to, storm_sector)
+                                    .map_or(true, |d| d > range)
+                                {
+                                    println!("{:?} is out of range of {:?}!", to, from);
+                                    return;
+                                }
+                                if occupancy.stronghold_full(&to.location, faction) {
+                                    println!("{} is already held by two factions!", to.location);
+                                    return;
+                                }
+                                let (source, destination) = match (entity_of(&from), entity_of(&to))
+                                {
+                                    (Some(source), Some(destination)) => (source, destination),
+                                    _ => return,
+                                };
+                                let mut group = troops
+                                    .iter_mut()
+                                    .filter(|(_, troop, unique)| {
+                                        unique.faction == faction && troop.location == Some(source)
+                                    })
+                                    .collect::<Vec<_>>();
+                                if count <= 0 || (group.len() as i32) < count {
+                                    println!("{} does not have {} troops to move!", faction, count);
+                                    return;
+                                }
+                                for (_, troop, _) in group.iter_mut().take(count as usize) {
+                                    troop.location = Some(destination);
+                                }
+                                commands.insert_one(source, Disorganized);
+                                commands.insert_one(destination, Disorganized);
+                                moved.send(TroopsMoved { faction });
+                                game_actions.resolved(&info, phase, action);
+                                // Hajr lets the same faction move another group
+                                if let Some(i) =
+                                    card_effects.extra_moves.iter().position(|&f| f == faction)
+                                {
+                                    card_effects.extra_moves.remove(i);
+                                    println!("{} may move again", faction);
+                                    return;
+                                }
+                            }
+                            MovementRequest::Pass { .. } => {
+                                game_actions.resolved(&info, phase, action)
+                            }
+                            MovementRequest::Ship { .. } => {
+                                println!("{} has already shipped!", faction);
+                                return;
+                            }
+                        }
+                        info.context = Context::None;
+                        *subphase = MovementSubPhase::Ship;
+                        queue.push_single(Action::PassTurn.into());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct RevivalRequest {
+    pub faction: Faction,
+    pub troops: i32,
+    pub leader: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Revival {
+    pub requests: VecDeque<RevivalRequest>,
+}
+
+fn revival_phase_system(
+    commands: &mut Commands,
+    (mut queue, mut info, mut revival, mut pool, mut game_actions): (
+        ResMut<ActionQueue>,
+        ResMut<Info>,
+        ResMut<Revival>,
+        ResMut<TokenPool>,
+        ResMut<GameActions>,
+    ),
+    (state, data, abilities, card_effects): (
+        Res<GamePhase>,
+        Res<Data>,
+        Res<FactionAbilities>,
+        Res<CardEffects>,
+    ),
+    mut players: Query<&mut Player>,
+    troops: Query<(Entity, &Troop, &Unique, Option<&Dead>)>,
+    leaders: Query<(Entity, &Leader, &Unique, Option<&Dead>)>,
+    spice: Query<(Entity, &Spice, &Unique)>,
+) {
+    if queue.is_empty() {
+        if let Phase::Revival = state.phase {
+            let faction = players.get_mut(info.get_active_player()).unwrap().faction;
+            let dead_troops = troops
+                .iter()
+                .filter(|(_, _, unique, dead)| unique.faction == faction && dead.is_some())
+                .map(|(entity, _, _, _)| entity)
+                .collect::<Vec<_>>();
+            let faction_leaders = leaders
+                .iter()
+                .filter(|(_, _, unique, _)| unique.faction == faction)
+                .collect::<Vec<_>>();
+            // A leader can only come back once every one of them is in the tanks, unless their
+            // faction can grow gholas
+            let gholas =
+                abilities.grows_gholas(faction, &info.factions_in_play, &card_effects.karama);
+            let leader_revivable = !faction_leaders.is_empty()
+                && if gholas {
+                    faction_leaders.iter().any(|(_, _, _, dead)| dead.is_some())
+                } else {
+                    faction_leaders.iter().all(|(_, _, _, dead)| dead.is_some())
+                };
+
+            // An eliminated faction stays in the tanks
+            if info.eliminated.contains(&faction) || (dead_troops.is_empty() && !leader_revivable) {
+                revival
+                    .requests
+                    .retain(|request| request.faction != faction);
+                info.context = Context::None;
+                queue.push_single(Action::PassTurn.into());
+                return;
+            }
+            info.context = Context::Reviving;
+
+            if let Some(request) = revival.requests.pop_front() {
+                if request.faction != faction {
+                    println!("It is not {}'s turn to revive!", request.faction);
+                    return;
+                }
+                if request.troops < 0
+                    || request.troops > faction.revival_limit()
+                    || request.troops as usize > dead_troops.len()
+                {
+                    println!("{} cannot revive {} troops!", faction, request.troops);
+                    return;
+                }
+                let leader = match request.leader {
+                    Some(ref name) => {
+                        if let Some(&(entity, leader, _, _)) = faction_leaders
+                            .iter()
+                            .find(|(_, leader, _, dead)| &leader.name == name && dead.is_some())
+                            .filter(|_| leader_revivable)
+                        {
+                            Some((entity, leader))
+                        } else {
+                            println!("{} cannot revive {}!", faction, name);
+                            return;
+                        }
+                    }
+                    None => None,
+                };
+                let cost = faction.revival_cost(request.troops)
+                    + leader.map_or(0, |(_, leader)| leader.power);
+                let payee = abilities.payee(
+                    AbilityWindow::Revival,
+                    faction,
+                    &info.factions_in_play,
+                    &card_effects.karama,
+                );
+                if !settle_spice(players.iter_mut(), faction, payee, cost) {
+                    println!("{} cannot afford {} spice!", faction, cost);
+                    return;
+                }
+                let mut actions =
+                    transfer_spice(commands, &mut pool, &data, &spice, faction, payee, cost);
+
+                let reserves = troops
+                    .iter()
+                    .filter(|(_, troop, unique, dead)| {
+                        unique.faction == faction && dead.is_none() && troop.location.is_none()
+                    })
+                    .count();
+                for (i, &entity) in dead_troops
+                    .iter()
+                    .rev()
+                    .take(request.troops as usize)
+                    .enumerate()
+                {
+                    commands.remove_one::<Dead>(entity);
+                    actions.push(
+                        Action::add_lerp(
+                            entity,
+                            Lerp::new(
+                                LerpType::world_to(Transform::from_translation(
+                                    data.token_nodes.fighters[0]
+                                        + ((reserves + i) as f32 * 0.0036 * Vec3::unit_y()),
+                                )),
+                                0.6,
+                                0.0,
+                            ),
+                        )
+                        .into(),
+                    );
+                }
+                if let Some((entity, leader)) = leader {
+                    let slot = data
+                        .leaders
+                        .iter()
+                        .filter(|l| l.faction == faction)
+                        .position(|l| l.name == leader.name)
+                        .unwrap();
+                    commands.remove_one::<Dead>(entity);
+                    actions.push(
+                        Action::add_lerp(
+                            entity,
+                            Lerp::new(
+                                LerpType::world_to(Transform::from_translation(
+                                    data.token_nodes.leaders[slot],
+                                )),
+                                0.6,
+                                0.0,
+                            ),
+                        )
+                        .into(),
+                    );
+                    println!(
+                        "{} revived {} for {} spice",
+                        faction, leader.name, leader.power
+                    );
+                }
+                println!(
+                    "{} revived {} troops for {} spice",
+                    faction,
+                    request.troops,
+                    faction.revival_cost(request.troops)
+                );
+                if !actions.is_empty() {
+                    queue.push_multiple(actions);
+                }
+                game_actions.resolved(&info, state.phase, GameAction::Revival { request });
+                info.context = Context::None;
+                queue.push_single(Action::PassTurn.into());
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct TraitorPick {
+    pub faction: Faction,
+    pub leader: String,
+}
+
+#[derive(Default)]
+pub struct TraitorPicks {
+    pub requests: VecDeque<TraitorPick>,
+}
+
+/// One half of the Bene Gesserit prediction. Clients only ever send this to the host.
+#[derive(Copy, Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum PredictionPick {
+    Faction { faction: Faction },
+    Turn { turn: i32 },
+}
 
-#[derive(Copy, Clone)]
-pub enum StormSubPhase {
-    Reveal,
-    WeatherControl,
-    FamilyAtomics,
-    MoveStorm,
+#[derive(Default)]
+pub struct PredictionPicks {
+    pub requests: VecDeque<PredictionPick>,
+}
+
+/// Sends a prediction card back to the deck face down
+pub fn return_prediction_card(
+    data: &Data,
+    card: Entity,
+    turn: bool,
+    time: f32,
+    delay: f32,
+) -> ActionChain {
+    let rotation = Quat::from_rotation_x(0.5 * PI) * Quat::from_rotation_z(PI);
+    let to: UITransform = if turn {
+        (data.prediction_nodes.src, rotation, 0.6).into()
+    } else {
+        (data.prediction_nodes.src, rotation).into()
+    };
+    Action::add_lerp(card, Lerp::new(LerpType::ui_to(to), time, delay)).into()
+}
+
+/// Records the Bene Gesserit's picks. Only the player making them gets to see which card was chosen.
+fn prediction_pick_system(
+    (mut info, mut queue, mut picks, data): (
+        ResMut<Info>,
+        ResMut<ActionQueue>,
+        ResMut<PredictionPicks>,
+        Res<Data>,
+    ),
+    (network, hot_seat, state, mut game_actions): (
+        Res<Network>,
+        Res<HotSeat>,
+        Res<GamePhase>,
+        ResMut<GameActions>,
+    ),
+    mut predictions: Query<&mut Prediction>,
+    faction_cards: Query<(Entity, &FactionPredictionCard)>,
+    turn_cards: Query<(Entity, &TurnPredictionCard)>,
+    (server, bots): (Query<&Server>, Query<&Bot>),
+) {
+    if info.context != Context::Predicting {
+        return;
+    }
+    let pick = if let Some(pick) = picks.requests.pop_front() {
+        pick
+    } else {
+        return;
+    };
+    let mut prediction = if let Some(prediction) = predictions.iter_mut().next() {
+        prediction
+    } else {
+        return;
+    };
+    // Whoever is watching the host's screen mustn't see a bot's, a remote or a concealed pick
+    let secret = bots.get(info.get_active_player()).is_ok()
+        || server.iter().next().map_or(false, |server| {
+            server.address_of(Faction::BeneGesserit).is_some()
+        })
+        || hot_seat.concealed(&network, info.get_active_player());
+    let (chosen, to, mut actions) = match pick {
+        PredictionPick::Faction { faction }
+            if prediction.faction.is_none() && info.factions_in_play.contains(&faction) =>
+        {
+            prediction.faction = Some(faction);
+            let num_factions = info.factions_in_play.len();
+            let delay = 1.5 / (2.0 * num_factions as f32);
+            let time = 1.5 - (delay * (num_factions - 1) as f32);
+            let chosen = faction_cards
+                .iter()
+                .find(|(_, card)| card.faction == faction)
+                .map(|(entity, _)| entity);
+            let actions = faction_cards
+                .iter()
+                .filter(|&(entity, _)| secret || Some(entity) != chosen)
+                .enumerate()
+                .map(|(i, (entity, _))| {
+                    return_prediction_card(&data, entity, false, time, 1.0 + delay * i as f32)
+                })
+                .collect::<Vec<_>>();
+            (
+                chosen,
+                UITransform::from((
+                    data.prediction_nodes.chosen_faction,
+                    Quat::from_rotation_x(0.5 * PI),
+                )),
+                actions,
+            )
+        }
+        PredictionPick::Turn { turn }
+            if prediction.faction.is_some()
+                && prediction.turn.is_none()
+                && (1..=LAST_TURN).contains(&turn) =>
+        {
+            prediction.turn = Some(turn);
+            let delay = 1.5 / 30.0;
+            let time = 1.5 - (delay * 14.0);
+            let chosen = turn_cards
+                .iter()
+                .find(|(_, card)| card.turn == turn)
+                .map(|(entity, _)| entity);
+            let actions = turn_cards
+                .iter()
+                .filter(|&(entity, _)| secret || Some(entity) != chosen)
+                .enumerate()
+                .map(|(i, (entity, _))| {
+                    return_prediction_card(&data, entity, true, time, 1.0 + delay * i as f32)
+                })
+                .collect::<Vec<_>>();
+            (
+                chosen,
+                UITransform::from((
+                    data.prediction_nodes.chosen_turn,
+                    Quat::from_rotation_x(0.5 * PI),
+                )),
+                actions,
+            )
+        }
+        pick => {
+            println!("Ignoring prediction pick {:?}", pick);
+            return;
+        }
+    };
+    game_actions.resolved(&info, state.phase, GameAction::Prediction { pick });
+    if let Some(chosen) = chosen.filter(|_| !secret) {
+        actions.push(Action::add_lerp(chosen, Lerp::new(LerpType::ui_to(to), 1.0, 0.0)).into());
+    }
+    queue.push_multiple_front(actions);
+    info.context = Context::None;
+}
+
+/// What a faction commits to a battle in one territory
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct BattlePlan {
+    pub faction: Faction,
+    pub location: String,
+    pub leader: Option<String>,
+    pub troops: i32,
+    /// Spice paid to have the troops fight at full strength under the advanced rules
+    pub spice: i32,
+    pub weapon: Option<String>,
+    pub defense: Option<String>,
+}
+
+impl BattlePlan {
+    /// What the troops and leader add up to before weapons and defenses are resolved.
+    /// `leader_power` is the committed leader's printed strength.
+    pub fn strength(
+        &self,
+        rules: RuleSet,
+        leader_power: i32,
+        abilities: &FactionAbilities,
+        in_play: &[Faction],
+        cancelled: &[Faction],
+    ) -> f32 {
+        let troops = if rules.spice_dialing()
+            && !abilities.fights_unspiced(self.faction, in_play, cancelled)
+        {
+            // Troops nobody paid for only count for half
+            let spiced = self.troops.min(self.spice);
+            spiced as f32 + (self.troops - spiced) as f32 * 0.5
+        } else {
+            self.troops as f32
+        };
+        let leader = if rules.karama_leaders() && cancelled.contains(&self.faction) {
+            0
+        } else {
+            leader_power
+        };
+        troops + leader as f32
+    }
+}
+
+#[derive(Default)]
+pub struct Battle {
+    pub plans: VecDeque<BattlePlan>,
+}
+
+/// How strong a plan is with its leader looked up and the faction's powers applied
+fn plan_strength(
+    plan: &BattlePlan,
+    (rules, data): (RuleSet, &Data),
+    (info, card_effects, abilities): (&Info, &CardEffects, &FactionAbilities),
+) -> f32 {
+    let leader_power = plan
+        .leader
+        .as_ref()
+        .and_then(|name| data.leaders.iter().find(|leader| leader.name == *name))
+        .map_or(0, |leader| leader.power);
+    plan.strength(
+        rules,
+        leader_power,
+        abilities,
+        &info.factions_in_play,
+        &card_effects.karama,
+    )
+}
+
+/// Announces how strong each battle plan is as it comes in
+fn battle_strength_system(
+    (battle, rules, data): (Res<Battle>, Res<RuleSet>, Res<Data>),
+    (info, card_effects, abilities): (Res<Info>, Res<CardEffects>, Res<FactionAbilities>),
+    mut announced: Local<usize>,
+) {
+    if battle.plans.len() < *announced {
+        *announced = 0;
+    }
+    for plan in battle.plans.iter().skip(*announced) {
+        println!(
+            "{} brings a strength of {} to {}",
+            plan.faction,
+            plan_strength(plan, (*rules, &data), (&info, &card_effects, &abilities)),
+            plan.location
+        );
+    }
+    *announced = battle.plans.len();
+}
+
+/// Settles each battle as soon as its plans are revealed. Clients get the revealed plans too, so
+/// they hear about it at the same time as the host.
+fn battle_resolution_system(
+    (wheels, rules, data): (Res<BattleWheels>, Res<RuleSet>, Res<Data>),
+    (info, card_effects, abilities): (Res<Info>, Res<CardEffects>, Res<FactionAbilities>),
+    mut resolved: Local<Vec<String>>,
+    mut events: ResMut<Events<BattleResolved>>,
+    players: Query<&Player>,
+) {
+    resolved.retain(|location| {
+        wheels
+            .revealed
+            .iter()
+            .any(|plan| plan.location == *location)
+    });
+    let storm_order = info
+        .play_order
+        .iter()
+        .filter_map(|&entity| players.get(entity).ok())
+        .map(|player| player.faction)
+        .collect::<Vec<_>>();
+    for plan in wheels.revealed.iter() {
+        if resolved.contains(&plan.location) {
+            continue;
+        }
+        let mut strengths = wheels
+            .revealed
+            .iter()
+            .filter(|other| other.location == plan.location)
+            .map(|other| {
+                (
+                    other.faction,
+                    plan_strength(other, (*rules, &data), (&info, &card_effects, &abilities)),
+                )
+            })
+            .collect::<Vec<_>>();
+        // Ties go to whoever is first in storm order, and `max_by` settles them on the last
+        strengths.sort_by_key(|(faction, _)| storm_order.iter().position(|f| f == faction));
+        let winner = strengths
+            .iter()
+            .rev()
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .map(|&(faction, _)| faction);
+        if let Some(winner) = winner {
+            println!("{} wins the battle for {}", winner, plan.location);
+            events.send(BattleResolved {
+                location: plan.location.clone(),
+                winner,
+            });
+        }
+        resolved.push(plan.location.clone());
+    }
+}
+
+/// A leader sent into battle against whoever holds their traitor card is exposed to everyone
+fn traitor_reveal_system(
+    state: Res<GamePhase>,
+    battle: Res<Battle>,
+    players: Query<&Player>,
+    mut traitor_cards: Query<(&TraitorCard, &mut Unique)>,
+) {
+    if state.phase != Phase::Battle {
+        return;
+    }
+    for plan in battle.plans.iter() {
+        let leader = if let Some(ref leader) = plan.leader {
+            leader
+        } else {
+            continue;
+        };
+        for opponent in battle
+            .plans
+            .iter()
+            .filter(|other| other.location == plan.location && other.faction != plan.faction)
+        {
+            if let Some(player) = players
+                .iter()
+                .find(|player| player.faction == opponent.faction)
+            {
+                for &card in player.traitor_cards.iter() {
+                    if let Ok((traitor, mut unique)) = traitor_cards.get_mut(card) {
+                        if traitor.leader.name == *leader && !unique.public {
+                            unique.public = true;
+                            println!("{} is a traitor to {}!", leader, opponent.faction);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Phase {
+    /// The phases of a turn in the order they are played, as shown on the phase track
+    /// Text keys for the name of each phase on the track
+    pub const TRACK: [&'static str; 9] = [
+        "phase.storm",
+        "phase.spice_blow",
+        "phase.charity",
+        "phase.bidding",
+        "phase.revival",
+        "phase.movement",
+        "phase.battle",
+        "phase.collection",
+        "phase.control",
+    ];
 }
 
 pub struct GamePhase {
@@ -1206,9 +3529,28 @@ impl Default for GamePhase {
     }
 }
 
-fn reset(mut phase: ResMut<GamePhase>, mut queue: ResMut<ActionQueue>) {
+fn reset(
+    mut phase: ResMut<GamePhase>,
+    mut queue: ResMut<ActionQueue>,
+    mut auction: ResMut<Auction>,
+    mut movement: ResMut<Movement>,
+    mut spice_deck: ResMut<SpiceDeck>,
+    (mut revival, mut battle, mut traitor_picks, mut prediction_picks): (
+        ResMut<Revival>,
+        ResMut<Battle>,
+        ResMut<TraitorPicks>,
+        ResMut<PredictionPicks>,
+    ),
+) {
     phase.phase = Phase::Setup {
         subphase: SetupSubPhase::ChooseFactions,
     };
     queue.clear();
+    *auction = Auction::default();
+    *movement = Movement::default();
+    *spice_deck = SpiceDeck::default();
+    *revival = Revival::default();
+    *battle = Battle::default();
+    *traitor_picks = TraitorPicks::default();
+    *prediction_picks = PredictionPicks::default();
 }
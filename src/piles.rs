@@ -0,0 +1,403 @@
+use std::{collections::HashMap, f32::consts::PI};
+
+use bevy::{
+    prelude::*,
+    render::camera::{Camera, OrthographicProjection},
+};
+
+use crate::{
+    audio::SoundEffect,
+    components::{Collider, Dead, Disorganized, Spice, SpiceNode, Troop, Unique},
+    data::Faction,
+    lerper::{Lerp, LerpType},
+    settings::Settings,
+    token_pool::TokenPool,
+    util::{grab, world_to_screen},
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// How far an expanded pile spreads its tokens from the middle
+const FAN_RADIUS: f32 = 0.025;
+const SPICE_HEIGHT: f32 = 0.0036;
+/// Lifts the count badge just above the top of its pile
+const BADGE_OFFSET: f32 = 6.0;
+/// Roughly how wide each digit of a badge is, to center it over its pile
+const BADGE_DIGIT_WIDTH: f32 = 8.0;
+
+/// Tokens that share a territory, keyed by where they are and whose they are. Spice belongs to
+/// nobody until it is collected.
+type PileKey = (Entity, Option<Faction>);
+
+type PileToken<'a> = (Option<&'a Troop>, Option<&'a Spice>, Option<&'a Unique>);
+
+pub struct PilesPlugin;
+
+impl Plugin for PilesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Piles>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                pile_tracking_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                stack_spice_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                pile_expand_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                pile_badge_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                strength_marker_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system());
+    }
+}
+
+#[derive(Default)]
+pub struct Piles {
+    /// Where every token on the board was last seen, so a pile can tidy up after tokens leave it
+    locations: HashMap<Entity, Entity>,
+    badges: HashMap<PileKey, Entity>,
+    pressed: Option<PileKey>,
+    pub expanded: Option<PileKey>,
+    /// The marker standing in for each collapsed stack of troops, and where it was put
+    markers: HashMap<PileKey, (Entity, Vec3)>,
+}
+
+/// A troop hidden behind its stack's strength marker. It keeps its place in the stack and all of
+/// its components, so the rules still count it like any other.
+pub struct Collapsed;
+
+struct StrengthMarker {
+    key: PileKey,
+}
+
+fn pile_key((troop, spice, unique): PileToken) -> Option<PileKey> {
+    match (troop, spice) {
+        (Some(troop), _) => troop
+            .location
+            .map(|location| (location, unique.map(|unique| unique.faction))),
+        (_, Some(spice)) => spice.location.map(|location| (location, None)),
+        _ => None,
+    }
+}
+
+/// Marks a territory as needing its piles re-laid whenever a token arrives or leaves, however it
+/// got there
+fn pile_tracking_system(
+    commands: &mut Commands,
+    mut piles: ResMut<Piles>,
+    mut sound_effects: ResMut<Events<SoundEffect>>,
+    troops: Query<(Entity, &Troop), Without<Dead>>,
+    spice: Query<(Entity, &Spice)>,
+) {
+    let current = troops
+        .iter()
+        .filter_map(|(entity, troop)| troop.location.map(|location| (entity, location)))
+        .chain(
+            spice
+                .iter()
+                .filter_map(|(entity, spice)| spice.location.map(|location| (entity, location))),
+        )
+        .collect::<HashMap<_, _>>();
+    let mut placed = false;
+    for (entity, &location) in current.iter() {
+        if piles.locations.get(entity) != Some(&location) {
+            commands.insert_one(location, Disorganized);
+            placed |= troops.get(*entity).is_ok();
+        }
+    }
+    if placed {
+        sound_effects.send(SoundEffect::TokenPlaced);
+    }
+    for (entity, &location) in piles.locations.iter() {
+        if current.get(entity) != Some(&location) {
+            commands.insert_one(location, Disorganized);
+        }
+    }
+    if piles.locations != current {
+        piles.locations = current;
+        piles.expanded = None;
+    }
+}
+
+/// Stacks the spice on a territory into a single pile, biggest tokens at the bottom
+fn stack_spice_system(
+    commands: &mut Commands,
+    spice: Query<(Entity, &Spice)>,
+    locations: Query<(Entity, &SpiceNode), With<Disorganized>>,
+) {
+    for (loc_entity, node) in locations.iter() {
+        let mut tokens = spice
+            .iter()
+            .filter(|(_, spice)| spice.location == Some(loc_entity))
+            .map(|(entity, spice)| (entity, spice.value))
+            .collect::<Vec<_>>();
+        tokens.sort_by(|(entity1, value1), (entity2, value2)| {
+            value2.cmp(value1).then(entity1.id().cmp(&entity2.id()))
+        });
+        for (i, (entity, _)) in tokens.into_iter().enumerate() {
+            commands.insert_one(
+                entity,
+                Lerp::new(
+                    LerpType::world_to(Transform::from_translation(
+                        Vec3::new(node.pos.x, node.pos.z, -node.pos.y)
+                            + i as f32 * SPICE_HEIGHT * Vec3::unit_y(),
+                    )),
+                    0.1,
+                    0.0,
+                ),
+            );
+        }
+        commands.remove_one::<Disorganized>(loc_entity);
+    }
+}
+
+/// Clicking a pile fans its tokens out so each one can be picked out on its own. Clicking it again,
+/// or anywhere else, stacks it back up.
+fn pile_expand_system(
+    commands: &mut Commands,
+    (windows, mouse_input): (Res<Windows>, Res<Input<MouseButton>>),
+    mut piles: ResMut<Piles>,
+    cameras: Query<(&Camera, &Transform), Without<OrthographicProjection>>,
+    tokens: Query<(Entity, &Collider, &Transform, PileToken)>,
+    markers: Query<(Entity, &Collider, &Transform, &StrengthMarker)>,
+) {
+    let pile_under_cursor = || {
+        grab(
+            &windows,
+            &cameras,
+            tokens
+                .iter()
+                .filter(|&(_, _, _, token)| pile_key(token).is_some())
+                .map(|(entity, collider, transform, _)| (entity, collider, *transform))
+                .chain(
+                    markers
+                        .iter()
+                        .map(|(entity, collider, transform, _)| (entity, collider, *transform)),
+                ),
+        )
+        .and_then(|entity| match markers.get(entity) {
+            Ok((_, _, _, marker)) => Some(marker.key),
+            Err(_) => tokens
+                .get(entity)
+                .ok()
+                .and_then(|(_, _, _, token)| pile_key(token)),
+        })
+    };
+
+    if mouse_input.just_pressed(MouseButton::Left) {
+        piles.pressed = pile_under_cursor();
+    } else if mouse_input.just_released(MouseButton::Left) {
+        let released = pile_under_cursor();
+        let pressed = piles.pressed.take();
+        let collapse = piles.expanded.take();
+        if let Some((location, _)) = collapse {
+            commands.insert_one(location, Disorganized);
+        }
+        if let Some(key) = released.filter(|&key| Some(key) == pressed && Some(key) != collapse) {
+            let mut members = tokens
+                .iter()
+                .filter(|&(_, _, _, token)| pile_key(token) == Some(key))
+                .map(|(entity, _, transform, ..)| (entity, *transform))
+                .collect::<Vec<_>>();
+            if members.len() > 1 {
+                members.sort_by(|(_, transform1), (_, transform2)| {
+                    transform1
+                        .translation
+                        .y
+                        .partial_cmp(&transform2.translation.y)
+                        .unwrap()
+                });
+                let base = members[0].1.translation;
+                let count = members.len();
+                for (i, (entity, transform)) in members.into_iter().enumerate() {
+                    let angle = 2.0 * PI * i as f32 / count as f32;
+                    commands.insert_one(
+                        entity,
+                        Lerp::new(
+                            LerpType::world_to(
+                                Transform::from_translation(
+                                    base + FAN_RADIUS * Vec3::new(angle.cos(), 0.0, angle.sin()),
+                                ) * Transform::from_rotation(transform.rotation),
+                            ),
+                            0.15,
+                            0.0,
+                        ),
+                    );
+                }
+                piles.expanded = Some(key);
+            }
+        }
+    }
+}
+
+/// Keeps a count over every faction's troops and a total over the spice in every territory, facing
+/// the camera however the board is turned
+fn pile_badge_system(
+    commands: &mut Commands,
+    (asset_server, windows): (Res<AssetServer>, Res<Windows>),
+    mut piles: ResMut<Piles>,
+    cameras: Query<(&Camera, &Transform), Without<OrthographicProjection>>,
+    tokens: Query<(&Transform, PileToken)>,
+    mut badges: Query<(&mut Text, &mut Style)>,
+) {
+    let mut counts: HashMap<PileKey, (i32, Vec3)> = HashMap::new();
+    for (transform, token) in tokens.iter() {
+        if let Some(key) = pile_key(token) {
+            let count = counts.entry(key).or_insert((0, transform.translation));
+            count.0 += token.1.map_or(1, |spice| spice.value);
+            if transform.translation.y > count.1.y {
+                count.1 = transform.translation;
+            }
+        }
+    }
+    let stale = piles
+        .badges
+        .keys()
+        .filter(|key| !counts.contains_key(key))
+        .copied()
+        .collect::<Vec<_>>();
+    for key in stale {
+        if let Some(badge) = piles.badges.remove(&key) {
+            commands.despawn(badge);
+        }
+    }
+
+    let view = cameras.iter().next().zip(windows.get_primary());
+    for (key, (count, top)) in counts {
+        let screen = view.and_then(|((camera, cam_transform), window)| {
+            world_to_screen(top, window, camera, cam_transform)
+        });
+        let value = count.to_string();
+        match piles.badges.get(&key).map(|&badge| badges.get_mut(badge)) {
+            Some(Ok((mut text, mut style))) => {
+                if text.value != value {
+                    text.value = value;
+                }
+                if let Some(screen) = screen {
+                    style.position = Rect {
+                        left: Val::Px(screen.x - 0.5 * BADGE_DIGIT_WIDTH * text.value.len() as f32),
+                        bottom: Val::Px(screen.y + BADGE_OFFSET),
+                        ..Default::default()
+                    };
+                }
+            }
+            // Spawned last frame and not queryable yet
+            Some(Err(_)) => (),
+            None => {
+                let badge = commands
+                    .spawn(TextBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            ..Default::default()
+                        },
+                        text: Text {
+                            font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                            value,
+                            style: TextStyle {
+                                font_size: 14.0,
+                                color: if key.1.is_none() {
+                                    Color::ORANGE
+                                } else {
+                                    Color::ANTIQUE_WHITE
+                                },
+                                ..Default::default()
+                            },
+                        },
+                        ..Default::default()
+                    })
+                    .with(ScreenEntity)
+                    .current_entity()
+                    .unwrap();
+                piles.badges.insert(key, badge);
+            }
+        }
+    }
+}
+
+/// Swaps every stack of more troops than the setting allows for a single big token, with the
+/// badge over it giving its strength. Expanding the stack, or it shrinking back under the limit,
+/// brings the troops back out.
+fn strength_marker_system(
+    commands: &mut Commands,
+    (settings, pool): (Res<Settings>, Res<TokenPool>),
+    mut piles: ResMut<Piles>,
+    troops: Query<(Entity, &Transform, &Troop, &Unique, Option<&Collapsed>), Without<Dead>>,
+    dead: Query<Entity, (With<Dead>, With<Collapsed>)>,
+) {
+    let threshold = settings.graphics.troop_markers as usize;
+    let mut stacks: HashMap<PileKey, Vec<Vec3>> = HashMap::new();
+    for (_, transform, troop, unique, _) in troops.iter() {
+        if let Some(key) = pile_key((Some(troop), None, Some(unique))) {
+            stacks.entry(key).or_default().push(transform.translation);
+        }
+    }
+    stacks.retain(|&key, members| {
+        threshold > 0 && members.len() > threshold && piles.expanded != Some(key)
+    });
+
+    let stale = piles
+        .markers
+        .keys()
+        .filter(|key| !stacks.contains_key(key))
+        .copied()
+        .collect::<Vec<_>>();
+    for key in stale {
+        if let Some((marker, _)) = piles.markers.remove(&key) {
+            commands.despawn_recursive(marker);
+        }
+    }
+    for (&key, members) in stacks.iter() {
+        // Stands where the bottom of the stack is, following it if the stack is laid out again
+        let base = members
+            .iter()
+            .copied()
+            .min_by(|pos1, pos2| pos1.y.partial_cmp(&pos2.y).unwrap())
+            .unwrap();
+        match piles.markers.get_mut(&key) {
+            Some((marker, pos)) => {
+                if *pos != base {
+                    *pos = base;
+                    commands.insert_one(*marker, Transform::from_translation(base));
+                }
+            }
+            None => {
+                let faction = key.1.unwrap();
+                let marker =
+                    pool.spawn_troop_marker(commands, faction, Transform::from_translation(base));
+                commands.insert_one(marker, StrengthMarker { key });
+                piles.markers.insert(key, (marker, base));
+            }
+        }
+    }
+
+    // Visibility follows on the next pass of the active player system
+    for (entity, _, troop, unique, collapsed) in troops.iter() {
+        let collapse = pile_key((Some(troop), None, Some(unique)))
+            .map_or(false, |key| stacks.contains_key(&key));
+        if collapse && collapsed.is_none() {
+            commands.insert_one(entity, Collapsed);
+        } else if !collapse && collapsed.is_some() {
+            commands.remove_one::<Collapsed>(entity);
+        }
+    }
+    for entity in dead.iter() {
+        commands.remove_one::<Collapsed>(entity);
+    }
+}
+
+fn reset(mut piles: ResMut<Piles>) {
+    *piles = Piles::default();
+}
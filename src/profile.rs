@@ -0,0 +1,383 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::Faction,
+    locale::Tr,
+    menu::{ButtonMaterials, Lobby},
+    network::{player_name, Client, Network, NetworkType},
+    settings::{config_dir, Settings},
+    tear_down,
+    util::unix_time,
+    victory::GameResult,
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+const PROFILE_FILE: &str = "profile.ron";
+/// Most past games kept on disk
+const HISTORY_LENGTH: usize = 100;
+/// Most past games listed on the profile screen
+const HISTORY_SHOWN: usize = 10;
+/// Where every new player's rating starts
+const START_RATING: f32 = 1500.0;
+/// Most a rating can move in one game
+const RATING_K: f32 = 32.0;
+
+pub struct ProfilePlugin;
+
+impl Plugin for ProfilePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_resource(Profile::load())
+            .on_state_enter(RESPONSE_STAGE, Screen::GameOver, record_result.system())
+            .on_state_enter(
+                RESPONSE_STAGE,
+                Screen::Profile,
+                init_profile_screen.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::Profile, tear_down.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::Profile,
+                profile_button_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::Profile,
+                profile_text_system.system(),
+            );
+    }
+}
+
+#[derive(Copy, Clone, Default, Serialize, Deserialize)]
+pub struct Record {
+    pub wins: u32,
+    pub losses: u32,
+}
+
+/// One finished game, as we played it
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Match {
+    /// Seconds since the Unix epoch when the game ended
+    pub finished: u64,
+    pub faction: Faction,
+    pub won: bool,
+    pub winners: Vec<Faction>,
+    pub turn: i32,
+    /// How far the game moved our rating, zero when nobody else in it was rated
+    #[serde(default)]
+    pub rating_change: f32,
+}
+
+/// What this player has done over every game played here, kept next to the settings
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub preferred_faction: Option<Faction>,
+    pub records: HashMap<Faction, Record>,
+    /// Newest last
+    pub history: Vec<Match>,
+    /// Elo rating from every online game against other rated players
+    pub rating: f32,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            preferred_faction: None,
+            records: HashMap::new(),
+            history: Vec::new(),
+            rating: START_RATING,
+        }
+    }
+}
+
+impl Profile {
+    /// Starts a fresh profile when there is none yet or it can't be read
+    pub fn load() -> Self {
+        let path = profile_path();
+        match fs::File::open(&path) {
+            Ok(file) => ron::de::from_reader(file).unwrap_or_else(|err| {
+                println!("Ignoring unreadable profile in {}: {}", path.display(), err);
+                Profile::default()
+            }),
+            Err(_) => Profile::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        // Kept for as long as the page is open in a browser, like the settings
+        if cfg!(target_arch = "wasm32") {
+            return;
+        }
+        let path = profile_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).expect("Failed to create profile directory!");
+        }
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .expect("Failed to serialize profile!");
+        fs::write(&path, text).expect("Failed to write profile file!");
+    }
+
+    /// The rating as shared with other players, if we share it at all
+    pub fn shared_rating(&self, settings: &Settings) -> Option<u32> {
+        if settings.online.share_rating {
+            Some(self.rating.round().max(0.0) as u32)
+        } else {
+            None
+        }
+    }
+
+    /// Counts the game as won or lost with the faction, and rates it against every opponent that
+    /// shared a rating
+    pub fn record(&mut self, faction: Faction, result: &GameResult, opponents: &[(Faction, u32)]) {
+        let won = result.winners.contains(&faction);
+        let rating_change = self.rate(won, result, opponents);
+        self.rating += rating_change;
+        let record = self.records.entry(faction).or_default();
+        if won {
+            record.wins += 1;
+        } else {
+            record.losses += 1;
+        }
+        self.history.push(Match {
+            finished: unix_time(),
+            faction,
+            won,
+            winners: result.winners.clone(),
+            turn: result.turn,
+            rating_change,
+        });
+        let excess = self.history.len().saturating_sub(HISTORY_LENGTH);
+        self.history.drain(..excess);
+    }
+
+    /// Multi-player Elo: the game counts as a win against every opponent that lost, a loss against
+    /// every opponent that won and a draw against the rest, with the change averaged over them all
+    fn rate(&self, won: bool, result: &GameResult, opponents: &[(Faction, u32)]) -> f32 {
+        if opponents.is_empty() {
+            return 0.0;
+        }
+        let surprise = opponents
+            .iter()
+            .map(|&(other, rating)| {
+                let score = match (won, result.winners.contains(&other)) {
+                    (true, false) => 1.0,
+                    (false, true) => 0.0,
+                    _ => 0.5,
+                };
+                let expected = 1.0 / (1.0 + 10f32.powf((rating as f32 - self.rating) / 400.0));
+                score - expected
+            })
+            .sum::<f32>();
+        RATING_K * surprise / opponents.len() as f32
+    }
+}
+
+fn profile_path() -> PathBuf {
+    config_dir().join(PROFILE_FILE)
+}
+
+/// Counts the game towards the faction we played. Hot seat games have no single player to credit.
+fn record_result(
+    (mut profile, result): (ResMut<Profile>, Res<GameResult>),
+    (network, lobby, settings): (Res<Network>, Res<Lobby>, Res<Settings>),
+    client: Query<&Client>,
+) {
+    let faction = match network.network_type {
+        NetworkType::Server => lobby.host().and_then(|seat| seat.faction),
+        NetworkType::Client => client.iter().next().and_then(|client| client.faction),
+        NetworkType::None | NetworkType::Local => None,
+    };
+    if let Some(faction) = faction {
+        // Only the other seats that shared a rating; bots and hidden ratings don't count
+        let opponents = lobby
+            .seats
+            .iter()
+            .filter(|_| settings.online.share_rating)
+            .filter_map(|seat| match (seat.faction, seat.rating) {
+                (Some(other), Some(rating)) if other != faction => Some((other, rating)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        profile.record(faction, &result, &opponents);
+        profile.save();
+    }
+}
+
+enum ProfileButton {
+    PreferredFaction,
+    ShareRating,
+    Back,
+}
+
+struct ProfileText;
+
+fn init_profile_screen(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    button_materials: Res<ButtonMaterials>,
+    tr: Res<Tr>,
+) {
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let text = |value: String, font_size: f32| TextBundle {
+        text: Text {
+            font: font.clone(),
+            value,
+            style: TextStyle {
+                font_size,
+                color: Color::ANTIQUE_WHITE,
+                ..Default::default()
+            },
+        },
+        ..Default::default()
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with_children(|parent| {
+            parent.spawn(text(String::new(), 18.0)).with(ProfileText);
+            for (action, label) in [
+                (ProfileButton::PreferredFaction, tr.get("profile.preferred")),
+                (ProfileButton::ShareRating, tr.get("profile.share_rating")),
+                (ProfileButton::Back, tr.get("menu.back")),
+            ] {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(280.0), Val::Px(32.0)),
+                            margin: Rect::all(Val::Px(8.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        material: button_materials.normal.clone(),
+                        ..Default::default()
+                    })
+                    .with(action)
+                    .with_children(|parent| {
+                        parent.spawn(text(label, 20.0));
+                    });
+            }
+        });
+}
+
+fn profile_button_system(
+    (mut state, mut profile, mut settings, button_materials): (
+        ResMut<State<Screen>>,
+        ResMut<Profile>,
+        ResMut<Settings>,
+        Res<ButtonMaterials>,
+    ),
+    mut interactions: Query<
+        (&Interaction, &mut Handle<ColorMaterial>, &ProfileButton),
+        Mutated<Interaction>,
+    >,
+) {
+    for (&interaction, mut material, action) in interactions.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {
+                *material = button_materials.pressed.clone();
+                match action {
+                    // Steps through every faction, then back to having no preference
+                    ProfileButton::PreferredFaction => {
+                        let next = match profile.preferred_faction {
+                            None => Faction::ALL.first(),
+                            Some(faction) => Faction::ALL.get(faction.index() + 1),
+                        };
+                        profile.preferred_faction = next.copied();
+                        profile.save();
+                    }
+                    ProfileButton::ShareRating => {
+                        settings.online.share_rating = !settings.online.share_rating;
+                        settings.save();
+                    }
+                    ProfileButton::Back => {
+                        state.set_next(Screen::MainMenu).unwrap();
+                    }
+                }
+            }
+            Interaction::Hovered => *material = button_materials.hovered.clone(),
+            Interaction::None => *material = button_materials.normal.clone(),
+        }
+    }
+}
+
+/// The name, the preferred faction, how each faction has gone and the latest games
+fn profile_text_system(
+    (profile, settings, tr): (Res<Profile>, Res<Settings>, Res<Tr>),
+    mut texts: Query<&mut Text, With<ProfileText>>,
+) {
+    let mut lines = vec![
+        tr.fmt("profile.name", &[&player_name(&settings)]),
+        tr.fmt(
+            "profile.preferred_faction",
+            &[&profile
+                .preferred_faction
+                .map(|faction| tr.faction(faction))
+                .unwrap_or_else(|| tr.get("profile.no_preference"))],
+        ),
+        tr.fmt(
+            if settings.online.share_rating {
+                "profile.rating_shared"
+            } else {
+                "profile.rating_hidden"
+            },
+            &[&profile.rating.round()],
+        ),
+        String::new(),
+        tr.get("profile.records"),
+    ];
+    let mut played = profile
+        .records
+        .iter()
+        .map(|(&faction, &record)| (faction, record))
+        .collect::<Vec<_>>();
+    played.sort_by_key(|(faction, _)| faction.index());
+    if played.is_empty() {
+        lines.push(tr.get("profile.no_games"));
+    }
+    lines.extend(played.into_iter().map(|(faction, record)| {
+        tr.fmt(
+            "profile.record",
+            &[&tr.faction(faction), &record.wins, &record.losses],
+        )
+    }));
+    if !profile.history.is_empty() {
+        lines.push(String::new());
+        lines.push(tr.get("profile.history"));
+    }
+    lines.extend(
+        profile
+            .history
+            .iter()
+            .rev()
+            .take(HISTORY_SHOWN)
+            .map(|game| {
+                tr.fmt(
+                    if game.won {
+                        "profile.won"
+                    } else {
+                        "profile.lost"
+                    },
+                    &[&tr.faction(game.faction), &game.turn],
+                )
+            }),
+    );
+    let value = lines.join("\n");
+    for mut text in texts.iter_mut() {
+        if text.value != value {
+            text.value = value.clone();
+        }
+    }
+}
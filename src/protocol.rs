@@ -0,0 +1,316 @@
+use std::io::Cursor;
+
+use bytecheck::CheckBytes;
+use rkyv::{check_archive, Archive, ArchiveWriter, Seek, Unarchive, Write};
+
+use crate::{
+    alliance::Alliance,
+    asset_pack::AssetFolder,
+    capture::CapturedLeader,
+    data::Faction,
+    decks::DeckPreset,
+    delta::StateChange,
+    desync::StateSection,
+    emote::Signal,
+    game_action::GameAction,
+    house_rules::HouseRulesScript,
+    interrupt::InterruptWindow,
+    menu::LobbySeat,
+    migration::{ReplicaSession, SecretClaim, SecretSeal},
+    network::{Channel, Latency},
+    notification::Notice,
+    phase::{BattlePlan, TroopsDeployed},
+    resources::RuleSet,
+    savegame::CardKind,
+    storm_dial::DialPick,
+    timer::TimeBank,
+    wheel::LockedPlan,
+};
+
+/// Everything a client can ask of the host
+#[derive(Archive, Unarchive, PartialEq, Clone, Debug)]
+#[archive(derive(CheckBytes))]
+pub enum ClientRequest {
+    Action {
+        action: GameAction,
+    },
+    /// Sent on reconnecting, with proof of the secrets we were dealt in case the host has changed
+    ResyncRequest {
+        token: u64,
+        claims: Vec<SecretClaim>,
+    },
+    /// Who a client wants to be known as, and the password, sent as soon as the server answers
+    /// along with what its assets look like
+    JoinRequest {
+        name: String,
+        password: String,
+        rating: Option<u32>,
+        assets: Vec<AssetFolder>,
+    },
+    Chat {
+        from: Option<Faction>,
+        text: String,
+    },
+    SelectFaction {
+        faction: Option<Faction>,
+    },
+    Ready {
+        ready: bool,
+    },
+    /// Where the player is pointing on the board, sent unreliably
+    Cursor {
+        from: Faction,
+        x: f32,
+        z: f32,
+    },
+    /// A ping or emote for everyone to see
+    Signal {
+        signal: Signal,
+    },
+    /// The latest state version the client has applied
+    StateAck {
+        version: u32,
+    },
+    /// The player quit from the pause menu, and whether they conceded on the way out
+    PlayerLeft {
+        faction: Faction,
+        conceded: bool,
+    },
+}
+
+impl ClientRequest {
+    /// Cursors move all the time, so a lost one is soon made up for
+    pub fn channel(&self) -> Channel {
+        match self {
+            ClientRequest::Cursor { .. } => Channel::Unreliable,
+            _ => Channel::Reliable,
+        }
+    }
+}
+
+/// A request numbered by the client that sent it, so anything the host says about it later can
+/// be matched up with it
+#[derive(Archive, Unarchive, PartialEq, Clone, Debug)]
+#[archive(derive(CheckBytes))]
+pub struct ClientMessage {
+    pub id: u32,
+    pub request: ClientRequest,
+}
+
+/// Everything the host tells its clients
+#[derive(Archive, Unarchive, PartialEq, Clone, Debug)]
+#[archive(derive(CheckBytes))]
+pub enum ServerEvent {
+    Load,
+    ServerInfo {
+        players: Vec<String>,
+        rules: RuleSet,
+    },
+    /// Why the server turned down the action in the client's request `request`
+    ActionRejected {
+        request: u32,
+        reason: String,
+    },
+    /// The interrupt window the host has open, or none once it closes
+    Interrupt {
+        window: Option<InterruptWindow>,
+    },
+    /// Why the server won't let the client in
+    JoinDenied {
+        reason: String,
+    },
+    /// The asset folders that don't match the host's, sent on joining when there are any
+    AssetMismatch {
+        folders: Vec<String>,
+    },
+    /// The cards the host picked, sent before the game starts
+    DeckPreset {
+        preset: DeckPreset,
+    },
+    /// The house rules the host picked, if any, sent before the game starts
+    HouseRules {
+        script: Option<HouseRulesScript>,
+    },
+    Chat {
+        from: Option<Faction>,
+        text: String,
+    },
+    LobbyState {
+        seats: Vec<LobbySeat>,
+        player_count: u32,
+        excluded: Vec<Faction>,
+        faction: Option<Faction>,
+    },
+    DealSecret {
+        kind: CardKind,
+        slot: u32,
+        key: String,
+    },
+    RevealCard {
+        kind: CardKind,
+        slot: u32,
+        key: String,
+    },
+    /// Cards shuffled back into a deck, which nobody may know until they are dealt again
+    HideCards {
+        kind: CardKind,
+        slots: Vec<u32>,
+    },
+    /// The order the host shuffled a secret deck into by slot, bottom card first
+    DeckOrder {
+        kind: CardKind,
+        slots: Vec<u32>,
+    },
+    /// The play order the host drew
+    PlayOrder {
+        factions: Vec<Faction>,
+    },
+    /// How far the host moved the storm
+    StormMoved {
+        sectors: i32,
+    },
+    /// The spice card the host drew off the top of the deck
+    SpiceDrawn {
+        key: String,
+    },
+    ResyncState {
+        faction: Option<Faction>,
+        state: Vec<u8>,
+    },
+    /// How everyone's connection to the server is doing, sent unreliably every second
+    Latency {
+        players: Vec<Latency>,
+    },
+    /// Seconds left on everyone's clock, sent unreliably every second
+    TimeBanks {
+        banks: Vec<TimeBank>,
+    },
+    /// Where a player is pointing on the board, sent unreliably
+    Cursor {
+        from: Faction,
+        x: f32,
+        z: f32,
+    },
+    /// A ping or emote from anyone, the receiver's own included
+    Signal {
+        signal: Signal,
+    },
+    /// A hash of everything public about the game, sent once a turn
+    StateHash {
+        turn: i32,
+        sections: Vec<StateSection>,
+    },
+    /// What changed since `base`, a version the client has acknowledged, or everything when there
+    /// is no base
+    StateDelta {
+        base: Option<u32>,
+        version: u32,
+        changes: Vec<StateChange>,
+    },
+    /// Troops shipped since the last state version, for clients to fly in as it arrives
+    Deploy {
+        deployments: Vec<TroopsDeployed>,
+    },
+    /// Where to find the client that takes over hosting if the host goes away
+    BackupHost {
+        address: String,
+        password: String,
+        /// Set for the backup itself
+        chosen: bool,
+    },
+    /// What the backup needs to host the game, sent to it alone. The state only holds what the
+    /// backup may see, and the seals let it check what everyone else says they were dealt.
+    Replica {
+        state: Vec<u8>,
+        sessions: Vec<ReplicaSession>,
+        seals: Vec<SecretSeal>,
+    },
+    /// Proof of a secret dealt to us, kept in case we have to show it to a new host
+    SecretProof {
+        claim: SecretClaim,
+    },
+    /// Where the Nexus negotiation stands, sent whenever it changes
+    Negotiation {
+        open: bool,
+        proposals: Vec<Alliance>,
+        done: Vec<Faction>,
+        alliances: Vec<Alliance>,
+    },
+    /// Who has locked in a battle plan and the plans of every battle where everyone has, sent
+    /// whenever either changes
+    BattleWheels {
+        open: bool,
+        locked: Vec<LockedPlan>,
+        revealed: Vec<BattlePlan>,
+    },
+    /// Who is dialing the first storm and who has, and both numbers once they are in
+    StormDial {
+        dialers: Vec<Faction>,
+        dialed: Vec<Faction>,
+        revealed: Vec<DialPick>,
+    },
+    /// A player quit from the pause menu, handing their faction to a bot, or conceded
+    PlayerLeft {
+        faction: Faction,
+        conceded: bool,
+    },
+    /// Whether the Mentat Pause is on and who is ready to move on, sent whenever either changes
+    MentatPause {
+        open: bool,
+        ready: Vec<Faction>,
+    },
+    /// Something that just happened in the game, for the toasts and the event log
+    Notice {
+        notice: Notice,
+    },
+    /// The leaders the Harkonnen have captured, sent whenever they change with only the names
+    /// the client may know
+    Captures {
+        pending: Vec<CapturedLeader>,
+        held: Vec<CapturedLeader>,
+        killed: Vec<CapturedLeader>,
+    },
+}
+
+impl ServerEvent {
+    /// Anything sent again every second or so can go unreliably
+    pub fn channel(&self) -> Channel {
+        match self {
+            ServerEvent::Latency { .. }
+            | ServerEvent::TimeBanks { .. }
+            | ServerEvent::Cursor { .. } => Channel::Unreliable,
+            _ => Channel::Reliable,
+        }
+    }
+
+    pub fn into_bytes(&self) -> Vec<u8> {
+        archive(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        check_archive::<Self>(bytes, 0)
+            .ok()
+            .map(|archived| archived.unarchive())
+    }
+}
+
+fn archive<T: Archive>(value: &T) -> Vec<u8> {
+    let mut writer = ArchiveWriter::new(Cursor::new(Vec::new()));
+    writer
+        .archive_root(value)
+        .expect("Failed to serialize message data!");
+    writer.into_inner().into_inner()
+}
+
+impl ClientMessage {
+    pub fn into_bytes(&self) -> Vec<u8> {
+        archive(self)
+    }
+
+    /// Anything that doesn't validate is garbage, whether from a broken client or a malicious one
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        check_archive::<Self>(bytes, 0)
+            .ok()
+            .map(|archived| archived.unarchive())
+    }
+}
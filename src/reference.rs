@@ -0,0 +1,380 @@
+use bevy::prelude::*;
+
+use crate::{
+    cards::HoveredCard,
+    data::{AbilityWindow, Faction, FactionAbilities, Terrain},
+    hud::HudMaterials,
+    layout::{Anchored, AnchoredHeight},
+    locale::{Localized, Tr},
+    menu::{ButtonMaterials, Chat},
+    phase::Phase,
+    resources::{Data, Info},
+    settings::{Binding, Settings},
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+type ButtonInteractions<'a, 'b, 'c> = Query<
+    'a,
+    (&'b Interaction, &'c mut Handle<ColorMaterial>),
+    (Mutated<Interaction>, With<ReferenceButton>),
+>;
+
+/// Most entry titles listed at once, scrolling to keep the picked one in view
+const RESULTS_SHOWN: usize = 10;
+
+pub struct ReferencePlugin;
+
+impl Plugin for ReferencePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // Ahead of everything else reading the keyboard, so typing a search doesn't also play
+        app.init_resource::<RulesReference>()
+            .add_system_to_stage(stage::PRE_UPDATE, reference_input_system.system())
+            .on_state_enter(RESPONSE_STAGE, Screen::HostingGame, init_reference.system())
+            .on_state_enter(RESPONSE_STAGE, Screen::JoinedGame, init_reference.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                reference_button_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                reference_button_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                reference_panel_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                reference_panel_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system());
+    }
+}
+
+/// The searchable rules panel: the phases of a turn, what each faction can do and what every
+/// treachery card does
+#[derive(Default)]
+pub struct RulesReference {
+    pub open: bool,
+    pub search: String,
+    /// Position of the picked entry among those matching the search
+    pub selected: usize,
+}
+
+impl RulesReference {
+    /// Opens the panel on the entry called `title`
+    pub fn open_at(&mut self, title: &str) {
+        self.open = true;
+        self.search = title.to_string();
+        self.selected = 0;
+    }
+}
+
+struct Entry {
+    /// Language key of the kind of entry
+    section: &'static str,
+    title: String,
+    body: Vec<String>,
+}
+
+impl Entry {
+    fn matches(&self, search: &str) -> bool {
+        self.title.to_lowercase().contains(search)
+            || self
+                .body
+                .iter()
+                .any(|line| line.to_lowercase().contains(search))
+    }
+}
+
+/// The phase an ability window falls in, by its language key
+fn window_key(window: AbilityWindow) -> &'static str {
+    match window {
+        AbilityWindow::Storm => "phase.storm",
+        AbilityWindow::Bidding => "phase.bidding",
+        AbilityWindow::Shipping => "phase.movement",
+        AbilityWindow::Revival => "phase.revival",
+        AbilityWindow::Battle => "phase.battle",
+    }
+}
+
+fn entries(tr: &Tr, data: &Data, abilities: &FactionAbilities, info: &Info) -> Vec<Entry> {
+    let mut entries = Phase::TRACK
+        .iter()
+        .map(|key| Entry {
+            section: "reference.section.phase",
+            title: tr.get(key),
+            body: tr
+                .get(&format!("reference.{}", key))
+                .lines()
+                .map(str::to_string)
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    for &faction in Faction::ALL.iter().filter(|faction| {
+        info.factions_in_play.is_empty() || info.factions_in_play.contains(faction)
+    }) {
+        let (troops, locations, spice) = faction.initial_values();
+        let mut body = vec![tr.fmt("reference.start", &[&troops, &spice])];
+        if troops > 0 {
+            body.push(match locations {
+                Some(locations) => tr.fmt("reference.home", &[&locations.join(", ")]),
+                None => tr.get("reference.anywhere"),
+            });
+        }
+        body.push(tr.fmt("reference.hand_limit", &[&faction.hand_limit()]));
+        body.push(tr.fmt(
+            "reference.revival",
+            &[&faction.free_revivals(), &faction.revival_limit()],
+        ));
+        body.push(tr.fmt(
+            "reference.movement",
+            &[
+                &faction.movement_range(false),
+                &faction.movement_range(true),
+            ],
+        ));
+        body.push(tr.fmt(
+            "reference.shipping",
+            &[&faction.shipping_cost(Terrain::Stronghold, 1)],
+        ));
+        body.extend(abilities.held_by(faction).map(|ability| {
+            tr.fmt(
+                "reference.ability",
+                &[&ability.name(), &tr.get(window_key(ability.window()))],
+            )
+        }));
+        entries.push(Entry {
+            section: "reference.section.faction",
+            title: tr.faction(faction),
+            body,
+        });
+    }
+
+    // The deck holds several copies of most cards, and one entry covers them all
+    for card in data.treachery_cards.iter() {
+        if entries.iter().any(|entry| entry.title == card.name) {
+            continue;
+        }
+        entries.push(Entry {
+            section: "reference.section.card",
+            title: card.name.clone(),
+            body: card
+                .effect
+                .description()
+                .lines()
+                .map(|line| line.trim().to_string())
+                .collect(),
+        });
+    }
+    entries
+}
+
+struct ReferencePanel;
+
+struct ReferenceText;
+
+struct ReferenceButton;
+
+fn init_reference(
+    commands: &mut Commands,
+    (data, asset_server): (Res<Data>, Res<AssetServer>),
+    (materials, button_materials): (Res<HudMaterials>, Res<ButtonMaterials>),
+) {
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(12.0)),
+                display: Display::None,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(ReferencePanel)
+        .with(Anchored::new(data.ui_structure.reference.clone()).with_height(AnchoredHeight::Auto))
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        font: font.clone(),
+                        value: String::new(),
+                        style: TextStyle {
+                            font_size: 16.0,
+                            color: Color::ANTIQUE_WHITE,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(ReferenceText);
+        });
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: button_materials.normal.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(ReferenceButton)
+        .with(Anchored::new(data.ui_structure.reference_button.clone()))
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        font,
+                        value: String::new(),
+                        style: TextStyle {
+                            font_size: 16.0,
+                            color: Color::ANTIQUE_WHITE,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(Localized("reference.button"));
+        });
+}
+
+/// The rules key opens the panel, on the card under the cursor in the hand tray if there is one.
+/// While the panel is open, typing goes into its search and no other key reaches the game.
+fn reference_input_system(
+    (mut reference, screen): (ResMut<RulesReference>, Res<State<Screen>>),
+    (settings, chat, hovered_card): (Res<Settings>, Res<Chat>, Res<HoveredCard>),
+    (mut keyboard_input, characters): (ResMut<Input<KeyCode>>, Res<Events<ReceivedCharacter>>),
+    mut reader: Local<EventReader<ReceivedCharacter>>,
+) {
+    let typed = reader.iter(&characters).map(|c| c.char).collect::<Vec<_>>();
+    if let Screen::HostingGame | Screen::JoinedGame = screen.current() {
+    } else {
+        return;
+    }
+    if settings.just_pressed(&keyboard_input, Binding::Rules) && !chat.open {
+        match hovered_card.name.as_ref() {
+            Some(name) => reference.open_at(name),
+            None => reference.open = !reference.open,
+        }
+        keyboard_input.reset(settings.key(Binding::Rules));
+    }
+    if !reference.open {
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        reference.open = false;
+    } else if keyboard_input.just_pressed(KeyCode::Back) {
+        if reference.search.pop().is_some() {
+            reference.selected = 0;
+        }
+    } else if keyboard_input.just_pressed(KeyCode::Up) {
+        reference.selected = reference.selected.saturating_sub(1);
+    } else if keyboard_input.just_pressed(KeyCode::Down) {
+        reference.selected += 1;
+    }
+    for c in typed.into_iter().filter(|c| !c.is_control()) {
+        reference.search.push(c);
+        reference.selected = 0;
+    }
+    let pressed = keyboard_input.get_pressed().copied().collect::<Vec<_>>();
+    for key in pressed {
+        keyboard_input.reset(key);
+    }
+}
+
+fn reference_button_system(
+    (mut reference, button_materials): (ResMut<RulesReference>, Res<ButtonMaterials>),
+    mut interactions: ButtonInteractions,
+) {
+    for (&interaction, mut material) in interactions.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {
+                *material = button_materials.pressed.clone();
+                reference.open = !reference.open;
+            }
+            Interaction::Hovered => *material = button_materials.hovered.clone(),
+            Interaction::None => *material = button_materials.normal.clone(),
+        }
+    }
+}
+
+/// Lists the entries matching the search, those named after it first, and shows the picked one
+fn reference_panel_system(
+    mut reference: ResMut<RulesReference>,
+    (tr, data, abilities, info): (Res<Tr>, Res<Data>, Res<FactionAbilities>, Res<Info>),
+    mut panels: Query<&mut Style, With<ReferencePanel>>,
+    mut texts: Query<&mut Text, With<ReferenceText>>,
+) {
+    let display = if reference.open {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    for mut style in panels.iter_mut() {
+        if style.display != display {
+            style.display = display;
+        }
+    }
+    if !reference.open {
+        return;
+    }
+
+    let search = reference.search.to_lowercase();
+    let (mut matches, others): (Vec<_>, Vec<_>) = entries(&tr, &data, &abilities, &info)
+        .into_iter()
+        .filter(|entry| entry.matches(&search))
+        .partition(|entry| entry.title.to_lowercase().contains(&search));
+    matches.extend(others);
+    let selected = reference.selected.min(matches.len().saturating_sub(1));
+    if reference.selected != selected {
+        reference.selected = selected;
+    }
+
+    let mut lines = vec![
+        tr.get("reference.title"),
+        tr.fmt("reference.search", &[&reference.search]),
+        String::new(),
+    ];
+    if matches.is_empty() {
+        lines.push(tr.get("reference.none"));
+    }
+    let start = (selected + 1).saturating_sub(RESULTS_SHOWN);
+    for (i, entry) in matches.iter().enumerate().skip(start).take(RESULTS_SHOWN) {
+        lines.push(format!(
+            "{} {} ({})",
+            if i == selected { ">" } else { " " },
+            entry.title,
+            tr.get(entry.section)
+        ));
+    }
+    if let Some(entry) = matches.get(selected) {
+        lines.push(String::new());
+        lines.push(entry.title.clone());
+        lines.extend(entry.body.iter().cloned());
+    }
+    lines.push(String::new());
+    lines.push(tr.get("reference.hint"));
+
+    let value = lines.join("\n");
+    for mut text in texts.iter_mut() {
+        if text.value != value {
+            text.value = value.clone();
+        }
+    }
+}
+
+fn reset(mut reference: ResMut<RulesReference>) {
+    *reference = RulesReference::default();
+}
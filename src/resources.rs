@@ -1,51 +1,108 @@
-use std::fs::File;
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Read},
+};
 
-use bevy::{ecs::Entity, math::Vec2};
+use bevy::{
+    ecs::{Entity, FromResources, Resources},
+    math::Vec2,
+};
+use bytecheck::CheckBytes;
+use rand::{rngs::StdRng, SeedableRng};
+use rkyv::{Archive, Unarchive};
 
-use crate::{data::*, phase::Context};
+use crate::{
+    alliance::Alliance,
+    data::*,
+    decks::{DeckPreset, STANDARD_DECK},
+    phase::Context,
+};
+
+pub use crate::dune_core::SectorNode;
 
 pub struct Data {
     pub leaders: Vec<Leader>,
     pub locations: Vec<Location>,
     pub treachery_cards: Vec<TreacheryCard>,
     pub spice_cards: Vec<SpiceCard>,
+    /// The preset the cards came from
+    pub deck_name: String,
     pub camera_nodes: CameraNodes,
     pub prediction_nodes: PredictionNodes,
     pub traitor_nodes: Vec<Vec2>,
     pub token_nodes: TokenNodes,
     pub ui_structure: UiStructure,
+    pub asset_manifest: Vec<ManifestEntry>,
+}
+
+/// The data files the browser build carries inside itself, having no files of its own to read
+#[cfg(target_arch = "wasm32")]
+const EMBEDDED_DATA: [(&str, &[u8]); 7] = [
+    ("data/leaders.ron", include_bytes!("../data/leaders.ron")),
+    (STANDARD_DECK, include_bytes!("../data/decks/standard.ron")),
+    ("data/ui.ron", include_bytes!("../data/ui.ron")),
+    ("data/assets.ron", include_bytes!("../data/assets.ron")),
+    ("data/lang/en.ron", include_bytes!("../data/lang/en.ron")),
+    ("data/lang/es.ron", include_bytes!("../data/lang/es.ron")),
+    ("data/tutorial.ron", include_bytes!("../data/tutorial.ron")),
+];
+
+/// Opens one of the files under `data/`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn open_data(path: &str) -> io::Result<Box<dyn Read>> {
+    Ok(Box::new(std::fs::File::open(path)?))
+}
+
+/// Opens one of the files under `data/`, out of those built in
+#[cfg(target_arch = "wasm32")]
+pub fn open_data(path: &str) -> io::Result<Box<dyn Read>> {
+    EMBEDDED_DATA
+        .iter()
+        .find(|(name, _)| *name == path)
+        .map(|&(_, bytes)| Box::new(bytes) as Box<dyn Read>)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.to_string()))
 }
 
 impl Default for Data {
     fn default() -> Self {
-        let locations = ron::de::from_reader(File::open("data/locations.ron").unwrap()).unwrap();
-        let leaders = ron::de::from_reader(File::open("data/leaders.ron").unwrap()).unwrap();
-        let treachery_cards =
-            ron::de::from_reader(File::open("data/treachery.ron").unwrap()).unwrap();
-        let spice_cards = ron::de::from_reader(File::open("data/spice.ron").unwrap()).unwrap();
-        let camera_nodes =
-            ron::de::from_reader(File::open("data/camera_nodes.ron").unwrap()).unwrap();
-        let prediction_nodes =
-            ron::de::from_reader(File::open("data/prediction_nodes.ron").unwrap()).unwrap();
-        let traitor_nodes =
-            ron::de::from_reader(File::open("data/traitor_nodes.ron").unwrap()).unwrap();
-        let token_nodes =
-            ron::de::from_reader(File::open("data/token_nodes.ron").unwrap()).unwrap();
-        let ui_structure = ron::de::from_reader(File::open("data/ui.ron").unwrap()).unwrap();
+        let leaders = ron::de::from_reader(open_data("data/leaders.ron").unwrap()).unwrap();
+        let decks: DeckPreset = ron::de::from_reader(open_data(STANDARD_DECK).unwrap()).unwrap();
+        let ui_structure = ron::de::from_reader(open_data("data/ui.ron").unwrap()).unwrap();
+        let asset_manifest = ron::de::from_reader(open_data("data/assets.ron").unwrap()).unwrap();
+        // The board comes in with the rest of the game's assets, see `set_board`
         Data {
-            locations,
+            locations: Vec::new(),
             leaders,
-            treachery_cards,
-            spice_cards,
-            camera_nodes,
-            prediction_nodes,
-            traitor_nodes,
-            token_nodes,
+            treachery_cards: decks.treachery,
+            spice_cards: decks.spice,
+            deck_name: decks.name,
+            camera_nodes: CameraNodes::default(),
+            prediction_nodes: PredictionNodes::default(),
+            traitor_nodes: Vec::new(),
+            token_nodes: TokenNodes::default(),
             ui_structure,
+            asset_manifest,
         }
     }
 }
 
+impl Data {
+    pub fn set_board(&mut self, board: &Board) {
+        self.locations = board.locations.clone();
+        self.camera_nodes = board.camera_nodes;
+        self.prediction_nodes = board.prediction_nodes.clone();
+        self.traitor_nodes = board.traitor_nodes.clone();
+        self.token_nodes = board.token_nodes.clone();
+    }
+
+    /// Cards are built from these once the game starts
+    pub fn set_decks(&mut self, preset: &DeckPreset) {
+        self.treachery_cards = preset.treachery.clone();
+        self.spice_cards = preset.spice.clone();
+        self.deck_name = preset.name.clone();
+    }
+}
+
 pub struct Info {
     pub turn: i32,
     pub players: Vec<String>,
@@ -55,6 +112,10 @@ pub struct Info {
     pub play_order: Vec<Entity>,
     pub default_clickables: Vec<Entity>,
     pub context: Context,
+    pub nexus: bool,
+    pub alliances: Vec<Alliance>,
+    /// Factions that conceded or lost everything, in the order they went out
+    pub eliminated: Vec<Faction>,
 }
 
 impl Default for Info {
@@ -68,6 +129,9 @@ impl Default for Info {
             play_order: Vec::new(),
             default_clickables: Vec::new(),
             context: Context::None,
+            nexus: false,
+            alliances: Vec::new(),
+            eliminated: Vec::new(),
         }
     }
 }
@@ -81,6 +145,25 @@ impl Info {
         self.play_order = Vec::new();
         self.default_clickables = Vec::new();
         self.context = Context::None;
+        self.nexus = false;
+        self.alliances = Vec::new();
+        self.eliminated = Vec::new();
+    }
+
+    /// The factions still playing, which is everyone in play who hasn't been eliminated
+    pub fn in_game(&self) -> Vec<Faction> {
+        self.factions_in_play
+            .iter()
+            .copied()
+            .filter(|faction| !self.eliminated.contains(faction))
+            .collect()
+    }
+
+    /// Who `faction` is allied with, if anyone
+    pub fn ally(&self, faction: Faction) -> Option<Faction> {
+        self.alliances
+            .iter()
+            .find_map(|alliance| alliance.partner(faction))
     }
 
     pub fn get_active_player(&self) -> Entity {
@@ -88,3 +171,209 @@ impl Info {
             .unwrap_or(self.play_order[self.current_turn])
     }
 }
+
+/// Randomness behind every shuffle and roll. Only the host's matters, and it never leaves the
+/// host: clients are sent the outcomes they get to see instead, and a game can be replayed from
+/// its seed.
+pub struct GameRng {
+    pub seed: u64,
+    pub rng: StdRng,
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng::new(rand::random())
+    }
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Starts the sequence over from the current seed
+    pub fn restart(&mut self) {
+        self.rng = StdRng::seed_from_u64(self.seed);
+    }
+}
+
+/// Which rules the game is played by, picked by the host in the lobby
+#[derive(Archive, Unarchive, Copy, Clone, PartialEq, Default, Debug)]
+#[archive(derive(CheckBytes))]
+pub enum RuleSet {
+    #[default]
+    Basic,
+    Advanced,
+}
+
+impl RuleSet {
+    pub fn next(&self) -> Self {
+        match self {
+            RuleSet::Basic => RuleSet::Advanced,
+            RuleSet::Advanced => RuleSet::Basic,
+        }
+    }
+
+    /// Troops only fight at full strength when a spice is paid for each of them
+    pub fn spice_dialing(&self) -> bool {
+        *self == RuleSet::Advanced
+    }
+
+    /// A leader adds nothing to a battle while their faction is under a karama
+    pub fn karama_leaders(&self) -> bool {
+        *self == RuleSet::Advanced
+    }
+
+    /// Faction powers marked as advanced are in play
+    pub fn advanced_powers(&self) -> bool {
+        *self == RuleSet::Advanced
+    }
+
+    /// Only the captor and the owner learn which leader the Harkonnen captured
+    pub fn hidden_captures(&self) -> bool {
+        *self == RuleSet::Advanced
+    }
+
+    /// Everyone's spice is out in the open rather than behind their shield
+    pub fn public_spice(&self) -> bool {
+        *self == RuleSet::Basic
+    }
+}
+
+impl std::fmt::Display for RuleSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleSet::Basic => write!(f, "Basic"),
+            RuleSet::Advanced => write!(f, "Advanced"),
+        }
+    }
+}
+
+pub struct SectorGraph {
+    pub terrain: HashMap<String, Terrain>,
+    pub edges: HashMap<SectorNode, Vec<SectorNode>>,
+}
+
+impl FromResources for SectorGraph {
+    fn from_resources(resources: &Resources) -> Self {
+        let data = resources.get::<Data>().unwrap();
+        SectorGraph::new(&data.locations)
+    }
+}
+
+impl SectorGraph {
+    pub fn new(locations: &[Location]) -> Self {
+        let nodes = locations
+            .iter()
+            .flat_map(|location| {
+                location.sectors.iter().map(move |(&sector, nodes)| {
+                    (SectorNode::new(&location.name, sector), &nodes.vertices)
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut edges = HashMap::new();
+        for (i, (node1, vertices1)) in nodes.iter().enumerate() {
+            for (node2, vertices2) in nodes[i + 1..].iter() {
+                // Sectors that share an edge share at least two vertices, corners only share one
+                let shared = vertices1
+                    .iter()
+                    .filter(|v1| {
+                        vertices2
+                            .iter()
+                            .any(|v2| (v1.x - v2.x).powi(2) + (v1.y - v2.y).powi(2) < 1e-8)
+                    })
+                    .count();
+                if shared >= 2 {
+                    edges
+                        .entry(node1.clone())
+                        .or_insert_with(Vec::new)
+                        .push(node2.clone());
+                    edges
+                        .entry(node2.clone())
+                        .or_insert_with(Vec::new)
+                        .push(node1.clone());
+                }
+            }
+            edges.entry(node1.clone()).or_insert_with(Vec::new);
+        }
+        let terrain = locations
+            .iter()
+            .map(|location| (location.name.clone(), location.terrain))
+            .collect();
+        SectorGraph { terrain, edges }
+    }
+
+    pub fn neighbors(&self, node: &SectorNode) -> &[SectorNode] {
+        self.edges.get(node).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn sectors_of<'a>(&'a self, location: &'a str) -> impl Iterator<Item = &'a SectorNode> {
+        self.edges
+            .keys()
+            .filter(move |node| node.location == location)
+    }
+
+    pub fn in_storm(&self, node: &SectorNode, storm_sector: Option<i32>) -> bool {
+        storm_sector == Some(node.sector)
+            && self.terrain.get(&node.location) != Some(&Terrain::PolarSink)
+    }
+
+    /// Number of territory borders crossed on the shortest path between two sectors, moving
+    /// freely between sectors of the same territory and never through the storm.
+    pub fn distance(
+        &self,
+        from: &SectorNode,
+        to: &SectorNode,
+        storm_sector: Option<i32>,
+    ) -> Option<i32> {
+        self.distances(from, storm_sector).remove(to)
+    }
+
+    pub fn distances(
+        &self,
+        from: &SectorNode,
+        storm_sector: Option<i32>,
+    ) -> HashMap<SectorNode, i32> {
+        let mut dist = HashMap::new();
+        if self.in_storm(from, storm_sector) {
+            return dist;
+        }
+        let mut queue = VecDeque::new();
+        dist.insert(from.clone(), 0);
+        queue.push_back(from.clone());
+        while let Some(node) = queue.pop_front() {
+            let d = dist[&node];
+            for next in self.neighbors(&node) {
+                if self.in_storm(next, storm_sector) {
+                    continue;
+                }
+                let cost = if next.location == node.location { 0 } else { 1 };
+                if dist.get(next).map_or(true, |&old| old > d + cost) {
+                    dist.insert(next.clone(), d + cost);
+                    if cost == 0 {
+                        queue.push_front(next.clone());
+                    } else {
+                        queue.push_back(next.clone());
+                    }
+                }
+            }
+        }
+        dist
+    }
+
+    /// All sectors within `range` territories of `from`.
+    pub fn reachable(
+        &self,
+        from: &SectorNode,
+        range: i32,
+        storm_sector: Option<i32>,
+    ) -> Vec<SectorNode> {
+        self.distances(from, storm_sector)
+            .into_iter()
+            .filter_map(|(node, d)| if d <= range { Some(node) } else { None })
+            .collect()
+    }
+}
@@ -0,0 +1,971 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{Cursor, Read, Write as IoWrite},
+    mem,
+    net::SocketAddr,
+};
+
+use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rkyv::{check_archive, Archive, ArchiveWriter, Seek, Unarchive, Write};
+
+use crate::{
+    alliance::Alliance,
+    capture::{home_node, CapturedLeader, Captures},
+    cards::CardEffects,
+    components::{
+        Collider, Dead, LocationSector, Player, Prediction, Secret, Spice, SpiceNode, Storm, Troop,
+        Unique, UniqueBundle,
+    },
+    data::{
+        Faction, FactionPredictionCard, Leader, Location, SpiceCard, StormCard, TraitorCard,
+        TreacheryCard, TurnPredictionCard,
+    },
+    decks::DeckPreset,
+    dune_core::Occupancy,
+    game_action::{GameActions, LoggedAction},
+    house_rules::{HouseRules, HouseRulesScript},
+    migration::{deal_hidden, HostMigration, ReplicaSession},
+    network::{Network, NetworkType, Server},
+    phase::{Action, ActionQueue, Auction, Context, GamePhase, Phase, SpiceDeck},
+    protocol::ServerEvent,
+    resources::{Data, GameRng, Info, RuleSet, SectorNode},
+    secret::SecretReveals,
+    settings::{Binding, Settings},
+    token_pool::TokenPool,
+    Screen, STATE_CHANGE_STAGE,
+};
+
+const SAVE_MAGIC: &[u8; 4] = b"DUNE";
+const SAVE_VERSION: u32 = 16;
+const SAVE_DIR: &str = "saves";
+const SAVE_PATH: &str = "saves/dune.sav";
+
+pub struct SaveGamePlugin;
+
+impl Plugin for SaveGamePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<LoadedGame>()
+            .init_resource::<ResyncRequests>()
+            .init_resource::<SaveRequest>()
+            .add_system(load_game.system())
+            .on_state_update(STATE_CHANGE_STAGE, Screen::HostingGame, save_game.system());
+    }
+}
+
+/// A save waiting to be applied once the game board has been spawned
+#[derive(Default)]
+pub struct LoadedGame {
+    pub save: Option<SaveGame>,
+    /// Why the last save couldn't be applied, until the HUD picks it up
+    pub failed: Option<String>,
+}
+
+/// Set to write the game to disk on the next frame, the same as the quick save key
+#[derive(Default)]
+pub struct SaveRequest {
+    pub pending: bool,
+    /// Set to pack the game up for a bug report instead, which is left in `snapshot`
+    pub report: bool,
+    pub snapshot: Option<Vec<u8>>,
+    /// Why the last save to disk failed, until the HUD picks it up
+    pub failed: Option<String>,
+}
+
+/// Reconnected clients waiting for a copy of the game state
+#[derive(Default)]
+pub struct ResyncRequests {
+    pub clients: Vec<(SocketAddr, Option<Faction>)>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub enum CardKind {
+    Treachery,
+    Traitor,
+    Spice,
+    Storm,
+    FactionPrediction,
+    TurnPrediction,
+}
+
+#[derive(Copy, Clone, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct SavedTransform {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl From<&Transform> for SavedTransform {
+    fn from(transform: &Transform) -> Self {
+        SavedTransform {
+            translation: transform.translation.into(),
+            rotation: transform.rotation.into(),
+            scale: transform.scale.into(),
+        }
+    }
+}
+
+impl From<SavedTransform> for Transform {
+    fn from(saved: SavedTransform) -> Self {
+        Transform {
+            translation: saved.translation.into(),
+            rotation: saved.rotation.into(),
+            scale: saved.scale.into(),
+        }
+    }
+}
+
+#[derive(Clone, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct SavedCard {
+    pub kind: CardKind,
+    /// Empty when the card's identity is hidden from whoever receives the save
+    pub key: String,
+    /// Secret slot of treachery and traitor cards, which are matched by slot rather than key
+    pub slot: Option<u32>,
+    pub owner: Option<Faction>,
+    /// Shown to everyone, so it stays in every copy of the save
+    pub public: bool,
+    pub transform: SavedTransform,
+}
+
+#[derive(Clone, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct SavedTroop {
+    pub faction: Faction,
+    pub location: Option<SectorNode>,
+    pub dead: bool,
+    pub transform: SavedTransform,
+}
+
+#[derive(Clone, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct SavedLeader {
+    pub name: String,
+    pub dead: bool,
+    pub transform: SavedTransform,
+}
+
+#[derive(Clone, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct SavedSpice {
+    pub value: i32,
+    pub owner: Option<Faction>,
+    pub location: Option<String>,
+    pub transform: SavedTransform,
+}
+
+/// Cards are referenced by their index in `SaveGame::cards`
+#[derive(Clone, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct SavedPlayer {
+    pub faction: Faction,
+    pub spice: i32,
+    pub traitor_cards: Vec<u32>,
+    pub treachery_cards: Vec<u32>,
+    pub prediction: Option<(Option<Faction>, Option<i32>)>,
+}
+
+#[derive(Clone, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct SavedAuction {
+    pub cards: Vec<(u32, [f32; 2])>,
+    pub starting_bidder: u32,
+    pub bidder: u32,
+    pub high_bid: Option<(Faction, i32)>,
+    pub passes: u32,
+    pub closed: bool,
+}
+
+#[derive(Clone, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct SaveGame {
+    pub turn: i32,
+    pub current_turn: u32,
+    pub play_order: Vec<Faction>,
+    pub active_player: Option<Faction>,
+    pub context: Context,
+    pub nexus: bool,
+    pub phase: Phase,
+    pub storm_sector: i32,
+    pub enabled_sectors: Vec<SectorNode>,
+    pub players: Vec<SavedPlayer>,
+    pub troops: Vec<SavedTroop>,
+    pub leaders: Vec<SavedLeader>,
+    pub spice: Vec<SavedSpice>,
+    pub cards: Vec<SavedCard>,
+    pub spice_discard: Vec<u32>,
+    pub spice_set_aside: Vec<u32>,
+    pub spice_revealed: bool,
+    pub treachery_discard: Vec<u32>,
+    pub auction: SavedAuction,
+    pub ornithopters: Vec<Faction>,
+    pub rules: RuleSet,
+    pub decks: DeckPreset,
+    pub house_rules: Option<HouseRulesScript>,
+    pub alliances: Vec<Alliance>,
+    pub eliminated: Vec<Faction>,
+    /// Leaders the captor has yet to decide on, then ones kept behind their shield
+    pub pending_captures: Vec<CapturedLeader>,
+    pub held_captures: Vec<CapturedLeader>,
+    pub actions: Vec<LoggedAction>,
+}
+
+impl SaveGame {
+    /// File layout is the magic, the version, the archive, then the position of the archive root
+    pub fn into_bytes(&self) -> Vec<u8> {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new()));
+        writer
+            .write(SAVE_MAGIC)
+            .expect("Failed to write save header!");
+        writer
+            .write(&SAVE_VERSION.to_le_bytes())
+            .expect("Failed to write save header!");
+        let pos = writer
+            .archive_root(self)
+            .expect("Failed to serialize save game!");
+        let mut bytes = writer.into_inner().into_inner();
+        bytes.extend_from_slice(&(pos as u64).to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 16 || &bytes[..4] != SAVE_MAGIC {
+            return Err("not a save file".to_string());
+        }
+        let mut version = [0; 4];
+        version.copy_from_slice(&bytes[4..8]);
+        let version = u32::from_le_bytes(version);
+        if version != SAVE_VERSION {
+            return Err(format!(
+                "unsupported save version {} (expected {})",
+                version, SAVE_VERSION
+            ));
+        }
+        let (archive, pos) = bytes.split_at(bytes.len() - 8);
+        let mut pos_bytes = [0; 8];
+        pos_bytes.copy_from_slice(pos);
+        check_archive::<Self>(archive, u64::from_le_bytes(pos_bytes) as usize)
+            .map(|archived| archived.unarchive())
+            .map_err(|_| "the save file is corrupted".to_string())
+    }
+}
+
+pub fn read_save() -> Result<SaveGame, String> {
+    let mut bytes = Vec::new();
+    File::open(SAVE_PATH)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|err| format!("couldn't read {}: {}", SAVE_PATH, err))?;
+    SaveGame::from_bytes(&bytes)
+}
+
+fn write_save(bytes: &[u8]) -> Result<(), String> {
+    fs::create_dir_all(SAVE_DIR)
+        .and_then(|_| File::create(SAVE_PATH))
+        .and_then(|mut file| file.write_all(bytes))
+        .map_err(|err| format!("couldn't write {}: {}", SAVE_PATH, err))
+}
+
+type CardComponents<'a> = (
+    Option<&'a TreacheryCard>,
+    Option<&'a TraitorCard>,
+    Option<&'a SpiceCard>,
+    Option<&'a StormCard>,
+    Option<&'a FactionPredictionCard>,
+    Option<&'a TurnPredictionCard>,
+    Option<&'a Secret<TreacheryCard>>,
+    Option<&'a Secret<TraitorCard>>,
+);
+
+fn card_key(
+    (treachery, traitor, spice, storm, faction, turn, secret_treachery, secret_traitor): CardComponents,
+) -> Option<(CardKind, String, Option<u32>)> {
+    if let Some(secret) = secret_treachery {
+        let key = secret.value.as_ref().map(|card| card.id.to_string());
+        Some((
+            CardKind::Treachery,
+            key.unwrap_or_default(),
+            Some(secret.slot),
+        ))
+    } else if let Some(secret) = secret_traitor {
+        let key = secret.value.as_ref().map(|card| card.leader.name.clone());
+        Some((
+            CardKind::Traitor,
+            key.unwrap_or_default(),
+            Some(secret.slot),
+        ))
+    } else if let Some(card) = treachery {
+        Some((CardKind::Treachery, card.id.to_string(), None))
+    } else if let Some(card) = traitor {
+        Some((CardKind::Traitor, card.leader.name.clone(), None))
+    } else if let Some(card) = spice {
+        Some((CardKind::Spice, card.name.clone(), None))
+    } else if let Some(card) = storm {
+        Some((CardKind::Storm, card.val.to_string(), None))
+    } else if let Some(card) = faction {
+        Some((CardKind::FactionPrediction, card.faction.to_string(), None))
+    } else {
+        turn.map(|card| (CardKind::TurnPrediction, card.turn.to_string(), None))
+    }
+}
+
+impl SaveGame {
+    /// A copy of the save with the identities of secret cards the given faction does not own,
+    /// anyone else's prediction and hidden spice, and any captives it may not know about removed
+    pub fn redacted_for(&self, faction: Option<Faction>, data: &Data) -> SaveGame {
+        let mut save = self.clone();
+        let hidden = self.rules.hidden_captures();
+        // A captive standing behind the shield would give away who it is, so it is put back with
+        // its own faction's leaders
+        for capture in self.held_captures.iter() {
+            let name = match &capture.leader {
+                Some(name) if capture.seen_by(faction, hidden).leader.is_none() => name,
+                _ => continue,
+            };
+            if let Some(leader) = save.leaders.iter_mut().find(|leader| leader.name == *name) {
+                let home = home_node(data, capture.owner, name);
+                leader.transform = (&Transform::from_translation(home)).into();
+            }
+        }
+        for capture in save
+            .pending_captures
+            .iter_mut()
+            .chain(save.held_captures.iter_mut())
+        {
+            *capture = capture.seen_by(faction, hidden);
+        }
+        for card in save.cards.iter_mut() {
+            if card.slot.is_some()
+                && !card.public
+                && (card.owner.is_none() || card.owner != faction)
+            {
+                card.key.clear();
+            }
+        }
+        // Only the Bene Gesserit know what they predicted
+        for player in save.players.iter_mut() {
+            if Some(player.faction) != faction {
+                player.prediction = None;
+                if !self.rules.public_spice() {
+                    player.spice = 0;
+                }
+            }
+        }
+        // The log holds everyone's secret picks
+        save.actions.clear();
+        save
+    }
+
+    /// A copy of the save for a spectator who sees every hand, so only the decks and the prediction
+    /// stay hidden. Casters are told about every capture too.
+    pub fn redacted_for_caster(&self) -> SaveGame {
+        let mut save = self.clone();
+        for card in save.cards.iter_mut() {
+            if card.slot.is_some() && !card.public && card.owner.is_none() {
+                card.key.clear();
+            }
+        }
+        for player in save.players.iter_mut() {
+            player.prediction = None;
+        }
+        save.actions.clear();
+        save
+    }
+}
+
+fn save_game(
+    (keyboard_input, settings, mut request): (
+        Res<Input<KeyCode>>,
+        Res<Settings>,
+        ResMut<SaveRequest>,
+    ),
+    (queue, data, house_rules): (Res<ActionQueue>, Res<Data>, Res<HouseRules>),
+    (info, rules, actions): (Res<Info>, Res<RuleSet>, Res<GameActions>),
+    (phase, captures): (Res<GamePhase>, Res<Captures>),
+    (auction, occupancy, spice_deck, card_effects): (
+        Res<Auction>,
+        Res<Occupancy>,
+        Res<SpiceDeck>,
+        Res<CardEffects>,
+    ),
+    (mut resync, mut migration, mut server): (
+        ResMut<ResyncRequests>,
+        ResMut<HostMigration>,
+        Query<&mut Server>,
+    ),
+    players: Query<(Entity, &Player, Option<&Prediction>)>,
+    storm: Query<&Storm>,
+    sectors: Query<(&LocationSector, &Collider)>,
+    locations: Query<&Location>,
+    leaders: Query<(&Leader, &Transform, Option<&Dead>)>,
+    troops: Query<(&Troop, &Unique, &Transform, Option<&Dead>)>,
+    spice: Query<(&Spice, Option<&Unique>, &Transform)>,
+    cards: Query<(Entity, &Transform, Option<&Unique>, CardComponents)>,
+) {
+    let mut to_file = settings.just_pressed(&keyboard_input, Binding::QuickSave)
+        || mem::take(&mut request.pending);
+    if to_file {
+        if let Phase::Setup { .. } = phase.phase {
+            println!("Cannot save until setup is complete");
+            to_file = false;
+        } else if !queue.is_empty() {
+            println!("Cannot save while actions are resolving");
+            to_file = false;
+        }
+    }
+    // A bug report takes the game as it is, settled or not, and goes without it during setup
+    let to_report = mem::take(&mut request.report) && !matches!(phase.phase, Phase::Setup { .. });
+    // Resyncs wait for the queue to settle rather than being refused
+    let to_clients = !resync.clients.is_empty() && queue.is_empty();
+    let to_backup =
+        migration.due && queue.is_empty() && !matches!(phase.phase, Phase::Setup { .. });
+    if !to_file && !to_clients && !to_backup && !to_report {
+        return;
+    }
+
+    let faction_of = |entity: Entity| players.get(entity).unwrap().1.faction;
+
+    let mut card_indices = HashMap::new();
+    let saved_cards = cards
+        .iter()
+        .filter_map(|(entity, transform, unique, components)| {
+            card_key(components)
+                .map(|(kind, key, slot)| (entity, kind, key, slot, transform, unique))
+        })
+        .enumerate()
+        .map(|(i, (entity, kind, key, slot, transform, unique))| {
+            card_indices.insert(entity, i as u32);
+            SavedCard {
+                kind,
+                key,
+                slot,
+                owner: unique.map(|unique| unique.faction),
+                public: unique.map_or(false, |unique| unique.public),
+                transform: transform.into(),
+            }
+        })
+        .collect::<Vec<_>>();
+    let card_index = |entity: &Entity| card_indices[entity];
+
+    let save = SaveGame {
+        turn: info.turn,
+        current_turn: info.current_turn as u32,
+        play_order: info.play_order.iter().map(|&e| faction_of(e)).collect(),
+        active_player: info.active_player.map(faction_of),
+        context: info.context,
+        nexus: info.nexus,
+        phase: phase.phase,
+        storm_sector: storm.iter().next().map_or(0, |storm| storm.sector),
+        enabled_sectors: sectors
+            .iter()
+            .filter(|(_, collider)| collider.enabled)
+            .map(|(loc_sec, _)| SectorNode::new(&loc_sec.location.name, loc_sec.sector))
+            .collect(),
+        players: players
+            .iter()
+            .map(|(_, player, prediction)| SavedPlayer {
+                faction: player.faction,
+                spice: player.spice,
+                traitor_cards: player.traitor_cards.iter().map(card_index).collect(),
+                treachery_cards: player.treachery_cards.iter().map(card_index).collect(),
+                prediction: prediction.map(|prediction| (prediction.faction, prediction.turn)),
+            })
+            .collect(),
+        troops: troops
+            .iter()
+            .map(|(troop, unique, transform, dead)| SavedTroop {
+                faction: unique.faction,
+                location: troop.location.map(|location| {
+                    let loc_sec = sectors.get_component::<LocationSector>(location).unwrap();
+                    SectorNode::new(&loc_sec.location.name, loc_sec.sector)
+                }),
+                dead: dead.is_some(),
+                transform: transform.into(),
+            })
+            .collect(),
+        leaders: leaders
+            .iter()
+            .map(|(leader, transform, dead)| SavedLeader {
+                name: leader.name.clone(),
+                dead: dead.is_some(),
+                transform: transform.into(),
+            })
+            .collect(),
+        spice: spice
+            .iter()
+            .map(|(spice, unique, transform)| SavedSpice {
+                value: spice.value,
+                owner: unique.map(|unique| unique.faction),
+                location: spice
+                    .location
+                    .map(|location| locations.get(location).unwrap().name.clone()),
+                transform: transform.into(),
+            })
+            .collect(),
+        cards: saved_cards,
+        spice_discard: spice_deck.discard.iter().map(card_index).collect(),
+        spice_set_aside: spice_deck.set_aside.iter().map(card_index).collect(),
+        spice_revealed: spice_deck.revealed,
+        treachery_discard: card_effects.discard.iter().map(card_index).collect(),
+        auction: SavedAuction {
+            cards: auction
+                .cards
+                .iter()
+                .map(|(card, pos)| (card_index(card), [pos.x, pos.y]))
+                .collect(),
+            starting_bidder: auction.starting_bidder as u32,
+            bidder: auction.bidder as u32,
+            high_bid: auction
+                .high_bid
+                .map(|(bidder, amount)| (faction_of(bidder), amount)),
+            passes: auction.passes as u32,
+            closed: auction.closed,
+        },
+        ornithopters: occupancy.ornithopters.clone(),
+        rules: *rules,
+        decks: DeckPreset {
+            name: data.deck_name.clone(),
+            treachery: data.treachery_cards.clone(),
+            spice: data.spice_cards.clone(),
+        },
+        house_rules: house_rules.active().cloned(),
+        alliances: info.alliances.clone(),
+        eliminated: info.eliminated.clone(),
+        pending_captures: captures.pending.clone(),
+        held_captures: captures.held.clone(),
+        actions: actions.log.clone(),
+    };
+
+    if to_file {
+        match write_save(&save.into_bytes()) {
+            Ok(()) => println!("Game saved to {}", SAVE_PATH),
+            Err(err) => {
+                println!("Failed to save the game: {}", err);
+                request.failed = Some(err);
+            }
+        }
+    }
+    if to_report {
+        request.snapshot = Some(save.into_bytes());
+    }
+    if to_clients {
+        if let Some(mut server) = server.iter_mut().next() {
+            for (address, faction) in resync.clients.drain(..) {
+                println!("Resyncing {} as {:?}", address, faction);
+                let state = if server.casters().contains(&address) {
+                    save.redacted_for_caster()
+                } else {
+                    save.redacted_for(faction, &data)
+                };
+                server.send_event(
+                    address,
+                    &ServerEvent::ResyncState {
+                        faction,
+                        state: state.into_bytes(),
+                    },
+                );
+            }
+        } else {
+            resync.clients.clear();
+        }
+    }
+    if to_backup {
+        migration.due = false;
+        if let (Some(address), Some(mut server)) = (migration.backup, server.iter_mut().next()) {
+            let sessions = server
+                .sessions
+                .iter()
+                .map(|(&token, session)| ReplicaSession::new(token, session))
+                .collect();
+            // The backup is a player too, so it only gets to see its own hand
+            let faction = server
+                .sessions
+                .values()
+                .find(|session| session.address == address)
+                .and_then(|session| session.faction);
+            let mut replica = save.redacted_for(faction, &data);
+            // Players prove their spice to the new host, but nobody can for the bots, so the
+            // backup has to carry on from what they had
+            for (player, saved) in replica.players.iter_mut().zip(save.players.iter()) {
+                if server.address_of(player.faction).is_none() {
+                    player.spice = saved.spice;
+                }
+            }
+            server.send_event(
+                address,
+                &ServerEvent::Replica {
+                    state: replica.into_bytes(),
+                    sessions,
+                    seals: migration.seals_for(&save),
+                },
+            );
+        }
+    }
+}
+
+/// Runs before the phase systems so a loaded game never sees a fresh setup phase
+fn load_game(
+    commands: &mut Commands,
+    screen: Res<State<Screen>>,
+    (mut loaded, mut reveals, mut actions, mut captures): (
+        ResMut<LoadedGame>,
+        ResMut<SecretReveals>,
+        ResMut<GameActions>,
+        ResMut<Captures>,
+    ),
+    (mut info, mut phase, mut queue, mut card_effects): (
+        ResMut<Info>,
+        ResMut<GamePhase>,
+        ResMut<ActionQueue>,
+        ResMut<CardEffects>,
+    ),
+    (mut auction, mut occupancy, mut spice_deck, mut rules): (
+        ResMut<Auction>,
+        ResMut<Occupancy>,
+        ResMut<SpiceDeck>,
+        ResMut<RuleSet>,
+    ),
+    (mut pool, data, network, mut game_rng): (
+        ResMut<TokenPool>,
+        Res<Data>,
+        Res<Network>,
+        ResMut<GameRng>,
+    ),
+    mut players: Query<(Entity, &mut Player, Option<&mut Prediction>)>,
+    mut storm: Query<&mut Storm>,
+    sectors: Query<(Entity, &LocationSector)>,
+    mut locations: Query<(Entity, &Location, Option<&mut SpiceNode>)>,
+    spice: Query<(Entity, &Spice)>,
+    mut tokens: QuerySet<(
+        Query<(Entity, &mut Troop, &Unique, &mut Transform)>,
+        Query<(Entity, &mut Transform, CardComponents)>,
+        Query<(Entity, &Leader, &mut Transform)>,
+    )>,
+) {
+    if let Screen::HostingGame = screen.current() {
+    } else {
+        return;
+    }
+    if players.iter_mut().next().is_none() {
+        return;
+    }
+    let save = if let Some(save) = loaded.save.take() {
+        save
+    } else {
+        return;
+    };
+
+    let player_entities = players
+        .iter_mut()
+        .map(|(entity, player, _)| (player.faction, entity))
+        .collect::<HashMap<_, _>>();
+    let sector_entities = sectors
+        .iter()
+        .map(|(entity, loc_sec)| {
+            (
+                SectorNode::new(&loc_sec.location.name, loc_sec.sector),
+                entity,
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    let location_entities = locations
+        .iter_mut()
+        .map(|(entity, location, _)| (location.name.clone(), entity))
+        .collect::<HashMap<_, _>>();
+
+    // Cards are matched up before anything is touched, so a save that doesn't fit leaves the
+    // game as it was
+    let candidates = tokens
+        .q1_mut()
+        .iter_mut()
+        .filter_map(|(entity, _, components)| card_key(components).map(|key| (entity, key)))
+        .collect::<Vec<_>>();
+    let mut matched = Vec::new();
+    for card in save.cards.iter() {
+        let found = candidates.iter().find(|(entity, (kind, key, slot))| {
+            *kind == card.kind
+                && !matched.iter().any(|&(other, _)| other == entity)
+                && match card.slot {
+                    Some(saved_slot) => *slot == Some(saved_slot),
+                    None => *key == card.key,
+                }
+        });
+        match found {
+            Some((entity, key)) => matched.push((entity, key)),
+            None => {
+                return load_failed(
+                    &mut loaded,
+                    format!("the save has a {:?} card that isn't in play", card.kind),
+                );
+            }
+        }
+    }
+    let card_entities = matched
+        .iter()
+        .map(|&(&entity, _)| entity)
+        .collect::<Vec<_>>();
+    let entities = match resolve_save(
+        &save,
+        &card_entities,
+        (&player_entities, &sector_entities, &location_entities),
+    ) {
+        Ok(entities) => entities,
+        Err(err) => return load_failed(&mut loaded, err),
+    };
+
+    for (card, &(&entity, (kind, key, slot))) in save.cards.iter().zip(matched.iter()) {
+        // Learn the identity of our own cards that were hidden before the resync. Slots are dealt
+        // out at random, so the host takes on the saved card in each one too.
+        if let Some(slot) = *slot {
+            if *key != card.key && !card.key.is_empty() {
+                reveals.pending.push((*kind, slot, card.key.clone()));
+            }
+        }
+        if let Ok((_, mut transform, _)) = tokens.q1_mut().get_mut(entity) {
+            *transform = card.transform.into();
+        }
+        if let Some(faction) = card.owner {
+            commands.insert(entity, UniqueBundle::new(faction));
+            if card.public {
+                commands.insert_one(
+                    entity,
+                    Unique {
+                        faction,
+                        public: true,
+                    },
+                );
+            }
+        }
+    }
+    // A replica only holds what the backup could see, so whatever was hidden from it is dealt out
+    // again until the players holding those cards show what they are
+    if network.network_type != NetworkType::Client {
+        reveals
+            .pending
+            .extend(deal_hidden(&save, &data, &info, &mut game_rng));
+    }
+
+    for (saved, (entity, traitor_cards, treachery_cards)) in
+        save.players.iter().zip(entities.hands.into_iter())
+    {
+        if let Ok((_, mut player, prediction)) = players.get_mut(entity) {
+            player.spice = saved.spice;
+            player.traitor_cards = traitor_cards;
+            player.treachery_cards = treachery_cards;
+            if let (Some(mut prediction), Some((faction, turn))) = (prediction, saved.prediction) {
+                prediction.faction = faction;
+                prediction.turn = turn;
+            }
+        }
+    }
+
+    // Troops
+    let mut pools = HashMap::new();
+    for (entity, _, unique, _) in tokens.q0_mut().iter_mut() {
+        pools
+            .entry(unique.faction)
+            .or_insert_with(Vec::new)
+            .push(entity);
+    }
+    for (saved, &location) in save.troops.iter().zip(entities.troop_locations.iter()) {
+        let entity = pools.get_mut(&saved.faction).and_then(|pool| pool.pop());
+        if let Some((entity, mut troop, _, mut transform)) =
+            entity.and_then(|entity| tokens.q0_mut().get_mut(entity).ok())
+        {
+            troop.location = location;
+            *transform = saved.transform.into();
+            if saved.dead {
+                commands.insert_one(entity, Dead);
+            }
+        }
+    }
+
+    // Leaders
+    for saved in save.leaders.iter() {
+        if let Some((entity, _, mut transform)) = tokens
+            .q2_mut()
+            .iter_mut()
+            .find(|(_, leader, _)| leader.name == saved.name)
+        {
+            *transform = saved.transform.into();
+            if saved.dead {
+                commands.insert_one(entity, Dead);
+            } else {
+                commands.remove_one::<Dead>(entity);
+            }
+        }
+    }
+
+    // Spice
+    for (entity, spice) in spice.iter() {
+        pool.release_spice(commands, entity, spice.value);
+    }
+    for (_, _, node) in locations.iter_mut() {
+        if let Some(mut node) = node {
+            node.val = 0;
+        }
+    }
+    for (saved, &location) in save.spice.iter().zip(entities.spice_locations.iter()) {
+        let entity = pool.spawn_spice(commands, saved.value, location, saved.transform.into());
+        if let Some(faction) = saved.owner {
+            commands.insert(entity, UniqueBundle::new(faction));
+        }
+        if let Some(location) = location {
+            if let Ok((_, _, Some(mut node))) = locations.get_mut(location) {
+                node.val += saved.value;
+            }
+        }
+    }
+
+    if let Some(mut storm) = storm.iter_mut().next() {
+        storm.sector = save.storm_sector;
+    }
+
+    info.turn = save.turn;
+    info.current_turn = save.current_turn as usize;
+    info.play_order = entities.play_order;
+    info.active_player = entities.active_player;
+    info.context = save.context;
+    info.nexus = save.nexus;
+    phase.phase = save.phase;
+
+    spice_deck.discard = entities.spice_discard;
+    spice_deck.set_aside = entities.spice_set_aside;
+    spice_deck.revealed = save.spice_revealed;
+    card_effects.discard = entities.treachery_discard;
+
+    auction.cards = entities.auction_cards;
+    auction.starting_bidder = save.auction.starting_bidder as usize;
+    auction.bidder = save.auction.bidder as usize;
+    auction.high_bid = entities.high_bid;
+    auction.passes = save.auction.passes as usize;
+    auction.closed = save.auction.closed;
+
+    occupancy.ornithopters = save.ornithopters;
+    *rules = save.rules;
+    info.alliances = save.alliances;
+    info.eliminated = save.eliminated;
+    // Captives are moved behind their captor's shield again once the captures are in place
+    captures.sync(save.pending_captures, save.held_captures, Vec::new());
+    actions.log = save.actions;
+
+    queue.clear();
+    if !entities.enabled_sectors.is_empty() {
+        queue.push_single_for_context(
+            Action::Enable {
+                clickables: entities.enabled_sectors,
+            }
+            .into(),
+            save.context,
+        );
+    }
+    println!("Game loaded from {}", SAVE_PATH);
+}
+
+fn load_failed(loaded: &mut LoadedGame, err: String) {
+    println!("Failed to load the game: {}", err);
+    loaded.failed = Some(err);
+}
+
+/// The entities everything in a save refers to, worked out before any of it is applied
+struct SaveEntities {
+    /// Each saved player's entity with their traitor and treachery cards
+    hands: Vec<(Entity, Vec<Entity>, Vec<Entity>)>,
+    troop_locations: Vec<Option<Entity>>,
+    spice_locations: Vec<Option<Entity>>,
+    play_order: Vec<Entity>,
+    active_player: Option<Entity>,
+    spice_discard: Vec<Entity>,
+    spice_set_aside: Vec<Entity>,
+    treachery_discard: Vec<Entity>,
+    auction_cards: Vec<(Entity, Vec2)>,
+    high_bid: Option<(Entity, i32)>,
+    enabled_sectors: Vec<Entity>,
+}
+
+fn resolve_save(
+    save: &SaveGame,
+    cards: &[Entity],
+    (players, sectors, locations): (
+        &HashMap<Faction, Entity>,
+        &HashMap<SectorNode, Entity>,
+        &HashMap<String, Entity>,
+    ),
+) -> Result<SaveEntities, String> {
+    let player = |faction: &Faction| {
+        players
+            .get(faction)
+            .copied()
+            .ok_or_else(|| format!("{} is not in this game", faction))
+    };
+    let sector = |node: &SectorNode| {
+        sectors
+            .get(node)
+            .copied()
+            .ok_or_else(|| format!("there is no sector {} of {}", node.sector, node.location))
+    };
+    let location = |name: &String| {
+        locations
+            .get(name)
+            .copied()
+            .ok_or_else(|| format!("there is no {} on this board", name))
+    };
+    let card = |i: &u32| {
+        cards
+            .get(*i as usize)
+            .copied()
+            .ok_or_else(|| format!("there is no card {}", i))
+    };
+    let card_list = |indices: &[u32]| indices.iter().map(card).collect::<Result<Vec<_>, String>>();
+    Ok(SaveEntities {
+        hands: save
+            .players
+            .iter()
+            .map(|saved| -> Result<_, String> {
+                Ok((
+                    player(&saved.faction)?,
+                    card_list(&saved.traitor_cards)?,
+                    card_list(&saved.treachery_cards)?,
+                ))
+            })
+            .collect::<Result<_, String>>()?,
+        troop_locations: save
+            .troops
+            .iter()
+            .map(|saved| saved.location.as_ref().map(sector).transpose())
+            .collect::<Result<_, _>>()?,
+        spice_locations: save
+            .spice
+            .iter()
+            .map(|saved| saved.location.as_ref().map(location).transpose())
+            .collect::<Result<_, _>>()?,
+        play_order: save
+            .play_order
+            .iter()
+            .map(player)
+            .collect::<Result<_, _>>()?,
+        active_player: save.active_player.as_ref().map(player).transpose()?,
+        spice_discard: card_list(&save.spice_discard)?,
+        spice_set_aside: card_list(&save.spice_set_aside)?,
+        treachery_discard: card_list(&save.treachery_discard)?,
+        auction_cards: save
+            .auction
+            .cards
+            .iter()
+            .map(|(i, [x, y])| card(i).map(|card| (card, Vec2::new(*x, *y))))
+            .collect::<Result<_, _>>()?,
+        high_bid: save
+            .auction
+            .high_bid
+            .map(|(faction, amount)| player(&faction).map(|entity| (entity, amount)))
+            .transpose()?,
+        enabled_sectors: save
+            .enabled_sectors
+            .iter()
+            .map(sector)
+            .collect::<Result<_, _>>()?,
+    })
+}
@@ -0,0 +1,326 @@
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::PI,
+    fs::{self, File},
+    path::PathBuf,
+};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    components::{Dead, Disorganized, LocationSector, Player, SpiceNode, Storm, Troop, Unique},
+    data::{Faction, Location, TreacheryCard},
+    layout::UiLayout,
+    lerper::{Lerp, LerpType},
+    phase::{
+        place_spice, Action, ActionQueue, BiddingSubPhase, GamePhase, MovementSubPhase, Phase,
+        SpiceBlowSubPhase, StormSubPhase,
+    },
+    resources::{Data, GameRng, Info},
+    token_pool::TokenPool,
+    util::shuffle_deck,
+    Screen,
+};
+
+/// Where the main menu looks for a scenario when none is given on the command line
+const SCENARIO_DIR: &str = "data/scenarios";
+
+pub struct ScenarioPlugin;
+
+impl Plugin for ScenarioPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<LoadedScenario>()
+            .add_system(apply_scenario.system());
+    }
+}
+
+/// A game already under way: who is playing, what they hold and where their troops are, and
+/// which turn and phase to pick it up from
+#[derive(Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    pub turn: i32,
+    pub phase: StartPhase,
+    pub storm_sector: i32,
+    pub factions: Vec<ScenarioFaction>,
+    /// Spice lying out on the board
+    #[serde(default)]
+    pub spice: Vec<ScenarioSpice>,
+}
+
+#[derive(Deserialize)]
+pub struct ScenarioFaction {
+    pub faction: Faction,
+    pub spice: i32,
+    /// Anything not placed stays in reserve
+    #[serde(default)]
+    pub troops: Vec<ScenarioTroops>,
+    /// Names of the treachery cards in hand
+    #[serde(default)]
+    pub treachery: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ScenarioTroops {
+    pub location: String,
+    /// Defaults to the territory's lowest numbered sector
+    #[serde(default)]
+    pub sector: Option<i32>,
+    pub count: usize,
+}
+
+#[derive(Deserialize)]
+pub struct ScenarioSpice {
+    pub location: String,
+    pub amount: i32,
+}
+
+/// The phases a scenario can begin in, each from its start
+#[derive(Copy, Clone, PartialEq, Deserialize, Debug)]
+pub enum StartPhase {
+    Storm,
+    SpiceBlow,
+    Charity,
+    Bidding,
+    Revival,
+    Movement,
+    Battle,
+}
+
+impl StartPhase {
+    fn phase(self) -> Phase {
+        match self {
+            StartPhase::Storm => Phase::Storm {
+                subphase: StormSubPhase::Reveal,
+            },
+            StartPhase::SpiceBlow => Phase::SpiceBlow {
+                subphase: SpiceBlowSubPhase::Reveal,
+            },
+            StartPhase::Charity => Phase::Charity,
+            StartPhase::Bidding => Phase::Bidding {
+                subphase: BiddingSubPhase::DealCards,
+            },
+            StartPhase::Revival => Phase::Revival,
+            StartPhase::Movement => Phase::Movement {
+                subphase: MovementSubPhase::Ornithopters,
+            },
+            StartPhase::Battle => Phase::Battle,
+        }
+    }
+}
+
+impl Scenario {
+    /// The factions in the scenario, in the order they sit at the table
+    pub fn factions_in_play(&self) -> Vec<Faction> {
+        Faction::ALL
+            .iter()
+            .copied()
+            .filter(|&faction| self.factions.iter().any(|entry| entry.faction == faction))
+            .collect()
+    }
+}
+
+/// A scenario waiting to be set up in place of the usual start once the board has been spawned
+#[derive(Default)]
+pub struct LoadedScenario {
+    pub scenario: Option<Scenario>,
+}
+
+/// The scenario to play: the file given with `--scenario`, otherwise the first one in the
+/// scenario folder
+pub fn read_scenario() -> Option<Scenario> {
+    let mut args = std::env::args()
+        .skip_while(|arg| arg != "--scenario")
+        .skip(1);
+    let path = match args.next() {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let mut paths = fs::read_dir(SCENARIO_DIR)
+                .ok()?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().map_or(false, |ext| ext == "ron"))
+                .collect::<Vec<_>>();
+            paths.sort();
+            paths.into_iter().next()?
+        }
+    };
+    let file = File::open(&path)
+        .map_err(|err| println!("Couldn't open {}: {}", path.display(), err))
+        .ok()?;
+    ron::de::from_reader(file)
+        .map_err(|err| println!("Couldn't read {}: {}", path.display(), err))
+        .ok()
+}
+
+type Locations<'a, 'b, 'c> = Query<'a, (Entity, &'b Location, Option<&'c mut SpiceNode>)>;
+type Reserves<'a, 'b, 'c> = Query<'a, (Entity, &'b mut Troop, &'c Unique), Without<Dead>>;
+type TreacheryDeck<'a, 'b, 'c> = Query<'a, (Entity, &'b mut Transform, &'c TreacheryCard)>;
+
+/// Runs before the phase systems so the scenario replaces the setup phase rather than following it
+fn apply_scenario(
+    commands: &mut Commands,
+    (mut loaded, mut info, mut phase, mut queue): (
+        ResMut<LoadedScenario>,
+        ResMut<Info>,
+        ResMut<GamePhase>,
+        ResMut<ActionQueue>,
+    ),
+    (screen, data, layout, mut game_rng): (
+        Res<State<Screen>>,
+        Res<Data>,
+        Res<UiLayout>,
+        ResMut<GameRng>,
+    ),
+    mut pool: ResMut<TokenPool>,
+    (mut players, mut storm): (Query<(Entity, &mut Player)>, Query<&mut Storm>),
+    (sectors, mut locations): (Query<(Entity, &LocationSector)>, Locations),
+    (mut troops, mut cards): (Reserves, TreacheryDeck),
+) {
+    if let Screen::HostingGame = screen.current() {
+    } else {
+        return;
+    }
+    if players.iter_mut().next().is_none() {
+        return;
+    }
+    let scenario = if let Some(scenario) = loaded.scenario.take() {
+        scenario
+    } else {
+        return;
+    };
+
+    // The setup phase would have shuffled the deck before anything was dealt from it
+    shuffle_deck(
+        &mut game_rng.rng,
+        0.001,
+        &mut cards
+            .iter_mut()
+            .map(|(entity, transform, _)| (entity, transform))
+            .collect(),
+    );
+
+    let turn_tile_pts = data
+        .ui_structure
+        .get_turn_tiles()
+        .iter()
+        .map(|tile| layout.place(tile).center())
+        .collect::<Vec<_>>();
+    let mut dealt = HashSet::new();
+    let mut actions = Vec::new();
+    for (j, &entity) in info.play_order.iter().enumerate() {
+        let (_, mut player) = if let Ok(player) = players.get_mut(entity) {
+            player
+        } else {
+            continue;
+        };
+        let setup = if let Some(setup) = scenario
+            .factions
+            .iter()
+            .find(|setup| setup.faction == player.faction)
+        {
+            setup
+        } else {
+            continue;
+        };
+        player.spice = setup.spice;
+
+        for name in setup.treachery.iter() {
+            let card = cards
+                .iter_mut()
+                .map(|(card, _, treachery)| (card, treachery))
+                .find(|(card, treachery)| treachery.name == *name && !dealt.contains(card))
+                .map(|(card, _)| card);
+            let card = if let Some(card) = card {
+                card
+            } else {
+                println!("No {} left in the treachery deck", name);
+                continue;
+            };
+            dealt.insert(card);
+            player.treachery_cards.push(card);
+            actions.push(
+                Action::add_lerp(
+                    card,
+                    Lerp::deal(
+                        LerpType::world_to_ui(
+                            (
+                                turn_tile_pts[j],
+                                Quat::from_rotation_x(0.5 * PI) * Quat::from_rotation_z(PI),
+                                0.4,
+                            )
+                                .into(),
+                        ),
+                        0.0,
+                    ),
+                )
+                .then(
+                    Action::Assign {
+                        element: card,
+                        faction: player.faction,
+                    }
+                    .into(),
+                ),
+            );
+        }
+
+        let mut reserves = troops
+            .iter_mut()
+            .filter(|(_, troop, unique)| {
+                unique.faction == player.faction && troop.location.is_none()
+            })
+            .map(|(troop, _, _)| troop)
+            .collect::<Vec<_>>();
+        for placement in setup.troops.iter() {
+            let sector = sectors
+                .iter()
+                .filter(|(_, loc_sec)| {
+                    loc_sec.location.name == placement.location
+                        && placement
+                            .sector
+                            .map_or(true, |sector| sector == loc_sec.sector)
+                })
+                .min_by_key(|(_, loc_sec)| loc_sec.sector)
+                .map(|(sector, _)| sector);
+            let sector = if let Some(sector) = sector {
+                sector
+            } else {
+                println!("There is no {} on the board", placement.location);
+                continue;
+            };
+            let count = placement.count.min(reserves.len());
+            for troop in reserves.drain(..count) {
+                if let Ok((_, mut troop, _)) = troops.get_mut(troop) {
+                    troop.location = Some(sector);
+                }
+            }
+            // The troops are stacked into place from the reserves
+            commands.insert_one(sector, Disorganized);
+        }
+    }
+    queue.push_multiple(actions);
+
+    let location_entities = locations
+        .iter_mut()
+        .map(|(entity, location, _)| (location.name.clone(), entity))
+        .collect::<HashMap<_, _>>();
+    for placement in scenario.spice.iter() {
+        let location = location_entities.get(&placement.location).copied();
+        if let Some((location, Some(mut node))) = location
+            .and_then(|location| locations.get_mut(location).ok())
+            .map(|(location, _, node)| (location, node))
+        {
+            place_spice(commands, &mut pool, placement.amount, location, &node, 0);
+            node.val += placement.amount;
+        } else {
+            println!("Spice can't be placed in {}", placement.location);
+        }
+    }
+
+    if let Some(mut storm) = storm.iter_mut().next() {
+        storm.sector = scenario.storm_sector;
+    }
+    info.turn = scenario.turn;
+    phase.phase = scenario.phase.phase();
+    println!("Starting scenario: {}", scenario.name);
+}
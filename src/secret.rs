@@ -0,0 +1,328 @@
+use std::{collections::VecDeque, f32::consts::PI};
+
+use bevy::prelude::*;
+
+use crate::{
+    components::{Player, Secret, Unique},
+    data::{Faction, TraitorCard, TreacheryCard},
+    material_cache::MaterialCache,
+    migration::{is_spice_label, SecretClaim, SPICE_CLAIMS},
+    network::{Network, NetworkType, Server},
+    protocol::ServerEvent,
+    resources::{Data, Info},
+    savegame::CardKind,
+    Screen, STATE_CHANGE_STAGE,
+};
+
+pub struct SecretPlugin;
+
+impl Plugin for SecretPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SecretReveals>()
+            .init_resource::<HostOutcomes>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                deal_secrets_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                reveal_secrets_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                reveal_secrets_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                deck_order_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                play_order_system.system(),
+            );
+    }
+}
+
+/// Card identities the server has told us about that still need to be applied. An empty key hides
+/// the card in that slot again.
+#[derive(Default)]
+pub struct SecretReveals {
+    pub pending: Vec<(CardKind, u32, String)>,
+    /// Proof of what we were dealt, to show whoever hosts next
+    pub claims: Vec<SecretClaim>,
+}
+
+impl SecretReveals {
+    pub fn keep_claim(&mut self, claim: SecretClaim) {
+        if is_spice_label(&claim.label) {
+            // The host sends the amount again whenever anything about the player changes
+            self.claims.retain(|held| *held != claim);
+            let kept = self
+                .claims
+                .iter()
+                .filter(|held| held.label == claim.label)
+                .count();
+            if kept >= SPICE_CLAIMS {
+                if let Some(oldest) = self
+                    .claims
+                    .iter()
+                    .position(|held| held.label == claim.label)
+                {
+                    self.claims.remove(oldest);
+                }
+            }
+        } else {
+            self.claims.retain(|held| held.label != claim.label);
+        }
+        self.claims.push(claim);
+    }
+}
+
+/// What the host shuffled or rolled. Clients never get the seed, so they follow these rather than
+/// shuffling for themselves.
+#[derive(Default)]
+pub struct HostOutcomes {
+    pub deck_orders: Vec<(CardKind, Vec<u32>)>,
+    pub play_order: Option<Vec<Faction>>,
+    pub storm_moves: VecDeque<i32>,
+    pub spice_draws: VecDeque<String>,
+}
+
+/// Where the `i`th card up a secret deck sits
+pub fn deck_position(kind: CardKind, i: usize) -> Transform {
+    let z = if kind == CardKind::Traitor {
+        -0.3
+    } else {
+        -0.87
+    };
+    Transform::from_translation(Vec3::new(1.23, 0.0049 + (i as f32 * 0.001), z))
+        * Transform::from_rotation(Quat::from_rotation_z(PI))
+}
+
+/// Tells clients how the host stacked a secret deck. Slots are handed out at random on the host,
+/// so the order gives nothing away about which card is which.
+pub fn send_deck_order(
+    server: &mut Query<&mut Server>,
+    kind: CardKind,
+    mut cards: Vec<(u32, f32)>,
+) {
+    cards.sort_by(|(_, y1), (_, y2)| y1.partial_cmp(y2).unwrap());
+    if let Some(mut server) = server.iter_mut().next() {
+        server.broadcast(&ServerEvent::DeckOrder {
+            kind,
+            slots: cards.into_iter().map(|(slot, _)| slot).collect(),
+        });
+    }
+}
+
+fn send_secret(server: &mut Server, kind: CardKind, slot: u32, key: String, unique: &Unique) {
+    if unique.public {
+        server.broadcast(&ServerEvent::RevealCard { kind, slot, key });
+    } else {
+        let owner = server.address_of(unique.faction);
+        for address in owner.into_iter().chain(server.casters()) {
+            server.send_event(
+                address,
+                &ServerEvent::DealSecret {
+                    kind,
+                    slot,
+                    key: key.clone(),
+                },
+            );
+        }
+    }
+}
+
+/// Only the owner of a card and casters learn what it is, unless it has been made public
+fn deal_secrets_system(
+    network: Res<Network>,
+    mut server: Query<&mut Server>,
+    treachery_cards: Query<(&Secret<TreacheryCard>, &Unique), Changed<Unique>>,
+    traitor_cards: Query<(&Secret<TraitorCard>, &Unique), Changed<Unique>>,
+) {
+    if network.network_type != NetworkType::Server {
+        return;
+    }
+    if let Some(mut server) = server.iter_mut().next() {
+        for (secret, unique) in treachery_cards.iter() {
+            if let Some(ref card) = secret.value {
+                send_secret(
+                    &mut server,
+                    CardKind::Treachery,
+                    secret.slot,
+                    card.id.to_string(),
+                    unique,
+                );
+            }
+        }
+        for (secret, unique) in traitor_cards.iter() {
+            if let Some(ref card) = secret.value {
+                send_secret(
+                    &mut server,
+                    CardKind::Traitor,
+                    secret.slot,
+                    card.leader.name.clone(),
+                    unique,
+                );
+            }
+        }
+    }
+}
+
+fn reveal_secrets_system(
+    commands: &mut Commands,
+    data: Res<Data>,
+    (asset_server, mut materials, mut cache): (
+        Res<AssetServer>,
+        ResMut<Assets<StandardMaterial>>,
+        ResMut<MaterialCache>,
+    ),
+    mut reveals: ResMut<SecretReveals>,
+    mut treachery_cards: Query<(Entity, &mut Secret<TreacheryCard>, &Children)>,
+    mut traitor_cards: Query<(Entity, &mut Secret<TraitorCard>, &Children)>,
+    mut faces: Query<&mut Handle<StandardMaterial>>,
+) {
+    // Hold on to anything that arrives before the cards have been spawned
+    if reveals.pending.is_empty() || treachery_cards.iter_mut().next().is_none() {
+        return;
+    }
+    for (kind, slot, key) in reveals.pending.drain(..) {
+        if key.is_empty() {
+            // Shuffled back into the deck, so nobody here may know what it is any more
+            let hidden = match kind {
+                CardKind::Treachery => treachery_cards
+                    .iter_mut()
+                    .find(|(_, secret, _)| secret.slot == slot)
+                    .map(|(entity, mut secret, children)| {
+                        secret.value = None;
+                        commands.remove_one::<TreacheryCard>(entity);
+                        (children[0], "treachery/treachery_back.png")
+                    }),
+                CardKind::Traitor => traitor_cards
+                    .iter_mut()
+                    .find(|(_, secret, _)| secret.slot == slot)
+                    .map(|(entity, mut secret, children)| {
+                        secret.value = None;
+                        commands.remove_one::<TraitorCard>(entity);
+                        (children[0], "traitor/traitor_back.png")
+                    }),
+                _ => None,
+            };
+            if let Some((face, texture)) = hidden {
+                if let Ok(mut material) = faces.get_mut(face) {
+                    *material = cache.textured(texture, &asset_server, &mut materials);
+                }
+            }
+            continue;
+        }
+        let revealed = match kind {
+            CardKind::Treachery => data
+                .treachery_cards
+                .iter()
+                .find(|card| card.id.to_string() == key)
+                .and_then(|card| {
+                    treachery_cards
+                        .iter_mut()
+                        .find(|(_, secret, _)| secret.slot == slot)
+                        .map(|(entity, mut secret, children)| {
+                            secret.value = Some(card.clone());
+                            commands.insert_one(entity, card.clone());
+                            (
+                                children[0],
+                                format!("treachery/treachery_{}.png", card.texture),
+                            )
+                        })
+                }),
+            CardKind::Traitor => data
+                .leaders
+                .iter()
+                .find(|leader| leader.name == key)
+                .and_then(|leader| {
+                    traitor_cards
+                        .iter_mut()
+                        .find(|(_, secret, _)| secret.slot == slot)
+                        .map(|(entity, mut secret, children)| {
+                            let card = TraitorCard {
+                                leader: leader.clone(),
+                            };
+                            secret.value = Some(card.clone());
+                            commands.insert_one(entity, card);
+                            (
+                                children[0],
+                                format!("traitor/traitor_{}.png", leader.texture),
+                            )
+                        })
+                }),
+            _ => None,
+        };
+        if let Some((face, texture)) = revealed {
+            if let Ok(mut material) = faces.get_mut(face) {
+                *material = cache.textured(&texture, &asset_server, &mut materials);
+            }
+        } else {
+            println!("Could not reveal {:?} card {} in slot {}", kind, key, slot);
+        }
+    }
+}
+
+/// Restacks the decks the way the host shuffled them
+fn deck_order_system(
+    mut outcomes: ResMut<HostOutcomes>,
+    mut treachery_cards: Query<(&Secret<TreacheryCard>, &mut Transform)>,
+    mut traitor_cards: Query<(&Secret<TraitorCard>, &mut Transform)>,
+) {
+    // Hold on to anything that arrives before the cards have been spawned
+    if outcomes.deck_orders.is_empty() || treachery_cards.iter_mut().next().is_none() {
+        return;
+    }
+    for (kind, slots) in outcomes.deck_orders.drain(..) {
+        for (i, slot) in slots.into_iter().enumerate() {
+            let transform = match kind {
+                CardKind::Treachery => treachery_cards
+                    .iter_mut()
+                    .find(|(secret, _)| secret.slot == slot)
+                    .map(|(_, transform)| transform),
+                CardKind::Traitor => traitor_cards
+                    .iter_mut()
+                    .find(|(secret, _)| secret.slot == slot)
+                    .map(|(_, transform)| transform),
+                _ => None,
+            };
+            if let Some(mut transform) = transform {
+                *transform = deck_position(kind, i);
+            } else {
+                println!("Could not find {:?} card in slot {}", kind, slot);
+            }
+        }
+    }
+}
+
+/// Puts the players in the order the host drew once they have all been spawned
+fn play_order_system(
+    mut outcomes: ResMut<HostOutcomes>,
+    mut info: ResMut<Info>,
+    players: Query<(Entity, &Player)>,
+) {
+    let ready = outcomes.play_order.as_ref().map_or(false, |factions| {
+        !factions.is_empty() && info.play_order.len() == factions.len()
+    });
+    if !ready {
+        return;
+    }
+    if let Some(factions) = outcomes.play_order.take() {
+        info.play_order = factions
+            .into_iter()
+            .filter_map(|faction| {
+                players
+                    .iter()
+                    .find(|(_, player)| player.faction == faction)
+                    .map(|(entity, _)| entity)
+            })
+            .collect();
+    }
+}
@@ -0,0 +1,555 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    path::PathBuf,
+};
+
+use bevy::{prelude::*, window::WindowMode};
+use serde::{Deserialize, Serialize};
+
+use crate::locale::DEFAULT_LANGUAGE;
+
+const SETTINGS_FILE: &str = "settings.ron";
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let settings = Settings::load();
+        let mut window = WindowDescriptor {
+            vsync: settings.graphics.vsync,
+            mode: settings.graphics.window_mode(),
+            ..Default::default()
+        };
+        if let Some((width, height)) = settings.graphics.resolution {
+            window.width = width as f32;
+            window.height = height as f32;
+        }
+        // Multisampling is baked into the render pipelines, so it only changes on restart
+        app.add_resource(Msaa {
+            samples: settings.graphics.msaa,
+        })
+        .add_resource(window)
+        .add_resource(settings)
+        .add_system(apply_window_settings.system())
+        .add_system(light_quality_system.system());
+    }
+}
+
+/// Every action the game input reads from a key, so players can move them around
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Binding {
+    PanForward,
+    PanBack,
+    PanLeft,
+    PanRight,
+    TurnLeft,
+    TurnRight,
+    ResetCamera,
+    LeaveView,
+    Pause,
+    ViewMain,
+    ViewBoard,
+    ViewShield,
+    ViewTreachery,
+    ViewTraitor,
+    ViewSpice,
+    ViewStorm,
+    Increase,
+    Decrease,
+    Submit,
+    Pass,
+    Confirm,
+    Undo,
+    CycleLeader,
+    Traitor1,
+    Traitor2,
+    Traitor3,
+    Traitor4,
+    QuickSave,
+    Rules,
+    Emote,
+    Restart,
+    DebugOverlay,
+    EventLog,
+    Navigate,
+    Performance,
+}
+
+impl Binding {
+    pub const ALL: [Binding; 35] = [
+        Binding::PanForward,
+        Binding::PanBack,
+        Binding::PanLeft,
+        Binding::PanRight,
+        Binding::TurnLeft,
+        Binding::TurnRight,
+        Binding::ResetCamera,
+        Binding::LeaveView,
+        Binding::Pause,
+        Binding::ViewMain,
+        Binding::ViewBoard,
+        Binding::ViewShield,
+        Binding::ViewTreachery,
+        Binding::ViewTraitor,
+        Binding::ViewSpice,
+        Binding::ViewStorm,
+        Binding::Increase,
+        Binding::Decrease,
+        Binding::Submit,
+        Binding::Pass,
+        Binding::Confirm,
+        Binding::Undo,
+        Binding::CycleLeader,
+        Binding::Traitor1,
+        Binding::Traitor2,
+        Binding::Traitor3,
+        Binding::Traitor4,
+        Binding::QuickSave,
+        Binding::Rules,
+        Binding::Emote,
+        Binding::Restart,
+        Binding::DebugOverlay,
+        Binding::EventLog,
+        Binding::Navigate,
+        Binding::Performance,
+    ];
+
+    /// The preset camera views, in the same order as `CameraNodes::views`
+    pub const VIEWS: [Binding; 7] = [
+        Binding::ViewMain,
+        Binding::ViewBoard,
+        Binding::ViewShield,
+        Binding::ViewTreachery,
+        Binding::ViewTraitor,
+        Binding::ViewSpice,
+        Binding::ViewStorm,
+    ];
+
+    pub const TRAITORS: [Binding; 4] = [
+        Binding::Traitor1,
+        Binding::Traitor2,
+        Binding::Traitor3,
+        Binding::Traitor4,
+    ];
+
+    pub fn default_key(&self) -> KeyCode {
+        match self {
+            Binding::PanForward => KeyCode::W,
+            Binding::PanBack => KeyCode::S,
+            Binding::PanLeft => KeyCode::A,
+            Binding::PanRight => KeyCode::D,
+            Binding::TurnLeft => KeyCode::Q,
+            Binding::TurnRight => KeyCode::E,
+            Binding::ResetCamera => KeyCode::Home,
+            Binding::LeaveView => KeyCode::End,
+            Binding::Pause => KeyCode::Escape,
+            Binding::ViewMain => KeyCode::F2,
+            Binding::ViewBoard => KeyCode::F3,
+            Binding::ViewShield => KeyCode::F4,
+            Binding::ViewTreachery => KeyCode::F6,
+            Binding::ViewTraitor => KeyCode::F7,
+            Binding::ViewSpice => KeyCode::F8,
+            Binding::ViewStorm => KeyCode::F9,
+            Binding::Increase => KeyCode::Up,
+            Binding::Decrease => KeyCode::Down,
+            Binding::Submit => KeyCode::Return,
+            Binding::Pass => KeyCode::Back,
+            Binding::Confirm => KeyCode::Space,
+            Binding::Undo => KeyCode::Delete,
+            Binding::CycleLeader => KeyCode::L,
+            Binding::Traitor1 => KeyCode::Key1,
+            Binding::Traitor2 => KeyCode::Key2,
+            Binding::Traitor3 => KeyCode::Key3,
+            Binding::Traitor4 => KeyCode::Key4,
+            Binding::QuickSave => KeyCode::F5,
+            Binding::Rules => KeyCode::F1,
+            Binding::Emote => KeyCode::G,
+            Binding::Restart => KeyCode::F10,
+            Binding::DebugOverlay => KeyCode::F12,
+            Binding::EventLog => KeyCode::J,
+            Binding::Navigate => KeyCode::N,
+            Binding::Performance => KeyCode::F11,
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Binding::PanForward => "Pan forward",
+            Binding::PanBack => "Pan back",
+            Binding::PanLeft => "Pan left",
+            Binding::PanRight => "Pan right",
+            Binding::TurnLeft => "Turn left",
+            Binding::TurnRight => "Turn right",
+            Binding::ResetCamera => "Reset camera",
+            Binding::LeaveView => "Leave view",
+            Binding::Pause => "Pause menu",
+            Binding::ViewMain => "Main view",
+            Binding::ViewBoard => "Board view",
+            Binding::ViewShield => "Shield view",
+            Binding::ViewTreachery => "Treachery deck view",
+            Binding::ViewTraitor => "Traitor deck view",
+            Binding::ViewSpice => "Spice deck view",
+            Binding::ViewStorm => "Storm deck view",
+            Binding::Increase => "Increase",
+            Binding::Decrease => "Decrease",
+            Binding::Submit => "Submit",
+            Binding::Pass => "Pass",
+            Binding::Confirm => "Confirm",
+            Binding::Undo => "Undo",
+            Binding::CycleLeader => "Cycle leader",
+            Binding::Traitor1 => "Traitor 1",
+            Binding::Traitor2 => "Traitor 2",
+            Binding::Traitor3 => "Traitor 3",
+            Binding::Traitor4 => "Traitor 4",
+            Binding::QuickSave => "Quick save",
+            Binding::Rules => "Rules reference",
+            Binding::Emote => "Emotes",
+            Binding::Restart => "Restart",
+            Binding::DebugOverlay => "Debug overlay",
+            Binding::EventLog => "Event log",
+            Binding::Navigate => "Navigate buttons",
+            Binding::Performance => "Performance overlay",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphicsSettings {
+    pub msaa: u32,
+    pub vsync: bool,
+    pub mode: DisplayMode,
+    /// Size of the window, or of the screen in exclusive fullscreen. None leaves it to the
+    /// platform.
+    pub resolution: Option<(u32, u32)>,
+    pub lighting: LightQuality,
+    /// Multiplies the size of the HUD, on top of the display's own scale factor
+    pub ui_scale: f32,
+    /// Stacks of more troops than this stand as a single marker, 0 keeping every token apart
+    pub troop_markers: u32,
+}
+
+impl GraphicsSettings {
+    fn window_mode(&self) -> WindowMode {
+        match self.mode {
+            DisplayMode::Windowed => WindowMode::Windowed,
+            DisplayMode::Borderless => WindowMode::BorderlessFullscreen,
+            DisplayMode::Fullscreen => WindowMode::Fullscreen {
+                use_size: self.resolution.is_some(),
+            },
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum DisplayMode {
+    Windowed,
+    Borderless,
+    /// Takes over the screen, switching it to the chosen resolution
+    Fullscreen,
+}
+
+impl DisplayMode {
+    pub const ALL: [DisplayMode; 3] = [
+        DisplayMode::Windowed,
+        DisplayMode::Borderless,
+        DisplayMode::Fullscreen,
+    ];
+
+    pub fn key(self) -> &'static str {
+        match self {
+            DisplayMode::Windowed => "settings.mode.windowed",
+            DisplayMode::Borderless => "settings.mode.borderless",
+            DisplayMode::Fullscreen => "settings.mode.fullscreen",
+        }
+    }
+}
+
+/// How many lights the board is lit with. Every light costs a pass over each mesh.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum LightQuality {
+    Low,
+    Medium,
+    High,
+}
+
+impl LightQuality {
+    pub const ALL: [LightQuality; 3] =
+        [LightQuality::Low, LightQuality::Medium, LightQuality::High];
+
+    pub fn key(self) -> &'static str {
+        match self {
+            LightQuality::Low => "settings.quality.low",
+            LightQuality::Medium => "settings.quality.medium",
+            LightQuality::High => "settings.quality.high",
+        }
+    }
+
+    /// Lights added to the main one, from `FILL_LIGHTS`
+    fn fill_lights(self) -> usize {
+        match self {
+            LightQuality::Low => 0,
+            LightQuality::Medium => 1,
+            LightQuality::High => FILL_LIGHTS.len(),
+        }
+    }
+}
+
+/// Resolutions offered in the settings menu, besides leaving it to the platform
+pub const RESOLUTIONS: [(u32, u32); 5] = [
+    (1280, 720),
+    (1600, 900),
+    (1920, 1080),
+    (2560, 1440),
+    (3840, 2160),
+];
+
+/// Where the fill lights sit, softening the shadowed side of the board and the tokens on it
+const FILL_LIGHTS: [(f32, f32, f32); 2] = [(-10.0, 6.0, -8.0), (0.0, 8.0, -12.0)];
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        GraphicsSettings {
+            msaa: 4,
+            vsync: true,
+            mode: DisplayMode::Windowed,
+            resolution: None,
+            lighting: LightQuality::High,
+            ui_scale: 1.0,
+            troop_markers: 0,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessibilitySettings {
+    /// Shows each faction's logo on its troop tokens, so they can be told apart without color
+    pub faction_icons: bool,
+    /// Swaps the menu and HUD colors for ones that stand out more
+    pub high_contrast: bool,
+    /// Multiplies the size of all text, on top of the UI scale
+    pub font_scale: f32,
+}
+
+impl Default for AccessibilitySettings {
+    fn default() -> Self {
+        AccessibilitySettings {
+            faction_icons: false,
+            high_contrast: false,
+            font_scale: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OnlineSettings {
+    /// Base URL of a lobby directory, like `http://example.com:8080`. Empty keeps games off it.
+    pub directory: String,
+    /// What hosted games are called in game lists. Empty uses the account name.
+    pub game_name: String,
+    /// Asked of anyone joining a game we host, and sent when joining someone else's
+    pub password: String,
+    /// What other players see us as. Empty uses the account name.
+    pub player_name: String,
+    /// Minutes spectators of a game we host lag behind it, so nobody can play off a stream.
+    /// Zero shows them the game live.
+    pub spectator_delay: u32,
+    /// Lets spectators of a game we host see every hand
+    pub allow_casters: bool,
+    /// Asks to see every hand when spectating, if the host allows it
+    pub caster: bool,
+    /// Shows our profile's rating next to our name in lobbies, and lets games against others who
+    /// share theirs move it
+    pub share_rating: bool,
+}
+
+/// Player preferences, kept in the user's config directory between runs
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Only the keys that differ from the defaults need to be here
+    pub bindings: HashMap<Binding, KeyCode>,
+    pub camera_sensitivity: f32,
+    /// Scales both the music and the sound effects
+    pub volume: f32,
+    pub music_volume: f32,
+    pub effects_volume: f32,
+    pub graphics: GraphicsSettings,
+    pub online: OnlineSettings,
+    pub accessibility: AccessibilitySettings,
+    /// Name of a file in the language folder
+    pub language: String,
+    /// Lets every action others could answer with a Karama go ahead without asking
+    pub auto_pass: bool,
+    /// Keeps the last few minutes of play in memory, to go in a bug report
+    pub bug_capture: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            bindings: HashMap::new(),
+            camera_sensitivity: 1.0,
+            volume: 0.8,
+            music_volume: 0.6,
+            effects_volume: 1.0,
+            graphics: GraphicsSettings::default(),
+            online: OnlineSettings::default(),
+            accessibility: AccessibilitySettings::default(),
+            language: DEFAULT_LANGUAGE.to_string(),
+            auto_pass: false,
+            bug_capture: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn key(&self, binding: Binding) -> KeyCode {
+        self.bindings
+            .get(&binding)
+            .copied()
+            .unwrap_or_else(|| binding.default_key())
+    }
+
+    pub fn pressed(&self, input: &Input<KeyCode>, binding: Binding) -> bool {
+        input.pressed(self.key(binding))
+    }
+
+    pub fn just_pressed(&self, input: &Input<KeyCode>, binding: Binding) -> bool {
+        input.just_pressed(self.key(binding))
+    }
+
+    /// Whatever else is already on this key
+    pub fn conflict(&self, binding: Binding, key: KeyCode) -> Option<Binding> {
+        Binding::ALL
+            .iter()
+            .copied()
+            .find(|&other| other != binding && self.key(other) == key)
+    }
+
+    /// Moves an action onto a new key, unless another action already uses it
+    pub fn rebind(&mut self, binding: Binding, key: KeyCode) -> Result<(), Binding> {
+        if let Some(other) = self.conflict(binding, key) {
+            return Err(other);
+        }
+        if key == binding.default_key() {
+            self.bindings.remove(&binding);
+        } else {
+            self.bindings.insert(binding, key);
+        }
+        Ok(())
+    }
+
+    /// Falls back to the defaults when there is no settings file yet or it can't be read
+    pub fn load() -> Self {
+        let path = settings_path();
+        match File::open(&path) {
+            Ok(file) => ron::de::from_reader(file).unwrap_or_else(|err| {
+                println!(
+                    "Ignoring unreadable settings in {}: {}",
+                    path.display(),
+                    err
+                );
+                Settings::default()
+            }),
+            Err(_) => Settings::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        // A browser has nowhere to keep them, so they last as long as the page
+        if cfg!(target_arch = "wasm32") {
+            return;
+        }
+        let path = settings_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).expect("Failed to create settings directory!");
+        }
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .expect("Failed to serialize settings!");
+        fs::write(&path, text).expect("Failed to write settings file!");
+        println!("Settings saved to {}", path.display());
+    }
+}
+
+fn settings_path() -> PathBuf {
+    config_dir().join(SETTINGS_FILE)
+}
+
+/// The platform's per-user config directory, or the working directory if there isn't one
+pub fn config_dir() -> PathBuf {
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|base| base.join("dune")).unwrap_or_default()
+}
+
+/// Puts the window in line with the settings as soon as they change
+fn apply_window_settings(settings: ChangedRes<Settings>, mut windows: ResMut<Windows>) {
+    if let Some(window) = windows.get_primary_mut() {
+        let graphics = &settings.graphics;
+        if window.vsync() != graphics.vsync {
+            window.set_vsync(graphics.vsync);
+        }
+        let mode = graphics.window_mode();
+        if std::mem::discriminant(&window.mode()) != std::mem::discriminant(&mode) {
+            window.set_mode(mode);
+        }
+        if let Some((width, height)) = graphics.resolution {
+            let (width, height) = (width as f32, height as f32);
+            if window.requested_width() != width || window.requested_height() != height {
+                window.set_resolution(width, height);
+            }
+        }
+    }
+}
+
+/// The light every game is lit by, whatever the quality
+pub struct KeyLight;
+
+struct FillLight;
+
+/// Adds or takes away fill lights to match the light quality, while there is a board to light
+fn light_quality_system(
+    commands: &mut Commands,
+    settings: Res<Settings>,
+    key_lights: Query<&KeyLight>,
+    fill_lights: Query<Entity, With<FillLight>>,
+) {
+    let wanted = if key_lights.iter().next().is_some() {
+        settings.graphics.lighting.fill_lights()
+    } else {
+        0
+    };
+    if fill_lights.iter().count() == wanted {
+        return;
+    }
+    for entity in fill_lights.iter() {
+        commands.despawn_recursive(entity);
+    }
+    for &(x, y, z) in FILL_LIGHTS.iter().take(wanted) {
+        commands
+            .spawn(LightBundle {
+                light: Light {
+                    color: Color::rgb(0.35, 0.33, 0.3),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(Vec3::new(x, y, z)),
+                ..Default::default()
+            })
+            .with(FillLight);
+    }
+}
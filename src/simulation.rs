@@ -0,0 +1,666 @@
+//! Plays whole games between random and greedy bots on the rules alone, with no window, checking
+//! after every step that nothing the rules guarantee has been broken. Every rule comes from
+//! `dune_core`, the same as the game itself. Run with `cargo test simulated`, and set
+//! `DUNE_SIMULATION_GAMES` to play more games or `DUNE_SIMULATION_SEED` to replay a particular run.
+
+use std::{collections::HashMap, env, fs::File};
+
+use rand::{prelude::SliceRandom, rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    data::SpiceCard,
+    decks::{DeckPreset, STANDARD_DECK},
+    dune_core::{
+        check_action, divide_spice, pick_spice, BiddingSubPhase, Context, Faction,
+        MovementSubPhase, Occupancy, Phase, Requirement, SectorNode, SetupSubPhase,
+        SpiceBlowSubPhase, TableState, Terrain, LAST_TURN, MAX_PLAYERS, MIN_PLAYERS,
+        STRONGHOLDS_TO_WIN, STRONGHOLD_LIMIT,
+    },
+};
+
+/// Troops each faction brings to the game
+const TROOPS: i32 = 20;
+/// Spice in the bank at the start, which everything is paid out of and back into
+const BANK: i32 = 1000;
+/// A turn that takes more steps than this is stuck
+const MAX_STEPS: usize = 64;
+/// Games played unless `DUNE_SIMULATION_GAMES` says otherwise
+const GAMES: usize = 1000;
+const STRONGHOLDS: [&str; 5] = [
+    "Arrakeen",
+    "Carthag",
+    "Sietch Tabr",
+    "Tuek's Sietch",
+    "Habbanya Sietch",
+];
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Strategy {
+    /// Does whatever, whenever
+    Random,
+    /// Goes all in on spice and strongholds
+    Greedy,
+}
+
+struct Simulation {
+    rng: StdRng,
+    factions: Vec<Faction>,
+    strategies: HashMap<Faction, Strategy>,
+    spice: HashMap<Faction, i32>,
+    reserves: HashMap<Faction, i32>,
+    tanks: HashMap<Faction, i32>,
+    occupancy: Occupancy,
+    terrain: HashMap<String, Terrain>,
+    /// Every territory, in a fixed order so a seed always plays out the same way
+    territories: Vec<String>,
+    board_spice: HashMap<String, i32>,
+    bank: i32,
+    spice_cards: Vec<SpiceCard>,
+    turn: i32,
+    phase: Phase,
+    context: Context,
+    active: Option<Faction>,
+    winners: Option<Vec<Faction>>,
+}
+
+impl Simulation {
+    fn new(seed: u64, spice_cards: &[SpiceCard]) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        // Mostly the base game, with some of the expansion factions thrown in now and then
+        let factions = if rng.gen_bool(0.5) {
+            Faction::BASE.to_vec()
+        } else {
+            let count = rng.gen_range(MIN_PLAYERS..=Faction::ALL.len().min(MAX_PLAYERS));
+            let mut factions = Faction::ALL
+                .choose_multiple(&mut rng, count)
+                .copied()
+                .collect::<Vec<_>>();
+            factions.sort_by_key(|faction| faction.index());
+            factions
+        };
+        let strategies = factions
+            .iter()
+            .map(|&faction| {
+                let strategy = if rng.gen_bool(0.5) {
+                    Strategy::Random
+                } else {
+                    Strategy::Greedy
+                };
+                (faction, strategy)
+            })
+            .collect();
+
+        let mut terrain = HashMap::new();
+        for card in spice_cards.iter().filter(|card| !card.is_shai_hulud()) {
+            terrain.insert(card.name.clone(), Terrain::Sand);
+        }
+        for faction in factions.iter() {
+            for location in faction.initial_values().1.unwrap_or_default() {
+                terrain.insert(location, Terrain::Rock);
+            }
+        }
+        for name in STRONGHOLDS.iter() {
+            terrain.insert(name.to_string(), Terrain::Stronghold);
+        }
+        terrain.insert("Polar Sink".to_string(), Terrain::PolarSink);
+        let mut territories = terrain.keys().cloned().collect::<Vec<_>>();
+        territories.sort();
+        let mut occupancy = Occupancy::default();
+        occupancy.learn_terrain(&terrain);
+
+        Simulation {
+            rng,
+            spice: factions.iter().map(|&faction| (faction, 0)).collect(),
+            reserves: factions.iter().map(|&faction| (faction, TROOPS)).collect(),
+            tanks: factions.iter().map(|&faction| (faction, 0)).collect(),
+            factions,
+            strategies,
+            occupancy,
+            terrain,
+            territories,
+            board_spice: HashMap::new(),
+            bank: BANK,
+            spice_cards: spice_cards.to_vec(),
+            turn: 0,
+            phase: Phase::Setup {
+                subphase: SetupSubPhase::ChooseFactions,
+            },
+            context: Context::None,
+            active: None,
+            winners: None,
+        }
+    }
+
+    fn play(&mut self) -> Result<Vec<Faction>, String> {
+        let mut steps = 0;
+        loop {
+            let turn = self.turn;
+            self.step()?;
+            self.check_invariants()?;
+            if let Some(winners) = self.winners.clone() {
+                return self.check_winners(winners);
+            }
+            steps = if self.turn == turn { steps + 1 } else { 0 };
+            if steps > MAX_STEPS {
+                return Err(format!("the turn never ended after {} steps", steps));
+            }
+        }
+    }
+
+    fn step(&mut self) -> Result<(), String> {
+        // What each phase waits on, the way the game sets it before handing out turns
+        self.context = match self.phase {
+            Phase::Bidding { .. } => Context::Bidding,
+            Phase::Revival => Context::Reviving,
+            Phase::Movement {
+                subphase: MovementSubPhase::Ship,
+            } => Context::Shipping,
+            _ => Context::None,
+        };
+        match self.phase {
+            Phase::Setup {
+                subphase: SetupSubPhase::ChooseFactions,
+            } => self.setup(),
+            Phase::SpiceBlow {
+                subphase: SpiceBlowSubPhase::Reveal,
+            } => self.spice_blow(),
+            Phase::Charity => self.charity(),
+            Phase::Bidding {
+                subphase: BiddingSubPhase::Bidding,
+            } => self.bidding()?,
+            Phase::Revival => self.revival()?,
+            Phase::Movement {
+                subphase: MovementSubPhase::Ornithopters,
+            } => self.occupancy.take_ornithopters(),
+            Phase::Movement {
+                subphase: MovementSubPhase::Ship,
+            } => self.shipping()?,
+            Phase::Movement {
+                subphase: MovementSubPhase::Move,
+            } => self.check_victory(),
+            Phase::Battle => {
+                self.battles()?;
+                self.check_victory();
+            }
+            Phase::Collection => self.collection(),
+            Phase::Control => self.control(),
+            _ => (),
+        }
+        self.context = Context::None;
+        self.active = None;
+        self.phase.advance();
+        Ok(())
+    }
+
+    /// Asks the rules whether the faction may make this decision right now
+    fn decide(&self, faction: Faction, requirement: Requirement) -> Result<(), String> {
+        check_action(
+            faction,
+            requirement,
+            &TableState {
+                factions_in_play: &self.factions,
+                phase: self.phase,
+                context: self.context,
+                active: self.active,
+                negotiating: false,
+            },
+        )
+        .map_err(|reason| format!("{} was turned away: {}", faction, reason))
+    }
+
+    fn troops_at(&self, faction: Faction, territory: &str) -> i32 {
+        self.occupancy
+            .territory(territory)
+            .get(&faction)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn troops_on_board(&self, faction: Faction) -> i32 {
+        self.occupancy
+            .sectors
+            .values()
+            .filter_map(|factions| factions.get(&faction))
+            .sum()
+    }
+
+    fn place(&mut self, faction: Faction, territory: &str, troops: i32) {
+        *self.reserves.get_mut(&faction).unwrap() -= troops;
+        *self
+            .occupancy
+            .sectors
+            .entry(SectorNode::new(territory, 0))
+            .or_default()
+            .entry(faction)
+            .or_insert(0) += troops;
+    }
+
+    /// Sends `troops` of the faction's troops in a territory to the Tleilaxu Tanks
+    fn kill(&mut self, faction: Faction, territory: &str, troops: i32) {
+        let node = SectorNode::new(territory, 0);
+        let factions = self.occupancy.sectors.get_mut(&node).unwrap();
+        let left = factions[&faction] - troops;
+        if left > 0 {
+            factions.insert(faction, left);
+        } else {
+            factions.remove(&faction);
+        }
+        if factions.is_empty() {
+            self.occupancy.sectors.remove(&node);
+        }
+        *self.tanks.get_mut(&faction).unwrap() += troops;
+    }
+
+    /// Pays out of the faction's tokens, the way a player would, to another faction or the bank
+    fn pay(&mut self, from: Faction, to: Option<Faction>, amount: i32) -> Result<(), String> {
+        let held = self.spice[&from];
+        let (tens, fives, twos, ones) = divide_spice(held);
+        if tens * 10 + fives * 5 + twos * 2 + ones != held {
+            return Err(format!("{} spice was broken into the wrong tokens", held));
+        }
+        let tokens = std::iter::repeat(10)
+            .take(tens as usize)
+            .chain(std::iter::repeat(5).take(fives as usize))
+            .chain(std::iter::repeat(2).take(twos as usize))
+            .chain(std::iter::repeat(1).take(ones as usize))
+            .map(|value| (value, value))
+            .collect::<Vec<_>>();
+        let paid = pick_spice(&tokens, amount)
+            .ok_or_else(|| format!("{} couldn't pay {} with {} spice", from, amount, held))?
+            .into_iter()
+            .sum::<i32>();
+        if paid < amount {
+            return Err(format!("{} only handed over {} of {}", from, paid, amount));
+        }
+        // Anything over is given back as change
+        *self.spice.get_mut(&from).unwrap() -= amount;
+        match to {
+            Some(to) => *self.spice.get_mut(&to).unwrap() += amount,
+            None => self.bank += amount,
+        }
+        Ok(())
+    }
+
+    /// Hands the faction spice from the bank
+    fn pay_out(&mut self, faction: Faction, amount: i32) {
+        self.bank -= amount;
+        *self.spice.get_mut(&faction).unwrap() += amount;
+    }
+
+    /// Whoever a payment goes to, when it isn't the faction paying it
+    fn payee(&self, receiver: Faction, payer: Faction) -> Option<Faction> {
+        Some(receiver).filter(|&receiver| receiver != payer && self.factions.contains(&receiver))
+    }
+
+    fn setup(&mut self) {
+        for faction in self.factions.clone() {
+            let (troops, locations, spice) = faction.initial_values();
+            self.pay_out(faction, spice);
+            if troops == 0 {
+                continue;
+            }
+            let territory = match locations.and_then(|locations| locations.first().cloned()) {
+                Some(territory) => territory,
+                None => self.territories.choose(&mut self.rng).unwrap().clone(),
+            };
+            if !self.occupancy.stronghold_full(&territory, faction) {
+                self.place(faction, &territory, troops);
+            }
+        }
+    }
+
+    fn spice_blow(&mut self) {
+        let card = self.spice_cards.choose(&mut self.rng).unwrap().clone();
+        if !card.is_shai_hulud() {
+            self.bank -= card.amount;
+            *self.board_spice.entry(card.name).or_insert(0) += card.amount;
+        }
+    }
+
+    fn charity(&mut self) {
+        for faction in self.factions.clone() {
+            let amount = faction.charity(self.spice[&faction]);
+            if amount > 0 {
+                self.pay_out(faction, amount);
+            }
+        }
+    }
+
+    /// One card goes up for each faction, and goes to the highest bidder
+    fn bidding(&mut self) -> Result<(), String> {
+        for _ in 0..self.factions.len() {
+            let mut high_bid = None;
+            for faction in self.factions.clone() {
+                self.active = Some(faction);
+                let held = self.spice[&faction];
+                let bid = match self.strategies[&faction] {
+                    Strategy::Random => self.rng.gen_range(0..=held / 2),
+                    Strategy::Greedy => held.min(3),
+                };
+                if bid > high_bid.map_or(0, |(_, amount)| amount) {
+                    self.decide(faction, Requirement::Turn(&[Context::Bidding]))?;
+                    high_bid = Some((faction, bid));
+                }
+            }
+            if let Some((winner, amount)) = high_bid {
+                // The Emperor is paid for every card bought from the auction
+                let payee = self.payee(Faction::Emperor, winner);
+                self.pay(winner, payee, amount)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn revival(&mut self) -> Result<(), String> {
+        for faction in self.factions.clone() {
+            self.active = Some(faction);
+            let dead = self.tanks[&faction];
+            if dead == 0 {
+                continue;
+            }
+            let free = dead.min(faction.free_revivals());
+            let most = dead.min(faction.revival_limit());
+            let mut troops = match self.strategies[&faction] {
+                Strategy::Random => self.rng.gen_range(free..=most),
+                Strategy::Greedy => most,
+            };
+            while troops > free && faction.revival_cost(troops) > self.spice[&faction] {
+                troops -= 1;
+            }
+            if troops == 0 {
+                continue;
+            }
+            self.decide(faction, Requirement::Turn(&[Context::Reviving]))?;
+            self.pay(faction, None, faction.revival_cost(troops))?;
+            *self.tanks.get_mut(&faction).unwrap() -= troops;
+            *self.reserves.get_mut(&faction).unwrap() += troops;
+        }
+        Ok(())
+    }
+
+    fn shipping(&mut self) -> Result<(), String> {
+        for faction in self.factions.clone() {
+            self.active = Some(faction);
+            let reserves = self.reserves[&faction];
+            if reserves == 0 {
+                continue;
+            }
+            let (territory, mut troops) = match self.strategies[&faction] {
+                Strategy::Random => {
+                    if self.rng.gen_bool(0.5) {
+                        continue;
+                    }
+                    let territory = self.territories.choose(&mut self.rng).unwrap().clone();
+                    (territory, self.rng.gen_range(1..=reserves))
+                }
+                Strategy::Greedy => {
+                    // The richest spice field, or a stronghold nobody else is holding
+                    let richest = self
+                        .territories
+                        .iter()
+                        .filter(|territory| self.board_spice.contains_key(*territory))
+                        .max_by_key(|territory| self.board_spice[*territory])
+                        .cloned();
+                    let open = STRONGHOLDS
+                        .iter()
+                        .find(|name| self.occupancy.factions_in(name).is_empty())
+                        .map(|name| name.to_string());
+                    match open.or(richest) {
+                        Some(territory) => (territory, reserves),
+                        None => continue,
+                    }
+                }
+            };
+            if self.occupancy.stronghold_full(&territory, faction) {
+                continue;
+            }
+            let terrain = self.terrain[&territory];
+            while troops > 0 && faction.shipping_cost(terrain, troops) > self.spice[&faction] {
+                troops -= 1;
+            }
+            if troops == 0 {
+                continue;
+            }
+            self.decide(
+                faction,
+                Requirement::Turn(&[Context::Shipping, Context::Moving]),
+            )?;
+            // Shipping is paid to the Guild, unless it's the Guild doing the shipping
+            let payee = self.payee(Faction::SpacingGuild, faction);
+            self.pay(faction, payee, faction.shipping_cost(terrain, troops))?;
+            self.place(faction, &territory, troops);
+        }
+        Ok(())
+    }
+
+    /// Fights it out wherever two factions share a territory, until only one is left in each
+    fn battles(&mut self) -> Result<(), String> {
+        for (territory, mut factions) in self.occupancy.co_located_enemies() {
+            while factions.len() > 1 {
+                let (a, b) = (factions[0], factions[1]);
+                for &faction in [a, b].iter() {
+                    self.decide(faction, Requirement::Phase(Phase::Battle))?;
+                }
+                let (troops_a, troops_b) =
+                    (self.troops_at(a, &territory), self.troops_at(b, &territory));
+                let (winner, loser, winner_troops, loser_troops) =
+                    if self.rng.gen_range(0..troops_a + troops_b) < troops_a {
+                        (a, b, troops_a, troops_b)
+                    } else {
+                        (b, a, troops_b, troops_a)
+                    };
+                // The loser loses everything, and the winner whatever it dialed
+                let dialed = self.rng.gen_range(0..=winner_troops.min(loser_troops));
+                self.kill(loser, &territory, loser_troops);
+                if dialed > 0 {
+                    self.kill(winner, &territory, dialed);
+                }
+                factions = self.occupancy.factions_in(&territory);
+            }
+        }
+        Ok(())
+    }
+
+    fn collection(&mut self) {
+        for territory in self.territories.clone() {
+            let available = self.board_spice.get(&territory).copied().unwrap_or(0);
+            if available == 0 {
+                continue;
+            }
+            let mut left = available;
+            for faction in self.occupancy.factions_in(&territory) {
+                let rate = self.occupancy.harvest_rate(faction);
+                let collected = (rate * self.troops_at(faction, &territory)).min(left);
+                *self.spice.get_mut(&faction).unwrap() += collected;
+                left -= collected;
+            }
+            if left > 0 {
+                self.board_spice.insert(territory, left);
+            } else {
+                self.board_spice.remove(&territory);
+            }
+        }
+    }
+
+    fn check_victory(&mut self) {
+        let winners = self.occupancy.stronghold_winners(&self.factions, |_| None);
+        if !winners.is_empty() {
+            self.winners = Some(winners);
+        }
+    }
+
+    fn control(&mut self) {
+        if self.turn + 1 < LAST_TURN {
+            self.turn += 1;
+        } else {
+            self.winners = Some(
+                self.occupancy
+                    .default_winner(&self.factions)
+                    .into_iter()
+                    .collect(),
+            );
+        }
+    }
+
+    fn check_invariants(&self) -> Result<(), String> {
+        let held = self.spice.values().sum::<i32>();
+        let on_board = self.board_spice.values().sum::<i32>();
+        if self.bank + held + on_board != BANK {
+            return Err(format!(
+                "spice went missing: {} in the bank, {} held and {} on the board",
+                self.bank, held, on_board
+            ));
+        }
+        if self.turn >= LAST_TURN {
+            return Err(format!("the game went on past turn {}", LAST_TURN));
+        }
+        if let Some((faction, spice)) = self.spice.iter().find(|(_, &spice)| spice < 0) {
+            return Err(format!("{} is {} spice in debt", faction, -spice));
+        }
+        for &faction in self.factions.iter() {
+            let (reserves, tanks) = (self.reserves[&faction], self.tanks[&faction]);
+            let on_board = self.troops_on_board(faction);
+            if reserves < 0 || tanks < 0 || on_board < 0 {
+                return Err(format!(
+                    "{} has {} troops in reserve, {} in the tanks and {} on the board",
+                    faction, reserves, tanks, on_board
+                ));
+            }
+            if reserves + tanks + on_board != TROOPS {
+                return Err(format!(
+                    "{} has {} troops instead of {}",
+                    faction,
+                    reserves + tanks + on_board,
+                    TROOPS
+                ));
+            }
+        }
+        for (name, factions) in self.occupancy.strongholds() {
+            if factions.len() > STRONGHOLD_LIMIT {
+                return Err(format!(
+                    "{} factions are crammed into {}",
+                    factions.len(),
+                    name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the game ended when and how the board says it should have. Whoever holds enough
+    /// strongholds on their own is counted straight off the troops rather than asked of the rules
+    /// that ended the game.
+    fn check_winners(&self, winners: Vec<Faction>) -> Result<Vec<Faction>, String> {
+        let held = |faction: Faction| {
+            STRONGHOLDS
+                .iter()
+                .filter(|name| {
+                    let troops = self.occupancy.territory(name);
+                    troops.len() == 1 && troops.get(&faction).map_or(false, |&count| count > 0)
+                })
+                .count()
+        };
+        let mut controllers = self
+            .factions
+            .iter()
+            .copied()
+            .filter(|&faction| held(faction) >= STRONGHOLDS_TO_WIN)
+            .collect::<Vec<_>>();
+        controllers.sort_by_key(|faction| faction.index());
+        if !controllers.is_empty() {
+            return if winners == controllers {
+                Ok(winners)
+            } else {
+                Err(format!(
+                    "{:?} won while {:?} held the strongholds",
+                    winners, controllers
+                ))
+            };
+        }
+        if self.turn + 1 < LAST_TURN {
+            return Err(format!(
+                "{:?} won on turn {} without holding {} strongholds",
+                winners,
+                self.turn + 1,
+                STRONGHOLDS_TO_WIN
+            ));
+        }
+        // Otherwise the game ran out of turns, which only the Fremen or the Guild can win
+        match winners[..] {
+            [] if !self.factions.contains(&Faction::SpacingGuild) => Ok(winners),
+            [winner @ Faction::Fremen] | [winner @ Faction::SpacingGuild]
+                if self.factions.contains(&winner) =>
+            {
+                Ok(winners)
+            }
+            _ => Err(format!("{:?} won when the turns ran out", winners)),
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+#[test]
+fn simulated_games_keep_the_rules() {
+    // Only the cards are needed, not their faces
+    let preset: DeckPreset = File::open(STANDARD_DECK)
+        .map_err(|err| err.to_string())
+        .and_then(|file| ron::de::from_reader(file).map_err(|err| err.to_string()))
+        .unwrap_or_else(|err| panic!("Couldn't load {}: {}", STANDARD_DECK, err));
+    let games = env_or("DUNE_SIMULATION_GAMES", GAMES);
+    let base_seed = env_or("DUNE_SIMULATION_SEED", 0u64);
+
+    let mut wins = HashMap::new();
+    let mut failures = Vec::new();
+    for game in 0..games {
+        let seed = base_seed.wrapping_add(game as u64);
+        let mut simulation = Simulation::new(seed, &preset.spice);
+        match simulation.play() {
+            Ok(winners) => {
+                for faction in winners {
+                    *wins.entry(faction).or_insert(0) += 1;
+                }
+            }
+            Err(reason) => failures.push(format!(
+                "Game {} (seed {}) went wrong on turn {} in {:?}: {}",
+                game,
+                seed,
+                simulation.turn + 1,
+                simulation.phase,
+                reason
+            )),
+        }
+    }
+    for faction in Faction::ALL.iter() {
+        if let Some(count) = wins.get(faction) {
+            println!("{}: {} wins", faction, count);
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} games broke a rule:\n{}",
+        failures.len(),
+        games,
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn simulated_tables_fit_the_lobby() {
+    let spice_cards = Vec::new();
+    for seed in 0..200 {
+        let simulation = Simulation::new(seed, &spice_cards);
+        let players = simulation.factions.len();
+        assert!(
+            (MIN_PLAYERS..=MAX_PLAYERS).contains(&players),
+            "seed {} seated {} factions",
+            seed,
+            players
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use std::{collections::HashMap, fs};
+
+use bevy::prelude::*;
+use serde::Serialize;
+
+use crate::{
+    components::{Dead, Player, Troop, Unique},
+    data::Faction,
+    dune_core::Occupancy,
+    phase::{BattleResolved, CardBought, GamePhase, Phase, PhaseStarted},
+    resources::Info,
+    util::unix_time,
+    victory::GameResult,
+    Screen, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+const STATS_DIR: &str = "stats";
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GameStats>()
+            .on_state_enter(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                spice_stats_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                event_stats_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                stronghold_stats_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                troop_stats_system.system(),
+            );
+    }
+}
+
+/// How one faction's game went
+#[derive(Clone, Default, Serialize)]
+pub struct FactionStats {
+    pub spice_earned: i32,
+    pub spice_spent: i32,
+    pub battles_won: u32,
+    pub troops_lost: u32,
+    pub cards_bought: u32,
+    /// Turns that ended with the faction holding at least one stronghold
+    pub turns_holding_stronghold: u32,
+}
+
+/// Everything counted over the course of a game, kept until the next one starts so the results
+/// screen can show it
+#[derive(Default)]
+pub struct GameStats {
+    factions: HashMap<Faction, FactionStats>,
+    /// Reserves as last seen, to tell spice coming in from spice going out
+    last_spice: HashMap<Faction, i32>,
+}
+
+/// What gets written out when the summary is exported
+#[derive(Serialize)]
+struct Summary<'a> {
+    winners: &'a [Faction],
+    reason: &'a str,
+    turn: i32,
+    factions: Vec<(Faction, &'a FactionStats)>,
+}
+
+impl GameStats {
+    fn of(&mut self, faction: Faction) -> &mut FactionStats {
+        self.factions.entry(faction).or_default()
+    }
+
+    /// Every faction that played, in the order of `Faction::ALL`
+    pub fn factions(&self) -> Vec<(Faction, &FactionStats)> {
+        let mut factions = self
+            .factions
+            .iter()
+            .map(|(&faction, stats)| (faction, stats))
+            .collect::<Vec<_>>();
+        factions.sort_by_key(|(faction, _)| faction.index());
+        factions
+    }
+
+    /// Writes the result and every faction's numbers to a JSON file, returning where it went
+    pub fn export(&self, result: &GameResult) -> Result<String, String> {
+        let summary = Summary {
+            winners: &result.winners,
+            reason: &result.reason,
+            turn: result.turn,
+            factions: self.factions(),
+        };
+        let json = serde_json::to_string_pretty(&summary).map_err(|err| err.to_string())?;
+        let stamp = unix_time();
+        let path = format!("{}/game_{}.json", STATS_DIR, stamp);
+        fs::create_dir_all(STATS_DIR)
+            .and_then(|_| fs::write(&path, json))
+            .map_err(|err| err.to_string())?;
+        println!("Game summary written to {}", path);
+        Ok(path)
+    }
+}
+
+/// Counts rises in a faction's reserve as spice earned and drops as spice spent. Setup only hands
+/// out the starting spice, so it doesn't count.
+fn spice_stats_system(
+    mut stats: ResMut<GameStats>,
+    phase: Res<GamePhase>,
+    players: Query<&Player>,
+) {
+    for player in players.iter() {
+        let last = stats.last_spice.insert(player.faction, player.spice);
+        if let Phase::Setup { .. } = phase.phase {
+            continue;
+        }
+        let change = player.spice - last.unwrap_or(player.spice);
+        if change > 0 {
+            stats.of(player.faction).spice_earned += change;
+        } else if change < 0 {
+            stats.of(player.faction).spice_spent -= change;
+        }
+    }
+}
+
+/// Battles won and cards bought, as the phases announce them
+fn event_stats_system(
+    mut stats: ResMut<GameStats>,
+    (battles, mut battle_reader): (
+        Res<Events<BattleResolved>>,
+        Local<EventReader<BattleResolved>>,
+    ),
+    (bought, mut bought_reader): (Res<Events<CardBought>>, Local<EventReader<CardBought>>),
+) {
+    for battle in battle_reader.iter(&battles) {
+        stats.of(battle.winner).battles_won += 1;
+    }
+    for card in bought_reader.iter(&bought) {
+        stats.of(card.faction).cards_bought += 1;
+    }
+}
+
+fn stronghold_stats_system(
+    (mut stats, info, occupancy): (ResMut<GameStats>, Res<Info>, Res<Occupancy>),
+    (phases, mut phase_reader): (Res<Events<PhaseStarted>>, Local<EventReader<PhaseStarted>>),
+) {
+    // Everyone in the game shows up in the summary, even with nothing to their name
+    for &faction in info.factions_in_play.iter() {
+        stats.of(faction);
+    }
+    // Control is the last phase of a turn, so whoever holds a stronghold then held it that turn
+    for _ in phase_reader
+        .iter(&phases)
+        .filter(|started| started.phase == Phase::Control)
+    {
+        for &faction in info.factions_in_play.iter() {
+            if !occupancy
+                .strongholds_controlled(faction, info.ally(faction))
+                .is_empty()
+            {
+                stats.of(faction).turns_holding_stronghold += 1;
+            }
+        }
+    }
+}
+
+fn troop_stats_system(
+    mut stats: ResMut<GameStats>,
+    killed: Query<&Unique, (With<Troop>, Added<Dead>)>,
+) {
+    for unique in killed.iter() {
+        stats.of(unique.faction).troops_lost += 1;
+    }
+}
+
+fn reset(mut stats: ResMut<GameStats>) {
+    *stats = GameStats::default();
+}
@@ -0,0 +1,553 @@
+use std::{collections::VecDeque, f32::consts::PI};
+
+use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
+
+use crate::{
+    alliance::own_faction,
+    bot::Bot,
+    components::Player,
+    data::Faction,
+    game_action::{submit, GameAction, GameActions},
+    hud::HudMaterials,
+    locale::Tr,
+    menu::{ButtonMaterials, Lobby},
+    network::{Client, Network, NetworkRole, NetworkType, Server},
+    phase::{GamePhase, Phase, StormSubPhase},
+    protocol::ServerEvent,
+    resources::Info,
+    wheel::{dial_rotation, DIAL_SLOTS},
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// The highest number either player can dial
+pub const DIAL_MAX: i32 = DIAL_SLOTS - 1;
+/// Seconds the storm waits for the dials to finish turning over before it moves
+pub const REVEAL_TIME: f32 = 3.0;
+const DIAL_SIZE: f32 = 180.0;
+/// How long the revealed dials spin before settling on their numbers
+const SPIN_SECONDS: f32 = 1.6;
+/// Whole turns the revealed dials make on the way to their numbers
+const SPIN_TURNS: f32 = 3.0;
+/// Seconds the settled dials stay up before clearing away
+const REVEAL_HOLD: f32 = 3.0;
+
+pub struct StormDialPlugin;
+
+impl Plugin for StormDialPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<StormDial>()
+            .init_resource::<DialInput>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                dial_sync_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                dial_open_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                dial_open_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                dial_drag_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                dial_drag_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                dial_button_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                dial_button_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                dial_reveal_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                dial_reveal_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system());
+    }
+}
+
+/// A number dialed to place the first storm
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct DialPick {
+    pub faction: Faction,
+    pub value: i32,
+}
+
+/// Where the first storm dial stands. The host keeps the numbers to itself until both are in,
+/// then reveals them at once.
+#[derive(Default)]
+pub struct StormDial {
+    /// Dials sent in, for the storm phase to settle
+    pub requests: VecDeque<DialPick>,
+    /// The first and last players in storm order
+    pub dialers: Vec<Faction>,
+    /// Who has dialed, without giving away what
+    pub dialed: Vec<Faction>,
+    pub revealed: Vec<DialPick>,
+    /// Only ever filled on the host
+    picks: Vec<DialPick>,
+}
+
+impl StormDial {
+    /// Takes on the host's view of the dial
+    pub fn sync(&mut self, dialers: Vec<Faction>, dialed: Vec<Faction>, revealed: Vec<DialPick>) {
+        self.dialers = dialers;
+        self.dialed = dialed;
+        self.revealed = revealed;
+    }
+
+    /// Keeps a dial secret until every dialer is in, then reveals all of them
+    pub fn accept(&mut self, pick: DialPick) -> Result<(), String> {
+        if !self.dialers.contains(&pick.faction) {
+            return Err(format!("{} is not dialing the storm", pick.faction));
+        }
+        if self.dialed.contains(&pick.faction) {
+            return Err(format!("{} has already dialed", pick.faction));
+        }
+        if pick.value < 0 || pick.value > DIAL_MAX {
+            return Err(format!("{} cannot dial {}", pick.faction, pick.value));
+        }
+        println!("{} has dialed the storm", pick.faction);
+        self.dialed.push(pick.faction);
+        self.picks.push(pick);
+        if self.picks.len() == self.dialers.len() {
+            self.revealed = self.picks.clone();
+        }
+        Ok(())
+    }
+
+    /// Sectors the first storm moves, once the dials are revealed
+    pub fn total(&self) -> Option<i32> {
+        if self.revealed.is_empty() {
+            None
+        } else {
+            Some(self.revealed.iter().map(|pick| pick.value).sum())
+        }
+    }
+}
+
+/// Tells everyone who is dialing and who has, and what they dialed once it is revealed
+fn dial_sync_system(
+    network: Res<Network>,
+    dial: Res<StormDial>,
+    mut sent: Local<(Vec<Faction>, Vec<Faction>, Vec<DialPick>)>,
+    mut server: Query<&mut Server>,
+) {
+    if network.network_type == NetworkType::Client {
+        return;
+    }
+    if sent.0 == dial.dialers && sent.1 == dial.dialed && sent.2 == dial.revealed {
+        return;
+    }
+    *sent = (
+        dial.dialers.clone(),
+        dial.dialed.clone(),
+        dial.revealed.clone(),
+    );
+    if let Some(mut server) = server.iter_mut().next() {
+        server.broadcast(&ServerEvent::StormDial {
+            dialers: dial.dialers.clone(),
+            dialed: dial.dialed.clone(),
+            revealed: dial.revealed.clone(),
+        });
+    }
+}
+
+/// The number being dialed at this machine
+#[derive(Default)]
+struct DialInput {
+    faction: Option<Faction>,
+    value: i32,
+    /// How far the dial is turned, counterclockwise
+    rotation: f32,
+    /// The cursor's angle around the dial and the dial's turn when the drag started
+    drag: Option<(f32, f32)>,
+    /// Dialers here whose number the host has yet to take
+    sent: Vec<Faction>,
+}
+
+struct DialPanel;
+
+struct DialFace;
+
+struct DialValue;
+
+struct DialConfirm;
+
+/// Opens the dial for whoever at this machine has a number to dial, and closes it once it is sent.
+/// Everyone sharing a hot seat machine dials in turn.
+fn dial_open_system(
+    commands: &mut Commands,
+    (network, lobby, info, phase): (Res<Network>, Res<Lobby>, Res<Info>, Res<GamePhase>),
+    (dial, mut input, tr): (Res<StormDial>, ResMut<DialInput>, Res<Tr>),
+    (asset_server, hud_materials, button_materials, mut colors): (
+        Res<AssetServer>,
+        Res<HudMaterials>,
+        Res<ButtonMaterials>,
+        ResMut<Assets<ColorMaterial>>,
+    ),
+    (players, bots, client): (Query<&Player>, Query<&Player, With<Bot>>, Query<&Client>),
+    panels: Query<Entity, With<DialPanel>>,
+) {
+    let dialing = phase.phase
+        == Phase::Storm {
+            subphase: StormSubPhase::Dial,
+        }
+        && network.role != NetworkRole::Spectator;
+    input.sent.retain(|faction| !dial.dialed.contains(faction));
+    let waiting = |faction: &Faction| {
+        dial.dialers.contains(faction)
+            && !dial.dialed.contains(faction)
+            && !input.sent.contains(faction)
+    };
+    let next = if !dialing {
+        None
+    } else if network.network_type == NetworkType::Local {
+        dial.dialers
+            .iter()
+            .copied()
+            .filter(|faction| !bots.iter().any(|bot| bot.faction == *faction))
+            .find(|faction| waiting(faction))
+    } else {
+        own_faction(&network, &lobby, &info, &players, &client).filter(|faction| waiting(faction))
+    };
+    if next == input.faction {
+        return;
+    }
+    for entity in panels.iter() {
+        commands.despawn_recursive(entity);
+    }
+    *input = DialInput {
+        faction: next,
+        sent: std::mem::take(&mut input.sent),
+        ..Default::default()
+    };
+    let faction = if let Some(faction) = next {
+        faction
+    } else {
+        return;
+    };
+
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let text = |value: String, font_size: f32| TextBundle {
+        text: Text {
+            font: font.clone(),
+            value,
+            style: TextStyle {
+                font_size,
+                color: Color::ANTIQUE_WHITE,
+                ..Default::default()
+            },
+        },
+        ..Default::default()
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(38.0),
+                    bottom: Val::Percent(20.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                padding: Rect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            material: hud_materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(DialPanel)
+        .with_children(|parent| {
+            parent
+                .spawn(text(
+                    tr.fmt("storm_dial.title", &[&tr.faction(faction)]),
+                    22.0,
+                ))
+                // Points at the number dialed
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(6.0), Val::Px(16.0)),
+                        ..Default::default()
+                    },
+                    material: hud_materials.current.clone(),
+                    ..Default::default()
+                })
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(DIAL_SIZE), Val::Px(DIAL_SIZE)),
+                        margin: Rect::all(Val::Px(4.0)),
+                        ..Default::default()
+                    },
+                    material: colors.add(asset_server.get_handle("wheel_dial.png").into()),
+                    ..Default::default()
+                })
+                .with(DialFace)
+                .spawn(text(String::new(), 18.0))
+                .with(DialValue)
+                .spawn(ButtonBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(4.0)),
+                        padding: Rect::all(Val::Px(6.0)),
+                        justify_content: JustifyContent::Center,
+                        ..Default::default()
+                    },
+                    material: button_materials.normal.clone(),
+                    ..Default::default()
+                })
+                .with(DialConfirm)
+                .with_children(|parent| {
+                    parent.spawn(text(tr.get("storm_dial.confirm"), 18.0));
+                });
+        });
+}
+
+/// Dragging the dial round turns it with the cursor, a number at a time
+fn dial_drag_system(
+    (windows, tr): (Res<Windows>, Res<Tr>),
+    mut input: ResMut<DialInput>,
+    mut dials: Query<(&Interaction, &GlobalTransform, &mut Transform), With<DialFace>>,
+    mut texts: Query<&mut Text, With<DialValue>>,
+) {
+    let cursor = windows
+        .get_primary()
+        .and_then(|window| window.cursor_position());
+    for (interaction, global, mut transform) in dials.iter_mut() {
+        match (interaction, cursor) {
+            (Interaction::Clicked, Some(cursor)) => {
+                let offset = cursor - global.translation.truncate();
+                let angle = offset.y.atan2(offset.x);
+                let rotation = input.rotation;
+                let (start_angle, start_rotation) = *input.drag.get_or_insert((angle, rotation));
+                input.rotation = start_rotation + angle - start_angle;
+                input.value =
+                    ((input.rotation / dial_rotation(1)).round() as i32).rem_euclid(DIAL_SLOTS);
+            }
+            _ => {
+                input.drag = None;
+                input.rotation = dial_rotation(input.value);
+            }
+        }
+        transform.rotation = Quat::from_rotation_z(input.rotation);
+    }
+    let value = tr.fmt("storm_dial.value", &[&input.value]);
+    for mut text in texts.iter_mut() {
+        if text.value != value {
+            text.value = value.clone();
+        }
+    }
+}
+
+type ConfirmButtons<'a, 'b, 'c> =
+    Query<'a, (&'b Interaction, &'c mut Handle<ColorMaterial>), With<DialConfirm>>;
+
+/// Confirming sends the number to the host, which keeps it secret until both are in
+fn dial_button_system(
+    network: Res<Network>,
+    (button_materials, mut actions): (Res<ButtonMaterials>, ResMut<GameActions>),
+    mut input: ResMut<DialInput>,
+    mut buttons: ConfirmButtons,
+    mut client: Query<&mut Client>,
+) {
+    let mut confirmed = false;
+    for (interaction, mut material) in buttons.iter_mut() {
+        *material = match interaction {
+            Interaction::Clicked => {
+                confirmed = true;
+                button_materials.pressed.clone()
+            }
+            Interaction::Hovered => button_materials.hovered.clone(),
+            Interaction::None => button_materials.normal.clone(),
+        };
+    }
+    let faction = match input.faction {
+        Some(faction) if confirmed && !input.sent.contains(&faction) => faction,
+        _ => return,
+    };
+    input.sent.push(faction);
+    submit(
+        &network,
+        &mut client,
+        &mut actions,
+        GameAction::StormDial {
+            pick: DialPick {
+                faction,
+                value: input.value,
+            },
+        },
+    );
+}
+
+/// The revealed dials spinning round to their numbers, and how long they have been up
+struct RevealPanel {
+    elapsed: f32,
+}
+
+struct RevealDial(i32);
+
+struct RevealTotal(i32);
+
+/// Spins both dials round to their numbers at the same moment above the board, then shows where
+/// that leaves the storm
+fn dial_reveal_system(
+    commands: &mut Commands,
+    (time, dial, tr): (Res<Time>, Res<StormDial>, Res<Tr>),
+    (asset_server, hud_materials, mut colors): (
+        Res<AssetServer>,
+        Res<HudMaterials>,
+        ResMut<Assets<ColorMaterial>>,
+    ),
+    (mut shown, mut panels): (Local<bool>, Query<(Entity, &mut RevealPanel)>),
+    mut dials: Query<(&RevealDial, &mut Transform)>,
+    mut totals: Query<(&RevealTotal, &mut Text)>,
+) {
+    for (entity, mut panel) in panels.iter_mut() {
+        panel.elapsed += time.delta_seconds();
+        // Eases out so the dials slow down as they settle
+        let t = (panel.elapsed / SPIN_SECONDS).min(1.0);
+        let spun = 1.0 - (1.0 - t).powi(3);
+        for (reveal, mut transform) in dials.iter_mut() {
+            let target = dial_rotation(reveal.0) + SPIN_TURNS * 2.0 * PI;
+            transform.rotation = Quat::from_rotation_z(target * spun);
+        }
+        if t >= 1.0 {
+            for (total, mut text) in totals.iter_mut() {
+                if text.value.is_empty() {
+                    text.value = tr.fmt("storm_dial.total", &[&total.0]);
+                }
+            }
+        }
+        if panel.elapsed > SPIN_SECONDS + REVEAL_HOLD {
+            commands.despawn_recursive(entity);
+        }
+    }
+
+    let total = match dial.total() {
+        Some(total) if !*shown => total,
+        Some(_) => return,
+        None => {
+            *shown = false;
+            return;
+        }
+    };
+    *shown = true;
+
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let text = |value: String, font_size: f32, color: Color| TextBundle {
+        text: Text {
+            font: font.clone(),
+            value,
+            style: TextStyle {
+                font_size,
+                color,
+                ..Default::default()
+            },
+        },
+        ..Default::default()
+    };
+    let face = colors.add(asset_server.get_handle("wheel_dial.png").into());
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(32.0),
+                    top: Val::Percent(8.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                padding: Rect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            material: hud_materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(RevealPanel { elapsed: 0.0 })
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        ..Default::default()
+                    },
+                    material: colors.add(Color::NONE.into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for pick in dial.revealed.iter() {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    flex_direction: FlexDirection::ColumnReverse,
+                                    align_items: AlignItems::Center,
+                                    margin: Rect::all(Val::Px(8.0)),
+                                    ..Default::default()
+                                },
+                                material: hud_materials.upcoming.clone(),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                parent
+                                    .spawn(text(
+                                        tr.faction(pick.faction),
+                                        20.0,
+                                        pick.faction.color(),
+                                    ))
+                                    .spawn(NodeBundle {
+                                        style: Style {
+                                            size: Size::new(Val::Px(6.0), Val::Px(16.0)),
+                                            ..Default::default()
+                                        },
+                                        material: hud_materials.current.clone(),
+                                        ..Default::default()
+                                    })
+                                    .spawn(ImageBundle {
+                                        style: Style {
+                                            size: Size::new(Val::Px(DIAL_SIZE), Val::Px(DIAL_SIZE)),
+                                            margin: Rect::all(Val::Px(4.0)),
+                                            ..Default::default()
+                                        },
+                                        material: face.clone(),
+                                        ..Default::default()
+                                    })
+                                    .with(RevealDial(pick.value));
+                            });
+                    }
+                })
+                .spawn(text(String::new(), 22.0, Color::ANTIQUE_WHITE))
+                .with(RevealTotal(total));
+        });
+}
+
+fn reset(mut dial: ResMut<StormDial>, mut input: ResMut<DialInput>) {
+    *dial = StormDial::default();
+    *input = DialInput::default();
+}
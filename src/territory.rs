@@ -0,0 +1,356 @@
+use bevy::{
+    prelude::*,
+    render::camera::{Camera, OrthographicProjection},
+};
+
+use crate::{
+    components::{Collider, LocationSector, Spice, SpiceNode, Storm},
+    data::{Location, Terrain},
+    dune_core::{Occupancy, STRONGHOLD_LIMIT},
+    hud::HudMaterials,
+    layout::{Anchored, AnchoredHeight},
+    locale::Tr,
+    resources::{Data, SectorGraph, SectorNode},
+    util::closest,
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// Where spice is laid down on the board, and which territory each spot is in
+type SpiceSpots<'a, 'b> = Query<'a, (Entity, &'b Location), With<SpiceNode>>;
+
+pub struct TerritoryPlugin;
+
+impl Plugin for TerritoryPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SelectedTerritory>()
+            .on_state_enter(
+                RESPONSE_STAGE,
+                Screen::HostingGame,
+                init_territory_panel.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                territory_select_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                territory_summary_system.system(),
+            )
+            .on_state_enter(
+                RESPONSE_STAGE,
+                Screen::HostingGame,
+                init_territory_banner.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                territory_banner_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system());
+    }
+}
+
+/// The territory whose summary is shown in the side panel
+#[derive(Default)]
+pub struct SelectedTerritory {
+    pub name: Option<String>,
+    /// Where the last click started, so dragging from one territory to another selects neither
+    pressed: Option<String>,
+}
+
+struct TerritoryPanel;
+
+struct TerritoryText;
+
+fn init_territory_panel(
+    commands: &mut Commands,
+    (data, asset_server): (Res<Data>, Res<AssetServer>),
+    materials: Res<HudMaterials>,
+) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(8.0)),
+                display: Display::None,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(TerritoryPanel)
+        .with(Anchored::new(data.ui_structure.territory.clone()).with_height(AnchoredHeight::Auto))
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                        value: String::new(),
+                        style: TextStyle {
+                            font_size: 16.0,
+                            color: Color::ANTIQUE_WHITE,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(TerritoryText);
+        });
+}
+
+/// Clicking a territory opens its summary, clicking off the board closes it again
+fn territory_select_system(
+    (windows, mouse_input): (Res<Windows>, Res<Input<MouseButton>>),
+    mut selected: ResMut<SelectedTerritory>,
+    cameras: Query<(&Camera, &Transform), Without<OrthographicProjection>>,
+    colliders: Query<(Entity, &Collider, &Transform, &LocationSector)>,
+) {
+    let territory_under_cursor =
+        || closest(&windows, &cameras, &colliders).map(|hit| hit.component.location.name.clone());
+    if mouse_input.just_pressed(MouseButton::Left) {
+        selected.pressed = territory_under_cursor();
+    } else if mouse_input.just_released(MouseButton::Left) {
+        let released = territory_under_cursor();
+        if released == selected.pressed.take() {
+            selected.name = released;
+        }
+    }
+}
+
+/// Lists who is in the selected territory and what else is going on there
+fn territory_summary_system(
+    (selected, occupancy, graph, tr): (
+        Res<SelectedTerritory>,
+        Res<Occupancy>,
+        Res<SectorGraph>,
+        Res<Tr>,
+    ),
+    (storm, sectors): (Query<&Storm>, Query<&LocationSector>),
+    (spice, spice_nodes): (Query<&Spice>, SpiceSpots),
+    mut panels: Query<&mut Style, With<TerritoryPanel>>,
+    mut texts: Query<&mut Text, With<TerritoryText>>,
+) {
+    let display = if selected.name.is_some() {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    for mut style in panels.iter_mut() {
+        if style.display != display {
+            style.display = display;
+        }
+    }
+    let name = if let Some(name) = selected.name.as_ref() {
+        name
+    } else {
+        return;
+    };
+
+    let mut lines = vec![name.clone()];
+    let factions = occupancy.factions_in(name);
+    if graph.terrain.get(name) == Some(&Terrain::Stronghold) {
+        lines.push(tr.fmt(
+            "territory.stronghold",
+            &[&factions.len(), &STRONGHOLD_LIMIT],
+        ));
+    }
+    let storm_sector = storm.iter().next().map(|storm| storm.sector);
+    let in_storm = sectors
+        .iter()
+        .filter(|loc_sec| loc_sec.location.name == *name)
+        .any(|loc_sec| graph.in_storm(&SectorNode::new(name, loc_sec.sector), storm_sector));
+    if in_storm {
+        lines.push(tr.get("territory.storm"));
+    }
+    let amount = spice_nodes
+        .iter()
+        .filter(|(_, location)| location.name == *name)
+        .map(|(node, _)| {
+            spice
+                .iter()
+                .filter(|spice| spice.location == Some(node))
+                .map(|spice| spice.value)
+                .sum::<i32>()
+        })
+        .sum::<i32>();
+    if amount > 0 {
+        lines.push(tr.fmt("territory.spice", &[&amount]));
+    }
+    let troops = occupancy.territory(name);
+    if factions.is_empty() {
+        lines.push(tr.get("territory.empty"));
+    }
+    for faction in factions {
+        lines.push(tr.fmt(
+            "territory.troops",
+            &[&tr.faction(faction), &troops[&faction]],
+        ));
+    }
+
+    let value = lines.join("\n");
+    for mut text in texts.iter_mut() {
+        if text.value != value {
+            text.value = value.clone();
+        }
+    }
+}
+
+struct TerritoryBanner;
+
+/// The two lines of the banner: the hovered territory, and the storm's sector under the compass
+enum BannerText {
+    Territory,
+    Storm,
+}
+
+/// One sector of the storm compass under the banner
+struct CompassSector(i32);
+
+/// Sectors around the board, numbered from 0 like the storm's
+const SECTORS: i32 = 18;
+
+fn init_territory_banner(
+    commands: &mut Commands,
+    (data, asset_server): (Res<Data>, Res<AssetServer>),
+    materials: Res<HudMaterials>,
+) {
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                padding: Rect::all(Val::Px(6.0)),
+                display: Display::None,
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(TerritoryBanner)
+        .with(
+            Anchored::new(data.ui_structure.territory_banner.clone())
+                .with_height(AnchoredHeight::Auto),
+        )
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        font: font.clone(),
+                        value: String::new(),
+                        style: TextStyle {
+                            font_size: 20.0,
+                            color: Color::ANTIQUE_WHITE,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(BannerText::Territory)
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        margin: Rect::all(Val::Px(4.0)),
+                        ..Default::default()
+                    },
+                    material: materials.panel.clone(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for sector in 0..SECTORS {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(10.0), Val::Px(10.0)),
+                                    margin: Rect::all(Val::Px(1.0)),
+                                    ..Default::default()
+                                },
+                                material: materials.upcoming.clone(),
+                                ..Default::default()
+                            })
+                            .with(CompassSector(sector));
+                    }
+                })
+                .spawn(TextBundle {
+                    text: Text {
+                        font,
+                        value: String::new(),
+                        style: TextStyle {
+                            font_size: 14.0,
+                            color: Color::ANTIQUE_WHITE,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(BannerText::Storm);
+        });
+}
+
+/// Names the territory and sector under the cursor across the top of the screen, over a strip of
+/// every sector with the storm's and the hovered one marked
+fn territory_banner_system(
+    (windows, tr): (Res<Windows>, Res<Tr>),
+    (graph, materials): (Res<SectorGraph>, Res<HudMaterials>),
+    cameras: Query<(&Camera, &Transform), Without<OrthographicProjection>>,
+    colliders: Query<(Entity, &Collider, &Transform, &LocationSector)>,
+    (storm, mut banners): (Query<&Storm>, Query<&mut Style, With<TerritoryBanner>>),
+    mut texts: Query<(&mut Text, &BannerText)>,
+    mut compass: Query<(&mut Handle<ColorMaterial>, &CompassSector)>,
+) {
+    let hovered = closest(&windows, &cameras, &colliders)
+        .map(|hit| (hit.component.location.name.clone(), hit.component.sector));
+    let display = if hovered.is_some() {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    for mut style in banners.iter_mut() {
+        if style.display != display {
+            style.display = display;
+        }
+    }
+    let (name, sector) = if let Some(hovered) = hovered {
+        hovered
+    } else {
+        return;
+    };
+
+    let storm_sector = storm.iter().next().map(|storm| storm.sector);
+    let mut territory = tr.fmt("territory.banner", &[&name, &sector]);
+    if graph.in_storm(&SectorNode::new(&name, sector), storm_sector) {
+        territory = format!("{} - {}", territory, tr.get("territory.storm"));
+    }
+    let storm = storm_sector
+        .map(|sector| tr.fmt("hud.storm", &[&sector]))
+        .unwrap_or_default();
+    for (mut text, line) in texts.iter_mut() {
+        let value = match line {
+            BannerText::Territory => &territory,
+            BannerText::Storm => &storm,
+        };
+        if text.value != *value {
+            text.value = value.clone();
+        }
+    }
+    for (mut material, &CompassSector(i)) in compass.iter_mut() {
+        let wanted = if Some(i) == storm_sector {
+            &materials.current
+        } else if i == sector {
+            &materials.done
+        } else {
+            &materials.upcoming
+        };
+        if *material != *wanted {
+            *material = wanted.clone();
+        }
+    }
+}
+
+fn reset(mut selected: ResMut<SelectedTerritory>) {
+    *selected = SelectedTerritory::default();
+}
@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
+
+use crate::{
+    bot::{Bot, BotSettings},
+    components::Player,
+    data::Faction,
+    game_action::{GameAction, GameActions},
+    locale::Tr,
+    network::{Network, NetworkType, Server},
+    phase::{ActionQueue, Auction, Context, Movement, MovementRequest, Revival, RevivalRequest},
+    protocol::ServerEvent,
+    resources::Info,
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// Seconds between the host telling everyone how much time each player has left
+const SYNC_INTERVAL: f32 = 1.0;
+/// Time bank choices offered in the lobby, in minutes
+const BANK_MINUTES: [Option<u32>; 5] = [None, Some(5), Some(10), Some(20), Some(30)];
+
+pub struct TurnTimerPlugin;
+
+impl Plugin for TurnTimerPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<TurnTimer>()
+            .init_resource::<TimeBanks>()
+            .on_state_enter(RESPONSE_STAGE, Screen::HostingGame, init_clock.system())
+            .on_state_enter(RESPONSE_STAGE, Screen::JoinedGame, init_clock.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                time_bank_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                substitute_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                clock_tick_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                clock_display_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::JoinedGame,
+                clock_display_system.system(),
+            );
+    }
+}
+
+/// What happens to a player whose time bank runs dry
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum OutOfTime {
+    /// Pass wherever passing is allowed, and let a bot decide once where it isn't
+    AutoPass,
+    /// A bot plays their seat for the rest of the game
+    BotTakeover,
+    /// The game keeps waiting on them, with the clock showing they're over
+    Pause,
+}
+
+impl OutOfTime {
+    pub fn next(&self) -> Self {
+        match self {
+            OutOfTime::AutoPass => OutOfTime::BotTakeover,
+            OutOfTime::BotTakeover => OutOfTime::Pause,
+            OutOfTime::Pause => OutOfTime::AutoPass,
+        }
+    }
+}
+
+impl std::fmt::Display for OutOfTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutOfTime::AutoPass => write!(f, "Pass"),
+            OutOfTime::BotTakeover => write!(f, "Bot"),
+            OutOfTime::Pause => write!(f, "Pause"),
+        }
+    }
+}
+
+/// Chess clock settings, picked by the host in the lobby
+pub struct TurnTimer {
+    /// Minutes each player gets for the whole game, or no clock at all
+    pub bank: Option<u32>,
+    pub out_of_time: OutOfTime,
+}
+
+impl Default for TurnTimer {
+    fn default() -> Self {
+        TurnTimer {
+            bank: None,
+            out_of_time: OutOfTime::AutoPass,
+        }
+    }
+}
+
+impl TurnTimer {
+    pub fn next_bank(&mut self) {
+        let i = BANK_MINUTES
+            .iter()
+            .position(|&minutes| minutes == self.bank)
+            .unwrap_or(0);
+        self.bank = BANK_MINUTES[(i + 1) % BANK_MINUTES.len()];
+    }
+
+    pub fn bank_label(&self, tr: &Tr) -> String {
+        match self.bank {
+            Some(minutes) => tr.fmt("lobby.time_bank", &[&minutes]),
+            None => tr.get("lobby.time_bank_off"),
+        }
+    }
+
+    pub fn out_of_time_label(&self, tr: &Tr) -> String {
+        tr.fmt(
+            "lobby.out_of_time",
+            &[&tr.get(&format!("out_of_time.{:?}", self.out_of_time))],
+        )
+    }
+}
+
+#[derive(Archive, Unarchive, PartialEq, Copy, Clone, Debug)]
+#[archive(derive(CheckBytes))]
+pub struct TimeBank {
+    pub faction: Faction,
+    pub seconds: f32,
+}
+
+/// Seconds each faction has left. The host keeps the real clock and clients follow along.
+#[derive(Default)]
+pub struct TimeBanks {
+    pub remaining: HashMap<Faction, f32>,
+}
+
+/// A bot making a single decision for a player who ran out of time where there was no passing
+struct Substitute(Context);
+
+struct ClockText;
+
+fn init_clock(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    mut banks: ResMut<TimeBanks>,
+) {
+    banks.remaining.clear();
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(60.0),
+                    left: Val::Percent(45.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                style: TextStyle {
+                    font_size: 28.0,
+                    color: Color::ANTIQUE_WHITE,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(ClockText);
+}
+
+/// Whoever the game is waiting on right now, if it's waiting on anyone
+fn pending(info: &Info, queue: &ActionQueue) -> Option<Entity> {
+    if info.play_order.is_empty() || info.context == Context::None || !queue.is_empty() {
+        None
+    } else {
+        Some(info.get_active_player())
+    }
+}
+
+/// Runs the clock of whoever the game is waiting on, and deals with them once it runs out
+fn time_bank_system(
+    commands: &mut Commands,
+    (time, network, timer, settings): (Res<Time>, Res<Network>, Res<TurnTimer>, Res<BotSettings>),
+    (info, queue, mut banks, mut sync): (
+        Res<Info>,
+        Res<ActionQueue>,
+        ResMut<TimeBanks>,
+        Local<f32>,
+    ),
+    (auction, movement, revival, mut actions): (
+        Res<Auction>,
+        Res<Movement>,
+        Res<Revival>,
+        ResMut<GameActions>,
+    ),
+    players: Query<&Player, Without<Bot>>,
+    mut server: Query<&mut Server>,
+) {
+    let bank = match timer.bank {
+        Some(minutes) if network.network_type != NetworkType::Client => minutes as f32 * 60.0,
+        _ => return,
+    };
+    let active = pending(&info, &queue);
+    if let Some((entity, player)) =
+        active.and_then(|entity| Some((entity, players.get(entity).ok()?)))
+    {
+        let faction = player.faction;
+        let remaining = banks.remaining.entry(faction).or_insert(bank);
+        *remaining = (*remaining - time.delta_seconds()).max(0.0);
+        if *remaining <= 0.0 && !actions.pending(faction) {
+            match timer.out_of_time {
+                OutOfTime::Pause => (),
+                OutOfTime::BotTakeover => {
+                    println!("{} is out of time, a bot takes over", faction);
+                    commands.insert_one(
+                        entity,
+                        Bot {
+                            agent: settings.difficulty.agent(),
+                        },
+                    );
+                }
+                // Only pass once per decision, while the phase systems catch up
+                OutOfTime::AutoPass => match info.context {
+                    Context::Bidding => {
+                        if !auction.bids.iter().any(|&(other, _)| other == faction) {
+                            println!("{} is out of time and passes", faction);
+                            actions.submitted.push_back(GameAction::Bid {
+                                faction,
+                                amount: None,
+                            });
+                        }
+                    }
+                    Context::Shipping | Context::Moving => {
+                        if !movement
+                            .requests
+                            .iter()
+                            .any(|request| request.faction() == faction)
+                        {
+                            println!("{} is out of time and passes", faction);
+                            actions.submitted.push_back(GameAction::Movement {
+                                request: MovementRequest::Pass { faction },
+                            });
+                        }
+                    }
+                    Context::Reviving => {
+                        if !revival
+                            .requests
+                            .iter()
+                            .any(|request| request.faction == faction)
+                        {
+                            println!("{} is out of time and revives nothing", faction);
+                            actions.submitted.push_back(GameAction::Revival {
+                                request: RevivalRequest {
+                                    faction,
+                                    troops: 0,
+                                    leader: None,
+                                },
+                            });
+                        }
+                    }
+                    context => {
+                        println!("{} is out of time, a bot decides for them", faction);
+                        commands.insert(
+                            entity,
+                            (
+                                Bot {
+                                    agent: settings.difficulty.agent(),
+                                },
+                                Substitute(context),
+                            ),
+                        );
+                    }
+                },
+            }
+        }
+    }
+
+    *sync -= time.delta_seconds();
+    if *sync <= 0.0 {
+        *sync = SYNC_INTERVAL;
+        if let Some(mut server) = server.iter_mut().next() {
+            server.broadcast(&ServerEvent::TimeBanks {
+                banks: banks
+                    .remaining
+                    .iter()
+                    .map(|(&faction, &seconds)| TimeBank { faction, seconds })
+                    .collect(),
+            });
+        }
+    }
+}
+
+/// Hands the seat back once the substitute bot has made its decision
+fn substitute_system(
+    commands: &mut Commands,
+    (info, queue): (Res<Info>, Res<ActionQueue>),
+    substitutes: Query<(Entity, &Substitute)>,
+) {
+    for (entity, Substitute(context)) in substitutes.iter() {
+        if pending(&info, &queue) != Some(entity) || info.context != *context {
+            commands.remove_one::<Bot>(entity);
+            commands.remove_one::<Substitute>(entity);
+        }
+    }
+}
+
+/// Keeps a client's clock moving between the host's updates
+fn clock_tick_system(
+    (time, network): (Res<Time>, Res<Network>),
+    (info, queue): (Res<Info>, Res<ActionQueue>),
+    mut banks: ResMut<TimeBanks>,
+    players: Query<&Player>,
+) {
+    // The host runs the clocks for real in the time bank system
+    if network.network_type != NetworkType::Client {
+        return;
+    }
+    if let Some(player) = pending(&info, &queue).and_then(|entity| players.get(entity).ok()) {
+        if let Some(remaining) = banks.remaining.get_mut(&player.faction) {
+            *remaining = (*remaining - time.delta_seconds()).max(0.0);
+        }
+    }
+}
+
+fn clock_display_system(
+    (info, queue, banks): (Res<Info>, Res<ActionQueue>, Res<TimeBanks>),
+    players: Query<&Player>,
+    mut texts: Query<&mut Text, With<ClockText>>,
+) {
+    let shown = pending(&info, &queue)
+        .and_then(|entity| players.get(entity).ok())
+        .and_then(|player| {
+            banks
+                .remaining
+                .get(&player.faction)
+                .map(|&seconds| (player.faction, seconds))
+        });
+    let (value, color) = match shown {
+        Some((faction, seconds)) if seconds <= 0.0 => {
+            (format!("{}: out of time", faction), Color::RED)
+        }
+        Some((faction, seconds)) => {
+            let seconds = seconds.ceil() as u32;
+            (
+                format!("{}: {}:{:02}", faction, seconds / 60, seconds % 60),
+                if seconds < 60 {
+                    Color::ORANGE
+                } else {
+                    Color::ANTIQUE_WHITE
+                },
+            )
+        }
+        None => (String::new(), Color::ANTIQUE_WHITE),
+    };
+    for mut text in texts.iter_mut() {
+        if text.value != value {
+            text.value = value.clone();
+            text.style.color = color;
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use bevy::{ecs::FromResources, prelude::*};
+use ncollide3d::{
+    shape::{ConvexHull, Cylinder, ShapeHandle},
+    transformation::ToTriMesh,
+};
+
+use crate::{
+    components::{ColliderBundle, Spice, Tooltip, Unique},
+    dune_core::Faction,
+    material_cache::MaterialCache,
+    Screen, ScreenEntity, RESPONSE_STAGE,
+};
+
+/// Spice denominations, largest first
+pub const SPICE_VALUES: [i32; 4] = [10, 5, 2, 1];
+
+/// How far under the table idle tokens wait to be handed out again
+const STASH_DEPTH: f32 = 10.0;
+
+pub struct TokenPoolPlugin;
+
+impl Plugin for TokenPoolPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<TokenPool>()
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::JoinedGame, reset.system());
+    }
+}
+
+/// Shared meshes and materials for the spice and troop tokens, and the spice tokens taken off the
+/// table. Spice changes hands all game long, so tokens that leave play are hidden away and reused
+/// rather than despawned, and every token of a kind draws with the same material.
+pub struct TokenPool {
+    spice_mesh: Handle<Mesh>,
+    spice_shape: ShapeHandle<f32>,
+    spice_materials: HashMap<i32, Handle<StandardMaterial>>,
+    troop_materials: HashMap<Faction, Handle<StandardMaterial>>,
+    marker_mesh: Handle<Mesh>,
+    marker_shape: ShapeHandle<f32>,
+    idle: HashMap<i32, Vec<Entity>>,
+    spawned: usize,
+    recycled: usize,
+}
+
+impl FromResources for TokenPool {
+    fn from_resources(resources: &Resources) -> Self {
+        let asset_server = resources.get::<AssetServer>().unwrap();
+        let mut materials = resources.get_mut::<Assets<StandardMaterial>>().unwrap();
+        let mut cache = resources.get_mut::<MaterialCache>().unwrap();
+        let mut textured = |path: String| cache.textured(&path, &asset_server, &mut materials);
+        let spice_materials = SPICE_VALUES
+            .iter()
+            .map(|&value| (value, textured(format!("tokens/spice_{}.png", value))))
+            .collect();
+        let troop_materials = Faction::ALL
+            .iter()
+            .map(|&faction| {
+                let path = format!("tokens/{}_troop.png", faction.code());
+                (faction, textured(path))
+            })
+            .collect();
+        TokenPool {
+            spice_mesh: asset_server.get_handle("spice_token.gltf#Mesh0/Primitive0"),
+            spice_shape: ShapeHandle::new(
+                ConvexHull::try_from_points(
+                    &Cylinder::<f32>::new(0.0018, 0.017).to_trimesh(32).coords,
+                )
+                .unwrap(),
+            ),
+            spice_materials,
+            troop_materials,
+            marker_mesh: asset_server.get_handle("big_token.gltf#Mesh0/Primitive0"),
+            marker_shape: ShapeHandle::new(
+                ConvexHull::try_from_points(
+                    &Cylinder::<f32>::new(0.0035, 0.06).to_trimesh(32).coords,
+                )
+                .unwrap(),
+            ),
+            idle: HashMap::new(),
+            spawned: 0,
+            recycled: 0,
+        }
+    }
+}
+
+impl TokenPool {
+    pub fn troop_material(&self, faction: Faction) -> Handle<StandardMaterial> {
+        self.troop_materials[&faction].clone()
+    }
+
+    /// A big token in the faction's colors, standing in for a whole stack of its troops
+    pub fn spawn_troop_marker(
+        &self,
+        commands: &mut Commands,
+        faction: Faction,
+        transform: Transform,
+    ) -> Entity {
+        let material = self.troop_material(faction);
+        let mesh = self.marker_mesh.clone();
+        commands
+            .spawn(ColliderBundle::new(self.marker_shape.clone()).with_transform(transform))
+            .with(ScreenEntity)
+            .with(Visible::default())
+            .with_children(|parent| {
+                parent.spawn(PbrBundle {
+                    mesh,
+                    material,
+                    ..Default::default()
+                });
+            })
+            .current_entity()
+            .unwrap()
+    }
+
+    /// Puts a spice token of `value` into play, reusing an idle one when there is one
+    pub fn spawn_spice(
+        &mut self,
+        commands: &mut Commands,
+        value: i32,
+        location: Option<Entity>,
+        transform: Transform,
+    ) -> Entity {
+        let spice = Spice { value, location };
+        let tooltip = Tooltip(format!("{} spice", value));
+        if let Some(entity) = self.idle.get_mut(&value).and_then(Vec::pop) {
+            self.recycled += 1;
+            commands.insert(
+                entity,
+                (
+                    spice,
+                    tooltip,
+                    transform,
+                    Visible {
+                        is_visible: true,
+                        ..Default::default()
+                    },
+                ),
+            );
+            return entity;
+        }
+        self.spawned += 1;
+        let material = self.spice_materials[&value].clone();
+        let mesh = self.spice_mesh.clone();
+        commands
+            .spawn(ColliderBundle::new(self.spice_shape.clone()).with_transform(transform))
+            .with(ScreenEntity)
+            .with(spice)
+            .with(tooltip)
+            .with(Visible::default())
+            .with_children(|parent| {
+                parent.spawn(PbrBundle {
+                    mesh,
+                    material,
+                    ..Default::default()
+                });
+            })
+            .current_entity()
+            .unwrap()
+    }
+
+    /// Takes a spice token of `value` out of play, keeping it for the next `spawn_spice`
+    pub fn release_spice(&mut self, commands: &mut Commands, entity: Entity, value: i32) {
+        commands.remove::<(Spice, Tooltip)>(entity);
+        commands.remove_one::<Unique>(entity);
+        commands.insert(
+            entity,
+            (
+                Transform::from_translation(-STASH_DEPTH * Vec3::unit_y()),
+                Visible {
+                    is_visible: false,
+                    ..Default::default()
+                },
+            ),
+        );
+        self.idle.entry(value).or_default().push(entity);
+    }
+
+    /// Reports how many spice tokens were spawned and how many were reused since the last report
+    pub fn report(&mut self) {
+        if self.spawned + self.recycled > 0 {
+            println!(
+                "Spice tokens: {} spawned, {} reused",
+                self.spawned, self.recycled
+            );
+        }
+        self.spawned = 0;
+        self.recycled = 0;
+    }
+}
+
+/// The idle tokens go with the rest of the game's entities
+fn reset(mut pool: ResMut<TokenPool>) {
+    pool.idle.clear();
+    pool.spawned = 0;
+    pool.recycled = 0;
+}
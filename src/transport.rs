@@ -0,0 +1,445 @@
+//! How messages get between the host and its clients. Native builds talk UDP through laminar,
+//! and a native host also takes WebSocket connections, the only kind a browser can open, so a
+//! browser build can join without installing anything.
+
+use std::net::SocketAddr;
+
+use crate::network::Channel;
+
+#[cfg(target_arch = "wasm32")]
+pub use browser::{client, host};
+#[cfg(not(target_arch = "wasm32"))]
+pub use native::{client, host};
+
+/// Browsers connect to a host here
+pub const WEB_SOCKET_PORT: u16 = 12348;
+/// A host serves the browser build and its assets here
+pub const WEB_PAGE_PORT: u16 = 12349;
+
+/// Something that came in from a peer
+pub enum TransportEvent {
+    Packet {
+        address: SocketAddr,
+        channel: Channel,
+        payload: Vec<u8>,
+    },
+    Connect(SocketAddr),
+    Timeout(SocketAddr),
+    Disconnect(SocketAddr),
+}
+
+pub trait Transport: Send + Sync {
+    /// Sends `payload` to `address` on the channel. Peers that have gone away are skipped rather
+    /// than treated as an error, the same as UDP would.
+    fn send(
+        &mut self,
+        address: SocketAddr,
+        channel: Channel,
+        payload: Vec<u8>,
+    ) -> Result<(), String>;
+
+    /// Takes care of whatever is due, like resends and timeouts, and hands back the next event
+    fn poll(&mut self) -> Option<TransportEvent>;
+}
+
+/// WebSockets are a stream of whole messages, so each one starts with a byte for the channel it
+/// was sent on. Both arrive reliably, but the receiving end treats them as it would over UDP.
+fn frame(channel: Channel, payload: Vec<u8>) -> Vec<u8> {
+    let tag = match channel {
+        Channel::Reliable => 0,
+        Channel::Unreliable => 1,
+    };
+    std::iter::once(tag).chain(payload).collect()
+}
+
+fn unframe(address: SocketAddr, bytes: &[u8]) -> Option<TransportEvent> {
+    let (&tag, payload) = bytes.split_first()?;
+    let channel = match tag {
+        0 => Channel::Reliable,
+        1 => Channel::Unreliable,
+        _ => return None,
+    };
+    Some(TransportEvent::Packet {
+        address,
+        channel,
+        payload: payload.to_vec(),
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::{
+        collections::{HashMap, VecDeque},
+        io::ErrorKind,
+        net::{SocketAddr, TcpListener, TcpStream},
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use laminar::{Config, DeliveryGuarantee, Packet, Socket, SocketEvent};
+    use tungstenite::{Error, Message, WebSocket};
+
+    use super::{frame, unframe, Transport, TransportEvent, WEB_SOCKET_PORT};
+    use crate::{network::Channel, page_server};
+
+    /// How long a connection can go quiet before it counts as dropped
+    const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+    /// How long a browser gets to finish the WebSocket handshake
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Binds the host's UDP socket, and starts taking browsers if their port is free
+    pub fn host(port: u16) -> Box<dyn Transport> {
+        let udp = UdpTransport::bind(port).expect("Failed to bind server socket!");
+        let web = WebSocketListener::bind(WEB_SOCKET_PORT)
+            .map_err(|err| println!("Not taking browser players: {}", err))
+            .ok();
+        if web.is_some() {
+            page_server::start();
+        }
+        Box::new(HostTransport {
+            udp,
+            web,
+            web_turn: false,
+        })
+    }
+
+    pub fn client(port: u16) -> Box<dyn Transport> {
+        Box::new(UdpTransport::bind(port).expect("Failed to bind client socket!"))
+    }
+
+    /// Game state resyncs are much bigger than regular messages
+    fn socket_config() -> Config {
+        Config {
+            max_packet_size: 255 * 1024,
+            max_fragments: 255,
+            idle_connection_timeout: DISCONNECT_TIMEOUT,
+            ..Default::default()
+        }
+    }
+
+    pub struct UdpTransport {
+        socket: Socket,
+    }
+
+    impl UdpTransport {
+        fn bind(port: u16) -> Result<Self, String> {
+            Socket::bind_with_config(("0.0.0.0", port), socket_config())
+                .map(|socket| UdpTransport { socket })
+                .map_err(|err| err.to_string())
+        }
+    }
+
+    impl Transport for UdpTransport {
+        fn send(
+            &mut self,
+            address: SocketAddr,
+            channel: Channel,
+            payload: Vec<u8>,
+        ) -> Result<(), String> {
+            let packet = match channel {
+                Channel::Reliable => Packet::reliable_ordered(address, payload, None),
+                // Its own stream, so cosmetic updates never hold up or reorder game actions
+                Channel::Unreliable => Packet::unreliable_sequenced(address, payload, Some(1)),
+            };
+            self.socket.send(packet).map_err(|err| err.to_string())
+        }
+
+        fn poll(&mut self) -> Option<TransportEvent> {
+            self.socket.manual_poll(Instant::now());
+            self.socket.recv().map(|event| match event {
+                SocketEvent::Packet(packet) => TransportEvent::Packet {
+                    address: packet.addr(),
+                    channel: if packet.delivery_guarantee() == DeliveryGuarantee::Reliable {
+                        Channel::Reliable
+                    } else {
+                        Channel::Unreliable
+                    },
+                    payload: packet.payload().to_vec(),
+                },
+                SocketEvent::Connect(address) => TransportEvent::Connect(address),
+                SocketEvent::Timeout(address) => TransportEvent::Timeout(address),
+                SocketEvent::Disconnect(address) => TransportEvent::Disconnect(address),
+            })
+        }
+    }
+
+    /// Browsers that have connected to the host, and the ones still shaking hands
+    struct WebSocketListener {
+        listener: TcpListener,
+        /// Filled in by the handshake threads as browsers finish
+        handshakes: Arc<Mutex<Vec<(SocketAddr, WebSocket<TcpStream>)>>>,
+        peers: HashMap<SocketAddr, WebSocket<TcpStream>>,
+        events: VecDeque<TransportEvent>,
+    }
+
+    impl WebSocketListener {
+        fn bind(port: u16) -> Result<Self, String> {
+            let listener = TcpListener::bind(("0.0.0.0", port)).map_err(|err| err.to_string())?;
+            listener
+                .set_nonblocking(true)
+                .map_err(|err| err.to_string())?;
+            Ok(WebSocketListener {
+                listener,
+                handshakes: Arc::default(),
+                peers: HashMap::new(),
+                events: VecDeque::new(),
+            })
+        }
+
+        /// Shakes hands off the main thread, since a browser can take its time about it
+        fn accept(&mut self) {
+            while let Ok((stream, address)) = self.listener.accept() {
+                let handshakes = self.handshakes.clone();
+                thread::spawn(move || {
+                    let socket = stream
+                        .set_nonblocking(false)
+                        .and_then(|_| stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)))
+                        .map_err(|err| err.to_string())
+                        .and_then(|_| tungstenite::accept(stream).map_err(|err| err.to_string()))
+                        .and_then(|socket| {
+                            socket
+                                .get_ref()
+                                .set_nonblocking(true)
+                                .map(|_| socket)
+                                .map_err(|err| err.to_string())
+                        });
+                    match socket {
+                        Ok(socket) => handshakes.lock().unwrap().push((address, socket)),
+                        Err(err) => println!("Browser {} failed to connect: {}", address, err),
+                    }
+                });
+            }
+            for (address, socket) in self.handshakes.lock().unwrap().drain(..) {
+                self.peers.insert(address, socket);
+                self.events.push_back(TransportEvent::Connect(address));
+            }
+        }
+
+        fn contains(&self, address: SocketAddr) -> bool {
+            self.peers.contains_key(&address)
+        }
+    }
+
+    /// Whether a WebSocket error only means there is nothing more to do for now
+    fn would_block(err: &Error) -> bool {
+        matches!(err, Error::Io(err) if err.kind() == ErrorKind::WouldBlock)
+    }
+
+    impl Transport for WebSocketListener {
+        fn send(
+            &mut self,
+            address: SocketAddr,
+            channel: Channel,
+            payload: Vec<u8>,
+        ) -> Result<(), String> {
+            if let Some(socket) = self.peers.get_mut(&address) {
+                match socket.write_message(Message::Binary(frame(channel, payload))) {
+                    Err(err) if !would_block(&err) => return Err(err.to_string()),
+                    _ => (),
+                }
+            }
+            Ok(())
+        }
+
+        fn poll(&mut self) -> Option<TransportEvent> {
+            self.accept();
+            let mut closed = Vec::new();
+            for (&address, socket) in self.peers.iter_mut() {
+                loop {
+                    match socket.read_message() {
+                        Ok(Message::Binary(bytes)) => {
+                            self.events.extend(unframe(address, &bytes));
+                        }
+                        Ok(Message::Close(_)) => {
+                            closed.push(address);
+                            break;
+                        }
+                        Ok(_) => (),
+                        Err(err) if would_block(&err) => break,
+                        Err(_) => {
+                            closed.push(address);
+                            break;
+                        }
+                    }
+                }
+                // Whatever didn't fit in the socket last time
+                match socket.write_pending() {
+                    Err(err) if !would_block(&err) => closed.push(address),
+                    _ => (),
+                }
+            }
+            for address in closed {
+                if self.peers.remove(&address).is_some() {
+                    self.events.push_back(TransportEvent::Disconnect(address));
+                }
+            }
+            self.events.pop_front()
+        }
+    }
+
+    /// Native clients over UDP, and browsers over WebSockets when their port could be bound
+    struct HostTransport {
+        udp: UdpTransport,
+        web: Option<WebSocketListener>,
+        /// Takes turns between the two, so a busy one can't hold the other up
+        web_turn: bool,
+    }
+
+    impl Transport for HostTransport {
+        fn send(
+            &mut self,
+            address: SocketAddr,
+            channel: Channel,
+            payload: Vec<u8>,
+        ) -> Result<(), String> {
+            match self.web.as_mut() {
+                Some(web) if web.contains(address) => web.send(address, channel, payload),
+                _ => self.udp.send(address, channel, payload),
+            }
+        }
+
+        fn poll(&mut self) -> Option<TransportEvent> {
+            self.web_turn = !self.web_turn;
+            let udp = &mut self.udp;
+            match self.web.as_mut() {
+                Some(web) if self.web_turn => web.poll().or_else(|| udp.poll()),
+                Some(web) => udp.poll().or_else(|| web.poll()),
+                None => udp.poll(),
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod browser {
+    use std::{
+        collections::VecDeque,
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+
+    use js_sys::{ArrayBuffer, Uint8Array};
+    use wasm_bindgen::{closure::Closure, JsCast};
+    use web_sys::{BinaryType, MessageEvent, WebSocket};
+
+    use super::{frame, unframe, Transport, TransportEvent, WEB_SOCKET_PORT};
+    use crate::network::Channel;
+
+    /// A page can't host, so a game hosted from one never hears from anybody
+    pub fn host(_port: u16) -> Box<dyn Transport> {
+        println!("Games can't be hosted from a browser, only joined");
+        Box::new(Unhosted)
+    }
+
+    pub fn client(_port: u16) -> Box<dyn Transport> {
+        Box::new(BrowserTransport::default())
+    }
+
+    struct Unhosted;
+
+    impl Transport for Unhosted {
+        fn send(&mut self, _: SocketAddr, _: Channel, _: Vec<u8>) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn poll(&mut self) -> Option<TransportEvent> {
+            None
+        }
+    }
+
+    /// A WebSocket to the host that served the page. The socket opens on the first message sent,
+    /// and `address` only names the host in events, since a page can only reach where it came
+    /// from.
+    #[derive(Default)]
+    struct BrowserTransport {
+        socket: Option<WebSocket>,
+        /// Filled in by the socket's callbacks
+        events: Arc<Mutex<VecDeque<TransportEvent>>>,
+        /// Held until the socket opens
+        waiting: Vec<Vec<u8>>,
+        callbacks: Vec<Closure<dyn FnMut(web_sys::Event)>>,
+    }
+
+    // A page runs on one thread, so the socket and its callbacks are never shared across threads
+    unsafe impl Send for BrowserTransport {}
+    unsafe impl Sync for BrowserTransport {}
+
+    impl BrowserTransport {
+        fn open(&mut self, address: SocketAddr) -> Result<(), String> {
+            let host = web_sys::window()
+                .and_then(|window| window.location().hostname().ok())
+                .ok_or_else(|| "The page has no host to connect to".to_string())?;
+            let url = format!("ws://{}:{}", host, WEB_SOCKET_PORT);
+            let socket = WebSocket::new(&url).map_err(|err| format!("{:?}", err))?;
+            socket.set_binary_type(BinaryType::Arraybuffer);
+
+            let events = self.events.clone();
+            let on_open = Closure::wrap(Box::new(move |_: web_sys::Event| {
+                events
+                    .lock()
+                    .unwrap()
+                    .push_back(TransportEvent::Connect(address));
+            }) as Box<dyn FnMut(web_sys::Event)>);
+            let events = self.events.clone();
+            let on_message = Closure::wrap(Box::new(move |event: web_sys::Event| {
+                let data = match event.dyn_into::<MessageEvent>() {
+                    Ok(event) => event.data(),
+                    Err(_) => return,
+                };
+                if let Ok(buffer) = data.dyn_into::<ArrayBuffer>() {
+                    let bytes = Uint8Array::new(&buffer).to_vec();
+                    events.lock().unwrap().extend(unframe(address, &bytes));
+                }
+            }) as Box<dyn FnMut(web_sys::Event)>);
+            let events = self.events.clone();
+            let on_close = Closure::wrap(Box::new(move |_: web_sys::Event| {
+                events
+                    .lock()
+                    .unwrap()
+                    .push_back(TransportEvent::Disconnect(address));
+            }) as Box<dyn FnMut(web_sys::Event)>);
+            socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+            socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+            socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+            self.callbacks = vec![on_open, on_message, on_close];
+            self.socket = Some(socket);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), String> {
+            let socket = match self.socket.as_ref() {
+                Some(socket) if socket.ready_state() == WebSocket::OPEN => socket,
+                _ => return Ok(()),
+            };
+            for mut message in self.waiting.drain(..) {
+                socket
+                    .send_with_u8_array(&mut message)
+                    .map_err(|err| format!("{:?}", err))?;
+            }
+            Ok(())
+        }
+    }
+
+    impl Transport for BrowserTransport {
+        fn send(
+            &mut self,
+            address: SocketAddr,
+            channel: Channel,
+            payload: Vec<u8>,
+        ) -> Result<(), String> {
+            if self.socket.is_none() {
+                self.open(address)?;
+            }
+            self.waiting.push(frame(channel, payload));
+            self.flush()
+        }
+
+        fn poll(&mut self) -> Option<TransportEvent> {
+            if let Err(err) = self.flush() {
+                println!("Failed to send to the host: {}", err);
+            }
+            self.events.lock().unwrap().pop_front()
+        }
+    }
+}
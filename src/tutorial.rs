@@ -0,0 +1,403 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    bot::Bot,
+    cards::CardRequest,
+    components::Player,
+    data::{Faction, UiRect},
+    game_action::{GameAction, GameActions},
+    hud::{HotSeat, HudMaterials},
+    layout::{Anchored, AnchoredHeight},
+    locale::Tr,
+    phase::{GamePhase, MovementRequest, Phase},
+    resources::{open_data, Data},
+    territory::SelectedTerritory,
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// The scenario the tutorial walks a new player through
+const SCRIPT: &str = "data/tutorial.ron";
+/// Highlights fade in and out this many times a second
+const PULSE_RATE: f32 = 0.8;
+
+pub struct TutorialPlugin;
+
+impl Plugin for TutorialPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Tutorial>()
+            .init_resource::<TutorialMaterials>()
+            .on_state_enter(RESPONSE_STAGE, Screen::HostingGame, init_tutorial.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                tutorial_step_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                tutorial_popup_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                highlight_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system());
+    }
+}
+
+/// A scripted first turn: who the player plays, and what they are shown and asked to do along
+/// the way
+#[derive(Deserialize)]
+pub struct TutorialScript {
+    pub faction: Faction,
+    pub steps: Vec<TutorialStep>,
+}
+
+#[derive(Deserialize)]
+pub struct TutorialStep {
+    /// Language key of the text in the popup
+    pub text: String,
+    #[serde(default)]
+    pub highlight: Option<Highlight>,
+    pub until: Until,
+}
+
+/// What a step points the player at
+#[derive(Clone, PartialEq, Deserialize, Debug)]
+pub enum Highlight {
+    /// Opens the territory's summary, the same as clicking it would
+    Territory(String),
+    TurnOrder,
+    Phases,
+    TurnCounter,
+}
+
+impl Highlight {
+    fn rect(&self, data: &Data) -> Option<UiRect> {
+        match self {
+            Highlight::Territory(_) => None,
+            Highlight::TurnOrder => Some(data.ui_structure.turn_order.clone()),
+            Highlight::Phases => Some(data.ui_structure.phases.clone()),
+            Highlight::TurnCounter => Some(data.ui_structure.turn_counter.clone()),
+        }
+    }
+}
+
+/// What moves the tutorial on to its next step
+#[derive(Copy, Clone, PartialEq, Deserialize, Debug)]
+pub enum Until {
+    /// The player clicks the popup once they have read it
+    Next,
+    /// The game gets to this phase of the first turn, or is already past it
+    Phase(TutorialPhase),
+    /// The player makes this decision, which from then on they are allowed to make
+    Action(ExpectedAction),
+}
+
+/// The phases of a regular turn, in the order they come up, which is also where they are on the
+/// phase track
+#[derive(Copy, Clone, PartialEq, Deserialize, Debug)]
+pub enum TutorialPhase {
+    Storm,
+    SpiceBlow,
+    Charity,
+    Bidding,
+    Revival,
+    Movement,
+    Battle,
+}
+
+impl TutorialPhase {
+    fn reached(self, phase: Phase) -> bool {
+        phase
+            .track_index()
+            .map_or(false, |index| index >= self as usize)
+    }
+}
+
+/// The kinds of decision the tutorial teaches. Passing counts as making the decision, so a
+/// player who can't afford something is never stuck.
+#[derive(Copy, Clone, PartialEq, Deserialize, Debug)]
+pub enum ExpectedAction {
+    Traitor,
+    Bid,
+    Revival,
+    Ship,
+    Move,
+    Battle,
+}
+
+impl ExpectedAction {
+    pub fn made_by(self, action: &GameAction) -> bool {
+        match (self, action) {
+            (ExpectedAction::Traitor, GameAction::Traitor { .. })
+            | (ExpectedAction::Bid, GameAction::Bid { .. })
+            | (ExpectedAction::Revival, GameAction::Revival { .. })
+            | (ExpectedAction::Battle, GameAction::Battle { .. }) => true,
+            (ExpectedAction::Ship, GameAction::Movement { request }) => matches!(
+                request,
+                MovementRequest::Ship { .. } | MovementRequest::Pass { .. }
+            ),
+            (ExpectedAction::Move, GameAction::Movement { request }) => matches!(
+                request,
+                MovementRequest::Move { .. } | MovementRequest::Pass { .. }
+            ),
+            _ => false,
+        }
+    }
+
+    /// Cards can be asked about at any time, so turning them down is always allowed, and so is
+    /// moving on from the Mentat Pause
+    pub fn permits(allowed: &[ExpectedAction], action: &GameAction) -> bool {
+        matches!(
+            action,
+            GameAction::CardPlay {
+                request: CardRequest::Pass { .. }
+            } | GameAction::Interrupt { karama: false, .. }
+                | GameAction::MentatReady { .. }
+                | GameAction::Capture { .. }
+        ) || allowed.iter().any(|expected| expected.made_by(action))
+    }
+}
+
+pub struct Tutorial {
+    pub script: TutorialScript,
+    /// The step being shown, while the tutorial is running
+    pub step: Option<usize>,
+    /// How much of the action log had been seen when the step began
+    seen: usize,
+}
+
+impl Default for Tutorial {
+    fn default() -> Self {
+        Tutorial {
+            script: ron::de::from_reader(open_data(SCRIPT).unwrap()).unwrap(),
+            step: None,
+            seen: 0,
+        }
+    }
+}
+
+impl Tutorial {
+    pub fn start(&mut self) {
+        self.step = Some(0);
+        self.seen = 0;
+    }
+
+    fn current(&self) -> Option<&TutorialStep> {
+        self.step.and_then(|step| self.script.steps.get(step))
+    }
+
+    /// Every decision taught so far, including the one being taught now
+    fn allowed(&self) -> Vec<ExpectedAction> {
+        let taught = self.step.map_or(0, |step| step + 1);
+        self.script.steps[..taught.min(self.script.steps.len())]
+            .iter()
+            .filter_map(|step| match step.until {
+                Until::Action(expected) => Some(expected),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn advance(&mut self, seen: usize) {
+        self.step = self
+            .step
+            .map(|step| step + 1)
+            .filter(|&step| step < self.script.steps.len());
+        self.seen = seen;
+        if self.step.is_none() {
+            println!("Tutorial finished");
+        }
+    }
+}
+
+struct TutorialMaterials {
+    highlight: Handle<ColorMaterial>,
+}
+
+impl FromResources for TutorialMaterials {
+    fn from_resources(resources: &Resources) -> Self {
+        let mut materials = resources.get_mut::<Assets<ColorMaterial>>().unwrap();
+        TutorialMaterials {
+            highlight: materials.add(Color::rgba(1.0, 0.85, 0.3, 0.3).into()),
+        }
+    }
+}
+
+struct TutorialPopup;
+
+struct TutorialText;
+
+/// Rings the part of the HUD the current step is about
+struct TutorialHighlight;
+
+fn init_tutorial(
+    commands: &mut Commands,
+    (tutorial, data, asset_server): (Res<Tutorial>, Res<Data>, Res<AssetServer>),
+    materials: Res<HudMaterials>,
+) {
+    if tutorial.step.is_none() {
+        return;
+    }
+    // A button, so it can be clicked to move on
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(12.0)),
+                ..Default::default()
+            },
+            material: materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(TutorialPopup)
+        .with(Anchored::new(data.ui_structure.tutorial.clone()).with_height(AnchoredHeight::Auto))
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                        value: String::new(),
+                        style: TextStyle {
+                            font_size: 18.0,
+                            color: Color::ANTIQUE_WHITE,
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(TutorialText);
+        });
+}
+
+/// Moves on to the next step once the current one is done, and keeps the player to the decisions
+/// taught so far
+fn tutorial_step_system(
+    (mut tutorial, mut actions, phase): (ResMut<Tutorial>, ResMut<GameActions>, Res<GamePhase>),
+    (mut hot_seat, mut selected): (ResMut<HotSeat>, ResMut<SelectedTerritory>),
+    players: Query<(Entity, &Player), Without<Bot>>,
+    popups: Query<&Interaction, (Mutated<Interaction>, With<TutorialPopup>)>,
+) {
+    let (until, highlight) = match tutorial.current() {
+        Some(step) => (step.until, step.highlight.clone()),
+        None => {
+            actions.allowed = None;
+            return;
+        }
+    };
+    // Only one seat is played from this machine, so there's nobody to hide it from
+    if let Some((entity, _)) = players
+        .iter()
+        .find(|(_, player)| player.faction == tutorial.script.faction)
+    {
+        hot_seat.reveal(entity);
+    }
+    if let Some(Highlight::Territory(name)) = highlight.as_ref() {
+        if selected.name.is_none() {
+            selected.name = Some(name.clone());
+        }
+    }
+    let faction = tutorial.script.faction;
+    let done = match until {
+        Until::Next => popups
+            .iter()
+            .any(|interaction| *interaction == Interaction::Clicked),
+        Until::Phase(target) => target.reached(phase.phase),
+        Until::Action(expected) => actions.log[tutorial.seen.min(actions.log.len())..]
+            .iter()
+            .any(|logged| logged.action.faction() == faction && expected.made_by(&logged.action)),
+    };
+    if done {
+        if matches!(highlight, Some(Highlight::Territory(_))) {
+            selected.name = None;
+        }
+        tutorial.advance(actions.log.len());
+    }
+    actions.allowed = tutorial.step.map(|_| tutorial.allowed());
+}
+
+/// Shows the text of the current step, and takes the popup away once the tutorial is over
+fn tutorial_popup_system(
+    (tutorial, tr): (Res<Tutorial>, Res<Tr>),
+    mut popups: Query<&mut Style, With<TutorialPopup>>,
+    mut texts: Query<&mut Text, With<TutorialText>>,
+) {
+    let step = tutorial.current();
+    let display = if step.is_some() {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    for mut style in popups.iter_mut() {
+        if style.display != display {
+            style.display = display;
+        }
+    }
+    let step = if let Some(step) = step {
+        step
+    } else {
+        return;
+    };
+    let mut value = tr.get(&step.text);
+    if step.until == Until::Next {
+        value = format!("{}\n\n{}", value, tr.get("tutorial.next"));
+    }
+    for mut text in texts.iter_mut() {
+        if text.value != value {
+            text.value = value.clone();
+        }
+    }
+}
+
+/// Puts a pulsing ring around the HUD panel the current step is about
+fn highlight_system(
+    commands: &mut Commands,
+    (tutorial, data, time): (Res<Tutorial>, Res<Data>, Res<Time>),
+    (materials, mut colors): (Res<TutorialMaterials>, ResMut<Assets<ColorMaterial>>),
+    mut shown: Local<Option<Highlight>>,
+    highlights: Query<Entity, With<TutorialHighlight>>,
+) {
+    let highlight = tutorial
+        .current()
+        .and_then(|step| step.highlight.clone())
+        .filter(|highlight| highlight.rect(&data).is_some());
+    // The ring goes with the rest of the screen when a game ends, so it is put back for the next
+    let missing = highlight.is_some() && highlights.iter().next().is_none();
+    if *shown != highlight || missing {
+        for entity in highlights.iter() {
+            commands.despawn(entity);
+        }
+        if let Some(rect) = highlight
+            .as_ref()
+            .and_then(|highlight| highlight.rect(&data))
+        {
+            commands
+                .spawn(NodeBundle {
+                    material: materials.highlight.clone(),
+                    ..Default::default()
+                })
+                .with(ScreenEntity)
+                .with(TutorialHighlight)
+                .with(Anchored::new(rect));
+        }
+        *shown = highlight;
+    }
+    if shown.is_some() {
+        if let Some(material) = colors.get_mut(&materials.highlight) {
+            let phase = time.seconds_since_startup() as f32 * PULSE_RATE * 2.0 * PI;
+            let pulse = 0.5 - 0.5 * phase.cos();
+            material.color.set_a(0.15 + 0.3 * pulse);
+        }
+    }
+}
+
+fn reset(mut tutorial: ResMut<Tutorial>) {
+    tutorial.step = None;
+    tutorial.seen = 0;
+}
@@ -18,29 +18,23 @@ pub fn screen_to_world(ss_pos: Vec3, transform: Transform, v: Mat4) -> Vec3 {
     p.xyz() / p.w
 }
 
-pub fn divide_spice(mut total: i32) -> (i32, i32, i32, i32) {
-    let (mut tens, mut fives, mut twos, mut ones) = (0, 0, 0, 0);
-    while total > 0 {
-        match total {
-            1 => {
-                total -= 1;
-                ones += 1;
-            }
-            2..=4 => {
-                total -= 2;
-                twos += 1;
-            }
-            5..=9 => {
-                total -= 5;
-                fives += 1;
-            }
-            _ => {
-                total -= 10;
-                tens += 1;
-            }
-        }
+/// Where a point in the world shows up on the window, if it is in front of the camera
+pub fn world_to_screen(
+    pos: Vec3,
+    window: &Window,
+    camera: &Camera,
+    cam_transform: &Transform,
+) -> Option<Vec2> {
+    let clip =
+        camera.projection_matrix * cam_transform.compute_matrix().inverse() * pos.extend(1.0);
+    if clip.w <= 0.0 {
+        return None;
     }
-    (tens, fives, twos, ones)
+    let ndc = clip.xyz() / clip.w;
+    Some(Vec2::new(
+        (ndc.x + 1.0) * 0.5 * window.width(),
+        (ndc.y + 1.0) * 0.5 * window.height(),
+    ))
 }
 
 pub fn compute_click_ray(
@@ -62,6 +56,24 @@ pub fn compute_click_ray(
     )
 }
 
+fn ray_toi(collider: &Collider, transform: &Transform, ray: &Ray<f32>) -> Option<f32> {
+    let (axis, angle) = transform.rotation.to_axis_angle();
+    let angleaxis = axis * angle;
+    collider.shape.toi_with_ray(
+        &Isometry3::from_parts(
+            Translation3::new(
+                transform.translation.x,
+                transform.translation.y,
+                transform.translation.z,
+            ),
+            UnitQuaternion::new(Vector3::new(angleaxis.x, angleaxis.y, angleaxis.z)),
+        ),
+        ray,
+        100.0,
+        true,
+    )
+}
+
 pub struct RayCastResult<'a, T: Component> {
     pub intersection: Vec3,
     pub entity: Entity,
@@ -88,25 +100,7 @@ pub fn closest<'a, T: Component>(
                             None
                         })
                 {
-                    let (axis, angle) = transform.rotation.to_axis_angle();
-                    let angleaxis = axis * angle;
-                    if let Some(toi) = collider.shape.toi_with_ray(
-                        &Isometry3::from_parts(
-                            Translation3::new(
-                                transform.translation.x,
-                                transform.translation.y,
-                                transform.translation.z,
-                            ),
-                            UnitQuaternion::new(Vector3::new(
-                                angleaxis.x,
-                                angleaxis.y,
-                                angleaxis.z,
-                            )),
-                        ),
-                        &ray,
-                        100.0,
-                        true,
-                    ) {
+                    if let Some(toi) = ray_toi(collider, transform, &ray) {
                         if closest_toi.is_none() {
                             closest_toi = Some(toi);
                             closest_t = Some((entity, t));
@@ -160,25 +154,7 @@ pub fn closest_mut<'a, 'b, T: Component>(
                             None
                         })
                 {
-                    let (axis, angle) = transform.rotation.to_axis_angle();
-                    let angleaxis = axis * angle;
-                    if let Some(toi) = collider.shape.toi_with_ray(
-                        &Isometry3::from_parts(
-                            Translation3::new(
-                                transform.translation.x,
-                                transform.translation.y,
-                                transform.translation.z,
-                            ),
-                            UnitQuaternion::new(Vector3::new(
-                                angleaxis.x,
-                                angleaxis.y,
-                                angleaxis.z,
-                            )),
-                        ),
-                        &ray,
-                        100.0,
-                        true,
-                    ) {
+                    if let Some(toi) = ray_toi(collider, transform, &ray) {
                         if closest_toi.is_none() {
                             closest_toi = Some(toi);
                             closest_t = Some((entity, t));
@@ -206,6 +182,62 @@ pub fn closest_mut<'a, 'b, T: Component>(
     None
 }
 
+/// The nearest enabled collider under the cursor, whatever it happens to be
+pub fn hovered(
+    windows: &Res<Windows>,
+    cameras: &Query<(&Camera, &Transform), Without<OrthographicProjection>>,
+    colliders: &Query<(Entity, &Collider, &Transform)>,
+) -> Option<Entity> {
+    let (camera, cam_transform) = cameras.iter().next()?;
+    let window = windows.get_primary()?;
+    let ray = compute_click_ray(window, window.cursor_position()?, camera, cam_transform);
+    colliders
+        .iter()
+        .filter(|(_, collider, _)| collider.enabled)
+        .filter_map(|(entity, collider, transform)| {
+            ray_toi(collider, transform, &ray).map(|toi| (entity, toi))
+        })
+        .min_by(|(_, toi1), (_, toi2)| toi1.partial_cmp(toi2).unwrap())
+        .map(|(entity, _)| entity)
+}
+
+/// The nearest of the given colliders under the cursor, enabled or not
+pub fn grab<'a>(
+    windows: &Res<Windows>,
+    cameras: &Query<(&Camera, &Transform), Without<OrthographicProjection>>,
+    colliders: impl Iterator<Item = (Entity, &'a Collider, Transform)>,
+) -> Option<Entity> {
+    let (camera, cam_transform) = cameras.iter().next()?;
+    let window = windows.get_primary()?;
+    let ray = compute_click_ray(window, window.cursor_position()?, camera, cam_transform);
+    colliders
+        .filter_map(|(entity, collider, transform)| {
+            ray_toi(collider, &transform, &ray).map(|toi| (entity, toi))
+        })
+        .min_by(|(_, toi1), (_, toi2)| toi1.partial_cmp(toi2).unwrap())
+        .map(|(entity, _)| entity)
+}
+
+/// Where the cursor meets the horizontal plane at the given height
+pub fn cursor_on_plane(
+    windows: &Res<Windows>,
+    cameras: &Query<(&Camera, &Transform), Without<OrthographicProjection>>,
+    height: f32,
+) -> Option<Vec3> {
+    let (camera, cam_transform) = cameras.iter().next()?;
+    let window = windows.get_primary()?;
+    let ray = compute_click_ray(window, window.cursor_position()?, camera, cam_transform);
+    if ray.dir.y.abs() < f32::EPSILON {
+        return None;
+    }
+    let toi = (height - ray.origin.y) / ray.dir.y;
+    if toi < 0.0 {
+        return None;
+    }
+    let p = ray.point_at(toi);
+    Some(Vec3::new(p[0], p[1], p[2]))
+}
+
 pub fn shuffle_deck<R>(rng: &mut R, offset: f32, entities: &mut HashMap<Entity, Mut<Transform>>)
 where
     R: Rng + ?Sized,
@@ -221,10 +253,20 @@ where
         })
         .unwrap()
         .translation;
+    // Start from the order of the deck rather than the map so a seeded shuffle is repeatable
     let mut order = entities.keys().cloned().collect::<Vec<_>>();
+    order.sort_by(|entity1, entity2| {
+        entities[entity1]
+            .translation
+            .y
+            .partial_cmp(&entities[entity2].translation.y)
+            .unwrap()
+            .then(entity1.id().cmp(&entity2.id()))
+    });
     order.shuffle(rng);
-    for entity in order {
-        entities.get_mut(&entity).unwrap().translation = start + (offset * Vec3::unit_y());
+    for (i, entity) in order.into_iter().enumerate() {
+        entities.get_mut(&entity).unwrap().translation =
+            start + (i as f32 * offset * Vec3::unit_y());
     }
 }
 
@@ -235,3 +277,24 @@ pub fn hand_positions(n: i32) -> Vec<Vec2> {
         .collect();
     res
 }
+
+pub fn auction_positions(n: i32) -> Vec<Vec2> {
+    (0..n)
+        .map(|i| Vec2::new(0.6 * ((1.0 + i as f32) / (1.0 + n as f32)) - 0.3, 0.4))
+        .collect()
+}
+
+/// Seconds since the Unix epoch, by the system clock
+#[cfg(not(target_arch = "wasm32"))]
+pub fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// Seconds since the Unix epoch, by the browser's clock, since there is no system one to ask
+#[cfg(target_arch = "wasm32")]
+pub fn unix_time() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
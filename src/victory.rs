@@ -0,0 +1,372 @@
+use std::mem::discriminant;
+
+use bevy::prelude::*;
+
+use crate::{
+    components::{Player, Prediction},
+    data::Faction,
+    dune_core::{Occupancy, ALLIED_STRONGHOLDS_TO_WIN, LAST_TURN, STRONGHOLDS_TO_WIN},
+    mentat::MentatPause,
+    menu::ButtonMaterials,
+    network::{Network, NetworkType, Server},
+    phase::{Action, ActionQueue, GamePhase, Phase},
+    protocol::ServerEvent,
+    resources::Info,
+    stats::GameStats,
+    tear_down, Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+pub struct VictoryPlugin;
+
+impl Plugin for VictoryPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GameResult>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                victory_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                control_phase_system.system(),
+            )
+            .on_state_enter(RESPONSE_STAGE, Screen::GameOver, init_game_over.system())
+            .on_state_exit(RESPONSE_STAGE, Screen::GameOver, tear_down.system())
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::GameOver,
+                game_over_button_system.system(),
+            );
+    }
+}
+
+/// Outlives the game so the results screen can show it and a rematch can reuse the factions
+#[derive(Default)]
+pub struct GameResult {
+    pub winners: Vec<Faction>,
+    pub reason: String,
+    pub turn: i32,
+    pub factions: Vec<Faction>,
+}
+
+/// Whether the Bene Gesserit called both one of the winners and the turn they won on
+fn predicted(info: &Info, predictions: &Query<&Prediction>, winners: &[Faction]) -> bool {
+    info.in_game().contains(&Faction::BeneGesserit)
+        && predictions.iter().next().map_or(false, |prediction| {
+            prediction.turn == Some(info.turn + 1)
+                && prediction
+                    .faction
+                    .map_or(false, |faction| winners.contains(&faction))
+        })
+}
+
+fn end_game(
+    state: &mut State<Screen>,
+    result: &mut GameResult,
+    info: &Info,
+    winners: Vec<Faction>,
+    reason: String,
+) {
+    println!("{}", reason);
+    *result = GameResult {
+        winners,
+        reason,
+        turn: info.turn + 1,
+        factions: info.factions_in_play.clone(),
+    };
+    state.set_next(Screen::GameOver).unwrap();
+}
+
+/// Checks for a stronghold victory, or a last faction standing, whenever the Movement or Battle
+/// phase finishes
+fn victory_system(
+    mut state: ResMut<State<Screen>>,
+    mut result: ResMut<GameResult>,
+    (info, phase, occupancy): (Res<Info>, Res<GamePhase>, Res<Occupancy>),
+    mut last_phase: Local<Option<Phase>>,
+    predictions: Query<&Prediction>,
+) {
+    let resolved = matches!(
+        *last_phase,
+        Some(Phase::Movement { .. }) | Some(Phase::Battle)
+    ) && last_phase.as_ref().map(discriminant) != Some(discriminant(&phase.phase));
+    *last_phase = match phase.phase {
+        // A new game is starting, forget about the last one
+        Phase::Setup { .. } => None,
+        phase => Some(phase),
+    };
+    if !resolved {
+        return;
+    }
+
+    // Once everyone else has gone out, whoever is left takes the game
+    if let [survivor] = info.in_game()[..] {
+        end_game(
+            &mut state,
+            &mut result,
+            &info,
+            vec![survivor],
+            format!("{} outlasted everyone else and wins!", survivor),
+        );
+        return;
+    }
+
+    // Anyone eliminated can't win, even if their ally still holds the strongholds
+    let winners = occupancy.stronghold_winners(&info.in_game(), |faction| info.ally(faction));
+    if winners.is_empty() {
+        return;
+    }
+
+    // The Bene Gesserit steal the win if they called both the winner and the turn
+    if predicted(&info, &predictions, &winners) {
+        end_game(
+            &mut state,
+            &mut result,
+            &info,
+            vec![Faction::BeneGesserit],
+            "The Bene Gesserit predicted the outcome and win!".to_string(),
+        );
+        return;
+    }
+
+    let names = winners
+        .iter()
+        .map(|faction| faction.to_string())
+        .collect::<Vec<_>>()
+        .join(" and ");
+    let needed = if winners.len() > 1 && winners.iter().any(|&faction| info.ally(faction).is_some())
+    {
+        ALLIED_STRONGHOLDS_TO_WIN
+    } else {
+        STRONGHOLDS_TO_WIN
+    };
+    end_game(
+        &mut state,
+        &mut result,
+        &info,
+        winners,
+        format!("{} control {} strongholds and win!", names, needed),
+    );
+}
+
+/// Ends the turn, or the game if this was the last turn
+fn control_phase_system(
+    mut state: ResMut<State<Screen>>,
+    mut result: ResMut<GameResult>,
+    (mut queue, mut info, phase): (ResMut<ActionQueue>, ResMut<Info>, Res<GamePhase>),
+    (occupancy, mentat): (Res<Occupancy>, Res<MentatPause>),
+    (players, predictions): (Query<&Player>, Query<&Prediction>),
+) {
+    if !queue.is_empty() {
+        return;
+    }
+    if let Phase::Control = phase.phase {
+    } else {
+        return;
+    }
+    if info.turn + 1 < LAST_TURN {
+        // Everyone gets to look over the board before the next turn starts
+        if !mentat.finished(info.turn) {
+            return;
+        }
+        info.turn += 1;
+        queue.push_single(Action::AdvancePhase.into());
+        return;
+    }
+
+    let in_play = players
+        .iter()
+        .map(|player| player.faction)
+        .filter(|faction| !info.eliminated.contains(faction))
+        .collect::<Vec<_>>();
+    let default_winner = occupancy.default_winner(&in_play);
+    let fremen_win = default_winner == Some(Faction::Fremen);
+
+    // A prediction of the last turn can take a default win too
+    if default_winner.map_or(false, |winner| predicted(&info, &predictions, &[winner])) {
+        end_game(
+            &mut state,
+            &mut result,
+            &info,
+            vec![Faction::BeneGesserit],
+            "The Bene Gesserit predicted the outcome and win!".to_string(),
+        );
+    } else if fremen_win {
+        end_game(
+            &mut state,
+            &mut result,
+            &info,
+            vec![Faction::Fremen],
+            "The Fremen kept the invaders from their sietches and win!".to_string(),
+        );
+    } else if default_winner == Some(Faction::SpacingGuild) {
+        end_game(
+            &mut state,
+            &mut result,
+            &info,
+            vec![Faction::SpacingGuild],
+            "Nobody took control of Dune, so the Spacing Guild wins!".to_string(),
+        );
+    } else {
+        end_game(
+            &mut state,
+            &mut result,
+            &info,
+            Vec::new(),
+            "Nobody took control of Dune.".to_string(),
+        );
+    }
+}
+
+enum GameOverButton {
+    Rematch,
+    ExportStats,
+    MainMenu,
+}
+
+/// Says where the exported summary went
+struct ExportText;
+
+fn init_game_over(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    button_materials: Res<ButtonMaterials>,
+    mut colors: ResMut<Assets<ColorMaterial>>,
+    (network, result, stats): (Res<Network>, Res<GameResult>, Res<GameStats>),
+) {
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let text = |value: String, font_size: f32, color: Color| TextBundle {
+        text: Text {
+            font: font.clone(),
+            value,
+            style: TextStyle {
+                font_size,
+                color,
+                ..Default::default()
+            },
+        },
+        ..Default::default()
+    };
+    let (title, color) = match result.winners.first() {
+        Some(faction) if result.winners.len() == 1 => {
+            (format!("{} Victory", faction), faction.color())
+        }
+        Some(_) => ("Shared Victory".to_string(), Color::ANTIQUE_WHITE),
+        None => ("No Victor".to_string(), Color::ANTIQUE_WHITE),
+    };
+    let mut buttons = vec![
+        (GameOverButton::ExportStats, "Export Stats"),
+        (GameOverButton::MainMenu, "Main Menu"),
+    ];
+    // Only the host can start the next game
+    if network.network_type != NetworkType::Client {
+        buttons.insert(0, (GameOverButton::Rematch, "Rematch"));
+    }
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: colors.add(Color::rgb(0.05, 0.05, 0.05).into()),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with_children(|parent| {
+            parent
+                .spawn(text(title, 48.0, color))
+                .spawn(text(result.reason.clone(), 24.0, Color::ANTIQUE_WHITE))
+                .spawn(text(
+                    format!("Game ended on turn {}", result.turn),
+                    20.0,
+                    Color::ANTIQUE_WHITE,
+                ));
+            for (faction, stats) in stats.factions() {
+                parent.spawn(text(
+                    format!(
+                        "{}: earned {} spice and spent {}, won {} battles, lost {} troops, \
+                         bought {} cards, held a stronghold for {} turns",
+                        faction,
+                        stats.spice_earned,
+                        stats.spice_spent,
+                        stats.battles_won,
+                        stats.troops_lost,
+                        stats.cards_bought,
+                        stats.turns_holding_stronghold
+                    ),
+                    16.0,
+                    faction.color(),
+                ));
+            }
+            for (action, label) in buttons {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Percent(10.0), Val::Percent(6.0)),
+                            margin: Rect::all(Val::Px(8.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        material: button_materials.normal.clone(),
+                        ..Default::default()
+                    })
+                    .with(action)
+                    .with_children(|parent| {
+                        parent.spawn(text(label.to_string(), 20.0, Color::ANTIQUE_WHITE));
+                    });
+            }
+            parent
+                .spawn(text(String::new(), 16.0, Color::ANTIQUE_WHITE))
+                .with(ExportText);
+        });
+}
+
+fn game_over_button_system(
+    mut state: ResMut<State<Screen>>,
+    mut info: ResMut<Info>,
+    (result, stats): (Res<GameResult>, Res<GameStats>),
+    button_materials: Res<ButtonMaterials>,
+    mut interactions: Query<
+        (&Interaction, &mut Handle<ColorMaterial>, &GameOverButton),
+        (Mutated<Interaction>, With<Button>),
+    >,
+    mut server: Query<&mut Server>,
+    mut export_texts: Query<&mut Text, With<ExportText>>,
+) {
+    for (&interaction, mut material, action) in interactions.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {
+                *material = button_materials.pressed.clone();
+                match action {
+                    GameOverButton::Rematch => {
+                        info.factions_in_play = result.factions.clone();
+                        if let Some(mut server) = server.iter_mut().next() {
+                            server.broadcast(&ServerEvent::Load);
+                        }
+                        state.set_next(Screen::Loading).unwrap();
+                    }
+                    GameOverButton::ExportStats => {
+                        let value = match stats.export(&result) {
+                            Ok(path) => format!("Summary saved to {}", path),
+                            Err(err) => format!("Couldn't save the summary: {}", err),
+                        };
+                        for mut text in export_texts.iter_mut() {
+                            text.value = value.clone();
+                        }
+                    }
+                    GameOverButton::MainMenu => {
+                        state.set_next(Screen::MainMenu).unwrap();
+                    }
+                }
+            }
+            Interaction::Hovered => *material = button_materials.hovered.clone(),
+            Interaction::None => *material = button_materials.normal.clone(),
+        }
+    }
+}
@@ -0,0 +1,767 @@
+use std::{collections::HashMap, f32::consts::PI, mem::take};
+
+use bevy::prelude::*;
+use bytecheck::CheckBytes;
+use rkyv::{Archive, Unarchive};
+
+use crate::{
+    alliance::own_faction,
+    components::{Dead, Player, Secret, Unique},
+    data::{CardEffect, Faction, Leader, TreacheryCard},
+    dune_core::Occupancy,
+    game_action::{submit, GameAction, GameActions},
+    hud::HudMaterials,
+    locale::Tr,
+    menu::{ButtonMaterials, Lobby},
+    network::{Client, Network, NetworkRole, NetworkType, Server},
+    phase::{Battle, BattlePlan, BattleResolved, GamePhase, Phase},
+    protocol::ServerEvent,
+    resources::{Info, RuleSet},
+    Screen, ScreenEntity, RESPONSE_STAGE, STATE_CHANGE_STAGE,
+};
+
+/// The dial has a number for every troop count from 0 to 20
+pub const DIAL_SLOTS: i32 = 21;
+const DIAL_SIZE: f32 = 220.0;
+const REVEAL_DIAL_SIZE: f32 = 140.0;
+/// Seconds the wheels take to turn over once everyone in a battle has locked in
+const REVEAL_SECONDS: f32 = 0.8;
+/// Seconds the turned over wheels stay up before clearing away
+const REVEAL_HOLD: f32 = 5.0;
+
+pub struct BattleWheelPlugin;
+
+impl Plugin for BattleWheelPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<BattleWheels>()
+            .init_resource::<Wheel>()
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                battle_lock_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                wheel_open_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                wheel_dial_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                wheel_button_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                wheel_display_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                wheel_reveal_system.system(),
+            )
+            .on_state_update(
+                STATE_CHANGE_STAGE,
+                Screen::HostingGame,
+                reveal_winner_system.system(),
+            )
+            .on_state_exit(RESPONSE_STAGE, Screen::HostingGame, reset.system());
+    }
+}
+
+/// A faction that has locked in its plan for a battle, without giving away what is in it
+#[derive(Clone, PartialEq, Debug, Archive, Unarchive)]
+#[archive(derive(CheckBytes))]
+pub struct LockedPlan {
+    pub faction: Faction,
+    pub location: String,
+}
+
+/// Where the battle plans stand. The host keeps the plans to itself until everyone in a battle
+/// has locked in, then reveals all of them at once.
+#[derive(Default)]
+pub struct BattleWheels {
+    pub open: bool,
+    pub locked: Vec<LockedPlan>,
+    pub revealed: Vec<BattlePlan>,
+}
+
+impl BattleWheels {
+    /// Takes on the host's view of the battles
+    pub fn sync(&mut self, open: bool, locked: Vec<LockedPlan>, revealed: Vec<BattlePlan>) {
+        self.open = open;
+        self.locked = locked;
+        self.revealed = revealed;
+    }
+
+    fn locked_in(&self, faction: Faction, location: &str) -> bool {
+        self.locked
+            .iter()
+            .any(|plan| plan.faction == faction && plan.location == location)
+    }
+}
+
+/// The battle plan being put together at this machine
+#[derive(Default)]
+struct Wheel {
+    faction: Option<Faction>,
+    location: Option<String>,
+    troops: i32,
+    max_troops: i32,
+    spice: i32,
+    leader: Option<String>,
+    /// Cards are told apart by their slot, so two copies of a card can't both be played
+    weapon: Option<(u32, TreacheryCard)>,
+    defense: Option<(u32, TreacheryCard)>,
+    /// How far the dial is turned, counterclockwise
+    rotation: f32,
+    /// The cursor's angle around the dial and the dial's turn when the drag started
+    drag: Option<(f32, f32)>,
+    /// Battles planned here that the host has yet to lock in
+    sent: Vec<String>,
+}
+
+/// Turns the dial so the given number sits under the pointer at the top
+pub fn dial_rotation(troops: i32) -> f32 {
+    troops as f32 * 2.0 * PI / DIAL_SLOTS as f32
+}
+
+struct WheelPanel;
+
+struct WheelDial;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum WheelButton {
+    Leader,
+    Weapon,
+    Defense,
+    Spice,
+    Confirm,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum WheelText {
+    Troops,
+    Slot(WheelButton),
+}
+
+/// Reveals battle plans once every faction in the battle has locked one in, and tells everyone
+/// who is still deciding
+fn battle_lock_system(
+    (network, phase, battle, occupancy): (
+        Res<Network>,
+        Res<GamePhase>,
+        Res<Battle>,
+        Res<Occupancy>,
+    ),
+    mut wheels: ResMut<BattleWheels>,
+    mut server: Query<&mut Server>,
+) {
+    if network.network_type == NetworkType::Client {
+        return;
+    }
+    let open = phase.phase == Phase::Battle;
+    let mut locked = Vec::new();
+    let mut revealed = Vec::new();
+    if open {
+        locked = battle
+            .plans
+            .iter()
+            .map(|plan| LockedPlan {
+                faction: plan.faction,
+                location: plan.location.clone(),
+            })
+            .collect();
+        revealed = wheels.revealed.clone();
+        for (location, factions) in occupancy.co_located_enemies() {
+            let plans = battle
+                .plans
+                .iter()
+                .filter(|plan| plan.location == location)
+                .collect::<Vec<_>>();
+            let ready = factions
+                .iter()
+                .all(|&faction| plans.iter().any(|plan| plan.faction == faction));
+            if ready && !revealed.iter().any(|plan| plan.location == location) {
+                println!("The battle plans for {} are revealed", location);
+                revealed.extend(plans.into_iter().cloned());
+            }
+        }
+    }
+    if wheels.open == open && wheels.locked == locked && wheels.revealed == revealed {
+        return;
+    }
+    if let Some(mut server) = server.iter_mut().next() {
+        server.broadcast(&ServerEvent::BattleWheels {
+            open,
+            locked: locked.clone(),
+            revealed: revealed.clone(),
+        });
+    }
+    wheels.sync(open, locked, revealed);
+}
+
+/// Opens the wheel for the next battle this machine's faction has to plan, and closes it once
+/// the plan is sent
+fn wheel_open_system(
+    commands: &mut Commands,
+    (network, lobby, info, occupancy): (Res<Network>, Res<Lobby>, Res<Info>, Res<Occupancy>),
+    (wheels, mut wheel, tr): (Res<BattleWheels>, ResMut<Wheel>, Res<Tr>),
+    (asset_server, hud_materials, button_materials, mut colors): (
+        Res<AssetServer>,
+        Res<HudMaterials>,
+        Res<ButtonMaterials>,
+        ResMut<Assets<ColorMaterial>>,
+    ),
+    (players, client): (Query<&Player>, Query<&Client>),
+    (rules, panels): (Res<RuleSet>, Query<Entity, With<WheelPanel>>),
+) {
+    let own = own_faction(&network, &lobby, &info, &players, &client)
+        .filter(|_| wheels.open && network.role != NetworkRole::Spectator);
+    match own {
+        Some(faction) => wheel
+            .sent
+            .retain(|location| !wheels.locked_in(faction, location)),
+        None => wheel.sent.clear(),
+    }
+    let next = own.and_then(|faction| {
+        occupancy
+            .co_located_enemies()
+            .into_iter()
+            .filter(|(_, factions)| factions.contains(&faction))
+            .map(|(location, _)| location)
+            .find(|location| !wheels.locked_in(faction, location) && !wheel.sent.contains(location))
+    });
+    if next == wheel.location && own == wheel.faction {
+        return;
+    }
+    for entity in panels.iter() {
+        commands.despawn_recursive(entity);
+    }
+    let (faction, location) = if let (Some(faction), Some(location)) = (own, next.clone()) {
+        (faction, location)
+    } else {
+        *wheel = Wheel {
+            faction: own,
+            location: next,
+            sent: take(&mut wheel.sent),
+            ..Default::default()
+        };
+        return;
+    };
+    let max_troops = occupancy
+        .territory(&location)
+        .get(&faction)
+        .copied()
+        .unwrap_or(0)
+        .min(DIAL_SLOTS - 1);
+    *wheel = Wheel {
+        faction: own,
+        location: next,
+        troops: max_troops,
+        max_troops,
+        rotation: dial_rotation(max_troops),
+        sent: take(&mut wheel.sent),
+        ..Default::default()
+    };
+
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let text = |value: String, font_size: f32| TextBundle {
+        text: Text {
+            font: font.clone(),
+            value,
+            style: TextStyle {
+                font_size,
+                color: Color::ANTIQUE_WHITE,
+                ..Default::default()
+            },
+        },
+        ..Default::default()
+    };
+    let mut slots = vec![
+        WheelButton::Leader,
+        WheelButton::Weapon,
+        WheelButton::Defense,
+    ];
+    if rules.spice_dialing() {
+        slots.push(WheelButton::Spice);
+    }
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(35.0),
+                    bottom: Val::Percent(15.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                padding: Rect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            material: hud_materials.panel.clone(),
+            ..Default::default()
+        })
+        .with(ScreenEntity)
+        .with(WheelPanel)
+        .with_children(|parent| {
+            parent
+                .spawn(text(tr.fmt("wheel.title", &[&location]), 22.0))
+                // Points at the number dialed
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(6.0), Val::Px(16.0)),
+                        ..Default::default()
+                    },
+                    material: hud_materials.current.clone(),
+                    ..Default::default()
+                })
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(DIAL_SIZE), Val::Px(DIAL_SIZE)),
+                        margin: Rect::all(Val::Px(4.0)),
+                        ..Default::default()
+                    },
+                    material: colors.add(asset_server.get_handle("wheel_dial.png").into()),
+                    ..Default::default()
+                })
+                .with(WheelDial)
+                .spawn(text(String::new(), 18.0))
+                .with(WheelText::Troops)
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        ..Default::default()
+                    },
+                    material: colors.add(Color::NONE.into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for slot in slots {
+                        parent
+                            .spawn(ButtonBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(80.0), Val::Px(110.0)),
+                                    margin: Rect::all(Val::Px(3.0)),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::FlexEnd,
+                                    ..Default::default()
+                                },
+                                material: button_materials.normal.clone(),
+                                ..Default::default()
+                            })
+                            .with(slot)
+                            .with_children(|parent| {
+                                parent
+                                    .spawn(text(String::new(), 13.0))
+                                    .with(WheelText::Slot(slot));
+                            });
+                    }
+                })
+                .spawn(ButtonBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(4.0)),
+                        padding: Rect::all(Val::Px(6.0)),
+                        justify_content: JustifyContent::Center,
+                        ..Default::default()
+                    },
+                    material: button_materials.normal.clone(),
+                    ..Default::default()
+                })
+                .with(WheelButton::Confirm)
+                .with_children(|parent| {
+                    parent.spawn(text(tr.get("wheel.confirm"), 18.0));
+                });
+        });
+}
+
+/// Dragging the dial round turns it with the cursor, stopping at the troops there are to dial
+fn wheel_dial_system(
+    windows: Res<Windows>,
+    mut wheel: ResMut<Wheel>,
+    mut dials: Query<(&Interaction, &GlobalTransform, &mut Transform), With<WheelDial>>,
+) {
+    let cursor = windows
+        .get_primary()
+        .and_then(|window| window.cursor_position());
+    for (interaction, global, mut transform) in dials.iter_mut() {
+        match (interaction, cursor) {
+            (Interaction::Clicked, Some(cursor)) => {
+                let offset = cursor - global.translation.truncate();
+                let angle = offset.y.atan2(offset.x);
+                let rotation = wheel.rotation;
+                let (start_angle, start_rotation) = *wheel.drag.get_or_insert((angle, rotation));
+                let rotation = start_rotation + angle - start_angle;
+                let step = dial_rotation(1);
+                let dialed = ((rotation / step).round() as i32).rem_euclid(DIAL_SLOTS);
+                if dialed <= wheel.max_troops {
+                    wheel.troops = dialed;
+                    wheel.rotation = rotation;
+                } else {
+                    // Past the end, stop at whichever end is nearer
+                    let troops = if dialed - wheel.max_troops < DIAL_SLOTS - dialed {
+                        wheel.max_troops
+                    } else {
+                        0
+                    };
+                    wheel.troops = troops;
+                    wheel.rotation = dial_rotation(troops);
+                    wheel.drag = None;
+                }
+            }
+            _ => {
+                wheel.drag = None;
+                wheel.rotation = dial_rotation(wheel.troops);
+            }
+        }
+        wheel.spice = wheel.spice.min(wheel.troops);
+        transform.rotation = Quat::from_rotation_z(wheel.rotation);
+    }
+}
+
+/// The item after `current` in `options`, wrapping round
+fn cycle<T: Clone + PartialEq>(options: &[T], current: &T) -> T {
+    let next = options
+        .iter()
+        .position(|option| option == current)
+        .map_or(0, |i| (i + 1) % options.len());
+    options[next].clone()
+}
+
+type AliveLeaders<'a, 'b, 'c> = Query<'a, (&'b Leader, &'c Unique), Without<Dead>>;
+
+type WheelButtonInteraction<'a> = (
+    &'a Interaction,
+    &'a mut Handle<ColorMaterial>,
+    &'a WheelButton,
+);
+
+/// Clicking a slot puts the next leader or card in it, and confirming sends the plan to the host
+fn wheel_button_system(
+    network: Res<Network>,
+    (button_materials, mut actions): (Res<ButtonMaterials>, ResMut<GameActions>),
+    mut wheel: ResMut<Wheel>,
+    (players, leaders, cards): (Query<&Player>, AliveLeaders, Query<&Secret<TreacheryCard>>),
+    mut interactions: Query<WheelButtonInteraction, (Mutated<Interaction>, With<Button>)>,
+    mut client: Query<&mut Client>,
+) {
+    let (faction, location) = match (wheel.faction, wheel.location.clone()) {
+        (Some(faction), Some(location)) => (faction, location),
+        _ => return,
+    };
+    let player = if let Some(player) = players.iter().find(|player| player.faction == faction) {
+        player
+    } else {
+        return;
+    };
+    let hand = player
+        .treachery_cards
+        .iter()
+        .filter_map(|&entity| cards.get(entity).ok())
+        .filter_map(|secret| secret.value.clone().map(|card| (secret.slot, card)))
+        .collect::<Vec<_>>();
+    for (&interaction, mut material, button) in interactions.iter_mut() {
+        if *button == WheelButton::Confirm {
+            *material = match interaction {
+                Interaction::Clicked => button_materials.pressed.clone(),
+                Interaction::Hovered => button_materials.hovered.clone(),
+                Interaction::None => button_materials.normal.clone(),
+            };
+        }
+        if interaction != Interaction::Clicked {
+            continue;
+        }
+        match button {
+            WheelButton::Leader => {
+                let mut alive = leaders
+                    .iter()
+                    .filter(|(_, unique)| unique.faction == faction)
+                    .map(|(leader, _)| leader)
+                    .collect::<Vec<_>>();
+                alive.sort_by_key(|leader| -leader.power);
+                let options = std::iter::once(None)
+                    .chain(alive.into_iter().map(|leader| Some(leader.name.clone())))
+                    .collect::<Vec<_>>();
+                wheel.leader = cycle(&options, &wheel.leader);
+            }
+            WheelButton::Weapon | WheelButton::Defense => {
+                let (fits, other): (fn(CardEffect) -> bool, _) = if *button == WheelButton::Weapon {
+                    (|effect| effect.is_weapon(), wheel.defense.clone())
+                } else {
+                    (|effect| effect.is_defense(), wheel.weapon.clone())
+                };
+                let options = std::iter::once(None)
+                    .chain(
+                        hand.iter()
+                            .filter(|(slot, card)| {
+                                (fits(card.effect) || card.effect == CardEffect::Worthless)
+                                    && other.as_ref().map(|(other, _)| other) != Some(slot)
+                            })
+                            .cloned()
+                            .map(Some),
+                    )
+                    .collect::<Vec<_>>();
+                if *button == WheelButton::Weapon {
+                    wheel.weapon = cycle(&options, &wheel.weapon);
+                } else {
+                    wheel.defense = cycle(&options, &wheel.defense);
+                }
+            }
+            WheelButton::Spice => {
+                wheel.spice = (wheel.spice + 1) % (wheel.troops.min(player.spice) + 1);
+            }
+            WheelButton::Confirm => {
+                let plan = BattlePlan {
+                    faction,
+                    location: location.clone(),
+                    leader: wheel.leader.clone(),
+                    troops: wheel.troops,
+                    spice: wheel.spice,
+                    weapon: wheel.weapon.as_ref().map(|(_, card)| card.name.clone()),
+                    defense: wheel.defense.as_ref().map(|(_, card)| card.name.clone()),
+                };
+                println!("{} locks in a plan for {}", faction, location);
+                submit(
+                    &network,
+                    &mut client,
+                    &mut actions,
+                    GameAction::Battle { plan },
+                );
+                wheel.sent.push(location.clone());
+            }
+        }
+    }
+}
+
+/// Shows what is in each slot of the wheel, with the leader or card's picture once one is in it
+fn wheel_display_system(
+    (wheel, tr, asset_server): (Res<Wheel>, Res<Tr>, Res<AssetServer>),
+    (button_materials, mut colors): (Res<ButtonMaterials>, ResMut<Assets<ColorMaterial>>),
+    mut images: Local<HashMap<String, Handle<ColorMaterial>>>,
+    leaders: Query<&Leader>,
+    mut slots: Query<(&WheelButton, &mut Handle<ColorMaterial>)>,
+    mut texts: Query<(&WheelText, &mut Text)>,
+) {
+    let card = |card: &Option<(u32, TreacheryCard)>| {
+        card.as_ref()
+            .map(|(_, card)| format!("treachery/treachery_{}.png", card.texture))
+    };
+    for (button, mut material) in slots.iter_mut() {
+        let image = match button {
+            WheelButton::Leader => wheel.leader.as_ref().and_then(|name| {
+                leaders
+                    .iter()
+                    .find(|leader| leader.name == *name)
+                    .map(|leader| format!("leaders/{}.png", leader.texture))
+            }),
+            WheelButton::Weapon => card(&wheel.weapon),
+            WheelButton::Defense => card(&wheel.defense),
+            _ => continue,
+        };
+        let handle = match image {
+            Some(path) => images
+                .entry(path.clone())
+                .or_insert_with(|| colors.add(asset_server.get_handle(path.as_str()).into()))
+                .clone(),
+            None => button_materials.normal.clone(),
+        };
+        if *material != handle {
+            *material = handle;
+        }
+    }
+    for (kind, mut text) in texts.iter_mut() {
+        let value = match kind {
+            WheelText::Troops => tr.fmt("wheel.troops", &[&wheel.troops, &wheel.max_troops]),
+            WheelText::Slot(WheelButton::Leader) => wheel
+                .leader
+                .clone()
+                .unwrap_or_else(|| tr.get("wheel.leader")),
+            WheelText::Slot(WheelButton::Weapon) => wheel
+                .weapon
+                .as_ref()
+                .map_or_else(|| tr.get("wheel.weapon"), |(_, card)| card.name.clone()),
+            WheelText::Slot(WheelButton::Defense) => wheel
+                .defense
+                .as_ref()
+                .map_or_else(|| tr.get("wheel.defense"), |(_, card)| card.name.clone()),
+            WheelText::Slot(_) => tr.fmt("wheel.spice", &[&wheel.spice]),
+        };
+        if text.value != value {
+            text.value = value;
+        }
+    }
+}
+
+/// The wheels of one battle turning over, and how long they have been up
+struct RevealPanel {
+    elapsed: f32,
+}
+
+struct RevealWheel {
+    faction: Faction,
+    location: String,
+}
+
+/// Turns over every faction's wheel in a battle at the same moment, once all of them are in
+fn wheel_reveal_system(
+    commands: &mut Commands,
+    (time, wheels, tr): (Res<Time>, Res<BattleWheels>, Res<Tr>),
+    (asset_server, hud_materials, mut colors): (
+        Res<AssetServer>,
+        Res<HudMaterials>,
+        ResMut<Assets<ColorMaterial>>,
+    ),
+    mut shown: Local<usize>,
+    mut panels: Query<(Entity, &mut RevealPanel, &Children)>,
+    mut reveals: Query<&mut Transform, With<RevealWheel>>,
+) {
+    for (entity, mut panel, children) in panels.iter_mut() {
+        panel.elapsed += time.delta_seconds();
+        let turned = (panel.elapsed / REVEAL_SECONDS).min(1.0);
+        for &child in children.iter() {
+            if let Ok(mut transform) = reveals.get_mut(child) {
+                transform.scale = Vec3::new(turned, 1.0, 1.0);
+            }
+        }
+        if panel.elapsed > REVEAL_SECONDS + REVEAL_HOLD {
+            commands.despawn_recursive(entity);
+        }
+    }
+
+    if wheels.revealed.len() < *shown {
+        *shown = 0;
+    }
+    let new = &wheels.revealed[*shown..];
+    *shown = wheels.revealed.len();
+    let mut locations = new
+        .iter()
+        .map(|plan| plan.location.clone())
+        .collect::<Vec<_>>();
+    locations.dedup();
+
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let text = |value: String, font_size: f32, color: Color| TextBundle {
+        text: Text {
+            font: font.clone(),
+            value,
+            style: TextStyle {
+                font_size,
+                color,
+                ..Default::default()
+            },
+        },
+        ..Default::default()
+    };
+    let dial = colors.add(asset_server.get_handle("wheel_dial.png").into());
+    for location in locations {
+        commands
+            .spawn(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        left: Val::Percent(25.0),
+                        bottom: Val::Percent(30.0),
+                        ..Default::default()
+                    },
+                    flex_direction: FlexDirection::Row,
+                    padding: Rect::all(Val::Px(8.0)),
+                    ..Default::default()
+                },
+                material: hud_materials.panel.clone(),
+                ..Default::default()
+            })
+            .with(ScreenEntity)
+            .with(RevealPanel { elapsed: 0.0 })
+            .with_children(|parent| {
+                for plan in new.iter().filter(|plan| plan.location == location) {
+                    let mut lines = vec![tr.fmt("wheel.troops_dialed", &[&plan.troops])];
+                    lines.push(
+                        plan.leader
+                            .clone()
+                            .unwrap_or_else(|| tr.get("wheel.no_leader")),
+                    );
+                    lines.extend(plan.weapon.clone());
+                    lines.extend(plan.defense.clone());
+                    if plan.spice > 0 {
+                        lines.push(tr.fmt("wheel.spice", &[&plan.spice]));
+                    }
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::ColumnReverse,
+                                align_items: AlignItems::Center,
+                                margin: Rect::all(Val::Px(8.0)),
+                                ..Default::default()
+                            },
+                            material: hud_materials.upcoming.clone(),
+                            transform: Transform::from_scale(Vec3::new(0.0, 1.0, 1.0)),
+                            ..Default::default()
+                        })
+                        .with(RevealWheel {
+                            faction: plan.faction,
+                            location: location.clone(),
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn(text(tr.faction(plan.faction), 20.0, plan.faction.color()))
+                                .spawn(ImageBundle {
+                                    style: Style {
+                                        size: Size::new(
+                                            Val::Px(REVEAL_DIAL_SIZE),
+                                            Val::Px(REVEAL_DIAL_SIZE),
+                                        ),
+                                        margin: Rect::all(Val::Px(4.0)),
+                                        ..Default::default()
+                                    },
+                                    material: dial.clone(),
+                                    transform: Transform::from_rotation(Quat::from_rotation_z(
+                                        dial_rotation(plan.troops),
+                                    )),
+                                    ..Default::default()
+                                })
+                                .spawn(text(lines.join("\n"), 16.0, Color::ANTIQUE_WHITE));
+                        });
+                }
+            });
+    }
+}
+
+/// Lights up the winner's wheel once the battle is settled. The result can come in before the
+/// wheels have been spawned, so it is held on to until they are.
+fn reveal_winner_system(
+    events: Res<Events<BattleResolved>>,
+    mut reader: Local<EventReader<BattleResolved>>,
+    mut winners: Local<Vec<(String, Faction)>>,
+    (wheels, hud_materials): (Res<BattleWheels>, Res<HudMaterials>),
+    mut reveals: Query<(&RevealWheel, &mut Handle<ColorMaterial>)>,
+) {
+    winners.extend(
+        reader
+            .iter(&events)
+            .map(|event| (event.location.clone(), event.winner)),
+    );
+    winners.retain(|(location, faction)| {
+        let mut lit = false;
+        for (reveal, mut material) in reveals.iter_mut() {
+            if reveal.location == *location && reveal.faction == *faction {
+                *material = hud_materials.current.clone();
+                lit = true;
+            }
+        }
+        !lit && wheels
+            .revealed
+            .iter()
+            .any(|plan| plan.location == *location)
+    });
+}
+
+fn reset(mut wheels: ResMut<BattleWheels>, mut wheel: ResMut<Wheel>) {
+    *wheels = BattleWheels::default();
+    *wheel = Wheel::default();
+}